@@ -0,0 +1,16 @@
+use std::process::Command;
+
+/// Embeds the git commit being built, for `--build-info`. Builds from outside
+/// a git checkout get an empty commit.
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=TYPEWRITER_COMMIT={}", commit.trim());
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
@@ -0,0 +1,1757 @@
+#[cfg(test)]
+mod sessions;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::config;
+use crate::editor::{self, Buffer, REVEAL_STEP};
+use crate::input::{InputEvent, KeyChord, KeyMap};
+use crate::lock::{self, Owner};
+use crate::macros::Recorder;
+use crate::messages::{Level, Messages};
+use crate::page::{Fill, PageFit};
+use crate::paragraph::{Check, Limit, ParagraphLimit};
+use crate::paste;
+use crate::paths;
+use crate::pomodoro::{Phase, Pomodoro};
+use crate::print;
+use crate::renderer::{Overlay, StatusTone};
+use crate::saving::Writer;
+use crate::signals::Signal;
+use crate::snapshots;
+use crate::sound::{BellStyle, KeyboardLayout, SoundType};
+use crate::sprint::{self, Sprint};
+use crate::stats::{self, SessionStats};
+use crate::status::{Placeholder, StatusTemplate};
+use crate::ui::{
+    Confirm, ConfirmOutcome, Help, MarginOutcome, MarginSetter, Menu, MenuOutcome, Prompt, PromptOutcome,
+    RevealOutcome, RevealSetter,
+};
+
+/// Lines scrolled per turn of the mouse wheel
+const SCROLL_LINES: isize = 3;
+
+/// How long a status-row question waits for an answer
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the app does with input
+pub enum AppMode {
+    /// Keystrokes go to the buffer
+    Editing,
+    /// Waiting for the user to confirm discarding unsaved changes
+    ConfirmAbort(Confirm),
+    /// The Escape menu is open
+    Menu(Menu),
+    /// Asking for a file name to save under, then optionally closing
+    SaveAs { prompt: Prompt, then_close: bool },
+    /// Asking whether an existing file may be replaced by a save
+    ConfirmOverwrite { confirm: Confirm, path: String, then_close: bool },
+    /// Asking whether to save before closing
+    ConfirmSaveOnClose(Confirm),
+    /// The key binding list is open
+    Help(Help),
+    /// The summary of a sprint that just ended is shown
+    SprintOver(Overlay),
+    /// The recent status messages are shown
+    MessageLog(Overlay),
+    /// Asking whether to edit a file another typewriter has open, or only read it
+    LockConflict(Confirm),
+    /// Moving the margin stop along the ruler
+    SetMargin(MarginSetter),
+    /// Asking whether to keep a newly set margin or reveal delay in the
+    /// config file, as the setting `name`. `what` names it in messages.
+    ConfirmSaveSetting { confirm: Confirm, name: &'static str, value: String, what: &'static str },
+    /// Watching a line type itself while choosing the reveal delay
+    SetRevealRate(RevealSetter),
+    /// Asking whether to type out a big paste
+    ConfirmPaste { confirm: Confirm, text: String },
+    /// Asking whether a snapshot may replace unsaved changes
+    ConfirmRestore { confirm: Confirm, name: String, text: String },
+}
+
+/// How a session ended, as far as the app is concerned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionEnd {
+    /// The user closed with nothing left unsaved
+    Closed,
+    /// The user quit and threw away unsaved changes
+    Discarded,
+    /// A signal other than SIGINT stopped the session
+    Signalled(Signal),
+}
+
+/// Something that calls for the user's attention
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alert {
+    /// The bell, rung this many times a moment apart
+    Bell(usize),
+    /// A flash of the screen
+    Flash,
+}
+
+/// Something the app asks of the terminal, the sound system or the session
+/// around it. Effects are carried out in the order they are returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Plays a sound at the given moment, if sound is on
+    Sound { sound: SoundType, at: Instant },
+    /// Rings the bell or flashes the screen, as the `bell` setting asks
+    Alert(Alert),
+    /// Turns the sound on or off
+    ToggleSound,
+    /// Writes the text to a file in the background, reporting back to
+    /// `App::written` when done
+    Write { path: String, text: String },
+    /// The buffer was saved, so the save hook can run
+    Saved { path: String, words: usize },
+    /// Scrolls back to the cursor
+    FollowCursor,
+    /// Scrolls the page by this many lines
+    Scroll(isize),
+    /// The terminal has a new size
+    Resize(u16, u16),
+    /// Draws the whole screen again
+    Redraw,
+    /// A click on the screen, for the renderer to find on the page
+    Click(u16, u16),
+    /// Copies text to the clipboard
+    Copy(String),
+    /// Prints text, headed by the document's name
+    Print { text: String, title: String },
+    /// Ends the session
+    Exit(SessionEnd),
+}
+
+/// Settings the app needs besides its buffer
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    /// How far the audio output lags behind the screen, for turning sound back on
+    pub audio_latency: Duration,
+    /// Seed for the sound variation, for turning sound back on
+    pub seed: u64,
+    /// The keyboard typed on, for turning sound back on
+    pub layout: KeyboardLayout,
+    /// Length of the writing sprint to start with, if any
+    pub sprint: Option<Duration>,
+    /// Shell command to run after each save, if any
+    pub on_save: Option<String>,
+    /// The other typewriter that has the file open, if any
+    pub locked_by: Option<Owner>,
+    /// Whether the status row shows how clean the typing has been
+    pub cleanliness: bool,
+    /// Why the file can't be saved in place, if it can't
+    pub unwritable: Option<io::ErrorKind>,
+    /// Whether the file is typing itself out, until a key skips to the end
+    pub reveal_on_load: bool,
+    /// Size the paragraph being typed is measured against, if any
+    pub paragraph_limit: Option<Limit>,
+    /// Whether the text is measured against one printed page
+    pub fit_page: bool,
+    /// Config file a margin set while typing can be saved to
+    pub config_path: Option<PathBuf>,
+    /// Size in bytes past which a paste is confirmed before it is typed
+    pub paste_confirm: usize,
+    /// Whether to ring the bell for each marked-out character, for screen readers
+    pub accessible: bool,
+    /// Most frames drawn in a second while text is appearing, or 0 for no limit
+    pub max_fps: u64,
+    /// How the bell rings
+    pub bell: BellStyle,
+    /// Volume of the click for menus, prompts and toggles in percent, or 0 for none
+    pub ui_click_volume: u8,
+    /// Most snapshots kept of the file
+    pub snapshot_limit: usize,
+    /// Whether what was typed or marked out since the last save is underlined
+    pub show_changes: bool,
+    /// Shell command that printed pages are piped to
+    pub print_command: String,
+    /// A snapshot to put in place of the text at the start, by name and text
+    pub restore: Option<(String, String)>,
+    /// What writes saved files
+    pub writer: Writer,
+    /// How the status row is laid out, if not as it is by default
+    pub status: Option<StatusTemplate>,
+    /// Blank columns left of the page, which the status row starts after
+    pub padding_left: u16,
+    /// Where the time comes from
+    pub clock: Clock,
+}
+
+/// What the status row, overlays and ruler should show
+#[derive(Debug, Clone)]
+pub struct View {
+    /// Text of the status row, if any
+    pub status: Option<String>,
+    /// Where the cursor sits in the status row while a question is asked
+    pub status_cursor: Option<usize>,
+    /// How the status row is colored
+    pub tone: StatusTone,
+    /// Boxes drawn over the page, bottom first
+    pub overlays: Vec<Overlay>,
+    /// Column of the margin stop on the ruler, while it is being moved
+    pub ruler: Option<usize>,
+    /// Whether changes since the last save are underlined
+    pub show_changes: bool,
+    /// First line of the buffer past the page being fitted, tinted
+    pub overflow_from: Option<usize>,
+}
+
+/// A writing session: the buffer being typed into, the mode the input goes to
+/// and the timers running alongside. It doesn't touch the terminal; what it
+/// handles comes back as effects for the event loop to carry out.
+pub struct App<'a> {
+    /// The document being typed
+    buffer: &'a mut Buffer,
+    /// Work and rest cycles, if any; kept by the caller for the summary
+    pomodoro: &'a mut Option<Pomodoro>,
+    /// Where the session started from and how long it has been active; kept
+    /// by the caller for the summary
+    stats: &'a mut SessionStats,
+    /// What input goes to
+    mode: AppMode,
+    /// Settings for the session
+    options: SessionOptions,
+    /// Key bindings, for the help
+    keymap: KeyMap,
+    /// Columns and rows of the terminal
+    size: (u16, u16),
+    /// Whether the user chose to only read a file another typewriter has open
+    read_only: bool,
+    /// The macro being recorded or played
+    recorder: Recorder,
+    /// The writing sprint, until its summary is shown
+    sprint: Option<Sprint>,
+    /// Messages for the status row, each shown for a while
+    messages: Messages,
+    /// When a close waiting for the last characters to appear gives up waiting
+    closing: Option<Instant>,
+    /// While an opened file types itself out: up to when its clicks were played
+    loading: Option<Instant>,
+    /// The paragraph size limit, if any
+    paragraph: Option<ParagraphLimit>,
+    /// How the paragraph measured up at the last tick
+    paragraph_check: Option<Check>,
+    /// The page the text is fitted to, if any
+    page: Option<PageFit>,
+    /// How full the page was at the last tick
+    page_fill: Option<Fill>,
+    /// The save being written in the background, if any
+    saving: Option<Saving>,
+    /// Whether sound is on, for the status row
+    sound: bool,
+}
+
+/// A save being written in the background
+struct Saving {
+    /// The file being written
+    path: String,
+    /// The text being written, to tell whether the buffer changed meanwhile
+    text: String,
+    /// Words in the text, for the save hook
+    words: usize,
+    /// Whether to close once it is written
+    then_close: bool,
+    /// Times the close key was pressed while it was written, to handle once it is
+    closes: usize,
+    /// Where another save asked for meanwhile goes, to start once this one is
+    /// done: this file, or another one after a Save As
+    queued: Option<String>,
+}
+
+impl<'a> App<'a> {
+    /// Starts a session on a buffer, asking first about a lock held elsewhere.
+    /// A snapshot to restore replaces the text, after asking if it has
+    /// unsaved changes.
+    pub fn new(
+        buffer: &'a mut Buffer,
+        pomodoro: &'a mut Option<Pomodoro>,
+        stats: &'a mut SessionStats,
+        keymap: KeyMap,
+        size: (u16, u16),
+        options: SessionOptions,
+    ) -> Self {
+        let now = options.clock.now();
+        let mode = match &options.locked_by {
+            Some(owner) => AppMode::lock_conflict(buffer, owner, now),
+            None => AppMode::Editing,
+        };
+        let mut messages = Messages::new();
+        if let Some(kind) = options.unwritable {
+            let text = format!("Can't save to this file ({}); saving will ask for another place", kind);
+            messages.push(Level::Error, text, Level::Error.ttl());
+        }
+        let restore = options.restore.clone();
+        let mut app = Self {
+            sprint: options.sprint.map(|length| Sprint::start(length, buffer, now)),
+            loading: options.reveal_on_load.then_some(now),
+            paragraph: options.paragraph_limit.map(ParagraphLimit::new),
+            page: options.fit_page.then(PageFit::new),
+            buffer,
+            pomodoro,
+            stats,
+            mode,
+            options,
+            keymap,
+            size,
+            read_only: false,
+            recorder: Recorder::new(),
+            messages,
+            closing: None,
+            paragraph_check: None,
+            page_fill: None,
+            saving: None,
+            sound: false,
+        };
+        match restore {
+            // The lock is asked about first, and the snapshot left for another time
+            Some((name, _)) if app.buffer.is_modified() && !matches!(app.mode, AppMode::Editing) => {
+                log::warn!("not restoring snapshot {} over unsaved changes", name);
+            }
+            Some((name, text)) if app.buffer.is_modified() => {
+                let question = format!("Replace the unsaved changes with snapshot {}? (y/n)", name);
+                let confirm = Confirm::new(&question, now, CONFIRM_TIMEOUT);
+                app.mode = AppMode::ConfirmRestore { confirm, name, text };
+            }
+            Some((name, text)) => app.restore(&name, &text),
+            None => (),
+        }
+        app
+    }
+
+    /// Returns the buffer being typed
+    pub fn buffer(&self) -> &Buffer {
+        self.buffer
+    }
+
+    /// Returns the settings for the session
+    pub fn options(&self) -> &SessionOptions {
+        &self.options
+    }
+
+    /// Shows a message in the status row for as long as its level calls for
+    pub fn notify(&mut self, level: Level, text: String) {
+        self.messages.push(level, text, level.ttl());
+    }
+
+    /// Notes whether sound is on, for the status row
+    pub fn set_sound(&mut self, on: bool) {
+        self.sound = on;
+    }
+
+    /// Moves the cursor to a clicked position on the page. Like the arrow
+    /// keys, clicks only move within the line being typed.
+    pub fn click_at(&mut self, line: usize, column: usize) {
+        if line == self.buffer.cursor_position().0 {
+            self.buffer.move_to_column(column);
+        }
+    }
+
+    /// Returns when typed text is next due to appear, to wake up and draw it,
+    /// or None if nothing is waiting to appear
+    pub fn next_reveal(&self, now: Instant) -> Option<Instant> {
+        self.buffer.next_reveal(now)
+    }
+
+    /// Returns when a timer of the session next runs out: the wait for the
+    /// ink to dry on closing, a question's, the sprint, the pomodoro or a
+    /// message's time
+    pub fn next_deadline(&self) -> Option<Instant> {
+        [
+            self.closing,
+            self.mode.confirm().map(Confirm::deadline),
+            self.messages.next_expiry(),
+            self.sprint.as_ref().and_then(Sprint::deadline),
+            self.pomodoro.as_ref().map(Pomodoro::deadline),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Returns when the next key of a playing macro is due. Replaying pauses
+    /// while a prompt or menu is open, or during a rest.
+    pub fn next_replay(&self) -> Option<Instant> {
+        self.recorder.next_due().filter(|_| matches!(self.mode, AppMode::Editing) && !self.resting())
+    }
+
+    /// Returns whether the pomodoro is in a rest
+    fn resting(&self) -> bool {
+        self.pomodoro.as_ref().is_some_and(Pomodoro::is_resting)
+    }
+
+    /// Moves the timers on, before a frame is drawn. Gives up on a question
+    /// left unanswered, rings for the pomodoro changing phase, the sprint
+    /// ending, the paragraph going past its limit and the page filling up, and
+    /// ends a close that was waiting for the last characters.
+    pub fn tick(&mut self, now: Instant) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        if self.mode.confirm().is_some_and(|confirm| now >= confirm.deadline()) {
+            self.handle_mode(InputEvent::Ignored, now, &mut effects);
+        }
+        // Messages only count down while the status row shows them
+        self.messages.tick(now, matches!(self.mode, AppMode::Editing));
+
+        // Ring once when a rest starts and twice when work starts again. The
+        // sprint doesn't count down during a rest.
+        if let Some(phase) = self.pomodoro.as_mut().and_then(|pomodoro| pomodoro.update(now)) {
+            match phase {
+                Phase::Resting => {
+                    if let Some(sprint) = &mut self.sprint {
+                        sprint.pause(now);
+                    }
+                    effects.push(Effect::Alert(Alert::Bell(1)));
+                }
+                Phase::Working => {
+                    if let Some(sprint) = &mut self.sprint {
+                        sprint.resume(now);
+                    }
+                    effects.push(Effect::Alert(Alert::Bell(2)));
+                }
+            }
+        }
+
+        // Once the sprint has run its length, ring and show how it went. A
+        // prompt or menu that is open gets finished first.
+        if matches!(self.mode, AppMode::Editing) && self.sprint.as_ref().is_some_and(|sprint| sprint.is_over(now)) {
+            if let Some(sprint) = self.sprint.take() {
+                effects.push(Effect::Alert(Alert::Bell(1)));
+                self.mode = AppMode::SprintOver(sprint.summary(self.buffer, now));
+            }
+        }
+
+        if let Some(heard_until) = self.loading {
+            for c in self.buffer.revealed_between(heard_until, now) {
+                effects.push(Effect::Sound { sound: SoundType::KeyPress(c), at: now });
+            }
+            self.loading = (self.buffer.pending_reveals(now) > 0).then_some(now);
+        }
+
+        self.paragraph_check = self.paragraph.as_mut().map(|paragraph| paragraph.check(self.buffer));
+        if self.paragraph_check.as_ref().is_some_and(|check| check.crossed) {
+            effects.push(Effect::Alert(Alert::Bell(1)));
+        }
+        self.page_fill = self.page.as_mut().map(|page| page.check(self.buffer));
+        if self.page_fill.as_ref().is_some_and(|fill| fill.full) {
+            effects.push(Effect::Alert(Alert::Bell(1)));
+        }
+
+        // Close once the page is finished, so the last frame shows all of it
+        if self.closing.is_some_and(|deadline| self.buffer.pending_reveals(now) == 0 || now >= deadline) {
+            effects.push(Effect::Exit(SessionEnd::Closed));
+        }
+        effects
+    }
+
+    /// Returns what the status row, overlays and ruler should show. While
+    /// editing, the row shows the most pressing of the session's states. The
+    /// rest overlay, if any, stays under whatever box the mode draws over the
+    /// page.
+    pub fn view(&self, now: Instant) -> View {
+        let resting = self.resting();
+        let paragraph = self.paragraph_check.as_ref();
+        let page = self.page_fill.as_ref();
+        let message = self.messages.current();
+        let state = self
+            .saving
+            .as_ref()
+            .map(|_| "Saving…".to_string())
+            .or_else(|| self.loading.map(|_| "Typing out the file (any key skips to the end)".to_string()))
+            .or_else(|| self.read_only.then(|| "Read-only".to_string()))
+            .or_else(|| self.recorder.is_recording().then(|| "Recording macro".to_string()))
+            .or_else(|| self.sprint.as_ref().map(|sprint| sprint.status(now)))
+            .or_else(|| self.pomodoro.as_ref().filter(|_| !resting).map(|pomodoro| pomodoro.status(now)))
+            .or_else(|| paragraph.map(|check| check.status.clone()))
+            .or_else(|| page.map(|fill| fill.status.clone()))
+            .or_else(|| self.buffer.caps().then(|| "Typing in capitals".to_string()))
+            .or_else(|| self.options.show_changes.then(|| "Showing changes since saving".to_string()))
+            .or_else(|| {
+                let cleanliness = self.buffer.cleanliness();
+                self.options.cleanliness.then(|| format!("Clean: {}", stats::format_cleanliness(cleanliness)))
+            })
+            .or_else(|| (self.buffer.pending_reveals(now) > 0).then(|| "…".to_string()));
+        // A template lays the row out around what it says otherwise, which
+        // messages still take the place of
+        let status = message.as_ref().map(|(_, text)| text.clone()).or_else(|| match &self.options.status {
+            Some(template) => {
+                let width = self.size.0.saturating_sub(self.options.padding_left) as usize;
+                Some(template.render(width, |placeholder| self.status_value(placeholder, &state, now)))
+            }
+            None => state.clone(),
+        });
+        let rest = self.pomodoro.as_ref().filter(|_| resting).map(|pomodoro| pomodoro.rest_overlay(now));
+        // Only errors and the paragraph's and page's own statuses are colored
+        let tone = match message {
+            Some((Level::Error, _)) => StatusTone::Over,
+            Some(_) => StatusTone::Normal,
+            None => {
+                let paragraph = paragraph.filter(|check| state.as_ref() == Some(&check.status)).map(|check| check.tone);
+                let page = page.filter(|fill| state.as_ref() == Some(&fill.status)).map(|fill| fill.tone);
+                paragraph.or(page).unwrap_or(StatusTone::Normal)
+            }
+        };
+
+        let (status, status_cursor, overlay) = match &self.mode {
+            AppMode::Editing => (status, None, None),
+            AppMode::ConfirmAbort(confirm)
+            | AppMode::ConfirmSaveOnClose(confirm)
+            | AppMode::ConfirmOverwrite { confirm, .. }
+            | AppMode::LockConflict(confirm)
+            | AppMode::ConfirmSaveSetting { confirm, .. }
+            | AppMode::ConfirmPaste { confirm, .. }
+            | AppMode::ConfirmRestore { confirm, .. } => {
+                let question = format!("{} ", confirm.question());
+                let column = question.chars().count();
+                (Some(question), Some(column), None)
+            }
+            AppMode::SaveAs { prompt, .. } => {
+                let (text, column) = prompt.view(self.size.0 as usize);
+                (Some(text), Some(column), None)
+            }
+            AppMode::Menu(menu) => (None, None, Some(menu.overlay())),
+            AppMode::Help(help) => (None, None, Some(help.overlay(self.size.1))),
+            AppMode::SprintOver(overlay) | AppMode::MessageLog(overlay) => (None, None, Some(overlay.clone())),
+            AppMode::SetMargin(setter) => (Some(setter.status()), None, None),
+            AppMode::SetRevealRate(setter) => (Some(setter.status()), None, Some(setter.overlay(now))),
+        };
+        View {
+            status,
+            status_cursor,
+            tone: if matches!(self.mode, AppMode::Editing) { tone } else { StatusTone::Normal },
+            overlays: rest.into_iter().chain(overlay).collect(),
+            ruler: match &self.mode {
+                AppMode::SetMargin(setter) => Some(setter.column()),
+                _ => None,
+            },
+            show_changes: self.options.show_changes,
+            overflow_from: page.and_then(|fill| fill.overflow_from),
+        }
+    }
+
+    /// Returns what a placeholder in the status template stands for now.
+    /// `state` is what the status row would say without a template.
+    fn status_value(&self, placeholder: Placeholder, state: &Option<String>, now: Instant) -> String {
+        let (line, column) = self.buffer.cursor_position();
+        match placeholder {
+            Placeholder::File => print::title(self.buffer.file_path.as_deref()),
+            Placeholder::Modified => if self.buffer.is_modified() { "*" } else { "" }.to_string(),
+            Placeholder::Line => (line + 1).to_string(),
+            Placeholder::Col => (column + 1).to_string(),
+            Placeholder::Words => stats::word_count(self.buffer).to_string(),
+            Placeholder::Chars => self.buffer.text().chars().filter(|&c| c != '\n').count().to_string(),
+            Placeholder::Wpm => self.stats.words_per_minute(self.buffer, now).to_string(),
+            Placeholder::Goal => self.paragraph_check.as_ref().map(|check| check.status.clone()).unwrap_or_default(),
+            Placeholder::Page => print::page_at(line).to_string(),
+            Placeholder::Sound => if self.sound { "♪" } else { "" }.to_string(),
+            Placeholder::Mode => state.clone().unwrap_or_default(),
+            Placeholder::Clock => self.options.clock.local().format("%H:%M").to_string(),
+        }
+    }
+
+    /// Handles an event from the user or the terminal
+    pub fn handle(&mut self, event: InputEvent, now: Instant) -> Vec<Effect> {
+        self.handle_event(event, now, false)
+    }
+
+    /// Handles the next key of a playing macro, if one is due
+    pub fn replay(&mut self, now: Instant) -> Vec<Effect> {
+        match self.recorder.next_event(now) {
+            Some(event) => self.handle_event(event, now, true),
+            None => Vec::new(),
+        }
+    }
+
+    /// Handles an event, typed or replayed from a macro
+    fn handle_event(&mut self, mut event: InputEvent, now: Instant, replayed: bool) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        let resting = self.resting();
+        if let InputEvent::FocusGained | InputEvent::FocusLost = event {
+            match event {
+                InputEvent::FocusLost => self.stats.focus_lost(now),
+                _ => self.stats.input(now),
+            }
+            // The sprint stays paused through a rest, focused or not
+            if let Some(sprint) = &mut self.sprint {
+                match event {
+                    InputEvent::FocusLost => sprint.pause(now),
+                    _ if !resting => sprint.resume(now),
+                    _ => (),
+                }
+            }
+            return effects;
+        }
+        if let InputEvent::Resize(columns, rows) = event {
+            // Like a redraw, whatever the mode; the next render lays it out again
+            self.size = (columns, rows);
+            effects.push(Effect::Resize(columns, rows));
+            return effects;
+        }
+        log::debug!("{} {:?}", if replayed { "replayed" } else { "input" }, event);
+        if !replayed && event != InputEvent::Ignored {
+            self.stats.input(now);
+            if event != InputEvent::Close {
+                self.closing = None;
+            }
+        }
+
+        if event == InputEvent::Redraw {
+            // Works the same in every mode, so prompts and menus stay open
+            effects.push(Effect::Redraw);
+            return effects;
+        }
+        if self.loading.is_some() && !replayed && event != InputEvent::Ignored {
+            self.buffer.reveal_all_now(now);
+            self.loading = None;
+            return effects;
+        }
+
+        // Decided before the event changes the mode, so a menu entry clicks once
+        if self.options.ui_click_volume > 0 && self.clicks(&event) {
+            let volume = self.options.ui_click_volume as f32 / 100.0;
+            effects.push(Effect::Sound { sound: SoundType::UiTick(volume), at: now });
+        }
+
+        if !matches!(self.mode, AppMode::Editing) {
+            match self.handle_mode(event, now, &mut effects) {
+                // Carry on as if the chosen menu action's key had been pressed
+                Some(selected) => event = selected,
+                None => return effects,
+            }
+        }
+
+        if !replayed {
+            self.recorder.record(&event);
+        }
+        if !matches!(
+            event,
+            InputEvent::Click(..) | InputEvent::ScrollUp | InputEvent::ScrollDown | InputEvent::Ignored
+        ) {
+            effects.push(Effect::FollowCursor);
+        }
+        self.edit(event, now, resting, &mut effects);
+        effects
+    }
+
+    /// Returns whether an event gets the UI click: anything that works a menu,
+    /// prompt or question except text typed into a prompt, and the keys that
+    /// open one or toggle a setting. Typing has the typebar's own clicks.
+    fn clicks(&self, event: &InputEvent) -> bool {
+        use InputEvent::*;
+        match (&self.mode, event) {
+            (_, Ignored | Click(..) | ScrollUp | ScrollDown) => false,
+            (AppMode::Editing, _) => matches!(
+                event,
+                Escape
+                    | Help
+                    | MessageLog
+                    | SetMargin
+                    | SetRevealRate
+                    | ToggleCaps
+                    | ToggleChanges
+                    | ToggleSound
+                    | RecordMacro
+                    | RevealFaster
+                    | RevealSlower
+            ),
+            (AppMode::SaveAs { .. }, Char(_) | Backspace | Paste(_)) => false,
+            _ => true,
+        }
+    }
+
+    /// Handles an event while a prompt, question or box is open. Returns the
+    /// event to handle as if typed when a menu entry is chosen.
+    fn handle_mode(&mut self, event: InputEvent, now: Instant, effects: &mut Vec<Effect>) -> Option<InputEvent> {
+        match &mut self.mode {
+            AppMode::Editing => return Some(event),
+            AppMode::ConfirmAbort(confirm) => match confirm.handle(&event, now) {
+                ConfirmOutcome::Pending => (),
+                ConfirmOutcome::Accepted => effects.push(Effect::Exit(SessionEnd::Discarded)),
+                ConfirmOutcome::Declined | ConfirmOutcome::Cancelled => self.mode = AppMode::Editing,
+            },
+            AppMode::ConfirmSaveOnClose(confirm) => match confirm.handle(&event, now) {
+                ConfirmOutcome::Pending => (),
+                ConfirmOutcome::Accepted => match self.buffer.file_path {
+                    Some(_) => self.save(None, true, effects),
+                    None => self.mode = AppMode::save_as(self.buffer, true),
+                },
+                ConfirmOutcome::Declined => effects.push(Effect::Exit(SessionEnd::Discarded)),
+                ConfirmOutcome::Cancelled => self.mode = AppMode::Editing,
+            },
+            AppMode::SaveAs { prompt, then_close } => {
+                let then_close = *then_close;
+                match prompt.handle(&event) {
+                    PromptOutcome::Editing => (),
+                    PromptOutcome::Cancelled => self.mode = AppMode::Editing,
+                    PromptOutcome::Submitted(path) => {
+                        let path = path.trim().to_string();
+                        self.mode = AppMode::Editing;
+                        let current = self.buffer.file_path.as_deref().map(Path::new);
+                        match paths::expand(&path) {
+                            // Nothing to save to
+                            _ if path.is_empty() => (),
+                            // Asked again with what was typed, so it can be fixed
+                            Err(e) => {
+                                let label = format!("Can't save there ({}). Save as: ", e);
+                                self.mode = AppMode::SaveAs { prompt: Prompt::new(&label, &path), then_close };
+                            }
+                            Ok(target) if target.exists() && current != Some(&target) => {
+                                let question = format!("{} already exists. Overwrite? (y/n)", path);
+                                self.mode = AppMode::ConfirmOverwrite {
+                                    confirm: Confirm::new(&question, now, CONFIRM_TIMEOUT),
+                                    path,
+                                    then_close,
+                                };
+                            }
+                            Ok(_) => self.save(Some(&path), then_close, effects),
+                        }
+                    }
+                }
+            }
+            AppMode::ConfirmOverwrite { confirm, path, then_close } => {
+                let then_close = *then_close;
+                match confirm.handle(&event, now) {
+                    ConfirmOutcome::Pending => (),
+                    ConfirmOutcome::Accepted => {
+                        let path = path.clone();
+                        self.mode = AppMode::Editing;
+                        self.save(Some(&path), then_close, effects);
+                    }
+                    ConfirmOutcome::Declined | ConfirmOutcome::Cancelled => self.mode = AppMode::Editing,
+                }
+            }
+            AppMode::Help(help) => {
+                if !help.handle(&event, self.size.1) {
+                    self.mode = AppMode::Editing;
+                }
+            }
+            AppMode::LockConflict(confirm) => match confirm.handle(&event, now) {
+                ConfirmOutcome::Pending => (),
+                ConfirmOutcome::Accepted => {
+                    self.mode = AppMode::Editing;
+                    let path = self.buffer.file_path.as_deref();
+                    if let Some(Err(e)) = path.map(|path| lock::take_over(Path::new(path))) {
+                        self.notify(Level::Error, format!("Couldn't take over the lock: {}", e));
+                        self.read_only = true;
+                    }
+                }
+                ConfirmOutcome::Declined | ConfirmOutcome::Cancelled => {
+                    self.mode = AppMode::Editing;
+                    self.read_only = true;
+                }
+            },
+            AppMode::SetMargin(setter) => match setter.handle(&event) {
+                MarginOutcome::Open => (),
+                MarginOutcome::Cancelled => self.mode = AppMode::Editing,
+                MarginOutcome::Set(columns) => {
+                    // Lines already past the new margin are split to fit, as on opening
+                    self.buffer.set_hard_margin(Some(columns));
+                    self.buffer.split_long_lines(columns);
+                    let set = format!("Margin set at {}", columns);
+                    self.offer_to_save(set, "hard_margin", columns.to_string(), "margin", now);
+                }
+            },
+            AppMode::SetRevealRate(setter) => match setter.handle(&event, now) {
+                RevealOutcome::Open => (),
+                RevealOutcome::Cancelled => self.mode = AppMode::Editing,
+                RevealOutcome::Set(rate) => {
+                    self.buffer.set_reveal_rate(rate, now);
+                    let ms = rate.as_millis();
+                    let set = format!("Reveal delay: {} ms", ms);
+                    self.offer_to_save(set, "reveal_rate_ms", ms.to_string(), "reveal delay", now);
+                }
+            },
+            AppMode::ConfirmSaveSetting { confirm, name, value, what } => match confirm.handle(&event, now) {
+                ConfirmOutcome::Pending => (),
+                ConfirmOutcome::Accepted => {
+                    let (name, value, what) = (*name, std::mem::take(value), *what);
+                    self.mode = AppMode::Editing;
+                    if let Some(path) = &self.options.config_path {
+                        let (level, text) = match config::save_setting(path, name, &value) {
+                            Ok(()) => (Level::Info, format!("Saved the {} to {}", what, path.display())),
+                            Err(e) => {
+                                (Level::Error, format!("Couldn't save the {} to {}: {}", what, path.display(), e))
+                            }
+                        };
+                        self.notify(level, text);
+                    }
+                }
+                ConfirmOutcome::Declined | ConfirmOutcome::Cancelled => self.mode = AppMode::Editing,
+            },
+            AppMode::ConfirmPaste { confirm, text } => match confirm.handle(&event, now) {
+                ConfirmOutcome::Pending => (),
+                ConfirmOutcome::Accepted => {
+                    // All at once and silently, rather than a minute of clicks
+                    self.buffer.paste(text, now);
+                    self.mode = AppMode::Editing;
+                }
+                ConfirmOutcome::Declined | ConfirmOutcome::Cancelled => self.mode = AppMode::Editing,
+            },
+            AppMode::ConfirmRestore { confirm, name, text } => match confirm.handle(&event, now) {
+                ConfirmOutcome::Pending => (),
+                ConfirmOutcome::Accepted => {
+                    let (name, text) = (std::mem::take(name), std::mem::take(text));
+                    self.mode = AppMode::Editing;
+                    self.restore(&name, &text);
+                    effects.push(Effect::FollowCursor);
+                }
+                ConfirmOutcome::Declined | ConfirmOutcome::Cancelled => self.mode = AppMode::Editing,
+            },
+            AppMode::SprintOver(_) | AppMode::MessageLog(_) => {
+                if event != InputEvent::Ignored {
+                    self.mode = AppMode::Editing;
+                }
+            }
+            AppMode::Menu(menu) => match menu.handle(&event) {
+                MenuOutcome::Open => (),
+                MenuOutcome::Closed => self.mode = AppMode::Editing,
+                MenuOutcome::Selected(selected) => {
+                    self.mode = AppMode::Editing;
+                    return Some(selected);
+                }
+            },
+        }
+        None
+    }
+
+    /// Handles an event while editing
+    fn edit(&mut self, event: InputEvent, now: Instant, resting: bool, effects: &mut Vec<Effect>) {
+        let read_only = self.read_only;
+        match event {
+            // Resting or reading means no typing, though moving around still works
+            InputEvent::Char(_)
+            | InputEvent::Backspace
+            | InputEvent::NewLine
+            | InputEvent::Tab
+            | InputEvent::Undo
+            | InputEvent::PlayMacro
+            | InputEvent::Paste(_)
+                if resting || read_only => {}
+            InputEvent::Save | InputEvent::SaveAs if read_only => {
+                self.notify(Level::Info, "Read-only: the file is open in another typewriter".to_string());
+            }
+            InputEvent::Undo if !self.buffer.soft_undo() => {
+                self.notify(Level::Info, "Undo is off; start typewriter with --soft-undo to use it".to_string());
+            }
+            InputEvent::Paste(text) if paste::needs_confirm(&text, self.options.paste_confirm) => {
+                let confirm = Confirm::new(&paste::question(&text), now, CONFIRM_TIMEOUT);
+                self.mode = AppMode::ConfirmPaste { confirm, text };
+            }
+            // A smaller paste is typed out as if by hand
+            InputEvent::Char(_)
+            | InputEvent::Backspace
+            | InputEvent::NewLine
+            | InputEvent::Right
+            | InputEvent::Down
+            | InputEvent::Tab
+            | InputEvent::FinishCorrection
+            | InputEvent::Undo
+            | InputEvent::Paste(_) => {
+                let edits = event.editor_events();
+                let applied: Vec<editor::Effect> = edits.into_iter().flat_map(|edit| self.buffer.apply(edit)).collect();
+                // A visual bell that comes with a flash is the flash
+                let flashes = applied.contains(&editor::Effect::Flash);
+                for effect in applied {
+                    match effect {
+                        editor::Effect::PlaySound { sound, at } => effects.push(Effect::Sound { sound, at }),
+                        editor::Effect::Bell if flashes && self.options.bell == BellStyle::Visual => (),
+                        editor::Effect::Bell => effects.push(Effect::Alert(Alert::Bell(1))),
+                        editor::Effect::Flash => effects.push(Effect::Alert(Alert::Flash)),
+                        editor::Effect::NothingToUndo => self.notify(Level::Info, "Nothing to undo on this line".to_string()),
+                        // A screen reader can't see text being marked out, so it is heard
+                        editor::Effect::MarkedOut if self.options.accessible => {
+                            effects.push(Effect::Alert(Alert::Bell(1)))
+                        }
+                        editor::Effect::MarkedOut => (),
+                        editor::Effect::Committed => (),
+                    }
+                }
+            }
+            InputEvent::RevealFaster | InputEvent::RevealSlower => {
+                let rate = match event {
+                    InputEvent::RevealFaster => self.buffer.reveal_rate().saturating_sub(REVEAL_STEP),
+                    _ => self.buffer.reveal_rate() + REVEAL_STEP,
+                };
+                self.buffer.set_reveal_rate(rate, now);
+                self.notify(Level::Info, format!("Reveal delay: {} ms", self.buffer.reveal_rate().as_millis()));
+            }
+            InputEvent::Escape => self.mode = AppMode::Menu(Menu::main_menu()),
+            InputEvent::Help => self.mode = AppMode::Help(Help::from_keymap(&self.keymap)),
+            InputEvent::MessageLog => self.mode = AppMode::MessageLog(self.messages.overlay()),
+            InputEvent::SetMargin => {
+                let widest = self.size.0 as usize;
+                let setter = MarginSetter::new(self.buffer.hard_margin(), self.buffer.longest_line(), widest);
+                self.mode = AppMode::SetMargin(setter);
+            }
+            InputEvent::SetRevealRate => {
+                self.mode = AppMode::SetRevealRate(RevealSetter::new(self.buffer.reveal_rate(), now));
+            }
+            InputEvent::RecordMacro => {
+                if !self.recorder.toggle_recording() {
+                    self.notify(Level::Info, "Macro recorded".to_string());
+                }
+            }
+            InputEvent::PlayMacro => {
+                let skipped = self.recorder.start_playback(now);
+                if skipped > 0 {
+                    self.notify(Level::Info, format!("Skipping {} command(s) that can't be replayed", skipped));
+                }
+            }
+            InputEvent::ToggleCaps => {
+                self.buffer.set_caps(!self.buffer.caps());
+                self.notify(Level::Info, format!("Capitals {}", if self.buffer.caps() { "on" } else { "off" }));
+            }
+            InputEvent::ToggleChanges => {
+                self.options.show_changes = !self.options.show_changes;
+                let shown = if self.options.show_changes { "underlined" } else { "no longer underlined" };
+                self.notify(Level::Info, format!("Changes since the last save {}", shown));
+            }
+            InputEvent::ToggleSound => effects.push(Effect::ToggleSound),
+            InputEvent::Save if self.buffer.file_path.is_some() => self.save(None, false, effects),
+            InputEvent::Save | InputEvent::SaveAs => self.mode = AppMode::save_as(self.buffer, false),
+            InputEvent::Close | InputEvent::Abort if self.sprint.as_ref().is_some_and(|sprint| !sprint.is_over(now)) => {
+                let remaining = self.sprint.as_ref().map(|sprint| sprint.remaining(now)).unwrap_or_default();
+                self.notify(Level::Info, format!("No stopping mid-sprint: {} to go", sprint::format_clock(remaining)));
+                effects.push(Effect::Alert(Alert::Flash));
+            }
+            InputEvent::Close => match self.saving.as_mut() {
+                // Nothing changed since the save being written, so the close waits for it
+                Some(saving) if saving.text == self.buffer.saved_text() => saving.closes += 1,
+                _ => self.close(now, effects),
+            },
+            InputEvent::Abort => {
+                if !self.buffer.is_modified() {
+                    effects.push(Effect::Exit(SessionEnd::Closed));
+                    return;
+                }
+                let question = "Discard changes? (y/n)";
+                self.mode = AppMode::ConfirmAbort(Confirm::new(question, now, CONFIRM_TIMEOUT));
+            }
+            // Left and Up only mean something in prompts and menus
+            InputEvent::Left | InputEvent::Up => (),
+            InputEvent::CopyAll => effects.push(Effect::Copy(self.buffer.saved_text())),
+            InputEvent::Snapshot => self.snapshot(),
+            InputEvent::Print => {
+                let title = print::title(self.buffer.file_path.as_deref());
+                effects.push(Effect::Print { text: self.buffer.saved_text(), title });
+            }
+            InputEvent::Click(column, row) => effects.push(Effect::Click(column, row)),
+            InputEvent::ScrollUp => effects.push(Effect::Scroll(-SCROLL_LINES)),
+            InputEvent::ScrollDown => effects.push(Effect::Scroll(SCROLL_LINES)),
+            InputEvent::Unknown(key) => {
+                let chord = KeyChord::from_event(&key);
+                log::debug!("unbound key {:?}", key);
+                self.notify(Level::Info, format!("Unbound key: {}", chord));
+            }
+            // Already handled, whatever the mode
+            InputEvent::Redraw | InputEvent::FocusGained | InputEvent::FocusLost | InputEvent::Resize(..) => (),
+            InputEvent::Ignored => (),
+        }
+    }
+
+    /// Says a setting was changed and asks whether to keep it in the config
+    /// file as `name`, if there is one to keep it in
+    fn offer_to_save(&mut self, set: String, name: &'static str, value: String, what: &'static str, now: Instant) {
+        self.mode = match self.options.config_path {
+            Some(_) => {
+                let question = format!("{}. Save it to the config file? (y/n)", set);
+                let confirm = Confirm::new(&question, now, CONFIRM_TIMEOUT);
+                AppMode::ConfirmSaveSetting { confirm, name, value, what }
+            }
+            None => {
+                self.notify(Level::Info, set);
+                AppMode::Editing
+            }
+        };
+    }
+
+    /// Closes, asking first if there are unsaved changes. Waits up to one
+    /// reveal for what's still to appear, unless this is the second close.
+    fn close(&mut self, now: Instant, effects: &mut Vec<Effect>) {
+        if self.buffer.is_modified() {
+            let question = "Save changes before closing? (y/n)";
+            self.mode = AppMode::ConfirmSaveOnClose(Confirm::new(question, now, CONFIRM_TIMEOUT));
+        } else if self.closing.is_none() && self.buffer.pending_reveals(now) > 0 {
+            self.closing = Some(now + self.buffer.reveal_rate());
+            self.notify(Level::Info, "Letting the ink dry (close again to skip)".to_string());
+        } else {
+            effects.push(Effect::Exit(SessionEnd::Closed));
+        }
+    }
+
+    /// Saves the buffer in the background, pointing it at a new file first if
+    /// a path is given, and closes once it is written if asked. While a save
+    /// is being written, one more is queued to start after it.
+    fn save(&mut self, path: Option<&str>, then_close: bool, effects: &mut Vec<Effect>) {
+        if let Err(e) = path.map_or(Ok(()), |path| self.buffer.set_file_path(path)) {
+            log::error!("couldn't save {}: {}", path.unwrap_or_default(), e);
+            self.mode = AppMode::save_failed(self.buffer, &e, then_close);
+            return;
+        }
+        if let Some(saving) = &mut self.saving {
+            saving.queued = Some(self.buffer.file_path.clone().unwrap_or_default());
+            saving.then_close |= then_close;
+            return;
+        }
+        let path = self.buffer.file_path.clone().unwrap_or_default();
+        let (text, words) = (self.buffer.saved_text(), stats::word_count(self.buffer));
+        effects.push(Effect::Write { path: path.clone(), text: text.clone() });
+        self.saving = Some(Saving { path, text, words, then_close, closes: 0, queued: None });
+    }
+
+    /// Takes in how the save being written went. The buffer counts as saved
+    /// if nothing changed while it was written and it still belongs to the
+    /// file written. A queued save starts next, if there is anything left to
+    /// write or it goes to another file, or
+    /// closes pressed meanwhile are handled; if writing failed, asks for
+    /// somewhere else to save.
+    pub fn written(&mut self, result: io::Result<()>, now: Instant) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        let Some(saving) = self.saving.take() else {
+            return effects;
+        };
+        match result {
+            Ok(()) => {
+                log::info!("saved {} ({} words)", saving.path, saving.words);
+                let moved = saving.queued.as_ref().is_some_and(|target| *target != saving.path);
+                if moved {
+                    log::info!("{} was saved as another file while it was being saved", saving.path);
+                } else if !self.buffer.wrote(&saving.text) {
+                    log::info!("{} changed while it was being saved", saving.path);
+                }
+                effects.push(Effect::Saved { path: saving.path, words: saving.words });
+                if moved || (saving.queued.is_some() && self.buffer.is_modified()) {
+                    self.save(None, saving.then_close, &mut effects);
+                } else if saving.then_close {
+                    effects.push(Effect::Exit(SessionEnd::Closed));
+                } else {
+                    // A second close skips waiting for the ink to dry
+                    for _ in 0..saving.closes.min(2) {
+                        self.close(now, &mut effects);
+                    }
+                }
+            }
+            Err(e) => {
+                log::error!("couldn't save {}: {}", saving.path, e);
+                match self.mode {
+                    AppMode::Editing => {
+                        let then_close = saving.then_close || saving.closes > 0;
+                        self.mode = AppMode::save_failed(self.buffer, &e, then_close);
+                    }
+                    // Not taking over a menu or question that opened meanwhile
+                    _ => self.notify(Level::Error, format!("Couldn't save: {}", e)),
+                }
+            }
+        }
+        effects
+    }
+
+    /// Keeps a copy of the text as it would be saved beside the file, leaving
+    /// the file itself alone
+    fn snapshot(&mut self) {
+        let Some(path) = self.buffer.file_path.clone() else {
+            self.notify(Level::Info, "Save the file first; snapshots are kept beside it".to_string());
+            return;
+        };
+        let now = self.options.clock.local();
+        let taken = snapshots::write(Path::new(&path), &self.buffer.saved_text(), now, self.options.snapshot_limit);
+        match taken {
+            Ok((snapshot, number)) => {
+                log::info!("kept snapshot {} of {}", snapshot.name, path);
+                self.notify(Level::Info, format!("Snapshot {} saved", number));
+            }
+            Err(e) => {
+                log::error!("couldn't keep a snapshot of {}: {}", path, e);
+                self.notify(Level::Error, format!("Couldn't save a snapshot: {}", e));
+            }
+        }
+    }
+
+    /// Puts a snapshot's text in place of the buffer's. The file keeps its
+    /// text until the next save.
+    fn restore(&mut self, name: &str, text: &str) {
+        self.buffer.replace_text(text);
+        log::info!("restored snapshot {}", name);
+        self.notify(Level::Info, format!("Restored snapshot {}; saving puts it in the file", name));
+    }
+}
+
+impl AppMode {
+    /// Starts asking for a file name, pre-filled with the current one
+    fn save_as(buffer: &Buffer, then_close: bool) -> Self {
+        let current = buffer.file_path.as_deref().unwrap_or("");
+        AppMode::SaveAs {
+            prompt: Prompt::new("Save as: ", current),
+            then_close,
+        }
+    }
+
+    /// Asks for somewhere else to save after saving failed, such as for lack
+    /// of permission, pre-filled with the path that failed
+    fn save_failed(buffer: &Buffer, error: &io::Error, then_close: bool) -> Self {
+        let current = buffer.file_path.as_deref().unwrap_or("");
+        AppMode::SaveAs {
+            prompt: Prompt::new(&format!("Couldn't save ({}). Save as: ", error.kind()), current),
+            then_close,
+        }
+    }
+
+    /// Returns the question being asked, if the mode is one
+    fn confirm(&self) -> Option<&Confirm> {
+        match self {
+            AppMode::ConfirmAbort(confirm)
+            | AppMode::ConfirmSaveOnClose(confirm)
+            | AppMode::LockConflict(confirm)
+            | AppMode::ConfirmOverwrite { confirm, .. }
+            | AppMode::ConfirmSaveSetting { confirm, .. }
+            | AppMode::ConfirmPaste { confirm, .. }
+            | AppMode::ConfirmRestore { confirm, .. } => Some(confirm),
+            _ => None,
+        }
+    }
+
+    /// Starts asking what to do about another typewriter having the file open
+    fn lock_conflict(buffer: &Buffer, owner: &Owner, now: Instant) -> Self {
+        let path = buffer.file_path.as_deref().unwrap_or("");
+        let name = Path::new(path).file_name().map_or(path.into(), |name| name.to_string_lossy());
+        let question = format!("{} is open elsewhere ({}). Edit anyway? (y/n)", name, owner);
+        AppMode::LockConflict(Confirm::new(&question, now, CONFIRM_TIMEOUT))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+    use crate::messages::INFO_TTL;
+
+    /// Starts an app on a buffer with the default settings
+    fn app<'a>(
+        buffer: &'a mut Buffer,
+        pomodoro: &'a mut Option<Pomodoro>,
+        stats: &'a mut SessionStats,
+        options: SessionOptions,
+    ) -> App<'a> {
+        App::new(buffer, pomodoro, stats, KeyMap::default(), (80, 24), options)
+    }
+
+    /// Starts measuring a session from an empty page
+    fn session_stats() -> SessionStats {
+        SessionStats::start(&Buffer::new(Duration::ZERO), Instant::now(), stats::DEFAULT_IDLE_THRESHOLD)
+    }
+
+    /// Handles events one after another, returning every effect
+    fn replay(app: &mut App, events: impl IntoIterator<Item = InputEvent>) -> Vec<Effect> {
+        let now = Instant::now();
+        events.into_iter().flat_map(|event| app.handle(event, now)).collect()
+    }
+
+    /// Writes what the effects ask to, as the event loop would, returning
+    /// the effects of taking in each write
+    fn write(app: &mut App, effects: Vec<Effect>) -> Vec<Effect> {
+        let written = |effect| match effect {
+            Effect::Write { path, text } => app.written(std::fs::write(path, text), Instant::now()),
+            _ => Vec::new(),
+        };
+        effects.into_iter().flat_map(written).collect()
+    }
+
+    /// Turns text into key presses
+    fn keys(text: &str) -> Vec<InputEvent> {
+        text.chars().map(InputEvent::Char).collect()
+    }
+
+    #[test]
+    /// Test that quitting with changes asks first, and that declining goes
+    /// back to editing while accepting discards
+    fn test_abort() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+
+        let effects = replay(&mut app, keys("hi"));
+        assert!(effects.contains(&Effect::FollowCursor));
+        assert!(!effects.iter().any(|effect| matches!(effect, Effect::Exit(_))));
+
+        assert!(replay(&mut app, [InputEvent::Abort]).contains(&Effect::FollowCursor));
+        assert!(matches!(app.mode, AppMode::ConfirmAbort(_)));
+        assert_eq!(replay(&mut app, keys("n")), vec![]);
+        assert!(matches!(app.mode, AppMode::Editing));
+
+        replay(&mut app, [InputEvent::Abort]);
+        assert_eq!(replay(&mut app, keys("y")), vec![Effect::Exit(SessionEnd::Discarded)]);
+        assert_eq!(buffer.lines[0].len(), 2);
+    }
+
+    #[test]
+    /// Test that closing an untouched page ends straight away, and that closing
+    /// with changes asks whether to save
+    fn test_close() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        let effects = replay(&mut app, [InputEvent::Close]);
+        assert_eq!(effects, vec![Effect::FollowCursor, Effect::Exit(SessionEnd::Closed)]);
+
+        replay(&mut app, keys("a"));
+        replay(&mut app, [InputEvent::Close]);
+        assert!(matches!(app.mode, AppMode::ConfirmSaveOnClose(_)));
+        // Waiting out part of the question keeps it open, and waiting out all
+        // of it gives up on it
+        let deadline = app.next_deadline().unwrap();
+        assert_eq!(app.tick(deadline - Duration::from_millis(1)), vec![]);
+        assert!(matches!(app.mode, AppMode::ConfirmSaveOnClose(_)));
+        assert_eq!(app.tick(deadline), vec![]);
+        assert!(matches!(app.mode, AppMode::Editing));
+        assert_eq!(app.next_deadline(), None);
+
+        replay(&mut app, [InputEvent::Close]);
+        assert_eq!(replay(&mut app, keys("n")), vec![Effect::Exit(SessionEnd::Discarded)]);
+    }
+
+    #[test]
+    /// Test that saving an unnamed page asks for a name, then saves there,
+    /// and that saving from the close question closes afterwards
+    fn test_save_as() {
+        let dir = ScratchDir::new("app");
+        let path = dir.join("draft.txt").display().to_string();
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        replay(&mut app, keys("One two"));
+        replay(&mut app, [InputEvent::Save]);
+        assert!(matches!(app.mode, AppMode::SaveAs { then_close: false, .. }));
+
+        // A path that can't be expanded is asked for again
+        replay(&mut app, keys("$TYPEWRITER_UNSET_TEST_VAR/draft.txt"));
+        assert_eq!(replay(&mut app, [InputEvent::NewLine]), vec![]);
+        let status = app.view(Instant::now()).status.unwrap();
+        assert!(status.starts_with("Can't save there ($TYPEWRITER_UNSET_TEST_VAR isn't set). Save as: "));
+        replay(&mut app, vec![InputEvent::Backspace; 36]);
+        replay(&mut app, keys(&path));
+        let effects = replay(&mut app, [InputEvent::NewLine]);
+        assert_eq!(effects, vec![Effect::Write { path: path.clone(), text: "One two\n".to_string() }]);
+        assert_eq!(write(&mut app, effects), vec![Effect::Saved { path: path.clone(), words: 2 }]);
+        assert!(matches!(app.mode, AppMode::Editing));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "One two\n");
+
+        replay(&mut app, keys(" three"));
+        replay(&mut app, [InputEvent::Close]);
+        let effects = replay(&mut app, keys("y"));
+        let effects = write(&mut app, effects);
+        let saved = Effect::Saved { path: path.clone(), words: 3 };
+        assert_eq!(effects, vec![saved, Effect::Exit(SessionEnd::Closed)]);
+    }
+
+    #[test]
+    /// Test that a save is written in the background, with the page still
+    /// unsaved until it is, that saves meanwhile queue one more and that a
+    /// close meanwhile waits for it
+    fn test_save_in_background() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_file_path("notes.txt").unwrap();
+        let path = buffer.file_path.clone().unwrap();
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        replay(&mut app, keys("One"));
+        let effects = replay(&mut app, [InputEvent::Save]);
+        assert_eq!(effects.last(), Some(&Effect::Write { path: path.clone(), text: "One\n".to_string() }));
+        assert_eq!(app.view(Instant::now()).status.as_deref(), Some("Saving…"));
+        assert!(app.buffer.is_modified());
+
+        // Typing carries on, and saving twice more queues one save
+        replay(&mut app, keys(" two"));
+        let writes = |effects: Vec<Effect>| effects.into_iter().filter(|effect| matches!(effect, Effect::Write { .. }));
+        assert_eq!(writes(replay(&mut app, [InputEvent::Save, InputEvent::Save])).count(), 0);
+        let effects = app.written(Ok(()), Instant::now());
+        let write = Effect::Write { path: path.clone(), text: "One two\n".to_string() };
+        assert_eq!(effects, vec![Effect::Saved { path: path.clone(), words: 1 }, write]);
+        assert!(app.buffer.is_modified());
+
+        // Nothing changed since, so closing waits for the write
+        assert!(!replay(&mut app, [InputEvent::Close]).contains(&Effect::Exit(SessionEnd::Closed)));
+        assert!(matches!(app.mode, AppMode::Editing));
+        let effects = app.written(Ok(()), Instant::now());
+        assert_eq!(effects, vec![Effect::Saved { path, words: 2 }, Effect::Exit(SessionEnd::Closed)]);
+        assert!(!app.buffer.is_modified());
+        assert_eq!(app.view(Instant::now()).status, None);
+
+        // A failed write asks where else to save
+        replay(&mut app, keys("!"));
+        replay(&mut app, [InputEvent::Save]);
+        let failed = app.written(Err(io::Error::from(io::ErrorKind::NotFound)), Instant::now());
+        assert_eq!(failed, vec![]);
+        assert!(matches!(app.mode, AppMode::SaveAs { then_close: false, .. }));
+        assert!(app.buffer.is_modified());
+    }
+
+    #[test]
+    /// Test that a Save As while a save is being written goes to the new file
+    /// once it is done, and that the page only counts as saved once it has
+    fn test_save_as_while_saving() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_file_path("notes.txt").unwrap();
+        let path = buffer.file_path.clone().unwrap();
+        let dir = ScratchDir::new("app-save-as");
+        let other = dir.join("letter.txt").display().to_string();
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        replay(&mut app, keys("Dear"));
+        replay(&mut app, [InputEvent::Save, InputEvent::SaveAs]);
+        replay(&mut app, vec![InputEvent::Backspace; path.chars().count() + 10]);
+        replay(&mut app, keys(&other));
+        let effects = replay(&mut app, [InputEvent::NewLine]);
+        assert!(!effects.iter().any(|effect| matches!(effect, Effect::Write { .. })));
+        assert_eq!(app.buffer.file_path.as_deref(), Some(other.as_str()));
+
+        // Nothing was typed meanwhile, but the new file still has to be written
+        let effects = app.written(Ok(()), Instant::now());
+        let write = Effect::Write { path: other.clone(), text: "Dear\n".to_string() };
+        assert_eq!(effects, vec![Effect::Saved { path, words: 1 }, write]);
+        assert!(app.buffer.is_modified());
+
+        let effects = app.written(Ok(()), Instant::now());
+        assert_eq!(effects, vec![Effect::Saved { path: other, words: 1 }]);
+        assert!(!app.buffer.is_modified());
+    }
+
+    #[test]
+    /// Test that the menu opens on Escape and that choosing an entry carries
+    /// on as if its key had been pressed
+    fn test_menu() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        replay(&mut app, [InputEvent::Escape]);
+        assert!(matches!(app.mode, AppMode::Menu(_)));
+        assert_eq!(app.view(Instant::now()).overlays.len(), 1);
+        replay(&mut app, [InputEvent::Escape]);
+        assert!(matches!(app.mode, AppMode::Editing));
+        assert!(app.view(Instant::now()).overlays.is_empty());
+
+        // Down to "Toggle Sound"
+        replay(&mut app, [InputEvent::Escape, InputEvent::Down, InputEvent::Down]);
+        let effects = replay(&mut app, [InputEvent::NewLine]);
+        assert_eq!(effects, vec![Effect::FollowCursor, Effect::ToggleSound]);
+        assert!(matches!(app.mode, AppMode::Editing));
+    }
+
+    #[test]
+    /// Test choosing the reveal delay from the menu while a line types itself,
+    /// cancelling, and keeping a new delay in the config file
+    fn test_reveal_speed() {
+        let dir = ScratchDir::new("app-reveal");
+        let config_path = dir.join("config.toml");
+        let mut buffer = Buffer::new(Duration::from_millis(300));
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let options = SessionOptions { config_path: Some(config_path.clone()), ..Default::default() };
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, options);
+
+        // Down to "Reveal Speed"; Escape leaves the delay as it was
+        let open = [InputEvent::Escape, InputEvent::Down, InputEvent::Down, InputEvent::Down, InputEvent::NewLine];
+        replay(&mut app, open.clone());
+        assert!(matches!(app.mode, AppMode::SetRevealRate(_)));
+        replay(&mut app, [InputEvent::Right, InputEvent::Escape]);
+        assert!(matches!(app.mode, AppMode::Editing));
+        assert_eq!(app.buffer.reveal_rate(), Duration::from_millis(300));
+
+        // The line types itself at the delay on show, under the keys
+        let start = Instant::now();
+        replay(&mut app, open);
+        app.handle(InputEvent::Left, start);
+        app.handle(InputEvent::Left, start);
+        let view = app.view(start + Duration::from_millis(500));
+        assert_eq!(view.status.unwrap(), "Left/Right to change the delay, Enter to set, Esc to cancel");
+        assert_eq!(view.overlays[0].lines[0], "Characters appear after 200 ms");
+        assert_eq!(view.overlays[0].lines[2].trim_end(), "It");
+        assert_eq!(app.view(start + Duration::from_millis(1000)).overlays[0].lines[2].trim_end(), "It wa");
+
+        // Enter sets it at once and asks to keep it
+        replay(&mut app, [InputEvent::NewLine]);
+        assert_eq!(app.buffer.reveal_rate(), Duration::from_millis(200));
+        let question = app.view(Instant::now()).status.unwrap();
+        assert_eq!(question, "Reveal delay: 200 ms. Save it to the config file? (y/n) ");
+        replay(&mut app, [InputEvent::Char('y')]);
+        assert!(matches!(app.mode, AppMode::Editing));
+        assert_eq!(config::Config::load(&config_path).unwrap().reveal_rate, Duration::from_millis(200));
+        let saved = format!("Saved the reveal delay to {}", config_path.display());
+        assert_eq!(app.view(Instant::now()).status.unwrap(), saved);
+    }
+
+    #[test]
+    /// Test that printing, from its key or the menu, hands over the text as
+    /// saved, headed by the file's name
+    fn test_print() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        replay(&mut app, keys("Dear Sir,"));
+        let effects = replay(&mut app, [InputEvent::Print]);
+        let print = Effect::Print { text: "Dear Sir,".to_string(), title: "Untitled".to_string() };
+        assert_eq!(effects, vec![Effect::FollowCursor, print]);
+
+        // Down past "Reveal Speed" to "Print"
+        app.buffer.file_path = Some("/home/me/letter.txt".to_string());
+        replay(&mut app, [InputEvent::Escape, InputEvent::Down, InputEvent::Down, InputEvent::Down, InputEvent::Down]);
+        let effects = replay(&mut app, [InputEvent::NewLine]);
+        let print = Effect::Print { text: "Dear Sir,\n".to_string(), title: "letter.txt".to_string() };
+        assert_eq!(effects, vec![Effect::FollowCursor, print]);
+    }
+
+    #[test]
+    /// Test that a message outlasts key presses and a menu opened over it,
+    /// that errors are shown first and in red, and that the log lists them
+    fn test_messages() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        let start = Instant::now();
+        app.tick(start);
+        replay(&mut app, [InputEvent::ToggleCaps]);
+        assert_eq!(app.next_deadline(), Some(start + INFO_TTL));
+        replay(&mut app, keys("ab"));
+        assert_eq!(app.view(start).status.as_deref(), Some("Capitals on"));
+
+        // The menu hides the status row, and the message's time stops with it
+        replay(&mut app, [InputEvent::Escape]);
+        app.tick(start + INFO_TTL / 2);
+        app.tick(start + INFO_TTL * 10);
+        assert_eq!(app.next_deadline(), None);
+        replay(&mut app, [InputEvent::Escape]);
+        app.tick(start + INFO_TTL * 10);
+        assert_eq!(app.view(start).status.as_deref(), Some("Capitals on"));
+
+        app.notify(Level::Error, "Couldn't copy: no clipboard".to_string());
+        replay(&mut app, [InputEvent::ToggleCaps]);
+        let view = app.view(start);
+        assert_eq!(view.status.as_deref(), Some("Couldn't copy: no clipboard … and 2 more"));
+        assert_eq!(view.tone, StatusTone::Over);
+
+        replay(&mut app, [InputEvent::MessageLog]);
+        let log = app.view(start).overlays;
+        assert_eq!(log[0].lines, ["Capitals off", "Couldn't copy: no clipboard", "Capitals on"]);
+        replay(&mut app, [InputEvent::Char('x')]);
+        assert!(matches!(app.mode, AppMode::Editing));
+    }
+
+    #[test]
+    /// Test that the changes since the last save are underlined on request,
+    /// with the status row saying so, and that the setting starts it on
+    fn test_toggle_changes() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        assert!(!app.view(Instant::now()).show_changes);
+
+        replay(&mut app, [InputEvent::ToggleChanges]);
+        let view = app.view(Instant::now());
+        assert!(view.show_changes);
+        assert_eq!(view.status.as_deref(), Some("Changes since the last save underlined"));
+        // Once the message's time is up, typing or not
+        replay(&mut app, keys("a"));
+        let now = Instant::now();
+        app.tick(now);
+        app.tick(now + INFO_TTL);
+        assert_eq!(app.view(now).status.as_deref(), Some("Showing changes since saving"));
+
+        replay(&mut app, [InputEvent::ToggleChanges, InputEvent::Char('b')]);
+        app.tick(now + INFO_TTL * 2);
+        let view = app.view(now);
+        assert!(!view.show_changes);
+        assert_eq!(view.status, None);
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut stats = session_stats();
+        let options = SessionOptions { show_changes: true, ..SessionOptions::default() };
+        let shown = App::new(&mut buffer, &mut pomodoro, &mut stats, KeyMap::default(), (80, 24), options);
+        assert!(shown.view(Instant::now()).show_changes);
+    }
+
+    #[test]
+    /// Test that a session started with `--reveal-rate 0` draws typing at
+    /// once, with its clicks, and never waits for text to appear, unlike one
+    /// with the default delay
+    fn test_zero_reveal_rate() {
+        use crate::cli::Cli;
+        use clap::Parser;
+
+        let session = |args: &[&str]| -> (Vec<Option<Instant>>, Vec<Effect>, String) {
+            let mut config = config::Config::default();
+            Cli::try_parse_from(args).unwrap().apply(&mut config);
+            let mut buffer = config.buffer_builder().build().unwrap();
+            let mut pomodoro = None;
+            let mut stats = session_stats();
+            let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+            let now = Instant::now();
+            let mut wakes = Vec::new();
+            let mut effects = Vec::new();
+            for event in keys("Hi there").into_iter().chain([InputEvent::NewLine, InputEvent::Char('x')]) {
+                effects.extend(app.handle(event, now));
+                effects.extend(app.tick(now));
+                wakes.push(app.next_reveal(now));
+            }
+            let lines = app.buffer().lines.iter().map(|line| {
+                line.characters.iter().filter(|c| app.buffer().is_revealed(c, now)).map(|c| c.value).collect()
+            });
+            (wakes, effects, lines.collect::<Vec<String>>().join("\n"))
+        };
+
+        let (wakes, effects, shown) = session(&["typewriter", "--reveal-rate", "0"]);
+        assert!(wakes.iter().all(Option::is_none));
+        assert_eq!(shown, "Hi there\nx");
+        let clicks = effects.iter().filter(|effect| matches!(effect, Effect::Sound { .. }));
+        assert!(clicks.count() > 0);
+
+        let (wakes, _, shown) = session(&["typewriter"]);
+        assert!(wakes.iter().all(Option::is_some));
+        assert_eq!(shown, "\n");
+    }
+
+    #[test]
+    /// Test that fitting a page shows the lines left, warns near the end,
+    /// rings once when the page is exactly full and tints the lines past it
+    fn test_fit_page() {
+        // 52 printed lines, the last of them wrapped in two
+        let mut text = "Dear reader,\n".repeat(50);
+        text.push_str(&"It is a long line. ".repeat(6));
+        let mut buffer = Buffer::from_reader(text.as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let options = SessionOptions { fit_page: true, ..SessionOptions::default() };
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, options);
+        let now = Instant::now();
+        assert!(app.tick(now).is_empty());
+        let view = app.view(now);
+        assert_eq!(view.status.as_deref(), Some("Page: 4 lines left"));
+        assert_eq!(view.tone, StatusTone::Warning);
+        assert_eq!(view.overflow_from, None);
+
+        // Running the long line onto a third printed line leaves three
+        replay(&mut app, keys("And on and on until it wraps again."));
+        assert!(app.tick(now).is_empty());
+        assert_eq!(app.view(now).status.as_deref(), Some("Page: 3 lines left"));
+
+        let mut bells = 0;
+        for _ in 0..5 {
+            replay(&mut app, [InputEvent::NewLine]);
+            bells += app.tick(now).len();
+        }
+        assert_eq!(bells, 1);
+        let view = app.view(now);
+        assert_eq!(view.status.as_deref(), Some("Page: 2 lines over"));
+        assert_eq!(view.tone, StatusTone::Over);
+        assert_eq!(view.overflow_from, Some(54));
+    }
+
+    #[test]
+    /// Test that a status template fills in the row the width of the page,
+    /// with what the row says otherwise as {mode} and messages in its place
+    fn test_status_template() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.file_path = Some("/home/me/notes.txt".to_string());
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let template = "{file}{modified} │ Ln {line}, Col {col} │ {words}w {>} {mode} │ {sound}";
+        let options = SessionOptions {
+            status: crate::status::parse_status(template).ok(),
+            padding_left: 2,
+            ..SessionOptions::default()
+        };
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, options);
+        replay(&mut app, keys("hello world"));
+        let now = Instant::now();
+        assert_eq!(app.view(now).status.as_deref(), Some("notes.txt* │ Ln 1, Col 12 │ 2w"));
+
+        app.set_sound(true);
+        replay(&mut app, [InputEvent::ToggleCaps]);
+        assert_eq!(app.view(now).status.as_deref(), Some("Capitals on"));
+        app.tick(now);
+        app.tick(now + INFO_TTL);
+        let status = app.view(now).status.unwrap();
+        assert!(status.starts_with("notes.txt* │ Ln 1, Col 12 │ 2w    "));
+        assert!(status.ends_with("    Typing in capitals │ ♪"));
+        assert_eq!(status.chars().count(), 78);
+    }
+
+    #[test]
+    /// Test that the UI click is off by default, and once turned up sounds
+    /// for working the menu and prompts but not for typing
+    fn test_ui_click() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut quiet = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        let effects = replay(&mut quiet, [InputEvent::Escape, InputEvent::Down, InputEvent::Escape]);
+        assert!(!effects.iter().any(|effect| matches!(effect, Effect::Sound { .. })));
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let options = SessionOptions { ui_click_volume: 50, ..Default::default() };
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, options);
+        let ticks = |effects: Vec<Effect>| {
+            let ticks = effects.iter().filter(|effect| matches!(effect, Effect::Sound { sound: SoundType::UiTick(_), .. }));
+            ticks.count()
+        };
+        assert_eq!(ticks(replay(&mut app, keys("ab"))), 0);
+        // Down to "Toggle Sound", which clicks once, not again for the toggle it runs
+        assert_eq!(ticks(replay(&mut app, [InputEvent::Escape, InputEvent::Down, InputEvent::Down])), 3);
+        let effects = replay(&mut app, [InputEvent::NewLine]);
+        assert!(effects.contains(&Effect::ToggleSound));
+        assert_eq!(ticks(effects), 1);
+
+        // A file name typed into the prompt is quiet; submitting it isn't
+        replay(&mut app, [InputEvent::SaveAs]);
+        assert_eq!(ticks(replay(&mut app, keys("x").into_iter().chain([InputEvent::Backspace]))), 0);
+        assert_eq!(ticks(replay(&mut app, [InputEvent::Escape])), 1);
+        let effects = replay(&mut app, [InputEvent::ToggleCaps, InputEvent::Ignored]);
+        assert!(matches!(effects[0], Effect::Sound { sound: SoundType::UiTick(volume), .. } if volume == 0.5));
+        assert_eq!(ticks(effects), 1);
+    }
+
+    #[test]
+    /// Test that input keeps the session active and that losing focus starts
+    /// idle time, while replayed keys and timeouts don't count as input
+    fn test_activity() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let start = Instant::now();
+        let mut stats = SessionStats::start(&buffer, start, Duration::from_secs(90));
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        let second = Duration::from_secs(1);
+        app.handle(InputEvent::Char('a'), start + 60 * second);
+        app.handle(InputEvent::FocusLost, start + 80 * second);
+        app.tick(start + 100 * second);
+        app.handle(InputEvent::FocusGained, start + 1000 * second);
+        app.handle(InputEvent::Char('b'), start + 1030 * second);
+        app.tick(start + 1200 * second);
+        assert_eq!(stats.active(start + 1040 * second), 120 * second);
+    }
+
+    #[test]
+    /// Test that the timers ring through ticks: a sprint that has run out
+    /// shows its summary, which any key closes
+    fn test_tick_sprint() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let options = SessionOptions { sprint: Some(Duration::from_secs(60)), ..Default::default() };
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, options);
+        let now = Instant::now();
+        assert_eq!(app.tick(now), vec![]);
+        assert!(app.next_deadline().is_some());
+
+        // No closing before the end
+        let effects = replay(&mut app, [InputEvent::Close]);
+        assert!(effects.contains(&Effect::Alert(Alert::Flash)));
+        assert!(app.view(now).status.unwrap().starts_with("No stopping mid-sprint"));
+
+        assert_eq!(app.tick(now + Duration::from_secs(61)), vec![Effect::Alert(Alert::Bell(1))]);
+        assert!(matches!(app.mode, AppMode::SprintOver(_)));
+        assert_eq!(app.next_deadline(), None);
+        replay(&mut app, keys("x"));
+        assert!(matches!(app.mode, AppMode::Editing));
+        assert_eq!(replay(&mut app, [InputEvent::Close]).last(), Some(&Effect::Exit(SessionEnd::Closed)));
+    }
+
+    #[test]
+    /// Test that the terminal's own events become effects whatever the mode,
+    /// and that a resize is kept for laying out prompts
+    fn test_terminal_effects() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        replay(&mut app, [InputEvent::Save]);
+        assert_eq!(replay(&mut app, [InputEvent::Resize(40, 10)]), vec![Effect::Resize(40, 10)]);
+        assert_eq!(app.size, (40, 10));
+        assert_eq!(replay(&mut app, [InputEvent::Redraw]), vec![Effect::Redraw]);
+        assert!(matches!(app.mode, AppMode::SaveAs { .. }));
+
+        replay(&mut app, [InputEvent::Escape]);
+        let effects = replay(&mut app, [InputEvent::ScrollUp, InputEvent::Click(3, 4), InputEvent::CopyAll]);
+        let expected = [Effect::Scroll(-SCROLL_LINES), Effect::Click(3, 4), Effect::FollowCursor, Effect::Copy(String::new())];
+        assert_eq!(effects, expected);
+    }
+
+    #[test]
+    /// Test that a snapshot goes beside the file without saving it, and that
+    /// an untitled page has nowhere for one
+    fn test_snapshot() {
+        let dir = ScratchDir::new("app-snapshot");
+        let path = dir.join("draft.txt");
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let mut untitled = app(&mut buffer, &mut pomodoro, &mut stats, SessionOptions::default());
+        replay(&mut untitled, keys("Lost"));
+        replay(&mut untitled, [InputEvent::Snapshot]);
+        assert_eq!(untitled.view(Instant::now()).status.unwrap(), "Save the file first; snapshots are kept beside it");
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.file_path = Some(path.display().to_string());
+        let options = SessionOptions { snapshot_limit: 2, ..Default::default() };
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, options);
+        for (typed, number) in [("First", 1), (" second", 2), (" third", 2)] {
+            replay(&mut app, keys(typed));
+            replay(&mut app, [InputEvent::Snapshot]);
+            assert_eq!(app.view(Instant::now()).status.unwrap(), format!("Snapshot {} saved", number));
+        }
+        let kept = snapshots::list(&path).unwrap();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(std::fs::read_to_string(&kept[1].path).unwrap(), "First second third\n");
+        assert!(!path.exists());
+        assert!(app.buffer.is_modified());
+    }
+
+    #[test]
+    /// Test that restoring a snapshot replaces untouched text straight away,
+    /// but asks before replacing unsaved changes
+    fn test_restore() {
+        let restore = Some(("20240301-142530".to_string(), "Earlier draft".to_string()));
+        let mut buffer = Buffer::from_reader("Saved text".as_bytes(), Duration::ZERO).unwrap();
+        let mut pomodoro = None;
+        let mut stats = session_stats();
+        let options = SessionOptions { restore: restore.clone(), ..Default::default() };
+        let restored = app(&mut buffer, &mut pomodoro, &mut stats, options);
+        assert!(matches!(restored.mode, AppMode::Editing));
+        assert_eq!(restored.view(Instant::now()).status.unwrap(), "Restored snapshot 20240301-142530; saving puts it in the file");
+        assert_eq!(buffer.text(), "Earlier draft");
+        assert!(buffer.is_modified());
+
+        // With unsaved changes, anything but `y` keeps them
+        let mut buffer = Buffer::from_reader("Saved text".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        buffer.insert_char('!');
+        let options = SessionOptions { restore: restore.clone(), ..Default::default() };
+        let mut declined = app(&mut buffer, &mut pomodoro, &mut stats, options);
+        let question = declined.view(Instant::now()).status.unwrap();
+        assert_eq!(question, "Replace the unsaved changes with snapshot 20240301-142530? (y/n) ");
+        replay(&mut declined, keys("n"));
+        assert!(matches!(declined.mode, AppMode::Editing));
+        assert_eq!(buffer.text(), "Saved text!");
+
+        let mut buffer = Buffer::from_reader("Saved text".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        buffer.insert_char('!');
+        let options = SessionOptions { restore, ..Default::default() };
+        let mut app = app(&mut buffer, &mut pomodoro, &mut stats, options);
+        assert_eq!(replay(&mut app, keys("y")), vec![Effect::FollowCursor]);
+        assert!(matches!(app.mode, AppMode::Editing));
+        assert_eq!(buffer.text(), "Earlier draft");
+    }
+}
@@ -0,0 +1,402 @@
+use chrono::NaiveDateTime;
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::{Alert, App, Effect, SessionOptions};
+use crate::clock::Clock;
+use crate::editor::BufferBuilder;
+use crate::input::{InputEvent, InputHandler, KeyChord, KeyMap, ScriptedSource};
+use crate::journal;
+use crate::lock::Owner;
+use crate::paste;
+use crate::renderer::Renderer;
+use crate::saving::Saver;
+use crate::scratch::ScratchDir;
+use crate::sound::SoundType;
+use crate::stats::{self, SessionStats};
+
+/// Where the session scripts and their goldens are kept
+const SESSIONS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/sessions");
+
+/// The local time every session starts at
+const START: &str = "2024-06-03 09:05";
+
+/// Columns and rows of the terminal unless a script sets them
+const DEFAULT_SIZE: (u16, u16) = (40, 10);
+
+/// Stands for the session's scratch directory in scripts and goldens
+const DIR: &str = "{dir}";
+
+/// A line of a session script. Setting up comes first, then events and what
+/// to look at between them.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// `size 40x10`: the terminal's columns and rows
+    Size(u16, u16),
+    /// `margin 24`: the hard margin
+    Margin(usize),
+    /// `create NAME TEXT`: a file in the scratch directory
+    Create(String, String),
+    /// `open NAME`: a file in the scratch directory to type into
+    Open(String),
+    /// `cursor LINE COLUMN`: where the cursor goes back to in the file, both
+    /// counted from 1, as on reopening it
+    Cursor(usize, usize),
+    /// `journal`: the day's journal entry in the scratch directory to type into
+    Journal,
+    /// `locked`: another typewriter has the file open
+    Locked,
+    /// `type TEXT`: key presses, one per character
+    Type(String),
+    /// `key CHORD...`: key presses such as `ctrl+s enter`
+    Keys(Vec<KeyChord>),
+    /// `paste TEXT`: text pasted into the terminal
+    Paste(String),
+    /// `wait MS`: moves the clock on, letting timers run and questions time out
+    Wait(Duration),
+    /// `screen`: the screen as plain text
+    Screen,
+    /// `file NAME`: the bytes of a file in the scratch directory
+    File(String),
+}
+
+impl Step {
+    /// Returns whether it sets up the session, before the first event
+    fn is_setup(&self) -> bool {
+        matches!(
+            self,
+            Step::Size(..)
+                | Step::Margin(_)
+                | Step::Create(..)
+                | Step::Open(_)
+                | Step::Cursor(..)
+                | Step::Journal
+                | Step::Locked
+        )
+    }
+}
+
+/// Reads a script line, or None for a blank line or a `#` comment
+fn parse_step(line: &str) -> Result<Option<Step>, String> {
+    if line.trim().is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let number = |text: &str| text.parse::<u64>().map_err(|_| format!("`{}` isn't a number", text));
+    let step = match command {
+        "size" => {
+            let (columns, rows) = rest.split_once('x').ok_or_else(|| format!("`{}` isn't a size", rest))?;
+            Step::Size(number(columns)? as u16, number(rows)? as u16)
+        }
+        "margin" => Step::Margin(number(rest)? as usize),
+        "create" => {
+            let (name, text) = rest.split_once(' ').unwrap_or((rest, ""));
+            Step::Create(name.to_string(), unescape(text))
+        }
+        "open" => Step::Open(rest.to_string()),
+        "cursor" => {
+            let (line, column) = rest.split_once(' ').ok_or_else(|| format!("`{}` isn't a line and column", rest))?;
+            Step::Cursor(number(line)?.max(1) as usize, number(column)?.max(1) as usize)
+        }
+        "journal" => Step::Journal,
+        "locked" => Step::Locked,
+        "type" => Step::Type(unescape(rest)),
+        "key" => Step::Keys(rest.split_whitespace().map(KeyChord::parse).collect::<Result<_, _>>()?),
+        "paste" => Step::Paste(unescape(rest)),
+        "wait" => Step::Wait(Duration::from_millis(number(rest)?)),
+        "screen" => Step::Screen,
+        "file" => Step::File(rest.to_string()),
+        _ => return Err(format!("unknown step `{}`", command)),
+    };
+    Ok(Some(step))
+}
+
+/// Turns `\n`, `\t` and `\\` into what they stand for
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Describes an effect for the transcript, with sound times counted from the
+/// start of the session
+fn describe(effect: &Effect, start: Instant) -> String {
+    match effect {
+        Effect::Sound { sound, at } => {
+            let sound = match sound {
+                SoundType::KeyPress(_) => "key",
+                SoundType::Return => "return",
+                SoundType::Word(_) => "word",
+                SoundType::Bell => "bell",
+                SoundType::UiTick(_) => "tick",
+            };
+            format!("sound {} +{}ms", sound, at.saturating_duration_since(start).as_millis())
+        }
+        Effect::Alert(Alert::Bell(1)) => "bell".to_string(),
+        Effect::Alert(Alert::Bell(times)) => format!("bell rung {} times", times),
+        Effect::Alert(Alert::Flash) => "flash".to_string(),
+        Effect::ToggleSound => "toggle sound".to_string(),
+        Effect::Write { path, text } => format!("write {} ({} bytes)", path, text.len()),
+        Effect::Saved { path, words } => format!("saved {} ({} words)", path, words),
+        Effect::FollowCursor => "follow cursor".to_string(),
+        Effect::Scroll(lines) => format!("scroll {}", lines),
+        Effect::Resize(columns, rows) => format!("resize {}x{}", columns, rows),
+        Effect::Redraw => "redraw".to_string(),
+        Effect::Click(column, row) => format!("click {},{}", column, row),
+        Effect::Copy(text) => format!("copy {:?}", text),
+        Effect::Print { text, title } => format!("print {} ({} bytes)", title, text.len()),
+        Effect::Exit(end) => format!("exit {:?}", end),
+    }
+}
+
+/// Writes up a step's effects, each different one once with how many times
+/// it came, in the order they first came
+fn summarize(effects: &[Effect], start: Instant, transcript: &mut String) {
+    let mut seen: Vec<(String, usize)> = Vec::new();
+    for description in effects.iter().map(|effect| describe(effect, start)) {
+        match seen.iter_mut().find(|(seen, _)| *seen == description) {
+            Some((_, count)) => *count += 1,
+            None => seen.push((description, 1)),
+        }
+    }
+    for (description, count) in seen {
+        match count {
+            1 => transcript.push_str(&format!("  {}\n", description)),
+            count => transcript.push_str(&format!("  {} ×{}\n", description, count)),
+        }
+    }
+}
+
+/// Reads terminal events through an input handler with the default bindings,
+/// as if each came a moment after the last
+async fn input(events: Vec<Event>) -> io::Result<Vec<InputEvent>> {
+    let count = events.len();
+    let source = ScriptedSource::new(events.into_iter().map(Some));
+    let mut handler = InputHandler::with_source(Duration::ZERO, source);
+    let mut input = Vec::new();
+    for _ in 0..count {
+        match handler.next_event().await? {
+            InputEvent::Ignored => (),
+            event => input.push(event),
+        }
+    }
+    Ok(input)
+}
+
+
+/// Plays a session script against the app in a scratch directory, on a clock
+/// that only moves when the script waits and with no sound, and returns the
+/// transcript: each line of the script, followed by the effects it had and
+/// what it looked at
+async fn play(name: &str, script: &str) -> Result<String, String> {
+    let mut steps = Vec::new();
+    for (number, line) in script.lines().enumerate() {
+        let step = parse_step(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        steps.extend(step.map(|step| (line, step)));
+    }
+    let setup = steps.iter().take_while(|(_, step)| step.is_setup()).count();
+    if let Some((line, _)) = steps[setup..].iter().find(|(_, step)| step.is_setup()) {
+        return Err(format!("`{}` comes after the first event", line));
+    }
+
+    let dir = ScratchDir::new(&format!("session-{}", name));
+    let played = play_in(&dir, &steps, setup).await.map_err(|e| e.to_string());
+    Ok(played?.replace(&*dir.to_string_lossy(), DIR))
+}
+
+/// Plays the steps of a script in `dir`, the first `setup` of them setting up
+async fn play_in(dir: &Path, steps: &[(&str, Step)], setup: usize) -> io::Result<String> {
+    let local = NaiveDateTime::parse_from_str(START, "%Y-%m-%d %H:%M").expect("the start time is valid");
+    let clock = Clock::fixed(local);
+    let start = clock.now();
+    let mut transcript = String::new();
+
+    let (mut size, mut builder, mut locked_by) = (DEFAULT_SIZE, BufferBuilder::new().clock(clock.clone()), None);
+    let (mut open, mut journal) = (None, false);
+    for (line, step) in &steps[..setup] {
+        transcript.push_str(&format!("{}\n", line));
+        match step {
+            Step::Size(columns, rows) => size = (*columns, *rows),
+            Step::Margin(columns) => builder = builder.hard_margin(Some(*columns)),
+            Step::Create(name, text) => fs::write(dir.join(name), text)?,
+            Step::Open(name) => open = Some(dir.join(name)),
+            Step::Cursor(line, column) => builder = builder.cursor(Some((line - 1, column - 1))),
+            Step::Journal => journal = true,
+            Step::Locked => locked_by = Some(Owner { pid: 4242, host: "elsewhere".to_string() }),
+            _ => unreachable!("only setup steps come before the first event"),
+        }
+    }
+    let mut buffer = match open {
+        _ if journal => journal::open_entry(&dir.join("journal"), clock.local(), builder)?,
+        Some(path) => builder.open(&path.to_string_lossy())?,
+        None => builder.build()?,
+    };
+
+    let mut pomodoro = None;
+    let mut stats = SessionStats::start(&buffer, start, stats::DEFAULT_IDLE_THRESHOLD);
+    let options = SessionOptions {
+        locked_by,
+        paste_confirm: paste::DEFAULT_CONFIRM_BYTES,
+        clock: clock.clone(),
+        ..SessionOptions::default()
+    };
+    let mut renderer = Renderer::new(Vec::new());
+    renderer.resize(size.0, size.1);
+    let mut app = App::new(&mut buffer, &mut pomodoro, &mut stats, KeyMap::default(), size, options);
+    let mut saver = Saver::new(app.options().writer);
+    let mut ended = false;
+    crate::session::show(app.view(start), &mut renderer);
+    renderer.render(app.buffer())?;
+
+    for (line, step) in &steps[setup..] {
+        transcript.push_str(&format!("> {}\n", line));
+        let events = match step {
+            Step::Type(text) => {
+                let text = text.replace(DIR, &dir.to_string_lossy());
+                input(text.chars().map(|c| Event::Key(KeyEvent::from(KeyCode::Char(c)))).collect()).await?
+            }
+            Step::Keys(chords) => {
+                let keys = chords.iter().map(|chord| Event::Key(KeyEvent::new(chord.code, chord.modifiers)));
+                input(keys.collect()).await?
+            }
+            Step::Paste(text) => input(vec![Event::Paste(text.replace(DIR, &dir.to_string_lossy()))]).await?,
+            // Nothing is typed in the meantime; the timers run as the event
+            // loop wakes for them
+            Step::Wait(delay) => {
+                clock.advance(*delay);
+                Vec::new()
+            }
+            Step::Screen => {
+                for row in renderer.screen() {
+                    transcript.push_str(&format!("  |{}\n", row));
+                }
+                continue;
+            }
+            Step::File(name) => match fs::read(dir.join(name)) {
+                Ok(bytes) => {
+                    transcript.push_str(&format!("  {:?}\n", String::from_utf8_lossy(&bytes)));
+                    continue;
+                }
+                Err(e) => {
+                    transcript.push_str(&format!("  {}\n", e.kind()));
+                    continue;
+                }
+            },
+            _ => unreachable!("setup steps come before the first event"),
+        };
+        if ended && !events.is_empty() {
+            return Err(io::Error::other(format!("`{}` comes after the session ended", line)));
+        }
+
+        // Each line is one burst of input, after which the event loop takes in
+        // a finished save, runs the timers and draws a frame
+        let mut effects = Vec::new();
+        for event in events {
+            let handled = app.handle(event, clock.now());
+            effects.extend(handled.clone());
+            ended |= crate::session::apply(handled, &mut app, &mut renderer, &mut None, &mut None, &mut saver).await?.is_some();
+            if ended {
+                break;
+            }
+        }
+        while saver.is_busy() && !ended {
+            let written = app.written(saver.finished().await, clock.now());
+            effects.extend(written.clone());
+            ended |= crate::session::apply(written, &mut app, &mut renderer, &mut None, &mut None, &mut saver).await?.is_some();
+        }
+        if !ended {
+            let ticked = app.tick(clock.now());
+            effects.extend(ticked.clone());
+            ended |= crate::session::apply(ticked, &mut app, &mut renderer, &mut None, &mut None, &mut saver).await?.is_some();
+        }
+        summarize(&effects, start, &mut transcript);
+        crate::session::show(app.view(clock.now()), &mut renderer);
+        renderer.render(app.buffer())?;
+        renderer.output.clear();
+    }
+    Ok(transcript)
+}
+
+/// Returns the lines that differ between a golden and a played session, or
+/// None if they are the same
+fn diff(golden: &str, played: &str) -> Option<String> {
+    let (golden, played): (Vec<&str>, Vec<&str>) = (golden.lines().collect(), played.lines().collect());
+    let mut differences = String::new();
+    for idx in 0..golden.len().max(played.len()) {
+        let (expected, actual) = (golden.get(idx), played.get(idx));
+        if expected != actual {
+            differences.push_str(&format!("line {}:\n", idx + 1));
+            differences.push_str(&format!("  - {}\n", expected.unwrap_or(&"")));
+            differences.push_str(&format!("  + {}\n", actual.unwrap_or(&"")));
+        }
+    }
+    (!differences.is_empty()).then_some(differences)
+}
+
+#[tokio::test]
+/// Test that every session script plays out as its golden says. With
+/// UPDATE_GOLDENS set, the goldens are written afresh instead.
+async fn test_sessions() {
+    let update = env::var_os("UPDATE_GOLDENS").is_some();
+    let mut scripts: Vec<PathBuf> = fs::read_dir(SESSIONS_DIR)
+        .expect("the session scripts can be listed")
+        .map(|entry| entry.expect("the session scripts can be listed").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "session"))
+        .collect();
+    scripts.sort();
+    assert!(scripts.len() >= 6);
+
+    let mut failures = Vec::new();
+    for script in scripts {
+        let name = script.file_stem().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let text = fs::read_to_string(&script).expect("the session script can be read");
+        let played = play(&name, &text).await.unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let golden = script.with_extension("golden");
+        if update {
+            fs::write(&golden, &played).expect("the golden can be written");
+            continue;
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_default();
+        if let Some(differences) = diff(&expected, &played) {
+            failures.push(format!("{}:\n{}", name, differences));
+        }
+    }
+    assert!(failures.is_empty(), "set UPDATE_GOLDENS=1 to accept the changes\n{}", failures.join("\n"));
+}
+
+#[test]
+/// Test reading script lines, and that ones that can't be read are named
+fn test_parse_step() {
+    assert_eq!(parse_step("# a comment"), Ok(None));
+    assert_eq!(parse_step(""), Ok(None));
+    assert_eq!(parse_step("size 30x8"), Ok(Some(Step::Size(30, 8))));
+    assert_eq!(parse_step("type Dear Sir,\\n"), Ok(Some(Step::Type("Dear Sir,\n".to_string()))));
+    assert_eq!(
+        parse_step("create notes.txt one\\ttwo \\\\"),
+        Ok(Some(Step::Create("notes.txt".to_string(), "one\ttwo \\".to_string())))
+    );
+    assert_eq!(
+        parse_step("key ctrl+s enter"),
+        Ok(Some(Step::Keys(vec![KeyChord::parse("ctrl+s").unwrap(), KeyChord::parse("enter").unwrap()])))
+    );
+    assert_eq!(parse_step("wait 1500"), Ok(Some(Step::Wait(Duration::from_millis(1500)))));
+    assert_eq!(parse_step("wait soon"), Err("`soon` isn't a number".to_string()));
+    assert_eq!(parse_step("key ctrl+"), Err("invalid key chord `ctrl+`".to_string()));
+    assert_eq!(parse_step("press y"), Err("unknown step `press`".to_string()));
+}
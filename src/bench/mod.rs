@@ -0,0 +1,179 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::editor::Buffer;
+use crate::renderer::Renderer;
+
+/// What the synthetic page says, over and over
+const SENTENCE: &str = "The quick brown fox jumps over the lazy dog. ";
+
+/// How big a benchmark run is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sizes {
+    /// Lines on the synthetic page
+    pub lines: usize,
+    /// Characters on each of its lines
+    pub columns: usize,
+    /// Frames laid out and drawn
+    pub frames: usize,
+    /// Characters typed and backspaced over
+    pub edits: usize,
+}
+
+impl Default for Sizes {
+    fn default() -> Self {
+        Self {
+            lines: 10_000,
+            columns: 80,
+            frames: 1_000,
+            edits: 100_000,
+        }
+    }
+}
+
+/// How long the runs of one scenario took, in microseconds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Timings {
+    /// What was timed
+    pub scenario: &'static str,
+    /// How many times
+    pub runs: usize,
+    /// The median run
+    pub p50_us: f64,
+    /// The run slower than nine in ten
+    pub p90_us: f64,
+    /// The run slower than 99 in 100
+    pub p99_us: f64,
+    /// The slowest run
+    pub max_us: f64,
+}
+
+impl Timings {
+    /// Sums up the times of a scenario's runs
+    fn from_runs(scenario: &'static str, mut runs: Vec<Duration>) -> Self {
+        runs.sort_unstable();
+        let percentile = |percent: usize| {
+            let index = (runs.len() * percent).div_ceil(100).saturating_sub(1);
+            runs.get(index).map_or(0.0, |run| run.as_nanos() as f64 / 1000.0)
+        };
+        Self {
+            scenario,
+            runs: runs.len(),
+            p50_us: percentile(50),
+            p90_us: percentile(90),
+            p99_us: percentile(99),
+            max_us: percentile(100),
+        }
+    }
+}
+
+/// Returns a page of `lines` lines, each `columns` characters of the
+/// sentence carrying on from the line before
+pub fn synthetic_text(lines: usize, columns: usize) -> String {
+    let mut characters = SENTENCE.chars().cycle();
+    let mut text = String::with_capacity(lines * (columns + 1));
+    for _ in 0..lines {
+        text.extend(characters.by_ref().take(columns));
+        text.push('\n');
+    }
+    text
+}
+
+/// Returns a buffer holding the synthetic page, with everything already shown
+pub fn synthetic_buffer(lines: usize, columns: usize) -> io::Result<Buffer> {
+    Buffer::from_reader(synthetic_text(lines, columns).as_bytes(), Duration::ZERO)
+}
+
+/// Runs every scenario at the given sizes, without touching the terminal
+pub fn run(sizes: Sizes) -> io::Result<Vec<Timings>> {
+    let start = Instant::now();
+    let mut buffer = synthetic_buffer(sizes.lines, sizes.columns)?;
+    let load = Timings::from_runs("load", vec![start.elapsed()]);
+    buffer.move_to_end();
+    Ok(vec![
+        load,
+        render(&mut buffer, sizes, false)?,
+        render(&mut buffer, sizes, true)?,
+        edit(&mut buffer, sizes),
+    ])
+}
+
+/// Times frames drawn into nothing, a character typed before each. A full
+/// frame draws the whole screen afresh rather than just what changed.
+fn render(buffer: &mut Buffer, sizes: Sizes, full: bool) -> io::Result<Timings> {
+    let mut renderer = Renderer::new(io::sink());
+    renderer.render(buffer)?;
+    let mut runs = Vec::with_capacity(sizes.frames);
+    for frame in 0..sizes.frames {
+        type_char(buffer, frame, sizes.columns);
+        let start = Instant::now();
+        if full {
+            renderer.invalidate();
+        }
+        renderer.render(buffer)?;
+        runs.push(start.elapsed());
+    }
+    Ok(Timings::from_runs(if full { "render_full" } else { "render_typing" }, runs))
+}
+
+/// Times typing, with every tenth key a backspace
+fn edit(buffer: &mut Buffer, sizes: Sizes) -> Timings {
+    let mut runs = Vec::with_capacity(sizes.edits);
+    for edit in 0..sizes.edits {
+        let start = Instant::now();
+        match edit % 10 {
+            9 => buffer.backspace(),
+            _ => type_char(buffer, edit, sizes.columns),
+        }
+        runs.push(start.elapsed());
+    }
+    Timings::from_runs("edit", runs)
+}
+
+/// Types the sentence's character at `count`, starting a new line at `columns`
+fn type_char(buffer: &mut Buffer, count: usize, columns: usize) {
+    if buffer.cursor_position().1 >= columns {
+        buffer.new_line();
+    }
+    let index = count % SENTENCE.len();
+    buffer.insert_char(SENTENCE[index..].chars().next().unwrap_or(' '));
+}
+
+/// Formats the timings as one line of JSON
+pub fn to_json(timings: &[Timings]) -> String {
+    serde_json::to_string(timings).expect("timings are always valid JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the synthetic page has the lines and columns asked for
+    fn test_synthetic_text() {
+        let text = synthetic_text(3, 12);
+        assert_eq!(text, "The quick br\nown fox jump\ns over the l\n");
+        let buffer = synthetic_buffer(100, 80).unwrap();
+        assert_eq!(buffer.lines.len(), 101);
+        assert!(buffer.lines[..100].iter().all(|line| line.len() == 80));
+    }
+
+    #[test]
+    /// Test that a small run completes and prints parseable timings
+    fn test_run() {
+        let sizes = Sizes { lines: 200, columns: 40, frames: 20, edits: 500 };
+        let timings = run(sizes).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&to_json(&timings)).unwrap();
+        let runs = json.as_array().unwrap();
+        let scenarios: Vec<&str> = runs.iter().map(|run| run["scenario"].as_str().unwrap()).collect();
+        assert_eq!(scenarios, ["load", "render_typing", "render_full", "edit"]);
+        assert_eq!(json[3]["runs"], 500);
+        for run in runs {
+            let time = |field: &str| run[field].as_f64().unwrap();
+            assert!(time("p50_us") <= time("p90_us") && time("p90_us") <= time("p99_us"));
+            assert!(time("p99_us") <= time("max_us"));
+        }
+    }
+}
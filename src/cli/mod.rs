@@ -0,0 +1,872 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::editor::{RevealCurve, RevealMode};
+use crate::paragraph::Limit;
+use crate::renderer::Background;
+use crate::sound::{BellStyle, KeyboardLayout};
+use crate::status::StatusTemplate;
+
+/// A typewriter simulator that provides a more deliberate writing experience
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// File to open, created if it doesn't exist
+    pub file: Option<String>,
+
+    /// Open today's journal entry, under a heading with the time
+    #[arg(long, conflicts_with = "file")]
+    pub journal: bool,
+
+    /// Where journal entries are kept [default: ~/Documents/typewriter-journal]
+    #[arg(long, value_name = "PATH")]
+    pub journal_dir: Option<PathBuf>,
+
+    /// Also list the files here on the opening screen shown without a file
+    #[arg(long, value_name = "PATH")]
+    pub drafts_dir: Option<PathBuf>,
+
+    /// Play typewriter sounds
+    #[arg(long, overrides_with = "no_sound")]
+    pub sound: bool,
+
+    /// Don't play sounds, even if the config file or $TYPEWRITER_SOUND turns them on
+    #[arg(long, overrides_with = "sound")]
+    pub no_sound: bool,
+
+    /// How the bell rings: a tone, the terminal's bell, a flash, or not at all [default: audio]
+    #[arg(long, value_name = "audio|terminal|visual|none", value_parser = crate::sound::parse_bell)]
+    pub bell: Option<BellStyle>,
+
+    /// Volume of a quiet click for menus, prompts and toggles while sound is on, from 0 to 100 [default: 0]
+    #[arg(long, value_name = "PERCENT", value_parser = parse_volume)]
+    pub ui_click_volume: Option<u8>,
+
+    /// The keyboard typed on, so keys near each other click alike [default: qwerty]
+    #[arg(long, value_name = "qwerty|azerty|dvorak", value_parser = crate::sound::parse_layout)]
+    pub layout: Option<KeyboardLayout>,
+
+    /// Delay before a typed character appears, in milliseconds, or 0 for none [default: 300]
+    #[arg(long, value_name = "MS")]
+    pub reveal_rate: Option<u64>,
+
+    /// Show typed text a character at a time, or each word once it is finished [default: char]
+    #[arg(long, value_name = "char|word", value_parser = crate::editor::parse_reveal_mode)]
+    pub reveal_mode: Option<RevealMode>,
+
+    /// Have every character wait the whole delay, or let a burst of keys catch up [default: fixed]
+    #[arg(long, value_name = "fixed|cluster", value_parser = crate::editor::parse_reveal_curve)]
+    pub reveal_curve: Option<RevealCurve>,
+
+    /// Have an opened file type itself out before typing starts; any key skips to the end
+    #[arg(long, overrides_with = "no_reveal_on_load")]
+    pub reveal_on_load: bool,
+
+    /// Turn --reveal-on-load off, even if the config file turns it on
+    #[arg(long, overrides_with = "reveal_on_load", hide = true)]
+    pub no_reveal_on_load: bool,
+
+    /// How many characters a second an opened file types itself out at [default: 60]
+    #[arg(long, value_name = "CPS", value_parser = parse_positive)]
+    pub reveal_on_load_cps: Option<u64>,
+
+    /// How long to wait for input before checking timers, in milliseconds [default: 50]
+    #[arg(long, value_name = "MS", value_parser = parse_input_timeout)]
+    pub input_timeout: Option<u64>,
+
+    /// Most frames drawn in a second while text is appearing [default: 60]
+    #[arg(long, value_name = "FPS", value_parser = parse_max_fps)]
+    pub max_fps: Option<u64>,
+
+    /// How far the audio output lags behind the screen, in milliseconds [default: 0]
+    #[arg(long, value_name = "MS")]
+    pub audio_latency_ms: Option<u64>,
+
+    /// Play clicks with matching screen flashes to measure the audio latency
+    #[arg(long)]
+    pub calibrate_audio: bool,
+
+    /// Seed for the sound variation, to repeat an earlier session exactly
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Write for this long (such as 15m, 900s or 1h) before closing is allowed
+    #[arg(long, value_name = "DURATION", value_parser = crate::sprint::parse_duration)]
+    pub sprint: Option<Duration>,
+
+    /// Leave pauses longer than this (such as 90s or 5m) out of the active time [default: 90s]
+    #[arg(long, value_name = "DURATION", value_parser = crate::sprint::parse_duration)]
+    pub idle_threshold: Option<Duration>,
+
+    /// Alternate work and rest periods, in minutes such as 25/5
+    #[arg(long, value_name = "WORK/REST", value_parser = crate::pomodoro::parse_cycle)]
+    pub pomodoro: Option<(Duration, Duration)>,
+
+    /// Click to move the cursor and scroll with the mouse wheel
+    #[arg(long, overrides_with = "no_mouse")]
+    pub mouse: bool,
+
+    /// Turn --mouse off, even if the config file turns it on
+    #[arg(long, overrides_with = "mouse", hide = true)]
+    pub no_mouse: bool,
+
+    /// How many of the last lines stay at full brightness [default: 2]
+    #[arg(long, value_name = "N", value_parser = parse_positive)]
+    pub visible_lines: Option<u64>,
+
+    /// Dim everything before the sentence being typed, instead of all but the last lines
+    #[arg(long, overrides_with = "no_focus_sentence")]
+    pub focus_sentence: bool,
+
+    /// Turn --focus-sentence off, even if the config file turns it on
+    #[arg(long, overrides_with = "focus_sentence", hide = true)]
+    pub no_focus_sentence: bool,
+
+    /// Whether the terminal's background is light or dark, to keep faded text readable [default: auto]
+    #[arg(long, value_name = "light|dark|auto", value_parser = crate::renderer::parse_background)]
+    pub background: Option<Background>,
+
+    /// Blank rows kept above the page [default: 1]
+    #[arg(long, value_name = "ROWS")]
+    pub padding_top: Option<u16>,
+
+    /// Blank columns kept left of the page [default: 2]
+    #[arg(long, value_name = "COLUMNS")]
+    pub padding_left: Option<u16>,
+
+    /// Columns kept in view ahead of the cursor on a line wider than the window [default: 5]
+    #[arg(long, value_name = "COLUMNS")]
+    pub scroll_ahead: Option<u64>,
+
+    /// Show how much of the typing wasn't marked out, and tint lines with many corrections
+    #[arg(long, overrides_with = "no_cleanliness")]
+    pub cleanliness: bool,
+
+    /// Turn --cleanliness off, even if the config file turns it on
+    #[arg(long, overrides_with = "cleanliness", hide = true)]
+    pub no_cleanliness: bool,
+
+    /// Let Ctrl+Z undo typing and marking out on the line being typed, until Enter
+    #[arg(long, overrides_with = "no_soft_undo")]
+    pub soft_undo: bool,
+
+    /// Turn --soft-undo off, even if the config file turns it on
+    #[arg(long, overrides_with = "soft_undo", hide = true)]
+    pub no_soft_undo: bool,
+
+    /// Leave out spaces and tabs at the ends of lines when saving, except on the line being typed
+    #[arg(long, overrides_with = "no_trim_trailing_whitespace")]
+    pub trim_trailing_whitespace: bool,
+
+    /// Turn --trim-trailing-whitespace off, even if the config file turns it on
+    #[arg(long, overrides_with = "trim_trailing_whitespace", hide = true)]
+    pub no_trim_trailing_whitespace: bool,
+
+    /// Columns between the tab stops an opened file's tabs are shown up to [default: 8]
+    #[arg(long, value_name = "COLUMNS", value_parser = parse_positive)]
+    pub tab_width: Option<u64>,
+
+    /// Save the spaces shown for an opened file's tabs, rather than the tabs
+    #[arg(long, overrides_with = "no_expand_tabs_on_save")]
+    pub expand_tabs_on_save: bool,
+
+    /// Turn --expand-tabs-on-save off, even if the config file turns it on
+    #[arg(long, overrides_with = "expand_tabs_on_save", hide = true)]
+    pub no_expand_tabs_on_save: bool,
+
+    /// End the saved file with a newline, whatever its extension [default: only .txt and .md]
+    #[arg(long, overrides_with = "no_ensure_trailing_newline")]
+    pub ensure_trailing_newline: bool,
+
+    /// Save the file without adding a newline at the end
+    #[arg(long, overrides_with = "ensure_trailing_newline")]
+    pub no_ensure_trailing_newline: bool,
+
+    /// Draw spaces and tabs at the ends of lines as faint dots
+    #[arg(long, overrides_with = "no_show_trailing_whitespace")]
+    pub show_trailing_whitespace: bool,
+
+    /// Turn --show-trailing-whitespace off, even if the config file turns it on
+    #[arg(long, overrides_with = "show_trailing_whitespace", hide = true)]
+    pub no_show_trailing_whitespace: bool,
+
+    /// Draw spaces and tabs at the end of the line being typed as faint dots
+    #[arg(long, overrides_with = "no_show_current_trailing_spaces")]
+    pub show_current_trailing_spaces: bool,
+
+    /// Turn --show-current-trailing-spaces off, even if the config file turns it on
+    #[arg(long, overrides_with = "show_current_trailing_spaces", hide = true)]
+    pub no_show_current_trailing_spaces: bool,
+
+    /// Underline what has been typed or marked out since the last save
+    #[arg(long, overrides_with = "no_show_changes")]
+    pub show_changes: bool,
+
+    /// Turn --show-changes off, even if the config file turns it on
+    #[arg(long, overrides_with = "show_changes", hide = true)]
+    pub no_show_changes: bool,
+
+    /// Lay out the status row from placeholders such as {file}, {line} and {words}; {>} aligns the rest right
+    #[arg(long, value_name = "TEMPLATE", value_parser = crate::status::parse_status)]
+    pub status: Option<StatusTemplate>,
+
+    /// Refuse typing past this many characters on a line until Enter, like a carriage at its stop
+    #[arg(long, value_name = "COLUMNS", value_parser = parse_positive)]
+    pub hard_margin: Option<u64>,
+
+    /// Hint at hyphenating a word at the hard margin, and start a new line after a hyphen typed there
+    #[arg(long, overrides_with = "no_hyphen_hints")]
+    pub hyphen_hints: bool,
+
+    /// Turn --hyphen-hints off, even if the config file turns it on
+    #[arg(long, overrides_with = "hyphen_hints", hide = true)]
+    pub no_hyphen_hints: bool,
+
+    /// Work like a plain typewriter: no arrows but Right, no undo or mouse, sound on and a hard margin
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Type in capitals, as on a typewriter for labels; Ctrl+Shift+U or Alt+U turns it off
+    #[arg(long, overrides_with = "no_caps")]
+    pub caps: bool,
+
+    /// Turn --caps off, even if the config file turns it on
+    #[arg(long, overrides_with = "caps", hide = true)]
+    pub no_caps: bool,
+
+    /// Put in the space missed before a capital typed straight after `.`, `!` or `?`
+    #[arg(long, overrides_with = "no_auto_space_sentences")]
+    pub auto_space_sentences: bool,
+
+    /// Turn --auto-space-sentences off, even if the config file turns it on
+    #[arg(long, overrides_with = "auto_space_sentences", hide = true)]
+    pub no_auto_space_sentences: bool,
+
+    /// Columns the Tab key moves to, such as 12,30,48
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = parse_positive)]
+    pub tab_stops: Vec<u64>,
+
+    /// Line up numbers on the tab stops as their `.` is typed
+    #[arg(long, overrides_with = "no_decimal_align")]
+    pub decimal_align: bool,
+
+    /// Turn --decimal-align off, even if the config file turns it on
+    #[arg(long, overrides_with = "decimal_align", hide = true)]
+    pub no_decimal_align: bool,
+
+    /// Ask before typing out a paste bigger than this many kilobytes
+    #[arg(long, value_name = "KB", value_parser = parse_positive)]
+    pub paste_confirm: Option<u64>,
+
+    /// Most snapshots (F9) kept of a file, the oldest going first [default: 20]
+    #[arg(long, value_name = "COUNT", value_parser = parse_positive)]
+    pub snapshot_limit: Option<u64>,
+
+    /// Print the text after closing, through --print-command
+    #[arg(long, overrides_with = "no_print_on_exit")]
+    pub print_on_exit: bool,
+
+    /// Turn --print-on-exit off, even if the config file turns it on
+    #[arg(long, overrides_with = "print_on_exit", hide = true)]
+    pub no_print_on_exit: bool,
+
+    /// Shell command Print pipes the pages into [default: lpr]
+    #[arg(long, value_name = "COMMAND")]
+    pub print_command: Option<String>,
+
+    /// Open FILE with the text of one of its snapshots, leaving the file as it is until saved
+    #[arg(long, value_name = "NAME", requires = "file")]
+    pub restore_snapshot: Option<String>,
+
+    /// Work with a screen reader: no reveal delay or dimming, plain status and a bell on marking out
+    #[arg(long)]
+    pub accessible: bool,
+
+    /// Show the size of the paragraph being typed against this many characters, ringing once past it
+    #[arg(long, value_name = "CHARACTERS", value_parser = parse_positive, conflicts_with = "paragraph_words")]
+    pub paragraph_limit: Option<u64>,
+
+    /// Show the size of the paragraph being typed against this many words, ringing once past it
+    #[arg(long, value_name = "WORDS", value_parser = parse_positive)]
+    pub paragraph_words: Option<u64>,
+
+    /// Show how many lines are left on one printed page, ringing when it is full and tinting lines past it
+    #[arg(long, overrides_with = "no_fit_page")]
+    pub fit_page: bool,
+
+    /// Turn --fit-page off, even if the config file turns it on
+    #[arg(long, overrides_with = "fit_page", hide = true)]
+    pub no_fit_page: bool,
+
+    /// When killed or hung up, save into the open file instead of a .recovery file
+    #[arg(long, overrides_with = "no_autosave_on_exit")]
+    pub autosave_on_exit: bool,
+
+    /// Turn --autosave-on-exit off, even if the config file turns it on
+    #[arg(long, overrides_with = "autosave_on_exit", hide = true)]
+    pub no_autosave_on_exit: bool,
+
+    /// Shell command to run in the background after each save
+    #[arg(long, value_name = "COMMAND")]
+    pub on_save: Option<String>,
+
+    /// Don't print the session summary after closing
+    #[arg(long, short, overrides_with = "no_quiet")]
+    pub quiet: bool,
+
+    /// Turn --quiet off, even if the config file turns it on
+    #[arg(long, overrides_with = "quiet", hide = true)]
+    pub no_quiet: bool,
+
+    /// Read settings from this file instead of $TYPEWRITER_CONFIG or the default path
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Don't offer the setup questions on the first launch
+    #[arg(long)]
+    pub no_wizard: bool,
+
+    /// Render the typing of the --from file to an animated GIF and exit
+    #[arg(long, value_name = "OUT", requires = "from")]
+    pub render_gif: Option<PathBuf>,
+
+    /// Text file to replay for --render-gif, one character every 100 ms
+    #[arg(long, value_name = "FILE", requires = "render_gif")]
+    pub from: Option<PathBuf>,
+
+    /// Print the word, character, sentence and paragraph counts and reading time of FILE, and exit
+    #[arg(long, requires = "file")]
+    pub stats_only: bool,
+
+    /// Print --stats-only as one line of JSON
+    #[arg(long, requires = "stats_only")]
+    pub json: bool,
+
+    /// Time layout, rendering and editing on a large made-up page, print the timings as JSON, and exit
+    #[arg(long)]
+    pub bench: bool,
+
+    /// Print the snapshots of FILE, oldest first, with their word counts, and exit
+    #[arg(long, requires = "file")]
+    pub list_snapshots: bool,
+
+    /// Write a debug log to ~/.local/share/typewriter/typewriter.log
+    #[arg(long)]
+    pub debug: bool,
+
+    /// Print the recently opened files, newest first, and exit
+    #[arg(long)]
+    pub recent: bool,
+
+    /// Print a commented config file with every setting and exit
+    #[arg(long)]
+    pub write_default_config: bool,
+
+    /// Print the version, build and what was found on this system, for bug reports, and exit
+    #[arg(long)]
+    pub build_info: bool,
+}
+
+impl Cli {
+    /// Overrides the config with the flags that were given
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(ms) = self.reveal_rate {
+            config.reveal_rate = Duration::from_millis(ms);
+        }
+        if let Some(mode) = self.reveal_mode {
+            config.reveal_mode = mode;
+        }
+        if let Some(curve) = self.reveal_curve {
+            config.reveal_curve = curve;
+        }
+        if let Some(ms) = self.input_timeout {
+            config.input_timeout = Duration::from_millis(ms);
+        }
+        if let Some(fps) = self.max_fps {
+            config.max_fps = fps;
+        }
+        if let Some(ms) = self.audio_latency_ms {
+            config.audio_latency = Duration::from_millis(ms);
+        }
+        if let Some(cps) = self.reveal_on_load_cps {
+            config.reveal_on_load_cps = cps;
+        }
+        if let Some(lines) = self.visible_lines {
+            config.visible_lines = lines as usize;
+        }
+        if let Some(background) = self.background {
+            config.background = background;
+        }
+        if let Some(bell) = self.bell {
+            config.bell = bell;
+        }
+        if let Some(percent) = self.ui_click_volume {
+            config.ui_click_volume = percent;
+        }
+        if let Some(layout) = self.layout {
+            config.layout = layout;
+        }
+        if let Some(rows) = self.padding_top {
+            config.padding_top = rows;
+        }
+        if let Some(columns) = self.padding_left {
+            config.padding_left = columns;
+        }
+        if let Some(columns) = self.scroll_ahead {
+            config.scroll_ahead = columns as usize;
+        }
+        if !self.tab_stops.is_empty() {
+            config.tab_stops = self.tab_stops.iter().map(|&column| column as usize).collect();
+        }
+        if let Some(columns) = self.tab_width {
+            config.tab_width = columns as usize;
+        }
+        if let Some(columns) = self.hard_margin {
+            config.hard_margin = Some(columns as usize);
+        }
+        if let Some(kilobytes) = self.paste_confirm {
+            config.paste_confirm = (kilobytes as usize).saturating_mul(1024);
+        }
+        if let Some(count) = self.snapshot_limit {
+            config.snapshot_limit = count as usize;
+        }
+        if let Some(characters) = self.paragraph_limit {
+            config.paragraph_limit = Some(Limit::Characters(characters as usize));
+        }
+        if let Some(words) = self.paragraph_words {
+            config.paragraph_limit = Some(Limit::Words(words as usize));
+        }
+        if self.seed.is_some() {
+            config.seed = self.seed;
+        }
+        if self.sprint.is_some() {
+            config.sprint = self.sprint;
+        }
+        if let Some(threshold) = self.idle_threshold {
+            config.idle_threshold = threshold;
+        }
+        if self.pomodoro.is_some() {
+            config.pomodoro = self.pomodoro;
+        }
+        if self.drafts_dir.is_some() {
+            config.drafts_dir = self.drafts_dir.clone();
+        }
+        if self.on_save.is_some() {
+            config.on_save_command = self.on_save.clone();
+        }
+        if self.journal_dir.is_some() {
+            config.journal_dir = self.journal_dir.clone();
+        }
+        if let Some(command) = &self.print_command {
+            config.print_command = command.clone();
+        }
+        switch(&mut config.sound, self.sound, self.no_sound);
+        switch(&mut config.mouse, self.mouse, self.no_mouse);
+        switch(&mut config.focus_sentence, self.focus_sentence, self.no_focus_sentence);
+        switch(&mut config.reveal_on_load, self.reveal_on_load, self.no_reveal_on_load);
+        switch(&mut config.soft_undo, self.soft_undo, self.no_soft_undo);
+        switch(&mut config.trim_trailing_whitespace, self.trim_trailing_whitespace, self.no_trim_trailing_whitespace);
+        switch(&mut config.expand_tabs_on_save, self.expand_tabs_on_save, self.no_expand_tabs_on_save);
+        if self.ensure_trailing_newline {
+            config.ensure_trailing_newline = Some(true);
+        } else if self.no_ensure_trailing_newline {
+            config.ensure_trailing_newline = Some(false);
+        }
+        switch(&mut config.show_trailing_whitespace, self.show_trailing_whitespace, self.no_show_trailing_whitespace);
+        switch(
+            &mut config.show_current_trailing_spaces,
+            self.show_current_trailing_spaces,
+            self.no_show_current_trailing_spaces,
+        );
+        switch(&mut config.show_changes, self.show_changes, self.no_show_changes);
+        if let Some(template) = &self.status {
+            config.status = Some(template.clone());
+        }
+        switch(&mut config.cleanliness, self.cleanliness, self.no_cleanliness);
+        switch(&mut config.hyphen_hints, self.hyphen_hints, self.no_hyphen_hints);
+        switch(&mut config.caps, self.caps, self.no_caps);
+        switch(&mut config.auto_space_sentences, self.auto_space_sentences, self.no_auto_space_sentences);
+        switch(&mut config.decimal_align, self.decimal_align, self.no_decimal_align);
+        switch(&mut config.autosave_on_exit, self.autosave_on_exit, self.no_autosave_on_exit);
+        switch(&mut config.fit_page, self.fit_page, self.no_fit_page);
+        switch(&mut config.quiet, self.quiet, self.no_quiet);
+        switch(&mut config.print_on_exit, self.print_on_exit, self.no_print_on_exit);
+        // Like --strict, the preset wins over the other settings
+        config.accessible |= self.accessible;
+        if config.accessible {
+            config.apply_accessible();
+        }
+        if self.strict {
+            config.apply_strict();
+        }
+    }
+}
+
+/// Sets an on/off setting from its pair of flags, such as `--sound` and
+/// `--no-sound`, leaving it as the config file and environment had it if
+/// neither was given. Of the two, the one given last wins.
+fn switch(setting: &mut bool, on: bool, off: bool) {
+    if on {
+        *setting = true;
+    } else if off {
+        *setting = false;
+    }
+}
+
+/// Parses a number, such as milliseconds or columns, that must be more than zero
+fn parse_positive(value: &str) -> Result<u64, String> {
+    match value.parse::<u64>() {
+        Ok(0) => Err("must be more than 0".to_string()),
+        Ok(ms) => Ok(ms),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parses an input timeout, kept to what the config file allows
+fn parse_input_timeout(value: &str) -> Result<u64, String> {
+    crate::pacing::check_input_timeout(value.parse().map_err(|e: std::num::ParseIntError| e.to_string())?)
+}
+
+/// Parses a volume in percent, kept to what the config file allows
+fn parse_volume(value: &str) -> Result<u8, String> {
+    crate::sound::check_volume(value.parse().map_err(|e: std::num::ParseIntError| e.to_string())?)
+}
+
+/// Parses a frame rate cap, kept to what the config file allows
+fn parse_max_fps(value: &str) -> Result<u64, String> {
+    crate::pacing::check_max_fps(value.parse().map_err(|e: std::num::ParseIntError| e.to_string())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+
+    #[test]
+    /// Test the defaults when no flags are given
+    fn test_defaults() {
+        let cli = Cli::try_parse_from(["typewriter"]).unwrap();
+        assert_eq!(cli.file, None);
+        assert!(!cli.sound);
+        assert!(!cli.quiet);
+        assert_eq!(cli.seed, None);
+        assert_eq!(cli.config, None);
+
+        // Flags that weren't given leave the config alone
+        let mut config = Config::default();
+        cli.apply(&mut config);
+        assert_eq!(config.reveal_rate, Duration::from_millis(300));
+        assert_eq!(config.input_timeout, Duration::from_millis(50));
+        assert_eq!(config.audio_latency, Duration::ZERO);
+    }
+
+    #[test]
+    /// Test every way the config file, $TYPEWRITER_SOUND and the flags can
+    /// combine: the flags win over the environment, which wins over the file
+    fn test_sound_precedence() {
+        // (config file, environment, flag, sound on)
+        let cases = [
+            (false, None, None, false),
+            (false, None, Some("--sound"), true),
+            (false, None, Some("--no-sound"), false),
+            (false, Some("1"), None, true),
+            (false, Some("1"), Some("--sound"), true),
+            (false, Some("1"), Some("--no-sound"), false),
+            (false, Some("0"), None, false),
+            (false, Some("0"), Some("--sound"), true),
+            (false, Some("0"), Some("--no-sound"), false),
+            (true, None, None, true),
+            (true, None, Some("--sound"), true),
+            (true, None, Some("--no-sound"), false),
+            (true, Some("1"), None, true),
+            (true, Some("1"), Some("--sound"), true),
+            (true, Some("1"), Some("--no-sound"), false),
+            (true, Some("0"), None, false),
+            (true, Some("0"), Some("--sound"), true),
+            (true, Some("0"), Some("--no-sound"), false),
+        ];
+        for (file, env, flag, expected) in cases {
+            let mut config = Config::from_toml(&format!("sound = {}\n", file)).unwrap();
+            assert!(config.apply_env(|name| env.filter(|_| name == "TYPEWRITER_SOUND").map(String::from)).is_empty());
+            Cli::try_parse_from(["typewriter"].into_iter().chain(flag)).unwrap().apply(&mut config);
+            assert_eq!(config.sound, expected, "file {}, environment {:?}, flag {:?}", file, env, flag);
+        }
+    }
+
+    #[test]
+    /// Test that of a flag and its `--no-` form the last one given wins, and
+    /// that the `--no-` form turns off what the config file turned on
+    fn test_switch_flags() {
+        let cli = Cli::try_parse_from(["typewriter", "--sound", "--no-sound"]).unwrap();
+        assert!(!cli.sound && cli.no_sound);
+        let cli = Cli::try_parse_from(["typewriter", "--no-sound", "--sound"]).unwrap();
+        assert!(cli.sound && !cli.no_sound);
+
+        let mut config = Config::from_toml("mouse = true\nquiet = true\ncaps = true\n").unwrap();
+        Cli::try_parse_from(["typewriter", "--no-mouse", "--no-quiet", "--fit-page"]).unwrap().apply(&mut config);
+        assert!(!config.mouse);
+        assert!(!config.quiet);
+        assert!(config.caps);
+        assert!(config.fit_page);
+
+        let mut config = Config::from_toml("fit_page = true\n").unwrap();
+        Cli::try_parse_from(["typewriter", "--no-fit-page"]).unwrap().apply(&mut config);
+        assert!(!config.fit_page);
+    }
+
+    #[test]
+    /// Test a file with flags before and after it
+    fn test_file_and_flags() {
+        let cli = Cli::try_parse_from([
+            "typewriter",
+            "--sound",
+            "notes.txt",
+            "--audio-latency-ms",
+            "120",
+            "--seed",
+            "42",
+        ])
+        .unwrap();
+        assert_eq!(cli.file.as_deref(), Some("notes.txt"));
+        assert!(cli.sound);
+        assert_eq!(cli.audio_latency_ms, Some(120));
+        assert_eq!(cli.seed, Some(42));
+
+        let cli = Cli::try_parse_from(["typewriter", "--reveal-mode", "word"]).unwrap();
+        let mut config = Config::default();
+        cli.apply(&mut config);
+        assert_eq!(config.reveal_mode, RevealMode::Word);
+        Cli::try_parse_from(["typewriter", "--reveal-curve", "cluster"]).unwrap().apply(&mut config);
+        assert_eq!(config.reveal_curve, RevealCurve::Cluster);
+        assert!(!config.reveal_on_load);
+        Cli::try_parse_from(["typewriter", "--reveal-on-load", "--reveal-on-load-cps", "200"]).unwrap().apply(&mut config);
+        assert!(config.reveal_on_load);
+        assert_eq!(config.reveal_on_load_cps, 200);
+
+        let cli = Cli::try_parse_from(["typewriter", "--background", "light"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.background, Background::Light);
+
+        let cli = Cli::try_parse_from(["typewriter", "--bell", "visual"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.bell, BellStyle::Visual);
+
+        let cli = Cli::try_parse_from(["typewriter", "--ui-click-volume", "40"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.ui_click_volume, 40);
+        assert!(Cli::try_parse_from(["typewriter", "--ui-click-volume", "101"]).is_err());
+
+        let cli = Cli::try_parse_from(["typewriter", "--layout", "dvorak"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.layout, KeyboardLayout::Dvorak);
+
+        let cli = Cli::try_parse_from(["typewriter", "--idle-threshold", "5m"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.idle_threshold, Duration::from_secs(300));
+
+        let cli = Cli::try_parse_from(["typewriter", "--padding-top", "0", "--padding-left", "6"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!((config.padding_top, config.padding_left), (0, 6));
+
+        let cli = Cli::try_parse_from(["typewriter", "--scroll-ahead", "0"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.scroll_ahead, 0);
+
+        let cli = Cli::try_parse_from(["typewriter", "--trim-trailing-whitespace", "--show-trailing-whitespace"]).unwrap();
+        cli.apply(&mut config);
+        assert!(config.trim_trailing_whitespace && config.show_trailing_whitespace);
+
+        let cli = Cli::try_parse_from(["typewriter", "--show-current-trailing-spaces"]).unwrap();
+        cli.apply(&mut config);
+        assert!(config.show_current_trailing_spaces);
+        let cli = Cli::try_parse_from(["typewriter", "--no-show-current-trailing-spaces"]).unwrap();
+        cli.apply(&mut config);
+        assert!(!config.show_current_trailing_spaces);
+
+        let cli = Cli::try_parse_from(["typewriter", "--tab-width", "4", "--expand-tabs-on-save"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.tab_width, 4);
+        assert!(config.expand_tabs_on_save);
+        assert!(Cli::try_parse_from(["typewriter", "--tab-width", "0"]).is_err());
+
+        assert_eq!(config.ensure_trailing_newline, None);
+        let cli = Cli::try_parse_from(["typewriter", "--no-ensure-trailing-newline"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.ensure_trailing_newline, Some(false));
+        let cli = Cli::try_parse_from(["typewriter", "--no-ensure-trailing-newline", "--ensure-trailing-newline"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.ensure_trailing_newline, Some(true));
+
+        let cli = Cli::try_parse_from(["typewriter", "--show-changes"]).unwrap();
+        cli.apply(&mut config);
+        assert!(config.show_changes);
+
+        let cli = Cli::try_parse_from(["typewriter", "--status", "{file} {>} {words}w"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.status, crate::status::parse_status("{file} {>} {words}w").ok());
+        assert!(Cli::try_parse_from(["typewriter", "--status", "{file} {lines}"]).is_err());
+
+        let cli = Cli::try_parse_from(["typewriter", "--input-timeout", "20", "--max-fps", "30"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.input_timeout, Duration::from_millis(20));
+        assert_eq!(config.max_fps, 30);
+    }
+
+    #[test]
+    /// Test that flags beat the config file, which beats the defaults
+    fn test_precedence() {
+        let file = Config::from_toml("reveal_rate_ms = 200\ninput_timeout_ms = 80\nmouse = true\n").unwrap();
+
+        let mut config = file.clone();
+        let cli = Cli::try_parse_from(["typewriter", "--reveal-rate", "100"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.reveal_rate, Duration::from_millis(100));
+        assert_eq!(config.input_timeout, Duration::from_millis(80));
+        assert_eq!(config.audio_latency, Duration::ZERO);
+        assert!(config.mouse);
+        assert!(!config.sound);
+
+        // The environment sits between the file and the flags
+        let mut config = file.clone();
+        let env = |name: &str| (name == "TYPEWRITER_REVEAL_RATE").then(|| "150".to_string());
+        assert!(config.apply_env(env).is_empty());
+        assert_eq!(config.reveal_rate, Duration::from_millis(150));
+        cli.apply(&mut config);
+        assert_eq!(config.reveal_rate, Duration::from_millis(100));
+        let mut config = file.clone();
+        config.apply_env(env);
+        Cli::try_parse_from(["typewriter", "--sound"]).unwrap().apply(&mut config);
+        assert_eq!(config.reveal_rate, Duration::from_millis(150));
+        assert!(config.sound);
+
+        let mut config = Config::from_toml("on_save_command = \"make\"\n").unwrap();
+        let cli = Cli::try_parse_from(["typewriter", "--on-save", "git commit -qam save"]).unwrap();
+        cli.apply(&mut config);
+        assert_eq!(config.on_save_command.as_deref(), Some("git commit -qam save"));
+
+        // Profiles sit between the file and the environment
+        let text = "reveal_rate_ms = 200\n[[profile]]\nmatch = \"*.md\"\nreveal_rate_ms = 120\nmouse = true\n";
+        let mut config = Config::from_toml(text).unwrap();
+        config.apply_profiles(std::path::Path::new("notes.md"));
+        Cli::try_parse_from(["typewriter", "notes.md", "--reveal-rate", "90"]).unwrap().apply(&mut config);
+        assert_eq!(config.reveal_rate, Duration::from_millis(90));
+        assert!(config.mouse);
+
+        // --strict overrides even flags given alongside it
+        let mut config = file.clone();
+        Cli::try_parse_from(["typewriter", "--strict", "--mouse", "--soft-undo"]).unwrap().apply(&mut config);
+        assert!(!config.mouse);
+        assert!(!config.soft_undo);
+        assert!(config.sound);
+        assert_eq!(config.hard_margin, Some(72));
+
+        Cli::try_parse_from(["typewriter", "--caps"]).unwrap().apply(&mut config);
+        assert!(config.caps);
+        Cli::try_parse_from(["typewriter", "--auto-space-sentences"]).unwrap().apply(&mut config);
+        assert!(config.auto_space_sentences);
+        Cli::try_parse_from(["typewriter", "--tab-stops", "12,30", "--decimal-align"]).unwrap().apply(&mut config);
+        assert_eq!(config.tab_stops, [12, 30]);
+        assert!(config.decimal_align);
+        assert!(Cli::try_parse_from(["typewriter", "--tab-stops", "12,0"]).is_err());
+
+        // --accessible, or accessible in the file, overrides the reveal and focus settings
+        let mut config = Config::from_toml("reveal_rate_ms = 200\nreveal_mode = \"word\"\n").unwrap();
+        Cli::try_parse_from(["typewriter", "--accessible", "--focus-sentence"]).unwrap().apply(&mut config);
+        assert_eq!(config.reveal_rate, Duration::ZERO);
+        assert_eq!(config.reveal_mode, RevealMode::Char);
+        assert!(!config.focus_sentence);
+        let mut config = Config::from_toml("accessible = true\nreveal_on_load = true\n").unwrap();
+        Cli::try_parse_from(["typewriter"]).unwrap().apply(&mut config);
+        assert!(!config.reveal_on_load);
+
+        let mut config = Config::from_toml("paste_confirm_kb = 16\n").unwrap();
+        assert_eq!(config.paste_confirm, 16 * 1024);
+        Cli::try_parse_from(["typewriter", "--paste-confirm", "64"]).unwrap().apply(&mut config);
+        assert_eq!(config.paste_confirm, 64 * 1024);
+
+        let mut config = Config::from_toml("snapshot_limit = 5\n").unwrap();
+        Cli::try_parse_from(["typewriter", "--snapshot-limit", "50"]).unwrap().apply(&mut config);
+        assert_eq!(config.snapshot_limit, 50);
+        assert!(Cli::try_parse_from(["typewriter", "--snapshot-limit", "0"]).is_err());
+        // Snapshots belong to a file
+        assert!(Cli::try_parse_from(["typewriter", "--list-snapshots"]).is_err());
+        let cli = Cli::try_parse_from(["typewriter", "draft.txt", "--restore-snapshot", "20240301-142530"]).unwrap();
+        assert_eq!(cli.restore_snapshot.as_deref(), Some("20240301-142530"));
+
+        let mut config = Config::from_toml("print_command = \"lpr -P office\"\nprint_on_exit = true\n").unwrap();
+        Cli::try_parse_from(["typewriter", "--print-command", "lp -d home"]).unwrap().apply(&mut config);
+        assert_eq!(config.print_command, "lp -d home");
+        assert!(config.print_on_exit);
+        Cli::try_parse_from(["typewriter", "--no-print-on-exit"]).unwrap().apply(&mut config);
+        assert!(!config.print_on_exit);
+
+        // A paragraph limit from the flags replaces one in words from the file
+        let mut config = Config::from_toml("paragraph_words = 100\n").unwrap();
+        Cli::try_parse_from(["typewriter", "--paragraph-limit", "280"]).unwrap().apply(&mut config);
+        assert_eq!(config.paragraph_limit, Some(Limit::Characters(280)));
+    }
+
+    #[test]
+    /// Test that typos and bad values are rejected
+    fn test_errors() {
+        let error = Cli::try_parse_from(["typewriter", "--sonud"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnknownArgument);
+
+        let error = Cli::try_parse_from(["typewriter", "--reveal-rate", "fast"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "--reveal-mode", "line"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "--background", "grey"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "--hard-margin", "0"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "--input-timeout", "5000"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "--max-fps", "0"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "--paragraph-limit", "280", "--paragraph-words", "50"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+
+        let error = Cli::try_parse_from(["typewriter", "--seed", "lots"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "a.txt", "b.txt"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnknownArgument);
+
+        let error = Cli::try_parse_from(["typewriter", "--sprint", "15"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "--pomodoro", "25"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+
+        let error = Cli::try_parse_from(["typewriter", "--journal", "a.txt"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+
+        let error = Cli::try_parse_from(["typewriter", "--stats-only"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+
+        let error = Cli::try_parse_from(["typewriter", "--json", "a.txt"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    /// Test that --help and --version are answered
+    fn test_help_and_version() {
+        let error = Cli::try_parse_from(["typewriter", "--help"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::DisplayHelp);
+        assert!(error.to_string().contains("--reveal-rate <MS>"));
+        assert!(error.to_string().contains("--write-default-config"));
+        assert!(error.to_string().contains("--build-info"));
+
+        let error = Cli::try_parse_from(["typewriter", "--version"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::DisplayVersion);
+    }
+}
@@ -0,0 +1,113 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::io::{self, Write};
+
+/// Longest text sent through OSC 52. Terminals cap the escape sequence (hterm
+/// stops at 100,000 bytes of base64), so anything longer would be cut off or
+/// dropped without a word.
+pub const OSC52_MAX_BYTES: usize = 74_994;
+
+/// Base64 bytes written at a time, so a long sequence doesn't go out as one huge write
+const CHUNK_BYTES: usize = 4096;
+
+/// How the text reached the clipboard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopyMethod {
+    /// Through the terminal with OSC 52, which also works over SSH
+    Terminal,
+    /// Through the system clipboard
+    System,
+    /// Both of the above
+    Both,
+}
+
+/// Builds the OSC 52 sequence that puts text on the clipboard, or None if the
+/// text is too long for terminals to accept
+pub fn osc52_sequence(text: &str) -> Option<String> {
+    if text.len() > OSC52_MAX_BYTES {
+        return None;
+    }
+    Some(format!("\x1b]52;c;{}\x07", STANDARD.encode(text)))
+}
+
+/// Copies text to the clipboard: through the terminal when it fits, and through
+/// the system clipboard as well when built with the `clipboard` feature
+pub fn copy<W: Write>(output: &mut W, text: &str) -> Result<CopyMethod, String> {
+    let terminal = match osc52_sequence(text) {
+        Some(sequence) => {
+            write_chunked(output, &sequence).map_err(|e| e.to_string())?;
+            true
+        }
+        None => false,
+    };
+
+    match (terminal, copy_system(text)) {
+        (true, Ok(true)) => Ok(CopyMethod::Both),
+        (true, _) => Ok(CopyMethod::Terminal),
+        (false, Ok(true)) => Ok(CopyMethod::System),
+        (false, Ok(false)) => Err(format!(
+            "Too long to copy through the terminal ({} bytes, the limit is {})",
+            text.len(),
+            OSC52_MAX_BYTES
+        )),
+        (false, Err(e)) => Err(e),
+    }
+}
+
+/// Writes an escape sequence a chunk at a time
+fn write_chunked<W: Write>(output: &mut W, sequence: &str) -> io::Result<()> {
+    for chunk in sequence.as_bytes().chunks(CHUNK_BYTES) {
+        output.write_all(chunk)?;
+    }
+    output.flush()
+}
+
+/// Puts text on the system clipboard. Returns whether it did.
+#[cfg(feature = "clipboard")]
+fn copy_system(text: &str) -> Result<bool, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| e.to_string())?;
+    clipboard.set_text(text).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Without the `clipboard` feature there is no system clipboard to use
+#[cfg(not(feature = "clipboard"))]
+fn copy_system(_text: &str) -> Result<bool, String> {
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the OSC 52 sequence for a short text
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("hello").unwrap(), "\x1b]52;c;aGVsbG8=\x07");
+    }
+
+    #[test]
+    /// Test that text over the terminal limit isn't sent
+    fn test_osc52_limit() {
+        let text = "a".repeat(OSC52_MAX_BYTES);
+        let sequence = osc52_sequence(&text).unwrap();
+        // The whole sequence stays within what hterm accepts
+        assert!(sequence.len() <= 100_000 + 8);
+
+        assert_eq!(osc52_sequence(&"a".repeat(OSC52_MAX_BYTES + 1)), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "clipboard"))]
+    /// Test copying through the terminal, in chunks for long texts
+    fn test_copy() {
+        let mut output = Vec::new();
+        let text = "word ".repeat(10_000);
+        assert_eq!(copy(&mut output, &text), Ok(CopyMethod::Terminal));
+        assert_eq!(String::from_utf8(output).unwrap(), osc52_sequence(&text).unwrap());
+
+        let mut output = Vec::new();
+        assert!(copy(&mut output, &"a".repeat(OSC52_MAX_BYTES + 1)).is_err());
+        assert!(output.is_empty());
+    }
+}
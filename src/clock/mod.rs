@@ -0,0 +1,81 @@
+use chrono::{Local, NaiveDateTime};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where a session reads the time from: the system's clocks, or a fixed
+/// moment that only moves when told to, so a session plays out the same way
+/// every time. Copies of a fixed clock move together.
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+    /// The instant and the local time a fixed clock stands at, or None for
+    /// the system's clocks
+    fixed: Option<Arc<Mutex<(Instant, NaiveDateTime)>>>,
+}
+
+impl Clock {
+    /// Returns a clock standing at `local` time until it is moved on
+    #[allow(dead_code)] // For replaying sessions; the app runs on the system's clocks
+    pub fn fixed(local: NaiveDateTime) -> Self {
+        Self {
+            fixed: Some(Arc::new(Mutex::new((Instant::now(), local)))),
+        }
+    }
+
+    /// Moves a fixed clock on. The system's clocks move by themselves.
+    #[allow(dead_code)] // For replaying sessions; the app runs on the system's clocks
+    pub fn advance(&self, by: Duration) {
+        if let Some(fixed) = &self.fixed {
+            let mut fixed = fixed.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            fixed.0 += by;
+            fixed.1 += by;
+        }
+    }
+
+    /// Returns the instant it is now. The system's is read through tokio, so
+    /// that it stands still along with tokio's in a test that pauses it.
+    pub fn now(&self) -> Instant {
+        match &self.fixed {
+            Some(fixed) => fixed.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).0,
+            None => tokio::time::Instant::now().into_std(),
+        }
+    }
+
+    /// Returns the local time it is now
+    pub fn local(&self) -> NaiveDateTime {
+        match &self.fixed {
+            Some(fixed) => fixed.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).1,
+            None => Local::now().naive_local(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that a fixed clock stands still until moved, and that its copies
+    /// move with it
+    fn test_fixed() {
+        let local = NaiveDateTime::parse_from_str("2024-06-03 09:05", "%Y-%m-%d %H:%M").unwrap();
+        let clock = Clock::fixed(local);
+        let start = clock.now();
+        let copy = clock.clone();
+        assert_eq!(copy.now(), start);
+        assert_eq!(copy.local(), local);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(copy.now(), start + Duration::from_secs(90));
+        assert_eq!(copy.local().format("%H:%M:%S").to_string(), "09:06:30");
+    }
+
+    #[test]
+    /// Test that the system's clocks can't be moved on
+    fn test_system() {
+        let clock = Clock::default();
+        let before = Instant::now();
+        clock.advance(Duration::from_secs(3600));
+        assert!(clock.now() >= before);
+        assert!(clock.now() < before + Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,1340 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::Spanned;
+
+use crate::editor::{parse_reveal_curve, parse_reveal_mode, BufferBuilder, RevealCurve, RevealMode, DEFAULT_TAB_WIDTH};
+use crate::input::{KeyMap, ACTIONS};
+use crate::pacing::{self, DEFAULT_MAX_FPS};
+use crate::paragraph::Limit;
+use crate::paste;
+use crate::paths;
+use crate::print;
+use crate::renderer::{parse_background, Background, SCROLL_AHEAD};
+use crate::snapshots;
+use crate::sound::{check_volume, parse_bell, parse_layout, BellStyle, KeyboardLayout};
+use crate::status::{self, StatusTemplate};
+
+/// Names a config file to read instead of the default one; `--config` wins over it
+pub const CONFIG_VAR: &str = "TYPEWRITER_CONFIG";
+
+/// The only actions left bound under `--strict`: what a mechanical typewriter
+/// can do, plus saving, quitting and the help screen
+pub const STRICT_ACTIONS: &[&str] = &[
+    "save", "save_as", "close", "abort", "backspace", "newline", "right", "tab", "menu", "set_margin", "help", "redraw",
+];
+
+/// Where `--strict` puts the hard margin when none is set
+const STRICT_MARGIN: usize = 72;
+
+/// A commented template listing every setting with its default, printed by
+/// `--write-default-config`
+pub const DEFAULT_CONFIG: &str = r#"# typewriter configuration
+#
+# Lives at $XDG_CONFIG_HOME/typewriter/config.toml (usually
+# ~/.config/typewriter/config.toml), or wherever --config points.
+# Command-line flags override anything set here.
+
+# Delay before a typed character appears, in milliseconds, or 0 for none
+# reveal_rate_ms = 300
+
+# Show typed text a character at a time ("char"), or each word at once when
+# it is finished ("word")
+# reveal_mode = "char"
+
+# Have every character wait the whole delay ("fixed"), or let a quick burst
+# of keys appear a little sooner key by key, like typebars catching up
+# ("cluster")
+# reveal_curve = "fixed"
+
+# Have an opened file type itself out before typing starts, instead of
+# appearing all at once, at this many characters a second
+# reveal_on_load = false
+# reveal_on_load_cps = 60
+
+# How long to wait for input before checking timers, in milliseconds, up to
+# 1000. This doesn't change how smoothly text appears; max_fps does.
+# input_timeout_ms = 50
+
+# Most frames drawn in a second while text is appearing, from 1 to 240
+# max_fps = 60
+
+# How far the audio output lags behind the screen, in milliseconds
+# audio_latency_ms = 0
+
+# Play typewriter sounds
+# sound = false
+
+# How the bell rings at the margin, at the end of a sprint and so on: a tone
+# from the sound system ("audio", or the terminal's bell while sound is off),
+# the terminal's bell ("terminal"), a flash of the screen ("visual"), or not
+# at all ("none", which leaves out every flash too)
+# bell = "audio"
+
+# Volume of a quiet click for moving through menus, answering prompts and
+# toggling settings, from 0 (off) to 100, while sound is on
+# ui_click_volume = 0
+
+# The keyboard typed on, so keys near each other click alike: "qwerty",
+# "azerty" or "dvorak"
+# layout = "qwerty"
+
+# Click to move the cursor and scroll with the mouse wheel
+# mouse = false
+
+# How many of the last lines stay at full brightness; the rest are dimmed
+# visible_lines = 2
+
+# Instead, dim everything before the sentence being typed
+# focus_sentence = false
+
+# Whether the terminal is "light" or "dark", which decides how faded and
+# marked-out text is drawn; "auto" asks the terminal
+# background = "auto"
+
+# Blank rows above the page and columns left of it
+# padding_top = 1
+# padding_left = 2
+
+# On a line wider than the window, how many columns stay in view ahead of the
+# cursor; the page scrolls sideways before the cursor gets closer to the edge
+# scroll_ahead = 5
+
+# Show in the status row how much of the typing wasn't marked out, and tint
+# lines where more than a quarter of it was
+# cleanliness = false
+
+# Let Ctrl+Z take back typing and marking out on the line being typed, until
+# Enter moves on from it
+# soft_undo = false
+
+# Leave out spaces and tabs at the ends of lines when saving, except on the
+# line being typed. The page keeps them.
+# trim_trailing_whitespace = false
+
+# Columns between tab stops. Tabs in an opened file are shown as spaces up to
+# the next one, and saved as tabs again on lines not typed in.
+# tab_width = 8
+
+# Save the spaces shown for an opened file's tabs rather than the tabs
+# expand_tabs_on_save = false
+
+# End the saved file with a newline if it lacks one. Left unset, only .txt and
+# .md files get one; .typ files are saved as they are either way
+# ensure_trailing_newline = true
+
+# Draw spaces and tabs at the ends of lines as faint dots, except on the line
+# being typed
+# show_trailing_whitespace = false
+
+# Draw spaces and tabs at the end of the line being typed as faint dots, until
+# the line is left. They move the carriage towards the margin bell like letters
+# show_current_trailing_spaces = false
+
+# Underline what has been typed or marked out since the last save; Ctrl+Shift+D
+# or Alt+D turns it on and off while typing
+# show_changes = false
+
+# Lay out the status row from placeholders: {file}, {modified}, {line}, {col},
+# {words}, {chars}, {wpm}, {goal}, {page}, {sound}, {mode} and {clock}. │ or |
+# divide it into segments, left out while empty and dropped from the right
+# when the row is too narrow; what follows {>} is aligned to the right.
+# {mode} is what the status row says without a template
+# status = "{file}{modified} │ Ln {line}, Col {col} │ {words}w {>} {mode} │ {clock}"
+
+# Longest a line can get, in characters. Past it typing is refused until
+# Enter, like a carriage at its stop; the bell rings 8 characters before.
+# hard_margin = 72
+
+# Once the margin bell has rung mid-word, show a faint "‐?" at the hard margin;
+# a hyphen typed into the last column then carries on with a new line
+# hyphen_hints = false
+
+# Turn typed letters into capitals, like a typewriter for labels; Ctrl+Shift+U
+# or Alt+U turns it on and off while typing
+# caps = false
+
+# Put in the space missed between sentences: a capital typed straight after
+# `.`, `!` or `?` gets a space before it, as a keystroke of its own
+# auto_space_sentences = false
+
+# Columns the Tab key moves the carriage to, with a tick as it lands on one.
+# A stop at 12 leaves 12 characters before it. Without a stop ahead, Tab
+# types a space.
+# tab_stops = [12, 30, 48]
+
+# Line up numbers on the tab stops: typing `.` after a number puts spaces in
+# front of it so that the `.` lands on the next stop
+# decimal_align = false
+
+# Ask before typing out a paste bigger than this many kilobytes. A paste
+# that goes ahead appears at once, without sounds.
+# paste_confirm_kb = 4
+
+# Show the size of the paragraph being typed against a limit, in characters
+# or in words, with a bell when it is passed; the later of the two wins
+# paragraph_limit = 280
+# paragraph_words = 100
+
+# Show how many lines are left on one printed page, with a bell when it is
+# exactly full; lines past it are tinted
+# fit_page = false
+
+# Work with a screen reader: text appears as soon as it is typed, nothing is
+# dimmed or flashed, the status row is plain text and marking out a character
+# rings the bell
+# accessible = false
+
+# Seed for the sound variation; random when not set, and shown in the summary
+# seed = 1234
+
+# When killed or hung up, save into the open file instead of a .recovery file
+# autosave_on_exit = false
+
+# Start every session with a writing sprint of this length, during which
+# closing is refused
+# sprint = "15m"
+
+# Alternate work and rest periods, in minutes
+# pomodoro = "25/5"
+
+# Pauses longer than this are left out of the active time in the summary
+# idle_threshold = "90s"
+
+# Don't print the session summary after closing
+# quiet = false
+
+# Shell command run in the background after each save, with TYPEWRITER_FILE
+# and TYPEWRITER_WORDS set
+# on_save_command = "git -C ~/writing commit -qam autosave"
+
+# Also run it for the save made by autosave_on_exit
+# on_save_hooks_for_autosave = false
+
+# Where --journal keeps its entries
+# journal_dir = "~/Documents/typewriter-journal"
+
+# Also offer the files here, besides those in the working directory, when
+# starting without a file
+# drafts_dir = "~/Documents/drafts"
+
+# How many snapshots (F9) of a file to keep in <file>.rev beside it; taking
+# one more removes the oldest
+# snapshot_limit = 20
+
+# Shell command Print sends the pages to on its standard input, with
+# TYPEWRITER_FILE set to the file's name
+# print_command = "lpr -P office"
+
+# Print the text after closing, as if pulling the page out of the typewriter
+# print_on_exit = false
+
+# Key bindings: an action name, then one chord or a list of chords.
+# Binding an action replaces its default chords.
+[keys]
+# save = ["ctrl+s", "f2"]
+# close = ["ctrl+x", "f10"]
+
+# Profiles change settings for the files whose path matches a glob, such as
+# "*.md", "notes/*.txt" or "~/journal/**". When several match, later ones
+# win. Flags and environment variables still override them.
+# [[profile]]
+# match = "*.md"
+# reveal_rate_ms = 150
+"#;
+
+/// Returns the template with the given settings uncommented and set to the
+/// given TOML values. Only the first line for each is changed, so the
+/// examples in the profile section stay as they are.
+pub fn template_with(settings: &[(&str, String)]) -> String {
+    let mut pending: Vec<&(&str, String)> = settings.iter().collect();
+    let mut text = String::new();
+    for line in DEFAULT_CONFIG.lines() {
+        let position = pending.iter().position(|(name, _)| {
+            line.strip_prefix("# ")
+                .and_then(|setting| setting.strip_prefix(name))
+                .is_some_and(|rest| rest.starts_with(" = "))
+        });
+        match position {
+            Some(idx) => {
+                let (name, value) = pending.remove(idx);
+                text.push_str(&format!("{} = {}\n", name, value));
+            }
+            None => text.push_str(&format!("{}\n", line)),
+        }
+    }
+    text
+}
+
+/// Writes a new config file, creating its directory, but never over an
+/// existing one
+pub fn write_new(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(text.as_bytes())
+}
+
+/// Returns config text with one top-level setting set to a TOML value. The
+/// setting's line is replaced if there is one, or else its commented-out
+/// example; failing both, the setting goes in before the first table.
+pub fn with_setting(text: &str, name: &str, value: &str) -> String {
+    let mut lines: Vec<&str> = text.lines().collect();
+    let top_level = lines.iter().position(|line| line.trim_start().starts_with('[')).unwrap_or(lines.len());
+    let sets_name = |line: &str| line.split_once('=').is_some_and(|(key, _)| key.trim() == name);
+    let found = lines[..top_level]
+        .iter()
+        .position(|line| sets_name(line))
+        .or_else(|| lines[..top_level].iter().position(|line| line.strip_prefix("# ").is_some_and(sets_name)));
+
+    let setting = format!("{} = {}", name, value);
+    match found {
+        Some(idx) => lines[idx] = &setting,
+        None => {
+            // After the last setting, not after the blank lines before the table
+            let end = lines[..top_level].iter().rposition(|line| !line.trim().is_empty()).map_or(0, |idx| idx + 1);
+            lines.insert(end, &setting);
+        }
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Sets one setting in the config file, starting one from the template if
+/// there isn't one yet
+pub fn save_setting(path: &Path, name: &str, value: &str) -> io::Result<()> {
+    match fs::read_to_string(path) {
+        Ok(text) => fs::write(path, with_setting(&text, name, value)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => write_new(path, &template_with(&[(name, value.to_string())])),
+        Err(e) => Err(e),
+    }
+}
+
+/// Settings loaded from the user's config file
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Key bindings: the defaults with the `[keys]` table merged over them
+    pub keymap: KeyMap,
+    /// Delay before a typed character appears
+    pub reveal_rate: Duration,
+    /// Whether text appears a character or a word at a time
+    pub reveal_mode: RevealMode,
+    /// How long each typed character waits to appear
+    pub reveal_curve: RevealCurve,
+    /// Whether an opened file types itself out
+    pub reveal_on_load: bool,
+    /// How many characters a second an opened file types itself out at
+    pub reveal_on_load_cps: u64,
+    /// How long to wait for input before checking timers
+    pub input_timeout: Duration,
+    /// Most frames drawn in a second while text is appearing
+    pub max_fps: u64,
+    /// How far the audio output lags behind the screen
+    pub audio_latency: Duration,
+    /// Whether to play sounds
+    pub sound: bool,
+    /// How the bell rings
+    pub bell: BellStyle,
+    /// Volume of the click for menus, prompts and toggles, in percent
+    pub ui_click_volume: u8,
+    /// The keyboard typed on, which picks each key's click
+    pub layout: KeyboardLayout,
+    /// Whether to capture the mouse
+    pub mouse: bool,
+    /// How many of the last lines aren't dimmed
+    pub visible_lines: usize,
+    /// Whether to dim everything before the sentence being typed instead
+    pub focus_sentence: bool,
+    /// The terminal's background, or `Auto` to ask the terminal
+    pub background: Background,
+    /// Blank rows kept above the page
+    pub padding_top: u16,
+    /// Blank columns kept left of the page
+    pub padding_left: u16,
+    /// Columns kept in view ahead of the cursor on a line wider than the page
+    pub scroll_ahead: usize,
+    /// Whether to show how clean the typing has been
+    pub cleanliness: bool,
+    /// Whether changes to the line being typed can be undone
+    pub soft_undo: bool,
+    /// Whether saving leaves out whitespace at the ends of lines
+    pub trim_trailing_whitespace: bool,
+    /// Columns between the tab stops opened files' tabs are expanded to
+    pub tab_width: usize,
+    /// Whether lines opened with tabs are saved with spaces instead
+    pub expand_tabs_on_save: bool,
+    /// Whether saved files end with a newline, or None for only .txt and .md
+    pub ensure_trailing_newline: Option<bool>,
+    /// Whether whitespace at the ends of lines is drawn as faint dots
+    pub show_trailing_whitespace: bool,
+    /// Whether whitespace at the end of the line being typed is drawn as faint dots
+    pub show_current_trailing_spaces: bool,
+    /// Whether changes since the last save are underlined
+    pub show_changes: bool,
+    /// How the status row is laid out, if not as it is by default
+    pub status: Option<StatusTemplate>,
+    /// Longest a line can get before typing is refused, if there is a limit
+    pub hard_margin: Option<usize>,
+    /// Whether to hint at hyphenating words at the hard margin
+    pub hyphen_hints: bool,
+    /// Whether typed letters become capitals
+    pub caps: bool,
+    /// Whether a capital straight after the end of a sentence gets a space before it
+    pub auto_space_sentences: bool,
+    /// Columns the Tab key moves the carriage to
+    pub tab_stops: Vec<usize>,
+    /// Whether typing `.` after a number lines it up on the next tab stop
+    pub decimal_align: bool,
+    /// Size in bytes past which a paste is confirmed before it is typed
+    pub paste_confirm: usize,
+    /// Size the paragraph being typed is measured against, if any
+    pub paragraph_limit: Option<Limit>,
+    /// Whether the text is measured against one printed page
+    pub fit_page: bool,
+    /// Whether to work with a screen reader
+    pub accessible: bool,
+    /// Seed for the sound variation, random if None
+    pub seed: Option<u64>,
+    /// Whether a signal saves into the open file rather than a recovery file
+    pub autosave_on_exit: bool,
+    /// Length of the writing sprint each session starts with, if any
+    pub sprint: Option<Duration>,
+    /// Lengths of the pomodoro work and rest periods, if pomodoro mode is on
+    pub pomodoro: Option<(Duration, Duration)>,
+    /// Longest pause that counts as active time
+    pub idle_threshold: Duration,
+    /// Whether to leave out the summary printed after closing
+    pub quiet: bool,
+    /// Shell command to run after each save, if any
+    pub on_save_command: Option<String>,
+    /// Whether the save command also runs for autosaves
+    pub on_save_hooks_for_autosave: bool,
+    /// Where journal entries are kept, or None for the default
+    pub journal_dir: Option<PathBuf>,
+    /// Where the opening screen looks for drafts besides the working directory
+    pub drafts_dir: Option<PathBuf>,
+    /// Most snapshots kept of each file
+    pub snapshot_limit: usize,
+    /// Shell command the printed pages are piped into
+    pub print_command: String,
+    /// Whether the text is printed after closing
+    pub print_on_exit: bool,
+    /// Settings for particular files, in the order they appear in the file
+    pub profiles: Vec<Profile>,
+    /// Problems that didn't stop the file from loading, such as unknown keys
+    pub warnings: Vec<ConfigError>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keymap: KeyMap::default(),
+            reveal_rate: Duration::from_millis(300),
+            reveal_mode: RevealMode::Char,
+            reveal_curve: RevealCurve::Fixed,
+            reveal_on_load: false,
+            reveal_on_load_cps: 60,
+            input_timeout: Duration::from_millis(50),
+            max_fps: DEFAULT_MAX_FPS,
+            audio_latency: Duration::ZERO,
+            sound: false,
+            bell: BellStyle::Audio,
+            ui_click_volume: 0,
+            layout: KeyboardLayout::Qwerty,
+            mouse: false,
+            visible_lines: 2,
+            focus_sentence: false,
+            background: Background::Auto,
+            padding_top: 1,
+            padding_left: 2,
+            scroll_ahead: SCROLL_AHEAD,
+            cleanliness: false,
+            soft_undo: false,
+            trim_trailing_whitespace: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            expand_tabs_on_save: false,
+            ensure_trailing_newline: None,
+            show_trailing_whitespace: false,
+            show_current_trailing_spaces: false,
+            show_changes: false,
+            status: None,
+            hard_margin: None,
+            hyphen_hints: false,
+            caps: false,
+            auto_space_sentences: false,
+            tab_stops: Vec::new(),
+            decimal_align: false,
+            paste_confirm: paste::DEFAULT_CONFIRM_BYTES,
+            paragraph_limit: None,
+            fit_page: false,
+            accessible: false,
+            seed: None,
+            autosave_on_exit: false,
+            sprint: None,
+            pomodoro: None,
+            idle_threshold: crate::stats::DEFAULT_IDLE_THRESHOLD,
+            quiet: false,
+            on_save_command: None,
+            on_save_hooks_for_autosave: false,
+            journal_dir: None,
+            drafts_dir: None,
+            snapshot_limit: snapshots::DEFAULT_KEEP,
+            print_command: print::DEFAULT_COMMAND.to_string(),
+            print_on_exit: false,
+            profiles: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// A problem with the config file, pointing at the offending line when known
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    /// The file that failed to load
+    pub path: Option<PathBuf>,
+    /// 1-based line number of the problem
+    pub line: Option<usize>,
+    /// The setting the problem is with, if it is about one
+    pub key: Option<String>,
+    /// What went wrong
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if let Some(line) = self.line {
+            write!(f, "{}:", line)?;
+        }
+        if self.path.is_some() || self.line.is_some() {
+            write!(f, " ")?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "`{}` ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The `[keys]` table of the config file; other keys are read one by one
+#[derive(Debug, Deserialize)]
+struct RawKeys {
+    #[serde(default)]
+    keys: BTreeMap<String, Spanned<Chords>>,
+}
+
+/// The `[[profile]]` tables of the config file
+#[derive(Debug, Deserialize)]
+struct RawProfiles {
+    #[serde(default)]
+    profile: Vec<Spanned<BTreeMap<Spanned<String>, toml::Value>>>,
+}
+
+/// Settings that apply only to files whose path matches a glob
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// The glob as written, for logging
+    glob: String,
+    /// The glob, anchored anywhere in the path if it was relative
+    pattern: glob::Pattern,
+    /// The settings to merge over the rest, already checked
+    settings: Vec<(String, toml::Value)>,
+}
+
+impl Profile {
+    /// Returns whether the profile is for a file. The glob is matched against
+    /// the file's absolute path, so a relative glob such as `*.md` or
+    /// `journal/*.md` matches the end of it, wherever the file was opened from.
+    pub fn matches(&self, file: &Path) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..glob::MatchOptions::new()
+        };
+        let file = std::path::absolute(file).unwrap_or_else(|_| file.to_path_buf());
+        self.pattern.matches_path_with(&file, options)
+    }
+}
+
+/// One chord or a list of chords bound to an action
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Chords {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Config {
+    /// Returns where the config file lives by default
+    /// (`$XDG_CONFIG_HOME/typewriter/config.toml` or `~/.config/typewriter/config.toml`)
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_dir.join("typewriter").join("config.toml"))
+    }
+
+    /// Returns the config file to read: the one given with `--config`, else the
+    /// one named by `$TYPEWRITER_CONFIG`, else None for the default location
+    pub fn chosen_path(flag: Option<&Path>, var: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+        flag.map(Path::to_path_buf)
+            .or_else(|| var(CONFIG_VAR).filter(|path| !path.is_empty()).map(|path| paths::expand_with(&path, &var).unwrap_or_else(|_| path.into())))
+    }
+
+    /// Loads the config from its default location, falling back to the
+    /// defaults if there is no config file
+    pub fn load_default() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Loads the config from a file
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let with_path = |e: ConfigError| ConfigError {
+            path: Some(path.to_path_buf()),
+            ..e
+        };
+        let text = fs::read_to_string(path).map_err(|e| ConfigError {
+            path: Some(path.to_path_buf()),
+            line: None,
+            key: None,
+            message: e.to_string(),
+        })?;
+
+        let mut config = Self::from_toml(&text).map_err(with_path)?;
+        config.warnings = config.warnings.into_iter().map(with_path).collect();
+        Ok(config)
+    }
+
+    /// Parses the config from TOML text
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let syntax_error = |e: toml::de::Error| ConfigError {
+            path: None,
+            line: e.span().map(|span| line_of(text, span.start)),
+            key: None,
+            message: e.message().to_string(),
+        };
+        let table: BTreeMap<Spanned<String>, toml::Value> = toml::from_str(text).map_err(syntax_error)?;
+
+        let mut config = Self::default();
+        for (key, value) in table {
+            let line = line_of(text, key.span().start);
+            let name = key.into_inner();
+            let error = |message: String| ConfigError {
+                path: None,
+                line: Some(line),
+                key: Some(name.clone()),
+                message,
+            };
+
+            match name.as_str() {
+                "keys" | "profile" => (),
+                _ => {
+                    if !config.set(&name, value).map_err(error)? {
+                        config.warnings.push(error("is not a known setting and was ignored".to_string()));
+                    }
+                }
+            }
+        }
+
+        let raw: RawKeys = toml::from_str(text).map_err(|e| ConfigError {
+            key: Some("keys".to_string()),
+            ..syntax_error(e)
+        })?;
+        for (action, chords) in &raw.keys {
+            let line = line_of(text, chords.span().start);
+            let chords: Vec<&str> = match chords.get_ref() {
+                Chords::One(chord) => vec![chord.as_str()],
+                Chords::Many(chords) => chords.iter().map(String::as_str).collect(),
+            };
+            config.keymap.rebind(action, &chords).map_err(|message| ConfigError {
+                path: None,
+                line: Some(line),
+                key: None,
+                message: format!("[keys] {}", message),
+            })?;
+        }
+
+        let raw: RawProfiles = toml::from_str(text).map_err(|e| ConfigError {
+            key: Some("profile".to_string()),
+            ..syntax_error(e)
+        })?;
+        for table in raw.profile {
+            let table_line = line_of(text, table.span().start);
+            let mut glob = None;
+            let mut settings = Vec::new();
+            for (key, value) in table.into_inner() {
+                let line = line_of(text, key.span().start);
+                let name = key.into_inner();
+                let error = |message: String| ConfigError {
+                    path: None,
+                    line: Some(line),
+                    key: Some(name.clone()),
+                    message,
+                };
+
+                match name.as_str() {
+                    "match" => {
+                        let text: String = setting(value).map_err(error)?;
+                        let expanded = paths::expand(&text).map_err(|e| error(e.to_string()))?;
+                        let anchored = match expanded {
+                            path if path.is_absolute() => path.to_string_lossy().into_owned(),
+                            _ => format!("**/{}", text),
+                        };
+                        let pattern = glob::Pattern::new(&anchored)
+                            .map_err(|e| error(format!("is not a valid glob: {}", e.msg)))?;
+                        glob = Some((text, pattern));
+                    }
+                    "keys" | "profile" => config
+                        .warnings
+                        .push(error("can't be set in a profile and was ignored".to_string())),
+                    // Checked now, so a mistake shows up whichever file is opened
+                    _ => match Self::default().set(&name, value.clone()).map_err(error)? {
+                        true => settings.push((name, value)),
+                        false => config.warnings.push(error("is not a known setting and was ignored".to_string())),
+                    },
+                }
+            }
+
+            let Some((glob, pattern)) = glob else {
+                return Err(ConfigError {
+                    path: None,
+                    line: Some(table_line),
+                    key: Some("profile".to_string()),
+                    message: "needs a `match` glob".to_string(),
+                });
+            };
+            config.profiles.push(Profile { glob, pattern, settings });
+        }
+
+        Ok(config)
+    }
+
+    /// Merges the profiles that match a file over the settings, later
+    /// profiles winning over earlier ones
+    pub fn apply_profiles(&mut self, file: &Path) {
+        let profiles = std::mem::take(&mut self.profiles);
+        for profile in profiles.iter().filter(|profile| profile.matches(file)) {
+            log::debug!("using profile `{}` for {}", profile.glob, file.display());
+            for (name, value) in &profile.settings {
+                // Every setting was checked when the file was loaded
+                let _ = self.set(name, value.clone());
+            }
+        }
+        self.profiles = profiles;
+    }
+
+    /// Changes one setting from its TOML value. Returns false if there is no
+    /// setting by that name.
+    fn set(&mut self, name: &str, value: toml::Value) -> Result<bool, String> {
+        match name {
+            "reveal_rate_ms" => self.reveal_rate = Duration::from_millis(setting(value)?),
+            "reveal_mode" => self.reveal_mode = parse_reveal_mode(&setting::<String>(value)?)?,
+            "reveal_curve" => self.reveal_curve = parse_reveal_curve(&setting::<String>(value)?)?,
+            "reveal_on_load" => self.reveal_on_load = setting(value)?,
+            "reveal_on_load_cps" => match setting(value)? {
+                0 => return Err("must be more than 0".to_string()),
+                cps => self.reveal_on_load_cps = cps,
+            },
+            "input_timeout_ms" => {
+                self.input_timeout = Duration::from_millis(pacing::check_input_timeout(setting(value)?)?)
+            }
+            "max_fps" => self.max_fps = pacing::check_max_fps(setting(value)?)?,
+            "audio_latency_ms" => self.audio_latency = Duration::from_millis(setting(value)?),
+            "sound" => self.sound = setting(value)?,
+            "bell" => self.bell = parse_bell(&setting::<String>(value)?)?,
+            "ui_click_volume" => self.ui_click_volume = check_volume(setting(value)?)?,
+            "layout" => self.layout = parse_layout(&setting::<String>(value)?)?,
+            "mouse" => self.mouse = setting(value)?,
+            "visible_lines" => match setting(value)? {
+                0 => return Err("must be more than 0".to_string()),
+                lines => self.visible_lines = lines,
+            },
+            "focus_sentence" => self.focus_sentence = setting(value)?,
+            "background" => self.background = parse_background(&setting::<String>(value)?)?,
+            "padding_top" => self.padding_top = setting(value)?,
+            "padding_left" => self.padding_left = setting(value)?,
+            "scroll_ahead" => self.scroll_ahead = setting(value)?,
+            "cleanliness" => self.cleanliness = setting(value)?,
+            "soft_undo" => self.soft_undo = setting(value)?,
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = setting(value)?,
+            "tab_width" => match setting(value)? {
+                0 => return Err("must be more than 0".to_string()),
+                columns => self.tab_width = columns,
+            },
+            "expand_tabs_on_save" => self.expand_tabs_on_save = setting(value)?,
+            "ensure_trailing_newline" => self.ensure_trailing_newline = Some(setting(value)?),
+            "show_trailing_whitespace" => self.show_trailing_whitespace = setting(value)?,
+            "show_current_trailing_spaces" => self.show_current_trailing_spaces = setting(value)?,
+            "show_changes" => self.show_changes = setting(value)?,
+            "status" => self.status = Some(status::parse_status(&setting::<String>(value)?)?),
+            "hard_margin" => match setting(value)? {
+                0 => return Err("must be more than 0".to_string()),
+                columns => self.hard_margin = Some(columns),
+            },
+            "hyphen_hints" => self.hyphen_hints = setting(value)?,
+            "caps" => self.caps = setting(value)?,
+            "auto_space_sentences" => self.auto_space_sentences = setting(value)?,
+            "tab_stops" => {
+                let columns: Vec<usize> = setting(value)?;
+                if columns.contains(&0) {
+                    return Err("must all be more than 0".to_string());
+                }
+                self.tab_stops = columns;
+            }
+            "decimal_align" => self.decimal_align = setting(value)?,
+            "paste_confirm_kb" => match setting::<usize>(value)? {
+                0 => return Err("must be more than 0".to_string()),
+                kilobytes => self.paste_confirm = kilobytes.saturating_mul(1024),
+            },
+            "paragraph_limit" => match setting(value)? {
+                0 => return Err("must be more than 0".to_string()),
+                characters => self.paragraph_limit = Some(Limit::Characters(characters)),
+            },
+            "paragraph_words" => match setting(value)? {
+                0 => return Err("must be more than 0".to_string()),
+                words => self.paragraph_limit = Some(Limit::Words(words)),
+            },
+            "fit_page" => self.fit_page = setting(value)?,
+            "accessible" => self.accessible = setting(value)?,
+            "seed" => self.seed = Some(setting(value)?),
+            "autosave_on_exit" => self.autosave_on_exit = setting(value)?,
+            "sprint" => {
+                let length: String = setting(value)?;
+                self.sprint = Some(crate::sprint::parse_duration(&length)?);
+            }
+            "pomodoro" => {
+                let cycle: String = setting(value)?;
+                self.pomodoro = Some(crate::pomodoro::parse_cycle(&cycle)?);
+            }
+            "idle_threshold" => {
+                let length: String = setting(value)?;
+                self.idle_threshold = crate::sprint::parse_duration(&length)?;
+            }
+            "quiet" => self.quiet = setting(value)?,
+            "on_save_command" => self.on_save_command = Some(setting(value)?),
+            "on_save_hooks_for_autosave" => self.on_save_hooks_for_autosave = setting(value)?,
+            "journal_dir" => self.journal_dir = Some(expand(setting(value)?)?),
+            "drafts_dir" => self.drafts_dir = Some(expand(setting(value)?)?),
+            "snapshot_limit" => match setting(value)? {
+                0 => return Err("must be more than 0".to_string()),
+                count => self.snapshot_limit = count,
+            },
+            "print_command" => match setting::<String>(value)? {
+                command if command.trim().is_empty() => return Err("must not be empty".to_string()),
+                command => self.print_command = command,
+            },
+            "print_on_exit" => self.print_on_exit = setting(value)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Overrides settings with the `TYPEWRITER_*` environment variables that are
+    /// set, read through `var`. A value that can't be used leaves the setting
+    /// alone and is returned as a warning.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<ConfigError> {
+        let mut warnings = Vec::new();
+        let mut warn = |name: &str, message: String| {
+            warnings.push(ConfigError {
+                path: None,
+                line: None,
+                key: Some(name.to_string()),
+                message: format!("{}, so it was ignored", message),
+            })
+        };
+
+        if let Some(value) = var("TYPEWRITER_REVEAL_RATE") {
+            match value.trim().parse::<u64>() {
+                Ok(ms) => self.reveal_rate = Duration::from_millis(ms),
+                Err(_) => warn("TYPEWRITER_REVEAL_RATE", format!("must be milliseconds, not `{}`", value)),
+            }
+        }
+        if let Some(value) = var("TYPEWRITER_SOUND") {
+            match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => self.sound = true,
+                "0" | "false" | "no" | "off" => self.sound = false,
+                _ => warn("TYPEWRITER_SOUND", format!("must be 1 or 0, not `{}`", value)),
+            }
+        }
+        warnings
+    }
+
+    /// Turns the settings into a plain typewriter: only the keys in
+    /// [`STRICT_ACTIONS`] stay bound, and the mouse, undo, silence and spaces
+    /// put in for you go, while the carriage stops at a margin
+    pub fn apply_strict(&mut self) {
+        for (name, action, _) in ACTIONS {
+            if !STRICT_ACTIONS.contains(name) {
+                self.keymap.unbind(action);
+            }
+        }
+        self.mouse = false;
+        self.soft_undo = false;
+        self.auto_space_sentences = false;
+        self.show_changes = false;
+        self.sound = true;
+        self.hard_margin.get_or_insert(STRICT_MARGIN);
+    }
+
+    /// Returns a builder for buffers with the editing settings in place
+    pub fn buffer_builder(&self) -> BufferBuilder {
+        BufferBuilder::new()
+            .reveal_rate(self.reveal_rate)
+            .reveal_mode(self.reveal_mode)
+            .reveal_curve(self.reveal_curve)
+            .hard_margin(self.hard_margin)
+            .hyphen_hints(self.hyphen_hints)
+            .tab_width(self.tab_width)
+            .expand_tabs_on_save(self.expand_tabs_on_save)
+            .ensure_trailing_newline(self.ensure_trailing_newline)
+            .trim_trailing_whitespace(self.trim_trailing_whitespace)
+            .caps(self.caps)
+            .auto_space_sentences(self.auto_space_sentences)
+            .tab_stops(self.tab_stops.clone())
+            .decimal_align(self.decimal_align)
+            .soft_undo(self.soft_undo)
+    }
+
+    /// Turns off what a screen reader can't follow: the reveal delay, words
+    /// appearing at once, opened files typing themselves out and dimming
+    /// before the sentence. The renderer does the rest.
+    pub fn apply_accessible(&mut self) {
+        self.reveal_rate = Duration::ZERO;
+        self.reveal_mode = RevealMode::Char;
+        self.reveal_on_load = false;
+        self.focus_sentence = false;
+    }
+}
+
+/// Converts a setting's value to the type it should have
+fn setting<T: serde::de::DeserializeOwned>(value: toml::Value) -> Result<T, String> {
+    value.try_into().map_err(|e: toml::de::Error| e.message().to_string())
+}
+
+/// Expands `~` and variables in a path setting, as a shell would
+fn expand(path: String) -> Result<PathBuf, String> {
+    paths::expand(&path).map_err(|e| e.to_string())
+}
+
+/// Returns the 1-based line containing the byte offset
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+    use crate::input::{InputEvent, KeyChord};
+
+    #[test]
+    /// Test that an empty config gives the default bindings
+    fn test_empty_config() {
+        let config = Config::from_toml("").unwrap();
+        let ctrl_s = KeyChord::parse("ctrl+s").unwrap();
+        assert_eq!(config.keymap.get(&ctrl_s), Some(&InputEvent::Save));
+    }
+
+    #[test]
+    /// Test rebinding keys from the [keys] table
+    fn test_keys_table() {
+        let config = Config::from_toml("[keys]\nsave = \"ctrl+w\"\nclose = [\"ctrl+x\", \"f10\"]\n").unwrap();
+
+        let ctrl_w = KeyChord::parse("ctrl+w").unwrap();
+        let f10 = KeyChord::parse("f10").unwrap();
+        assert_eq!(config.keymap.get(&ctrl_w), Some(&InputEvent::Save));
+        assert_eq!(config.keymap.get(&f10), Some(&InputEvent::Close));
+    }
+
+    #[test]
+    /// Test that invalid bindings name the offending line
+    fn test_keys_errors_report_line() {
+        let error = Config::from_toml("[keys]\nsave = \"ctrl+w\"\nsav = \"ctrl+q\"\n").unwrap_err();
+        assert_eq!(error.line, Some(3));
+        assert_eq!(error.to_string(), "3: [keys] unknown action `sav`");
+
+        let error = Config::from_toml("[keys]\n\nsave = \"ctrl+nope\"\n").unwrap_err();
+        assert_eq!(error.line, Some(3));
+    }
+
+    #[test]
+    /// Test that TOML syntax errors are reported with their line
+    fn test_syntax_error_reports_line() {
+        let error = Config::from_toml("[keys]\nsave = ctrl+w\n").unwrap_err();
+        assert_eq!(error.line, Some(2));
+    }
+
+    #[test]
+    /// Test reading the top-level settings
+    fn test_settings() {
+        let text = "reveal_rate_ms = 150\nsound = true\nseed = 7\nautosave_on_exit = true\n";
+        let config = Config::from_toml(text).unwrap();
+        assert_eq!(config.reveal_rate, Duration::from_millis(150));
+        assert!(config.sound);
+        assert_eq!(config.seed, Some(7));
+        assert!(config.autosave_on_exit);
+
+        // Settings left out keep their defaults
+        assert_eq!(config.input_timeout, Duration::from_millis(50));
+        assert!(!config.mouse);
+    }
+
+    #[test]
+    /// Test that bad values name the line and the key
+    fn test_setting_errors() {
+        let error = Config::from_toml("sound = true\nreveal_rate_ms = \"fast\"\n").unwrap_err();
+        assert_eq!(error.line, Some(2));
+        assert_eq!(error.key.as_deref(), Some("reveal_rate_ms"));
+        assert!(error.to_string().starts_with("2: `reveal_rate_ms` "));
+
+        let error = Config::from_toml("input_timeout_ms = 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `input_timeout_ms` must be more than 0");
+        let error = Config::from_toml("input_timeout_ms = 60000\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `input_timeout_ms` must be at most 1000");
+        let error = Config::from_toml("max_fps = 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `max_fps` must be from 1 to 240");
+        let error = Config::from_toml("reveal_mode = \"line\"\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `reveal_mode` must be `char` or `word`, not `line`");
+        let error = Config::from_toml("reveal_curve = \"ease\"\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `reveal_curve` must be `fixed` or `cluster`, not `ease`");
+        let error = Config::from_toml("background = \"grey\"\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `background` must be `light`, `dark` or `auto`, not `grey`");
+        let error = Config::from_toml("bell = \"loud\"\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `bell` must be `audio`, `terminal`, `visual` or `none`, not `loud`");
+        let error = Config::from_toml("ui_click_volume = 150\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `ui_click_volume` must be from 0 to 100");
+        let error = Config::from_toml("layout = \"colemak\"\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `layout` must be `qwerty`, `azerty` or `dvorak`, not `colemak`");
+        let error = Config::from_toml("idle_threshold = \"90\"\n").unwrap_err();
+        assert!(error.to_string().starts_with("1: `idle_threshold` "));
+
+        let error = Config::from_toml("visible_lines = 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `visible_lines` must be more than 0");
+        let error = Config::from_toml("hard_margin = 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `hard_margin` must be more than 0");
+        let error = Config::from_toml("tab_stops = [8, 0]\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `tab_stops` must all be more than 0");
+        let error = Config::from_toml("paragraph_words = 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `paragraph_words` must be more than 0");
+        let error = Config::from_toml("reveal_on_load_cps = 0\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `reveal_on_load_cps` must be more than 0");
+        let error = Config::from_toml("print_command = \" \"\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `print_command` must not be empty");
+        let error = Config::from_toml("journal_dir = \"$TYPEWRITER_UNSET_TEST_VAR/journal\"\n").unwrap_err();
+        assert_eq!(error.to_string(), "1: `journal_dir` $TYPEWRITER_UNSET_TEST_VAR isn't set");
+        let error = Config::from_toml("drafts_dir = \"~nobody/drafts\"\n").unwrap_err();
+        assert!(error.to_string().starts_with("1: `drafts_dir` `~nobody` isn't supported"));
+
+        let error = Config::from_toml("[keys]\nsave = 5\n").unwrap_err();
+        assert_eq!(error.key.as_deref(), Some("keys"));
+        assert_eq!(error.line, Some(2));
+    }
+
+    #[test]
+    /// Test that unknown top-level keys are warned about but don't stop loading
+    fn test_unknown_key_warning() {
+        let config = Config::from_toml("sound = true\ncolour = \"blue\"\n").unwrap();
+        assert!(config.sound);
+        assert_eq!(config.warnings.len(), 1);
+        assert_eq!(config.warnings[0].line, Some(2));
+        assert_eq!(config.warnings[0].key.as_deref(), Some("colour"));
+    }
+
+    /// Returns a stand-in for the environment holding only the given variables,
+    /// so tests never change the real one that other tests run alongside
+    fn env_with(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: BTreeMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    /// Test that environment variables override the file
+    fn test_env_overrides() {
+        let mut config = Config::from_toml("reveal_rate_ms = 200\nsound = true\n").unwrap();
+        let warnings = config.apply_env(env_with(&[("TYPEWRITER_REVEAL_RATE", "120"), ("TYPEWRITER_SOUND", "0")]));
+        assert!(warnings.is_empty());
+        assert_eq!(config.reveal_rate, Duration::from_millis(120));
+        assert!(!config.sound);
+
+        // Unset variables leave the file's settings alone
+        let mut config = Config::from_toml("reveal_rate_ms = 200\n").unwrap();
+        assert!(config.apply_env(env_with(&[("TYPEWRITER_SOUND", "1")])).is_empty());
+        assert_eq!(config.reveal_rate, Duration::from_millis(200));
+        assert!(config.sound);
+
+        // No delay at all, from the file or the environment
+        let mut config = Config::from_toml("reveal_rate_ms = 0\n").unwrap();
+        assert_eq!(config.reveal_rate, Duration::ZERO);
+        config.reveal_rate = Duration::from_millis(200);
+        assert!(config.apply_env(env_with(&[("TYPEWRITER_REVEAL_RATE", "0")])).is_empty());
+        assert_eq!(config.reveal_rate, Duration::ZERO);
+    }
+
+    #[test]
+    /// Test that bad values are warned about and the setting kept
+    fn test_env_bad_values() {
+        let mut config = Config::from_toml("reveal_rate_ms = 200\nsound = true\n").unwrap();
+        let warnings = config.apply_env(env_with(&[("TYPEWRITER_REVEAL_RATE", "fast"), ("TYPEWRITER_SOUND", "loud")]));
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0].to_string(),
+            "`TYPEWRITER_REVEAL_RATE` must be milliseconds, not `fast`, so it was ignored"
+        );
+        assert_eq!(warnings[1].key.as_deref(), Some("TYPEWRITER_SOUND"));
+        assert_eq!(config.reveal_rate, Duration::from_millis(200));
+        assert!(config.sound);
+
+        let warnings = config.apply_env(env_with(&[("TYPEWRITER_REVEAL_RATE", "-5")]));
+        assert_eq!(warnings[0].to_string(), "`TYPEWRITER_REVEAL_RATE` must be milliseconds, not `-5`, so it was ignored");
+    }
+
+    #[test]
+    /// Test choosing the config file from the flag and the environment
+    fn test_chosen_path() {
+        let var = env_with(&[(CONFIG_VAR, "/etc/typewriter.toml")]);
+        assert_eq!(Config::chosen_path(None, &var), Some(PathBuf::from("/etc/typewriter.toml")));
+        assert_eq!(Config::chosen_path(Some(Path::new("mine.toml")), &var), Some(PathBuf::from("mine.toml")));
+        assert_eq!(Config::chosen_path(None, env_with(&[])), None);
+        assert_eq!(Config::chosen_path(None, env_with(&[(CONFIG_VAR, "")])), None);
+
+        // A file named by the environment has to exist
+        let missing = Config::chosen_path(None, env_with(&[(CONFIG_VAR, "/no/such/typewriter.toml")])).unwrap();
+        assert!(Config::load(&missing).is_err());
+    }
+
+    #[test]
+    /// Test that the template parses, both as written and with every line uncommented
+    fn test_default_config_template() {
+        let config = Config::from_toml(DEFAULT_CONFIG).unwrap();
+        assert!(config.warnings.is_empty());
+
+        let uncommented: String = DEFAULT_CONFIG
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(setting) if setting.contains(" = ") || setting.starts_with("[[") => setting,
+                _ => line,
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let mut config = Config::from_toml(&uncommented).unwrap();
+        assert!(config.warnings.is_empty());
+        assert!(config.journal_dir.clone().unwrap().ends_with("Documents/typewriter-journal"));
+        assert!(config.drafts_dir.clone().unwrap().ends_with("Documents/drafts"));
+        assert_eq!(config.snapshot_limit, 20);
+        assert_eq!(config.print_command, "lpr -P office");
+        assert!(!config.print_on_exit);
+        assert_eq!(config.reveal_rate, Config::default().reveal_rate);
+        assert_eq!(config.seed, Some(1234));
+        assert_eq!(config.hard_margin, Some(72));
+        assert!(!config.hyphen_hints);
+        assert!(!config.caps);
+        assert!(!config.auto_space_sentences);
+        assert_eq!(config.tab_stops, [12, 30, 48]);
+        assert!(!config.decimal_align);
+        assert_eq!(config.paste_confirm, 4096);
+        assert!(!config.accessible);
+        assert_eq!(config.paragraph_limit, Some(Limit::Words(100)));
+        assert!(!config.fit_page);
+        assert!(!config.soft_undo);
+        assert!(!config.trim_trailing_whitespace);
+        assert_eq!(config.tab_width, 8);
+        assert!(!config.expand_tabs_on_save);
+        assert_eq!(config.ensure_trailing_newline, Some(true));
+        assert!(!config.show_trailing_whitespace);
+        assert!(!config.show_current_trailing_spaces);
+        assert!(!config.show_changes);
+        let template = "{file}{modified} │ Ln {line}, Col {col} │ {words}w {>} {mode} │ {clock}";
+        assert_eq!(config.status, Some(status::parse_status(template).unwrap()));
+        assert!(!config.cleanliness);
+        assert_eq!(config.reveal_mode, RevealMode::Char);
+        assert_eq!(config.reveal_curve, RevealCurve::Fixed);
+        assert!(!config.reveal_on_load);
+        assert_eq!(config.reveal_on_load_cps, 60);
+        assert_eq!(config.input_timeout, Duration::from_millis(50));
+        assert_eq!(config.max_fps, 60);
+        assert_eq!(config.visible_lines, 2);
+        assert!(!config.focus_sentence);
+        assert_eq!(config.background, Background::Auto);
+        assert_eq!(config.bell, BellStyle::Audio);
+        assert_eq!(config.ui_click_volume, 0);
+        assert_eq!(config.layout, KeyboardLayout::Qwerty);
+        assert_eq!((config.padding_top, config.padding_left), (1, 2));
+        assert_eq!(config.scroll_ahead, 5);
+        assert_eq!(config.sprint, Some(Duration::from_secs(15 * 60)));
+        assert_eq!(config.pomodoro, Some((Duration::from_secs(25 * 60), Duration::from_secs(5 * 60))));
+        assert_eq!(config.idle_threshold, Duration::from_secs(90));
+        assert_eq!(config.on_save_command.as_deref(), Some("git -C ~/writing commit -qam autosave"));
+        assert!(!config.on_save_hooks_for_autosave);
+        assert_eq!(config.profiles.len(), 1);
+        assert!(config.profiles[0].matches(Path::new("notes.md")));
+        config.apply_profiles(Path::new("notes.md"));
+        assert_eq!(config.reveal_rate, Duration::from_millis(150));
+    }
+
+    #[test]
+    /// Test that strict mode leaves only the typewriter's own keys
+    fn test_apply_strict() {
+        let config_text =
+            "mouse = true\nsoft_undo = true\nauto_space_sentences = true\nshow_changes = true\n[keys]\nundo = \"f5\"\n";
+        let mut config = Config::from_toml(config_text).unwrap();
+        config.apply_strict();
+        let bound: Vec<&str> = ACTIONS
+            .iter()
+            .filter(|(_, action, _)| !config.keymap.chords_for(action).is_empty())
+            .map(|(name, _, _)| *name)
+            .collect();
+        assert_eq!(bound, STRICT_ACTIONS);
+        for chord in ["left", "up", "down", "end", "ctrl+z", "f5"] {
+            assert_eq!(config.keymap.get(&KeyChord::parse(chord).unwrap()), None);
+        }
+        assert!(config.sound);
+        assert!(!config.mouse);
+        assert!(!config.soft_undo);
+        assert!(!config.auto_space_sentences);
+        assert!(!config.show_changes);
+        assert_eq!(config.hard_margin, Some(72));
+
+        // A margin that was set is kept
+        let mut config = Config::from_toml("hard_margin = 60\n").unwrap();
+        config.apply_strict();
+        assert_eq!(config.hard_margin, Some(60));
+    }
+
+    #[test]
+    /// Test that buffers are made with the settings
+    fn test_buffer_builder() {
+        let config = Config::from_toml("reveal_rate_ms = 120
+hard_margin = 60
+caps = true
+soft_undo = true
+").unwrap();
+        let buffer = config.buffer_builder().build().unwrap();
+        assert_eq!(buffer.reveal_rate(), Duration::from_millis(120));
+        assert_eq!(buffer.hard_margin(), Some(60));
+        assert!(buffer.caps() && buffer.soft_undo());
+    }
+
+    #[test]
+    /// Test filling in the template, and that a written file isn't replaced
+    fn test_template_with() {
+        let text = template_with(&[("sound", "true".to_string()), ("reveal_rate_ms", "120".to_string())]);
+        let config = Config::from_toml(&text).unwrap();
+        assert!(config.sound);
+        assert_eq!(config.reveal_rate, Duration::from_millis(120));
+        assert!(text.contains("# reveal_rate_ms = 150\n"));
+        assert_eq!(text.lines().count(), DEFAULT_CONFIG.lines().count());
+
+        let dir = ScratchDir::new("config-write");
+        let path = dir.join("typewriter").join("config.toml");
+        write_new(&path, &text).unwrap();
+        assert!(write_new(&path, DEFAULT_CONFIG).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+    }
+
+    #[test]
+    /// Test setting one value in an existing config, keeping everything else
+    fn test_with_setting() {
+        // The setting's own line wins over its commented example
+        let text = "# hard_margin = 72\nsound = true\nhard_margin = 60\n";
+        assert_eq!(with_setting(text, "hard_margin", "64"), "# hard_margin = 72\nsound = true\nhard_margin = 64\n");
+
+        // The example is filled in, and profiles are left alone
+        let text = with_setting(DEFAULT_CONFIG, "hard_margin", "64");
+        assert!(text.contains("\nhard_margin = 64\n"));
+        assert!(!text.contains("# hard_margin = 72"));
+        let text = with_setting(PROFILES, "hard_margin", "64");
+        assert!(text.starts_with("reveal_rate_ms = 200\nsound = true\nhard_margin = 64\n\n[[profile]]\n"));
+        let config = Config::from_toml(&text).unwrap();
+        assert_eq!(config.hard_margin, Some(64));
+        assert_eq!(config.profiles.len(), 2);
+
+        let dir = ScratchDir::new("config-setting");
+        let path = dir.join("config.toml");
+        save_setting(&path, "hard_margin", "64").unwrap();
+        save_setting(&path, "sound", "true").unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.hard_margin, Some(64));
+        assert!(config.sound);
+    }
+
+    /// A config with two profiles for Markdown, the second only for notes
+    const PROFILES: &str = r#"reveal_rate_ms = 200
+sound = true
+
+[[profile]]
+match = "*.md"
+reveal_rate_ms = 100
+sound = false
+
+[[profile]]
+match = "notes/*.md"
+reveal_rate_ms = 50
+"#;
+
+    #[test]
+    /// Test that a file matching no profile keeps the base settings
+    fn test_profile_no_match() {
+        let mut config = Config::from_toml(PROFILES).unwrap();
+        assert!(config.warnings.is_empty());
+        assert_eq!(config.profiles.len(), 2);
+
+        config.apply_profiles(Path::new("notes/draft.txt"));
+        assert_eq!(config.reveal_rate, Duration::from_millis(200));
+        assert!(config.sound);
+    }
+
+    #[test]
+    /// Test that matching profiles apply in file order, relative or absolute
+    fn test_profile_multiple_matches() {
+        let mut config = Config::from_toml(PROFILES).unwrap();
+        config.apply_profiles(Path::new("notes/todo.md"));
+        assert_eq!(config.reveal_rate, Duration::from_millis(50));
+        assert!(!config.sound);
+
+        // Only the first matches outside notes/, however the path is written
+        for path in ["todo.md", "./other/todo.md", "/srv/writing/todo.md"] {
+            let mut config = Config::from_toml(PROFILES).unwrap();
+            config.apply_profiles(Path::new(path));
+            assert_eq!(config.reveal_rate, Duration::from_millis(100), "{}", path);
+        }
+
+        // A relative glob matches whole path components, and an absolute one the whole path
+        let config = Config::from_toml("[[profile]]\nmatch = \"/srv/*.md\"\nsound = true\n").unwrap();
+        assert!(config.profiles[0].matches(Path::new("/srv/a.md")));
+        assert!(!config.profiles[0].matches(Path::new("/srv/notes/a.md")));
+        assert!(!config.profiles[0].matches(Path::new("/home/srv/a.md")));
+        assert!(!Config::from_toml(PROFILES).unwrap().profiles[1].matches(Path::new("/srv/mynotes/a.md")));
+    }
+
+    #[test]
+    /// Test that profile mistakes are reported with their line when loading
+    fn test_profile_errors() {
+        let error = Config::from_toml("[[profile]]\nmatch = \"*.md\"\nsound = \"loud\"\n").unwrap_err();
+        assert_eq!(error.line, Some(3));
+        assert_eq!(error.key.as_deref(), Some("sound"));
+
+        let error = Config::from_toml("sound = true\n\n[[profile]]\nsound = false\n").unwrap_err();
+        assert_eq!(error.to_string(), "3: `profile` needs a `match` glob");
+
+        let error = Config::from_toml("[[profile]]\nmatch = \"[*.md\"\n").unwrap_err();
+        assert_eq!(error.line, Some(2));
+
+        let config = Config::from_toml("[[profile]]\nmatch = \"*.md\"\ncolour = \"blue\"\n").unwrap();
+        assert_eq!(config.warnings.len(), 1);
+        assert_eq!(config.warnings[0].line, Some(3));
+        assert!(config.profiles[0].settings.is_empty());
+    }
+}
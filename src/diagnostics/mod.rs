@@ -0,0 +1,231 @@
+use crossterm::terminal;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::sound::{SoundSystem, SAMPLES, UI_TICK_SAMPLE};
+
+/// Cargo features that can be turned on at build time
+const FEATURES: [(&str, bool); 2] = [
+    ("clipboard", cfg!(feature = "clipboard")),
+    ("gif-export", cfg!(feature = "gif-export")),
+];
+
+/// What the terminal can do, as far as it says
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerminalInfo {
+    /// Size as (columns, rows)
+    pub size: (u16, u16),
+    /// Whether it speaks the kitty keyboard protocol
+    pub keyboard_enhancement: bool,
+    /// Whether `$COLORTERM` claims 24-bit color
+    pub truecolor: bool,
+}
+
+/// Everything a bug report needs to know about this build and where it runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildInfo {
+    /// Package version
+    pub version: &'static str,
+    /// Git commit built from, if it was built from a checkout
+    pub commit: Option<&'static str>,
+    /// Cargo features turned on
+    pub features: Vec<&'static str>,
+    /// Config file that would be read, if a home directory could be found
+    pub config_path: Option<PathBuf>,
+    /// Whether the config file exists
+    pub config_found: bool,
+    /// Directory the sounds are played from
+    pub sound_dir: Option<PathBuf>,
+    /// Sound files found in it
+    pub samples: Vec<&'static str>,
+    /// The terminal, if output goes to one
+    pub terminal: Option<TerminalInfo>,
+}
+
+impl BuildInfo {
+    /// Looks everything up, given the config file chosen with `--config` or
+    /// `$TYPEWRITER_CONFIG`. Asks the terminal what it supports when output
+    /// goes to one.
+    pub fn gather(chosen_config: Option<PathBuf>) -> Self {
+        let config_path = chosen_config.or_else(Config::default_path);
+        let sound_dir = SoundSystem::find_sound_directory();
+        let samples = match &sound_dir {
+            Some(dir) => SAMPLES.into_iter().chain([UI_TICK_SAMPLE]).filter(|sample| dir.join(sample).exists()).collect(),
+            None => Vec::new(),
+        };
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: Some(env!("TYPEWRITER_COMMIT")).filter(|commit| !commit.is_empty()),
+            features: FEATURES.into_iter().filter(|(_, on)| *on).map(|(name, _)| name).collect(),
+            config_found: config_path.as_deref().is_some_and(Path::exists),
+            config_path,
+            sound_dir,
+            samples,
+            terminal: detect_terminal(),
+        }
+    }
+}
+
+/// Asks the terminal for its size and keyboard support
+fn detect_terminal() -> Option<TerminalInfo> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    Some(TerminalInfo {
+        size: terminal::size().ok()?,
+        keyboard_enhancement: terminal::supports_keyboard_enhancement().unwrap_or(false),
+        truecolor: is_truecolor(std::env::var("COLORTERM").ok().as_deref()),
+    })
+}
+
+/// Reads `$COLORTERM`, which terminals with 24-bit color set to `truecolor`
+/// or `24bit`
+fn is_truecolor(colorterm: Option<&str>) -> bool {
+    matches!(colorterm, Some("truecolor" | "24bit"))
+}
+
+/// Shows a yes/no answer
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "typewriter {}", self.version)?;
+        writeln!(f, "Commit: {}", self.commit.unwrap_or("not found"))?;
+        if self.features.is_empty() {
+            writeln!(f, "Features: none")?;
+        } else {
+            writeln!(f, "Features: {}", self.features.join(", "))?;
+        }
+
+        match &self.config_path {
+            Some(path) if self.config_found => writeln!(f, "Config file: {}", path.display())?,
+            Some(path) => writeln!(f, "Config file: {} (not found)", path.display())?,
+            None => writeln!(f, "Config file: not found")?,
+        }
+
+        match &self.sound_dir {
+            Some(dir) => writeln!(f, "Sound directory: {}", dir.display())?,
+            None => writeln!(f, "Sound directory: not found")?,
+        }
+        let missing: Vec<&str> = SAMPLES.into_iter().filter(|sample| !self.samples.contains(sample)).collect();
+        if missing.is_empty() {
+            writeln!(f, "Sound samples: all {} found", SAMPLES.len())?;
+        } else if missing.len() == SAMPLES.len() {
+            writeln!(f, "Sound samples: not found")?;
+        } else {
+            writeln!(
+                f,
+                "Sound samples: {} of {} found, missing {}",
+                SAMPLES.len() - missing.len(),
+                SAMPLES.len(),
+                missing.join(", ")
+            )?;
+        }
+        if self.samples.contains(&UI_TICK_SAMPLE) {
+            writeln!(f, "UI tick: {}", UI_TICK_SAMPLE)?;
+        } else {
+            writeln!(f, "UI tick: synthesized")?;
+        }
+
+        match &self.terminal {
+            Some(terminal) => writeln!(
+                f,
+                "Terminal: {}x{}, keyboard enhancement {}, truecolor {}",
+                terminal.size.0,
+                terminal.size.1,
+                yes_no(terminal.keyboard_enhancement),
+                yes_no(terminal.truecolor)
+            ),
+            None => writeln!(f, "Terminal: not found"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the report when everything is there
+    fn test_format_found() {
+        let info = BuildInfo {
+            version: "0.1.0",
+            commit: Some("3905575"),
+            features: vec!["clipboard", "gif-export"],
+            config_path: Some(PathBuf::from("/home/ada/.config/typewriter/config.toml")),
+            config_found: true,
+            sound_dir: Some(PathBuf::from("/home/ada/.local/share/typewriter/sounds")),
+            samples: SAMPLES.into_iter().chain([UI_TICK_SAMPLE]).collect(),
+            terminal: Some(TerminalInfo {
+                size: (80, 24),
+                keyboard_enhancement: true,
+                truecolor: false,
+            }),
+        };
+        assert_eq!(
+            info.to_string(),
+            "typewriter 0.1.0\n\
+             Commit: 3905575\n\
+             Features: clipboard, gif-export\n\
+             Config file: /home/ada/.config/typewriter/config.toml\n\
+             Sound directory: /home/ada/.local/share/typewriter/sounds\n\
+             Sound samples: all 7 found\n\
+             UI tick: ui-tick.wav\n\
+             Terminal: 80x24, keyboard enhancement yes, truecolor no\n"
+        );
+    }
+
+    #[test]
+    /// Test that whatever is missing is reported as not found
+    fn test_format_missing() {
+        let info = BuildInfo {
+            version: "0.1.0",
+            commit: None,
+            features: Vec::new(),
+            config_path: None,
+            config_found: false,
+            sound_dir: None,
+            samples: Vec::new(),
+            terminal: None,
+        };
+        assert_eq!(
+            info.to_string(),
+            "typewriter 0.1.0\n\
+             Commit: not found\n\
+             Features: none\n\
+             Config file: not found\n\
+             Sound directory: not found\n\
+             Sound samples: not found\n\
+             UI tick: synthesized\n\
+             Terminal: not found\n"
+        );
+
+        // A config file that isn't there yet, and a sound directory short of a file
+        let info = BuildInfo {
+            config_path: Some(PathBuf::from("/tmp/config.toml")),
+            sound_dir: Some(PathBuf::from("sounds")),
+            samples: SAMPLES[..6].to_vec(),
+            ..info
+        };
+        let report = info.to_string();
+        assert!(report.contains("Config file: /tmp/config.toml (not found)\n"));
+        assert!(report.contains("Sound samples: 6 of 7 found, missing classic-return.wav\n"));
+    }
+
+    #[test]
+    /// Test reading $COLORTERM
+    fn test_is_truecolor() {
+        assert!(is_truecolor(Some("truecolor")));
+        assert!(is_truecolor(Some("24bit")));
+        assert!(!is_truecolor(Some("yes")));
+        assert!(!is_truecolor(None));
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Extensions of the files offered by the opening screen
+pub const EXTENSIONS: [&str; 3] = ["txt", "md", "typ"];
+
+/// A file that can be picked on the opening screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Draft {
+    /// Where the file is, relative to the working directory if it is in it
+    pub path: PathBuf,
+    /// When the file was last written
+    pub modified: SystemTime,
+}
+
+/// Lists the recently opened files in the order given, then the text files
+/// in the given directories, most recently written first. Files that can't
+/// be read are left out and logged; a file reached twice is listed once.
+pub fn list(recent: &[PathBuf], dirs: &[PathBuf]) -> Vec<Draft> {
+    let mut seen = HashSet::new();
+    let mut drafts = Vec::new();
+    for path in recent {
+        add(path, &mut seen, &mut drafts);
+    }
+
+    let recent_count = drafts.len();
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!("couldn't list {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        for entry in entries {
+            match entry {
+                Ok(entry) if has_draft_extension(&entry.path()) => add(&entry.path(), &mut seen, &mut drafts),
+                Ok(_) => (),
+                Err(e) => log::warn!("skipping an entry in {}: {}", dir.display(), e),
+            }
+        }
+    }
+    drafts[recent_count..].sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.path.cmp(&b.path)));
+    drafts
+}
+
+/// Adds a file to the list unless it is there already or can't be read
+fn add(path: &Path, seen: &mut HashSet<PathBuf>, drafts: &mut Vec<Draft>) {
+    match draft(path) {
+        Ok(Some(draft)) => {
+            if seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())) {
+                drafts.push(draft);
+            }
+        }
+        Ok(None) => (),
+        Err(e) => log::warn!("skipping {}: {}", path.display(), e),
+    }
+}
+
+/// Returns whether a path ends in one of the offered extensions
+fn has_draft_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.iter().any(|offered| extension.eq_ignore_ascii_case(offered)))
+}
+
+/// Describes a file for the list, or None if it isn't a regular file
+fn draft(path: &Path) -> std::io::Result<Option<Draft>> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+    // Listing a file that then can't be opened would only lead to an error
+    File::open(path)?;
+    let cwd = env::current_dir().unwrap_or_default();
+    let shown = path.strip_prefix(".").or_else(|_| path.strip_prefix(&cwd)).unwrap_or(path);
+    Ok(Some(Draft {
+        path: shown.to_path_buf(),
+        modified: metadata.modified()?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+    use std::time::Duration;
+
+
+    /// Writes a file and sets when it was last written, in seconds after the epoch
+    fn write_at(path: &Path, seconds: u64) {
+        fs::write(path, "text").unwrap();
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+    }
+
+    #[test]
+    /// Test that only text files are listed, newest first
+    fn test_list() {
+        let dir = ScratchDir::new("drafts-list");
+        write_at(&dir.join("old.txt"), 1000);
+        write_at(&dir.join("new.md"), 3000);
+        write_at(&dir.join("middle.TYP"), 2000);
+        write_at(&dir.join("image.png"), 4000);
+        fs::create_dir(dir.join("folder.md")).unwrap();
+
+        let names: Vec<PathBuf> = list(&[], &[dir.to_path_buf()]).into_iter().map(|draft| draft.path).collect();
+        assert_eq!(names, vec![dir.join("new.md"), dir.join("middle.TYP"), dir.join("old.txt")]);
+    }
+
+    #[test]
+    /// Test that recent files come first in their own order, listed once
+    fn test_recent_first() {
+        let dir = ScratchDir::new("drafts-recent");
+        write_at(&dir.join("old.txt"), 1000);
+        write_at(&dir.join("new.txt"), 3000);
+        write_at(&dir.join("middle.txt"), 2000);
+
+        let recent = [dir.join("old.txt"), dir.join("gone.txt")];
+        let names: Vec<PathBuf> = list(&recent, &[dir.to_path_buf()]).into_iter().map(|draft| draft.path).collect();
+        assert_eq!(names, vec![dir.join("old.txt"), dir.join("new.txt"), dir.join("middle.txt")]);
+    }
+
+    #[test]
+    /// Test that missing directories and files listed twice are handled
+    fn test_missing_and_repeated_dirs() {
+        let dir = ScratchDir::new("drafts-repeated");
+        write_at(&dir.join("notes.txt"), 1000);
+
+        let drafts = list(&[], &[dir.join("missing"), dir.to_path_buf(), dir.join(".")]);
+        assert_eq!(drafts.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    /// Test that entries that can't be read are left out
+    fn test_unreadable_skipped() {
+        let dir = ScratchDir::new("drafts-unreadable");
+        write_at(&dir.join("open.txt"), 1000);
+        std::os::unix::fs::symlink(dir.join("gone.txt"), dir.join("broken.txt")).unwrap();
+
+        let names: Vec<PathBuf> = list(&[], &[dir.to_path_buf()]).into_iter().map(|draft| draft.path).collect();
+        assert_eq!(names, vec![dir.join("open.txt")]);
+    }
+}
@@ -0,0 +1,443 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use super::{loaded_lines, looks_binary, Buffer, RevealCurve, RevealMode, DEFAULT_TAB_WIDTH};
+use crate::clock::Clock;
+use crate::paths;
+
+/// Why a buffer couldn't be made with the settings given
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// Tab stops can't be 0 columns apart
+    TabWidth,
+    /// A line has to hold at least one character
+    HardMargin,
+    /// The cursor was to go back to a line that append-only text won't let
+    /// it change
+    CursorInLockedText(usize),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TabWidth => write!(f, "the tab width must be more than 0"),
+            Self::HardMargin => write!(f, "the hard margin must be more than 0"),
+            Self::CursorInLockedText(line) => write!(
+                f,
+                "the cursor can't go back to line {} when the text already written can't be changed",
+                line + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<BuildError> for io::Error {
+    fn from(e: BuildError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+    }
+}
+
+/// Gathers the settings for a buffer, then makes an empty one, opens a file
+/// or reads one in with all of them in place. One builder can make any
+/// number of buffers.
+#[derive(Debug, Clone)]
+pub struct BufferBuilder {
+    reveal_rate: Duration,
+    reveal_mode: RevealMode,
+    reveal_curve: RevealCurve,
+    hard_margin: Option<usize>,
+    hyphen_hints: bool,
+    tab_width: usize,
+    expand_tabs_on_save: bool,
+    ensure_trailing_newline: Option<bool>,
+    trim_trailing_whitespace: bool,
+    caps: bool,
+    auto_space_sentences: bool,
+    soft_undo: bool,
+    tab_stops: Vec<usize>,
+    decimal_align: bool,
+    append_only: bool,
+    cursor: Option<(usize, usize)>,
+    clock: Clock,
+}
+
+impl Default for BufferBuilder {
+    fn default() -> Self {
+        Self {
+            reveal_rate: Duration::ZERO,
+            reveal_mode: RevealMode::Char,
+            reveal_curve: RevealCurve::Fixed,
+            hard_margin: None,
+            hyphen_hints: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            expand_tabs_on_save: false,
+            ensure_trailing_newline: None,
+            trim_trailing_whitespace: false,
+            caps: false,
+            auto_space_sentences: false,
+            soft_undo: false,
+            tab_stops: Vec::new(),
+            decimal_align: false,
+            append_only: false,
+            cursor: None,
+            clock: Clock::default(),
+        }
+    }
+}
+
+impl BufferBuilder {
+    /// Starts from the defaults: text shown at once, no margin and tab stops
+    /// every `DEFAULT_TAB_WIDTH` columns, with every other setting off
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long typed characters wait to appear
+    pub fn reveal_rate(mut self, rate: Duration) -> Self {
+        self.reveal_rate = rate;
+        self
+    }
+
+    /// Sets whether text appears a character or a word at a time
+    pub fn reveal_mode(mut self, mode: RevealMode) -> Self {
+        self.reveal_mode = mode;
+        self
+    }
+
+    /// Sets how the wait varies from one typed character to the next
+    pub fn reveal_curve(mut self, curve: RevealCurve) -> Self {
+        self.reveal_curve = curve;
+        self
+    }
+
+    /// Sets how many characters a line can hold. Opened lines longer than
+    /// that are split to fit, and joined up again on saving.
+    pub fn hard_margin(mut self, columns: Option<usize>) -> Self {
+        self.hard_margin = columns;
+        self
+    }
+
+    /// Sets whether a word can be hyphenated at the hard margin
+    pub fn hyphen_hints(mut self, enabled: bool) -> Self {
+        self.hyphen_hints = enabled;
+        self
+    }
+
+    /// Sets the columns between the tab stops opened text's tabs are shown up to
+    pub fn tab_width(mut self, columns: usize) -> Self {
+        self.tab_width = columns;
+        self
+    }
+
+    /// Sets whether lines opened with tabs are saved with spaces instead
+    pub fn expand_tabs_on_save(mut self, enabled: bool) -> Self {
+        self.expand_tabs_on_save = enabled;
+        self
+    }
+
+    /// Sets whether saving ends the text with a newline, or None to decide by
+    /// the file's extension
+    pub fn ensure_trailing_newline(mut self, enabled: Option<bool>) -> Self {
+        self.ensure_trailing_newline = enabled;
+        self
+    }
+
+    /// Sets whether saving leaves out whitespace at the ends of lines
+    pub fn trim_trailing_whitespace(mut self, enabled: bool) -> Self {
+        self.trim_trailing_whitespace = enabled;
+        self
+    }
+
+    /// Sets whether typed letters become capitals
+    pub fn caps(mut self, enabled: bool) -> Self {
+        self.caps = enabled;
+        self
+    }
+
+    /// Sets whether a capital typed straight after the end of a sentence gets
+    /// the space missed before it
+    pub fn auto_space_sentences(mut self, enabled: bool) -> Self {
+        self.auto_space_sentences = enabled;
+        self
+    }
+
+    /// Sets whether changes to the line being typed can be undone
+    pub fn soft_undo(mut self, enabled: bool) -> Self {
+        self.soft_undo = enabled;
+        self
+    }
+
+    /// Sets the columns the Tab key moves the carriage to
+    pub fn tab_stops(mut self, columns: Vec<usize>) -> Self {
+        self.tab_stops = columns;
+        self
+    }
+
+    /// Sets whether typing `.` after a number lines it up on the next tab stop
+    pub fn decimal_align(mut self, enabled: bool) -> Self {
+        self.decimal_align = enabled;
+        self
+    }
+
+    /// Sets whether the text already there is locked, with typing going on
+    /// from a new line after it
+    pub fn append_only(mut self, enabled: bool) -> Self {
+        self.append_only = enabled;
+        self
+    }
+
+    /// Sets the (line, column) the cursor goes back to, such as where the
+    /// last session on a file ended, each kept within the text if it has
+    /// shrunk since. It can't be set along with append-only text, whose cursor
+    /// always starts after the end.
+    pub fn cursor(mut self, position: Option<(usize, usize)>) -> Self {
+        self.cursor = position;
+        self
+    }
+
+    /// Sets where the time characters are typed at comes from: the system's
+    /// clock unless a session is being replayed
+    #[allow(dead_code)] // For replaying sessions; the app runs on the system's clock
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Makes an empty buffer with no file
+    pub fn build(&self) -> Result<Buffer, BuildError> {
+        self.check()?;
+        Ok(self.configure(Buffer::new(self.reveal_rate)))
+    }
+
+    /// Opens a file, after expanding `~` and variables in its path. If the
+    /// file doesn't exist, creates a new empty file. A file that looks binary
+    /// is refused.
+    pub fn open(&self, path: &str) -> io::Result<Buffer> {
+        self.check()?;
+        let expanded = paths::expand(path)?;
+        let path = &*expanded.to_string_lossy();
+        // Create the file if it doesn't exist. Without permission to, start
+        // empty anyway; saving will ask for somewhere else.
+        if !Path::new(path).exists() {
+            if let Err(e) = File::create(path) {
+                if e.kind() != io::ErrorKind::PermissionDenied {
+                    return Err(e);
+                }
+            }
+            let mut buffer = Buffer::new(self.reveal_rate);
+            buffer.file_path = Some(path.to_string());
+            return Ok(self.configure(buffer));
+        }
+
+        let bytes = fs::read(path)?;
+        if looks_binary(&bytes) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "this looks like a binary file, not text, so it wasn't opened",
+            ));
+        }
+        let mut buffer = self.load(bytes.as_slice())?;
+        buffer.file_path = Some(path.to_string());
+        Ok(self.configure(buffer))
+    }
+
+    /// Reads everything from a reader, such as piped stdin, into a buffer
+    /// with no file, so saving asks for a name. Control characters are shown
+    /// by their stand-ins, and tabs as spaces.
+    pub fn read<R: Read>(&self, reader: R) -> io::Result<Buffer> {
+        self.check()?;
+        let buffer = self.load(reader)?;
+        Ok(self.configure(buffer))
+    }
+
+    /// Returns an error for settings that can't go together
+    fn check(&self) -> Result<(), BuildError> {
+        if self.tab_width == 0 {
+            return Err(BuildError::TabWidth);
+        }
+        if self.hard_margin == Some(0) {
+            return Err(BuildError::HardMargin);
+        }
+        match self.cursor {
+            Some((line, _)) if self.append_only => Err(BuildError::CursorInLockedText(line)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Makes a buffer of the text read, laid out with the tab stops
+    fn load<R: Read>(&self, mut reader: R) -> io::Result<Buffer> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        let mut buffer = Buffer::new(self.reveal_rate);
+        buffer.tab_width = self.tab_width;
+        buffer.lines = loaded_lines(&content, self.tab_width);
+        Ok(buffer)
+    }
+
+    /// Puts the settings in place on a buffer just made
+    fn configure(&self, mut buffer: Buffer) -> Buffer {
+        buffer.set_clock(self.clock.clone());
+        buffer.tab_width = self.tab_width;
+        buffer.set_expand_tabs_on_save(self.expand_tabs_on_save);
+        buffer.set_ensure_trailing_newline(self.ensure_trailing_newline);
+        buffer.set_trim_trailing_whitespace(self.trim_trailing_whitespace);
+        buffer.set_hard_margin(self.hard_margin);
+        if let Some(columns) = self.hard_margin {
+            buffer.split_long_lines(columns);
+        }
+        buffer.set_hyphen_hints(self.hyphen_hints);
+        buffer.set_caps(self.caps);
+        buffer.set_auto_space_sentences(self.auto_space_sentences);
+        buffer.set_reveal_mode(self.reveal_mode);
+        buffer.set_reveal_curve(self.reveal_curve);
+        buffer.set_soft_undo(self.soft_undo);
+        buffer.set_tab_stops(self.tab_stops.clone());
+        buffer.set_decimal_align(self.decimal_align);
+        if self.append_only {
+            buffer.move_to_end();
+            buffer.lock_existing_lines();
+        }
+        if let Some((line, column)) = self.cursor {
+            buffer.move_to_position(line, column);
+        }
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::CharacterState;
+    use crate::scratch::ScratchDir;
+
+    #[test]
+    /// Test that each setting has its default, and that the old constructors
+    /// make the same buffer
+    fn test_defaults() {
+        let buffer = BufferBuilder::new().build().unwrap();
+        assert_eq!(buffer.reveal_rate(), Duration::ZERO);
+        assert_eq!(buffer.reveal_mode, RevealMode::Char);
+        assert_eq!(buffer.hard_margin(), None);
+        assert!(!buffer.hyphen_hints);
+        assert_eq!(buffer.tab_width, DEFAULT_TAB_WIDTH);
+        assert!(!buffer.expand_tabs_on_save);
+        assert_eq!(buffer.ensure_trailing_newline, None);
+        assert!(!buffer.trim_trailing_whitespace);
+        assert!(!buffer.caps());
+        assert!(!buffer.auto_space_sentences);
+        assert!(!buffer.soft_undo());
+        assert!(buffer.tab_stops.is_empty());
+        assert!(!buffer.decimal_align);
+        assert_eq!(buffer.locked_lines, 0);
+        assert_eq!(buffer.cursor_position(), (0, 0));
+        assert_eq!(buffer.file_path, None);
+
+        let builder = BufferBuilder::new();
+        assert_eq!(builder.reveal_curve, RevealCurve::Fixed);
+        let rate = Duration::from_millis(150);
+        assert_eq!(Buffer::new(rate).reveal_rate(), builder.clone().reveal_rate(rate).build().unwrap().reveal_rate());
+        let read = Buffer::from_reader("a\tb\nc".as_bytes(), rate).unwrap();
+        assert_eq!(read.text(), builder.reveal_rate(rate).read("a\tb\nc".as_bytes()).unwrap().text());
+    }
+
+    #[test]
+    /// Test that the settings given are in place on buffers made, opened and
+    /// read, with long lines split and the text locked or the cursor put back
+    fn test_settings() {
+        let builder = BufferBuilder::new()
+            .reveal_rate(Duration::from_millis(80))
+            .reveal_mode(RevealMode::Word)
+            .reveal_curve(RevealCurve::Cluster)
+            .hard_margin(Some(10))
+            .hyphen_hints(true)
+            .tab_width(4)
+            .expand_tabs_on_save(true)
+            .ensure_trailing_newline(Some(true))
+            .trim_trailing_whitespace(true)
+            .caps(true)
+            .auto_space_sentences(true)
+            .soft_undo(true)
+            .tab_stops(vec![30, 12])
+            .decimal_align(true);
+        let buffer = builder.build().unwrap();
+        assert_eq!(buffer.reveal_rate(), Duration::from_millis(80));
+        assert_eq!(buffer.reveal_mode, RevealMode::Word);
+        assert_eq!(buffer.hard_margin(), Some(10));
+        assert!(buffer.hyphen_hints && buffer.expand_tabs_on_save && buffer.trim_trailing_whitespace);
+        assert_eq!(buffer.ensure_trailing_newline, Some(true));
+        assert!(buffer.caps() && buffer.auto_space_sentences && buffer.soft_undo());
+        assert_eq!(buffer.tab_stops, [12, 30]);
+        assert!(buffer.decimal_align);
+
+        let buffer = builder.read("\tone two three\nfour".as_bytes()).unwrap();
+        let lines: Vec<String> = buffer.text_lines().collect();
+        assert_eq!(lines, ["    one ", "two three", "four"]);
+        assert_eq!(buffer.saved_text(), "    one two three\nfour\n");
+
+        let dir = ScratchDir::new("builder");
+        let path = dir.join("notes.md");
+        let path = path.to_string_lossy().to_string();
+        fs::write(&path, "first\nsecond\nthird").unwrap();
+        let buffer = builder.clone().cursor(Some((1, 6))).open(&path).unwrap();
+        assert_eq!(buffer.cursor_position(), (1, 6));
+        let buffer = builder.clone().cursor(Some((1, 2))).open(&path).unwrap();
+        assert_eq!(buffer.cursor_position(), (1, 2));
+        // The file got shorter since the position was kept
+        let buffer = builder.clone().cursor(Some((7, 9))).open(&path).unwrap();
+        assert_eq!(buffer.cursor_position(), (2, 5));
+        let buffer = builder.clone().cursor(Some((0, 40))).open(&path).unwrap();
+        assert_eq!(buffer.cursor_position(), (0, 5));
+        let buffer = builder.clone().append_only(true).open(&path).unwrap();
+        assert_eq!(buffer.locked_lines, 3);
+        assert_eq!(buffer.cursor_position(), (3, 0));
+    }
+
+    #[test]
+    /// Test that Enter straight after the cursor is put back above the last
+    /// line starts a new line under it, rather than typing onto the next one
+    fn test_cursor_then_new_line() {
+        let mut buffer = BufferBuilder::new().cursor(Some((0, 3))).read("one\ntwo\nthree".as_bytes()).unwrap();
+        buffer.new_line();
+        buffer.insert_char('X');
+        assert_eq!(buffer.text(), "one\nX\ntwo\nthree");
+        assert_eq!(buffer.cursor_position(), (1, 1));
+    }
+
+    #[test]
+    /// Test that typing after the cursor is put back mid-line goes in there,
+    /// striking nothing out
+    fn test_cursor_mid_line_then_type() {
+        let mut buffer = BufferBuilder::new().cursor(Some((2, 2))).read("one\ntwo\nthree".as_bytes()).unwrap();
+        assert!(!buffer.mark_out_mode);
+        buffer.insert_char('X');
+        assert_eq!(buffer.text(), "one\ntwo\nthXree");
+        assert!(buffer.lines[2].characters.iter().all(|character| character.state == CharacterState::Normal));
+    }
+
+    #[test]
+    /// Test that settings that can't go together are refused, saying why
+    fn test_errors() {
+        let error = BufferBuilder::new().tab_width(0).build().unwrap_err();
+        assert_eq!(error.to_string(), "the tab width must be more than 0");
+        let error = BufferBuilder::new().hard_margin(Some(0)).build().unwrap_err();
+        assert_eq!(error.to_string(), "the hard margin must be more than 0");
+
+        let builder = BufferBuilder::new().append_only(true).cursor(Some((4, 0)));
+        assert_eq!(builder.build().unwrap_err(), BuildError::CursorInLockedText(4));
+        let error = builder.read("text".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            error.to_string(),
+            "the cursor can't go back to line 5 when the text already written can't be changed"
+        );
+
+        // Each on its own is fine
+        assert!(BufferBuilder::new().append_only(true).build().is_ok());
+        assert!(BufferBuilder::new().cursor(Some((4, 0))).build().is_ok());
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::BufferTime;
+
+/// Longest pause between two keys that keeps them in the same cluster
+const CLUSTER_GAP: Duration = Duration::from_millis(150);
+/// How much of the previous character's delay each further character of a
+/// cluster waits
+const CLUSTER_STEP: f64 = 0.8;
+/// Shortest delay in a cluster, as a share of the reveal delay
+const CLUSTER_FLOOR: f64 = 0.4;
+
+/// How long typed characters wait before they appear
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RevealCurve {
+    /// Every character waits the whole reveal delay
+    #[default]
+    Fixed,
+    /// A burst of keys speeds up as it goes, like typebars catching up
+    Cluster,
+}
+
+/// Parses a reveal curve: `fixed` or `cluster`
+pub fn parse_reveal_curve(value: &str) -> Result<RevealCurve, String> {
+    match value {
+        "fixed" => Ok(RevealCurve::Fixed),
+        "cluster" => Ok(RevealCurve::Cluster),
+        _ => Err(format!("must be `fixed` or `cluster`, not `{}`", value)),
+    }
+}
+
+impl RevealCurve {
+    /// Returns a fresh schedule following this curve
+    pub fn schedule(self) -> Box<dyn RevealSchedule> {
+        match self {
+            Self::Fixed => Box::new(FixedDelay),
+            Self::Cluster => Box::<ClusterDelay>::default(),
+        }
+    }
+}
+
+/// Decides how long each typed character waits before it appears. It is
+/// asked once per character, in the order they are typed.
+pub trait RevealSchedule: fmt::Debug {
+    /// Returns how long a character typed at `typed_at` waits, at most the
+    /// reveal delay `rate`
+    fn delay(&mut self, typed_at: BufferTime, rate: Duration) -> Duration;
+}
+
+/// Waits the whole reveal delay every time
+#[derive(Debug, Default)]
+pub struct FixedDelay;
+
+impl RevealSchedule for FixedDelay {
+    fn delay(&mut self, _typed_at: BufferTime, rate: Duration) -> Duration {
+        rate
+    }
+}
+
+/// Waits the whole reveal delay for the first key of a burst, then a little
+/// less for each key that follows within `CLUSTER_GAP` of the one before,
+/// down to `CLUSTER_FLOOR` of it. A character never appears before the one
+/// typed ahead of it.
+#[derive(Debug, Default)]
+pub struct ClusterDelay {
+    /// When the last character was typed and when it appears
+    previous: Option<(BufferTime, BufferTime)>,
+    /// How many characters the cluster has had after its first
+    run: i32,
+}
+
+impl RevealSchedule for ClusterDelay {
+    fn delay(&mut self, typed_at: BufferTime, rate: Duration) -> Duration {
+        let gap = CLUSTER_GAP.as_millis() as u64;
+        let previous = self.previous.filter(|(typed, _)| typed_at.0.saturating_sub(typed.0) <= gap);
+        self.run = if previous.is_some() { self.run + 1 } else { 0 };
+        let share = CLUSTER_STEP.powi(self.run).max(CLUSTER_FLOOR);
+        let shown = (typed_at + rate.mul_f64(share)).max(previous.map_or(typed_at, |(_, shown)| shown));
+        self.previous = Some((typed_at, shown));
+        Duration::from_millis(shown.0 - typed_at.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asks a schedule about keys typed at these milliseconds, returning when
+    /// each appears
+    fn shown(schedule: &mut dyn RevealSchedule, typed: &[u64], rate: u64) -> Vec<u64> {
+        typed
+            .iter()
+            .map(|&at| at + schedule.delay(BufferTime(at), Duration::from_millis(rate)).as_millis() as u64)
+            .collect()
+    }
+
+    #[test]
+    /// Test that the fixed curve waits the whole delay whatever the rhythm
+    fn test_fixed() {
+        let mut schedule = RevealCurve::Fixed.schedule();
+        assert_eq!(shown(schedule.as_mut(), &[0, 40, 80, 1000], 300), [300, 340, 380, 1300]);
+    }
+
+    #[test]
+    /// Test that a burst speeds up key by key down to the floor, and that a
+    /// pause starts a new cluster at the whole delay
+    fn test_cluster() {
+        let mut schedule = RevealCurve::Cluster.schedule();
+        // Delays of 300, 240, 192, 153, 122 and then the floor of 120
+        let typed = [0, 100, 200, 300, 400, 500];
+        assert_eq!(shown(schedule.as_mut(), &typed, 300), [300, 340, 392, 453, 522, 620]);
+
+        // 150ms is still the same cluster; 151ms isn't
+        assert_eq!(shown(schedule.as_mut(), &[650, 801], 300), [770, 1101]);
+    }
+
+    #[test]
+    /// Test that fast typing can't make a character overtake the one before it
+    fn test_cluster_order() {
+        let mut schedule = RevealCurve::Cluster.schedule();
+        assert_eq!(shown(schedule.as_mut(), &[0, 10, 20, 30], 300), [300, 300, 300, 300]);
+
+        let mut schedule = RevealCurve::Cluster.schedule();
+        assert_eq!(shown(schedule.as_mut(), &[0, 120, 240, 360], 100), [100, 200, 304, 411]);
+    }
+
+    #[test]
+    /// Test parsing the setting
+    fn test_parse_reveal_curve() {
+        assert_eq!(parse_reveal_curve("cluster"), Ok(RevealCurve::Cluster));
+        assert_eq!(parse_reveal_curve("fixed"), Ok(RevealCurve::Fixed));
+        assert_eq!(parse_reveal_curve("ease"), Err("must be `fixed` or `cluster`, not `ease`".to_string()));
+    }
+}
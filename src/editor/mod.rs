@@ -1,11 +1,178 @@
+mod builder;
+mod curve;
 mod types;
+mod wrap;
+pub use builder::*;
+pub use curve::*;
 pub use types::*;
+pub use wrap::*;
 
-use std::time::Duration;
-use std::fs::{self, File};
-use std::io;
+use std::collections::VecDeque;
+use std::ops::{Range, RangeInclusive};
+use std::time::{Duration, Instant};
+use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
 
+use unicode_width::UnicodeWidthChar;
+
+use crate::clock::Clock;
+use crate::paths;
+use crate::sound::SoundType;
+
+/// How many columns before the hard margin the bell rings
+pub const MARGIN_BELL_COLUMNS: usize = 8;
+
+/// How much the reveal rate changes with each press of the adjustment keys
+pub const REVEAL_STEP: Duration = Duration::from_millis(50);
+
+/// Slowest the reveal rate can be set to while typing
+pub const MAX_REVEAL_RATE: Duration = Duration::from_millis(2000);
+
+/// Pause at the end of each line as an opened file types itself out with `--reveal-on-load`
+pub const LOAD_RETURN_PAUSE: Duration = Duration::from_millis(120);
+
+/// Columns between the tab stops loaded tabs are expanded to, unless set
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// Most changes soft undo remembers; older ones are forgotten first
+pub const MAX_UNDO: usize = 200;
+
+/// Volume of the tick as the carriage lands on a tab stop, from 0 to 1
+pub const TAB_STOP_VOLUME: f32 = 0.6;
+
+/// How much of a file is looked at to tell whether it is text
+const SNIFF_BYTES: usize = 8 * 1024;
+
+/// Share of the characters looked at that can be control characters or
+/// invalid UTF-8 before a file is taken for binary
+const MAX_CONTROL_SHARE: f64 = 0.3;
+
+/// Returns whether the start of a file looks like binary rather than text:
+/// it holds a NUL byte, or too much of it isn't printable
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    let sniffed = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    if sniffed.contains(&0) {
+        return true;
+    }
+    let text = String::from_utf8_lossy(sniffed);
+    let (mut total, mut unprintable) = (0, 0);
+    for c in text.chars() {
+        total += 1;
+        if c == char::REPLACEMENT_CHARACTER || (c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c')) {
+            unprintable += 1;
+        }
+    }
+    total > 0 && unprintable as f64 / total as f64 > MAX_CONTROL_SHARE
+}
+
+/// Returns the visible stand-in for a control character, such as `␛` for
+/// Escape, so the terminal is never sent one raw. Tabs are kept.
+pub fn control_picture(c: char) -> Option<char> {
+    match c {
+        '\t' => None,
+        '\0'..='\x1f' => char::from_u32(0x2400 + c as u32),
+        '\x7f' => Some('\u{2421}'),
+        c if c.is_control() => Some(char::REPLACEMENT_CHARACTER),
+        _ => None,
+    }
+}
+
+/// Characters that print nothing and take no room, which some input methods
+/// and terminals send as keys: zero-width spaces and joiners, direction marks,
+/// embeddings and isolates, invisible operators and the byte order mark.
+/// Combining accents aren't among them, as they belong to the letter before.
+const INVISIBLE: [RangeInclusive<char>; 6] = [
+    '\u{180E}'..='\u{180E}',
+    '\u{200B}'..='\u{200F}',
+    '\u{202A}'..='\u{202E}',
+    '\u{2060}'..='\u{2064}',
+    '\u{2066}'..='\u{2069}',
+    '\u{FEFF}'..='\u{FEFF}',
+];
+
+/// Returns whether a character is one of the invisible ones, which are left
+/// out of what is typed or pasted
+pub fn is_invisible(c: char) -> bool {
+    INVISIBLE.iter().any(|range| range.contains(&c))
+}
+
+/// What a change to the line being typed did
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditKind {
+    /// A character was typed
+    Inserted,
+    /// A character was marked out, which had or hadn't changed since the
+    /// last save before that
+    MarkedOut { changed: bool },
+}
+
+/// A change to the line being typed that soft undo can take back
+#[derive(Debug, Clone)]
+struct Edit {
+    /// What changed
+    kind: EditKind,
+    /// Column of the character that changed
+    column: usize,
+    /// Cursor column before the change
+    cursor: usize,
+    /// Whether mark-out mode was on before the change
+    mark_out_mode: bool,
+    /// Whether the buffer differed from its file before the change
+    modified: bool,
+}
+
+/// Splits loaded text into lines on every newline, the exact inverse of how
+/// `Buffer::text` joins them, so blank lines (even trailing ones) come back as
+/// typed. Empty text is one empty line, like a new buffer. Carriage returns
+/// other than those ending lines are dropped, and tabs become spaces up to
+/// the next tab stop, so every character takes the cells its width says.
+fn loaded_lines(text: &str, tab_width: usize) -> Vec<Line> {
+    // UTF-8 has no byte order, so the mark only says the file is UTF-8
+    let text = text.strip_prefix('\u{FEFF}').inspect(|_| log::info!("byte order mark dropped")).unwrap_or(text);
+    let (mut lines, mut tabs, mut returns) = (Vec::new(), 0, 0);
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        returns += line.matches('\r').count();
+        let line = line.replace('\r', "");
+        tabs += line.matches('\t').count();
+        lines.push(loaded_line(&line, tab_width));
+    }
+    if tabs > 0 || returns > 0 {
+        log::info!("loaded text normalized: {} tabs expanded, {} stray carriage returns dropped", tabs, returns);
+    }
+    lines
+}
+
+/// Makes a line of loaded text, without its line ending, into a buffer line,
+/// keeping the text if tabs had to be expanded
+fn loaded_line(text: &str, tab_width: usize) -> Line {
+    let mut line = Line::new();
+    let mut column = 0;
+    let loaded = |value, original| {
+        let character = Character::new(value, BufferTime(0));
+        Character { typed: false, changed: false, original, ..character }
+    };
+    for c in text.chars() {
+        if c == '\t' {
+            let spaces = tab_width - column % tab_width;
+            line.characters.extend((0..spaces).map(|_| loaded(' ', None)));
+            column += spaces;
+            continue;
+        }
+        let character = match control_picture(c) {
+            Some(picture) => loaded(picture, Some(c)),
+            None => loaded(c, None),
+        };
+        column += character.value.width().unwrap_or(0);
+        line.push(character);
+    }
+    if text.contains('\t') {
+        line.loaded = Some(text.to_string());
+    }
+    line
+}
+
 /// The main editor buffer that holds all text content
 #[derive(Debug)]
 pub struct Buffer {
@@ -19,93 +186,495 @@ pub struct Buffer {
     mark_out_mode: bool,
     /// How many characters to reveal per second
     reveal_rate: Duration,
+    /// Characters typed up to here stay shown even if the reveal rate has
+    /// since been slowed down
+    revealed_until: Option<BufferTime>,
+    /// When the buffer was created, which character times count from
+    epoch: Instant,
+    /// Where the time characters are typed at comes from
+    clock: Clock,
+    /// When the last character spaced out by `type_out` appears, while that
+    /// may still be to come
+    typed_out_until: Option<BufferTime>,
     /// The file path if the buffer is associated with a file
     pub file_path: Option<String>,
     /// Whether the buffer has unsaved changes
     is_modified: bool,
+    /// Lines at the top that backspace can't step back into
+    locked_lines: usize,
+    /// Longest a line can get before typing is refused, like a carriage at its stop
+    hard_margin: Option<usize>,
+    /// Whether typing has been refused at the margin since the last Enter or backspace
+    at_margin: bool,
+    /// Whether a hyphen typed mid-word into the last column before the hard
+    /// margin carries on with a new line
+    hyphen_hints: bool,
+    /// Whether text appears a character or a word at a time
+    reveal_mode: RevealMode,
+    /// How long each typed character waits to appear
+    reveal_schedule: Box<dyn RevealSchedule>,
+    /// How much sooner than the reveal delay the last typed character appears
+    last_lead: Duration,
+    /// Whether typed letters become capitals, like a typewriter for labels
+    caps: bool,
+    /// Whether a capital typed straight after `.`, `!` or `?` gets the space
+    /// that was missed before it
+    auto_space_sentences: bool,
+    /// The lead of the space put in before the last typed character, if one was
+    auto_spaced: Option<Duration>,
+    /// Whether changes to the line being typed can be undone
+    soft_undo: bool,
+    /// Changes to the line being typed, newest last. Enter clears it.
+    undo: VecDeque<Edit>,
+    /// Changes not yet taken with `take_changes`, once they are being tracked
+    changes: Option<Vec<Change>>,
+    /// Whether saving leaves out spaces and tabs at the ends of lines
+    trim_trailing_whitespace: bool,
+    /// Columns between tab stops, for tabs in loaded text
+    tab_width: usize,
+    /// Whether lines loaded with tabs are saved with the spaces shown for
+    /// them, rather than as they were loaded
+    expand_tabs_on_save: bool,
+    /// Whether the saved text always ends with a newline, or None to decide
+    /// by the file's extension
+    ensure_trailing_newline: Option<bool>,
+    /// The line left by backspacing up past its start, which Down goes back
+    /// to. Backspacing up further keeps the first line left.
+    abandoned_line: Option<usize>,
+    /// Columns the Tab key moves the carriage to, in order
+    tab_stops: Vec<usize>,
+    /// Whether typing `.` after a number moves the number along so the `.`
+    /// lands on the next tab stop
+    decimal_align: bool,
+    /// Counts changes to the text; each line keeps the count as of its last one
+    revision: u64,
 }
 
 impl Buffer {
     /// Creates a new empty buffer with the specified reveal rate
     pub fn new(reveal_rate: Duration) -> Self {
-        let mut lines = Vec::new();
-        lines.push(Line::new());
-        
         Self {
-            lines,
+            lines: vec![Line::new()],
             current_line: 0,
             current_column: 0,
             mark_out_mode: false,
             reveal_rate,
+            revealed_until: None,
+            epoch: Instant::now(),
+            clock: Clock::default(),
+            typed_out_until: None,
             file_path: None,
             is_modified: false,
+            locked_lines: 0,
+            hard_margin: None,
+            at_margin: false,
+            hyphen_hints: false,
+            reveal_mode: RevealMode::Char,
+            reveal_schedule: RevealCurve::Fixed.schedule(),
+            last_lead: Duration::ZERO,
+            caps: false,
+            auto_space_sentences: false,
+            auto_spaced: None,
+            soft_undo: false,
+            undo: VecDeque::new(),
+            changes: None,
+            trim_trailing_whitespace: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            expand_tabs_on_save: false,
+            ensure_trailing_newline: None,
+            abandoned_line: None,
+            tab_stops: Vec::new(),
+            decimal_align: false,
+            revision: 0,
         }
     }
 
-    /// Creates a new buffer and loads content from the specified file.
-    /// If the file doesn't exist, creates a new empty file.
+    /// Creates a new buffer and loads content from the specified file, after
+    /// expanding `~` and variables in its path. If the file doesn't exist,
+    /// creates a new empty file. A file that looks binary is refused.
+    #[allow(dead_code)] // For opening a file without settings; the app goes through a builder
     pub fn from_file(path: &str, reveal_rate: Duration) -> io::Result<Self> {
-        let mut buffer = Self::new(reveal_rate);
-        buffer.file_path = Some(path.to_string());
+        BufferBuilder::new().reveal_rate(reveal_rate).open(path)
+    }
+
+    /// Creates a new buffer holding everything read from a reader, such as piped
+    /// stdin. The buffer has no file, so saving asks for a name. Control
+    /// characters are shown by their stand-ins, and tabs as spaces.
+    pub fn from_reader<R: Read>(reader: R, reveal_rate: Duration) -> io::Result<Self> {
+        BufferBuilder::new().reveal_rate(reveal_rate).read(reader)
+    }
 
-        // Create the file if it doesn't exist
-        if !Path::new(path).exists() {
-            File::create(path)?;
-            buffer.lines.clear();
-            buffer.lines.push(Line::new());
-            return Ok(buffer);
+    /// Puts `text` in place of the whole buffer, as if it had been loaded,
+    /// with the cursor at its end. The file and settings stay, and the buffer
+    /// counts as changed until it is saved.
+    pub fn replace_text(&mut self, text: &str) {
+        self.lines = loaded_lines(text, self.tab_width);
+        // None of it is in the file yet
+        for character in self.lines.iter_mut().flat_map(|line| &mut line.characters) {
+            character.changed = true;
+        }
+        self.locked_lines = 0;
+        self.mark_out_mode = false;
+        self.at_margin = false;
+        self.undo.clear();
+        self.abandoned_line = None;
+        let last = self.lines.len() - 1;
+        (self.current_line, self.current_column) = (last, self.lines[last].len());
+        self.record(Change::CursorMoved { line: self.current_line, column: self.current_column });
+        if let Some(columns) = self.hard_margin {
+            self.split_long_lines(columns);
         }
+        self.touch_from(0);
+        self.is_modified = true;
+    }
 
-        // Load existing content
-        let content = fs::read_to_string(path)?;
-        
-        // Split content into lines and populate buffer
-        buffer.lines.clear();
-        for line in content.lines() {
-            let mut buffer_line = Line::new();
-            for c in line.chars() {
-                buffer_line.push(Character::new(c));
+    /// Iterates over the text of each line, leaving out marked-out characters
+    pub fn text_lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.lines.iter().map(|line| {
+            line.characters
+                .iter()
+                .filter(|character| character.state == CharacterState::Normal)
+                .map(|character| line.saved_value(character))
+                .collect()
+        })
+    }
+
+    /// Returns the text: every line without its marked-out characters, joined
+    /// by newlines. Continuation lines are joined to the line before them
+    /// without one.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (idx, (line, characters)) in self.lines.iter().zip(self.text_lines()).enumerate() {
+            if idx > 0 && !line.continuation {
+                text.push('\n');
             }
-            buffer.lines.push(buffer_line);
+            text.push_str(&characters);
         }
-        
-        // Ensure there's at least one line
-        if buffer.lines.is_empty() {
-            buffer.lines.push(Line::new());
+        text
+    }
+
+    /// Returns the text that would be saved: as `text`, but without trailing
+    /// whitespace when trimming is on, with the tabs of lines loaded with
+    /// them and not typed in since, unless they are saved expanded, and ending
+    /// with a newline if the file should. The buffer itself keeps the
+    /// whitespace, and never gets the newline.
+    pub fn saved_text(&self) -> String {
+        let mut text = String::new();
+        let mut as_loaded = false;
+        for (idx, line) in self.lines.iter().enumerate() {
+            if idx > 0 && !line.continuation {
+                text.push('\n');
+                as_loaded = false;
+            }
+            // Its continuation lines are in what was loaded too
+            if as_loaded {
+                continue;
+            }
+            if let Some(loaded) = line.loaded.as_deref().filter(|_| !line.edited && !self.expand_tabs_on_save) {
+                let typing = self.saved_with(idx).contains(&self.current_line);
+                let trim = self.trim_trailing_whitespace && !typing;
+                text.push_str(if trim { loaded.trim_end_matches([' ', '\t']) } else { loaded });
+                as_loaded = true;
+                continue;
+            }
+            let end = match self.trim_trailing_whitespace {
+                true => self.trailing_whitespace(idx).unwrap_or(line.len()),
+                false => line.len(),
+            };
+            text.extend(
+                line.characters[..end]
+                    .iter()
+                    .filter(|character| character.state == CharacterState::Normal)
+                    .map(|character| line.saved_value(character)),
+            );
         }
-        
-        Ok(buffer)
+        if self.adds_trailing_newline() && !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Turns leaving out trailing whitespace when saving on or off
+    pub fn set_trim_trailing_whitespace(&mut self, enabled: bool) {
+        self.trim_trailing_whitespace = enabled;
+    }
+
+    /// Sets the columns between tab stops, expanding the tabs of loaded lines
+    /// not yet typed in or split at the margin again to suit
+    #[allow(dead_code)] // For changing the tab stops once open; a builder sets them first
+    pub fn set_tab_width(&mut self, columns: usize) {
+        self.tab_width = columns.max(1);
+        for idx in 0..self.lines.len() {
+            let split = self.lines.get(idx + 1).is_some_and(|next| next.continuation);
+            let line = &self.lines[idx];
+            let Some(loaded) = line.loaded.as_deref().filter(|_| !line.edited && !split) else {
+                continue;
+            };
+            let relaid = loaded_line(loaded, self.tab_width);
+            self.lines[idx].characters = relaid.characters;
+            self.touch_from(idx);
+        }
+        let (line, column) = (self.current_line, self.current_column);
+        self.set_cursor(line, column.min(self.lines[line].len()));
+    }
+
+    /// Sets whether saving ends the text with a newline: always, never, or
+    /// with None only for `.txt` and `.md` files. A `.typ` file is saved as
+    /// it is either way.
+    pub fn set_ensure_trailing_newline(&mut self, enabled: Option<bool>) {
+        self.ensure_trailing_newline = enabled;
+    }
+
+    /// Returns whether saving ends the text with a newline if it lacks one
+    fn adds_trailing_newline(&self) -> bool {
+        let extension = self.file_path.as_deref().and_then(|path| Path::new(path).extension());
+        let extension = extension.map(|extension| extension.to_string_lossy().to_lowercase());
+        match (extension.as_deref(), self.ensure_trailing_newline) {
+            (Some("typ"), _) => false,
+            (_, Some(enabled)) => enabled,
+            (extension, None) => matches!(extension, Some("txt" | "md")),
+        }
+    }
+
+    /// Turns saving lines loaded with tabs with the spaces shown for them on
+    /// or off
+    pub fn set_expand_tabs_on_save(&mut self, enabled: bool) {
+        self.expand_tabs_on_save = enabled;
+    }
+
+    /// Returns the column from which the rest of a line is trailing whitespace:
+    /// the spaces and tabs after its last other character, marked-out
+    /// characters aside. None if there is none, if the line is part of the
+    /// one being typed, or if more text follows on a continuation line.
+    pub fn trailing_whitespace(&self, line: usize) -> Option<usize> {
+        let Range { start, end } = self.saved_with(line);
+        if (start..end).contains(&self.current_line)
+            || !self.lines[line + 1..end].iter().all(|line| line.trailing_whitespace_len() == line.len())
+        {
+            return None;
+        }
+        let characters = &self.lines[line].characters;
+        let column = characters.len() - self.lines[line].trailing_whitespace_len();
+        characters[column..]
+            .iter()
+            .any(|character| character.state == CharacterState::Normal)
+            .then_some(column)
+    }
+
+    /// Returns the buffer lines saved as one line with `line`: it and the
+    /// continuation lines before and after it
+    pub fn saved_with(&self, line: usize) -> Range<usize> {
+        let start = (1..=line).rev().find(|&idx| !self.lines[idx].continuation).unwrap_or(0);
+        let end = (line + 1..self.lines.len()).find(|&idx| !self.lines[idx].continuation).unwrap_or(self.lines.len());
+        start..end
+    }
+
+    /// Splits lines longer than `columns` characters into lines that fit, each
+    /// marked as a continuation so saving joins them up again, breaking where
+    /// `find_break_point` says. The cursor stays on the character it was at.
+    pub fn split_long_lines(&mut self, columns: usize) {
+        if columns == 0 {
+            return;
+        }
+        let (cursor_line, cursor_column) = (self.current_line, self.current_column);
+        let mut cursor = (cursor_line, cursor_column);
+        // Where each of the old lines starts among the new ones
+        let mut starts = Vec::with_capacity(self.lines.len());
+        let mut lines = Vec::with_capacity(self.lines.len());
+        // Lines from the first split on change, or at least move down
+        let mut first_split = None;
+        for (idx, line) in std::mem::take(&mut self.lines).into_iter().enumerate() {
+            starts.push(lines.len());
+            let (mut rest, mut continuation) = (line.characters, line.continuation);
+            let (revision, edited, mut loaded) = (line.revision, line.edited, line.loaded);
+            // Column of the old line that `rest` starts at
+            let mut start = 0;
+            while rest.len() > columns {
+                let values: Vec<char> = rest.iter().map(|character| character.value).collect();
+                let cut = find_break_point(&values, columns);
+                let tail = rest.split_off(cut);
+                if idx == cursor_line && (start..start + cut).contains(&cursor_column) {
+                    cursor = (lines.len(), cursor_column - start);
+                }
+                self.record(Change::LineSplit { line: lines.len(), column: cut });
+                first_split.get_or_insert(lines.len());
+                lines.push(Line { characters: rest, continuation, revision, edited, loaded: loaded.take() });
+                (rest, continuation, start) = (tail, true, start + cut);
+            }
+            if idx == cursor_line && cursor_column >= start {
+                cursor = (lines.len(), cursor_column - start);
+            }
+            lines.push(Line { characters: rest, continuation, revision, edited, loaded });
+        }
+        self.locked_lines = starts.get(self.locked_lines).copied().unwrap_or(lines.len());
+        // Back to the last piece of a line left, where typing left off
+        let last_piece = |line: usize| starts.get(line + 1).copied().unwrap_or(lines.len()) - 1;
+        self.abandoned_line = self.abandoned_line.map(last_piece);
+        self.lines = lines;
+        if let Some(line) = first_split {
+            self.touch_from(line);
+        }
+        self.set_cursor(cursor.0, cursor.1);
+        self.undo.clear();
     }
 
     /// Saves the buffer content to its associated file
     pub fn save(&mut self) -> io::Result<()> {
         if let Some(path) = &self.file_path {
-            let mut content = String::new();
-            
-            // Convert buffer content to string
-            for (i, line) in self.lines.iter().enumerate() {
-                if i > 0 {
-                    content.push('\n');
-                }
-                for character in &line.characters {
-                    if character.state == CharacterState::Normal {
-                        content.push(character.value);
-                    }
-                }
-            }
-            
-            // Write to file
-            fs::write(path, content)?;
-            self.is_modified = false;
+            fs::write(path, self.saved_text())?;
+            self.mark_saved();
             Ok(())
         } else {
-            Err(io::Error::new(io::ErrorKind::Other, "No file path associated with buffer"))
+            Err(io::Error::other("No file path associated with buffer"))
+        }
+    }
+
+    /// Marks the buffer saved once `text` has been written to its file in the
+    /// background, unless it has changed since. Returns whether it was marked.
+    pub fn wrote(&mut self, text: &str) -> bool {
+        let unchanged = self.saved_text() == text;
+        if unchanged {
+            self.mark_saved();
+        }
+        unchanged
+    }
+
+    /// Records that the file now holds the buffer's text
+    fn mark_saved(&mut self) {
+        self.is_modified = false;
+        self.record(Change::Saved);
+        // Undoing anything from before the save makes the text differ from the file
+        for edit in &mut self.undo {
+            edit.modified = true;
+            if let EditKind::MarkedOut { changed } = &mut edit.kind {
+                *changed = true;
+            }
+        }
+        self.revision += 1;
+        for line in &mut self.lines {
+            let mut cleared = false;
+            for character in line.characters.iter_mut().filter(|character| character.changed) {
+                character.changed = false;
+                cleared = true;
+            }
+            if cleared {
+                line.revision = self.revision;
+            }
+        }
+    }
+
+    /// Sets the file path for the buffer, expanding `~` and variables in it
+    pub fn set_file_path(&mut self, path: &str) -> io::Result<()> {
+        self.file_path = Some(paths::expand(path)?.to_string_lossy().into_owned());
+        Ok(())
+    }
+
+    /// Sets how many characters a line can hold before typing is refused
+    pub fn set_hard_margin(&mut self, columns: Option<usize>) {
+        self.hard_margin = columns;
+    }
+
+    /// Returns how many characters a line can hold, if there is a hard margin
+    pub fn hard_margin(&self) -> Option<usize> {
+        self.hard_margin
+    }
+
+    /// Returns the number of characters in the longest line
+    pub fn longest_line(&self) -> usize {
+        self.lines.iter().map(Line::len).max().unwrap_or(0)
+    }
+
+    /// Turns hyphenating at the hard margin on or off
+    pub fn set_hyphen_hints(&mut self, enabled: bool) {
+        self.hyphen_hints = enabled;
+    }
+
+    /// Returns how many characters are left before the hard margin if the word
+    /// being typed could be hyphenated there: the margin bell has rung and the
+    /// cursor is in the middle of a word at the end of the line
+    pub fn hyphen_hint(&self) -> Option<usize> {
+        let margin = self.hard_margin.filter(|_| self.hyphen_hints)?;
+        let line = self.current_line();
+        let bell_column = margin.saturating_sub(MARGIN_BELL_COLUMNS);
+        let hint = !self.mark_out_mode
+            && self.current_column == line.len()
+            && (bell_column..margin).contains(&line.len())
+            && line.characters.last().is_some_and(|character| {
+                character.state == CharacterState::Normal
+                    && character.value != '-'
+                    && !is_word_boundary(character.value)
+            });
+        hint.then(|| margin - line.len())
+    }
+
+    /// Chooses whether text appears a character or a word at a time
+    pub fn set_reveal_mode(&mut self, mode: RevealMode) {
+        self.reveal_mode = mode;
+    }
+
+    /// Chooses how long typed characters wait to appear. In word mode a word
+    /// appears whole, so every character waits the whole reveal delay.
+    pub fn set_reveal_curve(&mut self, curve: RevealCurve) {
+        self.reveal_schedule = curve.schedule();
+    }
+
+    /// Turns soft undo on or off
+    pub fn set_soft_undo(&mut self, enabled: bool) {
+        self.soft_undo = enabled;
+        self.undo.clear();
+    }
+
+    /// Returns whether soft undo is on
+    pub fn soft_undo(&self) -> bool {
+        self.soft_undo
+    }
+
+    /// Takes back the latest typing or marking out on the line being typed,
+    /// putting the cursor back where it was. Returns false if there is
+    /// nothing left to undo on this line.
+    pub fn undo(&mut self) -> bool {
+        let Some(edit) = self.undo.pop_back() else {
+            return false;
+        };
+        let (line, column) = (self.current_line, edit.column);
+        match edit.kind {
+            EditKind::Inserted => {
+                self.lines[line].characters.remove(column);
+                self.touch(line);
+                self.record(Change::CharRemoved { line, column });
+            }
+            EditKind::MarkedOut { changed } => {
+                let character = &mut self.lines[line].characters[column];
+                if character.state == CharacterState::MarkedOut {
+                    character.state = CharacterState::Normal;
+                    character.changed = changed;
+                    self.touch(line);
+                    self.record(Change::CharRestored { line, column });
+                }
+            }
         }
+        self.set_cursor(line, edit.cursor);
+        self.mark_out_mode = edit.mark_out_mode;
+        self.is_modified = edit.modified;
+        self.at_margin = false;
+        true
     }
 
-    /// Sets the file path for the buffer
-    pub fn set_file_path(&mut self, path: &str) {
-        self.file_path = Some(path.to_string());
+    /// Remembers a change for soft undo, if it is on
+    fn record_edit(&mut self, kind: EditKind, column: usize, cursor: usize, mark_out_mode: bool, modified: bool) {
+        if !self.soft_undo {
+            return;
+        }
+        if self.undo.len() == MAX_UNDO {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(Edit {
+            kind,
+            column,
+            cursor,
+            mark_out_mode,
+            modified,
+        });
     }
 
     /// Returns whether the buffer has unsaved changes
@@ -113,61 +682,556 @@ impl Buffer {
         self.is_modified
     }
 
-    /// Inserts a character at the current position
-    pub fn insert_char(&mut self, c: char) {
+    /// Applies an edit, returning what should happen outside the buffer because
+    /// of it: sounds timed to the reveal, the bell and flashes at the margin
+    pub fn apply(&mut self, event: EditorEvent) -> Vec<Effect> {
+        let reveal_time = self.clock.now() + self.reveal_rate;
+        let column = self.current_column;
+        let mut effects = match event {
+            EditorEvent::Char(c) => {
+                let word_pending = self.word_pending();
+                match self.insert_char(c) {
+                    // The carriage is at its stop: one bell and flash for the
+                    // first key, then silence until Enter
+                    Typed::HitMargin => vec![Effect::Bell, Effect::Flash],
+                    Typed::Refused => Vec::new(),
+                    typed => {
+                        // A space put in first clicks like one typed, finishing
+                        // the word it ends
+                        let space = self.auto_spaced.map(|lead| {
+                            let sound = if word_pending { SoundType::Word(' ') } else { SoundType::KeyPress(' ') };
+                            Effect::PlaySound { sound, at: reveal_time - lead }
+                        });
+                        let word_pending = word_pending && space.is_none();
+                        // In word mode a word clicks once, when it is finished
+                        let sound = match (word_pending, self.word_pending()) {
+                            (true, false) => Some(SoundType::Word(c)),
+                            (false, false) => Some(SoundType::KeyPress(c)),
+                            (_, true) => None,
+                        };
+                        // Heard when the character appears, which the curve can bring forward
+                        let at = reveal_time - self.last_lead;
+                        let mut effects: Vec<Effect> =
+                            space.into_iter().chain(sound.map(|sound| Effect::PlaySound { sound, at })).collect();
+                        match typed {
+                            Typed::MarginBell => effects.push(Effect::Bell),
+                            Typed::MarkedOut => effects.push(Effect::MarkedOut),
+                            Typed::Hyphenated => {
+                                effects.push(Effect::PlaySound { sound: SoundType::Return, at: reveal_time });
+                                effects.push(Effect::Committed);
+                            }
+                            _ => (),
+                        }
+                        effects
+                    }
+                }
+            }
+            EditorEvent::Backspace => {
+                self.backspace();
+                Vec::new()
+            }
+            EditorEvent::NewLine => {
+                self.new_line();
+                vec![Effect::PlaySound { sound: SoundType::Return, at: reveal_time }, Effect::Committed]
+            }
+            EditorEvent::Right => {
+                self.move_right();
+                Vec::new()
+            }
+            EditorEvent::Down => {
+                self.return_to_abandoned_line();
+                Vec::new()
+            }
+            EditorEvent::FinishCorrection => {
+                self.finish_correction();
+                Vec::new()
+            }
+            EditorEvent::Tab => match self.tab() {
+                // Without a stop ahead, Tab is the space bar
+                None => return self.apply(EditorEvent::Char(' ')),
+                Some(typed) => {
+                    let mut effects = vec![Effect::PlaySound { sound: SoundType::KeyPress(' '), at: reveal_time }];
+                    if typed == Typed::MarginBell {
+                        effects.push(Effect::Bell);
+                    }
+                    effects
+                }
+            },
+            EditorEvent::Undo if self.undo() => Vec::new(),
+            EditorEvent::Undo => vec![Effect::NothingToUndo],
+        };
+        // The carriage ticks as it lands on a tab stop
+        let carriage = matches!(event, EditorEvent::Char(_) | EditorEvent::Tab | EditorEvent::Right);
+        if carriage && self.current_column != column && self.tab_stops().any(|stop| stop == self.current_column) {
+            effects.push(Effect::PlaySound { sound: SoundType::UiTick(TAB_STOP_VOLUME), at: reveal_time });
+        }
+        effects
+    }
+
+    /// Types out a pasted text in one go, without sounds or the bell, all of
+    /// it shown at `now`. Lines that run past the hard margin are split to fit
+    /// rather than refused, as when a file is opened.
+    pub fn paste(&mut self, text: &str, now: Instant) {
+        let margin = self.hard_margin.take();
+        let auto_space = std::mem::replace(&mut self.auto_space_sentences, false);
+        let decimal_align = std::mem::replace(&mut self.decimal_align, false);
+        for edit in EditorEvent::typing(text) {
+            match edit {
+                EditorEvent::NewLine => self.new_line(),
+                EditorEvent::Char(c) => {
+                    self.insert_char(c);
+                }
+                _ => (),
+            }
+        }
+        self.hard_margin = margin;
+        self.auto_space_sentences = auto_space;
+        self.decimal_align = decimal_align;
+        if let Some(columns) = margin {
+            self.split_long_lines(columns);
+        }
+        self.reveal_all_now(now);
+    }
+
+    /// Turns typing in capitals on or off. Loaded text is left as it is.
+    pub fn set_caps(&mut self, enabled: bool) {
+        self.caps = enabled;
+    }
+
+    /// Returns whether typed letters become capitals
+    pub fn caps(&self) -> bool {
+        self.caps
+    }
+
+    /// Turns putting in the space missed after the end of a sentence on or off
+    pub fn set_auto_space_sentences(&mut self, enabled: bool) {
+        self.auto_space_sentences = enabled;
+    }
+
+    /// Sets the columns the Tab key moves the carriage to. A stop at column
+    /// 12 leaves 12 characters before it.
+    pub fn set_tab_stops(&mut self, mut columns: Vec<usize>) {
+        columns.retain(|&column| column > 0);
+        columns.sort_unstable();
+        columns.dedup();
+        self.tab_stops = columns;
+    }
+
+    /// Turns lining up the `.` of numbers on tab stops on or off
+    pub fn set_decimal_align(&mut self, enabled: bool) {
+        self.decimal_align = enabled;
+    }
+
+    /// Returns the tab stops the carriage can reach: those short of the hard
+    /// margin, if there is one
+    fn tab_stops(&self) -> impl Iterator<Item = usize> + '_ {
+        let margin = self.hard_margin.unwrap_or(usize::MAX);
+        self.tab_stops.iter().copied().take_while(move |&stop| stop < margin)
+    }
+
+    /// Moves the carriage on to the next tab stop, over the text already on
+    /// the line and then typing spaces past its end, leaving mark-out mode.
+    /// Returns None if there is no stop ahead, or else whether the margin
+    /// bell rang on the way.
+    pub fn tab(&mut self) -> Option<Typed> {
+        let stop = self.tab_stops().find(|&stop| stop > self.current_column)?;
+        self.mark_out_mode = false;
+        let line = self.current_line;
+        self.set_cursor(line, stop.min(self.current_line().len()));
+        let mut typed = Typed::Inserted;
+        while self.current_column < stop {
+            match self.type_char(' ', self.is_modified, false) {
+                Typed::MarginBell => typed = Typed::MarginBell,
+                Typed::HitMargin | Typed::Refused => break,
+                _ => (),
+            }
+        }
+        Some(typed)
+    }
+
+    /// Puts spaces in front of the number just typed, so that the `.` about
+    /// to be typed after it lands on the next tab stop. A number is digits,
+    /// perhaps with commas and a leading minus, after a space or at the start
+    /// of the line.
+    fn align_number(&mut self) {
+        let (line, column) = (self.current_line, self.current_column);
+        let typed = &self.lines[line].characters;
+        if column != typed.len() {
+            return;
+        }
+        let normal = |character: &Character| character.state == CharacterState::Normal;
+        let numeral = |character: &&Character| normal(character) && matches!(character.value, '0'..='9' | ',');
+        let mut start = column - typed.iter().rev().take_while(numeral).count();
+        if start > 0 && normal(&typed[start - 1]) && typed[start - 1].value == '-' {
+            start -= 1;
+        }
+        let after_space = start == 0 || typed[start - 1].value == ' ';
+        if !after_space || !typed[start..].iter().any(|character| character.value.is_ascii_digit()) {
+            return;
+        }
+        let Some(stop) = self.tab_stops().find(|&stop| stop >= column) else {
+            return;
+        };
+        self.set_cursor(line, start);
+        for _ in column..stop {
+            self.type_char(' ', self.is_modified, false);
+        }
+        self.set_cursor(line, stop);
+    }
+
+    /// Inserts a character at the current position. At the hard margin the
+    /// line can't get any longer, so the character is refused until Enter.
+    /// A tab is typed as a space, as the Tab key is with no stop ahead. With
+    /// decimal alignment on, a `.` after a number lines it up on a tab stop.
+    pub fn insert_char(&mut self, c: char) -> Typed {
+        let c = if c == '\t' { ' ' } else { c };
+        if c == '.' && self.decimal_align && !self.mark_out_mode {
+            self.align_number();
+        }
+        let (mut modified, mut mark_out_mode) = (self.is_modified, self.mark_out_mode);
+        self.last_lead = Duration::ZERO;
+        self.auto_spaced = None;
         if self.mark_out_mode {
             // If we're in mark-out mode, mark out the character at current position
             let current_column = self.current_column;
             if let Some(character) = self.current_line_mut()
                 .characters
                 .get_mut(current_column) {
+                let (already, changed) = (character.state == CharacterState::MarkedOut, character.changed);
                 character.mark_out();
-                self.current_column += 1;
-                // Only exit mark-out mode if we've reached the end of existing text
-                if self.current_column >= self.current_line().characters.len() {
-                    self.mark_out_mode = false;
+                if !already {
+                    self.touch(self.current_line);
+                    self.record(Change::CharMarkedOut { line: self.current_line, column: current_column });
                 }
+                self.set_cursor(self.current_line, current_column + 1);
+                self.is_modified = true;
+                self.record_edit(EditKind::MarkedOut { changed }, current_column, current_column, true, modified);
+                return Typed::MarkedOut;
             }
-        } else {
-            // Normal insertion mode
-            let character = Character::new(c);
-            self.current_line_mut().push(character);
-            self.current_column += 1;
+            // Past the end of existing text, so leave mark-out mode and type normally
+            self.mark_out_mode = false;
+        }
+
+        // The missed space is typed as a key of its own, just before this one
+        let mut spaced = Typed::Inserted;
+        if self.misses_space(c) {
+            spaced = self.type_char(' ', modified, mark_out_mode);
+            if matches!(spaced, Typed::HitMargin | Typed::Refused) {
+                return spaced;
+            }
+            self.auto_spaced = Some(self.last_lead);
+            (modified, mark_out_mode) = (self.is_modified, self.mark_out_mode);
+        }
+
+        if !self.caps {
+            return match self.type_char(c, modified, mark_out_mode) {
+                Typed::Inserted => spaced,
+                typed => typed,
+            };
+        }
+        // A capital can take more than one character, such as SS for ß. A
+        // strike over marked-out text above is one character whatever is typed.
+        let mut typed = spaced;
+        let mut before = (modified, mark_out_mode);
+        for upper in c.to_uppercase() {
+            match self.type_char(upper, before.0, before.1) {
+                Typed::Inserted => (),
+                stop @ (Typed::HitMargin | Typed::Refused | Typed::Hyphenated) => return stop,
+                other => typed = other,
+            }
+            before = (self.is_modified, self.mark_out_mode);
+        }
+        typed
+    }
+
+    /// Returns whether `c` starts a sentence straight after the end of the one
+    /// before, with no space between. Only a capital counts, so `e.g.` and
+    /// `3.14` are left alone, as is a `.` after a lone letter such as an
+    /// initial or one that has been marked out.
+    fn misses_space(&self, c: char) -> bool {
+        if !self.auto_space_sentences || !c.is_uppercase() {
+            return false;
+        }
+        let Some((last, before)) = self.current_line().characters[..self.current_column].split_last() else {
+            return false;
+        };
+        if last.state == CharacterState::MarkedOut {
+            return false;
+        }
+        match last.value {
+            '!' | '?' => true,
+            '.' => before.iter().rev().take_while(|character| character.value.is_alphabetic()).count() > 1,
+            _ => false,
+        }
+    }
+
+    /// Types one character at the cursor, as `insert_char` does once mark-out
+    /// mode is out of the way
+    fn type_char(&mut self, c: char, modified: bool, mark_out_mode: bool) -> Typed {
+        if self.hard_margin.is_some_and(|margin| self.current_line().len() >= margin) {
+            if self.at_margin {
+                return Typed::Refused;
+            }
+            self.at_margin = true;
+            return Typed::HitMargin;
         }
+
+        // In word mode a word's characters wait for the end of the word, and
+        // then appear together with the character that ended it
+        let mut character = Character::new(c, self.time_at(self.clock.now()));
+        if self.typed_out_until.is_some_and(|until| until <= character.typed_at) {
+            self.typed_out_until = None;
+        }
+        if self.reveal_mode == RevealMode::Word {
+            if is_word_boundary(c) {
+                self.release_word(character.typed_at);
+            } else {
+                character.held = true;
+            }
+        } else if !self.reveals_at_once() {
+            let delay = self.reveal_schedule.delay(character.typed_at, self.reveal_rate);
+            character.lead = self.reveal_rate.saturating_sub(delay);
+            self.last_lead = character.lead;
+        }
+
+        // Usually at the end of the line, but a finished correction can leave
+        // the cursor in the middle of it
+        let current_column = self.current_column;
+        self.current_line_mut().characters.insert(current_column, character);
+        self.touch(self.current_line);
+        self.record(Change::CharInserted { line: self.current_line, column: current_column, value: c });
+        self.set_cursor(self.current_line, current_column + 1);
         self.is_modified = true;
+        self.at_margin = false;
+        self.record_edit(EditKind::Inserted, current_column, current_column, mark_out_mode, modified);
+
+        // A hyphen splitting a word in the last column leaves the rest of the
+        // word for the next line
+        let hyphenated = c == '-'
+            && self.hyphen_hints
+            && self.hard_margin == Some(self.current_line().len())
+            && current_column == self.current_line().len() - 1
+            && current_column > 0
+            && !is_word_boundary(self.current_line().characters[current_column - 1].value);
+        if hyphenated {
+            self.new_line();
+            return Typed::Hyphenated;
+        }
+
+        // The bell goes by the carriage's column, so spaces typed at the end
+        // of the line bring it closer just as letters do
+        let bell_column = self.hard_margin.map(|margin| margin.saturating_sub(MARGIN_BELL_COLUMNS));
+        match bell_column {
+            Some(column) if column > 0 && self.current_line().len() == column => Typed::MarginBell,
+            _ => Typed::Inserted,
+        }
     }
 
     /// Handles a backspace key press
     pub fn backspace(&mut self) {
+        self.at_margin = false;
+        self.show_word_now();
         if self.current_column > 0 {
-            self.current_column -= 1;
+            self.set_cursor(self.current_line, self.current_column - 1);
             self.mark_out_mode = true;
-        } else if self.current_line > 0 {
-            // Move to the end of the previous line
-            self.current_line -= 1;
-            self.current_column = self.current_line().characters.len();
+        } else if self.current_line > self.locked_lines {
+            // Move to the end of the previous line. The line left stays, as a
+            // typewriter can't take a line off the page, and Down goes back to it.
+            self.undo.clear();
+            self.abandoned_line.get_or_insert(self.current_line);
+            let line = self.current_line - 1;
+            self.set_cursor(line, self.lines[line].len());
             self.mark_out_mode = false;
         }
     }
 
+    /// Goes back down to the line left by backspacing up past its start, at
+    /// its end where typing left off, and out of mark-out mode. After
+    /// backspacing up several lines it is the first one left. Returns whether
+    /// there was such a line.
+    pub fn return_to_abandoned_line(&mut self) -> bool {
+        let Some(line) = self.abandoned_line.take() else {
+            return false;
+        };
+        self.show_word_now();
+        self.undo.clear();
+        self.set_cursor(line, self.lines[line].len());
+        self.mark_out_mode = false;
+        self.at_margin = false;
+        true
+    }
+
     /// Handles a right arrow key press
     pub fn move_right(&mut self) {
-        if self.current_column < self.current_line().characters.len() {
-            self.current_column += 1;
-            // Exit mark-out mode if we've reached the end of existing text
-            if self.current_column >= self.current_line().characters.len() {
-                self.mark_out_mode = false;
+        if self.current_column < self.current_line().len() {
+            self.set_cursor(self.current_line, self.current_column + 1);
+        } else {
+            // Exit mark-out mode once we try to move past the end of existing text
+            self.mark_out_mode = false;
+        }
+    }
+
+    /// Moves the cursor to a column of the current line as if by the keyboard:
+    /// moving left marks out like backspace, moving right works like the right
+    /// arrow, and going past the end of the line stops there
+    pub fn move_to_column(&mut self, column: usize) {
+        if column < self.current_column {
+            self.show_word_now();
+            self.set_cursor(self.current_line, column);
+            self.mark_out_mode = true;
+        } else {
+            for _ in self.current_column..column {
+                self.move_right();
             }
         }
     }
 
+    /// Returns the column of the last marked-out character on the cursor's line
+    pub fn last_marked_out_column(&self) -> Option<usize> {
+        self.current_line()
+            .characters
+            .iter()
+            .rposition(|character| character.state == CharacterState::MarkedOut)
+    }
+
+    /// Ends a correction: moves the cursor just past the last marked-out
+    /// character on the line (or to its end if nothing is marked out) and
+    /// leaves mark-out mode, so the replacement is typed right after it
+    pub fn finish_correction(&mut self) {
+        let column = match self.last_marked_out_column() {
+            Some(column) => column + 1,
+            None => self.current_line().len(),
+        };
+        self.set_cursor(self.current_line, column);
+        self.mark_out_mode = false;
+    }
+
+    /// Moves the cursor to the end of the last line, so typing adds to the text
+    pub fn move_to_end(&mut self) {
+        self.undo.clear();
+        let line = self.lines.len() - 1;
+        self.set_cursor(line, self.lines[line].len());
+        self.mark_out_mode = false;
+        self.abandoned_line = None;
+    }
+
+    /// Moves the cursor to the end of a line, or of the last line if there
+    /// aren't that many, such as when reopening a file that has since shrunk
+    pub fn move_to_line(&mut self, line: usize) {
+        self.undo.clear();
+        let line = line.min(self.lines.len() - 1);
+        self.set_cursor(line, self.lines[line].len());
+        self.mark_out_mode = false;
+        self.abandoned_line = None;
+    }
+
+    /// Puts the cursor at a column of a line, each kept within the text. It
+    /// isn't a keyboard move, so short of the line's end typing goes in there
+    /// rather than marking out.
+    pub fn move_to_position(&mut self, line: usize, column: usize) {
+        self.move_to_line(line);
+        self.set_cursor(self.current_line, column.min(self.current_line().len()));
+    }
+
+    /// Makes every line there is now read-only. Typing continues on a new line.
+    pub fn lock_existing_lines(&mut self) {
+        self.locked_lines = self.lines.len();
+        self.new_line();
+    }
+
     /// Handles an enter key press
     pub fn new_line(&mut self) {
-        // Create a new line and move to it
-        self.lines.push(Line::new());
-        self.current_line += 1;
-        self.current_column = 0;
+        self.release_word(self.time_at(self.clock.now()));
+        // Enter commits the line, so its changes can't be undone any more
+        self.undo.clear();
+        // Put a new line under the cursor's and move to it. After backspacing
+        // up, the lines below move down to make room, the line left with them.
+        let line = self.current_line + 1;
+        self.lines.insert(line, Line::new());
+        self.touch_from(line);
+        if line < self.locked_lines {
+            self.locked_lines += 1;
+        }
+        self.abandoned_line = self.abandoned_line.map(|left| if left >= line { left + 1 } else { left });
+        self.record(Change::LineAdded { line });
+        self.set_cursor(line, 0);
         self.mark_out_mode = false;
+        self.at_margin = false;
+    }
+
+    /// Lets the unfinished word on the current line appear a reveal delay
+    /// after `ended`, when the character ending it was typed
+    fn release_word(&mut self, ended: BufferTime) {
+        let mut released = false;
+        for character in self.current_line_mut().characters.iter_mut().filter(|character| character.held) {
+            character.held = false;
+            character.typed_at = ended;
+            released = true;
+        }
+        if released {
+            self.touch(self.current_line);
+        }
+    }
+
+    /// Shows the unfinished word on the current line straight away, since it
+    /// is about to be corrected
+    fn show_word_now(&mut self) {
+        let shown = self.time_at(self.clock.now()).saturating_sub(self.reveal_rate);
+        self.release_word(shown);
+    }
+
+    /// Returns whether a word is still being typed in word reveal mode, its
+    /// characters hidden until it is finished
+    pub fn word_pending(&self) -> bool {
+        self.current_line().characters.iter().any(|character| character.held)
+    }
+
+    /// Starts keeping a list of the changes made, to be taken with
+    /// `take_changes`. Only changes made through the buffer's methods are
+    /// listed, not ones made to `lines` directly.
+    #[allow(dead_code)] // For views of the buffer other than the terminal's
+    pub fn track_changes(&mut self) {
+        self.changes.get_or_insert_with(Vec::new);
+    }
+
+    /// Returns the changes made since they were last taken, oldest first
+    #[allow(dead_code)] // For views of the buffer other than the terminal's
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        self.changes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Adds a change to the list, if changes are being tracked
+    fn record(&mut self, change: Change) {
+        if let Some(changes) = &mut self.changes {
+            changes.push(change);
+        }
+    }
+
+    /// Counts a change to a line. The lines saved together with it count as
+    /// changed too, since whether the end of one shows as trailing whitespace
+    /// depends on the others.
+    fn touch(&mut self, line: usize) {
+        self.revision += 1;
+        for idx in self.saved_with(line) {
+            self.lines[idx].revision = self.revision;
+            self.lines[idx].edited = true;
+        }
+    }
+
+    /// Counts a change to every line from `line` on
+    fn touch_from(&mut self, line: usize) {
+        self.revision += 1;
+        for buffer_line in &mut self.lines[line..] {
+            buffer_line.revision = self.revision;
+        }
+    }
+
+    /// Moves the cursor, noting the change if it actually moved
+    fn set_cursor(&mut self, line: usize, column: usize) {
+        if (line, column) != (self.current_line, self.current_column) {
+            (self.current_line, self.current_column) = (line, column);
+            self.record(Change::CursorMoved { line, column });
+        }
     }
 
     /// Gets a reference to the current line
@@ -185,23 +1249,227 @@ impl Buffer {
         self.reveal_rate
     }
 
-    /// Returns the current cursor position (line, column)
-    pub fn cursor_position(&self) -> (usize, usize) {
-        (self.current_line, self.current_column)
+    /// Changes the reveal rate, up to `MAX_REVEAL_RATE`. Whatever is already
+    /// shown at `now` stays shown when the rate is slowed down.
+    pub fn set_reveal_rate(&mut self, rate: Duration, now: Instant) {
+        let shown = self.time_at(now).saturating_sub(self.reveal_rate);
+        self.revealed_until = self.revealed_until.max(Some(shown));
+        self.reveal_rate = rate.min(MAX_REVEAL_RATE);
     }
 
-    /// Returns true if the cursor is in mark-out mode
-    pub fn is_mark_out_mode(&self) -> bool {
-        self.mark_out_mode
+    /// Returns whether typed characters appear the moment they are typed:
+    /// there is no reveal delay and no text typing itself out. Nothing is then
+    /// worked out from the time, and no frame waits for a character.
+    pub fn reveals_at_once(&self) -> bool {
+        self.reveal_rate.is_zero() && self.typed_out_until.is_none()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Returns when a character of this buffer appears, unless it is held
+    pub fn shown_at(&self, character: &Character) -> BufferTime {
+        (character.typed_at + self.reveal_rate).saturating_sub(character.lead).max(character.typed_at)
+    }
 
-    #[test]
-    /// Test basic buffer creation
+    /// Returns whether a character of this buffer is shown by now
+    pub fn is_revealed(&self, character: &Character, now: Instant) -> bool {
+        !character.held
+            && (self.reveals_at_once()
+                || self.revealed_until.is_some_and(|until| character.typed_at <= until)
+                || self.time_at(now) >= self.shown_at(character))
+    }
+
+    /// Returns when the next character still held back by the reveal rate will
+    /// show up, or None if everything typed is already visible
+    pub fn next_reveal(&self, now: Instant) -> Option<Instant> {
+        if self.reveals_at_once() {
+            return None;
+        }
+        // A correction can be typed in the middle of a line, so the newest
+        // characters aren't necessarily at the end
+        self.lines
+            .iter()
+            .flat_map(|line| line.characters.iter())
+            // Held characters wait for a key press, not the time
+            .filter(|character| !character.held && !self.is_revealed(character, now))
+            .map(|character| self.shown_at(character))
+            .min()
+            .map(|time| self.instant_at(time))
+    }
+
+    /// Returns the instant it is now on the clock character times are read from
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Takes the time from `clock` from now on, counting character times from
+    /// now on it. Typed characters keep their times, so it is set before typing.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.epoch = clock.now();
+        self.clock = clock;
+    }
+
+    /// Returns the buffer time of an instant
+    pub fn time_at(&self, instant: Instant) -> BufferTime {
+        BufferTime::since(self.epoch, instant)
+    }
+
+    /// Returns the instant a buffer time falls on
+    pub fn instant_at(&self, time: BufferTime) -> Instant {
+        time.to_instant(self.epoch)
+    }
+
+    /// Spaces the characters out as if typed at a steady pace starting at
+    /// `start`: one every `interval`, with a `return_pause` at each line break
+    pub fn type_out(&mut self, start: Instant, interval: Duration, return_pause: Duration) {
+        self.touch_from(0);
+        let mut at = self.time_at(start);
+        for (idx, line) in self.lines.iter_mut().enumerate() {
+            // A line split off a long one follows on without a carriage return
+            if idx > 0 && !line.continuation {
+                at = at + return_pause;
+            }
+            for character in &mut line.characters {
+                at = at + interval;
+                character.typed_at = at;
+            }
+        }
+        self.typed_out_until = Some(at);
+    }
+
+    /// Returns the characters that appeared after `after` and by `until`, in
+    /// the order they appeared
+    pub fn revealed_between(&self, after: Instant, until: Instant) -> Vec<char> {
+        let (after, until) = (self.time_at(after), self.time_at(until));
+        let mut revealed: Vec<(BufferTime, char)> = self
+            .lines
+            .iter()
+            .flat_map(|line| line.characters.iter())
+            .filter(|character| !character.held)
+            .map(|character| (self.shown_at(character), character.value))
+            .filter(|(shown, _)| *shown > after && *shown <= until)
+            .collect();
+        revealed.sort_by_key(|(shown, _)| *shown);
+        revealed.into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// Shows everything still held back by the reveal rate at once, backdating
+    /// it to a reveal delay before `now`. Held words keep waiting for their end.
+    pub fn reveal_all_now(&mut self, now: Instant) {
+        let shown = self.time_at(now).saturating_sub(self.reveal_rate);
+        self.revision += 1;
+        for line in &mut self.lines {
+            if line.characters.iter().any(|character| character.typed_at > shown) {
+                line.revision = self.revision;
+            }
+            for character in &mut line.characters {
+                character.typed_at = character.typed_at.min(shown);
+            }
+        }
+        // A buffer younger than a reveal delay can't backdate far enough
+        self.revealed_until = self.revealed_until.max(Some(shown));
+        self.typed_out_until = None;
+    }
+
+    /// Returns how many typed characters are still held back by the reveal rate
+    pub fn pending_reveals(&self, now: Instant) -> usize {
+        self.lines
+            .iter()
+            .flat_map(|line| line.characters.iter())
+            .filter(|character| !self.is_revealed(character, now))
+            .count()
+    }
+
+    /// Returns where the sentence being typed starts as (line, column): just
+    /// past the whitespace after the last `.`, `!` or `?` before the cursor, or
+    /// the start of its paragraph. Closing quotes and brackets can follow the
+    /// punctuation, and marked-out characters are skipped. A sentence never
+    /// starts in the lines locked from an earlier session.
+    pub fn sentence_start(&self) -> (usize, usize) {
+        let mut start = (self.current_line, self.current_column);
+        // Whether whitespace, perhaps after closing quotes, follows what is being looked at
+        let mut spaced = false;
+        for line_idx in (self.locked_lines.min(self.current_line)..=self.current_line).rev() {
+            let line = &self.lines[line_idx];
+            let end = if line_idx == self.current_line { self.current_column } else { line.len() };
+            if line_idx < self.current_line {
+                // A blank line ends a paragraph, and a line break is whitespace
+                if line.characters.iter().all(|character| character.state == CharacterState::MarkedOut) {
+                    return start;
+                }
+                spaced = true;
+            }
+            for (column, character) in line.characters[..end].iter().enumerate().rev() {
+                if character.state == CharacterState::MarkedOut {
+                    continue;
+                }
+                match character.value {
+                    '.' | '!' | '?' if spaced => return start,
+                    '"' | '\'' | '”' | '’' | ')' | ']' if spaced => (),
+                    c if c.is_whitespace() => spaced = true,
+                    _ => {
+                        start = (line_idx, column);
+                        spaced = false;
+                    }
+                }
+            }
+        }
+        start
+    }
+
+    /// Returns the share of the characters typed this session that haven't
+    /// been marked out, from 0 to 1. Text loaded from a file doesn't count,
+    /// and with nothing typed the page is clean.
+    pub fn cleanliness(&self) -> f32 {
+        cleanliness(self.lines.iter().flat_map(|line| &line.characters))
+    }
+
+    /// Returns the cleanliness of one line, as for the whole buffer
+    pub fn line_cleanliness(&self, line: usize) -> f32 {
+        cleanliness(self.lines.get(line).into_iter().flat_map(|line| &line.characters))
+    }
+
+    /// Returns the current cursor position (line, column)
+    pub fn cursor_position(&self) -> (usize, usize) {
+        (self.current_line, self.current_column)
+    }
+
+    /// Returns true if the cursor is in mark-out mode
+    pub fn is_mark_out_mode(&self) -> bool {
+        self.mark_out_mode
+    }
+}
+
+/// Checks that a file could be saved, without changing it: an existing file
+/// must open for writing, and a missing one must be possible to create
+pub fn check_writable(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        fs::OpenOptions::new().write(true).open(path).map(drop)
+    } else {
+        fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+        fs::remove_file(path)
+    }
+}
+
+/// Returns the share of the typed characters among these that aren't marked out
+fn cleanliness<'a>(characters: impl Iterator<Item = &'a Character>) -> f32 {
+    let (typed, clean) = characters
+        .filter(|character| character.typed)
+        .fold((0, 0), |(typed, clean), character| {
+            (typed + 1, clean + usize::from(character.state == CharacterState::Normal))
+        });
+    if typed == 0 {
+        1.0
+    } else {
+        clean as f32 / typed as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+
+    #[test]
+    /// Test basic buffer creation
     fn test_buffer_creation() {
         let buffer = Buffer::new(Duration::from_millis(100));
         assert_eq!(buffer.lines.len(), 1);
@@ -210,6 +1478,574 @@ mod tests {
         assert!(!buffer.mark_out_mode);
     }
 
+    #[test]
+    /// Test loading piped text, which has no file to save to
+    fn test_from_reader() {
+        let buffer = Buffer::from_reader("draft\nsecond line\n".as_bytes(), Duration::from_millis(100)).unwrap();
+        assert_eq!(buffer.text(), "draft\nsecond line\n");
+        assert_eq!(buffer.lines.len(), 3);
+        assert_eq!(buffer.file_path, None);
+        assert!(!buffer.is_modified());
+
+        let buffer = Buffer::from_reader(io::empty(), Duration::from_millis(100)).unwrap();
+        assert_eq!(buffer.lines.len(), 1);
+
+        // Windows line endings are read as plain newlines
+        let buffer = Buffer::from_reader("a\r\nb\r\n".as_bytes(), Duration::from_millis(100)).unwrap();
+        assert_eq!(buffer.text(), "a\nb\n");
+    }
+
+    #[test]
+    /// Test putting other text in place of the buffer's, such as a snapshot
+    fn test_replace_text() {
+        let mut buffer = Buffer::from_reader("saved\n".as_bytes(), Duration::ZERO).unwrap();
+        buffer.file_path = Some("draft.txt".to_string());
+        buffer.set_hard_margin(Some(10));
+        buffer.move_to_end();
+        for c in "typed".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.backspace();
+
+        buffer.replace_text("an earlier draft\nof it");
+        assert_eq!(buffer.text(), "an earlier draft\nof it");
+        assert_eq!(buffer.lines.len(), 4, "the long line is split at the margin");
+        assert_eq!(buffer.cursor_position(), (3, 5));
+        assert!(!buffer.is_mark_out_mode());
+        assert!(buffer.is_modified());
+        assert_eq!(buffer.file_path.as_deref(), Some("draft.txt"));
+
+        // Typing carries on at the end
+        buffer.insert_char('!');
+        assert_eq!(buffer.text(), "an earlier draft\nof it!");
+    }
+
+    #[test]
+    /// Test that blank lines come back from a file exactly as they were typed
+    fn test_blank_lines_round_trip() {
+        let cases = [
+            // A new buffer, and one with just a paragraph break
+            "",
+            "\n",
+            "\n\n\n",
+            "\n\nleading",
+            "one\n\n\n\ntwo",
+            "trailing\n\n",
+        ];
+        for text in cases {
+            let buffer = Buffer::from_reader(text.as_bytes(), Duration::ZERO).unwrap();
+            assert_eq!(buffer.lines.len(), text.matches('\n').count() + 1, "{:?}", text);
+            assert_eq!(buffer.text(), text);
+        }
+
+        // Enter twice leaves one blank line between the paragraphs
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.insert_char('a');
+        buffer.new_line();
+        buffer.new_line();
+        assert_eq!(buffer.text(), "a\n\n");
+    }
+
+    #[test]
+    /// Test that file paths have `~` and variables expanded, and that one
+    /// that can't be expanded is refused without creating anything
+    fn test_file_path_expansion() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        if let Ok(home) = std::env::var("HOME") {
+            buffer.set_file_path("~/notes.txt").unwrap();
+            assert_eq!(buffer.file_path, Some(format!("{}/notes.txt", home)));
+        }
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let error = buffer.set_file_path("$TYPEWRITER_UNSET_TEST_VAR/notes.txt").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "$TYPEWRITER_UNSET_TEST_VAR isn't set");
+        assert_eq!(buffer.file_path, None);
+
+        let error = Buffer::from_file("~nobody/notes.txt", Duration::ZERO).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!Path::new("~nobody").exists());
+    }
+
+    #[test]
+    /// Test that a binary file is refused without being changed, while text
+    /// with a few escape sequences or invalid bytes is not taken for binary
+    fn test_binary_refused() {
+        let dir = ScratchDir::new("editor-binary");
+        let path = dir.join("notes");
+        let elf: Vec<u8> = b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0\x03\0>\0".repeat(100);
+        fs::write(&path, &elf).unwrap();
+        let error = Buffer::from_file(&path.to_string_lossy(), Duration::ZERO).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("binary"));
+        assert_eq!(fs::read(&path).unwrap(), elf);
+
+        assert!(!looks_binary(b""));
+        assert!(!looks_binary("\x1b[1mBold\x1b[0m and plain text\tafter a tab\r\n".as_bytes()));
+        assert!(!looks_binary(b"caf\xe9 au lait, written in Latin-1"));
+        assert!(looks_binary(b"\x01\x02\x03\x04text"));
+        assert!(looks_binary(&[0xff, 0xfe, 0x81, b'a']));
+        // Only the start is looked at
+        let late = [b"text".repeat(SNIFF_BYTES / 4).as_slice(), &[0]].concat();
+        assert!(!looks_binary(&late));
+    }
+
+    #[test]
+    /// Test that loaded tabs become spaces to the tab stops and stray carriage
+    /// returns are dropped, and that the round trip keeps the tabs of lines
+    /// not typed in: CRLF and stray CRs don't come back, tabs do unless the
+    /// line was edited or tabs are saved expanded
+    fn test_tabs_and_returns() {
+        let dir = ScratchDir::new("editor-tabs");
+        let path = dir.join("notes.txt");
+        let path = path.to_string_lossy().to_string();
+        fs::write(&path, "name\tvalue\r\n\tindented\rstray\nwide 漢\tx\n").unwrap();
+        let mut buffer = Buffer::from_file(&path, Duration::ZERO).unwrap();
+        let shown = |buffer: &Buffer| -> Vec<String> {
+            buffer.lines.iter().map(|line| line.characters.iter().map(|c| c.value).collect()).collect()
+        };
+        assert_eq!(shown(&buffer), ["name    value", "        indentedstray", "wide 漢 x", ""]);
+        // Every character takes as many cells as its width says
+        assert!(buffer.lines.iter().flat_map(|line| &line.characters).all(|c| c.value.width().is_some()));
+        assert!(!buffer.is_modified());
+
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "name\tvalue\n\tindentedstray\nwide 漢\tx\n");
+
+        // A line typed in is saved as it is shown
+        buffer.set_cursor(2, 8);
+        buffer.insert_char('\t');
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "name\tvalue\n\tindentedstray\nwide 漢 x \n");
+
+        buffer.set_expand_tabs_on_save(true);
+        assert_eq!(buffer.saved_text(), "name    value\n        indentedstray\nwide 漢 x \n");
+
+        // A different tab width lays out the untouched lines again
+        let mut buffer = Buffer::from_reader("\tone\n\ttwo".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        buffer.set_tab_width(4);
+        assert_eq!(shown(&buffer), ["    one", "    two"]);
+        assert_eq!(buffer.cursor_position(), (1, 7));
+        assert_eq!(buffer.saved_text(), "\tone\n\ttwo");
+    }
+
+    #[test]
+    /// Test that .txt and .md files are saved ending with one newline unless
+    /// turned off, other files only when turned on, and .typ files never,
+    /// without the newline counting as a change
+    fn test_ensure_trailing_newline() {
+        let dir = ScratchDir::new("editor-newline");
+        let file = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+        // A new file gets one, and the page doesn't
+        let mut buffer = Buffer::new(Duration::ZERO);
+        assert_eq!(buffer.saved_text(), "");
+        buffer.insert_char('a');
+        assert_eq!(buffer.saved_text(), "a");
+        buffer.set_file_path(&file("new.txt")).unwrap();
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(file("new.txt")).unwrap(), "a\n");
+        assert_eq!(buffer.text(), "a");
+        assert!(!buffer.is_modified());
+
+        // A file that has one keeps it without a second, and isn't changed by opening
+        fs::write(file("ends.md"), "line\n").unwrap();
+        let mut buffer = Buffer::from_file(&file("ends.md"), Duration::ZERO).unwrap();
+        assert!(!buffer.is_modified());
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(file("ends.md")).unwrap(), "line\n");
+        fs::write(file("bare.md"), "line").unwrap();
+        let buffer = Buffer::from_file(&file("bare.md"), Duration::ZERO).unwrap();
+        assert!(!buffer.is_modified());
+        assert_eq!(buffer.saved_text(), "line\n");
+
+        // The native format is saved as it is, whatever the setting
+        fs::write(file("draft.typ"), "line").unwrap();
+        let mut buffer = Buffer::from_file(&file("draft.typ"), Duration::ZERO).unwrap();
+        buffer.set_ensure_trailing_newline(Some(true));
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(file("draft.typ")).unwrap(), "line");
+
+        // Turned off for a .txt file, and on for one it doesn't know
+        let mut buffer = Buffer::from_reader("line".as_bytes(), Duration::ZERO).unwrap();
+        buffer.file_path = Some(file("off.txt"));
+        buffer.set_ensure_trailing_newline(Some(false));
+        assert_eq!(buffer.saved_text(), "line");
+        buffer.file_path = Some(file("notes.log"));
+        buffer.set_ensure_trailing_newline(None);
+        assert_eq!(buffer.saved_text(), "line");
+        buffer.set_ensure_trailing_newline(Some(true));
+        assert_eq!(buffer.saved_text(), "line\n");
+    }
+
+    #[test]
+    /// Test that a byte order mark is dropped from the start of loaded text,
+    /// and invisible characters from a paste
+    fn test_invisible_characters() {
+        let buffer = Buffer::from_reader("\u{FEFF}Dear Sir,\n".as_bytes(), Duration::ZERO).unwrap();
+        assert_eq!(buffer.text(), "Dear Sir,\n");
+        assert_eq!(buffer.lines[0].len(), 9);
+        assert!(is_invisible('\u{200C}') && is_invisible('\u{FEFF}'));
+        assert!(!is_invisible('\u{301}') && !is_invisible(' '));
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.paste("\u{FEFF}non\u{200C}joiner", Instant::now());
+        assert_eq!(buffer.text(), "nonjoiner");
+        assert_eq!(buffer.cursor_position(), (0, 9));
+    }
+
+    #[test]
+    /// Test that control characters load as visible stand-ins, and are saved
+    /// back as they were unless their line has been typed in
+    fn test_control_pictures() {
+        assert_eq!(control_picture('\x1b'), Some('␛'));
+        assert_eq!(control_picture('\x07'), Some('␇'));
+        assert_eq!(control_picture('\x7f'), Some('␡'));
+        assert_eq!(control_picture('\u{85}'), Some('\u{fffd}'));
+        assert_eq!(control_picture('\t'), None);
+        assert_eq!(control_picture('a'), None);
+
+        let dir = ScratchDir::new("editor-ansi");
+        let path = dir.join("notes.txt");
+        let path = path.to_string_lossy().to_string();
+        let text = "\x07bell\n\x1b[31mred\x1b[0m\tok";
+        fs::write(&path, text).unwrap();
+        let mut buffer = Buffer::from_file(&path, Duration::ZERO).unwrap();
+        buffer.set_ensure_trailing_newline(Some(false));
+        let shown: Vec<String> =
+            buffer.lines.iter().map(|line| line.characters.iter().map(|c| c.value).collect()).collect();
+        assert_eq!(shown, ["␇bell", "␛[31mred␛[0m    ok"]);
+        let mut characters = buffer.lines.iter().flat_map(|line| &line.characters);
+        assert!(characters.all(|c| !c.value.is_control()));
+
+        // Unedited, the file is saved byte for byte
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+        // Typing in the first line saves what it shows, while the second keeps its escapes
+        buffer.insert_char('x');
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x␇bell\n\x1b[31mred\x1b[0m\tok");
+    }
+
+    #[test]
+    /// Test that saving and reopening gives back the same lines, for many
+    /// random buffers typed with every kind of editing
+    fn test_save_load_round_trip() {
+        use rand::rngs::SmallRng;
+        use rand::{Rng, SeedableRng};
+
+        let dir = ScratchDir::new("editor-round-trip");
+        let path = dir.join("notes.txt");
+        let path = path.to_string_lossy().to_string();
+        let mut rng = SmallRng::seed_from_u64(143);
+        for _ in 0..200 {
+            let mut buffer = Buffer::new(Duration::ZERO);
+            buffer.set_ensure_trailing_newline(Some(false));
+            for _ in 0..rng.gen_range(0..40) {
+                match rng.gen_range(0..10) {
+                    0..=2 => buffer.new_line(),
+                    3 => buffer.backspace(),
+                    4 => buffer.finish_correction(),
+                    _ => {
+                        buffer.insert_char(['a', 'é', ' ', '.', '漢', '\t'][rng.gen_range(0..6)]);
+                    }
+                }
+            }
+            buffer.set_file_path(&path).unwrap();
+            buffer.save().unwrap();
+            let saved = fs::read(&path).unwrap();
+
+            let mut reopened = Buffer::from_file(&path, Duration::ZERO).unwrap();
+            reopened.set_ensure_trailing_newline(Some(false));
+            let lines: Vec<String> = buffer.text_lines().collect();
+            assert_eq!(reopened.text_lines().collect::<Vec<_>>(), lines);
+            reopened.save().unwrap();
+            assert_eq!(fs::read(&path).unwrap(), saved);
+        }
+    }
+
+    #[test]
+    /// Test that replaying the tracked changes on a copy of the lines keeps it
+    /// the same as the buffer, for many random buffers typed with every kind
+    /// of editing, each change coming once and in order
+    fn test_track_changes() {
+        use rand::rngs::SmallRng;
+        use rand::{Rng, SeedableRng};
+
+        let dir = ScratchDir::new("editor-changes");
+        let path = dir.join("notes.txt");
+        let path = path.to_string_lossy().to_string();
+        let mut rng = SmallRng::seed_from_u64(166);
+        for _ in 0..200 {
+            let mut buffer = Buffer::from_reader("Some loaded text\nto start with".as_bytes(), Duration::ZERO).unwrap();
+            buffer.set_file_path(&path).unwrap();
+            buffer.set_soft_undo(true);
+            buffer.set_hyphen_hints(true);
+            buffer.set_hard_margin(rng.gen_bool(0.5).then_some(12));
+            buffer.track_changes();
+            // Each line as (value, marked out) pairs, and whether it is a continuation
+            let mut lines: Vec<(Vec<(char, bool)>, bool)> = buffer
+                .lines
+                .iter()
+                .map(|line| (line.characters.iter().map(|character| (character.value, false)).collect(), false))
+                .collect();
+            let mut cursor = buffer.cursor_position();
+            let mut saves = 0;
+            for step in 0..60 {
+                match rng.gen_range(0..20) {
+                    0..=2 => buffer.new_line(),
+                    3..=4 => buffer.backspace(),
+                    5 => buffer.finish_correction(),
+                    6 => buffer.move_right(),
+                    7 => buffer.move_to_column(rng.gen_range(0..20)),
+                    8 => buffer.move_to_line(rng.gen_range(0..10)),
+                    9..=10 => {
+                        buffer.undo();
+                    }
+                    11 => buffer.split_long_lines(rng.gen_range(1..8)),
+                    12 => {
+                        buffer.save().unwrap();
+                        saves += 1;
+                    }
+                    _ => {
+                        buffer.insert_char(['a', 'é', ' ', '-', '漢', 'ß'][rng.gen_range(0..6)]);
+                    }
+                }
+
+                for change in buffer.take_changes() {
+                    match change {
+                        Change::CharInserted { line, column, value } => lines[line].0.insert(column, (value, false)),
+                        Change::CharMarkedOut { line, column } => {
+                            assert!(!lines[line].0[column].1);
+                            lines[line].0[column].1 = true;
+                        }
+                        Change::CharRemoved { line, column } => {
+                            lines[line].0.remove(column);
+                        }
+                        Change::CharRestored { line, column } => {
+                            assert!(lines[line].0[column].1);
+                            lines[line].0[column].1 = false;
+                        }
+                        Change::LineAdded { line } => lines.insert(line, (Vec::new(), false)),
+                        Change::LineSplit { line, column } => {
+                            let rest = lines[line].0.split_off(column);
+                            lines.insert(line + 1, (rest, true));
+                        }
+                        Change::CursorMoved { line, column } => {
+                            assert_ne!((line, column), cursor, "step {}", step);
+                            cursor = (line, column);
+                        }
+                        Change::Saved => saves -= 1,
+                    }
+                }
+                let expected: Vec<(Vec<(char, bool)>, bool)> = buffer
+                    .lines
+                    .iter()
+                    .map(|line| {
+                        let characters = line
+                            .characters
+                            .iter()
+                            .map(|character| (character.value, character.state == CharacterState::MarkedOut))
+                            .collect();
+                        (characters, line.continuation)
+                    })
+                    .collect();
+                assert_eq!(lines, expected, "step {}", step);
+                assert_eq!(cursor, buffer.cursor_position(), "step {}", step);
+                assert_eq!(saves, 0);
+            }
+        }
+
+        // Nothing is kept until tracking starts
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.insert_char('a');
+        assert!(buffer.take_changes().is_empty());
+        buffer.track_changes();
+        buffer.insert_char('b');
+        assert_eq!(
+            buffer.take_changes(),
+            [Change::CharInserted { line: 0, column: 1, value: 'b' }, Change::CursorMoved { line: 0, column: 2 }]
+        );
+        assert!(buffer.take_changes().is_empty());
+    }
+
+    #[test]
+    /// Test that trimming leaves trailing whitespace out of the saved file but
+    /// not the buffer, keeps it on the line being typed and between split
+    /// lines, and isn't stopped by marked-out characters after it
+    fn test_trim_trailing_whitespace() {
+        let dir = ScratchDir::new("editor-trim");
+        let path = dir.join("notes.txt");
+        let path = path.to_string_lossy().to_string();
+        let mut buffer = Buffer::from_reader("Loaded  \n\t\nabc def ghi \n".as_bytes(), Duration::ZERO).unwrap();
+        buffer.split_long_lines(4);
+        buffer.move_to_end();
+        for c in "typed  x".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.backspace();
+        buffer.insert_char('-');
+        buffer.new_line();
+        for c in "still typing ".chars() {
+            buffer.insert_char(c);
+        }
+        // The loaded tab is spaces on the page, and a tab again in the file
+        let text = "Loaded  \n\t\nabc def ghi \ntyped  \nstill typing ";
+        let shown = text.replace('\t', &" ".repeat(DEFAULT_TAB_WIDTH));
+        assert_eq!(buffer.text(), shown);
+
+        buffer.set_file_path(&path).unwrap();
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", text));
+
+        buffer.set_trim_trailing_whitespace(true);
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Loaded\n\nabc def ghi\ntyped\nstill typing \n");
+        assert_eq!(buffer.text(), shown);
+        assert_eq!(buffer.saved_text(), fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    /// Test that a change counts against its line, the lines saved together
+    /// with it, and every line a split moves down
+    fn test_revisions() {
+        let mut buffer = Buffer::from_reader("one\ntwo three\nfour".as_bytes(), Duration::ZERO).unwrap();
+        let revisions = |buffer: &Buffer| -> Vec<u64> { buffer.lines.iter().map(Line::revision).collect() };
+        assert_eq!(revisions(&buffer), [0, 0, 0]);
+
+        // Typing and marking out change only the line typed on
+        buffer.move_to_line(1);
+        buffer.insert_char('s');
+        assert_eq!(revisions(&buffer), [0, 1, 0]);
+        buffer.backspace();
+        buffer.insert_char('x');
+        assert_eq!(revisions(&buffer), [0, 2, 0]);
+        // Moving the cursor changes nothing, nor does marking out twice
+        buffer.backspace();
+        buffer.insert_char('x');
+        assert_eq!(revisions(&buffer), [0, 2, 0]);
+
+        // Splitting moves "four" down, and typing on either half of the split
+        // line changes both
+        buffer.split_long_lines(6);
+        assert_eq!(buffer.lines.len(), 4);
+        assert_eq!(revisions(&buffer), [0, 3, 3, 3]);
+        buffer.move_to_line(2);
+        buffer.insert_char('!');
+        assert_eq!(revisions(&buffer), [0, 4, 4, 3]);
+
+        // Enter above the last line moves the lines below down
+        buffer.new_line();
+        assert_eq!(revisions(&buffer), [0, 4, 4, 5, 5]);
+    }
+
+    #[test]
+    /// Test that what is typed or marked out counts as changed until the next
+    /// save, wherever the cursor goes and however the lines are split
+    fn test_changed_since_save() {
+        let dir = ScratchDir::new("editor-changed");
+        let path = dir.join("notes.txt");
+        let path = path.to_string_lossy().to_string();
+        fs::write(&path, "one\ntwo three\nfour").unwrap();
+        let mut buffer = Buffer::from_file(&path, Duration::ZERO).unwrap();
+        buffer.set_soft_undo(true);
+        let changed = |buffer: &Buffer| -> Vec<String> {
+            let changed = |line: &Line| line.characters.iter().filter(|c| c.changed).map(|c| c.value).collect();
+            buffer.lines.iter().map(changed).collect()
+        };
+        assert_eq!(changed(&buffer), ["", "", ""]);
+
+        // Striking over "ee" and typing "s" after it
+        buffer.move_to_line(1);
+        buffer.insert_char('s');
+        buffer.backspace();
+        buffer.backspace();
+        buffer.backspace();
+        buffer.insert_char('x');
+        buffer.insert_char('x');
+        assert_eq!(changed(&buffer), ["", "ees", ""]);
+
+        // Moving about and splitting the line keep the flags on their characters
+        buffer.move_to_line(0);
+        buffer.split_long_lines(6);
+        assert_eq!(changed(&buffer), ["", "", "ees", ""]);
+
+        buffer.save().unwrap();
+        assert_eq!(changed(&buffer), ["", "", "", ""]);
+
+        // Undoing a strike made since the save puts back the saved character
+        buffer.move_to_line(3);
+        buffer.backspace();
+        buffer.insert_char('x');
+        assert_eq!(changed(&buffer), ["", "", "", "r"]);
+        buffer.undo();
+        assert_eq!(changed(&buffer), ["", "", "", ""]);
+
+        // Undoing one made before it gives a character the file doesn't have.
+        // Undo left the cursor on the "r" again.
+        buffer.insert_char('x');
+        buffer.save().unwrap();
+        buffer.undo();
+        assert_eq!(changed(&buffer), ["", "", "", "r"]);
+    }
+
+    #[test]
+    /// Test that a long line is split to fit and saved back without line
+    /// breaks the file never had
+    fn test_split_long_lines() {
+        let dir = ScratchDir::new("editor-split");
+        let path = dir.join("notes.txt");
+        let path = path.to_string_lossy().to_string();
+        let long: String = (0..100).map(|idx| format!("w{:03} ", idx)).collect::<String>().trim_end().to_string();
+        assert_eq!(long.chars().count(), 499);
+        let text = format!("Before.\n{}\nAfter.\n{}", long, "x".repeat(150));
+        fs::write(&path, &text).unwrap();
+
+        let mut buffer = Buffer::from_file(&path, Duration::ZERO).unwrap();
+        buffer.set_ensure_trailing_newline(Some(false));
+        buffer.move_to_end();
+        buffer.split_long_lines(72);
+        // 499 characters break after the space ending every 14th word, and the
+        // unbroken word at 72 characters
+        let lengths: Vec<usize> = buffer.lines.iter().map(Line::len).collect();
+        assert_eq!(lengths, [7, 70, 70, 70, 70, 70, 70, 70, 9, 6, 72, 72, 6]);
+        let continued: Vec<usize> = (0..buffer.lines.len()).filter(|&idx| buffer.lines[idx].continuation).collect();
+        assert_eq!(continued, [2, 3, 4, 5, 6, 7, 8, 11, 12]);
+        assert_eq!(buffer.cursor_position(), (12, 6));
+        assert!(!buffer.is_modified());
+        assert_eq!(buffer.text(), text);
+
+        // Typing goes on where it was, and saving adds no line breaks
+        buffer.insert_char('y');
+        buffer.new_line();
+        buffer.insert_char('z');
+        buffer.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}y\nz", text));
+
+        // The cursor stays on its character when its line is split
+        let mut buffer = Buffer::from_reader("abc def ghi".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_column(9);
+        buffer.split_long_lines(4);
+        assert_eq!(buffer.cursor_position(), (2, 1));
+        assert_eq!(buffer.text_lines().collect::<Vec<_>>(), ["abc ", "def ", "ghi"]);
+
+        // A URL breaks after its slashes
+        let mut buffer = Buffer::from_reader("see https://example.com/a/b".as_bytes(), Duration::ZERO).unwrap();
+        buffer.split_long_lines(12);
+        assert_eq!(buffer.text_lines().collect::<Vec<_>>(), ["see ", "https://", "example.com/", "a/b"]);
+
+        // Typing out a split line pauses only at the real line break
+        let mut buffer = Buffer::from_reader("abcd\ne".as_bytes(), Duration::ZERO).unwrap();
+        buffer.split_long_lines(2);
+        let start = buffer.instant_at(BufferTime(0));
+        buffer.type_out(start, Duration::from_millis(10), Duration::from_millis(50));
+        let times: Vec<u64> =
+            buffer.lines.iter().flat_map(|line| &line.characters).map(|character| character.typed_at.0).collect();
+        assert_eq!(times, vec![10, 20, 30, 40, 100]);
+    }
+
     #[test]
     /// Test character insertion
     fn test_character_insertion() {
@@ -230,21 +2066,21 @@ mod tests {
         let mut buffer = Buffer::new(Duration::from_millis(100));
         
         // Insert some characters
-        buffer.insert_char('a');
-        buffer.insert_char('b');
-        buffer.insert_char('c');
+        buffer.apply(EditorEvent::Char('a'));
+        buffer.apply(EditorEvent::Char('b'));
+        buffer.apply(EditorEvent::Char('c'));
         
         // Backspace and mark out
-        buffer.backspace();
+        buffer.apply(EditorEvent::Backspace);
         assert!(buffer.mark_out_mode);
         assert_eq!(buffer.current_column, 2);
         
         // Mark out multiple characters
-        buffer.insert_char('x');
+        buffer.apply(EditorEvent::Char('x'));
         assert!(buffer.mark_out_mode); // Should still be in mark-out mode
         assert_eq!(buffer.current_line().characters[2].state, CharacterState::MarkedOut);
         
-        buffer.insert_char('x');
+        buffer.apply(EditorEvent::Char('x'));
         assert!(!buffer.mark_out_mode); // Should exit mark-out mode at end of text
     }
 
@@ -254,19 +2090,19 @@ mod tests {
         let mut buffer = Buffer::new(Duration::from_millis(100));
         
         // Insert some characters
-        buffer.insert_char('a');
-        buffer.insert_char('b');
+        buffer.apply(EditorEvent::Char('a'));
+        buffer.apply(EditorEvent::Char('b'));
         
         // Move back and then right
-        buffer.backspace();
+        buffer.apply(EditorEvent::Backspace);
         assert!(buffer.mark_out_mode);
         
-        buffer.move_right();
+        buffer.apply(EditorEvent::Right);
         assert!(buffer.mark_out_mode); // Should maintain mark-out mode
         assert_eq!(buffer.current_column, 2);
         
         // Should not move past end of text
-        buffer.move_right();
+        buffer.apply(EditorEvent::Right);
         assert_eq!(buffer.current_column, 2);
         assert!(!buffer.mark_out_mode); // Should exit mark-out mode at end
     }
@@ -276,8 +2112,8 @@ mod tests {
     fn test_new_line() {
         let mut buffer = Buffer::new(Duration::from_millis(100));
         
-        buffer.insert_char('a');
-        buffer.new_line();
+        buffer.apply(EditorEvent::Char('a'));
+        buffer.apply(EditorEvent::NewLine);
         
         assert_eq!(buffer.lines.len(), 2);
         assert_eq!(buffer.current_line, 1);
@@ -285,7 +2121,7 @@ mod tests {
         assert!(!buffer.mark_out_mode);
 
         // Test that new line starts fresh
-        buffer.insert_char('b');
+        buffer.apply(EditorEvent::Char('b'));
         assert_eq!(buffer.current_line().characters[0].value, 'b');
         assert_eq!(buffer.current_column, 1);
     }
@@ -296,18 +2132,1105 @@ mod tests {
         let mut buffer = Buffer::new(Duration::from_millis(100));
         
         // Create two lines with text
-        buffer.insert_char('a');
-        buffer.new_line();
-        buffer.insert_char('b');
+        buffer.apply(EditorEvent::Char('a'));
+        buffer.apply(EditorEvent::NewLine);
+        buffer.apply(EditorEvent::Char('b'));
         
         // Backspace at start of second line
-        buffer.backspace();
+        buffer.apply(EditorEvent::Backspace);
         assert!(buffer.mark_out_mode);
-        buffer.backspace();
+        buffer.apply(EditorEvent::Backspace);
         
         // Should move to end of previous line
         assert_eq!(buffer.current_line, 0);
         assert_eq!(buffer.current_column, 1);
         assert!(!buffer.mark_out_mode);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    /// Test that Tab moves the carriage on to the next stop short of the
+    /// margin, typing spaces past the end of the line, and that landing on a
+    /// stop ticks
+    fn test_tab_stops() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_tab_stops(vec![30, 12, 0, 12]);
+        assert_eq!(buffer.tab_stops, [12, 30]);
+        let ticks = |effects: &[Effect]| {
+            let tick = SoundType::UiTick(TAB_STOP_VOLUME);
+            effects.iter().filter(|effect| matches!(effect, Effect::PlaySound { sound, .. } if *sound == tick)).count()
+        };
+
+        for c in "Item".chars() {
+            buffer.apply(EditorEvent::Char(c));
+        }
+        assert_eq!(ticks(&buffer.apply(EditorEvent::Tab)), 1);
+        assert_eq!(buffer.cursor_position(), (0, 12));
+        assert_eq!(buffer.text(), format!("Item{}", " ".repeat(8)));
+        buffer.apply(EditorEvent::Tab);
+        assert_eq!(buffer.cursor_position(), (0, 30));
+
+        // Past the last stop, Tab is the space bar
+        buffer.apply(EditorEvent::Tab);
+        assert_eq!(buffer.cursor_position(), (0, 31));
+
+        // Over text already typed it moves without marking out, and typing
+        // onto a stop ticks too
+        buffer.new_line();
+        let typed: usize = "Typed across".chars().map(|c| ticks(&buffer.apply(EditorEvent::Char(c)))).sum();
+        assert_eq!(typed, 1);
+        for _ in 0..10 {
+            buffer.apply(EditorEvent::Backspace);
+        }
+        buffer.apply(EditorEvent::Tab);
+        assert_eq!(buffer.cursor_position(), (1, 12));
+        assert!(!buffer.mark_out_mode);
+        assert_eq!(buffer.text_lines().nth(1).unwrap(), "Typed across");
+
+        // Stops at or past the margin are out of reach
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_tab_stops(vec![12, 30]);
+        buffer.set_hard_margin(Some(30));
+        buffer.apply(EditorEvent::Tab);
+        buffer.apply(EditorEvent::Tab);
+        assert_eq!(buffer.cursor_position(), (0, 13));
+    }
+
+    #[test]
+    /// Test that typing `.` after a number puts spaces in front of it so
+    /// the `.` lands on the next stop, and that other text, numbers without
+    /// a `.` and stops past the margin are left alone
+    fn test_decimal_align() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_tab_stops(vec![10, 20]);
+        buffer.set_decimal_align(true);
+        let type_text = |buffer: &mut Buffer, text: &str| {
+            for c in text.chars() {
+                match c {
+                    '\t' => buffer.apply(EditorEvent::Tab),
+                    '\n' => buffer.apply(EditorEvent::NewLine),
+                    c => buffer.apply(EditorEvent::Char(c)),
+                };
+            }
+        };
+
+        type_text(&mut buffer, "Tea\t4.50\nCake\t12.5\nTotal\t1,234.05\nRefund\t-3.\n5.\n");
+        let lines: Vec<String> = buffer.text_lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Tea                4.50",
+                "Cake              12.5",
+                "Total          1,234.05",
+                "Refund            -3.",
+                "         5.",
+                "",
+            ]
+        );
+
+        // A number without a `.`, a `.` ending a word and one with no stop
+        // ahead stay as typed
+        type_text(&mut buffer, "Count\t42\nEnd of it.\nNear\t\t123456789012.");
+        let lines: Vec<String> = buffer.text_lines().skip(5).collect();
+        assert_eq!(lines, ["Count     42", "End of it.", "Near                123456789012."]);
+
+        // Nor are numbers whose stop is past the margin
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_tab_stops(vec![10, 20]);
+        buffer.set_decimal_align(true);
+        buffer.set_hard_margin(Some(20));
+        type_text(&mut buffer, "Tea\t12.5");
+        assert_eq!(buffer.text(), "Tea       12.5");
+    }
+
+    #[test]
+    /// Test that the lines left by backspacing up past their starts stay on
+    /// the page, and that Down goes back to the first of them where typing
+    /// left off
+    fn test_return_to_abandoned_line() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        assert!(!buffer.return_to_abandoned_line());
+        for c in "one\ntwo\n".chars() {
+            buffer.apply(if c == '\n' { EditorEvent::NewLine } else { EditorEvent::Char(c) });
+        }
+
+        // Up past the empty third line and the start of the second
+        for _ in 0..5 {
+            buffer.apply(EditorEvent::Backspace);
+        }
+        assert_eq!(buffer.cursor_position(), (0, 3));
+        assert_eq!(buffer.text(), "one\ntwo\n");
+        // Marking out on the first line doesn't lose the way back
+        buffer.apply(EditorEvent::Backspace);
+        buffer.apply(EditorEvent::Char('x'));
+        buffer.apply(EditorEvent::Down);
+        assert_eq!(buffer.cursor_position(), (2, 0));
+        assert!(!buffer.mark_out_mode);
+        assert_eq!(buffer.lines.len(), 3);
+
+        // Down again, or without having backspaced up, stays put
+        buffer.apply(EditorEvent::Down);
+        assert_eq!(buffer.cursor_position(), (2, 0));
+
+        // Typing had begun on the line left, so going back puts the cursor
+        // after it, the marked-out characters and all
+        buffer.apply(EditorEvent::Char('t'));
+        buffer.apply(EditorEvent::Char('h'));
+        buffer.apply(EditorEvent::Backspace);
+        buffer.apply(EditorEvent::Backspace);
+        buffer.apply(EditorEvent::Backspace);
+        assert_eq!(buffer.cursor_position(), (1, 3));
+        buffer.apply(EditorEvent::Down);
+        assert_eq!(buffer.cursor_position(), (2, 2));
+        buffer.apply(EditorEvent::Char('e'));
+        assert_eq!(buffer.text(), "on\ntwo\nthe");
+
+        // Enter from the line above puts a new line in between, and Down
+        // still goes back to the line left
+        buffer.apply(EditorEvent::Backspace);
+        buffer.apply(EditorEvent::Backspace);
+        buffer.apply(EditorEvent::Backspace);
+        buffer.apply(EditorEvent::Backspace);
+        buffer.apply(EditorEvent::NewLine);
+        assert_eq!(buffer.cursor_position(), (2, 0));
+        assert_eq!(buffer.text(), "on\ntwo\n\nthe");
+        assert!(buffer.return_to_abandoned_line());
+        assert_eq!(buffer.cursor_position(), (3, 3));
+    }
+
+    #[test]
+    /// Test that Enter after backspacing up a line types on a new line under
+    /// the cursor, the lines below moving down
+    fn test_new_line_after_backspacing_up() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.track_changes();
+        for c in "ab\ncd\nef\n".chars() {
+            buffer.apply(if c == '\n' { EditorEvent::NewLine } else { EditorEvent::Char(c) });
+        }
+        // Up onto "ef", then Enter
+        buffer.apply(EditorEvent::Backspace);
+        buffer.take_changes();
+        buffer.apply(EditorEvent::NewLine);
+        assert_eq!(buffer.take_changes(), [Change::LineAdded { line: 3 }, Change::CursorMoved { line: 3, column: 0 }]);
+        buffer.apply(EditorEvent::Char('X'));
+        assert_eq!(buffer.text(), "ab\ncd\nef\nX\n");
+        assert_eq!(buffer.cursor_position(), (3, 1));
+        // Down goes back to the line left, now moved down
+        buffer.apply(EditorEvent::Down);
+        assert_eq!(buffer.cursor_position(), (4, 0));
+    }
+
+    #[test]
+    /// Test that Enter after backspacing up two lines types on a new line
+    /// under the cursor, not on the line below it
+    fn test_new_line_after_backspacing_up_two_lines() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        for c in "ab\ncd\nef\n".chars() {
+            buffer.apply(if c == '\n' { EditorEvent::NewLine } else { EditorEvent::Char(c) });
+        }
+        // Up past "ef" onto "cd": one to reach it, two to mark it out and one more
+        for _ in 0..4 {
+            buffer.apply(EditorEvent::Backspace);
+        }
+        assert_eq!(buffer.cursor_position(), (1, 2));
+        buffer.apply(EditorEvent::NewLine);
+        buffer.apply(EditorEvent::Char('X'));
+        assert_eq!(buffer.text(), "ab\ncd\nX\nef\n");
+        assert_eq!(buffer.lines.len(), 5);
+        assert_eq!(buffer.cursor_position(), (2, 1));
+        // The line left first is the one Down goes back to
+        buffer.apply(EditorEvent::Down);
+        assert_eq!(buffer.cursor_position(), (4, 0));
+    }
+
+    #[test]
+    /// Test finding when the next hidden character is revealed
+    fn test_next_reveal() {
+        let reveal_rate = Duration::from_millis(100);
+        let mut buffer = Buffer::new(reveal_rate);
+        let now = Instant::now();
+        assert_eq!(buffer.next_reveal(now), None);
+
+        buffer.insert_char('a');
+        buffer.new_line();
+        buffer.insert_char('b');
+        buffer.lines[0].characters[0].typed_at = BufferTime(20);
+        buffer.lines[1].characters[0].typed_at = BufferTime(50);
+        assert_eq!(buffer.next_reveal(now), Some(buffer.instant_at(BufferTime(120))));
+
+        // Once the delay has passed for everything, nothing is pending
+        assert_eq!(buffer.next_reveal(buffer.instant_at(BufferTime(150))), None);
+    }
+
+    #[test]
+    /// Test that without a reveal delay typing shows the moment it is typed,
+    /// with no frame to wait for and no curve to follow, while a held word
+    /// and a file typing itself out still wait
+    fn test_zero_reveal_rate() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_reveal_curve(RevealCurve::Cluster);
+        assert!(buffer.reveals_at_once());
+        let effects = buffer.apply(EditorEvent::Char('a'));
+        let typed = Instant::now();
+        assert!(matches!(effects[..], [Effect::PlaySound { at, .. }] if at <= typed));
+        buffer.insert_char('b');
+        buffer.insert_char('c');
+        assert!(buffer.lines[0].characters.iter().all(|character| character.lead.is_zero()));
+
+        // Not even a character dated later is waited for
+        buffer.lines[0].characters[2].typed_at = BufferTime(60_000);
+        let epoch = buffer.instant_at(BufferTime(0));
+        assert!(buffer.is_revealed(&buffer.lines[0].characters[2], epoch));
+        assert_eq!(buffer.next_reveal(epoch), None);
+        assert_eq!(buffer.pending_reveals(epoch), 0);
+
+        // Words still wait for their end, which is a key press rather than a time
+        buffer.set_reveal_mode(RevealMode::Word);
+        buffer.insert_char(' ');
+        buffer.insert_char('d');
+        assert_eq!(buffer.pending_reveals(epoch), 1);
+        assert_eq!(buffer.next_reveal(epoch), None);
+
+        // A file typing itself out takes its time until skipped
+        let mut buffer = Buffer::from_reader("ab".as_bytes(), Duration::ZERO).unwrap();
+        let start = buffer.instant_at(BufferTime(1000));
+        buffer.type_out(start, Duration::from_millis(10), Duration::from_millis(50));
+        assert!(!buffer.reveals_at_once());
+        assert_eq!(buffer.next_reveal(start), Some(buffer.instant_at(BufferTime(1010))));
+        buffer.reveal_all_now(start);
+        assert!(buffer.reveals_at_once());
+        assert_eq!(buffer.next_reveal(start), None);
+    }
+
+    #[test]
+    /// Test that spaces at the end of the line move the carriage towards the
+    /// margin bell like any other character, though they are trailing
+    /// whitespace until a word follows them
+    fn test_margin_bell_counts_trailing_spaces() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_hard_margin(Some(20));
+        let typed: Vec<Typed> = "word        ".chars().map(|c| buffer.insert_char(c)).collect();
+        assert_eq!(typed.iter().filter(|&&typed| typed == Typed::MarginBell).count(), 1);
+        assert_eq!(typed.last(), Some(&Typed::MarginBell));
+        assert_eq!(buffer.current_line().trailing_whitespace_len(), 8);
+        // The line is being typed, so none of it is trailing whitespace yet
+        assert_eq!(buffer.trailing_whitespace(0), None);
+
+        buffer.insert_char('x');
+        assert_eq!(buffer.current_line().trailing_whitespace_len(), 0);
+        buffer.new_line();
+        assert_eq!(buffer.trailing_whitespace(0), None);
+    }
+
+    #[test]
+    /// Test that the hard margin refuses typing until Enter, announcing only
+    /// the first refusal in a row, while striking out still works
+    fn test_hard_margin() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        buffer.set_hard_margin(Some(10));
+        let effects: Vec<Vec<Effect>> = "abcdefghij".chars().map(|c| buffer.apply(EditorEvent::Char(c))).collect();
+        assert_eq!(effects[1].last(), Some(&Effect::Bell));
+        assert_eq!(effects.iter().flatten().filter(|&effect| *effect == Effect::Bell).count(), 1);
+        buffer.is_modified = false;
+
+        assert_eq!(buffer.apply(EditorEvent::Char('k')), [Effect::Bell, Effect::Flash]);
+        assert_eq!(buffer.apply(EditorEvent::Char('l')), []);
+        assert_eq!(buffer.insert_char('m'), Typed::Refused);
+        assert_eq!(buffer.lines[0].len(), 10);
+        assert!(!buffer.is_modified());
+
+        // Striking out at the margin doesn't make the line longer
+        buffer.apply(EditorEvent::Backspace);
+        assert_eq!(buffer.insert_char('x'), Typed::MarkedOut);
+        assert_eq!(buffer.lines[0].characters[9].state, CharacterState::MarkedOut);
+        assert_eq!(buffer.insert_char('j'), Typed::HitMargin);
+        assert_eq!(buffer.insert_char('j'), Typed::Refused);
+
+        buffer.apply(EditorEvent::NewLine);
+        assert_eq!(buffer.insert_char('n'), Typed::Inserted);
+        assert_eq!(buffer.lines[1].len(), 1);
+    }
+
+    #[test]
+    /// Test hyphenating a word in the last column before the hard margin
+    fn test_hyphen_at_margin() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_hard_margin(Some(12));
+        buffer.set_hyphen_hints(true);
+        for c in "The typewri".chars() {
+            buffer.insert_char(c);
+            assert_eq!(buffer.hyphen_hint().is_some(), buffer.lines[0].len() >= 4 && c != ' ');
+        }
+        assert_eq!(buffer.hyphen_hint(), Some(1));
+
+        let effects = buffer.apply(EditorEvent::Char('-'));
+        assert_eq!(effects.last(), Some(&Effect::Committed));
+        assert_eq!(buffer.text(), "The typewri-\n");
+        assert_eq!(buffer.cursor_position(), (1, 0));
+        assert_eq!(buffer.hyphen_hint(), None);
+
+        // Without hints a hyphen there is just a hyphen
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_hard_margin(Some(12));
+        for c in "The typewri-".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(buffer.hyphen_hint(), None);
+        assert_eq!(buffer.cursor_position(), (0, 12));
+    }
+
+    #[test]
+    /// Test that hyphens away from the margin, or after a space, don't start a line
+    fn test_hyphen_not_at_margin() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_hard_margin(Some(12));
+        buffer.set_hyphen_hints(true);
+        for c in "A well-know".chars() {
+            assert_ne!(buffer.insert_char(c), Typed::Hyphenated);
+        }
+        assert_eq!(buffer.cursor_position(), (0, 11));
+        // A word that already has a hyphen still gets the hint, but not right after it
+        assert_eq!(buffer.hyphen_hint(), Some(1));
+        buffer.insert_char('-');
+        assert_eq!(buffer.cursor_position(), (1, 0));
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_hard_margin(Some(12));
+        buffer.set_hyphen_hints(true);
+        for c in "Dashes like".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.insert_char(' ');
+        assert_eq!(buffer.hyphen_hint(), None);
+        assert_eq!(buffer.insert_char('-'), Typed::HitMargin);
+
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_hard_margin(Some(12));
+        buffer.set_hyphen_hints(true);
+        for c in "Half-".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(buffer.hyphen_hint(), None);
+        assert_eq!(buffer.text(), "Half-");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    /// Test spotting files that can't be saved, in a directory without write permission
+    fn test_check_writable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = ScratchDir::new("editor-readonly");
+        let existing = dir.join("motd");
+        fs::write(&existing, "Welcome\n").unwrap();
+        let missing = dir.join("draft.txt");
+        assert!(check_writable(&existing).is_ok());
+        assert!(check_writable(&missing).is_ok());
+        assert!(!missing.exists());
+
+        fs::set_permissions(&existing, fs::Permissions::from_mode(0o444)).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+        // Permissions don't stop root, so there is nothing more to see then
+        if fs::write(dir.join("probe"), "").is_err() {
+            let error = check_writable(&existing).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+            assert_eq!(check_writable(&missing).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+            // The file still opens, and a missing one opens empty
+            let buffer = Buffer::from_file(&existing.to_string_lossy(), Duration::ZERO).unwrap();
+            assert_eq!(buffer.text(), "Welcome\n");
+            let buffer = Buffer::from_file(&missing.to_string_lossy(), Duration::ZERO).unwrap();
+            assert_eq!(buffer.file_path, Some(missing.to_string_lossy().into_owned()));
+            assert!(!missing.exists());
+        }
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    /// Test the share of typing left unmarked, overall and by line
+    fn test_cleanliness() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        assert_eq!(buffer.cleanliness(), 1.0);
+        for c in "tpyo".chars() {
+            buffer.insert_char(c);
+        }
+        for _ in 0..3 {
+            buffer.backspace();
+        }
+        for c in "xxxypo".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.new_line();
+        for c in "fine".chars() {
+            buffer.insert_char(c);
+        }
+        // Eleven typed, three marked out; the striking keys themselves don't count
+        assert_eq!(buffer.text(), "typo\nfine");
+        assert_eq!(buffer.cleanliness(), 8.0 / 11.0);
+        assert_eq!(buffer.line_cleanliness(0), 4.0 / 7.0);
+        assert_eq!(buffer.line_cleanliness(1), 1.0);
+        assert_eq!(buffer.line_cleanliness(5), 1.0);
+
+        // Taking back a mark-out cleans the line again
+        buffer.set_soft_undo(true);
+        buffer.insert_char(' ');
+        buffer.backspace();
+        buffer.insert_char('x');
+        assert_eq!(buffer.line_cleanliness(1), 0.8);
+        buffer.undo();
+        assert_eq!(buffer.line_cleanliness(1), 1.0);
+    }
+
+    #[test]
+    /// Test that text loaded from a file isn't counted as typed
+    fn test_cleanliness_loaded() {
+        let mut buffer = Buffer::from_reader("Written before.\n".as_bytes(), Duration::ZERO).unwrap();
+        assert_eq!(buffer.cleanliness(), 1.0);
+        buffer.move_to_end();
+        for c in "New".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(buffer.cleanliness(), 1.0);
+
+        // Striking loaded text isn't a typing mistake either
+        buffer.move_to_line(0);
+        buffer.backspace();
+        buffer.insert_char('x');
+        buffer.move_to_end();
+        buffer.backspace();
+        buffer.insert_char('x');
+        assert_eq!(buffer.text(), "Written before\nNe");
+        assert_eq!(buffer.cleanliness(), 2.0 / 3.0);
+        assert_eq!(buffer.line_cleanliness(0), 1.0);
+    }
+
+    #[test]
+    /// Test the sounds edits ask for, timed to when their text appears
+    fn test_apply_sounds() {
+        let reveal_rate = Duration::from_millis(100);
+        let mut buffer = Buffer::new(reveal_rate);
+        let before = Instant::now();
+        let sounds = |effects: Vec<Effect>| -> Vec<SoundType> {
+            effects
+                .into_iter()
+                .filter_map(|effect| match effect {
+                    Effect::PlaySound { sound, at } => {
+                        assert!(at >= before + reveal_rate);
+                        Some(sound)
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(sounds(buffer.apply(EditorEvent::Char('a'))), [SoundType::KeyPress('a')]);
+        assert_eq!(sounds(buffer.apply(EditorEvent::Backspace)), []);
+        let effects = buffer.apply(EditorEvent::Char('x'));
+        assert_eq!(effects.last(), Some(&Effect::MarkedOut));
+        assert_eq!(sounds(effects), [SoundType::KeyPress('x')]);
+        let effects = buffer.apply(EditorEvent::NewLine);
+        assert_eq!(effects.last(), Some(&Effect::Committed));
+        assert_eq!(sounds(effects), [SoundType::Return]);
+
+        // A word clicks once, as it ends
+        buffer.set_reveal_mode(RevealMode::Word);
+        assert_eq!(sounds(buffer.apply(EditorEvent::Char('o'))), []);
+        assert_eq!(sounds(buffer.apply(EditorEvent::Char('k'))), []);
+        assert_eq!(sounds(buffer.apply(EditorEvent::Char(' '))), [SoundType::Word(' ')]);
+        assert_eq!(sounds(buffer.apply(EditorEvent::Char('.'))), [SoundType::KeyPress('.')]);
+
+        assert_eq!(buffer.apply(EditorEvent::Undo), [Effect::NothingToUndo]);
+        buffer.set_soft_undo(true);
+        buffer.apply(EditorEvent::Char('!'));
+        assert_eq!(buffer.apply(EditorEvent::Undo), []);
+        assert_eq!(buffer.text(), "\nok .");
+    }
+
+    #[test]
+    /// Test that with the cluster curve each key of a burst waits less than
+    /// the one before, and that its click is heard when it appears
+    fn test_reveal_curve() {
+        let reveal_rate = Duration::from_millis(300);
+        let mut buffer = Buffer::new(reveal_rate);
+        buffer.set_reveal_curve(RevealCurve::Cluster);
+        let mut clicks = Vec::new();
+        for c in "abc".chars() {
+            for effect in buffer.apply(EditorEvent::Char(c)) {
+                if let Effect::PlaySound { at, .. } = effect {
+                    clicks.push(at);
+                }
+            }
+            std::thread::sleep(Duration::from_millis(60));
+        }
+
+        let characters = &buffer.lines[0].characters;
+        let waits: Vec<u64> =
+            characters.iter().map(|character| buffer.shown_at(character).0 - character.typed_at.0).collect();
+        assert_eq!(waits[0], 300);
+        assert!(waits[1] < waits[0] && waits[2] < waits[1], "{:?}", waits);
+        for (character, at) in characters.iter().zip(clicks) {
+            let shown = buffer.instant_at(buffer.shown_at(character));
+            assert!(at.max(shown) - at.min(shown) < Duration::from_millis(2));
+        }
+
+        // Word mode shows words whole, so the curve sits out
+        buffer.set_reveal_mode(RevealMode::Word);
+        buffer.apply(EditorEvent::Char(' '));
+        assert_eq!(buffer.lines[0].characters[3].lead, Duration::ZERO);
+    }
+
+    #[test]
+    /// Test finding where the sentence being typed starts
+    fn test_sentence_start() {
+        let type_text = |text: &str| {
+            let mut buffer = Buffer::new(Duration::ZERO);
+            for (idx, line) in text.split('\n').enumerate() {
+                if idx > 0 {
+                    buffer.new_line();
+                }
+                for c in line.chars() {
+                    buffer.insert_char(c);
+                }
+            }
+            buffer
+        };
+
+        assert_eq!(Buffer::new(Duration::ZERO).sentence_start(), (0, 0));
+        assert_eq!(type_text("It rained. We stayed").sentence_start(), (0, 11));
+        assert_eq!(type_text("Wait!  What?  Now").sentence_start(), (0, 14));
+        assert_eq!(type_text("She said \"go.\" Then").sentence_start(), (0, 15));
+
+        // Until a space follows it, the full stop belongs to the sentence it ends
+        assert_eq!(type_text("One. Two.").sentence_start(), (0, 5));
+        assert_eq!(type_text("One. Two. ").sentence_start(), (0, 10));
+
+        // Numbers and abbreviations without a space don't end sentences
+        assert_eq!(type_text("Pi is 3.14 or so").sentence_start(), (0, 0));
+
+        // Sentences carry on over line breaks, but not over blank lines
+        assert_eq!(type_text("Done. The long\nsentence goes on").sentence_start(), (0, 6));
+        assert_eq!(type_text("# Heading\n\nFirst words").sentence_start(), (2, 0));
+        assert_eq!(type_text("No stop here\nstill going").sentence_start(), (0, 0));
+
+        // Marked-out text is skipped, and the cursor's position is what counts
+        let mut buffer = type_text("Yes. No");
+        buffer.backspace();
+        buffer.backspace();
+        for c in "xxMaybe".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(buffer.sentence_start(), (0, 7));
+        let mut buffer = type_text("One. Two. Three");
+        buffer.move_to_column(7);
+        assert_eq!(buffer.sentence_start(), (0, 5));
+
+        let mut buffer = type_text("Written yesterday");
+        buffer.lock_existing_lines();
+        buffer.insert_char('a');
+        assert_eq!(buffer.sentence_start(), (1, 0));
+    }
+
+    #[test]
+    /// Test that slowing the reveal rate never hides a character again, while
+    /// speeding it up shows waiting ones sooner
+    fn test_set_reveal_rate() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        buffer.insert_char('a');
+        buffer.insert_char('b');
+        buffer.lines[0].characters[0].typed_at = BufferTime(1000);
+        buffer.lines[0].characters[1].typed_at = BufferTime(1120);
+        let later = buffer.instant_at(BufferTime(1150));
+
+        // At 150 ms only 'a' is shown; slowing down keeps it but holds 'b' back longer
+        assert_eq!(buffer.pending_reveals(later), 1);
+        buffer.set_reveal_rate(Duration::from_millis(500), later);
+        assert_eq!(buffer.pending_reveals(later), 1);
+        assert!(buffer.is_revealed(&buffer.lines[0].characters[0], later));
+        assert_eq!(buffer.next_reveal(later), Some(buffer.instant_at(BufferTime(1620))));
+
+        // Going back and forth keeps everything that was ever shown
+        buffer.set_reveal_rate(Duration::ZERO, later);
+        assert_eq!(buffer.pending_reveals(later), 0);
+        buffer.set_reveal_rate(Duration::from_millis(1000), later);
+        assert_eq!(buffer.pending_reveals(later), 0);
+        assert_eq!(buffer.next_reveal(later), None);
+
+        // Characters typed afterwards wait for the new rate
+        buffer.insert_char('c');
+        buffer.lines[0].characters[2].typed_at = BufferTime(1160);
+        let typed = buffer.instant_at(BufferTime(1160));
+        assert_eq!(buffer.next_reveal(typed), Some(typed + Duration::from_millis(1000)));
+
+        buffer.set_reveal_rate(Duration::from_secs(10), typed);
+        assert_eq!(buffer.reveal_rate(), MAX_REVEAL_RATE);
+    }
+
+    #[test]
+    /// Test that in word mode a word waits for its end, then appears all at once
+    fn test_word_reveal() {
+        let reveal_rate = Duration::from_millis(100);
+        let mut buffer = Buffer::new(reveal_rate);
+        buffer.set_reveal_mode(RevealMode::Word);
+        for c in "don't".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.lines[0].characters[0].typed_at = BufferTime(0);
+        let now = Instant::now();
+        assert!(buffer.word_pending());
+        assert_eq!(buffer.pending_reveals(now + Duration::from_secs(60)), 5);
+        assert_eq!(buffer.next_reveal(now), None);
+
+        // The space ends the word, and every character lines up with it
+        buffer.insert_char(' ');
+        assert!(!buffer.word_pending());
+        let ended = buffer.lines[0].characters[5].typed_at;
+        assert!(buffer.lines[0].characters.iter().all(|character| character.typed_at == ended));
+        assert_eq!(buffer.next_reveal(buffer.instant_at(ended)), Some(buffer.instant_at(ended + reveal_rate)));
+        assert_eq!(buffer.pending_reveals(buffer.instant_at(ended + reveal_rate)), 0);
+
+        // Enter ends a word too
+        buffer.insert_char('x');
+        buffer.new_line();
+        assert!(!buffer.lines[0].characters[6].held);
+        assert!(buffer.lines[0].characters[6].typed_at >= ended);
+    }
+
+    #[test]
+    /// Test that stepping back into an unfinished word shows it at once
+    fn test_word_reveal_backspace() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        // Nothing can be shown earlier than a reveal delay into the buffer's life
+        buffer.epoch -= Duration::from_secs(1);
+        buffer.set_reveal_mode(RevealMode::Word);
+        for c in "Hi. Thre".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.backspace();
+        let now = Instant::now();
+        let shown = |buffer: &Buffer| -> String {
+            buffer.lines[0].characters.iter().filter(|c| buffer.is_revealed(c, now)).map(|c| c.value).collect()
+        };
+        assert_eq!(shown(&buffer), "Thre");
+
+        // The finished sentence before it still appears on its own schedule
+        assert_eq!(buffer.pending_reveals(now), 4);
+        let first = buffer.lines[0].characters[0].typed_at + buffer.reveal_rate();
+        assert_eq!(buffer.next_reveal(now), Some(buffer.instant_at(first)));
+    }
+
+    #[test]
+    /// Test undoing typing and marking out on the line being typed
+    fn test_soft_undo() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_soft_undo(true);
+        for c in "cat".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.backspace();
+        buffer.insert_char('x');
+        assert_eq!(buffer.text(), "ca");
+
+        // The mark-out comes back off, with the cursor on it in mark-out mode
+        assert!(buffer.undo());
+        assert_eq!(buffer.lines[0].characters[2].state, CharacterState::Normal);
+        assert_eq!(buffer.cursor_position(), (0, 2));
+        assert!(buffer.is_mark_out_mode());
+
+        // Undoing past the first change does nothing, and leaves the buffer as it was
+        for _ in 0..3 {
+            assert!(buffer.undo());
+        }
+        assert!(!buffer.undo());
+        assert_eq!(buffer.text(), "");
+        assert_eq!(buffer.cursor_position(), (0, 0));
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    /// Test that Enter commits the line, and that saving counts as a change
+    /// of the file for what is undone afterwards
+    fn test_soft_undo_clears_on_enter() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_soft_undo(true);
+        buffer.insert_char('a');
+        buffer.new_line();
+        assert!(!buffer.undo());
+        buffer.insert_char('b');
+        buffer.backspace();
+        buffer.backspace();
+        assert!(!buffer.undo());
+        assert_eq!(buffer.text(), "a\nb");
+
+        let dir = ScratchDir::new("editor-undo");
+        let path = dir.join("notes.txt");
+        buffer.set_file_path(&path.to_string_lossy()).unwrap();
+        buffer.insert_char('x');
+        buffer.save().unwrap();
+        buffer.insert_char('y');
+        assert!(buffer.undo());
+        assert!(!buffer.is_modified());
+        assert!(buffer.undo());
+        assert!(buffer.is_modified());
+
+        // Off by default, and a full undo history forgets its oldest change
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.insert_char('a');
+        assert!(!buffer.undo());
+        buffer.set_soft_undo(true);
+        for _ in 0..MAX_UNDO + 5 {
+            buffer.insert_char('a');
+        }
+        let undone = std::iter::from_fn(|| buffer.undo().then_some(())).count();
+        assert_eq!(undone, MAX_UNDO);
+        assert_eq!(buffer.lines[0].len(), 6);
+    }
+
+    #[test]
+    /// Test typing in capitals, which can take more than one character
+    fn test_caps() {
+        let mut buffer = Buffer::from_reader("loaded ".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        buffer.set_caps(true);
+        for c in "Straße ÉTÉ 1!".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(buffer.text(), "loaded STRASSE ÉTÉ 1!");
+
+        // Striking over marked-out text takes one character per key, even for ß
+        for _ in 0..4 {
+            buffer.backspace();
+        }
+        buffer.insert_char('ß');
+        assert_eq!(buffer.text(), "loaded STRASSE ÉT 1!");
+        assert_eq!(buffer.cursor_position(), (0, 18));
+
+        // Turned off, typing is left alone
+        buffer.set_caps(false);
+        buffer.finish_correction();
+        buffer.insert_char('ß');
+        assert_eq!(buffer.text(), "loaded STRASSE ÉTß 1!");
+    }
+
+    #[test]
+    /// Test when a capital straight after the end of a sentence gets a space
+    /// put in before it
+    fn test_auto_space_sentences() {
+        // `\u{8}` is Backspace
+        let cases = [
+            ("End.Next", "End. Next"),
+            ("Wait!What?Yes", "Wait! What? Yes"),
+            ("Mr.Smith", "Mr. Smith"),
+            ("End. Next", "End. Next"),
+            // Not a capital, so not a new sentence
+            ("e.g.the", "e.g.the"),
+            ("example.com", "example.com"),
+            ("end.next", "end.next"),
+            // Numbers and initials
+            ("3.14", "3.14"),
+            ("v2.X", "v2.X"),
+            ("U.S.A", "U.S.A"),
+            ("J.R.Tolkien", "J.R.Tolkien"),
+            ("...Then", "...Then"),
+            // The `.` was marked out
+            ("End.\u{8}xNext", "EndNext"),
+            // Not after a full stop
+            (".Start", ".Start"),
+            ("End,Next", "End,Next"),
+        ];
+        for (typed, expected) in cases {
+            let mut buffer = Buffer::new(Duration::ZERO);
+            buffer.set_auto_space_sentences(true);
+            for c in typed.chars() {
+                match c {
+                    '\u{8}' => buffer.backspace(),
+                    c => {
+                        buffer.insert_char(c);
+                    }
+                }
+            }
+            assert_eq!(buffer.text(), expected, "{:?}", typed);
+        }
+
+        // Off, nothing is put in
+        let mut buffer = Buffer::new(Duration::ZERO);
+        for c in "End.Next".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(buffer.text(), "End.Next");
+
+        // Pasted text is left as it is
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_auto_space_sentences(true);
+        buffer.paste("End.Next", Instant::now());
+        assert_eq!(buffer.text(), "End.Next");
+    }
+
+    #[test]
+    /// Test that a space put in is a keystroke of its own, with its own time
+    /// and sound, that can hit the margin like any other
+    fn test_auto_space_keystroke() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        buffer.set_auto_space_sentences(true);
+        buffer.set_soft_undo(true);
+        for c in "Done".chars() {
+            buffer.apply(EditorEvent::Char(c));
+        }
+        buffer.apply(EditorEvent::Char('.'));
+        let sounds: Vec<SoundType> = buffer
+            .apply(EditorEvent::Char('N'))
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::PlaySound { sound, .. } => Some(sound),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sounds, [SoundType::KeyPress(' '), SoundType::KeyPress('N')]);
+        assert_eq!(buffer.lines[0].characters[5].value, ' ');
+        assert!(buffer.lines[0].characters[5].typed_at <= buffer.lines[0].characters[6].typed_at);
+
+        // Undo takes back the capital and then the space
+        assert!(buffer.undo());
+        assert_eq!(buffer.text(), "Done. ");
+        assert!(buffer.undo());
+        assert_eq!(buffer.text(), "Done.");
+
+        // In word mode the space finishes the word, which clicks once
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        buffer.set_auto_space_sentences(true);
+        buffer.set_reveal_mode(RevealMode::Word);
+        for c in "Done.".chars() {
+            buffer.apply(EditorEvent::Char(c));
+        }
+        let effects = buffer.apply(EditorEvent::Char('N'));
+        assert!(matches!(effects[..], [Effect::PlaySound { sound: SoundType::KeyPress(' '), .. }]));
+
+        // With no room for the space, the capital isn't typed either
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_auto_space_sentences(true);
+        buffer.set_hard_margin(Some(4));
+        for c in "End.".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(buffer.insert_char('N'), Typed::HitMargin);
+        assert_eq!(buffer.text(), "End.");
+    }
+
+    #[test]
+    /// Test spacing out loaded text as if it were being typed
+    fn test_type_out() {
+        let reveal_rate = Duration::from_millis(100);
+        let mut buffer = Buffer::from_reader("ab\nc".as_bytes(), reveal_rate).unwrap();
+        let start = buffer.instant_at(BufferTime(1000));
+        buffer.type_out(start, Duration::from_millis(10), Duration::from_millis(50));
+
+        let times: Vec<u64> =
+            buffer.lines.iter().flat_map(|line| &line.characters).map(|character| character.typed_at.0).collect();
+        assert_eq!(times, vec![1010, 1020, 1080]);
+        assert_eq!(buffer.pending_reveals(start), 3);
+        assert_eq!(buffer.pending_reveals(buffer.instant_at(BufferTime(1125))), 1);
+        assert_eq!(buffer.next_reveal(start), Some(buffer.instant_at(BufferTime(1110))));
+
+        let shown = buffer.revealed_between(start, buffer.instant_at(BufferTime(1120)));
+        assert_eq!(shown, vec!['a', 'b']);
+        assert!(buffer.revealed_between(buffer.instant_at(BufferTime(1120)), start).is_empty());
+    }
+
+    #[test]
+    /// Test skipping to the end of the reveals
+    fn test_reveal_all_now() {
+        let mut buffer = Buffer::from_reader("ab\nc".as_bytes(), Duration::from_millis(100)).unwrap();
+        buffer.epoch -= Duration::from_secs(1);
+        let now = Instant::now();
+        buffer.type_out(now, Duration::from_millis(10), Duration::from_millis(50));
+        buffer.reveal_all_now(now);
+        assert_eq!(buffer.pending_reveals(now), 0);
+        assert_eq!(buffer.next_reveal(now), None);
+
+        // Typing afterwards still waits for the reveal delay
+        buffer.move_to_end();
+        buffer.insert_char('d');
+        assert_eq!(buffer.pending_reveals(Instant::now()), 1);
+
+        // Even right after the buffer was created
+        let mut buffer = Buffer::from_reader("ab".as_bytes(), Duration::from_millis(100)).unwrap();
+        let now = Instant::now();
+        buffer.type_out(now, Duration::from_millis(10), Duration::ZERO);
+        buffer.reveal_all_now(now);
+        assert_eq!(buffer.pending_reveals(now), 0);
+    }
+
+    #[test]
+    /// Test counting the characters still held back by the reveal rate
+    fn test_pending_reveals() {
+        let reveal_rate = Duration::from_millis(100);
+        let mut buffer = Buffer::new(reveal_rate);
+        assert_eq!(buffer.pending_reveals(Instant::now()), 0);
+
+        for c in "ab".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.new_line();
+        buffer.insert_char('c');
+        for (character, at) in buffer.lines.iter_mut().flat_map(|line| &mut line.characters).zip([950, 1000, 1000]) {
+            character.typed_at = BufferTime(at);
+        }
+        let typed = buffer.instant_at(BufferTime(1000));
+        assert_eq!(buffer.pending_reveals(typed), 3);
+
+        // The oldest character shows up first
+        assert_eq!(buffer.pending_reveals(typed + Duration::from_millis(60)), 2);
+        assert_eq!(buffer.pending_reveals(typed + reveal_rate), 0);
+    }
+
+    #[test]
+    /// Test moving to a column the way a mouse click does
+    fn test_move_to_column() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        for c in "abcd".chars() {
+            buffer.insert_char(c);
+        }
+
+        buffer.move_to_column(1);
+        assert_eq!(buffer.current_column, 1);
+        assert!(buffer.mark_out_mode);
+
+        buffer.move_to_column(3);
+        assert_eq!(buffer.current_column, 3);
+        assert!(buffer.mark_out_mode);
+
+        // Past the end clamps to the end and leaves mark-out mode
+        buffer.move_to_column(10);
+        assert_eq!(buffer.current_column, 4);
+        assert!(!buffer.mark_out_mode);
+    }
+
+    #[test]
+    /// Test that a paste is typed out silently, shown at once and split at the margin
+    fn test_paste() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        buffer.set_hard_margin(Some(10));
+        buffer.set_caps(true);
+        buffer.insert_char('a');
+        let now = Instant::now();
+        buffer.paste("bc\r\nthe quick\tbrown fox\x07", now);
+
+        // Typed as if by hand, in capitals, but nothing is left to appear
+        assert_eq!(buffer.text(), "ABC\nTHE QUICK BROWN FOX");
+        assert_eq!(buffer.pending_reveals(now), 0);
+        let lines: Vec<String> = buffer.text_lines().collect();
+        assert_eq!(lines, ["ABC", "THE QUICK ", "BROWN FOX"]);
+        assert_eq!(buffer.cursor_position(), (2, 9));
+
+        // The margin still holds for what is typed next
+        assert_eq!(buffer.insert_char('!'), Typed::Inserted);
+        assert_eq!(buffer.insert_char('!'), Typed::HitMargin);
+    }
+
+    #[test]
+    /// Test that the text leaves out marked-out characters
+    fn test_text() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        for c in "cat".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.backspace();
+        buffer.insert_char('x');
+        buffer.new_line();
+        buffer.insert_char('d');
+
+        assert_eq!(buffer.text_lines().collect::<Vec<_>>(), vec!["ca", "d"]);
+        assert_eq!(buffer.text(), "ca\nd");
+    }
+
+    #[test]
+    /// Test that locked lines can't be stepped back into
+    fn test_locked_lines() {
+        let mut buffer = Buffer::from_reader("yesterday\nmore".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        assert_eq!(buffer.cursor_position(), (1, 4));
+
+        buffer.lock_existing_lines();
+        buffer.insert_char('a');
+        for _ in 0..3 {
+            buffer.backspace();
+        }
+        assert_eq!(buffer.cursor_position(), (2, 0));
+        buffer.insert_char('x');
+        assert_eq!(buffer.text(), "yesterday\nmore\n");
+    }
+
+    #[test]
+    /// Test moving to a line, clamped to the lines there are
+    fn test_move_to_line() {
+        let mut buffer = Buffer::from_reader("one\ntwo\nthree".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_line(1);
+        assert_eq!(buffer.cursor_position(), (1, 3));
+
+        buffer.move_to_line(40);
+        assert_eq!(buffer.cursor_position(), (2, 5));
+    }
+
+    /// Steps back over `count` characters and marks them out, as backspace
+    /// then typing does
+    fn mark_out(buffer: &mut Buffer, count: usize) {
+        for _ in 0..count {
+            buffer.backspace();
+        }
+        for _ in 0..count {
+            buffer.insert_char('x');
+        }
+    }
+
+    #[test]
+    /// Test typing a correction right after the struck word with one keystroke
+    fn test_finish_correction() {
+        let mut buffer = Buffer::from_reader("teh".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_line(0);
+        assert_eq!(buffer.last_marked_out_column(), None);
+        mark_out(&mut buffer, 3);
+        assert_eq!(buffer.last_marked_out_column(), Some(2));
+
+        buffer.finish_correction();
+        assert_eq!(buffer.cursor_position(), (0, 3));
+        assert!(!buffer.is_mark_out_mode());
+        for c in " the".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(buffer.text(), " the");
+    }
+
+    #[test]
+    /// Test that with several struck regions the correction follows the last,
+    /// ahead of any text after it
+    fn test_finish_correction_multiple_regions() {
+        let mut buffer = Buffer::from_reader("a bb cc dd".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_line(0);
+
+        // Strike "bb", then "cc", stepping back over the text between them
+        for _ in 0..8 {
+            buffer.backspace();
+        }
+        buffer.insert_char('x');
+        buffer.insert_char('x');
+        buffer.move_right();
+        buffer.insert_char('x');
+        buffer.insert_char('x');
+        assert_eq!(buffer.last_marked_out_column(), Some(6));
+        assert!(buffer.is_mark_out_mode());
+
+        // Typing now goes in after "cc" instead of striking " dd"
+        buffer.finish_correction();
+        assert_eq!(buffer.cursor_position(), (0, 7));
+        buffer.insert_char('C');
+        assert_eq!(buffer.text(), "a  C dd");
+        assert_eq!(buffer.cursor_position(), (0, 8));
+
+        // Nothing marked out on the line means the end of it
+        buffer.new_line();
+        buffer.insert_char('e');
+        buffer.backspace();
+        buffer.finish_correction();
+        assert_eq!(buffer.cursor_position(), (1, 1));
+        assert!(!buffer.is_mark_out_mode());
+    }
+}
@@ -1,4 +1,8 @@
-use std::time::Instant;
+use std::ops::Add;
+use std::time::{Duration, Instant};
+
+use super::is_invisible;
+use crate::sound::SoundType;
 
 /// Represents the state of a character in the editor
 #[derive(Debug, Clone, PartialEq)]
@@ -9,38 +13,217 @@ pub enum CharacterState {
     MarkedOut,
 }
 
+/// A moment in the life of a buffer, in milliseconds since it was created.
+/// Unlike an `Instant`, it can name any time, so loaded or replayed text can
+/// be given times of its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BufferTime(pub u64);
+
+impl BufferTime {
+    /// Returns the time of `instant` for a buffer created at `epoch`, or zero
+    /// for anything before it
+    pub fn since(epoch: Instant, instant: Instant) -> Self {
+        Self(instant.saturating_duration_since(epoch).as_millis() as u64)
+    }
+
+    /// Returns the instant this time falls on for a buffer created at `epoch`
+    pub fn to_instant(self, epoch: Instant) -> Instant {
+        epoch + Duration::from_millis(self.0)
+    }
+
+    /// Returns the time `duration` earlier, or zero if that is before the buffer
+    pub fn saturating_sub(self, duration: Duration) -> Self {
+        Self(self.0.saturating_sub(duration.as_millis() as u64))
+    }
+}
+
+impl Add<Duration> for BufferTime {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        Self(self.0 + duration.as_millis() as u64)
+    }
+}
+
 /// Represents a single character in the editor buffer
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Character {
     /// The actual character
     pub value: char,
     /// The current state of the character
     pub state: CharacterState,
     /// When this character was typed
-    pub timestamp: Instant,
+    pub typed_at: BufferTime,
+    /// How much sooner than a whole reveal delay after `typed_at` it appears
+    pub lead: Duration,
+    /// Whether it is kept hidden until its word is finished, in word reveal mode
+    pub held: bool,
+    /// Whether it was typed this session, rather than loaded from a file
+    pub typed: bool,
+    /// Whether it was typed or marked out since the buffer was last saved
+    pub changed: bool,
+    /// The control character this stands in for, if it was loaded as one.
+    /// It is saved in place of the stand-in while its line is unedited.
+    pub original: Option<char>,
 }
 
 impl Character {
-    /// Creates a new character in normal state
-    pub fn new(value: char) -> Self {
+    /// Creates a new character in normal state, typed at the given time
+    pub fn new(value: char, typed_at: BufferTime) -> Self {
         Self {
             value,
             state: CharacterState::Normal,
-            timestamp: Instant::now(),
+            typed_at,
+            lead: Duration::ZERO,
+            held: false,
+            typed: true,
+            changed: true,
+            original: None,
         }
     }
 
     /// Marks out this character (crosses it out)
     pub fn mark_out(&mut self) {
         self.state = CharacterState::MarkedOut;
+        self.changed = true;
+    }
+}
+
+/// How typed text appears on the page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RevealMode {
+    /// Each character a reveal delay after it was typed
+    Char,
+    /// Each word at once, a reveal delay after the space or punctuation ending it
+    Word,
+}
+
+/// Parses a reveal mode: `char` or `word`
+pub fn parse_reveal_mode(value: &str) -> Result<RevealMode, String> {
+    match value {
+        "char" => Ok(RevealMode::Char),
+        "word" => Ok(RevealMode::Word),
+        _ => Err(format!("must be `char` or `word`, not `{}`", value)),
+    }
+}
+
+/// Returns whether a character ends the word before it. Apostrophes and
+/// hyphens belong to the word.
+pub fn is_word_boundary(c: char) -> bool {
+    !(c.is_alphanumeric() || matches!(c, '\'' | '’' | '-'))
+}
+
+/// What became of a typed character
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Typed {
+    /// It was typed
+    Inserted,
+    /// It was struck over a character in mark-out mode, marking it out
+    MarkedOut,
+    /// It was typed and brought the line near the hard margin, where the bell rings
+    MarginBell,
+    /// It was a hyphen splitting a word at the hard margin, so a new line was
+    /// started after it, with hyphen hints on
+    Hyphenated,
+    /// The line is at the hard margin, so it was refused. Only the first
+    /// refusal in a row gives this; the rest are `Refused`.
+    HitMargin,
+    /// Refused again at the hard margin
+    Refused,
+}
+
+/// A change to the buffer, as typed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditorEvent {
+    /// A character, typed or struck over a marked-out one
+    Char(char),
+    /// Backspace, which marks out in place
+    Backspace,
+    /// Enter
+    NewLine,
+    /// Move right over what is already typed
+    Right,
+    /// Go back down to the line left by backspacing up past its start
+    Down,
+    /// Move on to the next tab stop, or type a space without one
+    Tab,
+    /// Leave mark-out mode, keeping the cursor where it is
+    FinishCorrection,
+    /// Take back the last change to the line being typed
+    Undo,
+}
+
+impl EditorEvent {
+    /// Returns the keys that would type out a pasted text: line breaks are
+    /// Enter and tabs are spaces, and other control characters and invisible
+    /// ones are left out
+    pub fn typing(text: &str) -> impl Iterator<Item = EditorEvent> + '_ {
+        text.chars().filter_map(|c| match c {
+            '\n' => Some(EditorEvent::NewLine),
+            '\t' => Some(EditorEvent::Char(' ')),
+            c if c.is_control() || is_invisible(c) => None,
+            c => Some(EditorEvent::Char(c)),
+        })
     }
 }
 
+/// Something outside the buffer that should happen because of an edit
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// A sound, timed to when the text it belongs to appears
+    PlaySound { sound: SoundType, at: Instant },
+    /// The terminal bell
+    Bell,
+    /// A flash of the screen
+    Flash,
+    /// The line being typed was finished, so it can no longer be undone
+    Committed,
+    /// Undo was asked for with nothing on the line to take back
+    NothingToUndo,
+    /// A character was marked out
+    MarkedOut,
+}
+
+/// A change made to a buffer, for keeping a view of it up to date without
+/// looking over all of it. See `Buffer::track_changes`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    /// A character was typed at this place, moving the rest of the line along
+    CharInserted { line: usize, column: usize, value: char },
+    /// The character at this place was marked out
+    CharMarkedOut { line: usize, column: usize },
+    /// Undo took away the character typed at this place
+    CharRemoved { line: usize, column: usize },
+    /// Undo took back marking out the character at this place
+    CharRestored { line: usize, column: usize },
+    /// An empty line was added at this index
+    LineAdded { line: usize },
+    /// A line too long for the page was broken at this column, the rest of it
+    /// becoming a continuation line after it
+    LineSplit { line: usize, column: usize },
+    /// The cursor moved here
+    CursorMoved { line: usize, column: usize },
+    /// The buffer was saved to its file
+    Saved,
+}
+
 /// Represents a line of text in the editor
 #[derive(Debug, Clone)]
 pub struct Line {
     /// The characters in this line
     pub characters: Vec<Character>,
+    /// Whether this line carries on the one before it, having been split off a
+    /// line too long for the page. It is saved without a line break before it.
+    pub continuation: bool,
+    /// Buffer revision in which this line, or one saved together with it,
+    /// last changed
+    pub(super) revision: u64,
+    /// Whether this line, or one saved together with it, has been typed in
+    /// or marked out since it was loaded
+    pub(super) edited: bool,
+    /// The line as it was loaded, kept when it held tabs: they are spaces on
+    /// the page, and this is saved in their place while the line is unedited
+    pub(super) loaded: Option<String>,
 }
 
 impl Line {
@@ -48,9 +231,26 @@ impl Line {
     pub fn new() -> Self {
         Self {
             characters: Vec::new(),
+            continuation: false,
+            revision: 0,
+            edited: false,
+            loaded: None,
         }
     }
 
+    /// Returns the buffer revision this line last changed in, so a view can
+    /// tell whether what it made of the line still holds. Only changes made
+    /// through the buffer's methods count.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns the character saved for one on this line: the control
+    /// character a loaded stand-in replaced, until the line is edited
+    pub fn saved_value(&self, character: &Character) -> char {
+        character.original.filter(|_| !self.edited).unwrap_or(character.value)
+    }
+
     /// Adds a character to this line
     pub fn push(&mut self, character: Character) {
         self.characters.push(character);
@@ -65,18 +265,27 @@ impl Line {
     pub fn is_empty(&self) -> bool {
         self.characters.is_empty()
     }
+
+    /// Returns how many characters end the line after its last one that would
+    /// be saved other than a space or tab. Marked-out characters count among
+    /// them, since they aren't saved. They still take up columns, so the
+    /// margin bell and the hard margin count them like any other character.
+    pub fn trailing_whitespace_len(&self) -> usize {
+        let is_space = |character: &Character| {
+            character.state == CharacterState::MarkedOut || matches!(character.value, ' ' | '\t')
+        };
+        self.characters.iter().rev().take_while(|character| is_space(character)).count()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
-    use std::time::Duration;
 
     #[test]
     /// Test creating a new character
     fn test_new_character() {
-        let c = Character::new('a');
+        let c = Character::new('a', BufferTime(0));
         assert_eq!(c.value, 'a');
         assert_eq!(c.state, CharacterState::Normal);
     }
@@ -84,21 +293,27 @@ mod tests {
     #[test]
     /// Test marking out a character
     fn test_mark_out_character() {
-        let mut c = Character::new('a');
+        let mut c = Character::new('a', BufferTime(0));
+        c.changed = false;
         c.mark_out();
         assert_eq!(c.state, CharacterState::MarkedOut);
+        assert!(c.changed);
     }
 
     #[test]
-    /// Test character timestamp is set correctly
-    fn test_character_timestamp() {
-        let before = Instant::now();
-        thread::sleep(Duration::from_millis(10));
-        let c = Character::new('a');
-        thread::sleep(Duration::from_millis(10));
-        let after = Instant::now();
-        
-        assert!(c.timestamp >= before && c.timestamp <= after);
+    /// Test converting between buffer times and instants
+    fn test_buffer_time() {
+        let c = Character::new('a', BufferTime(1500));
+        assert_eq!(c.typed_at, BufferTime(1500));
+
+        let epoch = Instant::now();
+        let typed = c.typed_at.to_instant(epoch);
+        assert_eq!(typed, epoch + Duration::from_millis(1500));
+        assert_eq!(BufferTime::since(epoch, typed), c.typed_at);
+        assert_eq!(BufferTime::since(epoch + Duration::from_secs(1), epoch), BufferTime(0));
+
+        assert_eq!(c.typed_at + Duration::from_millis(300), BufferTime(1800));
+        assert_eq!(c.typed_at.saturating_sub(Duration::from_secs(2)), BufferTime(0));
     }
 
     #[test]
@@ -108,9 +323,29 @@ mod tests {
         assert!(line.is_empty());
         assert_eq!(line.len(), 0);
 
-        line.push(Character::new('a'));
+        line.push(Character::new('a', BufferTime(0)));
         assert!(!line.is_empty());
         assert_eq!(line.len(), 1);
         assert_eq!(line.characters[0].value, 'a');
     }
+
+    #[test]
+    /// Test that trailing whitespace counts spaces, tabs and marked-out
+    /// characters after the last character saved
+    fn test_trailing_whitespace_len() {
+        let mut line = Line::new();
+        assert_eq!(line.trailing_whitespace_len(), 0);
+        for c in "a b \t".chars() {
+            line.push(Character::new(c, BufferTime(0)));
+        }
+        assert_eq!(line.trailing_whitespace_len(), 2);
+
+        let mut marked = Character::new('c', BufferTime(0));
+        marked.mark_out();
+        line.push(marked);
+        line.push(Character::new(' ', BufferTime(0)));
+        assert_eq!(line.trailing_whitespace_len(), 4);
+        line.push(Character::new('d', BufferTime(0)));
+        assert_eq!(line.trailing_whitespace_len(), 0);
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,94 @@
+use unicode_width::UnicodeWidthChar;
+
+/// Characters a word too long for a line may break after, as in a path,
+/// a URL or a hyphenated word
+const BREAK_AFTER: [char; 3] = ['/', '-', '_'];
+
+/// Joins the characters either side of it into one, as in some emoji
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// Returns how many of `line`'s characters go on a line at most `max_width`
+/// characters long. The line breaks after the last space that fits. A word
+/// too long for a line breaks after the last `/`, `-` or `_` that fits, or
+/// next to a wide character such as one of a run of CJK, or else at
+/// `max_width`. A character is never parted from the combining marks after
+/// it. The whole line if it fits.
+pub fn find_break_point(line: &[char], max_width: usize) -> usize {
+    if line.len() <= max_width {
+        return line.len();
+    }
+    let max_width = max_width.max(1);
+    if let Some(space) = line[..max_width].iter().rposition(|c| c.is_whitespace()) {
+        return space + 1;
+    }
+    let joined = |at: usize| line[at].width() == Some(0) || line[at - 1] == ZERO_WIDTH_JOINER;
+    let allowed = |at: usize| BREAK_AFTER.contains(&line[at - 1]) || is_wide(line[at - 1]) || is_wide(line[at]);
+    (1..=max_width)
+        .rev()
+        .find(|&at| !joined(at) && allowed(at))
+        .or_else(|| (1..=max_width).rev().find(|&at| !joined(at)))
+        .unwrap_or(max_width)
+}
+
+/// Returns whether a character takes two columns, as CJK ones do
+fn is_wide(c: char) -> bool {
+    c.width() == Some(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits `text` into the lines `find_break_point` breaks it into
+    fn broken(text: &str, max_width: usize) -> Vec<String> {
+        let mut rest: Vec<char> = text.chars().collect();
+        let mut lines = Vec::new();
+        while !rest.is_empty() {
+            let tail = rest.split_off(find_break_point(&rest, max_width));
+            lines.push(std::mem::replace(&mut rest, tail).into_iter().collect());
+        }
+        lines
+    }
+
+    #[test]
+    /// Test that lines break after spaces, and a line that fits is left whole
+    fn test_spaces() {
+        assert_eq!(broken("one two three", 13), ["one two three"]);
+        assert_eq!(broken("one two three", 9), ["one two ", "three"]);
+        assert_eq!(broken("one two-three", 9), ["one ", "two-three"]);
+    }
+
+    #[test]
+    /// Test that a URL breaks after its slashes rather than inside a name
+    fn test_url() {
+        assert_eq!(
+            broken("https://example.com/docs/getting_started", 16),
+            ["https://", "example.com/", "docs/getting_", "started"]
+        );
+    }
+
+    #[test]
+    /// Test that a hyphenated word too long for a line breaks after a hyphen
+    fn test_hyphenated() {
+        assert_eq!(broken("mother-in-law's", 10), ["mother-in-", "law's"]);
+    }
+
+    #[test]
+    /// Test that a run of CJK breaks between any two of its characters, and
+    /// next to the Latin text around it
+    fn test_cjk() {
+        assert_eq!(broken("漢字漢字漢字", 4), ["漢字漢字", "漢字"]);
+        assert_eq!(broken("abc漢字defgh", 6), ["abc漢字", "defgh"]);
+    }
+
+    #[test]
+    /// Test that a token with nowhere to break is cut at the width, but never
+    /// between a letter and its combining accents
+    fn test_forced() {
+        assert_eq!(broken("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(broken("abce\u{301}\u{302}fgh", 4), ["abc", "e\u{301}\u{302}f", "gh"]);
+        assert_eq!(broken("ab/e\u{301}fg", 3), ["ab/", "e\u{301}f", "g"]);
+        assert_eq!(broken("ab\u{200D}cd", 3), ["a", "b\u{200D}c", "d"]);
+        assert_eq!(find_break_point(&['x'; 3], 0), 1);
+    }
+}
@@ -0,0 +1,477 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+
+use crate::editor::{Buffer, BufferTime, CharacterState};
+use crate::renderer::{Focus, LayoutCache, RenderOptions, Shade, Viewport, VISIBLE_LINES};
+
+/// Widest page that can be exported, in characters
+pub const MAX_COLUMNS: usize = 100;
+
+/// Rows of the page in view; longer texts scroll as they do on screen
+pub const MAX_ROWS: usize = 30;
+
+/// Most frames an export may have
+pub const MAX_FRAMES: usize = 2000;
+
+/// Time between characters when a plain file is replayed
+pub const TYPING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Extra time a carriage return takes when a plain file is replayed
+pub const RETURN_PAUSE: Duration = Duration::from_millis(400);
+
+/// How long the finished page stays up before the animation starts over
+const FINAL_PAUSE: Duration = Duration::from_secs(3);
+
+/// Width of a character cell in font pixels
+const CELL_WIDTH: usize = 8;
+
+/// Height of a character cell in font pixels: the 8-pixel glyph with room
+/// between lines
+const CELL_HEIGHT: usize = 12;
+
+/// Font pixels above the glyph in its cell
+const GLYPH_TOP: usize = 2;
+
+/// Font pixel row of the glyph that the line through marked-out text is drawn on
+const STRIKE_ROW: usize = 4;
+
+/// Image pixels per font pixel
+const SCALE: usize = 2;
+
+/// Empty cells around the page
+const MARGIN: usize = 1;
+
+/// The colours used: paper, ink and faded ink, as RGB
+const PALETTE: [u8; 9] = [0xf6, 0xf1, 0xe4, 0x22, 0x22, 0x22, 0x9a, 0x96, 0x8c];
+
+/// Palette index of the paper
+const PAPER: u8 = 0;
+
+/// Palette index of fresh ink
+const INK: u8 = 1;
+
+/// Palette index of the ink on lines that have scrolled past
+const FADED: u8 = 2;
+
+/// Why an export couldn't be made
+#[derive(Debug)]
+pub enum ExportError {
+    /// A line is wider than the page can be
+    TooWide { line: usize, columns: usize },
+    /// The typing would take more frames than allowed
+    TooManyFrames(usize),
+    /// Writing the file failed
+    Io(io::Error),
+    /// The GIF encoder refused the image
+    Encoding(gif::EncodingError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooWide { line, columns } => write!(
+                f,
+                "line {} is {} characters long, but an exported page is at most {} wide",
+                line, columns, MAX_COLUMNS
+            ),
+            Self::TooManyFrames(frames) => write!(
+                f,
+                "typing it takes {} frames, but an export has at most {}; try a shorter text",
+                frames, MAX_FRAMES
+            ),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Encoding(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<gif::EncodingError> for ExportError {
+    fn from(e: gif::EncodingError) -> Self {
+        Self::Encoding(e)
+    }
+}
+
+/// Gives every character of a buffer loaded from a plain file the time it
+/// would have been typed at a steady pace after `start`
+pub fn replay_timing(buffer: &mut Buffer, start: Instant) {
+    buffer.type_out(start, TYPING_INTERVAL, RETURN_PAUSE);
+}
+
+/// Writes the typing of a buffer to a GIF file, returning how many frames it has
+pub fn export(buffer: &Buffer, path: &Path) -> Result<usize, ExportError> {
+    let mut output = BufWriter::new(File::create(path)?);
+    let frames = write_gif(buffer, &mut output)?;
+    output.flush()?;
+    Ok(frames)
+}
+
+/// Encodes the typing of a buffer as a looping GIF: a frame each time more
+/// characters are revealed, shown until the next one
+pub fn write_gif<W: Write>(buffer: &Buffer, output: W) -> Result<usize, ExportError> {
+    let mut page = Page::new(buffer)?;
+    let times = page.frame_times();
+    if times.len() > MAX_FRAMES {
+        return Err(ExportError::TooManyFrames(times.len()));
+    }
+
+    let (width, height) = page.pixel_size();
+    let mut encoder = gif::Encoder::new(output, width as u16, height as u16, &PALETTE)?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for (idx, &at) in times.iter().enumerate() {
+        let next = times.get(idx + 1).copied().unwrap_or(at + centiseconds(FINAL_PAUSE));
+        let frame = gif::Frame {
+            width: width as u16,
+            height: height as u16,
+            delay: (next - at).min(u16::MAX as u64) as u16,
+            buffer: Cow::Owned(page.grid(at).pixels(SCALE)),
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&frame)?;
+    }
+    encoder.into_inner()?;
+    Ok(times.len())
+}
+
+/// Returns a duration in the hundredths of a second GIF delays are counted in
+fn centiseconds(duration: Duration) -> u64 {
+    (duration.as_millis() / 10) as u64
+}
+
+/// A character as it appears in one frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cell {
+    /// The character drawn
+    value: char,
+    /// Palette index of its ink
+    ink: u8,
+    /// Whether a line is drawn through it
+    struck: bool,
+}
+
+/// One frame of the page as a grid of character cells, margin not included
+#[derive(Debug, Clone, PartialEq)]
+struct Grid {
+    /// Width in cells
+    columns: usize,
+    /// Rows of cells, each no longer than `columns`
+    rows: Vec<Vec<Cell>>,
+    /// Height in cells
+    height: usize,
+}
+
+/// The buffer being exported, with each character's reveal time worked out
+struct Page<'a> {
+    /// The buffer, laid out for each frame as the screen would be
+    buffer: &'a Buffer,
+    /// Rows laid out for earlier frames
+    cache: LayoutCache,
+    /// When the first keystroke was typed
+    start: BufferTime,
+    /// The centisecond each character of each line appears at, counted from
+    /// the first keystroke
+    lines: Vec<Vec<u64>>,
+    /// Width of the widest line
+    columns: usize,
+    /// Rows in view
+    rows: usize,
+}
+
+impl<'a> Page<'a> {
+    /// Works out when each character of a buffer appears, failing if a line
+    /// is too wide to export
+    fn new(buffer: &'a Buffer) -> Result<Self, ExportError> {
+        let start = buffer
+            .lines
+            .iter()
+            .flat_map(|line| line.characters.iter().map(|character| character.typed_at))
+            .min()
+            .unwrap_or_default();
+        let lines: Vec<Vec<u64>> = buffer
+            .lines
+            .iter()
+            .map(|line| {
+                line.characters
+                    .iter()
+                    .map(|character| centiseconds(Duration::from_millis(buffer.shown_at(character).0 - start.0)))
+                    .collect()
+            })
+            .collect();
+
+        if let Some((idx, line)) = lines.iter().enumerate().find(|(_, line)| line.len() > MAX_COLUMNS) {
+            return Err(ExportError::TooWide {
+                line: idx + 1,
+                columns: line.len(),
+            });
+        }
+        Ok(Self {
+            buffer,
+            cache: LayoutCache::default(),
+            start,
+            columns: lines.iter().map(Vec::len).max().unwrap_or(0).max(1),
+            rows: lines.len().clamp(1, MAX_ROWS),
+            lines,
+        })
+    }
+
+    /// Returns the size of the image in pixels
+    fn pixel_size(&self) -> (usize, usize) {
+        (
+            (self.columns + 2 * MARGIN) * CELL_WIDTH * SCALE,
+            (self.rows + 2 * MARGIN) * CELL_HEIGHT * SCALE,
+        )
+    }
+
+    /// Returns when each frame starts: a blank page, then each moment more
+    /// characters appear
+    fn frame_times(&self) -> Vec<u64> {
+        let mut times: Vec<u64> = self.lines.iter().flatten().copied().collect();
+        times.push(0);
+        times.sort_unstable();
+        times.dedup();
+        times
+    }
+
+    /// Returns what the page shows at a moment, laid out as the screen would
+    /// be. As on screen, it scrolls to keep the line being typed in view and
+    /// fades the lines above it.
+    fn grid(&mut self, at: u64) -> Grid {
+        let current = self
+            .lines
+            .iter()
+            .rposition(|line| line.iter().any(|&shown| shown <= at))
+            .unwrap_or(0);
+        let top = (current + 1).saturating_sub(self.rows);
+        let fresh = (current + 1).saturating_sub(VISIBLE_LINES);
+        // A row more for the status row, which stays empty
+        let viewport = Viewport {
+            size: (self.columns as u16, self.rows as u16 + 1),
+            scroll_top: top,
+            scroll_left: 0,
+        };
+        // The whole text is in the buffer already, so the lines kept bright
+        // are counted back from its end to the line being typed
+        let options = RenderOptions {
+            focus: Focus::Lines(self.lines.len() - fresh),
+            ..RenderOptions::default()
+        };
+        // The end of the centisecond, so everything due during it is shown
+        let now = self.buffer.instant_at(self.start + Duration::from_millis(at * 10 + 9));
+        let frame = self.cache.layout(self.buffer, viewport, &options, now);
+        let rows = frame
+            .lines
+            .iter()
+            .take(current + 1 - top)
+            .map(|line| {
+                line.iter()
+                    .map(|(character, shade, _)| Cell {
+                        value: character.value,
+                        ink: if *shade == Shade::Bright { INK } else { FADED },
+                        struck: character.state == CharacterState::MarkedOut,
+                    })
+                    .collect()
+            })
+            .collect();
+        Grid {
+            columns: self.columns,
+            rows,
+            height: self.rows,
+        }
+    }
+}
+
+impl Grid {
+    /// Draws the grid with its margin as palette indices, each font pixel
+    /// `scale` image pixels across
+    fn pixels(&self, scale: usize) -> Vec<u8> {
+        let width = (self.columns + 2 * MARGIN) * CELL_WIDTH * scale;
+        let height = (self.height + 2 * MARGIN) * CELL_HEIGHT * scale;
+        let mut pixels = vec![PAPER; width * height];
+        for (row, cells) in self.rows.iter().enumerate() {
+            for (column, cell) in cells.iter().enumerate() {
+                let left = (column + MARGIN) * CELL_WIDTH;
+                let top = (row + MARGIN) * CELL_HEIGHT;
+                for (y, x) in cell_pixels(cell) {
+                    for dy in 0..scale {
+                        let start = ((top + y) * scale + dy) * width + (left + x) * scale;
+                        pixels[start..start + scale].fill(cell.ink);
+                    }
+                }
+            }
+        }
+        pixels
+    }
+}
+
+/// Returns the font pixels a cell inks, as (row, column) within the cell
+fn cell_pixels(cell: &Cell) -> Vec<(usize, usize)> {
+    let glyph = BASIC_FONTS
+        .get(cell.value)
+        .or_else(|| LATIN_FONTS.get(cell.value))
+        .or_else(|| BASIC_FONTS.get('?'))
+        .unwrap_or_default();
+    // The lowest bit of each row is its leftmost pixel
+    let mut inked: Vec<(usize, usize)> = glyph
+        .iter()
+        .enumerate()
+        .flat_map(|(y, bits)| (0..8).filter(move |x| bits & (1 << x) != 0).map(move |x| (GLYPH_TOP + y, x)))
+        .collect();
+    if cell.struck {
+        inked.extend((0..CELL_WIDTH).map(|x| (GLYPH_TOP + STRIKE_ROW, x)));
+    }
+    inked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a buffer holding the text, typed at the replay pace
+    fn replayed(text: &str, reveal_rate: Duration) -> Buffer {
+        let mut buffer = Buffer::from_reader(text.as_bytes(), reveal_rate).unwrap();
+        replay_timing(&mut buffer, Instant::now());
+        buffer
+    }
+
+    /// Draws one cell at its real size, `#` for ink, `+` for faded ink and
+    /// `.` for paper
+    fn picture(cell: Cell) -> Vec<String> {
+        let grid = Grid {
+            columns: 1,
+            rows: vec![vec![cell]],
+            height: 1,
+        };
+        let width = (1 + 2 * MARGIN) * CELL_WIDTH;
+        grid.pixels(1)
+            .chunks(width)
+            .skip(MARGIN * CELL_HEIGHT)
+            .take(CELL_HEIGHT)
+            .map(|row| {
+                row[MARGIN * CELL_WIDTH..(MARGIN + 1) * CELL_WIDTH]
+                    .iter()
+                    .map(|&pixel| match pixel {
+                        INK => '#',
+                        FADED => '+',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Test the pixels of a plain, a faded and a marked-out character
+    fn test_golden_cells() {
+        let cell = Cell {
+            value: 'H',
+            ink: INK,
+            struck: false,
+        };
+        #[rustfmt::skip]
+        let plain = [
+            "........",
+            "........",
+            "##..##..",
+            "##..##..",
+            "##..##..",
+            "######..",
+            "##..##..",
+            "##..##..",
+            "##..##..",
+            "........",
+            "........",
+            "........",
+        ];
+        assert_eq!(picture(cell), plain);
+
+        let faded: Vec<String> = plain.iter().map(|row| row.replace('#', "+")).collect();
+        assert_eq!(picture(Cell { ink: FADED, ..cell }), faded);
+
+        let mut struck: Vec<String> = plain.iter().map(|row| row.to_string()).collect();
+        struck[GLYPH_TOP + STRIKE_ROW] = "########".to_string();
+        assert_eq!(picture(Cell { struck: true, ..cell }), struck);
+    }
+
+    #[test]
+    /// Test that frames follow the reveal of each character, blank page first
+    fn test_frames() {
+        let buffer = replayed("ab\nc", Duration::from_millis(300));
+        let mut page = Page::new(&buffer).unwrap();
+        assert_eq!(page.pixel_size(), (4 * CELL_WIDTH * SCALE, 4 * CELL_HEIGHT * SCALE));
+
+        // Characters come every 10 cs, with 40 more for the return, 30 cs after typing
+        assert_eq!(page.frame_times(), vec![0, 30, 40, 90]);
+        assert!(page.grid(0).rows.iter().all(Vec::is_empty));
+        let values = |grid: Grid| -> Vec<String> {
+            grid.rows.iter().map(|row| row.iter().map(|cell| cell.value).collect()).collect()
+        };
+        assert_eq!(values(page.grid(40)), vec!["ab"]);
+        assert_eq!(values(page.grid(90)), vec!["ab", "c"]);
+    }
+
+    #[test]
+    /// Test that the page scrolls to the line being typed and fades the ones above
+    fn test_scrolling_and_fading() {
+        let text: Vec<String> = (0..MAX_ROWS + 5).map(|idx| format!("{}", idx % 10)).collect();
+        let buffer = replayed(&text.join("\n"), Duration::ZERO);
+        let mut page = Page::new(&buffer).unwrap();
+        assert_eq!(page.rows, MAX_ROWS);
+
+        let last = *page.frame_times().last().unwrap();
+        let grid = page.grid(last);
+        assert_eq!(grid.rows.len(), MAX_ROWS);
+        assert_eq!(grid.rows[0][0].value, '5');
+        assert_eq!(grid.rows[MAX_ROWS - VISIBLE_LINES - 1][0].ink, FADED);
+        assert_eq!(grid.rows[MAX_ROWS - VISIBLE_LINES][0].ink, INK);
+    }
+
+    #[test]
+    /// Test that the GIF decodes with a frame for each reveal and the right delays
+    fn test_write_gif() {
+        let buffer = replayed("ab\nc", Duration::from_millis(300));
+        let mut bytes = Vec::new();
+        assert_eq!(write_gif(&buffer, &mut bytes).unwrap(), 4);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(bytes.as_slice()).unwrap();
+        assert_eq!(
+            (decoder.width() as usize, decoder.height() as usize),
+            (4 * CELL_WIDTH * SCALE, 4 * CELL_HEIGHT * SCALE)
+        );
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![30, 10, 50, 300]);
+    }
+
+    #[test]
+    /// Test that pages too wide or too long to type are refused with a reason
+    fn test_limits() {
+        let buffer = replayed(&format!("short\n{}", "a".repeat(MAX_COLUMNS + 1)), Duration::ZERO);
+        let error = write_gif(&buffer, Vec::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("line 2 is {} characters long, but an exported page is at most {} wide", MAX_COLUMNS + 1, MAX_COLUMNS)
+        );
+
+        let line = "a".repeat(MAX_COLUMNS);
+        let text = vec![line.as_str(); MAX_FRAMES / MAX_COLUMNS + 1].join("\n");
+        let error = write_gif(&replayed(&text, Duration::ZERO), Vec::new()).unwrap_err();
+        assert!(matches!(error, ExportError::TooManyFrames(frames) if frames > MAX_FRAMES));
+    }
+}
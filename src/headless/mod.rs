@@ -0,0 +1,217 @@
+use crate::cli::Cli;
+use crate::config::{self, Config};
+use crate::diagnostics::BuildInfo;
+use crate::editor::Buffer;
+use crate::recent::RecentFiles;
+use crate::stats::DocumentStats;
+use crate::{bench, drafts, paths, report, snapshots, ExitStatus};
+use log::Level;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+#[cfg(feature = "gif-export")]
+use std::time::Instant;
+
+/// Whether a run needs the terminal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Launch {
+    /// It prints what was asked for and exits, leaving the terminal alone
+    Headless,
+    /// It draws the page in the terminal
+    Terminal,
+    /// It would draw the page, but stdout goes to a file or a pipe
+    NotATerminal,
+}
+
+/// Decides whether a run needs the terminal. The flags that print and exit
+/// run wherever stdout goes; everything else draws and needs one.
+pub fn launch(cli: &Cli, stdout_is_terminal: bool) -> Launch {
+    let headless = cli.write_default_config
+        || cli.build_info
+        || cli.bench
+        || cli.stats_only
+        || cli.list_snapshots
+        || cli.recent
+        || cli.render_gif.is_some();
+    match (headless, stdout_is_terminal) {
+        (true, _) => Launch::Headless,
+        (false, true) => Launch::Terminal,
+        (false, false) => Launch::NotATerminal,
+    }
+}
+
+/// Carries out the flags that need neither the config nor the recent files,
+/// returning how it went, or nothing if none of them was given
+pub fn run(cli: &Cli, env_var: impl Fn(&str) -> Option<String>) -> Option<ExitStatus> {
+    if cli.write_default_config {
+        print!("{}", config::DEFAULT_CONFIG);
+        return Some(ExitStatus::Clean);
+    }
+    if cli.build_info {
+        print!("{}", BuildInfo::gather(Config::chosen_path(cli.config.as_deref(), env_var)));
+        return Some(ExitStatus::Clean);
+    }
+    if cli.bench {
+        return Some(print_bench());
+    }
+    match (cli.stats_only, cli.list_snapshots, &cli.file) {
+        (true, _, Some(file)) => Some(print_stats(Path::new(file), cli.json)),
+        (_, true, Some(file)) => Some(print_snapshots(file)),
+        _ => None,
+    }
+}
+
+/// Prints the recent files for `--recent`, newest first
+pub fn print_recent(recent: &RecentFiles) -> ExitStatus {
+    for file in recent.files() {
+        println!("{}", file.path.display());
+    }
+    ExitStatus::Clean
+}
+
+/// Prints the statistics of a document for `--stats-only`, as labelled rows
+/// or as JSON
+fn print_stats(path: &Path, json: bool) -> ExitStatus {
+    match document_stats(path) {
+        Ok(stats) if json => println!("{}", stats.to_json()),
+        Ok(stats) => print!("{}", stats.format()),
+        Err(e) => {
+            report(Level::Error, &e);
+            return ExitStatus::File;
+        }
+    }
+    ExitStatus::Clean
+}
+
+/// Times layout, rendering and editing on a large made-up page for `--bench`,
+/// and prints the timings as JSON
+fn print_bench() -> ExitStatus {
+    match bench::run(bench::Sizes::default()) {
+        Ok(timings) => println!("{}", bench::to_json(&timings)),
+        Err(e) => {
+            report(Level::Error, &format!("Benchmark failed: {}", e));
+            return ExitStatus::Terminal;
+        }
+    }
+    ExitStatus::Clean
+}
+
+/// Prints the snapshots of a file for `--list-snapshots`, or says there are none
+fn print_snapshots(file: &str) -> ExitStatus {
+    let listed = paths::expand(file)
+        .map_err(io::Error::from)
+        .and_then(|path| snapshots::list(&path).and_then(|list| snapshots::format_list(&list)));
+    match listed {
+        Ok(listed) if listed.is_empty() => println!("No snapshots of {} yet; F9 takes one", file),
+        Ok(listed) => print!("{}", listed),
+        Err(e) => {
+            report(Level::Error, &format!("Error listing the snapshots of {}: {}", file, e));
+            return ExitStatus::File;
+        }
+    }
+    ExitStatus::Clean
+}
+
+/// Counts what a document holds. Unlike opening it to edit, a missing file
+/// isn't created, and only the kinds of file the opening screen lists are read.
+fn document_stats(path: &Path) -> Result<DocumentStats, String> {
+    let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    if let Some(extension) = extension.filter(|extension| !drafts::EXTENSIONS.contains(&extension.as_str())) {
+        return Err(format!(
+            "Can't count {}: unknown file format `.{}` (only .txt, .md and .typ are read)",
+            path.display(),
+            extension
+        ));
+    }
+    let loaded = std::fs::File::open(path).and_then(|file| Buffer::from_reader(file, Duration::ZERO));
+    let buffer = loaded.map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+    Ok(DocumentStats::count(&path.display().to_string(), &buffer))
+}
+
+/// Replays a text file at a steady pace and writes the typing as a GIF
+#[cfg(feature = "gif-export")]
+pub fn render_gif(output: &Path, source: &Path, reveal_rate: Duration) -> ExitStatus {
+    use crate::export;
+
+    let loaded = std::fs::File::open(source).and_then(|file| Buffer::from_reader(file, reveal_rate));
+    let mut buffer = match loaded {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            report(Level::Error, &format!("Error reading {}: {}", source.display(), e));
+            return ExitStatus::File;
+        }
+    };
+    buffer.split_long_lines(export::MAX_COLUMNS);
+    export::replay_timing(&mut buffer, Instant::now());
+    match export::export(&buffer, output) {
+        Ok(frames) => {
+            report(Level::Info, &format!("Wrote {} ({} frames)", output.display(), frames));
+            ExitStatus::Clean
+        }
+        Err(e) => {
+            report(Level::Error, &format!("Couldn't render {}: {}", output.display(), e));
+            ExitStatus::File
+        }
+    }
+}
+
+/// Stands in for GIF rendering in builds without it
+#[cfg(not(feature = "gif-export"))]
+pub fn render_gif(_output: &Path, _source: &Path, _reveal_rate: Duration) -> ExitStatus {
+    report(Level::Error, "This typewriter was built without GIF export; build it with --features gif-export");
+    ExitStatus::Terminal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+    use clap::Parser;
+
+    #[test]
+    /// Test that only the flags that print and exit run without a terminal
+    fn test_launch() {
+        let cli = |args: &[&str]| Cli::try_parse_from(["typewriter"].iter().chain(args)).unwrap();
+        assert_eq!(launch(&cli(&["notes.txt"]), true), Launch::Terminal);
+        assert_eq!(launch(&cli(&["notes.txt"]), false), Launch::NotATerminal);
+        assert_eq!(launch(&cli(&[]), false), Launch::NotATerminal);
+        assert_eq!(launch(&cli(&["--calibrate-audio"]), false), Launch::NotATerminal);
+        let headless: [&[&str]; 7] = [
+            &["--stats-only", "--json", "notes.txt"],
+            &["--list-snapshots", "notes.txt"],
+            &["--recent"],
+            &["--bench"],
+            &["--build-info"],
+            &["--write-default-config"],
+            &["--render-gif", "out.gif", "--from", "notes.txt"],
+        ];
+        for args in headless {
+            assert_eq!(launch(&cli(args), false), Launch::Headless);
+            assert_eq!(launch(&cli(args), true), Launch::Headless);
+        }
+    }
+
+    #[test]
+    /// Test that --stats-only counts text files, and refuses other formats and
+    /// missing files with the file exit status, creating nothing
+    fn test_document_stats() {
+        let dir = ScratchDir::new("stats");
+        let draft = dir.join("Draft.MD");
+        std::fs::write(&draft, "One two.\n\nThree").unwrap();
+        let stats = document_stats(&draft).unwrap();
+        assert_eq!((stats.words, stats.sentences, stats.paragraphs), (3, 2, 2));
+        assert_eq!(stats.file, draft.display().to_string());
+        assert_eq!(print_stats(&draft, true), ExitStatus::Clean);
+
+        let pdf = dir.join("draft.pdf");
+        std::fs::write(&pdf, "%PDF").unwrap();
+        let error = document_stats(&pdf).unwrap_err();
+        assert!(error.ends_with("unknown file format `.pdf` (only .txt, .md and .typ are read)"));
+        assert_eq!(print_stats(&pdf, false), ExitStatus::File);
+
+        let missing = dir.join("missing.txt");
+        assert!(document_stats(&missing).unwrap_err().starts_with("Error reading"));
+        assert_eq!(print_stats(&missing, false), ExitStatus::File);
+        assert!(!missing.exists());
+    }
+}
@@ -0,0 +1,273 @@
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// How long a closing editor waits for the save hook to finish
+pub const CLOSE_GRACE: Duration = Duration::from_secs(2);
+
+/// How a run of the save hook ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookOutcome {
+    /// The command exited with status 0
+    Succeeded,
+    /// The command exited with another status, or None if a signal killed it
+    Failed(Option<i32>),
+    /// The command couldn't be started or waited for
+    Error(String),
+}
+
+impl HookOutcome {
+    /// Returns the warning for the status row, or None if all went well
+    pub fn warning(&self) -> Option<String> {
+        match self {
+            HookOutcome::Succeeded => None,
+            HookOutcome::Failed(Some(code)) => Some(format!("Save hook failed (exit {})", code)),
+            HookOutcome::Failed(None) => Some("Save hook was killed".to_string()),
+            HookOutcome::Error(e) => Some(format!("Save hook couldn't run: {}", e)),
+        }
+    }
+}
+
+/// Runs a shell command after each save, one run at a time. Saving while the
+/// command is still running queues one more run, with the latest file and
+/// word count, rather than starting a second copy; further saves in the
+/// meantime just update that queued run.
+pub struct SaveHook {
+    /// The shell command to run
+    command: String,
+    /// Whether a run is in progress
+    running: bool,
+    /// The file and word count for the run queued behind the current one
+    queued: Option<(String, usize)>,
+    /// Where finished runs report back
+    sender: UnboundedSender<HookOutcome>,
+    receiver: UnboundedReceiver<HookOutcome>,
+}
+
+impl SaveHook {
+    /// Creates a hook for a shell command
+    pub fn new(command: String) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            command,
+            running: false,
+            queued: None,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Runs the command for a file that was just saved, or queues it if a run
+    /// is still going. What it prints is logged once it is done, so before
+    /// closing the editor waits for it with `settle`.
+    pub fn saved(&mut self, path: &str, words: usize) {
+        if self.running {
+            self.queued = Some((path.to_string(), words));
+            return;
+        }
+
+        self.running = true;
+        match self.spawn(path, words, true) {
+            Ok(child) => {
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    let _ = sender.send(wait(child).await);
+                });
+            }
+            Err(e) => {
+                let _ = self.sender.send(HookOutcome::Error(e.to_string()));
+            }
+        }
+    }
+
+    /// Waits for the current run to finish and starts the queued one, if any.
+    /// Waits forever while nothing is running. Safe to cancel.
+    pub async fn finished(&mut self) -> HookOutcome {
+        let outcome = match self.receiver.recv().await {
+            Some(outcome) => outcome,
+            // The hook holds a sender itself, so this can't happen
+            None => std::future::pending().await,
+        };
+        self.running = false;
+        if let Some((path, words)) = self.queued.take() {
+            self.saved(&path, words);
+        }
+        outcome
+    }
+
+    /// Runs the command for a file saved as the editor closes, such as by
+    /// autosaving on a signal. Nothing will be left to read what it prints, so
+    /// that is dropped and the run carries on by itself.
+    pub fn saved_on_exit(&self, path: &str, words: usize) {
+        if let Err(e) = self.spawn(path, words, false) {
+            log::warn!("save hook couldn't run: {}", e);
+        }
+    }
+
+    /// Waits up to `limit` for the current run and the one queued behind it
+    /// to finish, logging how they went. A queued run that can't be waited
+    /// for starts with its output dropped, so it isn't lost; a run still going
+    /// once the editor has closed may be cut off if it prints any more.
+    pub async fn settle(&mut self, limit: Duration) {
+        let deadline = tokio::time::Instant::now() + limit;
+        while self.running {
+            match tokio::time::timeout_at(deadline, self.finished()).await {
+                Ok(outcome) => match outcome.warning() {
+                    Some(warning) => log::warn!("{}", warning),
+                    None => log::info!("save hook finished"),
+                },
+                Err(_) => {
+                    log::warn!("save hook still running as the editor closes");
+                    break;
+                }
+            }
+        }
+        if let Some((path, words)) = self.queued.take() {
+            self.saved_on_exit(&path, words);
+        }
+    }
+
+    /// Starts the command with the file and word count in its environment,
+    /// its output piped back to be logged or else dropped
+    fn spawn(&self, path: &str, words: usize, piped: bool) -> std::io::Result<Child> {
+        log::info!("running save hook: {}", self.command);
+        let output = || if piped { Stdio::piped() } else { Stdio::null() };
+        Command::from(shell(&self.command))
+            .env("TYPEWRITER_FILE", path)
+            .env("TYPEWRITER_WORDS", words.to_string())
+            .stdin(Stdio::null())
+            .stdout(output())
+            .stderr(output())
+            .spawn()
+    }
+}
+
+/// Returns a command that runs a line through the platform's shell
+pub fn shell(command: &str) -> std::process::Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Waits for a run to end, logging what it printed
+async fn wait(child: Child) -> HookOutcome {
+    let output = match child.wait_with_output().await {
+        Ok(output) => output,
+        Err(e) => return HookOutcome::Error(e.to_string()),
+    };
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        log::info!("save hook: {}", line);
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        log::warn!("save hook: {}", line);
+    }
+    if output.status.success() {
+        HookOutcome::Succeeded
+    } else {
+        HookOutcome::Failed(output.status.code())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+    use std::fs;
+
+
+    #[tokio::test]
+    /// Test that the command sees the file and word count
+    async fn test_environment() {
+        let dir = ScratchDir::new("hooks-env");
+        let marker = dir.join("marker");
+        let command = format!("echo \"$TYPEWRITER_FILE $TYPEWRITER_WORDS\" > {}", marker.display());
+        let mut hook = SaveHook::new(command);
+
+        hook.saved("notes.txt", 42);
+        assert_eq!(hook.finished().await, HookOutcome::Succeeded);
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "notes.txt 42\n");
+    }
+
+    #[tokio::test]
+    /// Test that a failing command is reported with its exit code
+    async fn test_failure() {
+        let mut hook = SaveHook::new("echo oops >&2; exit 3".to_string());
+        hook.saved("notes.txt", 1);
+
+        let outcome = hook.finished().await;
+        assert_eq!(outcome, HookOutcome::Failed(Some(3)));
+        assert_eq!(outcome.warning().as_deref(), Some("Save hook failed (exit 3)"));
+    }
+
+    #[tokio::test]
+    /// Test that saves during a run are folded into one queued run
+    async fn test_overlapping_saves() {
+        let dir = ScratchDir::new("hooks-overlap");
+        let marker = dir.join("marker");
+        let command = format!("sleep 0.1; echo $TYPEWRITER_WORDS >> {}", marker.display());
+        let mut hook = SaveHook::new(command);
+
+        hook.saved("notes.txt", 1);
+        hook.saved("notes.txt", 2);
+        hook.saved("notes.txt", 3);
+        assert_eq!(hook.finished().await, HookOutcome::Succeeded);
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "1\n");
+        assert_eq!(hook.finished().await, HookOutcome::Succeeded);
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "1\n3\n");
+
+        // Nothing else was queued
+        let idle = tokio::time::timeout(std::time::Duration::from_millis(200), hook.finished()).await;
+        assert!(idle.is_err());
+    }
+
+    #[tokio::test]
+    /// Test that settling waits for the current run and the queued one, even
+    /// when they print
+    async fn test_settle() {
+        let dir = ScratchDir::new("hooks-settle");
+        let marker = dir.join("marker");
+        let command = format!("sleep 0.1; echo printed; echo $TYPEWRITER_WORDS >> {}", marker.display());
+        let mut hook = SaveHook::new(command);
+
+        hook.saved("notes.txt", 1);
+        hook.saved("notes.txt", 2);
+        hook.settle(CLOSE_GRACE).await;
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "1\n2\n");
+        assert!(!hook.running);
+    }
+
+    #[tokio::test]
+    /// Test that a queued run isn't lost when there's no time to wait for
+    /// the one before it, and that a run on exit goes ahead by itself
+    async fn test_settle_without_waiting() {
+        let dir = ScratchDir::new("hooks-settle-now");
+        let marker = dir.join("marker");
+        let command = format!("sleep 0.2; echo printed; echo $TYPEWRITER_WORDS >> {}", marker.display());
+        let mut hook = SaveHook::new(command);
+
+        hook.saved("notes.txt", 1);
+        hook.saved("notes.txt", 2);
+        hook.settle(Duration::ZERO).await;
+        hook.saved_on_exit("notes.txt", 3);
+        // The runs weren't waited for, so they are looked for until they are done
+        let read = || fs::read_to_string(&marker).unwrap_or_default();
+        for _ in 0..100 {
+            if read().lines().count() == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let mut written: Vec<String> = read().lines().map(str::to_string).collect();
+        written.sort();
+        assert_eq!(written, ["1", "2", "3"]);
+    }
+}
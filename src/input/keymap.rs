@@ -0,0 +1,409 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::InputEvent;
+
+/// Every action that can be bound to a key, with its config-file name and the
+/// description shown in the help overlay
+pub const ACTIONS: &[(&str, InputEvent, &str)] = &[
+    ("save", InputEvent::Save, "Save the file"),
+    ("save_as", InputEvent::SaveAs, "Save under a new name"),
+    ("close", InputEvent::Close, "Close, offering to save"),
+    ("abort", InputEvent::Abort, "Quit without saving"),
+    ("backspace", InputEvent::Backspace, "Step back to mark out text"),
+    ("newline", InputEvent::NewLine, "Start a new line"),
+    ("right", InputEvent::Right, "Move right"),
+    ("tab", InputEvent::Tab, "Move to the next tab stop"),
+    ("finish_correction", InputEvent::FinishCorrection, "Type after the marked-out text"),
+    ("undo", InputEvent::Undo, "Undo on the current line"),
+    ("left", InputEvent::Left, "Move left"),
+    ("up", InputEvent::Up, "Move up"),
+    ("down", InputEvent::Down, "Move down"),
+    ("menu", InputEvent::Escape, "Open the menu"),
+    ("toggle_sound", InputEvent::ToggleSound, "Turn sound on or off"),
+    ("toggle_caps", InputEvent::ToggleCaps, "Type in capitals, or stop"),
+    ("toggle_changes", InputEvent::ToggleChanges, "Underline changes since saving"),
+    ("set_margin", InputEvent::SetMargin, "Set the right margin"),
+    ("set_reveal_rate", InputEvent::SetRevealRate, "Choose the reveal delay"),
+    ("reveal_faster", InputEvent::RevealFaster, "Show typed text sooner"),
+    ("reveal_slower", InputEvent::RevealSlower, "Show typed text later"),
+    ("help", InputEvent::Help, "Show this help"),
+    ("message_log", InputEvent::MessageLog, "Show recent messages"),
+    ("redraw", InputEvent::Redraw, "Repaint the screen"),
+    ("record_macro", InputEvent::RecordMacro, "Start or stop recording a macro"),
+    ("play_macro", InputEvent::PlayMacro, "Replay the recorded macro"),
+    ("copy_all", InputEvent::CopyAll, "Copy the text to the clipboard"),
+    ("snapshot", InputEvent::Snapshot, "Keep a snapshot of the text"),
+    ("print", InputEvent::Print, "Print the text"),
+];
+
+/// Bindings used when the config file doesn't override them
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("ctrl+s", "save"),
+    ("f2", "save"),
+    ("ctrl+shift+s", "save_as"),
+    ("alt+s", "save_as"),
+    ("ctrl+x", "close"),
+    ("ctrl+c", "close"),
+    ("f10", "close"),
+    ("ctrl+q", "abort"),
+    ("backspace", "backspace"),
+    ("enter", "newline"),
+    ("right", "right"),
+    ("tab", "tab"),
+    ("ctrl+e", "finish_correction"),
+    ("end", "finish_correction"),
+    ("ctrl+z", "undo"),
+    ("left", "left"),
+    ("up", "up"),
+    ("down", "down"),
+    ("esc", "menu"),
+    ("ctrl+shift+u", "toggle_caps"),
+    ("alt+u", "toggle_caps"),
+    ("ctrl+shift+d", "toggle_changes"),
+    ("alt+d", "toggle_changes"),
+    ("ctrl+shift+m", "set_margin"),
+    ("alt+m", "set_margin"),
+    ("ctrl+up", "reveal_faster"),
+    ("f7", "reveal_faster"),
+    ("ctrl+down", "reveal_slower"),
+    ("f8", "reveal_slower"),
+    ("f1", "help"),
+    ("ctrl+g", "help"),
+    ("ctrl+shift+l", "message_log"),
+    ("alt+l", "message_log"),
+    ("ctrl+l", "redraw"),
+    ("ctrl+shift+r", "record_macro"),
+    ("alt+r", "record_macro"),
+    ("ctrl+shift+p", "play_macro"),
+    ("alt+p", "play_macro"),
+    ("ctrl+shift+c", "copy_all"),
+    ("alt+c", "copy_all"),
+    ("f9", "snapshot"),
+];
+
+/// A key together with the modifiers held while pressing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    /// The key itself, with letters stored in lowercase
+    pub code: KeyCode,
+    /// Modifiers that must be held
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    /// Creates a chord, normalizing letters to lowercase
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let code = match code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            other => other,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Parses a description such as "ctrl+s", "F2" or "Shift+Alt+Enter".
+    /// Names are case-insensitive and modifiers may come in any order.
+    pub fn parse(description: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = description.split('+').map(str::trim).collect();
+        let (key, modifier_names) = parts
+            .split_last()
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| format!("invalid key chord `{}`", description))?;
+
+        let mut modifiers = KeyModifiers::empty();
+        for name in modifier_names {
+            modifiers |= match name.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "option" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                "super" | "cmd" => KeyModifiers::SUPER,
+                _ => return Err(format!("unknown modifier `{}` in `{}`", name, description)),
+            };
+        }
+
+        let code = parse_key_name(key)
+            .ok_or_else(|| format!("unknown key `{}` in `{}`", key, description))?;
+        Ok(Self::new(code, modifiers))
+    }
+
+    /// Returns the chord a key event corresponds to
+    pub fn from_event(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    /// Formats the chord the way the help screen shows it, e.g. "Ctrl+Shift+S"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modifiers = [
+            (KeyModifiers::CONTROL, "Ctrl"),
+            (KeyModifiers::ALT, "Alt"),
+            (KeyModifiers::SHIFT, "Shift"),
+            (KeyModifiers::SUPER, "Super"),
+        ];
+        for (modifier, name) in modifiers {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char('+') => write!(f, "Plus"),
+            KeyCode::Char(c) => write!(f, "{}", c.to_uppercase()),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::F(number) => write!(f, "F{}", number),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// Parses the name of a single key (without modifiers)
+fn parse_key_name(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    let lower = name.to_lowercase();
+    let code = match lower.as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "backspace" => KeyCode::Backspace,
+        "tab" => KeyCode::Tab,
+        "esc" | "escape" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "plus" => KeyCode::Char('+'),
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "insert" => KeyCode::Insert,
+        "delete" | "del" => KeyCode::Delete,
+        _ => {
+            let number = lower.strip_prefix('f')?.parse().ok()?;
+            if !(1..=24).contains(&number) {
+                return None;
+            }
+            KeyCode::F(number)
+        }
+    };
+    Some(code)
+}
+
+/// Looks up the action for a config-file action name
+pub fn action_from_name(name: &str) -> Option<InputEvent> {
+    ACTIONS
+        .iter()
+        .find(|(action, _, _)| *action == name)
+        .map(|(_, event, _)| event.clone())
+}
+
+/// Maps key chords to the actions they trigger
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: HashMap<KeyChord, InputEvent>,
+}
+
+impl KeyMap {
+    /// Creates a key map without any bindings
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Returns the action bound to a chord
+    pub fn get(&self, chord: &KeyChord) -> Option<&InputEvent> {
+        self.bindings.get(chord)
+    }
+
+    /// Binds a chord to an action, replacing whatever the chord did before
+    pub fn bind(&mut self, chord: KeyChord, action: InputEvent) {
+        self.bindings.insert(chord, action);
+    }
+
+    /// Returns the chords bound to an action, sorted by how they are displayed
+    pub fn chords_for(&self, action: &InputEvent) -> Vec<KeyChord> {
+        let mut chords: Vec<KeyChord> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(chord, _)| *chord)
+            .collect();
+        chords.sort_by_key(|chord| chord.to_string());
+        chords
+    }
+
+    /// Removes every chord bound to an action
+    pub fn unbind(&mut self, action: &InputEvent) {
+        self.bindings.retain(|_, bound| bound != action);
+    }
+
+    /// Rebinds a named action to the given chord descriptions. The action loses its
+    /// previous chords, and the new chords are taken over from any other action.
+    pub fn rebind(&mut self, action_name: &str, chords: &[&str]) -> Result<(), String> {
+        let action = action_from_name(action_name)
+            .ok_or_else(|| format!("unknown action `{}`", action_name))?;
+        let chords = chords
+            .iter()
+            .map(|chord| KeyChord::parse(chord))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.bindings.retain(|_, bound| *bound != action);
+        for chord in chords {
+            self.bind(chord, action.clone());
+        }
+        Ok(())
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let mut keymap = Self::empty();
+        for (chord, action) in DEFAULT_BINDINGS {
+            let chord = KeyChord::parse(chord).expect("default chord should parse");
+            let action = action_from_name(action).expect("default action should exist");
+            keymap.bind(chord, action);
+        }
+        keymap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test parsing chords with and without modifiers
+    fn test_parse_chord() {
+        let chord = KeyChord::parse("ctrl+s").unwrap();
+        assert_eq!(chord, KeyChord::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+
+        let chord = KeyChord::parse("f2").unwrap();
+        assert_eq!(chord, KeyChord::new(KeyCode::F(2), KeyModifiers::empty()));
+
+        let chord = KeyChord::parse("alt+enter").unwrap();
+        assert_eq!(chord, KeyChord::new(KeyCode::Enter, KeyModifiers::ALT));
+    }
+
+    #[test]
+    /// Test that chord names are case-insensitive and modifiers unordered
+    fn test_parse_chord_normalization() {
+        let expected = KeyChord::new(KeyCode::Char('s'), KeyModifiers::CONTROL | KeyModifiers::SHIFT);
+        assert_eq!(KeyChord::parse("Ctrl+Shift+S").unwrap(), expected);
+        assert_eq!(KeyChord::parse("shift+CTRL+s").unwrap(), expected);
+        assert_eq!(KeyChord::parse(" ctrl + shift + s ").unwrap(), expected);
+        assert_eq!(KeyChord::parse("F10").unwrap(), KeyChord::parse("f10").unwrap());
+    }
+
+    #[test]
+    /// Test that malformed chords are rejected
+    fn test_parse_chord_errors() {
+        assert!(KeyChord::parse("").is_err());
+        assert!(KeyChord::parse("ctrl+").is_err());
+        assert!(KeyChord::parse("hyper+s").is_err());
+        assert!(KeyChord::parse("ctrl+banana").is_err());
+        assert!(KeyChord::parse("f0").is_err());
+        assert!(KeyChord::parse("f99").is_err());
+    }
+
+    #[test]
+    /// Test the default bindings
+    fn test_default_bindings() {
+        let keymap = KeyMap::default();
+        let save = KeyChord::parse("ctrl+s").unwrap();
+        let close = KeyChord::parse("ctrl+x").unwrap();
+        assert_eq!(keymap.get(&save), Some(&InputEvent::Save));
+        assert_eq!(keymap.get(&close), Some(&InputEvent::Close));
+        for chord in ["ctrl+e", "end"] {
+            assert_eq!(keymap.get(&KeyChord::parse(chord).unwrap()), Some(&InputEvent::FinishCorrection));
+        }
+        for (chord, event) in [
+            ("ctrl+up", InputEvent::RevealFaster),
+            ("f7", InputEvent::RevealFaster),
+            ("ctrl+down", InputEvent::RevealSlower),
+            ("f8", InputEvent::RevealSlower),
+            ("ctrl+z", InputEvent::Undo),
+        ] {
+            assert_eq!(keymap.get(&KeyChord::parse(chord).unwrap()), Some(&event));
+        }
+    }
+
+    #[test]
+    /// Test how chords are displayed
+    fn test_display_chord() {
+        let display = |chord: &str| KeyChord::parse(chord).unwrap().to_string();
+        assert_eq!(display("ctrl+s"), "Ctrl+S");
+        assert_eq!(display("shift+ctrl+s"), "Ctrl+Shift+S");
+        assert_eq!(display("f10"), "F10");
+        assert_eq!(display("esc"), "Esc");
+        assert_eq!(display("alt+space"), "Alt+Space");
+        assert_eq!(display("enter"), "Enter");
+        assert_eq!(display("pagedown"), "PageDown");
+    }
+
+    #[test]
+    /// Test listing the chords bound to an action
+    fn test_chords_for() {
+        let keymap = KeyMap::default();
+        let chords: Vec<String> = keymap
+            .chords_for(&InputEvent::Save)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(chords, vec!["Ctrl+S", "F2"]);
+        assert!(keymap.chords_for(&InputEvent::ToggleSound).is_empty());
+    }
+
+    #[test]
+    /// Test rebinding Save to Ctrl+W
+    fn test_rebind_save() {
+        let mut keymap = KeyMap::default();
+        keymap.rebind("save", &["ctrl+w"]).unwrap();
+
+        let ctrl_w = KeyChord::parse("ctrl+w").unwrap();
+        let ctrl_s = KeyChord::parse("ctrl+s").unwrap();
+        assert_eq!(keymap.get(&ctrl_w), Some(&InputEvent::Save));
+        assert_eq!(keymap.get(&ctrl_s), None);
+
+        // Other bindings are untouched
+        let ctrl_x = KeyChord::parse("ctrl+x").unwrap();
+        assert_eq!(keymap.get(&ctrl_x), Some(&InputEvent::Close));
+    }
+
+    #[test]
+    /// Test that a rebound chord is taken over from its previous action
+    fn test_rebind_steals_chord() {
+        let mut keymap = KeyMap::default();
+        keymap.rebind("save", &["ctrl+x"]).unwrap();
+
+        let ctrl_x = KeyChord::parse("ctrl+x").unwrap();
+        assert_eq!(keymap.get(&ctrl_x), Some(&InputEvent::Save));
+    }
+
+    #[test]
+    /// Test that unbinding an action frees all its chords
+    fn test_unbind() {
+        let mut keymap = KeyMap::default();
+        keymap.unbind(&InputEvent::FinishCorrection);
+        assert!(keymap.chords_for(&InputEvent::FinishCorrection).is_empty());
+        assert_eq!(keymap.get(&KeyChord::parse("end").unwrap()), None);
+        assert_eq!(keymap.get(&KeyChord::parse("ctrl+s").unwrap()), Some(&InputEvent::Save));
+    }
+
+    #[test]
+    /// Test that unknown actions and chords are reported
+    fn test_rebind_errors() {
+        let mut keymap = KeyMap::default();
+        assert_eq!(keymap.rebind("sav", &["ctrl+w"]), Err("unknown action `sav`".to_string()));
+        assert!(keymap.rebind("save", &["ctrl+nope"]).is_err());
+
+        // A failed rebind leaves the defaults in place
+        let ctrl_s = KeyChord::parse("ctrl+s").unwrap();
+        assert_eq!(keymap.get(&ctrl_s), Some(&InputEvent::Save));
+    }
+}
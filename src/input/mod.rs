@@ -1,8 +1,24 @@
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+mod keymap;
+pub use keymap::*;
+
+use crossterm::event::{
+    Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use std::io;
 use std::time::Duration;
+use tokio_stream::StreamExt;
+
+use crate::editor::{self, EditorEvent};
+
+/// Fewest characters arriving together that are taken for a paste when the
+/// terminal doesn't mark pastes out. Nobody types this many between two reads.
+const BURST_CHARS: usize = 32;
+
+/// How long such a paste may pause between chunks and still count as one
+const BURST_GAP: Duration = Duration::from_millis(5);
 
 /// Represents different types of input events our editor can handle
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum InputEvent {
     /// A regular character was typed
     Char(char),
@@ -12,53 +28,364 @@ pub enum InputEvent {
     NewLine,
     /// Right arrow was pressed
     Right,
-    /// Save command (Ctrl+S)
+    /// Left arrow was pressed (only used in prompts)
+    Left,
+    /// Up arrow was pressed
+    Up,
+    /// Down arrow was pressed
+    Down,
+    /// Tab was pressed, moving on to the next tab stop
+    Tab,
+    /// Escape was pressed (opens the menu)
+    Escape,
+    /// Save command (Ctrl+S or F2)
     Save,
-    /// Close command (Ctrl+X)
+    /// Save under a new name (Ctrl+Shift+S with keyboard enhancement, Alt+S otherwise)
+    SaveAs,
+    /// Close command (Ctrl+X, Ctrl+C or F10)
     Close,
-    /// No event occurred within timeout
-    Timeout,
+    /// Quit without saving, after confirmation (Ctrl+Q)
+    Abort,
+    /// Turn typewriter sounds on or off
+    ToggleSound,
+    /// Turn typing in capitals on or off (Ctrl+Shift+U or Alt+U)
+    ToggleCaps,
+    /// Turn underlining the changes since the last save on or off (Ctrl+Shift+D or Alt+D)
+    ToggleChanges,
+    /// Set the right margin along a ruler (Ctrl+Shift+M or Alt+M)
+    SetMargin,
+    /// Choose the reveal delay by watching a line type itself (from the menu)
+    SetRevealRate,
+    /// Show the key bindings (F1 or Ctrl+G)
+    Help,
+    /// Show the recent status messages (Ctrl+Shift+L or Alt+L)
+    MessageLog,
+    /// Repaint the whole screen (Ctrl+L)
+    Redraw,
+    /// Start or stop recording a macro (Ctrl+Shift+R or Alt+R)
+    RecordMacro,
+    /// Replay the recorded macro (Ctrl+Shift+P or Alt+P)
+    PlayMacro,
+    /// Copy the whole text to the clipboard (Ctrl+Shift+C or Alt+C)
+    CopyAll,
+    /// Keep a snapshot of the text beside the file (F9)
+    Snapshot,
+    /// Send the text to the printer (from the menu)
+    Print,
+    /// Jump past the marked-out text to type its replacement (Ctrl+E or End)
+    FinishCorrection,
+    /// Take back the latest change on the line being typed, with --soft-undo (Ctrl+Z)
+    Undo,
+    /// Make typed characters appear sooner (Ctrl+Up or F7)
+    RevealFaster,
+    /// Make typed characters appear later (Ctrl+Down or F8)
+    RevealSlower,
+    /// The left mouse button was pressed at a (column, row) of the screen
+    Click(u16, u16),
+    /// The mouse wheel turned up
+    ScrollUp,
+    /// The mouse wheel turned down
+    ScrollDown,
+    /// The terminal window came into focus
+    FocusGained,
+    /// The terminal window lost focus
+    FocusLost,
+    /// The terminal window changed size, to (columns, rows)
+    Resize(u16, u16),
+    /// Text was pasted into the terminal
+    Paste(String),
+    /// A key that isn't bound to anything
+    Unknown(KeyEvent),
+    /// Something the editor doesn't react to, such as a key release
+    Ignored,
+}
+
+impl InputEvent {
+    /// Returns the edits this event makes to the buffer: one for a key, one
+    /// for each character of a paste, or none
+    pub fn editor_events(&self) -> Vec<EditorEvent> {
+        match *self {
+            InputEvent::Char(c) => vec![EditorEvent::Char(c)],
+            InputEvent::Backspace => vec![EditorEvent::Backspace],
+            InputEvent::NewLine => vec![EditorEvent::NewLine],
+            InputEvent::Right => vec![EditorEvent::Right],
+            InputEvent::Down => vec![EditorEvent::Down],
+            InputEvent::Tab => vec![EditorEvent::Tab],
+            InputEvent::FinishCorrection => vec![EditorEvent::FinishCorrection],
+            InputEvent::Undo => vec![EditorEvent::Undo],
+            InputEvent::Paste(ref text) => EditorEvent::typing(text).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Somewhere terminal events come from
+pub trait EventSource {
+    /// Waits for the next event. Dropping the wait part way loses nothing.
+    async fn next(&mut self) -> io::Result<Event>;
+}
+
+/// Reads events from the terminal through crossterm's async event stream
+#[derive(Default)]
+pub struct CrosstermSource {
+    /// Opened on the first read so tests never touch the tty
+    events: Option<EventStream>,
+}
+
+impl EventSource for CrosstermSource {
+    async fn next(&mut self) -> io::Result<Event> {
+        let events = self.events.get_or_insert_with(EventStream::new);
+        match events.next().await {
+            Some(event) => event,
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "terminal input closed")),
+        }
+    }
 }
 
 /// Handles keyboard input events
-pub struct InputHandler {
-    /// How long to wait for input before timing out
+pub struct InputHandler<S = CrosstermSource> {
+    /// How long the event loop goes without input before waking anyway, to
+    /// update timers such as the sprint countdown
     timeout: Duration,
+    /// Whether the terminal reports disambiguated chords (kitty keyboard protocol)
+    keyboard_enhanced: bool,
+    /// Which chords trigger which actions
+    keymap: KeyMap,
+    /// Where the events are read from
+    source: S,
 }
 
 impl InputHandler {
-    /// Creates a new input handler with the specified timeout
+    /// Creates a new input handler with the specified timeout and default bindings
     pub fn new(timeout: Duration) -> Self {
-        Self { timeout }
+        Self::with_source(timeout, CrosstermSource::default())
     }
+}
 
-    /// Reads the next input event, waiting up to timeout duration
-    pub async fn next_event(&self) -> std::io::Result<InputEvent> {
-        if event::poll(self.timeout)? {
-            if let Event::Key(key) = event::read()? {
-                Ok(self.handle_key_event(key))
-            } else {
-                Ok(InputEvent::Timeout)
+impl<S: EventSource> InputHandler<S> {
+    /// Creates an input handler reading from the given source, with default bindings
+    pub fn with_source(timeout: Duration, source: S) -> Self {
+        Self {
+            timeout,
+            keyboard_enhanced: false,
+            keymap: KeyMap::default(),
+            source,
+        }
+    }
+
+    /// Replaces the default bindings with the given key map
+    pub fn with_keymap(mut self, keymap: KeyMap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    /// Returns the bindings in use
+    pub fn keymap(&self) -> &KeyMap {
+        &self.keymap
+    }
+
+    /// Returns how long the event loop goes without input before waking anyway
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Tells the handler whether keyboard enhancement is active, which makes
+    /// chords like Ctrl+Shift+S distinguishable from Ctrl+S
+    pub fn set_keyboard_enhanced(&mut self, enabled: bool) {
+        self.keyboard_enhanced = enabled;
+    }
+
+    /// Waits for the next input event without blocking the runtime. Dropping
+    /// the wait, as `select!` does when something else is ready first, loses
+    /// no input.
+    pub async fn next_event(&mut self) -> io::Result<InputEvent> {
+        let event = self.source.next().await?;
+        Ok(self.translate(event))
+    }
+
+    /// Returns `first` along with the events that have already arrived after
+    /// it, without waiting for more. Ignored events are left out. Where the
+    /// terminal doesn't mark pastes out, a run of characters too long to have
+    /// been typed is a paste: the rest of it is waited for, and it comes back
+    /// as one Paste event.
+    pub async fn burst(&mut self, first: InputEvent) -> io::Result<Vec<InputEvent>> {
+        let mut events = vec![first];
+        self.read_until_quiet(&mut events, Duration::ZERO).await?;
+        if typed_runs(&events).any(|run| run.len() >= BURST_CHARS) {
+            self.read_until_quiet(&mut events, BURST_GAP).await?;
+        }
+        Ok(join_pasted_runs(events))
+    }
+
+    /// Reads events until none arrives within `gap`
+    async fn read_until_quiet(&mut self, events: &mut Vec<InputEvent>, gap: Duration) -> io::Result<()> {
+        while let Ok(event) = tokio::time::timeout(gap, self.source.next()).await {
+            let event = self.translate(event?);
+            if event != InputEvent::Ignored {
+                events.push(event);
             }
-        } else {
-            Ok(InputEvent::Timeout)
+        }
+        Ok(())
+    }
+
+    /// Converts a terminal event into our InputEvent enum
+    fn translate(&self, event: Event) -> InputEvent {
+        match event {
+            Event::Key(key) => self.handle_key_event(key),
+            Event::Mouse(mouse) => Self::handle_mouse_event(mouse),
+            Event::FocusGained => InputEvent::FocusGained,
+            Event::FocusLost => InputEvent::FocusLost,
+            Event::Resize(columns, rows) => InputEvent::Resize(columns, rows),
+            Event::Paste(text) => InputEvent::Paste(text),
+        }
+    }
+
+    /// Converts a mouse event into our InputEvent enum
+    fn handle_mouse_event(mouse: MouseEvent) -> InputEvent {
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => InputEvent::Click(mouse.column, mouse.row),
+            MouseEventKind::ScrollUp => InputEvent::ScrollUp,
+            MouseEventKind::ScrollDown => InputEvent::ScrollDown,
+            _ => InputEvent::Ignored,
         }
     }
 
     /// Converts a key event into our InputEvent enum
     fn handle_key_event(&self, key: KeyEvent) -> InputEvent {
+        // Some platforms (Windows) report releases as well as presses. Only presses
+        // and auto-repeats type, so holding a key down still repeats it.
+        if key.kind == KeyEventKind::Release {
+            return InputEvent::Ignored;
+        }
+
+        // Without keyboard enhancement a held Shift can't be told apart reliably
+        // on control chords, so an unbound Ctrl+Shift chord is taken as its
+        // plain Ctrl one. A bound one keeps its Shift, so Ctrl+Shift+C copies
+        // rather than closing as Ctrl+C would.
+        let chord = KeyChord::from_event(&key);
+        let unshifted = || {
+            let mut chord = chord;
+            chord.modifiers.remove(KeyModifiers::SHIFT);
+            chord
+        };
+        let relaxed = !self.keyboard_enhanced && chord.modifiers.contains(KeyModifiers::CONTROL);
+        let bound = self.keymap.get(&chord).or_else(|| relaxed.then(|| self.keymap.get(&unshifted())).flatten());
+        if let Some(event) = bound {
+            return event.clone();
+        }
+
+        // Unbound Ctrl and Alt chords don't type their letter. Both together is
+        // how Windows reports AltGr, which does type.
+        let alt_gr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        let command = key.modifiers.intersection(alt_gr);
         match key.code {
-            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                InputEvent::Save
+            // Typed, it would only shift the columns and click for nothing
+            KeyCode::Char(c) if editor::is_invisible(c) => {
+                log::debug!("invisible character U+{:04X} left out", c as u32);
+                InputEvent::Ignored
             }
-            KeyCode::Char('x') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                InputEvent::Close
+            KeyCode::Char(c) if command.is_empty() || command == alt_gr => InputEvent::Char(c),
+            _ => InputEvent::Unknown(key),
+        }
+    }
+}
+
+/// Returns whether an event types something, as the keys of a paste do
+fn is_typed(event: &InputEvent) -> bool {
+    matches!(event, InputEvent::Char(_) | InputEvent::NewLine)
+}
+
+/// Splits events into runs of typed characters and whatever comes between them
+fn typed_runs(events: &[InputEvent]) -> impl Iterator<Item = &[InputEvent]> {
+    events.chunk_by(|a, b| is_typed(a) == is_typed(b)).filter(|run| is_typed(&run[0]))
+}
+
+/// Turns each run of at least `BURST_CHARS` typed characters into one Paste.
+/// Shorter runs are left as they are.
+fn join_pasted_runs(events: Vec<InputEvent>) -> Vec<InputEvent> {
+    let mut joined = Vec::with_capacity(events.len());
+    for run in events.chunk_by(|a, b| is_typed(a) == is_typed(b)) {
+        if is_typed(&run[0]) && run.len() >= BURST_CHARS {
+            let text = run
+                .iter()
+                .map(|event| match event {
+                    InputEvent::Char(c) => *c,
+                    _ => '\n',
+                })
+                .collect();
+            joined.push(InputEvent::Paste(text));
+        } else {
+            joined.extend_from_slice(run);
+        }
+    }
+    joined
+}
+
+/// One step of a script
+#[cfg(test)]
+enum Step {
+    /// An event, or None for a moment where nothing is waiting
+    Event(Option<Event>),
+    /// Nothing for this long
+    Wait(Duration),
+    /// Nothing until then: a wait once its first read has started
+    Until(tokio::time::Instant),
+}
+
+/// Replays a fixed list of events, for tests. A None entry stands for a moment
+/// where nothing is waiting: the read that comes to it waits until it is given
+/// up on, which ends a burst of input. Once the script runs out the source
+/// stays idle, like a terminal nobody types into.
+#[cfg(test)]
+pub struct ScriptedSource {
+    steps: std::collections::VecDeque<Step>,
+}
+
+#[cfg(test)]
+impl ScriptedSource {
+    /// Creates a source that plays back the given events in order
+    pub fn new(events: impl IntoIterator<Item = Option<Event>>) -> Self {
+        Self {
+            steps: events.into_iter().map(Step::Event).collect(),
+        }
+    }
+
+    /// Adds a pause after the events so far, during which nothing comes
+    pub fn wait(mut self, delay: Duration) -> Self {
+        self.steps.push_back(Step::Wait(delay));
+        self
+    }
+
+    /// Adds more events to play back after the ones so far
+    pub fn then(mut self, events: impl IntoIterator<Item = Option<Event>>) -> Self {
+        self.steps.extend(events.into_iter().map(Step::Event));
+        self
+    }
+
+    /// Turns a string into key presses, one per character
+    pub fn keys(text: &str) -> impl Iterator<Item = Option<Event>> + '_ {
+        text.chars()
+            .map(|c| Some(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::empty()))))
+    }
+}
+
+#[cfg(test)]
+impl EventSource for ScriptedSource {
+    async fn next(&mut self) -> io::Result<Event> {
+        loop {
+            match self.steps.pop_front() {
+                Some(Step::Event(Some(event))) => return Ok(event),
+                Some(Step::Event(None)) | None => return std::future::pending().await,
+                Some(Step::Wait(delay)) => self.steps.push_front(Step::Until(tokio::time::Instant::now() + delay)),
+                Some(Step::Until(deadline)) => {
+                    // Left in the script until it is over, as a deadline rather
+                    // than what is left of it, so a read dropped part way, when
+                    // the event loop wakes for something else, doesn't cut it short
+                    self.steps.push_front(Step::Until(deadline));
+                    tokio::time::sleep_until(deadline).await;
+                    self.steps.pop_front();
+                }
             }
-            KeyCode::Char(c) => InputEvent::Char(c),
-            KeyCode::Backspace => InputEvent::Backspace,
-            KeyCode::Enter => InputEvent::NewLine,
-            KeyCode::Right => InputEvent::Right,
-            _ => InputEvent::Timeout,
         }
     }
 }
@@ -96,6 +423,16 @@ mod tests {
         let key = KeyEvent::new(KeyCode::Right, KeyModifiers::empty());
         assert_eq!(handler.handle_key_event(key), InputEvent::Right);
 
+        // Test left/up/down arrows and escape
+        let key = KeyEvent::new(KeyCode::Left, KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Left);
+        let key = KeyEvent::new(KeyCode::Up, KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Up);
+        let key = KeyEvent::new(KeyCode::Down, KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Down);
+        let key = KeyEvent::new(KeyCode::Esc, KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Escape);
+
         // Test save (Ctrl+S)
         let key = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
         assert_eq!(handler.handle_key_event(key), InputEvent::Save);
@@ -104,4 +441,245 @@ mod tests {
         let key = KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL);
         assert_eq!(handler.handle_key_event(key), InputEvent::Close);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    /// Test that only presses and repeats are translated, not releases
+    fn test_key_event_kinds() {
+        let handler = InputHandler::new(Duration::from_millis(100));
+
+        let key = KeyEvent::new_with_kind(KeyCode::Char('a'), KeyModifiers::empty(), KeyEventKind::Press);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Char('a'));
+
+        let key = KeyEvent::new_with_kind(KeyCode::Char('a'), KeyModifiers::empty(), KeyEventKind::Repeat);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Char('a'));
+
+        let key = KeyEvent::new_with_kind(KeyCode::Char('a'), KeyModifiers::empty(), KeyEventKind::Release);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Ignored);
+
+        // Releasing a command chord must not trigger it a second time
+        let key = KeyEvent::new_with_kind(KeyCode::Char('s'), KeyModifiers::CONTROL, KeyEventKind::Release);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Ignored);
+    }
+
+    #[test]
+    /// Test that invisible characters sent as keys are left out, while
+    /// combining accents still type
+    fn test_invisible_keys() {
+        let handler = InputHandler::new(Duration::from_millis(100));
+        for c in ['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}', '\u{202E}'] {
+            let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::empty());
+            assert_eq!(handler.handle_key_event(key), InputEvent::Ignored);
+        }
+        let key = KeyEvent::new(KeyCode::Char('\u{301}'), KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Char('\u{301}'));
+
+        // Nor are they pasted
+        let paste = InputEvent::Paste("zero\u{200B}width".to_string());
+        assert_eq!(paste.editor_events().len(), 9);
+    }
+
+    #[test]
+    /// Test that Ctrl+Shift+S saves under a new name wherever the Shift is
+    /// reported, and that Alt+S does everywhere
+    fn test_save_as_chords() {
+        let mut handler = InputHandler::new(Duration::from_millis(100));
+        let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+
+        let key = KeyEvent::new(KeyCode::Char('s'), ctrl_shift);
+        assert_eq!(handler.handle_key_event(key), InputEvent::SaveAs);
+
+        // The fallback chord works everywhere
+        let key = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::ALT);
+        assert_eq!(handler.handle_key_event(key), InputEvent::SaveAs);
+
+        handler.set_keyboard_enhanced(true);
+        let key = KeyEvent::new(KeyCode::Char('s'), ctrl_shift);
+        assert_eq!(handler.handle_key_event(key), InputEvent::SaveAs);
+        let key = KeyEvent::new(KeyCode::Char('S'), ctrl_shift);
+        assert_eq!(handler.handle_key_event(key), InputEvent::SaveAs);
+
+        // Plain Ctrl+S is unaffected by enhancement
+        let key = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Save);
+    }
+
+    #[test]
+    /// Test that without keyboard enhancement a bound Ctrl+Shift chord keeps
+    /// its Shift, so Ctrl+Shift+C copies instead of closing, while an unbound
+    /// one falls back to its plain Ctrl chord
+    fn test_shifted_chords_without_enhancement() {
+        let handler = InputHandler::new(Duration::from_millis(100));
+        let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+        for c in ['c', 'C'] {
+            assert_eq!(handler.handle_key_event(KeyEvent::new(KeyCode::Char(c), ctrl_shift)), InputEvent::CopyAll);
+        }
+        let key = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Close);
+        let key = KeyEvent::new(KeyCode::Char('x'), ctrl_shift);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Close);
+    }
+
+    #[test]
+    /// Test that the handler uses a custom key map
+    fn test_custom_keymap() {
+        let mut keymap = KeyMap::default();
+        keymap.rebind("save", &["ctrl+w"]).unwrap();
+        let handler = InputHandler::new(Duration::from_millis(100)).with_keymap(keymap);
+
+        let key = KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Save);
+
+        // The old chord no longer saves
+        let key = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Unknown(key));
+    }
+
+    #[test]
+    /// Test the function key alternatives for chords lost to flow control
+    fn test_alternate_save_and_close() {
+        let handler = InputHandler::new(Duration::from_millis(100));
+
+        let key = KeyEvent::new(KeyCode::F(2), KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Save);
+
+        let key = KeyEvent::new(KeyCode::F(10), KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Close);
+    }
+
+    #[test]
+    /// Test that Ctrl+Q asks to quit without saving
+    fn test_abort_chord() {
+        let handler = InputHandler::new(Duration::from_millis(100));
+        let key = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Abort);
+    }
+
+    #[test]
+    /// Test that F1 and Ctrl+G open the help
+    fn test_help_chords() {
+        let handler = InputHandler::new(Duration::from_millis(100));
+        let key = KeyEvent::new(KeyCode::F(1), KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Help);
+
+        let key = KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Help);
+    }
+
+    #[test]
+    /// Test that Ctrl+L asks for a redraw
+    fn test_redraw_chord() {
+        let handler = InputHandler::new(Duration::from_millis(100));
+        let key = KeyEvent::new(KeyCode::Char('l'), KeyModifiers::CONTROL);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Redraw);
+    }
+
+    #[tokio::test]
+    /// Test that events already waiting are collected together
+    async fn test_burst() {
+        let release = KeyEvent::new_with_kind(KeyCode::Char('c'), KeyModifiers::empty(), KeyEventKind::Release);
+        let events = ScriptedSource::keys("ab")
+            .chain([Some(Event::Key(release))])
+            .chain([None])
+            .chain(ScriptedSource::keys("d"));
+        let mut handler = InputHandler::with_source(Duration::from_millis(100), ScriptedSource::new(events));
+
+        // The release does nothing, and the gap ends the burst
+        let first = handler.next_event().await.unwrap();
+        assert_eq!(handler.burst(first).await.unwrap(), vec![InputEvent::Char('a'), InputEvent::Char('b')]);
+        assert_eq!(handler.next_event().await.unwrap(), InputEvent::Char('d'));
+        // Then nothing comes, however long the read waits
+        assert!(tokio::time::timeout(Duration::from_millis(20), handler.next_event()).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Test that nothing comes during a pause in a script, and that reads given
+    /// up on part way through it don't cut it short
+    async fn test_scripted_wait() {
+        let mut source = ScriptedSource::new(ScriptedSource::keys("a"))
+            .wait(Duration::from_millis(250))
+            .then(ScriptedSource::keys("b"));
+        let started = tokio::time::Instant::now();
+        assert!(source.next().await.is_ok());
+        for _ in 0..2 {
+            assert!(tokio::time::timeout(Duration::from_millis(100), source.next()).await.is_err());
+        }
+        assert_eq!(
+            source.next().await.unwrap(),
+            Event::Key(KeyEvent::new(KeyCode::Char('b'), KeyModifiers::empty()))
+        );
+        assert_eq!(started.elapsed(), Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    /// Test that a long run of keys is taken for a paste, but quick typing isn't
+    async fn test_burst_paste() {
+        let enter = Some(Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty())));
+        let save = Some(Event::Key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL)));
+        let pasted = "x".repeat(40);
+        let events = ScriptedSource::keys("quick")
+            .chain([None])
+            .chain(ScriptedSource::keys("ok"))
+            .chain([save])
+            .chain(ScriptedSource::keys(&pasted))
+            .chain([enter])
+            .chain(ScriptedSource::keys("end"))
+            // Waiting for more of the paste takes a second gap to give up
+            .chain([None, None, Some(Event::Paste("bracketed".to_string()))]);
+        let mut handler = InputHandler::with_source(Duration::from_millis(100), ScriptedSource::new(events));
+
+        let first = handler.next_event().await.unwrap();
+        let typed: Vec<InputEvent> = "quick".chars().map(InputEvent::Char).collect();
+        assert_eq!(handler.burst(first).await.unwrap(), typed);
+
+        let first = handler.next_event().await.unwrap();
+        let expected = vec![
+            InputEvent::Char('o'),
+            InputEvent::Char('k'),
+            InputEvent::Save,
+            InputEvent::Paste(format!("{}\nend", pasted)),
+        ];
+        assert_eq!(handler.burst(first).await.unwrap(), expected);
+
+        // Terminals with bracketed paste send it whole
+        let paste = InputEvent::Paste("bracketed".to_string());
+        assert_eq!(handler.next_event().await.unwrap(), paste);
+        assert_eq!(paste.editor_events().len(), 9);
+    }
+
+    #[test]
+    /// Test that keys without a binding are reported rather than dropped
+    fn test_unknown_keys() {
+        let handler = InputHandler::new(Duration::from_millis(100));
+
+        let key = KeyEvent::new(KeyCode::F(12), KeyModifiers::empty());
+        assert_eq!(handler.handle_key_event(key), InputEvent::Unknown(key));
+
+        let key = KeyEvent::new(KeyCode::Char('b'), KeyModifiers::CONTROL);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Unknown(key));
+
+        // Shifted letters and AltGr characters still type
+        let key = KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Char('A'));
+        let key = KeyEvent::new(KeyCode::Char('@'), KeyModifiers::CONTROL | KeyModifiers::ALT);
+        assert_eq!(handler.handle_key_event(key), InputEvent::Char('@'));
+    }
+
+    #[test]
+    /// Test translating mouse clicks and the scroll wheel
+    fn test_mouse_events() {
+        let mouse = |kind| MouseEvent {
+            kind,
+            column: 4,
+            row: 2,
+            modifiers: KeyModifiers::empty(),
+        };
+        let click = mouse(MouseEventKind::Down(MouseButton::Left));
+        assert_eq!(InputHandler::<CrosstermSource>::handle_mouse_event(click), InputEvent::Click(4, 2));
+
+        let wheel = mouse(MouseEventKind::ScrollDown);
+        assert_eq!(InputHandler::<CrosstermSource>::handle_mouse_event(wheel), InputEvent::ScrollDown);
+
+        let moved = mouse(MouseEventKind::Moved);
+        assert_eq!(InputHandler::<CrosstermSource>::handle_mouse_event(moved), InputEvent::Ignored);
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::editor::{Buffer, BufferBuilder};
+
+/// Returns where journal entries go unless the config says otherwise
+/// (`~/Documents/typewriter-journal`)
+pub fn default_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join("Documents").join("typewriter-journal"))
+}
+
+/// Returns the entry file for a day, such as `2024-06-03.md`
+pub fn entry_path(dir: &Path, date: NaiveDate) -> PathBuf {
+    dir.join(format!("{}.md", date.format("%Y-%m-%d")))
+}
+
+/// Opens the entry for the day `now` falls on, creating it and its directory if
+/// needed, with the builder's settings and the cursor under a new `## HH:MM`
+/// heading at the end. Text from an entry last written on an earlier day can't
+/// be marked out.
+///
+/// The buffer keeps the path it was opened with, so a session running past
+/// midnight goes on writing to the day it started on.
+pub fn open_entry(dir: &Path, now: NaiveDateTime, builder: BufferBuilder) -> io::Result<Buffer> {
+    fs::create_dir_all(dir)?;
+    let path = entry_path(dir, now.date());
+    let last_written = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(|modified| DateTime::<Local>::from(modified).date_naive());
+
+    let path = path.to_str().ok_or_else(|| io::Error::other("Journal path is not valid UTF-8"))?;
+    let mut buffer = builder.append_only(last_written.is_some_and(|date| date < now.date())).open(path)?;
+    buffer.move_to_end();
+    insert_heading(&mut buffer, now);
+    Ok(buffer)
+}
+
+/// Types a `## HH:MM` heading for the session, a blank line away from any
+/// earlier text, and leaves the cursor on the line below it
+fn insert_heading(buffer: &mut Buffer, now: NaiveDateTime) {
+    if buffer.lines.iter().any(|line| !line.is_empty()) {
+        if !buffer.lines[buffer.cursor_position().0].is_empty() {
+            buffer.new_line();
+        }
+        if !buffer.lines[buffer.cursor_position().0 - 1].is_empty() {
+            buffer.new_line();
+        }
+    }
+
+    for c in now.format("## %H:%M").to_string().chars() {
+        buffer.insert_char(c);
+    }
+    buffer.new_line();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+
+
+    /// Returns a moment on a fixed day
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    /// Test the file name for a day
+    fn test_entry_path() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap();
+        assert_eq!(entry_path(Path::new("/journal"), date), PathBuf::from("/journal/2024-06-03.md"));
+    }
+
+    #[test]
+    /// Test that a new entry and its directory are created with a heading
+    fn test_new_entry() {
+        let scratch = ScratchDir::new("journal-new");
+        let dir = scratch.join("nested");
+        let buffer = open_entry(&dir, at("2024-06-03", "09:05"), BufferBuilder::new()).unwrap();
+
+        let path = dir.join("2024-06-03.md");
+        assert!(path.exists());
+        assert_eq!(buffer.file_path.as_deref(), path.to_str());
+        assert_eq!(buffer.text(), "## 09:05\n");
+        assert_eq!(buffer.cursor_position(), (1, 0));
+    }
+
+    #[test]
+    /// Test that an entry from earlier the same day is appended to
+    fn test_existing_entry_appends() {
+        let dir = ScratchDir::new("journal-existing");
+        fs::create_dir_all(&dir).unwrap();
+        let today = Local::now().date_naive().format("%Y-%m-%d").to_string();
+        fs::write(dir.join(format!("{}.md", today)), "## 09:05\nMorning.").unwrap();
+
+        let mut buffer = open_entry(&dir, at(&today, "21:40"), BufferBuilder::new()).unwrap();
+        assert_eq!(buffer.text(), "## 09:05\nMorning.\n\n## 21:40\n");
+        assert_eq!(buffer.cursor_position(), (4, 0));
+
+        // Written today, so the earlier text can still be stepped back into
+        for _ in 0..5 {
+            buffer.backspace();
+        }
+        assert!(buffer.cursor_position().0 < 4);
+    }
+
+    #[test]
+    /// Test that an entry last written on an earlier day is read-only
+    fn test_earlier_entry_locked() {
+        let dir = ScratchDir::new("journal-earlier");
+        fs::create_dir_all(&dir).unwrap();
+
+        // The file was written "yesterday" as far as the session is concerned
+        let tomorrow = Local::now().date_naive().succ_opt().unwrap();
+        let name = tomorrow.format("%Y-%m-%d").to_string();
+        fs::write(dir.join(format!("{}.md", name)), "## 23:50\nLate.\n").unwrap();
+
+        let mut buffer = open_entry(&dir, at(&name, "07:30"), BufferBuilder::new()).unwrap();
+        assert_eq!(buffer.text(), "## 23:50\nLate.\n\n## 07:30\n");
+        for _ in 0..20 {
+            buffer.backspace();
+        }
+        // The file's final newline gave it a blank last line, which is locked too
+        assert_eq!(buffer.cursor_position(), (3, 0));
+    }
+
+    #[test]
+    /// Test that the heading is the only thing added to an empty entry
+    fn test_heading_on_empty_file() {
+        let dir = ScratchDir::new("journal-empty");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("2024-06-03.md"), "").unwrap();
+
+        let buffer = open_entry(&dir, at("2024-06-03", "12:00"), BufferBuilder::new()).unwrap();
+        assert_eq!(buffer.text(), "## 12:00\n");
+    }
+}
@@ -0,0 +1,289 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Lock files this process holds. Global so the panic hook can remove them
+/// without access to the session.
+static HELD: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// The typewriter process a lock file belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Owner {
+    /// Process ID
+    pub pid: u32,
+    /// Name of the machine the process runs on
+    pub host: String,
+}
+
+impl Owner {
+    /// Returns this process
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            host: hostname(),
+        }
+    }
+
+    /// Reads a lock file's contents: the PID on the first line, the host on the second
+    fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        let pid = lines.next()?.trim().parse().ok()?;
+        let host = lines.next().unwrap_or("").trim().to_string();
+        Some(Self { pid, host })
+    }
+
+    /// Writes a lock file's contents
+    fn to_text(&self) -> String {
+        format!("{}\n{}\n", self.pid, self.host)
+    }
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PID {} on {}", self.pid, self.host)
+    }
+}
+
+/// What came of trying to lock a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockOutcome {
+    /// The file is now locked by this process
+    Acquired,
+    /// Another typewriter that is still running has it open
+    HeldBy(Owner),
+}
+
+/// Returns the lock file for a file: `.<name>.typewriter.lock` next to it
+pub fn lock_path(file: &Path) -> PathBuf {
+    let name = file.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    file.with_file_name(format!(".{}.typewriter.lock", name))
+}
+
+/// Locks a file for this process. A lock left behind by a process that is no
+/// longer running is removed and taken over.
+pub fn acquire(file: &Path) -> io::Result<LockOutcome> {
+    acquire_as(file, &Owner::current(), is_alive)
+}
+
+/// Does the work of `acquire` for the given owner, with a way to tell
+/// whether another owner is still running
+fn acquire_as(file: &Path, owner: &Owner, alive: impl Fn(&Owner) -> bool) -> io::Result<LockOutcome> {
+    let path = lock_path(file);
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut lock) => {
+                lock.write_all(owner.to_text().as_bytes())?;
+                hold(path);
+                return Ok(LockOutcome::Acquired);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(e),
+        }
+
+        // A process on another machine can't be checked, so it counts as running
+        let holder = fs::read_to_string(&path).ok().as_deref().and_then(Owner::parse);
+        match holder {
+            Some(holder) if holder != *owner && (holder.host != owner.host || alive(&holder)) => {
+                return Ok(LockOutcome::HeldBy(holder));
+            }
+            _ => {
+                log::info!("removing stale lock {}", path.display());
+                match fs::remove_file(&path) {
+                    Ok(()) => (),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+/// Takes a file's lock from whoever holds it
+pub fn take_over(file: &Path) -> io::Result<()> {
+    let path = lock_path(file);
+    fs::write(&path, Owner::current().to_text())?;
+    hold(path);
+    Ok(())
+}
+
+/// Removes every lock this process holds, leaving any another process took over
+pub fn release_all() {
+    let held = match HELD.lock() {
+        Ok(mut held) => std::mem::take(&mut *held),
+        Err(_) => return,
+    };
+    for path in held {
+        release_path(&path);
+    }
+}
+
+/// Installs a panic hook that removes the held locks before the panic is
+/// reported, so a crash doesn't leave the file looking open
+pub fn install_panic_hook() {
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        release_all();
+        previous_hook(info);
+    }));
+}
+
+/// Remembers a lock file for `release_all`
+fn hold(path: PathBuf) {
+    if let Ok(mut held) = HELD.lock() {
+        if !held.contains(&path) {
+            held.push(path);
+        }
+    }
+}
+
+/// Removes a lock file if it still belongs to this process
+fn release_path(path: &Path) {
+    let owner = fs::read_to_string(path).ok().as_deref().and_then(Owner::parse);
+    if owner == Some(Owner::current()) {
+        if let Err(e) = fs::remove_file(path) {
+            log::warn!("couldn't remove lock {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Returns whether a process on this machine is running
+#[cfg(unix)]
+fn is_alive(owner: &Owner) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(owner.pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // Signal 0 only checks that the process exists. EPERM means it does but
+    // belongs to someone else.
+    // SAFETY: kill with signal 0 sends nothing and touches no memory
+    let found = unsafe { libc::kill(pid, 0) } == 0;
+    found || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Returns whether a process on this machine is running. There is no cheap
+/// check here, so every lock counts as live.
+#[cfg(not(unix))]
+fn is_alive(_owner: &Owner) -> bool {
+    true
+}
+
+/// Returns this machine's name
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut name = [0u8; 256];
+    // SAFETY: the buffer is writable for the length given
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return String::new();
+    }
+    let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..end]).into_owned()
+}
+
+/// Returns this machine's name
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+
+
+    /// Returns a process on this machine other than the test itself
+    fn other() -> Owner {
+        Owner {
+            pid: std::process::id() + 1,
+            ..Owner::current()
+        }
+    }
+
+    #[test]
+    /// Test where the lock file goes
+    fn test_lock_path() {
+        assert_eq!(lock_path(Path::new("notes.txt")), PathBuf::from(".notes.txt.typewriter.lock"));
+        assert_eq!(lock_path(Path::new("drafts/a.md")), PathBuf::from("drafts/.a.md.typewriter.lock"));
+    }
+
+    #[test]
+    /// Test locking a file and releasing it again
+    fn test_acquire_and_release() {
+        let dir = ScratchDir::new("lock-acquire");
+        let file = dir.join("notes.txt");
+        assert_eq!(acquire(&file).unwrap(), LockOutcome::Acquired);
+        let text = fs::read_to_string(lock_path(&file)).unwrap();
+        assert_eq!(Owner::parse(&text), Some(Owner::current()));
+
+        // Opening it again from the same process isn't a conflict
+        assert_eq!(acquire(&file).unwrap(), LockOutcome::Acquired);
+
+        release_path(&lock_path(&file));
+        assert!(!lock_path(&file).exists());
+    }
+
+    #[test]
+    /// Test that a lock held by a running process is reported and left alone
+    fn test_live_lock() {
+        let dir = ScratchDir::new("lock-live");
+        let file = dir.join("notes.txt");
+        fs::write(lock_path(&file), other().to_text()).unwrap();
+
+        let outcome = acquire_as(&file, &Owner::current(), |_| true).unwrap();
+        assert_eq!(outcome, LockOutcome::HeldBy(other()));
+        assert_eq!(fs::read_to_string(lock_path(&file)).unwrap(), other().to_text());
+
+        // Locks from other machines can't be checked, so they count as live
+        let remote = Owner {
+            pid: 1,
+            host: "elsewhere".to_string(),
+        };
+        fs::write(lock_path(&file), remote.to_text()).unwrap();
+        let outcome = acquire_as(&file, &Owner::current(), |_| false).unwrap();
+        assert_eq!(outcome, LockOutcome::HeldBy(remote));
+    }
+
+    #[test]
+    /// Test that locks left by dead processes, or unreadable ones, are replaced
+    fn test_stale_lock() {
+        let dir = ScratchDir::new("lock-stale");
+        let file = dir.join("notes.txt");
+        fs::write(lock_path(&file), other().to_text()).unwrap();
+        let outcome = acquire_as(&file, &Owner::current(), |_| false).unwrap();
+        assert_eq!(outcome, LockOutcome::Acquired);
+        assert_eq!(fs::read_to_string(lock_path(&file)).unwrap(), Owner::current().to_text());
+
+        fs::write(lock_path(&file), "garbage").unwrap();
+        assert_eq!(acquire_as(&file, &Owner::current(), |_| true).unwrap(), LockOutcome::Acquired);
+    }
+
+    #[test]
+    /// Test that a taken-over lock is ours, and isn't removed once someone else has it
+    fn test_take_over_and_release() {
+        let dir = ScratchDir::new("lock-take-over");
+        let file = dir.join("notes.txt");
+        fs::write(lock_path(&file), other().to_text()).unwrap();
+        take_over(&file).unwrap();
+        assert_eq!(fs::read_to_string(lock_path(&file)).unwrap(), Owner::current().to_text());
+
+        fs::write(lock_path(&file), other().to_text()).unwrap();
+        release_path(&lock_path(&file));
+        assert!(lock_path(&file).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    /// Test telling running processes from finished ones
+    fn test_is_alive() {
+        assert!(is_alive(&Owner::current()));
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!is_alive(&Owner { pid, ..Owner::current() }));
+    }
+}
@@ -0,0 +1,194 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Size at which the log is moved aside and a new one started
+pub const MAX_SIZE: u64 = 1024 * 1024;
+
+/// How long `flush` waits for the writer to catch up
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns where `--debug` writes its log
+pub fn log_path() -> Option<PathBuf> {
+    Some(crate::recent::data_dir()?.join("typewriter.log"))
+}
+
+/// Sends every log record at debug level or above to a file. The file is
+/// written by its own thread, so logging never waits on the disk.
+pub fn init(path: &Path) -> io::Result<()> {
+    let mut file = RotatingFile::open(path, MAX_SIZE)?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || write_messages(receiver, &mut file));
+
+    log::set_boxed_logger(Box::new(FileLogger { sender })).map_err(io::Error::other)?;
+    log::set_max_level(LevelFilter::Debug);
+    Ok(())
+}
+
+/// What the logger hands to the writer thread
+enum Message {
+    /// A formatted line to append
+    Line(String),
+    /// A request to answer once everything before it is written
+    Flush(Sender<()>),
+}
+
+/// Formats records and passes them to the writer thread
+struct FileLogger {
+    sender: Sender<Message>,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let line = format!(
+            "{} {:<5} {}: {}\n",
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        // Nothing useful can be done if the writer thread is gone
+        let _ = self.sender.send(Message::Line(line));
+    }
+
+    fn flush(&self) {
+        let (done, finished) = mpsc::channel();
+        if self.sender.send(Message::Flush(done)).is_ok() {
+            let _ = finished.recv_timeout(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+/// Writes lines until the logger is dropped
+fn write_messages(receiver: Receiver<Message>, file: &mut RotatingFile) {
+    while let Ok(message) = receiver.recv() {
+        match message {
+            Message::Line(line) => {
+                // Stderr belongs to the terminal, so a failing log is given up on quietly
+                let _ = file.write_line(&line);
+            }
+            Message::Flush(done) => {
+                let _ = file.file.flush();
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// A log file that is moved to `<name>.1` once it reaches its maximum size,
+/// replacing any older one, so at most two files are kept
+struct RotatingFile {
+    /// Where the current log is
+    path: PathBuf,
+    /// The current log, open for appending
+    file: File,
+    /// Bytes in the current log
+    size: u64,
+    /// Size at which to rotate
+    max_size: u64,
+}
+
+impl RotatingFile {
+    /// Opens the log for appending, creating it and its directory if needed
+    fn open(path: &Path, max_size: u64) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+        })
+    }
+
+    /// Appends a line, rotating first if it would take the log past its size
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64;
+        if self.size > 0 && self.size + length > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += length;
+        Ok(())
+    }
+
+    /// Moves the current log to `<name>.1` and starts an empty one
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.path, rotated_path(&self.path))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Returns where a log goes once it is full
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+
+
+    #[test]
+    /// Test that a full log is moved aside and only two files are kept
+    fn test_rotation() {
+        let dir = ScratchDir::new("logging-rotation");
+        let path = dir.join("logs").join("typewriter.log");
+        let mut file = RotatingFile::open(&path, 20).unwrap();
+
+        file.write_line("first line\n").unwrap();
+        file.write_line("second\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first line\nsecond\n");
+
+        // This one doesn't fit, so the log starts over
+        file.write_line("third line\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third line\n");
+        assert_eq!(fs::read_to_string(rotated_path(&path)).unwrap(), "first line\nsecond\n");
+
+        // The next rotation replaces the older file
+        file.write_line("fourth line\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\n");
+        assert_eq!(fs::read_to_string(rotated_path(&path)).unwrap(), "third line\n");
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 2);
+    }
+
+    #[test]
+    /// Test that reopening a log carries on counting from its size
+    fn test_reopen_counts_existing() {
+        let dir = ScratchDir::new("logging-reopen");
+        let path = dir.join("typewriter.log");
+        fs::write(&path, "0123456789\n").unwrap();
+
+        let mut file = RotatingFile::open(&path, 16).unwrap();
+        file.write_line("more text\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "more text\n");
+    }
+
+    #[test]
+    /// Test that a line longer than the limit is still written
+    fn test_oversized_line() {
+        let dir = ScratchDir::new("logging-oversized");
+        let path = dir.join("typewriter.log");
+        let mut file = RotatingFile::open(&path, 4).unwrap();
+        file.write_line("a long line\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a long line\n");
+        assert!(!rotated_path(&path).exists());
+    }
+}
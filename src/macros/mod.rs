@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::input::InputEvent;
+
+/// Pause between replayed events, so the reveal animation shows them being typed
+pub const PLAYBACK_DELAY: Duration = Duration::from_millis(60);
+
+/// Returns whether an event is replayed when a macro plays back. Commands that
+/// act on the file or the session are kept in the recording but skipped.
+pub fn is_playable(event: &InputEvent) -> bool {
+    matches!(
+        event,
+        InputEvent::Char(_)
+            | InputEvent::Backspace
+            | InputEvent::NewLine
+            | InputEvent::Right
+            | InputEvent::Tab
+            | InputEvent::FinishCorrection
+            | InputEvent::Left
+            | InputEvent::Up
+            | InputEvent::Down
+            | InputEvent::Paste(_)
+    )
+}
+
+/// Returns whether an event is left out of recordings altogether
+fn is_unrecorded(event: &InputEvent) -> bool {
+    matches!(
+        event,
+        InputEvent::RecordMacro
+            | InputEvent::PlayMacro
+            | InputEvent::Click(..)
+            | InputEvent::ScrollUp
+            | InputEvent::ScrollDown
+            | InputEvent::Unknown(_)
+            | InputEvent::Ignored
+    )
+}
+
+/// Records one macro per session and plays it back
+#[derive(Debug, Default)]
+pub struct Recorder {
+    /// Whether events are being recorded
+    recording: bool,
+    /// The recorded macro
+    events: Vec<InputEvent>,
+    /// Events still to be replayed
+    playback: VecDeque<InputEvent>,
+    /// When the next replayed event is due
+    next_due: Option<Instant>,
+}
+
+impl Recorder {
+    /// Creates a recorder with an empty macro
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether events are being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Starts or stops recording. Starting throws away the previous macro.
+    /// Returns whether recording is now on.
+    pub fn toggle_recording(&mut self) -> bool {
+        self.recording = !self.recording;
+        if self.recording {
+            self.events.clear();
+        }
+        self.recording
+    }
+
+    /// Adds an event to the macro if recording is on
+    pub fn record(&mut self, event: &InputEvent) {
+        if self.recording && !is_unrecorded(event) {
+            self.events.push(event.clone());
+        }
+    }
+
+    /// Starts replaying the macro, stopping any recording first. Returns how many
+    /// recorded events will be skipped because they can't be replayed.
+    pub fn start_playback(&mut self, now: Instant) -> usize {
+        self.recording = false;
+        self.playback = self.events.iter().filter(|event| is_playable(event)).cloned().collect();
+        self.next_due = (!self.playback.is_empty()).then_some(now);
+        self.events.len() - self.playback.len()
+    }
+
+    /// Returns when the next replayed event is due, if a macro is playing
+    pub fn next_due(&self) -> Option<Instant> {
+        self.next_due
+    }
+
+    /// Takes the next replayed event if it is due
+    pub fn next_event(&mut self, now: Instant) -> Option<InputEvent> {
+        if self.next_due? > now {
+            return None;
+        }
+        let event = self.playback.pop_front();
+        self.next_due = (!self.playback.is_empty()).then(|| now + PLAYBACK_DELAY);
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test which events are replayed
+    fn test_playable_events() {
+        assert!(is_playable(&InputEvent::Char('a')));
+        assert!(is_playable(&InputEvent::Backspace));
+        assert!(is_playable(&InputEvent::NewLine));
+        assert!(!is_playable(&InputEvent::Save));
+        assert!(!is_playable(&InputEvent::Close));
+        assert!(!is_playable(&InputEvent::Abort));
+        assert!(!is_playable(&InputEvent::Escape));
+    }
+
+    #[test]
+    /// Test that only events typed while recording are kept, without the toggles
+    fn test_recording() {
+        let mut recorder = Recorder::new();
+        recorder.record(&InputEvent::Char('x'));
+        assert!(recorder.toggle_recording());
+        recorder.record(&InputEvent::Char('a'));
+        recorder.record(&InputEvent::Ignored);
+        recorder.record(&InputEvent::Click(1, 1));
+        recorder.record(&InputEvent::PlayMacro);
+        recorder.record(&InputEvent::Save);
+        recorder.record(&InputEvent::RecordMacro);
+        assert!(!recorder.toggle_recording());
+        recorder.record(&InputEvent::Char('y'));
+        assert_eq!(recorder.events, vec![InputEvent::Char('a'), InputEvent::Save]);
+
+        // Recording again starts a new macro
+        recorder.toggle_recording();
+        assert!(recorder.events.is_empty());
+    }
+
+    #[test]
+    /// Test replaying with skipped commands and the delay between events
+    fn test_playback() {
+        let mut recorder = Recorder::new();
+        recorder.toggle_recording();
+        for event in [InputEvent::Char('h'), InputEvent::Save, InputEvent::Char('i'), InputEvent::Close] {
+            recorder.record(&event);
+        }
+        recorder.toggle_recording();
+
+        let now = Instant::now();
+        assert_eq!(recorder.start_playback(now), 2);
+        assert_eq!(recorder.next_event(now), Some(InputEvent::Char('h')));
+
+        // The next one waits for the delay
+        assert_eq!(recorder.next_due(), Some(now + PLAYBACK_DELAY));
+        assert_eq!(recorder.next_event(now), None);
+        assert_eq!(recorder.next_event(now + PLAYBACK_DELAY), Some(InputEvent::Char('i')));
+        assert_eq!(recorder.next_due(), None);
+
+        // The macro can be played again
+        assert_eq!(recorder.start_playback(now), 2);
+        assert_eq!(recorder.next_event(now), Some(InputEvent::Char('h')));
+    }
+
+    #[test]
+    /// Test that playing stops a recording in progress
+    fn test_playback_stops_recording() {
+        let mut recorder = Recorder::new();
+        recorder.toggle_recording();
+        recorder.record(&InputEvent::Char('a'));
+        recorder.start_playback(Instant::now());
+        assert!(!recorder.is_recording());
+        assert_eq!(recorder.next_event(Instant::now()), Some(InputEvent::Char('a')));
+    }
+}
@@ -1,129 +1,617 @@
+mod app;
+mod bench;
+mod cli;
+mod clipboard;
+mod clock;
+mod config;
+mod diagnostics;
+mod drafts;
 mod editor;
+#[cfg(feature = "gif-export")]
+mod export;
+mod headless;
+mod hooks;
 mod input;
+mod journal;
+mod lock;
+mod logging;
+mod macros;
+mod messages;
+mod pacing;
+mod page;
+mod paragraph;
+mod paste;
+mod paths;
+mod pomodoro;
+mod print;
+mod recent;
+mod recovery;
 mod renderer;
+mod saving;
+#[cfg(test)]
+mod scratch;
+mod session;
+mod signals;
+mod snapshots;
 mod sound;
+mod sprint;
+mod stats;
+mod status;
+mod ui;
+
+use app::{SessionEnd, SessionOptions};
+use clap::Parser;
+use cli::Cli;
+use clock::Clock;
+use config::Config;
+use diagnostics::BuildInfo;
+use editor::{Buffer, BufferBuilder, RevealCurve, LOAD_RETURN_PAUSE};
+use headless::Launch;
+use hooks::SaveHook;
+use input::{EventSource, InputHandler};
+use lock::LockOutcome;
+use pomodoro::Pomodoro;
+use print::CommandSpooler;
+use recent::{Position, RecentFiles};
+use renderer::{Focus, InitError, Renderer};
+use saving::Writer;
+use session::Picked;
+use signals::{OsSignals, Signal, SignalSource};
+use sound::{KeyboardLayout, SoundSystem, SoundType};
+use stats::SessionStats;
+use ui::Picker;
+use std::io::{self, stdout, IsTerminal, Stdout, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use log::Level;
+
+/// Number of clicks played by `--calibrate-audio`
+const CALIBRATION_CLICKS: usize = 5;
+
+/// What to say when the page can't be drawn because stdout is redirected
+const NOT_A_TERMINAL: &str = "typewriter needs a terminal to draw the page in, but its output goes to a file or a \
+    pipe. To print a file's counts instead, use --stats-only FILE (with --json for JSON); --list-snapshots FILE, --recent \
+    and --bench run without a terminal too.";
 
-use editor::Buffer;
-use input::{InputEvent, InputHandler};
-use renderer::Renderer;
-use sound::{SoundSystem, SoundType};
-use std::io::{self, stdout, Write};
-use std::time::Duration;
-use std::env;
+/// Exit codes for scripts wrapping typewriter. Signals use the usual 128 + n.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExitStatus {
+    /// Closed with everything saved
+    Clean = 0,
+    /// Quit discarding unsaved changes
+    Discarded = 1,
+    /// The terminal was too small or couldn't be set up, or the config was invalid
+    Terminal = 2,
+    /// A file couldn't be read or written
+    File = 3,
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status as u8)
+    }
+}
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-    // Get filename from command line args if provided
-    let args: Vec<String> = env::args().collect();
-    let reveal_rate = Duration::from_millis(300);
-    let input_timeout = Duration::from_millis(50);
-    
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // Raw mode and the alternate screen would only end up in the file
+    if headless::launch(&cli, stdout().is_terminal()) == Launch::NotATerminal {
+        report(Level::Error, NOT_A_TERMINAL);
+        return ExitStatus::Terminal.into();
+    }
+    // Needs no config, and leaves the terminal alone
+    let env_var = |name: &str| std::env::var(name).ok();
+    if let Some(status) = headless::run(&cli, env_var) {
+        return status.into();
+    }
+
+    if cli.debug {
+        start_debug_log(&cli, env_var);
+    }
+    let mut recent = RecentFiles::load();
+    if cli.recent {
+        return headless::print_recent(&recent).into();
+    }
+
+    // Catch SIGINT, SIGTERM and SIGHUP before raw mode goes on, so none of them
+    // can kill the editor with the terminal still in raw mode
+    let mut signals = match OsSignals::new() {
+        Ok(signals) => signals,
+        Err(e) => {
+            report(Level::Error, &format!("Error listening for signals: {}", e));
+            return ExitStatus::Terminal.into();
+        }
+    };
+
+    // Never leave the terminal in raw mode, or the file locked, if something goes wrong
+    renderer::install_panic_hook();
+    lock::install_panic_hook();
+
+    let chosen_path = Config::chosen_path(cli.config.as_deref(), env_var);
+    if let Some(signal) = offer_setup(&cli, chosen_path.as_deref(), &mut signals).await {
+        return ExitCode::from(signal.exit_code());
+    }
+    let config = match load_config(&cli, chosen_path.as_deref(), env_var) {
+        Ok(config) => config,
+        Err(status) => return status.into(),
+    };
+    let seed = config.seed.unwrap_or_else(rand::random);
+    log::info!("sound variation seed: {}", seed);
+
+    if cli.calibrate_audio {
+        return match calibrate_audio(config.audio_latency, seed, config.layout).await {
+            Ok(()) => ExitStatus::Clean.into(),
+            Err(e) => {
+                report(Level::Error, &format!("Error: {}", e));
+                ExitStatus::Terminal.into()
+            }
+        };
+    }
+    if let (Some(output), Some(source)) = (&cli.render_gif, &cli.from) {
+        return headless::render_gif(output, source, config.reveal_rate).into();
+    }
+
     // Check if sound is enabled
-    let sound_system = if args.contains(&"--sound".to_string()) {
-        SoundSystem::new()
+    let sound_system = if config.sound {
+        SoundSystem::new(config.audio_latency, seed, config.layout)
     } else {
         None
     };
-    
-    // Initialize buffer, either empty or from file
-    let mut buffer = if args.len() > 1 && !args[1].starts_with("--") {
-        Buffer::from_file(&args[1], reveal_rate)?
-    } else {
-        Buffer::new(reveal_rate)
+
+    let picking = cli.file.is_none() && !cli.journal && io::stdin().is_terminal();
+    let builder = config.buffer_builder();
+    let (mut buffer, restore) = match open_document(&cli, &config, &builder, &recent) {
+        Ok(opened) => opened,
+        Err(status) => return status.into(),
+    };
+    let mut renderer = match open_terminal(&config) {
+        Ok(renderer) => renderer,
+        Err(status) => return status.into(),
     };
+    let mut input_handler = InputHandler::new(config.input_timeout).with_keymap(config.keymap.clone());
+    input_handler.set_keyboard_enhanced(renderer.keyboard_enhanced());
 
-    let input_handler = InputHandler::new(input_timeout);
-    let mut renderer = Renderer::new(stdout());
+    // Without a file, offer the recent drafts before starting on a blank page
+    if picking {
+        let picked = offer_drafts(&mut input_handler, &mut renderer, &mut signals, &config, &builder, &recent).await;
+        match picked {
+            Ok(Picked::Open(opened)) => buffer = *opened,
+            Ok(Picked::New) => (),
+            Ok(Picked::Quit) => return close_terminal(renderer, Ok(ExitStatus::Clean.into())),
+            Ok(Picked::Signalled(signal)) => return close_terminal(renderer, Ok(ExitCode::from(signal.exit_code()))),
+            Err(e) => return close_terminal(renderer, Err(e)),
+        }
+    }
 
-    // Set up terminal
-    renderer.init()?;
+    start_page(&config, &mut buffer, &mut renderer, &mut recent);
+    let options = session_options(&config, seed, &buffer, chosen_path, restore);
+    let mut pomodoro = config.pomodoro.map(|(work, rest)| Pomodoro::start(work, rest, Instant::now()));
+    let mut stats = SessionStats::start(&buffer, Instant::now(), config.idle_threshold)
+        .with_seed(config.sound.then_some(seed));
+    let result = session::run(
+        &mut input_handler,
+        &mut renderer,
+        &mut buffer,
+        &mut signals,
+        sound_system,
+        &mut pomodoro,
+        &mut stats,
+        options,
+    )
+    .await;
 
-    // Main event loop
-    loop {
-        // Render current state
-        renderer.render(&buffer)?;
-
-        // Handle input
-        match input_handler.next_event().await? {
-            InputEvent::Char(c) => {
-                if let Some(sound_system) = &sound_system {
-                    let reveal_time = std::time::Instant::now() + reveal_rate;
-                    sound_system.schedule_sound(SoundType::KeyPress(c), reveal_time);
-                }
-                buffer.insert_char(c);
-            }
-            InputEvent::Backspace => buffer.backspace(),
-            InputEvent::NewLine => {
-                if let Some(sound_system) = &sound_system {
-                    let reveal_time = std::time::Instant::now() + reveal_rate;
-                    sound_system.schedule_sound(SoundType::Return, reveal_time);
-                }
-                buffer.new_line();
+    let code = finish(result, &config, &mut buffer, renderer, &mut recent, &stats, &pomodoro);
+    log::logger().flush();
+    code
+}
+
+/// Starts the debug log for `--debug`, opening it with the build details
+fn start_debug_log(cli: &Cli, env_var: impl Fn(&str) -> Option<String>) {
+    match logging::log_path() {
+        Some(path) => {
+            if let Err(e) = logging::init(&path) {
+                eprintln!("Couldn't start the debug log at {}: {}", path.display(), e);
             }
-            InputEvent::Right => buffer.move_right(),
-            InputEvent::Save => {
-                if buffer.file_path.is_none() {
-                    // If no file path is set, prompt for one
-                    renderer.cleanup()?;
-                    print!("Enter filename to save: ");
-                    io::stdout().flush()?;
-                    let mut filename = String::new();
-                    io::stdin().read_line(&mut filename)?;
-                    buffer.set_file_path(filename.trim());
-                    renderer.init()?;
-                }
-                
-                if let Err(e) = buffer.save() {
-                    renderer.cleanup()?;
-                    eprintln!("Error saving file: {}", e);
-                    std::thread::sleep(Duration::from_secs(2));
-                    renderer.init()?;
-                }
+        }
+        None => eprintln!("No home directory for the debug log"),
+    }
+    let info = BuildInfo::gather(Config::chosen_path(cli.config.as_deref(), env_var));
+    for line in info.to_string().lines() {
+        log::info!("{}", line);
+    }
+}
+
+/// Asks the first-run questions if there is no config file yet and the run is
+/// interactive, so they are only ever asked once. Returns the signal that cut
+/// them short, if any.
+async fn offer_setup(cli: &Cli, chosen_path: Option<&Path>, signals: &mut impl SignalSource) -> Option<Signal> {
+    let interactive = !cli.calibrate_audio && cli.render_gif.is_none() && io::stdin().is_terminal();
+    if chosen_path.is_some() || cli.no_wizard || !interactive {
+        return None;
+    }
+    let path = Config::default_path().filter(|path| !path.exists())?;
+    match session::first_run(&path, cli, signals).await {
+        Ok(signal) => signal,
+        Err(e) => {
+            report(Level::Warn, &format!("Couldn't write {}: {}", path.display(), e));
+            None
+        }
+    }
+}
+
+/// Loads the settings. They come from the defaults, then the config file,
+/// then its profiles for the file being opened, then the environment, then
+/// the flags, each overriding the one before.
+fn load_config(
+    cli: &Cli,
+    chosen_path: Option<&Path>,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> Result<Config, ExitStatus> {
+    let loaded = match chosen_path {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    };
+    let mut config = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            report(Level::Error, &format!("Error in config file: {}", e));
+            return Err(ExitStatus::Terminal);
+        }
+    };
+    for warning in &config.warnings {
+        report(Level::Warn, &format!("Warning in config file: {}", warning));
+    }
+    // Profiles follow the file named up front; one picked on the opening
+    // screen comes too late, as the terminal is set up by then
+    let profile_file = match &cli.file {
+        Some(path) => Some(PathBuf::from(path)),
+        None if cli.journal => cli
+            .journal_dir
+            .clone()
+            .or_else(|| config.journal_dir.clone())
+            .or_else(journal::default_dir)
+            .map(|dir| journal::entry_path(&dir, chrono::Local::now().date_naive())),
+        None => None,
+    };
+    if let Some(path) = &profile_file {
+        config.apply_profiles(path);
+    }
+    for warning in config.apply_env(env_var) {
+        report(Level::Warn, &format!("Warning in environment: {}", warning));
+    }
+    cli.apply(&mut config);
+    Ok(config)
+}
+
+/// Opens the buffer from the file, from piped stdin, or empty, along with the
+/// snapshot to restore into it, if one was asked for. Key presses still arrive
+/// when stdin is piped, since crossterm then reads /dev/tty. An empty start
+/// may be replaced from the opening screen. Reopening a file puts the cursor
+/// back on the line the last session ended on, while a journal entry always
+/// starts at a new heading instead.
+fn open_document(
+    cli: &Cli,
+    config: &Config,
+    builder: &BufferBuilder,
+    recent: &RecentFiles,
+) -> Result<(Buffer, Option<(String, String)>), ExitStatus> {
+    let loaded = match &cli.file {
+        None if cli.journal => match config.journal_dir.clone().or_else(journal::default_dir) {
+            Some(dir) => journal::open_entry(&dir, chrono::Local::now().naive_local(), builder.clone()),
+            None => Err(io::Error::other("no home directory for the journal; set journal_dir")),
+        },
+        Some(path) => {
+            let position = paths::expand(path).ok().and_then(|path| recent.position_for(&path));
+            builder.clone().cursor(position.map(|position| (position.line, position.column))).open(path)
+        }
+        None if !io::stdin().is_terminal() => builder.read(io::stdin().lock()),
+        None => builder.build().map_err(io::Error::from),
+    };
+    let buffer = match loaded {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            let source = match &cli.file {
+                Some(path) => path.as_str(),
+                None if cli.journal => "journal",
+                None => "stdin",
+            };
+            report(Level::Error, &format!("Error reading {}: {}", source, e));
+            return Err(ExitStatus::File);
+        }
+    };
+    // A snapshot to restore must exist before the terminal is taken over
+    let restore = match (&cli.restore_snapshot, &buffer.file_path) {
+        (Some(name), Some(path)) => match read_snapshot(Path::new(path), name) {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                report(Level::Error, &format!("Error restoring a snapshot: {}", e));
+                return Err(ExitStatus::File);
             }
-            InputEvent::Close => {
-                if buffer.is_modified() {
-                    renderer.cleanup()?;
-                    print!("Save changes before closing? (y/n) ");
-                    io::stdout().flush()?;
-                    let mut input = String::new();
-                    io::stdin().read_line(&mut input)?;
-                    if input.trim().to_lowercase().starts_with('y') {
-                        if buffer.file_path.is_none() {
-                            print!("Enter filename to save: ");
-                            io::stdout().flush()?;
-                            let mut filename = String::new();
-                            io::stdin().read_line(&mut filename)?;
-                            buffer.set_file_path(filename.trim());
-                        }
-                        buffer.save()?;
-                    }
-                }
-                break;
+        },
+        _ => None,
+    };
+    Ok((buffer, restore))
+}
+
+/// Sets the renderer up the way the config asks and takes over the terminal
+fn open_terminal(config: &Config) -> Result<Renderer<Stdout>, ExitStatus> {
+    let mut renderer = Renderer::new(stdout());
+    renderer.set_mouse_capture(config.mouse);
+    renderer.set_focus(if config.focus_sentence { Focus::Sentence } else { Focus::Lines(config.visible_lines) });
+    renderer.set_background(config.background);
+    renderer.set_padding((config.padding_left, config.padding_top));
+    renderer.set_scroll_ahead(config.scroll_ahead);
+    renderer.set_tint_corrections(config.cleanliness);
+    renderer.set_accessible(config.accessible);
+    renderer.set_show_trailing_whitespace(config.show_trailing_whitespace);
+    renderer.set_show_current_trailing_spaces(config.show_current_trailing_spaces);
+    // A sprint pauses while the terminal is out of focus
+    renderer.set_focus_reporting(config.sprint.is_some());
+
+    match renderer.init() {
+        Ok(()) => Ok(renderer),
+        // Nothing was written, so there is nothing to clean up
+        Err(InitError::NotATerminal) => {
+            report(Level::Error, NOT_A_TERMINAL);
+            Err(ExitStatus::Terminal)
+        }
+        Err(e) => {
+            let _ = renderer.cleanup();
+            report(Level::Error, &format!("Error: {}", e));
+            Err(ExitStatus::Terminal)
+        }
+    }
+}
+
+/// Gives the terminal back before exiting with `exit`, or with the terminal
+/// status if that or the cleanup failed
+fn close_terminal<W: Write>(mut renderer: Renderer<W>, exit: io::Result<ExitCode>) -> ExitCode {
+    let cleaned = renderer.cleanup();
+    match (exit, cleaned) {
+        (Ok(code), Ok(())) => code,
+        (Err(e), _) | (_, Err(e)) => {
+            report(Level::Error, &format!("Error: {}", e));
+            ExitStatus::Terminal.into()
+        }
+    }
+}
+
+/// Shows the opening screen if there are recent drafts to choose from, and
+/// reports a new document straight away if there are none
+async fn offer_drafts<S: EventSource, W: Write>(
+    input_handler: &mut InputHandler<S>,
+    renderer: &mut Renderer<W>,
+    signals: &mut impl SignalSource,
+    config: &Config,
+    builder: &BufferBuilder,
+    recent: &RecentFiles,
+) -> io::Result<Picked> {
+    let mut dirs = vec![PathBuf::from(".")];
+    dirs.extend(config.drafts_dir.clone());
+    let recent_paths: Vec<PathBuf> = recent.files().iter().map(|file| file.path.clone()).collect();
+    let drafts = drafts::list(&recent_paths, &dirs);
+    if drafts.is_empty() {
+        return Ok(Picked::New);
+    }
+    session::pick(input_handler, renderer, signals, Picker::new(drafts), builder, recent).await
+}
+
+/// Gets the page ready for the session: typed out if the file is to appear
+/// as if typed, scrolled back to where it was, and the file recorded as
+/// recently opened
+fn start_page<W: Write>(config: &Config, buffer: &mut Buffer, renderer: &mut Renderer<W>, recent: &mut RecentFiles) {
+    if config.reveal_rate.is_zero() && config.reveal_curve == RevealCurve::Cluster {
+        log::info!("no reveal delay, so the cluster curve has nothing to shorten until one is set");
+    }
+    // The layout is by line, so the page doesn't shift as the file appears
+    if config.reveal_on_load {
+        let interval = Duration::from_secs_f64(1.0 / config.reveal_on_load_cps as f64);
+        buffer.type_out(Instant::now(), interval, LOAD_RETURN_PAUSE);
+    }
+
+    if let Some(path) = buffer.file_path.clone() {
+        let path = Path::new(&path);
+        // The page goes back to where it was too, as far as the cursor stays in view
+        let scroll_top = recent.position_for(path).map_or(0, |position| position.scroll_top);
+        renderer.set_scroll_top(scroll_top);
+        recent.record(path, position(buffer, scroll_top));
+        save_recent(recent);
+    }
+}
+
+/// Gathers what the session needs to know from the config, locking the file
+/// and checking that it can be saved in place on the way
+fn session_options(
+    config: &Config,
+    seed: u64,
+    buffer: &Buffer,
+    chosen_path: Option<PathBuf>,
+    restore: Option<(String, String)>,
+) -> SessionOptions {
+    // Two sessions saving the same file would overwrite each other's work
+    let mut locked_by = None;
+    if let Some(path) = &buffer.file_path {
+        match lock::acquire(Path::new(path)) {
+            Ok(LockOutcome::Acquired) => (),
+            Ok(LockOutcome::HeldBy(owner)) => locked_by = Some(owner),
+            Err(e) => log::warn!("couldn't lock {}: {}", path, e),
+        }
+    }
+
+    // Say straight away if the file can't be saved in place, rather than at
+    // the first save
+    let unwritable = buffer.file_path.as_deref().and_then(|path| editor::check_writable(Path::new(path)).err());
+    if let Some(e) = &unwritable {
+        log::warn!("can't write to {}: {}", buffer.file_path.as_deref().unwrap_or_default(), e);
+    }
+
+    SessionOptions {
+        audio_latency: config.audio_latency,
+        seed,
+        layout: config.layout,
+        sprint: config.sprint,
+        on_save: config.on_save_command.clone(),
+        locked_by,
+        cleanliness: config.cleanliness,
+        unwritable: unwritable.map(|e| e.kind()),
+        reveal_on_load: config.reveal_on_load,
+        paragraph_limit: config.paragraph_limit,
+        fit_page: config.fit_page,
+        config_path: chosen_path.or_else(Config::default_path),
+        paste_confirm: config.paste_confirm,
+        max_fps: config.max_fps,
+        bell: config.bell,
+        ui_click_volume: config.ui_click_volume,
+        snapshot_limit: config.snapshot_limit,
+        show_changes: config.show_changes,
+        print_command: config.print_command.clone(),
+        restore,
+        accessible: config.accessible,
+        writer: Writer::default(),
+        status: config.status.clone(),
+        padding_left: config.padding_left,
+        clock: Clock::default(),
+    }
+}
+
+/// Winds up after the session: rescues unsaved work if a signal stopped it,
+/// gives the terminal back, prints the page if asked to and says how it went.
+/// After a hangup the terminal may be gone, so writing to it can fail; the
+/// exit code still matters then.
+fn finish<W: Write>(
+    result: io::Result<SessionEnd>,
+    config: &Config,
+    buffer: &mut Buffer,
+    mut renderer: Renderer<W>,
+    recent: &mut RecentFiles,
+    stats: &SessionStats,
+    pomodoro: &Option<Pomodoro>,
+) -> ExitCode {
+    let snapshot = match result {
+        Ok(SessionEnd::Signalled(_)) => Some(recovery::write_snapshot(buffer, config.autosave_on_exit)),
+        _ => None,
+    };
+
+    if let (Ok(SessionEnd::Closed | SessionEnd::Discarded), Some(path)) = (&result, &buffer.file_path) {
+        recent.record(Path::new(path), position(buffer, renderer.scroll_top()));
+        save_recent(recent);
+    }
+
+    lock::release_all();
+    let cleaned = renderer.cleanup();
+    match &snapshot {
+        Some(Ok(Some(path))) => report(Level::Info, &format!("Unsaved changes written to {}", path.display())),
+        Some(Err(e)) => report(Level::Error, &format!("Couldn't rescue unsaved changes: {}", e)),
+        _ => (),
+    }
+
+    // The page comes out of the typewriter once the session is over
+    let text = buffer.saved_text();
+    if config.print_on_exit && matches!(result, Ok(SessionEnd::Closed)) && !text.trim().is_empty() {
+        let title = print::title(buffer.file_path.as_deref());
+        let mut spooler = CommandSpooler::new(config.print_command.clone());
+        match print::print(&mut spooler, &text, &title, chrono::Local::now().date_naive()) {
+            Ok(pages) => report(Level::Info, &format!("Sent {} to the printer", print::pages_word(pages))),
+            Err(e) => report(Level::Error, &format!("Couldn't print: {}", e)),
+        }
+    }
+
+    // The autosave counts as a save for the hook only if asked for
+    if let (Some(Ok(Some(path))), Some(command)) = (&snapshot, &config.on_save_command) {
+        let autosaved = buffer.file_path.as_deref().is_some_and(|file| Path::new(file) == path);
+        if autosaved && config.on_save_hooks_for_autosave {
+            SaveHook::new(command.clone()).saved_on_exit(&path.to_string_lossy(), stats::word_count(buffer));
+        }
+    }
+
+    log::info!("session ended: {:?}", result);
+    match (result, cleaned) {
+        (Ok(SessionEnd::Signalled(signal)), _) => ExitCode::from(signal.exit_code()),
+        (Err(e), _) | (_, Err(e)) => {
+            report(Level::Error, &format!("Error: {}", e));
+            ExitStatus::Terminal.into()
+        }
+        (Ok(SessionEnd::Discarded), Ok(())) => ExitStatus::Discarded.into(),
+        (Ok(SessionEnd::Closed), Ok(())) => {
+            if !config.quiet {
+                let mut summary = stats.finish(buffer, Instant::now());
+                summary.cycles = pomodoro.as_ref().map(Pomodoro::cycles);
+                print!("{}", stats::format_summary(&summary));
             }
-            InputEvent::Timeout => (), // Do nothing on timeout
+            ExitStatus::Clean.into()
         }
     }
+}
+
+/// Tells the user about something outside the editor screen, and logs it.
+/// After a hangup stderr may be gone, so failing to write is ignored.
+fn report(level: Level, message: &str) {
+    log::log!(level, "{}", message);
+    log::logger().flush();
+    let _ = writeln!(io::stderr(), "{}", message);
+}
+
+/// Returns where the cursor is in the buffer, with the line at the top of the
+/// page, to go back to when the file is next opened
+fn position(buffer: &Buffer, scroll_top: usize) -> Position {
+    let (line, column) = buffer.cursor_position();
+    Position { line, column, scroll_top }
+}
+
+/// Writes the recent file list, logging rather than interrupting if that fails
+fn save_recent(recent: &RecentFiles) {
+    if let Err(e) = recent.save() {
+        log::warn!("couldn't save the recent file list: {}", e);
+    }
+}
+
+/// Plays a series of clicks at one-second intervals, each paired with a screen flash,
+/// so the user can judge how far the sound lags behind the picture
+async fn calibrate_audio(audio_latency: Duration, seed: u64, layout: KeyboardLayout) -> io::Result<()> {
+    let Some(mut sound_system) = SoundSystem::new(audio_latency, seed, layout) else {
+        report(Level::Warn, "Audio output is unavailable, nothing to calibrate");
+        return Ok(());
+    };
+    if let Err(e) = sound_system.ready().await {
+        report(Level::Warn, &format!("Audio output is unavailable ({}), nothing to calibrate", e));
+        return Ok(());
+    }
+
+    let mut renderer = Renderer::new(stdout());
+    renderer.init()?;
+
+    for click in 1..=CALIBRATION_CLICKS {
+        let flash_at = Instant::now() + Duration::from_secs(1);
+        sound_system.schedule_sound(SoundType::KeyPress('a'), flash_at);
+
+        let message = format!(
+            "Audio calibration ({} ms offset): click {} of {}",
+            audio_latency.as_millis(),
+            click,
+            CALIBRATION_CLICKS
+        );
+        renderer.render_calibration(false, &message)?;
+        tokio::time::sleep_until(flash_at.into()).await;
+        renderer.render_calibration(true, &message)?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        renderer.render_calibration(false, &message)?;
+    }
 
-    // Clean up
     renderer.cleanup()?;
+    println!(
+        "If the click came after the flash, raise --audio-latency-ms (currently {}); \
+         if it came before, lower it.",
+        audio_latency.as_millis()
+    );
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[tokio::test]
-    /// Test that the main components can be initialized
-    async fn test_component_initialization() {
-        let reveal_rate = Duration::from_millis(100);
-        let input_timeout = Duration::from_millis(50);
-        
-        let buffer = Buffer::new(reveal_rate);
-        let input_handler = InputHandler::new(input_timeout);
-        let renderer = Renderer::new(Vec::new()); // Use Vec as a mock writer
-        
-        assert_eq!(buffer.reveal_rate(), reveal_rate);
-        assert!(renderer.output.is_empty());
-    }
+/// Finds a file's snapshot by name for `--restore-snapshot`, returning its
+/// name and text
+fn read_snapshot(file: &Path, name: &str) -> io::Result<(String, String)> {
+    let snapshot = snapshots::find(file, name)?;
+    let text = std::fs::read_to_string(&snapshot.path)?;
+    Ok((snapshot.name, text))
 }
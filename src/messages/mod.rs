@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::renderer::Overlay;
+
+/// How long a passing note stays in the status row
+pub const INFO_TTL: Duration = Duration::from_secs(4);
+/// How long something that went wrong stays in the status row
+pub const ERROR_TTL: Duration = Duration::from_secs(8);
+
+/// Once this many messages are waiting, the status row says how many more
+/// there are besides the one it shows
+const COLLAPSE_AT: usize = 3;
+/// Most messages the log keeps, oldest going first
+const LOG_LENGTH: usize = 50;
+
+/// How much a message matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// A passing note, such as a file being saved
+    Info,
+    /// Something that went wrong, shown ahead of any notes
+    Error,
+}
+
+impl Level {
+    /// Returns how long messages of this level are shown unless told otherwise
+    pub fn ttl(self) -> Duration {
+        match self {
+            Level::Info => INFO_TTL,
+            Level::Error => ERROR_TTL,
+        }
+    }
+}
+
+/// A message waiting to be shown, or being shown
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    level: Level,
+    text: String,
+    /// Time left in the status row, counted down only while it can be seen
+    remaining: Duration,
+}
+
+/// The messages for the status row, each shown until its time is up. Errors
+/// come before notes and newer before older. Time only passes for them while
+/// the status row can be seen, so one shown as a menu opens is still there
+/// when it closes.
+#[derive(Debug, Default)]
+pub struct Messages {
+    /// Messages whose time isn't up, oldest first
+    queue: Vec<Message>,
+    /// Every message, up to `LOG_LENGTH`, oldest first
+    log: VecDeque<String>,
+    /// When they were last counted down, and whether they could be seen then
+    last_tick: Option<(Instant, bool)>,
+}
+
+impl Messages {
+    /// Creates an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message to show for `ttl` of the time the status row can be
+    /// seen. The same text already waiting is shown afresh rather than twice.
+    pub fn push(&mut self, level: Level, text: impl Into<String>, ttl: Duration) {
+        let text = text.into();
+        self.queue.retain(|message| message.text != text);
+        self.queue.push(Message { level, text: text.clone(), remaining: ttl });
+        if self.log.len() == LOG_LENGTH {
+            self.log.pop_front();
+        }
+        self.log.push_back(text);
+    }
+
+    /// Counts the messages down by the time since the last tick, if the
+    /// status row could be seen through it, and drops those whose time is up
+    pub fn tick(&mut self, now: Instant, shown: bool) {
+        if let Some((last, true)) = self.last_tick {
+            let elapsed = now.saturating_duration_since(last);
+            for message in &mut self.queue {
+                message.remaining = message.remaining.saturating_sub(elapsed);
+            }
+        }
+        self.queue.retain(|message| !message.remaining.is_zero());
+        self.last_tick = Some((now, shown));
+    }
+
+    /// Returns when the next message's time is up, if the status row could be
+    /// seen at the last tick
+    pub fn next_expiry(&self) -> Option<Instant> {
+        let (last, true) = self.last_tick? else {
+            return None;
+        };
+        self.queue.iter().map(|message| last + message.remaining).min()
+    }
+
+    /// Returns the message to show and its level: the newest error, or the
+    /// newest note if there are none. With many waiting, it ends with how
+    /// many more there are.
+    pub fn current(&self) -> Option<(Level, String)> {
+        let message = self.queue.iter().max_by_key(|message| message.level)?;
+        let text = match self.queue.len() {
+            waiting if waiting >= COLLAPSE_AT => format!("{} … and {} more", message.text, waiting - 1),
+            _ => message.text.clone(),
+        };
+        Some((message.level, text))
+    }
+
+    /// Describes the log of recent messages, newest first
+    pub fn overlay(&self) -> Overlay {
+        let mut lines: Vec<String> = self.log.iter().rev().cloned().collect();
+        if lines.is_empty() {
+            lines.push("No messages yet".to_string());
+        }
+        Overlay {
+            title: "Messages".to_string(),
+            lines,
+            highlighted: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that errors are shown before notes, and newer before older
+    fn test_priority() {
+        let mut messages = Messages::new();
+        assert_eq!(messages.current(), None);
+        messages.push(Level::Info, "Saved", INFO_TTL);
+        assert_eq!(messages.current(), Some((Level::Info, "Saved".to_string())));
+        messages.push(Level::Error, "Couldn't copy", ERROR_TTL);
+        messages.push(Level::Info, "Capitals on", INFO_TTL);
+        assert_eq!(messages.current(), Some((Level::Error, "Couldn't copy … and 2 more".to_string())));
+
+        // The same text again doesn't queue twice
+        let mut messages = Messages::new();
+        messages.push(Level::Info, "Capitals on", INFO_TTL);
+        messages.push(Level::Info, "Capitals off", INFO_TTL);
+        messages.push(Level::Info, "Capitals on", INFO_TTL);
+        assert_eq!(messages.current(), Some((Level::Info, "Capitals on".to_string())));
+    }
+
+    #[test]
+    /// Test that messages go once their time is up, and that time doesn't
+    /// pass for them while the status row is hidden
+    fn test_expiry() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut messages = Messages::new();
+        messages.push(Level::Info, "Saved", Duration::from_secs(1));
+        messages.push(Level::Error, "Couldn't print", Duration::from_secs(3));
+        assert_eq!(messages.next_expiry(), None);
+
+        messages.tick(at(0), true);
+        assert_eq!(messages.next_expiry(), Some(at(1000)));
+        messages.tick(at(999), true);
+        assert_eq!(messages.queue.len(), 2);
+        messages.tick(at(1000), true);
+        assert_eq!(messages.queue.len(), 1);
+
+        // Hidden behind a menu from 1.5 to 10 seconds in
+        messages.tick(at(1500), false);
+        assert_eq!(messages.next_expiry(), None);
+        messages.tick(at(10_000), true);
+        assert_eq!(messages.current(), Some((Level::Error, "Couldn't print".to_string())));
+        assert_eq!(messages.next_expiry(), Some(at(11_500)));
+        messages.tick(at(11_500), true);
+        assert_eq!(messages.current(), None);
+    }
+
+    #[test]
+    /// Test that many messages collapse into a count, all of them staying in
+    /// the log
+    fn test_collapse() {
+        let mut messages = Messages::new();
+        messages.push(Level::Info, "one", INFO_TTL);
+        messages.push(Level::Info, "two", INFO_TTL);
+        assert_eq!(messages.current(), Some((Level::Info, "two".to_string())));
+        messages.push(Level::Info, "three", INFO_TTL);
+        messages.push(Level::Info, "four", INFO_TTL);
+        assert_eq!(messages.current(), Some((Level::Info, "four … and 3 more".to_string())));
+
+        let overlay = messages.overlay();
+        assert_eq!(overlay.title, "Messages");
+        assert_eq!(overlay.lines, ["four", "three", "two", "one"]);
+        assert_eq!(Messages::new().overlay().lines, ["No messages yet"]);
+
+        for number in 0..LOG_LENGTH {
+            messages.push(Level::Info, number.to_string(), INFO_TTL);
+        }
+        assert_eq!(messages.overlay().lines.len(), LOG_LENGTH);
+        assert_eq!(messages.overlay().lines.last().map(String::as_str), Some("0"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+/// Most frames drawn in a second while text is appearing, by default
+pub const DEFAULT_MAX_FPS: u64 = 60;
+
+/// Range the frame rate cap can be set within
+const FPS_RANGE: RangeInclusive<u64> = 1..=240;
+
+/// Longest the input can be waited on before timers are checked, in milliseconds
+const MAX_INPUT_TIMEOUT_MS: u64 = 1000;
+
+/// Checks a frame rate cap is within the range it can be set to
+pub fn check_max_fps(fps: u64) -> Result<u64, String> {
+    if FPS_RANGE.contains(&fps) {
+        Ok(fps)
+    } else {
+        Err(format!("must be from {} to {}", FPS_RANGE.start(), FPS_RANGE.end()))
+    }
+}
+
+/// Checks an input timeout in milliseconds is more than zero and at most a second
+pub fn check_input_timeout(ms: u64) -> Result<u64, String> {
+    match ms {
+        0 => Err("must be more than 0".to_string()),
+        ms if ms > MAX_INPUT_TIMEOUT_MS => Err(format!("must be at most {}", MAX_INPUT_TIMEOUT_MS)),
+        ms => Ok(ms),
+    }
+}
+
+/// Spaces out the frames drawn for text appearing on its own, so a fast reveal
+/// is drawn at most so many times a second. Frames for input are never held
+/// back.
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    /// Shortest time between two frames drawn for reveals; zero draws one
+    /// whenever a character is due
+    interval: Duration,
+    /// When the last frame was drawn
+    last_frame: Option<Instant>,
+}
+
+impl FramePacer {
+    /// Paces frames to at most `max_fps` a second, or not at all for 0
+    pub fn new(max_fps: u64) -> Self {
+        let interval = match max_fps {
+            0 => Duration::ZERO,
+            fps => Duration::from_secs(1) / fps as u32,
+        };
+        Self { interval, last_frame: None }
+    }
+
+    /// Notes that a frame was drawn
+    pub fn rendered(&mut self, now: Instant) {
+        self.last_frame = Some(now);
+    }
+
+    /// Returns when to wake up to draw the next frame, given when the next
+    /// character is due to appear, if any: then, but no sooner than the cap
+    /// allows after the last frame
+    pub fn next_wake(&self, next_reveal: Option<Instant>) -> Option<Instant> {
+        let next_reveal = next_reveal?;
+        Some(match self.last_frame {
+            Some(last_frame) => next_reveal.max(last_frame + self.interval),
+            None => next_reveal,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that the next wake waits for the cap only when a reveal is due sooner
+    fn test_next_wake() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut pacer = FramePacer::new(50);
+        // Nothing due, nothing to wake for
+        assert_eq!(pacer.next_wake(None), None);
+        // Before the first frame nothing holds a reveal back
+        assert_eq!(pacer.next_wake(Some(ms(1))), Some(ms(1)));
+
+        pacer.rendered(start);
+        assert_eq!(pacer.next_wake(None), None);
+        assert_eq!(pacer.next_wake(Some(ms(5))), Some(ms(20)));
+        assert_eq!(pacer.next_wake(Some(ms(20))), Some(ms(20)));
+        assert_eq!(pacer.next_wake(Some(ms(35))), Some(ms(35)));
+        // A reveal that was due already waits out the frame too
+        assert_eq!(pacer.next_wake(Some(start)), Some(ms(20)));
+
+        // Without a cap the reveal's own time is kept
+        let mut pacer = FramePacer::new(0);
+        pacer.rendered(start);
+        assert_eq!(pacer.next_wake(Some(ms(1))), Some(ms(1)));
+    }
+
+    #[test]
+    /// Test the ranges the frame rate cap and input timeout are kept to
+    fn test_checks() {
+        assert_eq!(check_max_fps(DEFAULT_MAX_FPS), Ok(60));
+        assert_eq!(check_max_fps(240), Ok(240));
+        assert_eq!(check_max_fps(0), Err("must be from 1 to 240".to_string()));
+        assert_eq!(check_max_fps(1000), Err("must be from 1 to 240".to_string()));
+
+        assert_eq!(check_input_timeout(50), Ok(50));
+        assert_eq!(check_input_timeout(0), Err("must be more than 0".to_string()));
+        assert_eq!(check_input_timeout(5000), Err("must be at most 1000".to_string()));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::editor::Buffer;
+use crate::print::{self, BODY_LINES};
+use crate::renderer::StatusTone;
+
+/// Lines left on the page at which the status turns amber
+const WARN_LINES: usize = 5;
+
+/// What the status row should say about how much of the page is used
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    /// The lines left or over, such as "Page: 12 lines left"
+    pub status: String,
+    /// How close to the end of the page the text is
+    pub tone: StatusTone,
+    /// Whether the page has just become exactly full
+    pub full: bool,
+    /// First line of the buffer that no longer fits on the page, if any
+    pub overflow_from: Option<usize>,
+}
+
+/// Measures the text against one printed page, counting lines as they are
+/// printed: a line longer than the page is wide takes as many lines as it
+/// wraps onto.
+#[derive(Debug, Clone, Default)]
+pub struct PageFit {
+    /// Whether the bell has rung for the page filling up, until it has room again
+    rung: bool,
+}
+
+impl PageFit {
+    /// Starts measuring
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Measures the text against the page, noting when it becomes exactly full
+    pub fn check(&mut self, buffer: &Buffer) -> Fill {
+        let (used, overflow_from) = measure(buffer);
+        let (status, tone) = match BODY_LINES.checked_sub(used) {
+            None => (format!("Page: {} over", lines(used - BODY_LINES)), StatusTone::Over),
+            Some(0) => ("Page: full".to_string(), StatusTone::Warning),
+            Some(left) if left <= WARN_LINES => (format!("Page: {} left", lines(left)), StatusTone::Warning),
+            Some(left) => (format!("Page: {} left", lines(left)), StatusTone::Normal),
+        };
+        let full = used == BODY_LINES && !self.rung;
+        if used < BODY_LINES {
+            self.rung = false;
+        } else if full {
+            self.rung = true;
+        }
+        Fill {
+            status,
+            tone,
+            full,
+            overflow_from,
+        }
+    }
+}
+
+/// Returns "1 line" or "N lines"
+fn lines(count: usize) -> String {
+    match count {
+        1 => "1 line".to_string(),
+        count => format!("{} lines", count),
+    }
+}
+
+/// Returns how many printed lines the text takes, and the first line of the
+/// buffer that goes past the page. Lines split at the margin are printed
+/// joined up again, so they are wrapped together.
+fn measure(buffer: &Buffer) -> (usize, Option<usize>) {
+    let texts: Vec<String> = buffer.text_lines().collect();
+    let mut used = 0;
+    let mut overflow_from = None;
+    let mut idx = 0;
+    while idx < texts.len() {
+        let group = buffer.saved_with(idx);
+        let joined = texts[group.clone()].concat();
+        let printed = print::printed_lines(&joined);
+        if overflow_from.is_none() && used + printed > BODY_LINES {
+            // The buffer line whose text carries the group onto the next page
+            let mut prefix = String::new();
+            overflow_from = group.clone().find(|&line| {
+                prefix.push_str(&texts[line]);
+                used + print::printed_lines(&prefix) > BODY_LINES
+            });
+        }
+        used += printed;
+        idx = group.end;
+    }
+    (used, overflow_from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A line of 100 columns, which wraps onto two printed lines
+    const LONG: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, \
+        sed do eiusmod tempor incididunt ut labore";
+
+    /// Opens text with the cursor at its end
+    fn buffer_with(text: &str) -> Buffer {
+        let mut buffer = Buffer::from_reader(text.as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        buffer
+    }
+
+    /// Returns `short` one-word lines followed by `long` lines that wrap
+    fn text(short: usize, long: usize) -> String {
+        let lines: Vec<&str> = std::iter::repeat_n("Word.", short).chain(std::iter::repeat_n(LONG, long)).collect();
+        lines.join("\n")
+    }
+
+    #[test]
+    /// Test that lines too wide for the page count as the lines they wrap onto
+    fn test_measure_wrapped() {
+        assert_eq!(print::printed_lines(LONG), 2);
+        assert_eq!(measure(&buffer_with("")), (1, None));
+        assert_eq!(measure(&buffer_with(&text(3, 2))), (7, None));
+
+        // 46 short lines and 5 long ones fill the page exactly, unlike 56 lines
+        assert_eq!(measure(&buffer_with(&text(46, 5))), (BODY_LINES, None));
+        // One more long line starts the next page
+        assert_eq!(measure(&buffer_with(&text(46, 6))), (BODY_LINES + 2, Some(51)));
+        // A long line that only half fits goes past the page as a whole
+        assert_eq!(measure(&buffer_with(&text(55, 1))), (BODY_LINES + 1, Some(55)));
+    }
+
+    #[test]
+    /// Test that lines split at the margin are wrapped as the one line they
+    /// are printed as, and that the overflow starts on the split line that
+    /// goes past the page
+    fn test_measure_split() {
+        let mut buffer = buffer_with(&text(52, 1));
+        buffer.split_long_lines(40);
+        // Split into three on screen, but two lines on the page
+        assert_eq!(buffer.lines.len(), 55);
+        assert_eq!(measure(&buffer), (54, None));
+
+        let mut buffer = buffer_with(&text(55, 1));
+        buffer.split_long_lines(40);
+        assert_eq!(measure(&buffer), (BODY_LINES + 1, Some(56)));
+    }
+
+    #[test]
+    /// Test the status and tone approaching the end of the page, and that the
+    /// bell rings once when it is exactly full
+    fn test_check() {
+        let mut page = PageFit::new();
+        let fill = page.check(&buffer_with(&text(44, 3)));
+        assert_eq!((fill.status.as_str(), fill.tone), ("Page: 6 lines left", StatusTone::Normal));
+        let fill = page.check(&buffer_with(&text(45, 3)));
+        assert_eq!((fill.status.as_str(), fill.tone), ("Page: 5 lines left", StatusTone::Warning));
+        let fill = page.check(&buffer_with(&text(44, 5)));
+        assert_eq!((fill.status.as_str(), fill.tone), ("Page: 2 lines left", StatusTone::Warning));
+
+        let fill = page.check(&buffer_with(&text(46, 5)));
+        assert_eq!((fill.status.as_str(), fill.tone, fill.full), ("Page: full", StatusTone::Warning, true));
+        assert!(!page.check(&buffer_with(&text(46, 5))).full);
+
+        let fill = page.check(&buffer_with(&text(47, 5)));
+        assert_eq!((fill.status.as_str(), fill.tone, fill.full), ("Page: 1 line over", StatusTone::Over, false));
+        assert_eq!(fill.overflow_from, Some(51));
+
+        // Coming back to a full page from over it stays quiet, but filling it
+        // again after making room rings again
+        assert!(!page.check(&buffer_with(&text(46, 5))).full);
+        assert_eq!(page.check(&buffer_with(&text(45, 5))).status, "Page: 1 line left");
+        assert!(page.check(&buffer_with(&text(46, 5))).full);
+    }
+
+    #[test]
+    /// Test that typing onto the end of a line that wraps fills the page
+    fn test_check_typing() {
+        let mut page = PageFit::new();
+        let mut buffer = buffer_with(&(text(54, 0) + "\nShort"));
+        assert_eq!(page.check(&buffer).status, "Page: 1 line left");
+        let mut rings = 0;
+        for c in " and then some more words, until the line runs on past the edge of the page".chars() {
+            buffer.insert_char(c);
+            rings += usize::from(page.check(&buffer).full);
+        }
+        assert_eq!(rings, 1);
+        assert_eq!(page.check(&buffer).status, "Page: full");
+        assert_eq!(page.check(&buffer).overflow_from, None);
+    }
+}
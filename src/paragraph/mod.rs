@@ -0,0 +1,268 @@
+use crate::editor::{Buffer, CharacterState, Line};
+use crate::renderer::StatusTone;
+
+/// Share of the limit past which the status turns amber
+const WARN_SHARE: f32 = 0.9;
+
+/// How long a paragraph may get before the status turns red
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    /// Characters, counting each line break inside the paragraph as one
+    Characters(usize),
+    /// Words, separated by whitespace or line breaks
+    Words(usize),
+}
+
+/// The size of a stretch of text, leaving out marked-out characters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Size {
+    /// Characters, including line breaks between the lines measured
+    pub characters: usize,
+    /// Words
+    pub words: usize,
+    /// Lines measured
+    pub lines: usize,
+    /// Whether the first line measured carries on the one before it
+    pub continued: bool,
+}
+
+impl Size {
+    /// Measures one line
+    pub fn of_line(line: &Line) -> Self {
+        let text: String = line
+            .characters
+            .iter()
+            .filter(|character| character.state == CharacterState::Normal)
+            .map(|character| character.value)
+            .collect();
+        Self {
+            characters: text.chars().count(),
+            words: text.split_whitespace().count(),
+            lines: 1,
+            continued: line.continuation,
+        }
+    }
+
+    /// Returns the size of this text with `other` on the lines after it. A
+    /// line split off a long one follows on without a line break.
+    fn join(self, other: Size) -> Self {
+        let breaks = usize::from(self.lines > 0 && other.lines > 0 && !other.continued);
+        Self {
+            characters: self.characters + other.characters + breaks,
+            words: self.words + other.words,
+            lines: self.lines + other.lines,
+            continued: if self.lines > 0 { self.continued } else { other.continued },
+        }
+    }
+}
+
+/// What the status row should say about the paragraph being typed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    /// The size against the limit, such as "Paragraph: 245/280 characters"
+    pub status: String,
+    /// How close to the limit the paragraph is
+    pub tone: StatusTone,
+    /// Whether the paragraph has just gone past the limit for the first time
+    pub crossed: bool,
+}
+
+/// The lines of a paragraph above and below the cursor's line
+#[derive(Debug, Clone, Copy)]
+struct Around {
+    /// The cursor line and line count this was measured for
+    measured_for: (usize, usize),
+    /// First line of the paragraph
+    start: usize,
+    /// Size of the lines above the cursor's
+    above: Size,
+    /// Size of the lines below the cursor's
+    below: Size,
+}
+
+/// Measures the paragraph the cursor is in against a limit. A paragraph is
+/// the cursor's line and the lines around it up to blank lines.
+#[derive(Debug, Clone)]
+pub struct ParagraphLimit {
+    limit: Limit,
+    /// The rest of the paragraph, kept while the cursor stays on its line
+    around: Option<Around>,
+    /// First line of the paragraph the bell last rang for
+    rung: Option<usize>,
+}
+
+impl ParagraphLimit {
+    /// Starts measuring against a limit
+    pub fn new(limit: Limit) -> Self {
+        Self {
+            limit,
+            around: None,
+            rung: None,
+        }
+    }
+
+    /// Returns the first line and size of the paragraph the cursor is in.
+    /// Typing only changes the cursor's line, so the rest of the paragraph is
+    /// measured again only after the cursor moves to another line.
+    pub fn measure(&mut self, buffer: &Buffer) -> (usize, Size) {
+        let cursor_line = buffer.cursor_position().0;
+        let measured_for = (cursor_line, buffer.lines.len());
+        let around = match self.around {
+            Some(around) if around.measured_for == measured_for => around,
+            _ => *self.around.insert(measure_around(buffer, cursor_line, measured_for)),
+        };
+        let size = around.above.join(Size::of_line(&buffer.lines[cursor_line])).join(around.below);
+        (around.start, size)
+    }
+
+    /// Measures the paragraph the cursor is in, noting when it first goes
+    /// past the limit
+    pub fn check(&mut self, buffer: &Buffer) -> Check {
+        let (start, size) = self.measure(buffer);
+        let (count, limit, unit) = match self.limit {
+            Limit::Characters(limit) => (size.characters, limit, "characters"),
+            Limit::Words(limit) => (size.words, limit, "words"),
+        };
+
+        let tone = if count > limit {
+            StatusTone::Over
+        } else if count as f32 > limit as f32 * WARN_SHARE {
+            StatusTone::Warning
+        } else {
+            StatusTone::Normal
+        };
+        let crossed = tone == StatusTone::Over && self.rung != Some(start);
+        if crossed {
+            self.rung = Some(start);
+        }
+        Check {
+            status: format!("Paragraph: {}/{} {}", count, limit, unit),
+            tone,
+            crossed,
+        }
+    }
+}
+
+/// Measures the lines of the paragraph above and below the cursor's line,
+/// stopping at blank lines
+fn measure_around(buffer: &Buffer, cursor_line: usize, measured_for: (usize, usize)) -> Around {
+    let is_blank = |size: &Size| size.words == 0;
+    let above: Vec<Size> = buffer.lines[..cursor_line]
+        .iter()
+        .rev()
+        .map(Size::of_line)
+        .take_while(|size| !is_blank(size))
+        .collect();
+    let below = buffer.lines[cursor_line + 1..]
+        .iter()
+        .map(Size::of_line)
+        .take_while(|size| !is_blank(size))
+        .fold(Size::default(), Size::join);
+    Around {
+        measured_for,
+        start: cursor_line - above.len(),
+        above: above.into_iter().rev().fold(Size::default(), Size::join),
+        below,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Opens text with the cursor at its end
+    fn buffer_with(text: &str) -> Buffer {
+        let mut buffer = Buffer::from_reader(text.as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        buffer
+    }
+
+    #[test]
+    /// Test that a paragraph runs across its lines up to blank lines
+    fn test_measure_across_lines() {
+        let mut paragraph = ParagraphLimit::new(Limit::Words(100));
+        let buffer = buffer_with("Before.\n\nOne two\nthree four\nfive");
+        let (start, size) = paragraph.measure(&buffer);
+        assert_eq!(start, 2);
+        assert_eq!(size, Size { characters: 23, words: 5, lines: 3, continued: false });
+
+        // The lines below the cursor belong to its paragraph too
+        let mut buffer = buffer_with("One two\nthree\n\nAfter.");
+        buffer.move_to_line(0);
+        assert_eq!(paragraph.measure(&buffer), (0, Size { characters: 13, words: 3, lines: 2, continued: false }));
+
+        // A line split off a long one carries on without a line break
+        let mut buffer = buffer_with("One two three four");
+        buffer.split_long_lines(8);
+        assert_eq!(ParagraphLimit::new(Limit::Words(100)).measure(&buffer).1, Size { characters: 18, words: 4, lines: 3, continued: false });
+
+        // A blank line starts a new, empty paragraph
+        let buffer = buffer_with("One two\n\n");
+        assert_eq!(paragraph.measure(&buffer).1.words, 0);
+    }
+
+    #[test]
+    /// Test that marked-out characters aren't counted, and that typing on the
+    /// cursor's line is picked up
+    fn test_measure_typing() {
+        let mut paragraph = ParagraphLimit::new(Limit::Characters(100));
+        let mut buffer = buffer_with("Ab\n");
+        assert_eq!(paragraph.measure(&buffer).1.characters, 3);
+
+        for c in "teh".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(paragraph.measure(&buffer).1.characters, 6);
+        buffer.backspace();
+        buffer.insert_char('x');
+        assert_eq!(paragraph.measure(&buffer).1.characters, 5);
+
+        buffer.new_line();
+        buffer.insert_char('c');
+        assert_eq!(paragraph.measure(&buffer).1, Size { characters: 7, words: 3, lines: 3, continued: false });
+    }
+
+    #[test]
+    /// Test the status, and that the bell rings once for each paragraph
+    fn test_check_bell_latch() {
+        let mut paragraph = ParagraphLimit::new(Limit::Characters(10));
+        let mut buffer = buffer_with("");
+        let mut crossings = 0;
+        let mut tones = Vec::new();
+        for c in "Twelve chars and more".chars() {
+            buffer.insert_char(c);
+            let check = paragraph.check(&buffer);
+            crossings += usize::from(check.crossed);
+            tones.push(check.tone);
+        }
+        assert_eq!(crossings, 1);
+        assert_eq!(tones[8], StatusTone::Normal);
+        assert_eq!(tones[9], StatusTone::Warning);
+        assert_eq!(tones[10], StatusTone::Over);
+        assert_eq!(paragraph.check(&buffer).status, "Paragraph: 21/10 characters");
+
+        // Falling back under the limit and going over again stays quiet
+        for _ in 0..15 {
+            buffer.backspace();
+        }
+        for _ in 0..15 {
+            buffer.insert_char('x');
+        }
+        assert_eq!(paragraph.check(&buffer).tone, StatusTone::Normal);
+        for c in " and more".chars() {
+            buffer.insert_char(c);
+            assert!(!paragraph.check(&buffer).crossed);
+        }
+        assert_eq!(paragraph.check(&buffer).tone, StatusTone::Over);
+
+        // A new paragraph rings again
+        buffer.new_line();
+        buffer.new_line();
+        for c in "Another long one".chars() {
+            buffer.insert_char(c);
+            crossings += usize::from(paragraph.check(&buffer).crossed);
+        }
+        assert_eq!(crossings, 2);
+    }
+}
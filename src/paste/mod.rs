@@ -0,0 +1,62 @@
+/// Size past which a paste is confirmed before it is typed, by default
+pub const DEFAULT_CONFIRM_BYTES: usize = 4 * 1024;
+
+/// Most characters of a paste's first line shown when asking about it
+const PREVIEW_CHARS: usize = 24;
+
+/// Returns whether a paste is big enough to ask about before typing it out,
+/// given the size in bytes it may have without asking
+pub fn needs_confirm(text: &str, limit: usize) -> bool {
+    text.len() > limit
+}
+
+/// Asks whether to type out a paste, giving its size and how it starts
+pub fn question(text: &str) -> String {
+    format!("Paste {} starting \"{}\"? (y/n)", format_size(text.len()), preview(text))
+}
+
+/// Shows a size in bytes, in kilobytes once it reaches one
+fn format_size(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} bytes", bytes)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+/// Returns the start of the first line that isn't blank, cut short with an
+/// ellipsis if it is long
+fn preview(text: &str) -> String {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    let mut preview: String = line.chars().take(PREVIEW_CHARS).collect();
+    if line.chars().count() > PREVIEW_CHARS {
+        preview.push('…');
+    }
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that only pastes past the limit are asked about
+    fn test_needs_confirm() {
+        let limit = DEFAULT_CONFIRM_BYTES;
+        assert!(!needs_confirm("a few words", limit));
+        assert!(!needs_confirm(&"a".repeat(limit), limit));
+        assert!(needs_confirm(&"a".repeat(limit + 1), limit));
+        // Measured in bytes, so wide characters count for more
+        assert!(needs_confirm(&"é".repeat(limit / 2 + 1), limit));
+    }
+
+    #[test]
+    /// Test the question's size and preview of the first line
+    fn test_question() {
+        let text = format!("\n  Chapter One  \n{}", "It was a dark and stormy night. ".repeat(200));
+        assert_eq!(question(&text), "Paste 6.3 KB starting \"Chapter One\"? (y/n)");
+
+        let text = "The quick brown fox jumps over the lazy dog";
+        assert_eq!(question(text), "Paste 43 bytes starting \"The quick brown fox jump…\"? (y/n)");
+    }
+}
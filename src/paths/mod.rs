@@ -0,0 +1,136 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a path couldn't be expanded
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathError {
+    /// The path starts with `~` but there is no home directory
+    NoHome,
+    /// The path names another user's home, such as `~alice/notes.txt`
+    OtherUser(String),
+    /// The path uses a variable that isn't set
+    Unset(String),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoHome => write!(f, "there is no home directory for `~`; set $HOME"),
+            Self::OtherUser(user) => write!(f, "`~{}` isn't supported; write out the path to their home", user),
+            Self::Unset(name) => write!(f, "${} isn't set", name),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+impl From<PathError> for io::Error {
+    fn from(e: PathError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+    }
+}
+
+/// Expands a path the way a shell would, reading the environment
+pub fn expand(path: &str) -> Result<PathBuf, PathError> {
+    expand_with(path, |name| env::var(name).ok())
+}
+
+/// Expands a leading `~` or `~/` to the home directory and `$NAME` or
+/// `${NAME}` anywhere to the variable's value, read through `var`. On Windows
+/// `/` becomes `\`; `%NAME%` is left alone.
+pub fn expand_with(path: &str, var: impl Fn(&str) -> Option<String>) -> Result<PathBuf, PathError> {
+    let mut expanded = String::new();
+    let mut rest = path;
+    if let Some(after) = path.strip_prefix('~') {
+        let user_len = after.find(['/', '\\']).unwrap_or(after.len());
+        if user_len > 0 {
+            return Err(PathError::OtherUser(after[..user_len].to_string()));
+        }
+        let home = var("HOME").or_else(|| var("USERPROFILE")).filter(|home| !home.is_empty());
+        expanded.push_str(&home.ok_or(PathError::NoHome)?);
+        rest = after;
+    }
+
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, next) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let len = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..len], &after[len..])
+            }
+        };
+        // A `$` that doesn't start a name is kept as it is
+        if name.is_empty() {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        expanded.push_str(&var(name).ok_or_else(|| PathError::Unset(name.to_string()))?);
+        rest = next;
+    }
+    expanded.push_str(rest);
+
+    if cfg!(windows) {
+        expanded = expanded.replace('/', "\\");
+    }
+    Ok(PathBuf::from(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looks variables up in a fixed list
+    fn vars(pairs: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| pairs.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+    }
+
+    #[test]
+    /// Test that a leading `~` becomes the home directory, and only a leading one
+    fn test_home() {
+        let var = vars(&[("HOME", "/home/ann")]);
+        assert_eq!(expand_with("~", &var), Ok(PathBuf::from("/home/ann")));
+        assert_eq!(expand_with("~/notes/draft.txt", &var), Ok(PathBuf::from("/home/ann/notes/draft.txt")));
+        assert_eq!(expand_with("notes/~draft.txt", &var), Ok(PathBuf::from("notes/~draft.txt")));
+
+        // Windows keeps the home elsewhere
+        let var = vars(&[("USERPROFILE", "/users/ann")]);
+        assert_eq!(expand_with("~/draft.txt", &var), Ok(PathBuf::from("/users/ann/draft.txt")));
+        assert_eq!(expand_with("~/draft.txt", vars(&[])), Err(PathError::NoHome));
+    }
+
+    #[test]
+    /// Test that another user's home is refused rather than made into a directory
+    fn test_other_user() {
+        let var = vars(&[("HOME", "/home/ann")]);
+        let error = expand_with("~bob/notes.txt", &var).unwrap_err();
+        assert_eq!(error, PathError::OtherUser("bob".to_string()));
+        assert_eq!(error.to_string(), "`~bob` isn't supported; write out the path to their home");
+    }
+
+    #[test]
+    /// Test that variables are expanded wherever they are, and that an unset
+    /// one is an error naming it
+    fn test_variables() {
+        let var = vars(&[("HOME", "/home/ann"), ("XDG_DATA_HOME", "/data"), ("PROJECT", "novel")]);
+        assert_eq!(expand_with("$XDG_DATA_HOME/typewriter", &var), Ok(PathBuf::from("/data/typewriter")));
+        assert_eq!(expand_with("~/${PROJECT}s/$PROJECT.md", &var), Ok(PathBuf::from("/home/ann/novels/novel.md")));
+        // A lone `$` or an unclosed brace is kept as typed
+        assert_eq!(expand_with("cost$/a$-b", &var), Ok(PathBuf::from("cost$/a$-b")));
+        assert_eq!(expand_with("${PROJECT", &var), Ok(PathBuf::from("${PROJECT")));
+        // Windows' own syntax isn't expanded
+        assert_eq!(expand_with("%PROJECT%.txt", &var), Ok(PathBuf::from("%PROJECT%.txt")));
+
+        let error = expand_with("$NOTES/draft.txt", &var).unwrap_err();
+        assert_eq!(error, PathError::Unset("NOTES".to_string()));
+        assert_eq!(error.to_string(), "$NOTES isn't set");
+        assert_eq!(io::Error::from(error).kind(), io::ErrorKind::InvalidInput);
+    }
+}
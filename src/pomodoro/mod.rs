@@ -0,0 +1,183 @@
+use std::time::{Duration, Instant};
+
+use crate::renderer::Overlay;
+use crate::sprint::{format_clock, parse_duration};
+
+/// Parses work and rest lengths such as "25/5" (minutes) or "50m/10m"
+pub fn parse_cycle(text: &str) -> Result<(Duration, Duration), String> {
+    let (work, rest) = text
+        .split_once('/')
+        .ok_or_else(|| "expected work and rest lengths such as 25/5".to_string())?;
+    let length = |part: &str| {
+        let part = part.trim();
+        if !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()) {
+            parse_duration(&format!("{}m", part))
+        } else {
+            parse_duration(part)
+        }
+    };
+    Ok((length(work)?, length(rest)?))
+}
+
+/// Which part of the cycle is running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Typing as usual
+    Working,
+    /// Typing is held off and the page is dimmed
+    Resting,
+}
+
+/// Alternates work and rest periods of fixed lengths, starting with work
+#[derive(Debug, Clone)]
+pub struct Pomodoro {
+    /// Length of a work period
+    work: Duration,
+    /// Length of a rest period
+    rest: Duration,
+    /// The period running now
+    phase: Phase,
+    /// When the period running now started
+    phase_started: Instant,
+    /// Work periods that ran their full length
+    cycles: usize,
+}
+
+impl Pomodoro {
+    /// Starts with a work period
+    pub fn start(work: Duration, rest: Duration, now: Instant) -> Self {
+        Self {
+            work,
+            rest,
+            phase: Phase::Working,
+            phase_started: now,
+            cycles: 0,
+        }
+    }
+
+    /// Returns whether a rest period is running
+    pub fn is_resting(&self) -> bool {
+        self.phase == Phase::Resting
+    }
+
+    /// Returns how many work periods have been completed
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// Returns when the period running now ends
+    pub fn deadline(&self) -> Instant {
+        let length = match self.phase {
+            Phase::Working => self.work,
+            Phase::Resting => self.rest,
+        };
+        self.phase_started + length
+    }
+
+    /// Returns how much of the period running now is left
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.deadline().saturating_duration_since(now)
+    }
+
+    /// Moves on to the next period once the current one has run out, catching
+    /// up on any missed while the loop wasn't looking. Returns the new phase if
+    /// it changed.
+    pub fn update(&mut self, now: Instant) -> Option<Phase> {
+        let before = self.phase;
+        while now >= self.deadline() {
+            self.phase_started = self.deadline();
+            self.phase = match self.phase {
+                Phase::Working => {
+                    self.cycles += 1;
+                    Phase::Resting
+                }
+                Phase::Resting => Phase::Working,
+            };
+        }
+        (self.phase != before).then_some(self.phase)
+    }
+
+    /// Describes a work period's countdown for the status row
+    pub fn status(&self, now: Instant) -> String {
+        format!("Pomodoro {}: {} left", self.cycles + 1, format_clock(self.remaining(now)))
+    }
+
+    /// Returns the box shown over the page while resting
+    pub fn rest_overlay(&self, now: Instant) -> Overlay {
+        Overlay {
+            title: "Rest".to_string(),
+            lines: vec![format!("Rest — {} remaining", format_clock(self.remaining(now)))],
+            highlighted: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    /// Returns a 25/5 pomodoro started at a fixed moment, and that moment
+    fn pomodoro() -> (Pomodoro, Instant) {
+        let start = Instant::now();
+        (Pomodoro::start(25 * MINUTE, 5 * MINUTE, start), start)
+    }
+
+    #[test]
+    /// Test the accepted ways of writing a cycle
+    fn test_parse_cycle() {
+        assert_eq!(parse_cycle("25/5"), Ok((25 * MINUTE, 5 * MINUTE)));
+        assert_eq!(parse_cycle("50m/90s"), Ok((50 * MINUTE, Duration::from_secs(90))));
+        assert!(parse_cycle("25").is_err());
+        assert!(parse_cycle("25/").is_err());
+        assert!(parse_cycle("0/5").is_err());
+    }
+
+    #[test]
+    /// Test going from work to rest and back
+    fn test_transitions() {
+        let (mut pomodoro, start) = pomodoro();
+        assert_eq!(pomodoro.update(start + 24 * MINUTE), None);
+        assert_eq!(pomodoro.status(start + 24 * MINUTE), "Pomodoro 1: 1:00 left");
+
+        assert_eq!(pomodoro.update(start + 25 * MINUTE), Some(Phase::Resting));
+        assert!(pomodoro.is_resting());
+        assert_eq!(pomodoro.cycles(), 1);
+        let overlay = pomodoro.rest_overlay(start + 25 * MINUTE + Duration::from_secs(28));
+        assert_eq!(overlay.lines, vec!["Rest — 4:32 remaining"]);
+
+        assert_eq!(pomodoro.update(start + 29 * MINUTE), None);
+        assert_eq!(pomodoro.update(start + 30 * MINUTE), Some(Phase::Working));
+        assert_eq!(pomodoro.deadline(), start + 55 * MINUTE);
+        assert_eq!(pomodoro.cycles(), 1);
+    }
+
+    #[test]
+    /// Test catching up after the clock jumped past several periods
+    fn test_missed_periods() {
+        let (mut pomodoro, start) = pomodoro();
+
+        // Two whole cycles and then some: resting in the third
+        assert_eq!(pomodoro.update(start + 86 * MINUTE), Some(Phase::Resting));
+        assert_eq!(pomodoro.cycles(), 3);
+        assert_eq!(pomodoro.remaining(start + 86 * MINUTE), 4 * MINUTE);
+
+        // A whole cycle later it is resting again, which isn't a change
+        assert_eq!(pomodoro.update(start + 116 * MINUTE), None);
+        assert_eq!(pomodoro.cycles(), 4);
+    }
+
+    #[test]
+    /// Test that quitting mid-period counts only the work periods completed
+    fn test_quitting_mid_period() {
+        let (mut pomodoro, start) = pomodoro();
+        pomodoro.update(start + 27 * MINUTE);
+        assert!(pomodoro.is_resting());
+        assert_eq!(pomodoro.cycles(), 1);
+
+        let (mut pomodoro, start) = self::pomodoro();
+        pomodoro.update(start + 24 * MINUTE);
+        assert_eq!(pomodoro.cycles(), 0);
+    }
+}
@@ -0,0 +1,282 @@
+use chrono::NaiveDate;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::thread;
+use unicode_width::UnicodeWidthChar;
+
+use crate::hooks;
+
+/// Columns on a printed line
+pub const PAGE_WIDTH: usize = 72;
+/// Lines on a printed page, header and footer included
+pub const PAGE_LINES: usize = 60;
+/// Lines the header takes at the top of a page: the title and a blank line
+const HEADER_LINES: usize = 2;
+/// Lines the footer takes at the bottom of a page: a blank line and the page number
+const FOOTER_LINES: usize = 2;
+/// Columns between tab stops
+const TAB_WIDTH: usize = 8;
+/// Lines of text a page holds between its header and footer
+pub const BODY_LINES: usize = PAGE_LINES - HEADER_LINES - FOOTER_LINES;
+
+/// Command the pages go to when the config file doesn't name one
+#[cfg(not(windows))]
+pub const DEFAULT_COMMAND: &str = "lpr";
+/// Command the pages go to when the config file doesn't name one
+#[cfg(windows)]
+pub const DEFAULT_COMMAND: &str = "powershell -NoProfile -Command \"$input | Out-Printer\"";
+
+/// Where printed pages go, so tests can see what would have been printed
+pub trait Spooler {
+    /// Sends formatted pages, separated by form feeds, to the printer
+    fn spool(&mut self, pages: &str, title: &str) -> io::Result<()>;
+}
+
+/// Prints by piping the pages into a shell command, with the document's name
+/// in `TYPEWRITER_FILE`
+pub struct CommandSpooler {
+    /// The shell command to run
+    command: String,
+}
+
+impl CommandSpooler {
+    /// Creates a spooler for a shell command
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+}
+
+impl Spooler for CommandSpooler {
+    fn spool(&mut self, pages: &str, title: &str) -> io::Result<()> {
+        log::info!("printing with: {}", self.command);
+        let mut child = hooks::shell(&self.command)
+            .env("TYPEWRITER_FILE", title)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Written from a thread of its own, so a command that stops reading
+        // can't leave both sides waiting on each other
+        let mut stdin = child.stdin.take().ok_or_else(|| io::Error::other("no input to the print command"))?;
+        let pages = pages.to_string();
+        let writer = thread::spawn(move || stdin.write_all(pages.as_bytes()));
+        let output = child.wait_with_output()?;
+        let written = writer.join().unwrap_or_else(|_| Err(io::Error::other("writing to the print command failed")));
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        for line in stderr.lines() {
+            log::warn!("print command: {}", line);
+        }
+        if !output.status.success() {
+            let reason = match (stderr.lines().next(), output.status.code()) {
+                (Some(line), _) => line.to_string(),
+                (None, Some(code)) => format!("exit {}", code),
+                (None, None) => "killed".to_string(),
+            };
+            return Err(io::Error::other(format!("the print command failed ({})", reason)));
+        }
+        written
+    }
+}
+
+/// Returns the heading for a document: the name of its file, or "Untitled"
+/// before it has one
+pub fn title(file_path: Option<&str>) -> String {
+    let name = file_path.and_then(|path| Path::new(path).file_name());
+    name.map_or_else(|| "Untitled".to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Returns the page, from 1, that the text's line `line` (from 0) is printed
+/// on, not counting lines too long that are wrapped onto the next
+pub fn page_at(line: usize) -> usize {
+    line / BODY_LINES + 1
+}
+
+/// Returns how many printed lines a line of text takes once wrapped
+pub fn printed_lines(line: &str) -> usize {
+    wrap(line).len()
+}
+
+/// Formats the text and sends it to the spooler, returning how many pages
+/// were printed
+pub fn print(spooler: &mut impl Spooler, text: &str, title: &str, date: NaiveDate) -> io::Result<usize> {
+    let pages = format_pages(text, title, date);
+    spooler.spool(&pages.join("\x0c"), title)?;
+    Ok(pages.len())
+}
+
+/// Returns a count of printed pages, such as "1 page" or "3 pages"
+pub fn pages_word(pages: usize) -> String {
+    format!("{} page{}", pages, if pages == 1 { "" } else { "s" })
+}
+
+/// Lays the text out on pages of `PAGE_LINES` lines of up to `PAGE_WIDTH`
+/// columns. Each starts with the title and date and ends with its number,
+/// blank lines filling out the last one. Lines too long are wrapped between
+/// words, or within a word longer than a line.
+pub fn format_pages(text: &str, title: &str, date: NaiveDate) -> Vec<String> {
+    let lines: Vec<String> = text.split('\n').flat_map(wrap).collect();
+    let bodies: Vec<&[String]> = lines.chunks(BODY_LINES).collect();
+    let count = bodies.len();
+
+    let date = date.format("%Y-%m-%d").to_string();
+    let title: String = title.chars().take(PAGE_WIDTH.saturating_sub(date.len() + 2)).collect();
+    let header = format!("{:<width$}{}", title, date, width = PAGE_WIDTH - date.len());
+    bodies
+        .iter()
+        .enumerate()
+        .map(|(idx, body)| {
+            let mut page = vec![header.clone(), String::new()];
+            page.extend(body.iter().cloned());
+            page.resize(PAGE_LINES - FOOTER_LINES, String::new());
+            let number = format!("Page {} of {}", idx + 1, count);
+            page.push(String::new());
+            page.push(format!("{:^width$}", number, width = PAGE_WIDTH).trim_end().to_string());
+            page.join("\n") + "\n"
+        })
+        .collect()
+}
+
+/// Splits a line of text into lines of up to `PAGE_WIDTH` columns, with tabs
+/// turned into spaces. A break goes after the last space that fits, which
+/// stays at the end of its line.
+fn wrap(line: &str) -> Vec<String> {
+    let mut expanded = String::new();
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = TAB_WIDTH - column % TAB_WIDTH;
+            expanded.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            expanded.push(c);
+            column += c.width().unwrap_or(0);
+        }
+    }
+
+    let mut wrapped = Vec::new();
+    let mut rest: Vec<char> = expanded.trim_end().chars().collect();
+    loop {
+        let mut width = 0;
+        let fits = rest.iter().take_while(|c| {
+            width += c.width().unwrap_or(0);
+            width <= PAGE_WIDTH
+        });
+        let fits = fits.count();
+        if fits == rest.len() {
+            wrapped.push(rest.into_iter().collect());
+            return wrapped;
+        }
+        let end = match rest[..fits].iter().rposition(|c| *c == ' ') {
+            Some(space) if space > 0 => space + 1,
+            _ => fits.max(1),
+        };
+        let line: String = rest.drain(..end).collect();
+        wrapped.push(line.trim_end().to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+
+    /// Keeps what would have been printed
+    #[derive(Default)]
+    struct Captured {
+        printed: Vec<(String, String)>,
+    }
+
+    impl Spooler for Captured {
+        fn spool(&mut self, pages: &str, title: &str) -> io::Result<()> {
+            self.printed.push((pages.to_string(), title.to_string()));
+            Ok(())
+        }
+    }
+
+    /// Returns the first of March 2024
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    #[test]
+    /// Test the header, footer and size of a page
+    fn test_single_page() {
+        let pages = format_pages("Dear Sir,\n\nYours.", "letter.txt", date());
+        assert_eq!(pages.len(), 1);
+        let lines: Vec<&str> = pages[0].lines().collect();
+        assert_eq!(lines.len(), PAGE_LINES);
+        assert_eq!(lines[0], format!("letter.txt{}2024-03-01", " ".repeat(52)));
+        assert_eq!(lines[0].chars().count(), PAGE_WIDTH);
+        assert_eq!(lines[1..6], ["", "Dear Sir,", "", "Yours.", ""]);
+        assert_eq!(lines[PAGE_LINES - 1], format!("{}Page 1 of 1", " ".repeat(30)));
+    }
+
+    #[test]
+    /// Test that a long text runs on to more pages, each numbered
+    fn test_page_breaks() {
+        let text: Vec<String> = (1..=60).map(|number| format!("Line {}", number)).collect();
+        let pages = format_pages(&text.join("\n"), "long.txt", date());
+        assert_eq!(pages.len(), 2);
+        let first: Vec<&str> = pages[0].lines().collect();
+        let second: Vec<&str> = pages[1].lines().collect();
+        assert_eq!((first[2], first[57]), ("Line 1", "Line 56"));
+        assert_eq!((second[2], second[5], second[6]), ("Line 57", "Line 60", ""));
+        assert!(first[59].ends_with("Page 1 of 2") && second[59].ends_with("Page 2 of 2"));
+        assert_eq!(second.len(), PAGE_LINES);
+        assert_eq!((page_at(0), page_at(55), page_at(56)), (1, 1, 2));
+    }
+
+    #[test]
+    /// Test wrapping long lines between words, within words too long for a
+    /// line, and with tabs and wide characters
+    fn test_wrap() {
+        let words = "word ".repeat(20);
+        assert_eq!(wrap(&words), [words[..70].trim_end(), "word word word word word word"].map(str::to_string));
+        let unbroken = "x".repeat(150);
+        assert_eq!(wrap(&unbroken), ["x".repeat(72), "x".repeat(72), "x".repeat(6)]);
+        assert_eq!(wrap("a\tb"), ["a       b"]);
+        assert_eq!(wrap(&"漢".repeat(40)), ["漢".repeat(36), "漢".repeat(4)]);
+        assert_eq!(wrap(""), [""]);
+    }
+
+    #[test]
+    /// Test the heading for a document with and without a file
+    fn test_title() {
+        assert_eq!(title(Some("/home/me/letters/dear-sir.txt")), "dear-sir.txt");
+        assert_eq!(title(None), "Untitled");
+    }
+
+    #[test]
+    /// Test that the pages go to the spooler as one job split by form feeds
+    fn test_print() {
+        let mut spooler = Captured::default();
+        let text = vec!["line"; 100].join("\n");
+        assert_eq!(print(&mut spooler, &text, "draft.md", date()).unwrap(), 2);
+        let (pages, title) = &spooler.printed[0];
+        assert_eq!(title, "draft.md");
+        assert_eq!(pages.split('\x0c').count(), 2);
+        assert_eq!(pages.lines().count(), 2 * PAGE_LINES);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    /// Test that the command gets the pages and the title, and that a
+    /// failing one says why
+    fn test_command_spooler() {
+        let dir = ScratchDir::new("print");
+        let path = dir.join("notes.txt");
+        let command = format!("{{ echo \"$TYPEWRITER_FILE\"; cat; }} > {}", path.display());
+        CommandSpooler::new(command).spool("page\x0c", "draft.md").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "draft.md\npage\x0c");
+
+        let error = CommandSpooler::new("echo 'lpr: no default destination' >&2; exit 1".to_string())
+            .spool("page", "draft.md")
+            .unwrap_err();
+        assert_eq!(error.to_string(), "the print command failed (lpr: no default destination)");
+        let error = CommandSpooler::new("exit 3".to_string()).spool("page", "draft.md").unwrap_err();
+        assert_eq!(error.to_string(), "the print command failed (exit 3)");
+    }
+}
@@ -0,0 +1,243 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many files are remembered
+pub const LIMIT: usize = 10;
+
+/// Where the cursor and the page were in a file when a session on it ended.
+/// Lists saved before the column and scroll were kept load with them at 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Position {
+    /// Line the cursor was on
+    pub line: usize,
+    /// Column of the cursor on that line
+    pub column: usize,
+    /// Line shown at the top of the page
+    pub scroll_top: usize,
+}
+
+/// A file opened in an earlier session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentFile {
+    /// Absolute path of the file
+    pub path: PathBuf,
+    /// Where the cursor and page were when the session ended
+    #[serde(flatten)]
+    pub position: Position,
+}
+
+/// The files opened most recently, newest first, kept in `recent.json`
+#[derive(Debug, Clone, Default)]
+pub struct RecentFiles {
+    /// Where the list is saved, or None if there is no data directory
+    store: Option<PathBuf>,
+    /// The files, newest first
+    files: Vec<RecentFile>,
+}
+
+/// Returns the directory for files typewriter keeps for the user
+/// (`$XDG_DATA_HOME/typewriter` or `~/.local/share/typewriter`)
+pub fn data_dir() -> Option<PathBuf> {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))?;
+    Some(data_dir.join("typewriter"))
+}
+
+impl RecentFiles {
+    /// Loads the list from the data directory
+    pub fn load() -> Self {
+        match data_dir() {
+            Some(dir) => Self::load_from(dir.join("recent.json")),
+            None => Self::default(),
+        }
+    }
+
+    /// Loads the list from a file. A missing file gives an empty list, and so
+    /// does a corrupt one, which is overwritten on the next save. Files that
+    /// no longer exist are dropped.
+    pub fn load_from(store: PathBuf) -> Self {
+        let files = match fs::read_to_string(&store) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                log::warn!("starting a fresh recent file list; {} is unreadable: {}", store.display(), e);
+                Vec::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                log::warn!("couldn't read {}: {}", store.display(), e);
+                Vec::new()
+            }
+        };
+        let mut recent = Self {
+            store: Some(store),
+            files,
+        };
+        recent.prune();
+        recent
+    }
+
+    /// Returns the remembered files, newest first
+    pub fn files(&self) -> &[RecentFile] {
+        &self.files
+    }
+
+    /// Returns where the cursor and page were when a file was last closed
+    pub fn position_for(&self, path: &Path) -> Option<Position> {
+        let path = absolute(path);
+        self.files.iter().find(|file| file.path == path).map(|file| file.position)
+    }
+
+    /// Puts a file at the top of the list with its cursor and page position,
+    /// dropping the oldest once there are more than `LIMIT`
+    pub fn record(&mut self, path: &Path, position: Position) {
+        let path = absolute(path);
+        self.files.retain(|file| file.path != path);
+        self.files.insert(0, RecentFile { path, position });
+        self.files.truncate(LIMIT);
+    }
+
+    /// Writes the list back to where it was loaded from
+    pub fn save(&self) -> io::Result<()> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        if let Some(dir) = store.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(&self.files).map_err(io::Error::other)?;
+        fs::write(store, text)
+    }
+
+    /// Drops files that have been deleted or moved since they were opened
+    fn prune(&mut self) {
+        self.files.retain(|file| file.path.is_file());
+    }
+}
+
+/// Returns the absolute path of a file, so the same file opened from
+/// different directories is remembered once
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+
+
+    /// Creates empty files with the given names, returning their paths
+    fn touch(dir: &Path, names: &[&str]) -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                fs::write(&path, "").unwrap();
+                absolute(&path)
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Test that recording puts a file first, once, and keeps only the newest
+    fn test_ordering() {
+        let dir = ScratchDir::new("recent-ordering");
+        let names: Vec<String> = (0..12).map(|idx| format!("{}.txt", idx)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let paths = touch(&dir, &names);
+
+        let mut recent = RecentFiles::load_from(dir.join("recent.json"));
+        for path in &paths {
+            recent.record(path, Position::default());
+        }
+        assert_eq!(recent.files().len(), LIMIT);
+        assert_eq!(recent.files()[0].path, paths[11]);
+        assert_eq!(recent.files()[LIMIT - 1].path, paths[2]);
+
+        // Opening one again moves it to the top without a duplicate
+        let position = Position { line: 3, column: 1, scroll_top: 0 };
+        recent.record(&paths[5], position);
+        assert_eq!(recent.files()[0], RecentFile { path: paths[5].clone(), position });
+        assert_eq!(recent.files().iter().filter(|file| file.path == paths[5]).count(), 1);
+        assert_eq!(recent.files().len(), LIMIT);
+    }
+
+    #[test]
+    /// Test that the list and positions survive a save and load, and that a
+    /// list from before columns and scrolling were kept still loads
+    fn test_round_trip() {
+        let dir = ScratchDir::new("recent-round-trip");
+        let paths = touch(&dir, &["a.txt", "b.txt"]);
+        let store = dir.join("state").join("recent.json");
+
+        let mut recent = RecentFiles::load_from(store.clone());
+        let (a, b) = (Position { line: 4, column: 2, scroll_top: 1 }, Position { line: 9, column: 0, scroll_top: 5 });
+        recent.record(&paths[0], a);
+        recent.record(&paths[1], b);
+        recent.save().unwrap();
+
+        let recent = RecentFiles::load_from(store.clone());
+        assert_eq!(recent.files().len(), 2);
+        assert_eq!(recent.files()[0].path, paths[1]);
+        assert_eq!(recent.position_for(&paths[0]), Some(a));
+        assert_eq!(recent.position_for(&dir.join(".").join("b.txt")), Some(b));
+        assert_eq!(recent.position_for(&dir.join("c.txt")), None);
+
+        let old = serde_json::json!([{ "path": paths[0], "line": 7 }]);
+        fs::write(&store, old.to_string()).unwrap();
+        let recent = RecentFiles::load_from(store);
+        assert_eq!(recent.position_for(&paths[0]), Some(Position { line: 7, ..Position::default() }));
+    }
+
+    #[test]
+    /// Test that a file named relative to the working directory is
+    /// remembered under its absolute path
+    fn test_relative_path() {
+        let dir = ScratchDir::new("recent-relative");
+        let mut recent = RecentFiles::load_from(dir.join("recent.json"));
+        let position = Position { line: 2, column: 0, scroll_top: 0 };
+        recent.record(Path::new("Cargo.toml"), position);
+        let absolute = env::current_dir().unwrap().join("Cargo.toml");
+        assert_eq!(recent.files()[0].path, fs::canonicalize(&absolute).unwrap());
+        assert_eq!(recent.position_for(&absolute), Some(position));
+        assert_eq!(recent.position_for(Path::new("./Cargo.toml")), Some(position));
+    }
+
+    #[test]
+    /// Test that files that are gone are dropped on the next load
+    fn test_pruning() {
+        let dir = ScratchDir::new("recent-pruning");
+        let paths = touch(&dir, &["kept.txt", "deleted.txt"]);
+        let store = dir.join("recent.json");
+
+        let mut recent = RecentFiles::load_from(store.clone());
+        recent.record(&paths[0], Position::default());
+        recent.record(&paths[1], Position::default());
+        recent.save().unwrap();
+        fs::remove_file(&paths[1]).unwrap();
+
+        let recent = RecentFiles::load_from(store);
+        assert_eq!(recent.files().len(), 1);
+        assert_eq!(recent.files()[0].path, paths[0]);
+    }
+
+    #[test]
+    /// Test that a corrupt list is replaced by an empty one
+    fn test_corrupt_store() {
+        let dir = ScratchDir::new("recent-corrupt");
+        let paths = touch(&dir, &["a.txt"]);
+        let store = dir.join("recent.json");
+        fs::write(&store, "{ not json").unwrap();
+
+        let mut recent = RecentFiles::load_from(store.clone());
+        assert!(recent.files().is_empty());
+
+        recent.record(&paths[0], Position::default());
+        recent.save().unwrap();
+        assert_eq!(RecentFiles::load_from(store).files().len(), 1);
+    }
+}
@@ -0,0 +1,117 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::editor::Buffer;
+
+/// Returns the directory for files typewriter keeps between sessions
+/// (`$XDG_STATE_HOME/typewriter` or `~/.local/state/typewriter`)
+fn state_dir() -> Option<PathBuf> {
+    let state_dir = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+    Some(state_dir.join("typewriter"))
+}
+
+/// Returns where unsaved work is rescued to: `<file>.recovery` next to the
+/// file, or `untitled.recovery` in the state directory for a buffer without one
+fn recovery_path(file_path: Option<&str>, state_dir: Option<PathBuf>) -> Option<PathBuf> {
+    match file_path {
+        Some(path) => {
+            let mut path = OsString::from(path);
+            path.push(".recovery");
+            Some(PathBuf::from(path))
+        }
+        None => Some(state_dir?.join("untitled.recovery")),
+    }
+}
+
+/// Writes unsaved changes somewhere safe before an unplanned exit. With
+/// `autosave` a buffer that has a file is saved to it; otherwise the text goes
+/// to the recovery file. Returns where it went, or None if nothing was unsaved.
+pub fn write_snapshot(buffer: &mut Buffer, autosave: bool) -> io::Result<Option<PathBuf>> {
+    write_snapshot_in(buffer, autosave, state_dir())
+}
+
+/// Does the work of `write_snapshot` with the state directory given
+fn write_snapshot_in(buffer: &mut Buffer, autosave: bool, state_dir: Option<PathBuf>) -> io::Result<Option<PathBuf>> {
+    if !buffer.is_modified() {
+        return Ok(None);
+    }
+
+    if let (true, Some(path)) = (autosave, buffer.file_path.clone()) {
+        buffer.save()?;
+        return Ok(Some(PathBuf::from(path)));
+    }
+
+    let path = recovery_path(buffer.file_path.as_deref(), state_dir)
+        .ok_or_else(|| io::Error::other("No directory to write the recovery file to"))?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, buffer.saved_text())?;
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+    use std::path::Path;
+    use std::time::Duration;
+
+
+    /// Returns a buffer with unsaved text
+    fn typed_buffer(text: &str, file_path: Option<&Path>) -> Buffer {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        if let Some(path) = file_path {
+            buffer.set_file_path(path.to_str().unwrap()).unwrap();
+        }
+        for c in text.chars() {
+            buffer.insert_char(c);
+        }
+        buffer
+    }
+
+    #[test]
+    /// Test that an unchanged buffer writes nothing
+    fn test_nothing_unsaved() {
+        let dir = ScratchDir::new("recovery-unchanged");
+        let mut buffer = Buffer::new(Duration::ZERO);
+        assert_eq!(write_snapshot_in(&mut buffer, false, Some(dir.to_path_buf())).unwrap(), None);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    /// Test that a buffer without a file goes to the state directory
+    fn test_untitled_snapshot() {
+        let dir = ScratchDir::new("recovery-untitled");
+        let mut buffer = typed_buffer("draft", None);
+        let path = write_snapshot_in(&mut buffer, true, Some(dir.join("typewriter"))).unwrap().unwrap();
+        assert_eq!(path, dir.join("typewriter").join("untitled.recovery"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "draft");
+
+        assert!(write_snapshot_in(&mut buffer, false, None).is_err());
+    }
+
+    #[test]
+    /// Test that a buffer with a file is rescued next to it, or into it with autosave
+    fn test_file_snapshot() {
+        let dir = ScratchDir::new("recovery-file");
+        let file = dir.join("notes.txt");
+        fs::write(&file, "old").unwrap();
+
+        let mut buffer = typed_buffer("new", Some(&file));
+        let path = write_snapshot_in(&mut buffer, false, None).unwrap().unwrap();
+        assert_eq!(path, dir.join("notes.txt.recovery"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old");
+
+        let path = write_snapshot_in(&mut buffer, true, None).unwrap().unwrap();
+        assert_eq!(path, file);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new\n");
+        assert!(!buffer.is_modified());
+    }
+}
@@ -0,0 +1,297 @@
+use crossterm::style::{Color, StyledContent, Stylize};
+use std::fmt::Display;
+use std::time::Duration;
+
+use super::{Shade, Tint};
+use crate::editor::{Character, CharacterState};
+
+/// How long to wait for the terminal to say what its background is
+const QUERY_TIMEOUT: Duration = Duration::from_millis(150);
+
+/// Whether the terminal draws text on a light or a dark background
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    /// Ask the terminal when it starts, taking it as dark if there is no answer
+    Auto,
+    /// Dark text on a light background
+    Light,
+    /// Light text on a dark background
+    Dark,
+}
+
+/// Parses a background setting as given on the command line or in the config
+pub fn parse_background(value: &str) -> Result<Background, String> {
+    match value {
+        "auto" => Ok(Background::Auto),
+        "light" => Ok(Background::Light),
+        "dark" => Ok(Background::Dark),
+        _ => Err(format!("must be `light`, `dark` or `auto`, not `{}`", value)),
+    }
+}
+
+impl Background {
+    /// Styles text outside the focus. The dim attribute fades text towards the
+    /// background, which leaves almost nothing of it on light terminals.
+    pub(crate) fn dim<D: Display>(self, styled: StyledContent<D>) -> StyledContent<D> {
+        match self {
+            Background::Light => styled.with(Color::DarkGrey),
+            Background::Auto | Background::Dark => styled.dim(),
+        }
+    }
+
+    /// Styles text well before the focus, fainter than `dim`
+    fn faint<D: Display>(self, styled: StyledContent<D>) -> StyledContent<D> {
+        match self {
+            Background::Light => styled.with(Color::Grey),
+            Background::Auto | Background::Dark => styled.with(Color::DarkGrey).dim(),
+        }
+    }
+
+    /// Styles a character of the page. A marked-out one is struck through
+    /// however far back it is, so what was just crossed out stays readable
+    /// on the focused lines and old corrections fade with their lines. A
+    /// highlighted character is never drawn fainter than dim, so it can be
+    /// read on the highlight. `tint` colors it for a line with many
+    /// corrections or one past the page being fitted, and `changed`
+    /// underlines it.
+    pub(crate) fn character(
+        self,
+        character: &Character,
+        shade: Shade,
+        highlight: bool,
+        tint: Option<Tint>,
+        changed: bool,
+    ) -> StyledContent<char> {
+        let mut styled = match (&character.state, self) {
+            (CharacterState::Normal, _) => character.value.stylize(),
+            (CharacterState::MarkedOut, Background::Light) => character.value.crossed_out().with(Color::DarkGrey),
+            (CharacterState::MarkedOut, Background::Auto | Background::Dark) => character.value.crossed_out(),
+        };
+        styled = match (tint, self) {
+            (None, _) => styled,
+            (Some(Tint::Corrections), Background::Light) => styled.with(Color::DarkMagenta),
+            (Some(Tint::Corrections), Background::Auto | Background::Dark) => styled.with(Color::DarkYellow),
+            (Some(Tint::Overflow), Background::Light) => styled.with(Color::DarkBlue),
+            (Some(Tint::Overflow), Background::Auto | Background::Dark) => styled.with(Color::DarkCyan),
+        };
+        if changed {
+            styled = styled.underlined();
+        }
+        styled = match shade {
+            Shade::Bright => styled,
+            Shade::Faint if !highlight => self.faint(styled),
+            Shade::Dim | Shade::Faint => self.dim(styled),
+        };
+        if highlight {
+            styled = match self {
+                Background::Light => styled.on(Color::Grey),
+                Background::Auto | Background::Dark => styled.reverse(),
+            };
+        }
+        styled
+    }
+}
+
+/// Asks the terminal for its background color. Must be called in raw mode and
+/// before anything else reads input, since the answer arrives as input.
+#[cfg(unix)]
+pub(crate) fn detect() -> Option<Background> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    // Every terminal answers the device attributes query, so its answer marks
+    // the end of the replies even when the color query is ignored. Nothing is
+    // left behind to turn up as key presses.
+    tty.write_all(b"\x1b]11;?\x07\x1b[c").ok()?;
+    tty.flush().ok()?;
+
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+    while !has_device_attributes(&reply) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            log::debug!("no answer from the terminal about its background");
+            break;
+        }
+        let mut poll = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: the pollfd is valid for the one entry given
+        if unsafe { libc::poll(&mut poll, 1, left.as_millis() as libc::c_int) } <= 0 {
+            continue;
+        }
+        let mut chunk = [0u8; 256];
+        match tty.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => reply.extend_from_slice(&chunk[..read]),
+        }
+    }
+    parse_reply(&reply)
+}
+
+/// Asks the terminal for its background color. There is no way to wait for
+/// the answer here, so the background is never known.
+#[cfg(not(unix))]
+pub(crate) fn detect() -> Option<Background> {
+    None
+}
+
+/// Returns whether the device attributes answer (`ESC [ ? ... c`) has arrived
+fn has_device_attributes(reply: &[u8]) -> bool {
+    reply.windows(3).enumerate().any(|(start, window)| {
+        window == b"\x1b[?"
+            && reply[start + 3..]
+                .iter()
+                .find(|byte| !(byte.is_ascii_digit() || **byte == b';'))
+                .is_some_and(|&byte| byte == b'c')
+    })
+}
+
+/// Reads the background from the terminal's answer to the OSC 11 query, such as
+/// `ESC ] 11 ; rgb:ffff/ffff/ffff BEL`. The answer may end with BEL or ST and
+/// have anything around it.
+pub(crate) fn parse_reply(reply: &[u8]) -> Option<Background> {
+    let start = reply.windows(4).position(|window| window == b"]11;")? + 4;
+    let rest = &reply[start..];
+    let end = rest.iter().position(|&byte| byte == 0x07 || byte == 0x1b)?;
+    let color = std::str::from_utf8(&rest[..end]).ok()?;
+    let components = color.strip_prefix("rgb:").or_else(|| color.strip_prefix("rgba:"))?;
+
+    let mut channels = components.split('/').map(|hex| {
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = 16u32.checked_pow(hex.len() as u32).filter(|_| !hex.is_empty() && hex.len() <= 4)? - 1;
+        Some(value as f64 / max as f64)
+    });
+    let (red, green, blue) = (channels.next()??, channels.next()??, channels.next()??);
+
+    let luminance = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+    Some(if luminance > 0.5 { Background::Light } else { Background::Dark })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the answers common terminals give
+    fn test_parse_reply() {
+        // xterm, VTE and kitty answer in kind, with 16 bits a channel
+        assert_eq!(parse_reply(b"\x1b]11;rgb:ffff/ffff/ffff\x07"), Some(Background::Light));
+        assert_eq!(parse_reply(b"\x1b]11;rgb:0000/0000/0000\x1b\\"), Some(Background::Dark));
+        assert_eq!(parse_reply(b"\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\"), Some(Background::Dark));
+        // Solarized light, with 8 bits a channel
+        assert_eq!(parse_reply(b"\x1b]11;rgb:fd/f6/e3\x07"), Some(Background::Light));
+        // rxvt adds an alpha channel
+        assert_eq!(parse_reply(b"\x1b]11;rgba:fafa/fafa/fafa/ffff\x07"), Some(Background::Light));
+        // A saturated blue is dark, whatever its numbers
+        assert_eq!(parse_reply(b"\x1b]11;rgb:0000/0000/ffff\x07"), Some(Background::Dark));
+
+        // The device attributes answer that follows is ignored
+        let reply = b"\x1b]11;rgb:ffff/ffff/dddd\x1b\\\x1b[?62;22c";
+        assert_eq!(parse_reply(reply), Some(Background::Light));
+    }
+
+    #[test]
+    /// Test the attributes and colors of every character state, shade and
+    /// highlight on both backgrounds
+    fn test_character_styles() {
+        use crossterm::style::{Attribute, Attributes};
+        use Attribute::{CrossedOut, Dim, Reverse};
+        use Background::{Dark, Light};
+        use Color::{DarkGrey, Grey};
+
+        let bright = Shade::Bright;
+        let (dim, faint) = (Shade::Dim, Shade::Faint);
+        // Background, marked out, shade, highlighted: attributes, foreground, background
+        #[rustfmt::skip]
+        let table = [
+            (Dark, false, bright, false, vec![], None, None),
+            (Dark, false, dim, false, vec![Dim], None, None),
+            (Dark, false, faint, false, vec![Dim], Some(DarkGrey), None),
+            (Dark, true, bright, false, vec![CrossedOut], None, None),
+            (Dark, true, dim, false, vec![CrossedOut, Dim], None, None),
+            (Dark, true, faint, false, vec![CrossedOut, Dim], Some(DarkGrey), None),
+            (Dark, false, bright, true, vec![Reverse], None, None),
+            (Dark, false, dim, true, vec![Dim, Reverse], None, None),
+            (Dark, false, faint, true, vec![Dim, Reverse], None, None),
+            (Dark, true, bright, true, vec![CrossedOut, Reverse], None, None),
+            (Dark, true, dim, true, vec![CrossedOut, Dim, Reverse], None, None),
+            (Dark, true, faint, true, vec![CrossedOut, Dim, Reverse], None, None),
+            (Light, false, bright, false, vec![], None, None),
+            (Light, false, dim, false, vec![], Some(DarkGrey), None),
+            (Light, false, faint, false, vec![], Some(Grey), None),
+            (Light, true, bright, false, vec![CrossedOut], Some(DarkGrey), None),
+            (Light, true, dim, false, vec![CrossedOut], Some(DarkGrey), None),
+            (Light, true, faint, false, vec![CrossedOut], Some(Grey), None),
+            (Light, false, bright, true, vec![], None, Some(Grey)),
+            (Light, false, dim, true, vec![], Some(DarkGrey), Some(Grey)),
+            (Light, false, faint, true, vec![], Some(DarkGrey), Some(Grey)),
+            (Light, true, bright, true, vec![CrossedOut], Some(DarkGrey), Some(Grey)),
+            (Light, true, dim, true, vec![CrossedOut], Some(DarkGrey), Some(Grey)),
+            (Light, true, faint, true, vec![CrossedOut], Some(DarkGrey), Some(Grey)),
+        ];
+        for (background, marked_out, shade, highlight, attributes, foreground, on) in table {
+            let mut character = Character::new('a', crate::editor::BufferTime(0));
+            if marked_out {
+                character.mark_out();
+            }
+            let styled = background.character(&character, shade, highlight, None, false);
+            let case = (background, marked_out, shade, highlight);
+            assert_eq!(styled.style().attributes, Attributes::from(&attributes[..]), "{:?}", case);
+            assert_eq!(styled.style().foreground_color, foreground, "{:?}", case);
+            assert_eq!(styled.style().background_color, on, "{:?}", case);
+        }
+    }
+
+    #[test]
+    /// Test the colors of each tint on both backgrounds
+    fn test_tints() {
+        let character = Character::new('a', crate::editor::BufferTime(0));
+        #[rustfmt::skip]
+        let table = [
+            (Background::Dark, Tint::Corrections, Color::DarkYellow),
+            (Background::Dark, Tint::Overflow, Color::DarkCyan),
+            (Background::Light, Tint::Corrections, Color::DarkMagenta),
+            (Background::Light, Tint::Overflow, Color::DarkBlue),
+        ];
+        for (background, tint, color) in table {
+            let styled = background.character(&character, Shade::Bright, false, Some(tint), false);
+            assert_eq!(styled.style().foreground_color, Some(color), "{:?}", (background, tint));
+        }
+    }
+
+    #[test]
+    /// Test that missing or garbled answers give nothing
+    fn test_parse_bad_reply() {
+        assert_eq!(parse_reply(b""), None);
+        assert_eq!(parse_reply(b"\x1b[?1;2c"), None);
+        assert_eq!(parse_reply(b"\x1b]11;rgb:ffff/ffff/ffff"), None);
+        assert_eq!(parse_reply(b"\x1b]11;rgb:ffff/ffff\x07"), None);
+        assert_eq!(parse_reply(b"\x1b]11;rgb:gggg/0000/0000\x07"), None);
+        assert_eq!(parse_reply(b"\x1b]11;rgb:fffff/0/0\x07"), None);
+        assert_eq!(parse_reply(b"\x1b]11;white\x07"), None);
+    }
+
+    #[test]
+    /// Test spotting the end of the terminal's answers
+    fn test_has_device_attributes() {
+        assert!(has_device_attributes(b"\x1b[?62;22c"));
+        assert!(has_device_attributes(b"\x1b]11;rgb:0/0/0\x07\x1b[?1;2c"));
+        assert!(!has_device_attributes(b"\x1b]11;rgb:0/0/0\x07"));
+        assert!(!has_device_attributes(b"\x1b[?62;2"));
+        assert!(!has_device_attributes(b"\x1b[?62;2u"));
+    }
+
+    #[test]
+    /// Test the setting's values
+    fn test_parse_background() {
+        assert_eq!(parse_background("light"), Ok(Background::Light));
+        assert_eq!(parse_background("auto"), Ok(Background::Auto));
+        assert_eq!(parse_background("Dark"), Err("must be `light`, `dark` or `auto`, not `Dark`".to_string()));
+    }
+}
@@ -0,0 +1,730 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::Instant;
+use unicode_width::UnicodeWidthChar;
+
+use super::{Focus, Overlay, OverlayLayout, StatusTone, VISIBLE_LINES};
+use crate::editor::{Buffer, Character, CharacterState};
+
+/// Lines with less of their typing left unmarked than this are tinted, when
+/// tinting is on
+const TINT_BELOW: f32 = 0.75;
+
+/// Drawn faintly at the hard margin while a word could be hyphenated there
+pub(super) const HYPHEN_HINT: &str = "‐?";
+
+/// Drawn faintly in place of each space or tab at the end of a line, when
+/// trailing whitespace or the line being typed's trailing spaces are shown
+const TRAILING_SPACE: char = '·';
+
+/// Dimmed lines more than this many lines above the focus are drawn faint
+const FAINT_AFTER: usize = 5;
+
+/// Why a row is drawn in a color of its own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tint {
+    /// The line has many corrections
+    Corrections,
+    /// The line goes past the page being fitted
+    Overflow,
+}
+
+/// How brightly a character is drawn, by how far its line is from the focus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Shade {
+    /// In the focus, at full brightness
+    Bright,
+    /// Just before the focus, or behind an overlay
+    Dim,
+    /// Well before the focus
+    Faint,
+}
+
+/// Everything besides the buffer and the scroll position that decides what a
+/// frame shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    /// What isn't dimmed
+    pub focus: Focus,
+    /// Blank (columns, rows) kept left of and above the page
+    pub padding: (u16, u16),
+    /// Whether to tint lines with many corrections
+    pub tint_corrections: bool,
+    /// First buffer line past the page being fitted, tinted from there on
+    pub overflow_from: Option<usize>,
+    /// Whether to lay out for a screen reader: nothing dimmed or hinted, and a
+    /// plain status row
+    pub accessible: bool,
+    /// Whether spaces and tabs at the ends of lines are drawn as faint dots
+    pub show_trailing_whitespace: bool,
+    /// Whether spaces and tabs at the end of the cursor's line are drawn as
+    /// faint dots while it is typed
+    pub show_current_trailing_spaces: bool,
+    /// Whether what was typed or marked out since the last save is underlined
+    pub show_changes: bool,
+    /// Message shown in the bottom row, if any
+    pub status: Option<String>,
+    /// Column of the cursor in the bottom row while the user types there
+    pub status_cursor: Option<usize>,
+    /// How the status message is colored
+    pub status_tone: StatusTone,
+    /// Boxes drawn over the page, bottom first
+    pub overlays: Vec<Overlay>,
+    /// Column of the margin stop on the ruler across the top of the page, if shown
+    pub ruler: Option<usize>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            focus: Focus::Lines(VISIBLE_LINES),
+            padding: (0, 0),
+            tint_corrections: false,
+            overflow_from: None,
+            accessible: false,
+            show_trailing_whitespace: false,
+            show_current_trailing_spaces: false,
+            show_changes: false,
+            status: None,
+            status_cursor: None,
+            status_tone: StatusTone::Normal,
+            overlays: Vec::new(),
+            ruler: None,
+        }
+    }
+}
+
+/// The part of the page a frame shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// Terminal size as (columns, rows), padding included
+    pub size: (u16, u16),
+    /// Buffer line shown in the top row
+    pub scroll_top: usize,
+    /// Page column shown in the leftmost column
+    pub scroll_left: usize,
+}
+
+impl Viewport {
+    /// Returns the space inside the padding as (columns, rows)
+    pub fn inner(&self, padding: (u16, u16)) -> (u16, u16) {
+        (self.size.0.saturating_sub(padding.0), self.size.1.saturating_sub(padding.1))
+    }
+
+    /// Number of rows the page gets below the padding; the bottom row belongs to
+    /// the status message
+    pub fn page_rows(&self, padding: (u16, u16)) -> usize {
+        (self.inner(padding).1 as usize).saturating_sub(1).max(1)
+    }
+}
+
+/// Everything that goes into one drawn screen, worked out before anything is
+/// drawn. The last frame drawn is kept so only what changed is written to the
+/// terminal again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Revealed characters of each row of the page, with how brightly they are
+    /// drawn and whether they are highlighted, from the first column in view
+    pub lines: Vec<Vec<(Character, Shade, bool)>>,
+    /// For each row, how many characters are scrolled off to the left, and
+    /// how many columns a wide one straddling the edge leaves blank
+    pub hidden: Vec<(usize, usize)>,
+    /// How each row is tinted, if it is
+    pub tinted: Vec<Option<Tint>>,
+    /// Whether characters changed since the last save are underlined
+    pub show_changes: bool,
+    /// Buffer line shown in the top row
+    pub scroll_top: usize,
+    /// Page column shown in the leftmost column
+    pub scroll_left: usize,
+    /// Where the page cursor goes, or None if its line is scrolled out of view
+    pub cursor_pos: Option<(u16, u16)>,
+    /// Where the terminal's cursor is shown: in the page, in the status row
+    /// while a prompt is typed there, or nowhere under an overlay
+    pub cursor: Option<(u16, u16)>,
+    /// Terminal size the frame was laid out for
+    pub size: (u16, u16),
+    /// Blank (columns, rows) left of and above the page
+    pub padding: (u16, u16),
+    /// Message in the bottom row, cut to the width inside the padding
+    pub status: Option<String>,
+    /// Color of the bottom row
+    pub status_tone: StatusTone,
+    /// Boxes drawn over the page, bottom first, laid out for `size`
+    pub layers: Vec<OverlayLayout>,
+    /// Where the hyphenation hint is drawn, if anywhere
+    pub hyphen_hint: Option<(u16, u16)>,
+    /// Marks of the ruler over the top row while one is shown, each with
+    /// whether it is dimmed and reversed
+    pub ruler: Option<Vec<(char, bool, bool)>>,
+}
+
+impl Frame {
+    /// Returns whether the screen showing this frame can be brought up to
+    /// `next` by redrawing just the rows that changed: the screen is the same
+    /// size, changes are underlined in both or neither and nothing is drawn
+    /// over the page in either
+    pub fn updates_to(&self, next: &Frame) -> bool {
+        self.size == next.size
+            && self.padding == next.padding
+            && self.show_changes == next.show_changes
+            && self.layers.is_empty()
+            && next.layers.is_empty()
+            && self.ruler.is_none()
+            && next.ruler.is_none()
+    }
+
+    /// Returns whether row `idx` of the page differs between this frame and
+    /// `next`, the hyphenation hint included
+    pub fn row_changed(&self, next: &Frame, idx: usize) -> bool {
+        let hinted = |frame: &Frame| frame.hyphen_hint.is_some_and(|(_, row)| row == frame.padding.1 + idx as u16);
+        self.lines.get(idx) != next.lines.get(idx)
+            || self.hidden.get(idx) != next.hidden.get(idx)
+            || self.tinted.get(idx) != next.tinted.get(idx)
+            || (self.hyphen_hint != next.hyphen_hint && (hinted(self) || hinted(next)))
+    }
+}
+
+/// Returns how many columns characters take up on screen, wide ones two
+pub(super) fn display_column(characters: &[Character]) -> usize {
+    characters.iter().map(|character| character.value.width().unwrap_or(0)).sum()
+}
+
+/// Swaps typographic glyphs for the plain characters a screen reader reads
+/// without fuss
+fn plain_text(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '…' => plain.push_str("..."),
+            '‐' | '–' | '—' => plain.push('-'),
+            '‘' | '’' => plain.push('\''),
+            '“' | '”' => plain.push('"'),
+            c => plain.push(c),
+        }
+    }
+    plain
+}
+
+/// What a row of the page is laid out from besides its line, whose revision
+/// stands for its characters
+#[derive(Debug, Clone, PartialEq)]
+struct RowKey {
+    /// Buffer revision the line last changed in
+    revision: u64,
+    /// Page column shown in the leftmost column
+    scroll_left: usize,
+    /// Column from which the line is highlighted, in mark-out mode on the
+    /// cursor's line
+    highlight_from: Option<usize>,
+    /// Whether the cursor is on the line or one saved together with it
+    near_cursor: bool,
+    /// Column from which the line is at full brightness, `usize::MAX` if
+    /// none of it is
+    bright_from: usize,
+    /// How the rest of the line is drawn
+    before_bright: Shade,
+    /// Whether the line may be tinted for its corrections
+    tint_corrections: bool,
+    /// Whether the line goes past the page being fitted
+    overflow: bool,
+    /// Whether the line is laid out for a screen reader
+    accessible: bool,
+    /// Whether trailing whitespace is drawn as dots
+    show_trailing_whitespace: bool,
+    /// Whether the spaces ending the cursor's line are drawn as dots, on that line
+    show_current_trailing_spaces: bool,
+}
+
+/// A row of the page as laid out for its key
+#[derive(Debug, Clone)]
+struct Row {
+    /// What it was laid out from
+    key: RowKey,
+    /// Revealed characters in view, with how brightly they are drawn and
+    /// whether they are highlighted
+    characters: Vec<(Character, Shade, bool)>,
+    /// Characters scrolled off to the left, and columns left blank
+    hidden: (usize, usize),
+    /// How it is tinted, if it is
+    tinted: Option<Tint>,
+}
+
+/// Rows laid out for earlier frames, so a frame only works out again the
+/// lines that changed since. Rows with characters still to appear are laid
+/// out every time, since the time alone changes them.
+#[derive(Debug, Default)]
+pub struct LayoutCache {
+    /// Rows in view whose characters had all appeared, by buffer line
+    rows: HashMap<usize, Row>,
+    /// How many rows the last layout worked out afresh
+    recomputed: usize,
+}
+
+/// Lays out one line of the buffer as a row of the page, returning whether
+/// all its characters in view have appeared
+fn layout_row(buffer: &Buffer, line_idx: usize, key: RowKey, now: Instant) -> (Row, bool) {
+    let line = &buffer.lines[line_idx];
+
+    // Characters starting left of the first column in view are scrolled off,
+    // a wide one straddling it included
+    let mut used = 0;
+    let hidden = line
+        .characters
+        .iter()
+        .take_while(|character| {
+            let starts_at = used;
+            used += character.value.width().unwrap_or(0);
+            starts_at < key.scroll_left
+        })
+        .count();
+    let blank = display_column(&line.characters[..hidden]).saturating_sub(key.scroll_left);
+    let trailing = match (key.show_current_trailing_spaces, key.show_trailing_whitespace) {
+        (true, _) => Some(line.len() - line.trailing_whitespace_len()),
+        (false, true) => buffer.trailing_whitespace(line_idx),
+        (false, false) => None,
+    };
+
+    let mut settled = true;
+    let characters = line
+        .characters
+        .iter()
+        .enumerate()
+        .skip(hidden)
+        // Only show characters that have "matured" based on reveal rate
+        .filter(|(_, character)| {
+            let revealed = buffer.is_revealed(character, now);
+            settled &= revealed;
+            revealed
+        })
+        .map(|(char_idx, character)| {
+            // In mark-out mode, highlight characters from cursor position to end of line
+            let should_highlight = key.highlight_from.is_some_and(|column| char_idx >= column);
+            // Only dim what comes before the visible region
+            let shade = if key.accessible || char_idx >= key.bright_from { Shade::Bright } else { key.before_bright };
+            let is_trailing =
+                trailing.is_some_and(|column| char_idx >= column) && character.state == CharacterState::Normal;
+            if is_trailing {
+                let dot = Character { value: TRAILING_SPACE, ..character.clone() };
+                let shade = if key.accessible { Shade::Bright } else { shade.max(Shade::Dim) };
+                return (dot, shade, should_highlight);
+            }
+            (character.clone(), shade, should_highlight)
+        })
+        .collect();
+
+    // Corrections show through on a line past the page
+    let tinted = if key.tint_corrections && buffer.line_cleanliness(line_idx) < TINT_BELOW {
+        Some(Tint::Corrections)
+    } else {
+        key.overflow.then_some(Tint::Overflow)
+    };
+    let row = Row {
+        key,
+        characters,
+        hidden: (hidden, blank),
+        tinted,
+    };
+    (row, settled)
+}
+
+impl LayoutCache {
+    /// Returns how many rows the last layout worked out afresh
+    pub fn recomputed(&self) -> usize {
+        self.recomputed
+    }
+
+    /// Works out what the screen should show for the buffer at `now`, without
+    /// drawing anything. Rows of earlier frames that still hold are reused.
+    pub fn layout(&mut self, buffer: &Buffer, viewport: Viewport, opts: &RenderOptions, now: Instant) -> Frame {
+        let (cursor_line, cursor_col) = buffer.cursor_position();
+        let is_mark_out_mode = buffer.is_mark_out_mode();
+        let (left, top) = opts.padding;
+        let (width, _) = viewport.inner(opts.padding);
+        let Viewport { scroll_top, scroll_left, .. } = viewport;
+
+        // Everything from this (line, column) on is shown at full brightness,
+        // or nothing while an overlay has the focus
+        let visible_start = match opts.focus {
+            _ if !opts.overlays.is_empty() => (usize::MAX, 0),
+            Focus::Lines(count) => (buffer.lines.len().saturating_sub(count), 0),
+            Focus::Sentence => buffer.sentence_start(),
+        };
+        let near_cursor = buffer.saved_with(cursor_line);
+        let end = (scroll_top + viewport.page_rows(opts.padding)).min(buffer.lines.len());
+        self.rows.retain(|line_idx, _| (scroll_top..end).contains(line_idx));
+        self.recomputed = 0;
+
+        let mut cursor_pos = None;
+        let mut rows = Vec::with_capacity(end.saturating_sub(scroll_top));
+        for line_idx in scroll_top..end {
+            let line = &buffer.lines[line_idx];
+            let is_current_line = line_idx == cursor_line;
+            if is_current_line {
+                let column = display_column(&line.characters[..cursor_col]).saturating_sub(scroll_left);
+                let row = (line_idx - scroll_top) as u16;
+                cursor_pos = Some((left + column as u16, top + row));
+            }
+
+            let key = RowKey {
+                revision: line.revision(),
+                scroll_left,
+                highlight_from: Some(cursor_col).filter(|_| is_mark_out_mode && is_current_line),
+                near_cursor: near_cursor.contains(&line_idx),
+                bright_from: match line_idx.cmp(&visible_start.0) {
+                    Ordering::Less => usize::MAX,
+                    Ordering::Equal => visible_start.1,
+                    Ordering::Greater => 0,
+                },
+                // Behind an overlay the whole page is only dimmed
+                before_bright: if opts.overlays.is_empty() && line_idx + FAINT_AFTER < visible_start.0 {
+                    Shade::Faint
+                } else {
+                    Shade::Dim
+                },
+                tint_corrections: opts.tint_corrections,
+                overflow: opts.overflow_from.is_some_and(|from| line_idx >= from),
+                accessible: opts.accessible,
+                show_trailing_whitespace: opts.show_trailing_whitespace,
+                show_current_trailing_spaces: opts.show_current_trailing_spaces && is_current_line,
+            };
+            match self.rows.get(&line_idx).filter(|row| row.key == key) {
+                Some(row) => rows.push(row.clone()),
+                None => {
+                    self.recomputed += 1;
+                    let (row, settled) = layout_row(buffer, line_idx, key, now);
+                    if settled {
+                        self.rows.insert(line_idx, row.clone());
+                    } else {
+                        self.rows.remove(&line_idx);
+                    }
+                    rows.push(row);
+                }
+            }
+        }
+        let tinted = rows.iter().map(|row| row.tinted).collect();
+        let hidden = rows.iter().map(|row| row.hidden).collect();
+        let lines = rows.into_iter().map(|row| row.characters).collect();
+
+        // The hint sits where the hard margin stops the line, if that's on screen
+        let hyphen_hint = buffer
+            .hyphen_hint()
+            .filter(|_| opts.overlays.is_empty() && !opts.accessible)
+            .zip(cursor_pos)
+            .map(|(hint_left, (column, row))| (column.saturating_add(hint_left as u16), row))
+            .filter(|&(column, _)| column as usize + HYPHEN_HINT.chars().count() <= viewport.size.0 as usize);
+
+        let status = opts.status.as_ref().map(|status| {
+            let status = if opts.accessible { plain_text(status) } else { status.clone() };
+            status.chars().take(width as usize).collect()
+        });
+
+        let layers: Vec<OverlayLayout> = opts
+            .overlays
+            .iter()
+            .map(|overlay| overlay.layout(viewport.inner(opts.padding)))
+            .collect();
+
+        let cursor = if !layers.is_empty() {
+            None
+        } else if let Some(column) = opts.status_cursor.filter(|_| status.is_some()) {
+            let column = column.min(width.saturating_sub(1) as usize) as u16;
+            Some((left + column, viewport.size.1.saturating_sub(1)))
+        } else {
+            cursor_pos
+        };
+
+        // Marked every five columns and numbered every ten, scrolling sideways
+        // with the page
+        let ruler = opts.ruler.map(|stop| {
+            (scroll_left + 1..=scroll_left + width as usize)
+                .map(|column| {
+                    let mark = match column {
+                        column if column % 10 == 0 => char::from_digit((column / 10 % 10) as u32, 10).unwrap_or('|'),
+                        column if column % 5 == 0 => ':',
+                        _ => '.',
+                    };
+                    let reversed = column == stop;
+                    (mark, !reversed && !opts.accessible, reversed)
+                })
+                .collect()
+        });
+
+        Frame {
+            lines,
+            hidden,
+            tinted,
+            show_changes: opts.show_changes,
+            scroll_top,
+            scroll_left,
+            cursor_pos,
+            cursor,
+            size: viewport.size,
+            padding: opts.padding,
+            status,
+            status_tone: opts.status_tone,
+            layers,
+            hyphen_hint,
+            ruler,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Lays out a frame from scratch
+    fn layout(buffer: &Buffer, viewport: Viewport, opts: &RenderOptions, now: Instant) -> Frame {
+        LayoutCache::default().layout(buffer, viewport, opts, now)
+    }
+
+    /// Returns the text of each row of the page
+    fn rows(frame: &Frame) -> Vec<String> {
+        frame.lines.iter().map(|line| line.iter().map(|(character, _, _)| character.value).collect()).collect()
+    }
+
+    /// Returns a viewport of the given size, scrolled to the top left
+    fn viewport(size: (u16, u16)) -> Viewport {
+        Viewport {
+            size,
+            scroll_top: 0,
+            scroll_left: 0,
+        }
+    }
+
+    #[test]
+    /// Test that a frame holds what is revealed by its moment, with the
+    /// cursor where the next character goes
+    fn test_layout_reveal() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        for c in "ab".chars() {
+            buffer.insert_char(c);
+        }
+        let now = Instant::now();
+        let opts = RenderOptions::default();
+
+        let frame = layout(&buffer, viewport((80, 24)), &opts, now);
+        assert_eq!(rows(&frame), [""]);
+        assert_eq!(frame.cursor_pos, Some((2, 0)));
+        assert_eq!(frame.cursor, Some((2, 0)));
+
+        let frame = layout(&buffer, viewport((80, 24)), &opts, now + Duration::from_millis(200));
+        assert_eq!(rows(&frame), ["ab"]);
+    }
+
+    #[test]
+    /// Test that the viewport picks the rows and columns shown, inside the padding
+    fn test_layout_viewport() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        for idx in 0..6 {
+            if idx > 0 {
+                buffer.new_line();
+            }
+            for c in format!("line {}", idx).chars() {
+                buffer.insert_char(c);
+            }
+        }
+        let opts = RenderOptions {
+            padding: (2, 1),
+            ..RenderOptions::default()
+        };
+        // Six rows less the padding and the status row leave four for the page
+        let viewport = Viewport {
+            size: (40, 6),
+            scroll_top: 2,
+            scroll_left: 3,
+        };
+        let frame = layout(&buffer, viewport, &opts, Instant::now());
+        assert_eq!(rows(&frame), ["e 2", "e 3", "e 4", "e 5"]);
+        assert_eq!(frame.hidden, [(3, 0); 4]);
+        assert_eq!(frame.cursor_pos, Some((5, 4)));
+    }
+
+    #[test]
+    /// Test that lines well above the focus are faint, and that behind an
+    /// overlay the page is only dimmed
+    fn test_layout_shades() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        for idx in 0..10 {
+            if idx > 0 {
+                buffer.new_line();
+            }
+            buffer.insert_char(char::from(b'a' + idx));
+        }
+        let mut opts = RenderOptions::default();
+        let shades = |frame: Frame| -> Vec<Shade> { frame.lines.iter().map(|line| line[0].1).collect() };
+        let frame = layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+        let (bright, dim, faint) = (Shade::Bright, Shade::Dim, Shade::Faint);
+        assert_eq!(shades(frame), [faint, faint, faint, dim, dim, dim, dim, dim, bright, bright]);
+
+        opts.overlays = vec![Overlay {
+            title: "Menu".to_string(),
+            lines: vec!["Save".to_string()],
+            highlighted: None,
+        }];
+        let frame = layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+        assert_eq!(shades(frame), [dim; 10]);
+    }
+
+    #[test]
+    /// Test where the terminal's cursor goes with a status prompt and under an
+    /// overlay, and how the status row is cut and the ruler marked
+    fn test_layout_chrome() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.insert_char('a');
+        let mut opts = RenderOptions {
+            status: Some("Save as: “draft”…".repeat(4)),
+            status_cursor: Some(100),
+            ruler: Some(10),
+            ..RenderOptions::default()
+        };
+        let frame = layout(&buffer, viewport((30, 10)), &opts, Instant::now());
+        assert_eq!(frame.status.as_ref().unwrap().chars().count(), 30);
+        assert_eq!(frame.cursor, Some((29, 9)));
+        assert_eq!(frame.cursor_pos, Some((1, 0)));
+        let marks: String = frame.ruler.as_ref().unwrap().iter().map(|&(mark, _, _)| mark).collect();
+        assert_eq!(marks, "....:....1....:....2....:....3");
+        assert_eq!(frame.ruler.as_ref().unwrap()[9], ('1', false, true));
+
+        opts.accessible = true;
+        let frame = layout(&buffer, viewport((30, 10)), &opts, Instant::now());
+        assert!(frame.status.unwrap().starts_with("Save as: \"draft\"..."));
+
+        opts.overlays = vec![Overlay {
+            title: "Menu".to_string(),
+            lines: vec!["Save".to_string()],
+            highlighted: None,
+        }];
+        let frame = layout(&buffer, viewport((30, 10)), &opts, Instant::now());
+        assert_eq!(frame.cursor, None);
+        assert_eq!(frame.layers.len(), 1);
+    }
+
+    #[test]
+    /// Test that after a keystroke at the end of a 50,000-line buffer only
+    /// the line typed on is laid out again
+    fn test_cache_keystroke() {
+        let text = vec!["The quick brown fox jumps over the lazy dog."; 50_000].join("\n");
+        let mut buffer = Buffer::from_reader(text.as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        let opts = RenderOptions::default();
+        let viewport = Viewport {
+            size: (80, 24),
+            scroll_top: 50_000 - 23,
+            scroll_left: 0,
+        };
+
+        let mut cache = LayoutCache::default();
+        cache.layout(&buffer, viewport, &opts, Instant::now());
+        assert_eq!(cache.recomputed(), 23);
+        cache.layout(&buffer, viewport, &opts, Instant::now());
+        assert_eq!(cache.recomputed(), 0);
+
+        buffer.insert_char('!');
+        let frame = cache.layout(&buffer, viewport, &opts, Instant::now());
+        assert_eq!(cache.recomputed(), 1);
+        assert_eq!(frame, layout(&buffer, viewport, &opts, Instant::now()));
+    }
+
+    #[test]
+    /// Test that a new line lays out again the lines whose shade or cursor
+    /// changed along with it, and nothing else
+    fn test_cache_new_line() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        for idx in 0..10 {
+            if idx > 0 {
+                buffer.new_line();
+            }
+            for c in format!("line {}", idx).chars() {
+                buffer.insert_char(c);
+            }
+        }
+        let opts = RenderOptions::default();
+        let mut cache = LayoutCache::default();
+        cache.layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+
+        // The line dimmed by it, the line turned faint, the line left and the new one
+        buffer.new_line();
+        let frame = cache.layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+        assert_eq!(cache.recomputed(), 4);
+        assert_eq!(frame, layout(&buffer, viewport((80, 24)), &opts, Instant::now()));
+    }
+
+    #[test]
+    /// Test that lines split to fit the margin, and the lines they push down,
+    /// are laid out again
+    fn test_cache_split() {
+        let text = format!("short\n{}\nend", "word ".repeat(6));
+        let mut buffer = Buffer::from_reader(text.as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        let opts = RenderOptions {
+            show_trailing_whitespace: true,
+            ..RenderOptions::default()
+        };
+        let mut cache = LayoutCache::default();
+        cache.layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+
+        buffer.set_hard_margin(Some(10));
+        buffer.paste("!", Instant::now());
+        let frame = cache.layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+        assert_eq!(rows(&frame), ["short", "word word ", "word word ", "word word·", "end!"]);
+        assert_eq!(cache.recomputed(), 4);
+        assert_eq!(frame, layout(&buffer, viewport((80, 24)), &opts, Instant::now()));
+
+    }
+
+    #[test]
+    /// Test that a line with characters still to appear is laid out each
+    /// time, and kept once they all have
+    fn test_cache_reveal() {
+        let mut buffer = Buffer::new(Duration::from_millis(100));
+        buffer.insert_char('a');
+        let now = Instant::now();
+        let opts = RenderOptions::default();
+        let mut cache = LayoutCache::default();
+
+        let frame = cache.layout(&buffer, viewport((80, 24)), &opts, now);
+        assert_eq!(rows(&frame), [""]);
+        let later = now + Duration::from_millis(200);
+        let frame = cache.layout(&buffer, viewport((80, 24)), &opts, later);
+        assert_eq!(rows(&frame), ["a"]);
+        assert_eq!(cache.recomputed(), 1);
+        cache.layout(&buffer, viewport((80, 24)), &opts, later);
+        assert_eq!(cache.recomputed(), 0);
+    }
+
+    #[test]
+    /// Test which rows count as changed between two frames
+    fn test_row_changed() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_hard_margin(Some(12));
+        buffer.set_hyphen_hints(true);
+        for c in "One\nThe typ".chars() {
+            match c {
+                '\n' => buffer.new_line(),
+                c => {
+                    buffer.insert_char(c);
+                }
+            }
+        }
+        let opts = RenderOptions::default();
+        let before = layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+        buffer.insert_char('e');
+        let after = layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+        assert!(before.updates_to(&after));
+        assert!(!before.row_changed(&after, 0));
+        assert!(before.row_changed(&after, 1));
+
+        // The hint moving away from a row changes it too
+        buffer.insert_char(' ');
+        let spaced = layout(&buffer, viewport((80, 24)), &opts, Instant::now());
+        assert_eq!(spaced.hyphen_hint, None);
+        assert!(after.row_changed(&spaced, 1));
+
+        // A different size needs the whole screen
+        let resized = layout(&buffer, viewport((60, 24)), &opts, Instant::now());
+        assert!(!spaced.updates_to(&resized));
+    }
+}
@@ -1,23 +1,269 @@
+mod background;
+mod layout;
+pub use background::{parse_background, Background};
+use layout::HYPHEN_HINT;
+pub use layout::{Frame, LayoutCache, RenderOptions, Shade, Tint, Viewport};
+
 use crossterm::{
     cursor,
-    style::{self, Stylize},
+    event::{
+        DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste, EnableFocusChange,
+        EnableMouseCapture, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    style::{self, Color, Stylize},
     terminal::{self, Clear, ClearType},
     QueueableCommand,
 };
-use std::io::{self, Write};
-use std::time::Instant;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthChar;
+
+use crate::clipboard::{self, CopyMethod};
+use crate::editor::{Buffer, Character};
+
+/// Number of lines kept fully visible unless set otherwise
+pub(crate) const VISIBLE_LINES: usize = 2;
+
+/// Columns kept in view ahead of the cursor on a line wider than the page,
+/// unless set otherwise
+pub(crate) const SCROLL_AHEAD: usize = 5;
+
+/// Smallest usable terminal as (columns, rows): a few lines of page above the status row.
+/// The padding comes on top of this.
+pub const MIN_SIZE: (u16, u16) = (20, 4);
+
+/// Whether keyboard enhancement flags are currently pushed to the terminal.
+/// Global so the panic hook can pop them without access to the renderer.
+static KEYBOARD_ENHANCED: AtomicBool = AtomicBool::new(false);
+
+/// Whether mouse capture is currently enabled, for the same reason
+static MOUSE_CAPTURED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the terminal is currently reporting focus changes, for the same reason
+static FOCUS_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether pastes are currently marked out from typing, for the same reason
+static BRACKETED_PASTE: AtomicBool = AtomicBool::new(false);
+
+/// How long `flash` keeps the screen inverted
+const FLASH_TIME: Duration = Duration::from_millis(100);
+
+/// Why the terminal couldn't be set up for rendering
+#[derive(Debug)]
+pub enum InitError {
+    /// The output goes to a file or a pipe rather than a terminal
+    NotATerminal,
+    /// The terminal is smaller than `MIN_SIZE` plus the padding, as
+    /// (columns, rows) and the least that will do
+    TooSmall { size: (u16, u16), min: (u16, u16) },
+    /// The terminal refused a setting
+    Io(io::Error),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotATerminal => write!(f, "output is not a terminal"),
+            Self::TooSmall { size, min } => write!(
+                f,
+                "terminal is too small ({}x{}, need at least {}x{})",
+                size.0, size.1, min.0, min.1
+            ),
+            Self::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+impl From<io::Error> for InitError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<InitError> for io::Error {
+    fn from(e: InitError) -> Self {
+        match e {
+            InitError::Io(e) => e,
+            e => io::Error::other(e.to_string()),
+        }
+    }
+}
+
+/// Installs a panic hook that restores the terminal before the panic message is
+/// printed, so a crash never leaves raw mode or keyboard flags behind
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal(&mut io::stdout());
+        default_hook(info);
+    }));
+}
+
+/// Undoes everything `Renderer::init` changed in the terminal
+fn restore_terminal<W: Write>(output: &mut W) -> io::Result<()> {
+    if KEYBOARD_ENHANCED.swap(false, Ordering::SeqCst) {
+        output.queue(PopKeyboardEnhancementFlags)?;
+    }
+    if MOUSE_CAPTURED.swap(false, Ordering::SeqCst) {
+        output.queue(DisableMouseCapture)?;
+    }
+    if FOCUS_REPORTED.swap(false, Ordering::SeqCst) {
+        output.queue(DisableFocusChange)?;
+    }
+    if BRACKETED_PASTE.swap(false, Ordering::SeqCst) {
+        output.queue(DisableBracketedPaste)?;
+    }
+    terminal::disable_raw_mode()?;
+    output.queue(terminal::LeaveAlternateScreen)?;
+    output.queue(cursor::Show)?;
+    output.flush()
+}
+
+/// What stays at full brightness while typing; the rest of the page is dimmed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Focus {
+    /// The last lines of the page
+    Lines(usize),
+    /// The sentence being typed, from its start onwards
+    Sentence,
+}
+
+/// How the status row is colored, to warn about a limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusTone {
+    /// Reversed, like any message
+    Normal,
+    /// Amber, when a limit is close
+    Warning,
+    /// Red, when a limit is passed
+    Over,
+}
+
+/// A box of text drawn centered over the (dimmed) page
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    /// Title shown in the top border
+    pub title: String,
+    /// Lines of text inside the box
+    pub lines: Vec<String>,
+    /// Line drawn in reverse video, if any
+    pub highlighted: Option<usize>,
+}
+
+/// Where an overlay lands on screen, with the full text of each row
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayLayout {
+    /// Column of the left border
+    pub left: u16,
+    /// Row of the top border
+    pub top: u16,
+    /// Every row of the box, borders included
+    pub rows: Vec<String>,
+    /// Index into `rows` of the highlighted row, if any
+    pub highlighted_row: Option<usize>,
+}
+
+impl Overlay {
+    /// Works out the bordered box for this overlay, centered on a screen of the
+    /// given (columns, rows) size. Lines that don't fit are cut off, and a screen
+    /// too small for even the borders gets no box at all.
+    pub fn layout(&self, size: (u16, u16)) -> OverlayLayout {
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        if width < 4 || height < 2 {
+            return OverlayLayout {
+                left: 0,
+                top: 0,
+                rows: Vec::new(),
+                highlighted_row: None,
+            };
+        }
+
+        // Inner width fits the longest line and the title, within the screen
+        let content_width = self
+            .lines
+            .iter()
+            .map(|line| text_width(line))
+            .chain([text_width(&self.title) + 1])
+            .max()
+            .unwrap_or(0);
+        let inner = content_width.min(width - 4);
+        let box_width = inner + 4;
+        let line_count = self.lines.len().min(height - 2);
+        let box_height = line_count + 2;
+
+        let title = clip(&self.title, inner.saturating_sub(1));
+        let title_len = text_width(&title);
+        let mut rows = Vec::with_capacity(box_height);
+        if title.is_empty() {
+            rows.push(format!("┌{}┐", "─".repeat(inner + 2)));
+        } else {
+            rows.push(format!("┌─ {} {}┐", title, "─".repeat(inner.saturating_sub(title_len + 1))));
+        }
+        for line in self.lines.iter().take(line_count) {
+            let text = clip(line, inner);
+            rows.push(format!("│ {}{} │", text, " ".repeat(inner - text_width(&text))));
+        }
+        rows.push(format!("└{}┘", "─".repeat(inner + 2)));
 
-use crate::editor::{Buffer, Character, CharacterState};
+        OverlayLayout {
+            left: ((width - box_width) / 2) as u16,
+            top: ((height - box_height) / 2) as u16,
+            rows,
+            highlighted_row: self.highlighted.filter(|&idx| idx < line_count).map(|idx| idx + 1),
+        }
+    }
+}
 
-/// Number of lines to keep fully visible
-const VISIBLE_LINES: usize = 2;
+/// Returns how many columns text takes up on screen
+fn text_width(text: &str) -> usize {
+    text.chars().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+/// Cuts text down to at most the given number of columns
+fn clip(text: &str, columns: usize) -> String {
+    let mut used = 0;
+    text.chars()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= columns
+        })
+        .collect()
+}
 
 /// Handles rendering the buffer to the terminal
 pub struct Renderer<W: Write> {
     /// The output writer (usually stdout)
-    output: W,
+    pub(crate) output: W,
     /// Current cursor position in terminal coordinates
     cursor_pos: (u16, u16),
+    /// Terminal size as (columns, rows)
+    size: (u16, u16),
+    /// What besides the buffer and the scroll position decides what each frame shows
+    options: RenderOptions,
+    /// The frame currently on screen, or None if the screen must be repainted
+    last_frame: Option<Frame>,
+    /// Rows laid out for earlier frames that still hold
+    layout_cache: LayoutCache,
+    /// Buffer line shown in the top row
+    scroll_top: usize,
+    /// Page column shown in the leftmost column, once a line is wider than the page
+    scroll_left: usize,
+    /// Columns kept in view ahead of the cursor before the page scrolls sideways
+    scroll_ahead: usize,
+    /// Whether scrolling keeps the cursor line in view. Turned off while the user
+    /// scrolls with the mouse wheel.
+    follow_cursor: bool,
+    /// Whether to ask the terminal for mouse events
+    mouse_capture: bool,
+    /// Whether to ask the terminal to report gaining and losing focus
+    focus_reporting: bool,
+    /// The terminal's background, which decides how text is dimmed and highlighted.
+    /// `Auto` until `init` asks the terminal.
+    background: Background,
 }
 
 impl<W: Write> Renderer<W> {
@@ -26,98 +272,571 @@ impl<W: Write> Renderer<W> {
         Self { 
             output,
             cursor_pos: (0, 0),
+            size: (80, 24),
+            options: RenderOptions::default(),
+            last_frame: None,
+            layout_cache: LayoutCache::default(),
+            scroll_top: 0,
+            scroll_left: 0,
+            scroll_ahead: SCROLL_AHEAD,
+            follow_cursor: true,
+            mouse_capture: false,
+            focus_reporting: false,
+            background: Background::Auto,
         }
     }
 
-    /// Initializes the terminal for rendering
-    pub fn init(&mut self) -> io::Result<()> {
+    /// Initializes the terminal for rendering. Fails without touching the
+    /// terminal if the output isn't one, or if it is smaller than `MIN_SIZE`
+    /// plus the padding.
+    pub fn init(&mut self) -> Result<(), InitError>
+    where
+        W: IsTerminal,
+    {
+        if !self.output.is_terminal() {
+            return Err(InitError::NotATerminal);
+        }
+        let (columns, rows) = terminal::size()?;
+        let min = self.min_size();
+        if columns < min.0 || rows < min.1 {
+            return Err(InitError::TooSmall { size: (columns, rows), min });
+        }
+        self.size = (columns, rows);
+
+        // Raw mode also turns off XON/XOFF flow control (IXON) so Ctrl+S and Ctrl+Q
+        // reach us; disable_raw_mode restores the original setting on cleanup
         terminal::enable_raw_mode()?;
+        self.invalidate();
+        if self.background == Background::Auto {
+            self.background = background::detect().unwrap_or(Background::Dark);
+            log::debug!("terminal background is {:?}", self.background);
+        }
         self.output.queue(terminal::EnterAlternateScreen)?;
         self.output.queue(cursor::Show)?;
-        self.output.flush()
+
+        // Terminals speaking the kitty keyboard protocol can tell chords like
+        // Ctrl+Shift+S apart from Ctrl+S. Others keep the legacy encoding.
+        if terminal::supports_keyboard_enhancement().unwrap_or(false)
+            && !KEYBOARD_ENHANCED.swap(true, Ordering::SeqCst)
+        {
+            self.output.queue(PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES,
+            ))?;
+        }
+        if self.mouse_capture && !MOUSE_CAPTURED.swap(true, Ordering::SeqCst) {
+            self.output.queue(EnableMouseCapture)?;
+        }
+        if self.focus_reporting && !FOCUS_REPORTED.swap(true, Ordering::SeqCst) {
+            self.output.queue(EnableFocusChange)?;
+        }
+        // Pastes arrive whole, so a big one can be caught before it is typed
+        if !BRACKETED_PASTE.swap(true, Ordering::SeqCst) {
+            self.output.queue(EnableBracketedPaste)?;
+        }
+
+        Ok(self.output.flush()?)
     }
 
     /// Cleans up the terminal
     pub fn cleanup(&mut self) -> io::Result<()> {
-        terminal::disable_raw_mode()?;
-        self.output.queue(terminal::LeaveAlternateScreen)?;
-        self.output.queue(cursor::Show)?;
+        restore_terminal(&mut self.output)
+    }
+
+    /// Returns true if the terminal accepted the keyboard enhancement flags
+    pub fn keyboard_enhanced(&self) -> bool {
+        KEYBOARD_ENHANCED.load(Ordering::SeqCst)
+    }
+
+    /// Returns the space inside the padding as (columns, rows)
+    pub fn size(&self) -> (u16, u16) {
+        (self.size.0.saturating_sub(self.options.padding.0), self.size.1.saturating_sub(self.options.padding.1))
+    }
+
+    /// Returns the smallest terminal that fits `MIN_SIZE` inside the padding
+    fn min_size(&self) -> (u16, u16) {
+        (MIN_SIZE.0 + self.options.padding.0, MIN_SIZE.1 + self.options.padding.1)
+    }
+
+    /// Sets or clears the message shown in the bottom row
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.options.status = status;
+    }
+
+    /// Places the cursor in the bottom row at the given column instead of in the page,
+    /// for when the status row holds a text prompt
+    pub fn set_status_cursor(&mut self, column: Option<usize>) {
+        self.options.status_cursor = column;
+    }
+
+    /// Sets how the status message is colored
+    pub fn set_status_tone(&mut self, tone: StatusTone) {
+        self.options.status_tone = tone;
+    }
+
+    /// Sets or clears the box drawn over the page
+    pub fn set_overlay(&mut self, overlay: Option<Overlay>) {
+        self.set_overlays(overlay.into_iter().collect());
+    }
+
+    /// Sets the boxes drawn over the page, each one over the ones before it
+    pub fn set_overlays(&mut self, overlays: Vec<Overlay>) {
+        self.options.overlays = overlays;
+    }
+
+    /// Shows a ruler across the top of the page with the margin stop at the
+    /// given column, the last one a line can reach, or hides it
+    pub fn set_ruler(&mut self, stop: Option<usize>) {
+        self.options.ruler = stop;
+    }
+
+    /// Chooses whether `init` turns on mouse capture
+    pub fn set_mouse_capture(&mut self, enabled: bool) {
+        self.mouse_capture = enabled;
+    }
+
+    /// Chooses whether `init` asks for focus changes. Terminals that don't
+    /// support them just never send any.
+    pub fn set_focus_reporting(&mut self, enabled: bool) {
+        self.focus_reporting = enabled;
+    }
+
+    /// Chooses what stays at full brightness
+    pub fn set_focus(&mut self, focus: Focus) {
+        self.options.focus = focus;
+    }
+
+    /// Sets the blank (columns, rows) kept left of and above the page
+    pub fn set_padding(&mut self, padding: (u16, u16)) {
+        self.options.padding = padding;
+    }
+
+    /// Chooses whether lines with many corrections are tinted
+    pub fn set_tint_corrections(&mut self, enabled: bool) {
+        self.options.tint_corrections = enabled;
+    }
+
+    /// Tints the buffer's lines from `line` on, past the page being fitted,
+    /// or none with None
+    pub fn set_overflow_from(&mut self, line: Option<usize>) {
+        self.options.overflow_from = line;
+    }
+
+    /// Chooses whether to draw for a screen reader. Nothing is dimmed, the
+    /// screen doesn't flash, the hyphen hint is left out and the status row
+    /// has no typographic glyphs.
+    pub fn set_accessible(&mut self, enabled: bool) {
+        self.options.accessible = enabled;
+    }
+
+    /// Chooses whether spaces and tabs at the ends of lines other than the
+    /// one being typed are drawn as faint dots
+    pub fn set_show_trailing_whitespace(&mut self, enabled: bool) {
+        self.options.show_trailing_whitespace = enabled;
+    }
+
+    /// Chooses whether spaces and tabs at the end of the line being typed are
+    /// drawn as faint dots, until the cursor leaves it
+    pub fn set_show_current_trailing_spaces(&mut self, enabled: bool) {
+        self.options.show_current_trailing_spaces = enabled;
+    }
+
+    /// Chooses whether what was typed or marked out since the last save is
+    /// underlined
+    pub fn set_show_changes(&mut self, enabled: bool) {
+        self.options.show_changes = enabled;
+    }
+
+    /// Sets the terminal's background, or `Auto` to ask the terminal in `init`
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    /// Rings the terminal bell
+    pub fn bell(&mut self) -> io::Result<()> {
+        self.output.queue(style::Print('\x07'))?;
         self.output.flush()
     }
 
-    /// Renders the buffer to the terminal
-    pub fn render(&mut self, buffer: &Buffer) -> io::Result<()> {
-        // Clear the screen
-        self.output.queue(Clear(ClearType::All))?;
-        self.output.queue(cursor::MoveTo(0, 0))?;
+    /// Briefly inverts the whole screen as a visual bell
+    pub async fn flash(&mut self) -> io::Result<()> {
+        if self.options.accessible {
+            return Ok(());
+        }
+        // DECSCNM: reverse video for the whole screen
+        self.output.queue(style::Print("\x1b[?5h"))?;
+        self.output.flush()?;
+        tokio::time::sleep(FLASH_TIME).await;
+        self.output.queue(style::Print("\x1b[?5l"))?;
+        self.output.flush()
+    }
+
+    /// Scrolls the page by a number of lines (negative is up) without moving the
+    /// cursor. The page stays put until `follow_cursor` is called.
+    pub fn scroll_by(&mut self, lines: isize) {
+        self.follow_cursor = false;
+        self.scroll_top = self.scroll_top.saturating_add_signed(lines);
+    }
+
+    /// Puts a line at the top of the page, such as where it was when the file
+    /// was last closed. The page still scrolls as far as it must to show the
+    /// cursor, and no further than the end of the buffer.
+    pub fn set_scroll_top(&mut self, line: usize) {
+        self.scroll_top = line;
+    }
+
+    /// Returns the line at the top of the page as of the last render
+    pub fn scroll_top(&self) -> usize {
+        self.scroll_top
+    }
+
+    /// Sets how many columns stay in view ahead of the cursor on a line wider
+    /// than the page. The page scrolls sideways as the cursor comes that close
+    /// to the right edge, and back as it comes that close to the left.
+    pub fn set_scroll_ahead(&mut self, columns: usize) {
+        self.scroll_ahead = columns;
+    }
+
+    /// Goes back to scrolling so the cursor line stays in view
+    pub fn follow_cursor(&mut self) {
+        self.follow_cursor = true;
+    }
 
-        let now = Instant::now();
+    /// Maps a screen cell to the buffer position (line, column) of the character
+    /// drawn there, as of the last render. Cells past the end of a line map to
+    /// the end of its revealed text, and cells in the left padding to its start;
+    /// rows without a line map to None.
+    pub fn position_at(&self, column: u16, row: u16) -> Option<(usize, usize)> {
+        let frame = self.last_frame.as_ref()?;
+        let row = row.checked_sub(self.options.padding.1)?;
+        let column = column.saturating_sub(self.options.padding.0);
+        let line = frame.lines.get(row as usize)?;
+        let (hidden, blank) = frame.hidden[row as usize];
+
+        let mut left = blank;
+        for (idx, (character, _, _)) in line.iter().enumerate() {
+            let width = character.value.width().unwrap_or(0);
+            if (column as usize) < left + width {
+                return Some((frame.scroll_top + row as usize, hidden + idx));
+            }
+            left += width;
+        }
+        Some((frame.scroll_top + row as usize, hidden + line.len()))
+    }
+
+    /// Moves the scroll position so the cursor stays in view, unless the mouse
+    /// wheel has taken over, and keeps it within the buffer. Sideways, the
+    /// page follows the cursor whatever the wheel did, keeping `scroll_ahead`
+    /// columns in view on either side of it where the page is wide enough.
+    fn update_scroll(&mut self, buffer: &Buffer) {
+        let rows = self.viewport().page_rows(self.options.padding);
         let (cursor_line, cursor_col) = buffer.cursor_position();
-        let is_mark_out_mode = buffer.is_mark_out_mode();
-
-        // Calculate the number of lines that should be visible at full brightness
-        let visible_start = buffer.lines.len().saturating_sub(VISIBLE_LINES);
-
-        // Render each line
-        for (line_idx, line) in buffer.lines.iter().enumerate() {
-            // Move to the start of the current line
-            self.output.queue(cursor::MoveTo(0, line_idx as u16))?;
-
-            // Only dim lines that are above the visible region
-            let should_dim = line_idx < visible_start;
-            let is_current_line = line_idx == cursor_line;
-            
-            for (char_idx, character) in line.characters.iter().enumerate() {
-                // Only show characters that have "matured" based on reveal rate
-                if now.duration_since(character.timestamp) >= buffer.reveal_rate() {
-                    // In mark-out mode, highlight characters from cursor position to end of line
-                    let should_highlight = is_mark_out_mode && is_current_line && char_idx >= cursor_col;
-                    self.render_character(character, should_dim, should_highlight)?;
+        let width = self.size().0 as usize;
+        let ahead = self.scroll_ahead.min(width.saturating_sub(1) / 2);
+        let column = layout::display_column(&buffer.lines[cursor_line].characters[..cursor_col]);
+        if column + ahead >= self.scroll_left + width {
+            self.scroll_left = column + ahead + 1 - width;
+        } else if column < self.scroll_left + ahead {
+            self.scroll_left = column.saturating_sub(ahead);
+        }
+
+        if self.follow_cursor {
+            if cursor_line < self.scroll_top {
+                self.scroll_top = cursor_line;
+            } else if cursor_line >= self.scroll_top + rows {
+                self.scroll_top = cursor_line + 1 - rows;
+            }
+        }
+        self.scroll_top = self.scroll_top.min(buffer.lines.len().saturating_sub(rows));
+    }
+
+    /// Puts text on the clipboard, through the terminal where possible
+    pub fn copy_to_clipboard(&mut self, text: &str) -> Result<CopyMethod, String> {
+        clipboard::copy(&mut self.output, text)
+    }
+
+    /// Forgets what is on screen, so the next render repaints everything
+    pub fn invalidate(&mut self) {
+        self.last_frame = None;
+    }
+
+    /// Takes on the terminal's new size. Everything laid out for the old one,
+    /// the page, status row and overlays alike, is worked out again by the next
+    /// render, which repaints the whole screen.
+    pub fn resize(&mut self, columns: u16, rows: u16) {
+        log::debug!("terminal resized to {}x{}", columns, rows);
+        self.size = (columns, rows);
+        self.invalidate();
+    }
+
+    /// Returns the part of the page in view as of the last scroll
+    fn viewport(&self) -> Viewport {
+        Viewport {
+            size: self.size,
+            scroll_top: self.scroll_top,
+            scroll_left: self.scroll_left,
+        }
+    }
+
+    /// Works out what the screen should show for the buffer right now,
+    /// laying out again only the lines that changed since the last frame
+    fn frame(&mut self, buffer: &Buffer) -> Frame {
+        self.layout_cache.layout(buffer, self.viewport(), &self.options, buffer.now())
+    }
+
+    /// Renders the buffer to the terminal, unless the screen already shows it
+    pub fn render(&mut self, buffer: &Buffer) -> io::Result<()> {
+        let started = Instant::now();
+        self.update_scroll(buffer);
+        let frame = self.frame(buffer);
+        if self.last_frame.as_ref() == Some(&frame) {
+            return Ok(());
+        }
+        self.draw(frame)?;
+        log::debug!("rendered in {:?}, {} lines laid out", started.elapsed(), self.layout_cache.recomputed());
+        Ok(())
+    }
+
+    /// Writes a frame to the terminal in one flush. Only the rows that differ
+    /// from the frame on screen are redrawn, unless overlays or the ruler are
+    /// drawn over the page, the screen changed size or nothing is on it yet,
+    /// which repaint everything: the page and status row first, then each
+    /// overlay over them.
+    fn draw(&mut self, frame: Frame) -> io::Result<()> {
+        let (left, top) = frame.padding;
+        let last = self.last_frame.take().filter(|last| last.updates_to(&frame));
+        if last.is_none() {
+            self.output.queue(Clear(ClearType::All))?;
+            self.output.queue(cursor::MoveTo(left, top))?;
+        }
+
+        let rows = frame.lines.len().max(last.as_ref().map_or(0, |last| last.lines.len()));
+        for idx in 0..rows {
+            if let Some(last) = &last {
+                if !last.row_changed(&frame, idx) {
+                    continue;
                 }
+                self.output.queue(cursor::MoveTo(left, top + idx as u16))?;
+                self.output.queue(Clear(ClearType::UntilNewLine))?;
             }
-            
-            // Store cursor position if this is the current line
-            if is_current_line {
-                self.cursor_pos = (cursor_col as u16, line_idx as u16);
+            if idx < frame.lines.len() {
+                self.draw_row(&frame, idx)?;
             }
-            
-            // Add newline after each line
-            writeln!(self.output)?;
+        }
+        if let Some(cursor_pos) = frame.cursor_pos {
+            self.cursor_pos = cursor_pos;
+        }
+        if let Some((column, row)) = frame.hyphen_hint {
+            self.output.queue(cursor::MoveTo(column, row))?;
+            self.output.queue(style::PrintStyledContent(self.background.dim(style::style(HYPHEN_HINT))))?;
         }
 
-        // Move cursor to its position
-        self.output.queue(cursor::MoveTo(self.cursor_pos.0, self.cursor_pos.1))?;
-        
+        let status_changed = last
+            .as_ref()
+            .is_none_or(|last| (&last.status, last.status_tone) != (&frame.status, frame.status_tone));
+        if status_changed {
+            self.draw_status(&frame, last.is_some())?;
+        }
+
+        if let Some(marks) = &frame.ruler {
+            self.draw_ruler(marks, frame.padding)?;
+        }
+        for layer in &frame.layers {
+            self.draw_overlay(layer, frame.padding)?;
+        }
+
+        if let Some((column, row)) = frame.cursor {
+            self.output.queue(cursor::MoveTo(column, row))?;
+            self.output.queue(cursor::Show)?;
+        } else {
+            self.output.queue(cursor::Hide)?;
+        }
+
+        self.last_frame = Some(frame);
         self.output.flush()
     }
 
-    /// Renders a single character with appropriate styling
-    fn render_character(&mut self, character: &Character, should_dim: bool, highlight: bool) -> io::Result<()> {
-        let mut styled = match character.state {
-            CharacterState::Normal => style::style(character.value),
-            CharacterState::MarkedOut => style::style(character.value).crossed_out(),
+    /// Draws row `idx` of the page, leaving out what runs past the right edge
+    fn draw_row(&mut self, frame: &Frame, idx: usize) -> io::Result<()> {
+        let (left, top) = frame.padding;
+        let width = frame.size.0.saturating_sub(left) as usize;
+        // Move to the start of the row, or just past the half of a wide
+        // character scrolled off to the left
+        let (_, blank) = frame.hidden[idx];
+        self.output.queue(cursor::MoveTo(left + blank as u16, top + idx as u16))?;
+        let mut used = blank;
+        for (character, shade, should_highlight) in &frame.lines[idx] {
+            used += character.value.width().unwrap_or(0);
+            if used > width {
+                break;
+            }
+            let changed = frame.show_changes && character.changed;
+            self.render_character(character, *shade, *should_highlight, frame.tinted[idx], changed)?;
+        }
+        writeln!(self.output)
+    }
+
+    /// Draws the status message in the bottom row, or clears the row when
+    /// there is none and something may be left there
+    fn draw_status(&mut self, frame: &Frame, clear: bool) -> io::Result<()> {
+        if frame.status.is_none() && !clear {
+            return Ok(());
+        }
+        self.output.queue(cursor::MoveTo(frame.padding.0, frame.size.1.saturating_sub(1)))?;
+        self.output.queue(Clear(ClearType::CurrentLine))?;
+        let Some(text) = frame.status.clone() else {
+            return Ok(());
         };
+        let styled = match frame.status_tone {
+            StatusTone::Normal => style::style(text).reverse(),
+            StatusTone::Warning => style::style(text).black().on(Color::DarkYellow),
+            StatusTone::Over => style::style(text).white().on(Color::DarkRed),
+        };
+        self.output.queue(style::PrintStyledContent(styled))?;
+        Ok(())
+    }
+
+    /// Draws the ruler over the top row of the page
+    fn draw_ruler(&mut self, marks: &[(char, bool, bool)], padding: (u16, u16)) -> io::Result<()> {
+        self.output.queue(cursor::MoveTo(padding.0, padding.1))?;
+        self.output.queue(Clear(ClearType::UntilNewLine))?;
+        for &(mark, dim, reversed) in marks {
+            let styled = if reversed {
+                style::style(mark).reverse()
+            } else if dim {
+                self.background.dim(style::style(mark))
+            } else {
+                style::style(mark)
+            };
+            self.output.queue(style::PrintStyledContent(styled))?;
+        }
+        Ok(())
+    }
 
-        // Apply dimming effect for older lines
-        if should_dim {
-            styled = styled.dim();
+    /// Draws an overlay's box in the space inside the padding
+    fn draw_overlay(&mut self, layout: &OverlayLayout, padding: (u16, u16)) -> io::Result<()> {
+        let (left, top) = (padding.0 + layout.left, padding.1 + layout.top);
+
+        for (idx, row) in layout.rows.iter().enumerate() {
+            self.output.queue(cursor::MoveTo(left, top + idx as u16))?;
+            if layout.highlighted_row == Some(idx) {
+                // Reverse the inside of the row, keeping the borders plain
+                let chars: Vec<char> = row.chars().collect();
+                let inside: String = chars[1..chars.len() - 1].iter().collect();
+                self.output.queue(style::Print(chars[0]))?;
+                self.output.queue(style::PrintStyledContent(style::style(inside).reverse()))?;
+                self.output.queue(style::Print(chars[chars.len() - 1]))?;
+            } else {
+                self.output.queue(style::Print(row))?;
+            }
         }
+        Ok(())
+    }
+
+    /// Renders a frame of the audio calibration screen.
+    /// While `lit` is set the whole screen is inverted to act as the visual cue.
+    pub fn render_calibration(&mut self, lit: bool, message: &str) -> io::Result<()> {
+        self.invalidate();
+        self.output.queue(Clear(ClearType::All))?;
+        self.output.queue(cursor::Hide)?;
 
-        // Apply highlight effect if needed
-        if highlight {
-            styled = styled.reverse();
+        if lit {
+            let (width, height) = terminal::size()?;
+            let blank = " ".repeat(width as usize);
+            for row in 0..height {
+                self.output.queue(cursor::MoveTo(0, row))?;
+                self.output.queue(style::PrintStyledContent(style::style(&blank).reverse()))?;
+            }
         }
 
+        self.output.queue(cursor::MoveTo(self.options.padding.0, self.options.padding.1))?;
+        let styled = if lit {
+            style::style(message).reverse()
+        } else {
+            style::style(message)
+        };
+        self.output.queue(style::PrintStyledContent(styled))?;
+        self.output.queue(cursor::Show)?;
+
+        self.output.flush()
+    }
+
+    /// Renders a single character, styled by the background
+    fn render_character(
+        &mut self,
+        character: &Character,
+        shade: Shade,
+        highlight: bool,
+        tint: Option<Tint>,
+        changed: bool,
+    ) -> io::Result<()> {
+        let styled = self.background.character(character, shade, highlight, tint, changed);
         self.output.queue(style::PrintStyledContent(styled))?;
         Ok(())
     }
 }
 
+#[cfg(test)]
+impl<W: Write> Renderer<W> {
+    /// Returns the frame on screen as plain text, a string per row with its
+    /// trailing spaces left off. Marked-out characters are struck through with
+    /// a combining overlay, and the cursor isn't shown.
+    pub fn screen(&self) -> Vec<String> {
+        let Some(frame) = &self.last_frame else {
+            return Vec::new();
+        };
+        let (left, top) = frame.padding;
+        let mut cells = vec![vec![" ".to_string(); frame.size.0 as usize]; frame.size.1 as usize];
+        let mut paint = |column: u16, row: u16, text: &mut dyn Iterator<Item = String>| {
+            let Some(cells) = cells.get_mut(row as usize) else {
+                return;
+            };
+            let mut column = column as usize;
+            for text in text {
+                let width = text.chars().next().and_then(|c| c.width()).unwrap_or(0);
+                match width {
+                    0 if column > 0 => cells[column - 1].push_str(&text),
+                    0 => (),
+                    width if column + width <= cells.len() => {
+                        cells[column] = text;
+                        // The second half of a wide character is left empty
+                        if width == 2 {
+                            cells[column + 1].clear();
+                        }
+                        column += width;
+                    }
+                    _ => break,
+                }
+            }
+        };
+
+        for (idx, line) in frame.lines.iter().enumerate() {
+            let struck = |(character, _, _): &(Character, _, _)| match character.state {
+                crate::editor::CharacterState::MarkedOut => format!("{}\u{336}", character.value),
+                _ => character.value.to_string(),
+            };
+            paint(left + frame.hidden[idx].1 as u16, top + idx as u16, &mut line.iter().map(struck));
+        }
+        if let Some(status) = &frame.status {
+            paint(left, frame.size.1.saturating_sub(1), &mut status.chars().map(String::from));
+        }
+        if let Some(marks) = &frame.ruler {
+            paint(left, top, &mut marks.iter().map(|(mark, _, _)| mark.to_string()));
+        }
+        for layer in &frame.layers {
+            for (idx, row) in layer.rows.iter().enumerate() {
+                paint(left + layer.left, top + layer.top + idx as u16, &mut row.chars().map(String::from));
+            }
+        }
+        cells.iter().map(|row| row.concat().trim_end().to_string()).collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::editor::Line;
-    use std::time::Duration;
+    use crate::scratch::ScratchDir;
+    use crate::editor::BufferTime;
 
     /// A mock writer for testing
     struct MockWriter {
@@ -145,6 +864,59 @@ mod tests {
         }
     }
 
+    /// Returns the (column, row) of every cell written to, following cursor moves
+    /// and skipping other escape sequences
+    fn painted_cells(output: &[u8]) -> Vec<(usize, usize)> {
+        let text = String::from_utf8_lossy(output);
+        let mut chars = text.chars();
+        let (mut column, mut row) = (0, 0);
+        let mut cells = Vec::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => match chars.next() {
+                    Some('[') => {
+                        let mut params = String::new();
+                        for c in chars.by_ref() {
+                            if ('@'..='~').contains(&c) {
+                                if c == 'H' {
+                                    let mut numbers = params.split(';').map(|n| n.parse::<usize>().unwrap_or(1));
+                                    row = numbers.next().unwrap_or(1) - 1;
+                                    column = numbers.next().unwrap_or(1) - 1;
+                                }
+                                break;
+                            }
+                            params.push(c);
+                        }
+                    }
+                    Some(']') => {
+                        for c in chars.by_ref() {
+                            if c == '\x07' {
+                                break;
+                            }
+                        }
+                    }
+                    _ => (),
+                },
+                '\n' => row += 1,
+                '\r' => column = 0,
+                '\x07' => (),
+                c => {
+                    cells.push((column, row));
+                    column += c.width().unwrap_or(0);
+                    if c.width() == Some(2) {
+                        cells.push((column - 1, row));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    /// Returns the text of each row of the page in a frame
+    fn rows(frame: &Frame) -> Vec<String> {
+        frame.lines.iter().map(|line| line.iter().map(|(character, _, _)| character.value).collect()).collect()
+    }
+
     #[test]
     /// Test renderer creation and basic operations
     fn test_renderer_creation() {
@@ -153,14 +925,318 @@ mod tests {
         assert_eq!(renderer.cursor_pos, (0, 0));
     }
 
+    #[test]
+    /// Test that the status message is drawn in the bottom row
+    fn test_status_rendering() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let buffer = Buffer::new(std::time::Duration::from_millis(100));
+
+        renderer.set_status(Some("Discard changes? (y/n)".to_string()));
+        renderer.render(&buffer).unwrap();
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(output.contains("Discard changes? (y/n)"));
+        // Bottom row of the default 80x24 size (1-based in the escape sequence)
+        assert!(output.contains("\x1b[24;1H"));
+
+        renderer.set_status(None);
+        renderer.output.contents.clear();
+        renderer.render(&buffer).unwrap();
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(!output.contains("Discard changes"));
+
+        // Warnings color the row instead of reversing it
+        renderer.set_status(Some("Paragraph: 290/280 characters".to_string()));
+        renderer.set_status_tone(StatusTone::Over);
+        renderer.output.contents.clear();
+        renderer.render(&buffer).unwrap();
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(output.contains("\x1b[48;5;1m"));
+        assert!(!output.contains("\x1b[7m"));
+    }
+
+    #[test]
+    /// Test that an overlay is drawn as a centered box
+    fn test_overlay_rendering() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let buffer = Buffer::new(std::time::Duration::from_millis(100));
+
+        renderer.set_overlay(Some(Overlay {
+            title: "Menu".to_string(),
+            lines: vec!["Save".to_string(), "Quit".to_string()],
+            highlighted: Some(1),
+        }));
+        renderer.render(&buffer).unwrap();
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(output.contains("┌─ Menu ┐"));
+        assert!(output.contains("│ Save  │"));
+        assert!(output.contains("└───────┘"));
+        // A 9x4 box on an 80x24 screen starts at column 35, row 10 (1-based 36, 11)
+        assert!(output.contains("\x1b[11;36H"));
+    }
+
+    #[test]
+    /// Test that rendering an unchanged screen writes nothing
+    fn test_unchanged_frame_skipped() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        buffer.insert_char('a');
+
+        renderer.render(&buffer).unwrap();
+        assert!(!renderer.output.contents().is_empty());
+
+        renderer.output.contents.clear();
+        renderer.render(&buffer).unwrap();
+        assert!(renderer.output.contents().is_empty());
+
+        // Any change is drawn
+        buffer.insert_char('b');
+        renderer.render(&buffer).unwrap();
+        assert!(renderer.output.contents().contains(&b'b'));
+    }
+
+    #[test]
+    /// Test that only the rows that changed are redrawn, until an overlay
+    /// needs the whole screen
+    fn test_draw_changed_rows() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for c in "one".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.new_line();
+        renderer.set_status(Some("Saved".to_string()));
+        renderer.render(&buffer).unwrap();
+
+        renderer.output.contents.clear();
+        buffer.insert_char('t');
+        renderer.set_status(None);
+        renderer.render(&buffer).unwrap();
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        // The second row is cleared and drawn again, and the status row cleared
+        assert_eq!(output, "\x1b[2;1H\x1b[K\x1b[2;1Ht\n\x1b[24;1H\x1b[2K\x1b[2;2H\x1b[?25h");
+
+        renderer.output.contents.clear();
+        renderer.set_overlay(Some(Overlay {
+            title: "Menu".to_string(),
+            lines: vec!["Save".to_string()],
+            highlighted: None,
+        }));
+        renderer.render(&buffer).unwrap();
+        assert!(String::from_utf8_lossy(renderer.output.contents()).starts_with("\x1b[2J"));
+    }
+
+    #[test]
+    /// Test that the render after invalidate repaints the full screen
+    fn test_invalidate() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        buffer.insert_char('a');
+        renderer.set_status(Some("Status".to_string()));
+        renderer.set_overlay(Some(Overlay {
+            title: "Menu".to_string(),
+            lines: vec!["Save".to_string()],
+            highlighted: None,
+        }));
+        renderer.render(&buffer).unwrap();
+        let first = renderer.output.contents().to_vec();
+
+        renderer.output.contents.clear();
+        renderer.invalidate();
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.output.contents(), &first[..]);
+
+        let output = String::from_utf8_lossy(&first).to_string();
+        assert!(output.contains("\x1b[2J"));
+        assert!(output.contains('a'));
+        assert!(output.contains("Status"));
+        assert!(output.contains("┌─ Menu ┐"));
+    }
+
+    #[test]
+    /// Test that the page scrolls to keep the cursor line in view
+    fn test_scroll_follows_cursor() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.size = (80, 5);
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for _ in 0..9 {
+            buffer.new_line();
+        }
+        buffer.insert_char('z');
+
+        // Ten lines on a page of four rows shows the last four
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_top, 6);
+        assert_eq!(renderer.cursor_pos, (1, 3));
+
+        // The wheel moves the page but not the cursor, which goes out of view
+        renderer.scroll_by(-3);
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_top, 3);
+        assert_eq!(renderer.last_frame.as_ref().unwrap().cursor_pos, None);
+
+        // It can't scroll past the end of the buffer
+        renderer.scroll_by(100);
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_top, 6);
+
+        renderer.scroll_by(-6);
+        renderer.follow_cursor();
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_top, 6);
+    }
+
+    #[test]
+    /// Test that a page put back where it was stays there while the cursor is
+    /// in view, and otherwise moves just far enough to show it
+    fn test_set_scroll_top() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.size = (80, 5);
+        let mut buffer = Buffer::from_reader("line\n".repeat(20).as_bytes(), std::time::Duration::ZERO).unwrap();
+        buffer.move_to_position(10, 0);
+        renderer.set_scroll_top(8);
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_top(), 8);
+
+        // The cursor is below the page kept
+        renderer.set_scroll_top(2);
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_top(), 7);
+
+        // The file is shorter than when the page was kept
+        buffer.move_to_position(20, 0);
+        renderer.set_scroll_top(50);
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_top(), 17);
+    }
+
+    #[test]
+    /// Test that a long line scrolls sideways as the cursor comes within five
+    /// columns of the right edge of an 80-column terminal, and back at the left
+    fn test_scroll_ahead() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for _ in 0..74 {
+            buffer.insert_char('a');
+        }
+        // Columns 75 to 79 are still ahead of the cursor
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_left, 0);
+        assert_eq!(renderer.cursor_pos, (74, 0));
+
+        // The next character takes the cursor into the last five columns
+        buffer.insert_char('b');
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_left, 1);
+        assert_eq!(renderer.cursor_pos, (74, 0));
+        for _ in 0..10 {
+            buffer.insert_char('c');
+        }
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_left, 11);
+        assert_eq!(renderer.cursor_pos, (74, 0));
+        let frame = renderer.last_frame.as_ref().unwrap();
+        assert_eq!(rows(frame), [format!("{}b{}", "a".repeat(63), "c".repeat(10))]);
+        assert_eq!(frame.hidden, [(11, 0)]);
+        assert_eq!(renderer.position_at(0, 0), Some((0, 11)));
+
+        // Stepping back only scrolls once the cursor is within five columns
+        // of the left edge, and a new line goes back to the start
+        for column in (16..85).rev() {
+            buffer.move_to_column(column);
+            renderer.render(&buffer).unwrap();
+        }
+        assert_eq!(renderer.scroll_left, 11);
+        buffer.move_to_column(15);
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_left, 10);
+        buffer.new_line();
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_left, 0);
+
+        // The padding takes columns away from the page, and half of a wide
+        // character scrolled off leaves its column blank
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.set_padding((2, 1));
+        renderer.set_scroll_ahead(3);
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        buffer.insert_char('漢');
+        for _ in 0..72 {
+            buffer.insert_char('a');
+        }
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_left, 0);
+        buffer.insert_char('a');
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_left, 1);
+        assert_eq!(renderer.cursor_pos, (76, 1));
+        let frame = renderer.last_frame.as_ref().unwrap();
+        assert_eq!(rows(frame), ["a".repeat(73)]);
+        assert_eq!(frame.hidden, [(1, 1)]);
+    }
+
+    #[test]
+    /// Test dimming by line count and by sentence
+    fn test_focus() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for (idx, line) in ["One. Two", "three. Fo", "ur"].iter().enumerate() {
+            if idx > 0 {
+                buffer.new_line();
+            }
+            for c in line.chars() {
+                buffer.insert_char(c);
+            }
+        }
+        let dimmed = |renderer: &mut Renderer<MockWriter>| -> Vec<String> {
+            let frame = renderer.frame(&buffer);
+            frame
+                .lines
+                .iter()
+                .map(|line| line.iter().map(|&(_, shade, _)| if shade == Shade::Bright { '+' } else { '-' }).collect())
+                .collect()
+        };
+
+        assert_eq!(dimmed(&mut renderer), ["--------", "+++++++++", "++"]);
+        renderer.set_focus(Focus::Lines(1));
+        assert_eq!(dimmed(&mut renderer), ["--------", "---------", "++"]);
+        renderer.set_focus(Focus::Lines(5));
+        assert_eq!(dimmed(&mut renderer), ["++++++++", "+++++++++", "++"]);
+
+        renderer.set_focus(Focus::Sentence);
+        assert_eq!(dimmed(&mut renderer), ["--------", "-------++", "++"]);
+    }
+
+    #[test]
+    /// Test mapping screen cells back to buffer positions
+    fn test_position_at() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        assert_eq!(renderer.position_at(0, 0), None);
+
+        // "a漢b": the wide character covers columns 1 and 2
+        for c in "a漢b".chars() {
+            buffer.insert_char(c);
+        }
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.cursor_pos, (4, 0));
+        assert_eq!(renderer.position_at(0, 0), Some((0, 0)));
+        assert_eq!(renderer.position_at(1, 0), Some((0, 1)));
+        assert_eq!(renderer.position_at(2, 0), Some((0, 1)));
+        assert_eq!(renderer.position_at(3, 0), Some((0, 2)));
+
+        // Past the end of the line clamps to its end; below the text is nothing
+        assert_eq!(renderer.position_at(40, 0), Some((0, 3)));
+        assert_eq!(renderer.position_at(0, 1), None);
+    }
+
     #[test]
     /// Test character rendering
     fn test_character_rendering() {
-        let mut writer = MockWriter::new();
+        let writer = MockWriter::new();
         let mut renderer = Renderer::new(writer);
         
-        let character = Character::new('a');
-        renderer.render_character(&character, false, false).unwrap();
+        let character = Character::new('a', BufferTime(0));
+        renderer.render_character(&character, Shade::Bright, false, None, false).unwrap();
         
         // The output should contain the character 'a' plus some ANSI codes
         assert!(renderer.output.contents().contains(&b'a'));
@@ -169,16 +1245,347 @@ mod tests {
     #[test]
     /// Test marked out character rendering
     fn test_marked_out_rendering() {
-        let mut writer = MockWriter::new();
+        let writer = MockWriter::new();
         let mut renderer = Renderer::new(writer);
         
-        let mut character = Character::new('a');
+        let mut character = Character::new('a', BufferTime(0));
         character.mark_out();
-        renderer.render_character(&character, false, false).unwrap();
+        renderer.render_character(&character, Shade::Bright, false, None, false).unwrap();
         
         // The output should contain the character 'a' plus ANSI codes for strikethrough
         assert!(renderer.output.contents().contains(&b'a'));
         // Should contain ANSI codes for strikethrough (we don't test the exact codes as they might vary)
         assert!(renderer.output.contents().len() > 1);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    /// Test that dimmed text stays readable on a light background
+    fn test_light_background_rendering() {
+        let character = Character::new('a', BufferTime(0));
+
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.render_character(&character, Shade::Dim, false, None, false).unwrap();
+        assert!(String::from_utf8_lossy(renderer.output.contents()).contains("\x1b[2m"));
+
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.set_background(Background::Light);
+        renderer.render_character(&character, Shade::Dim, false, None, false).unwrap();
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(!output.contains("\x1b[2m"));
+        assert!(output.contains("\x1b[38;5;8m"));
+    }
+
+    #[test]
+    /// Test that output to a file is refused before the terminal is touched,
+    /// with nothing written to it
+    fn test_init_not_a_terminal() {
+        let dir = ScratchDir::new("renderer-init");
+        let path = dir.join("notes");
+        let mut renderer = Renderer::new(std::fs::File::create(&path).unwrap());
+        let error = renderer.init().unwrap_err();
+        assert!(matches!(error, InitError::NotATerminal));
+        assert_eq!(error.to_string(), "output is not a terminal");
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+    }
+
+    #[test]
+    /// Test where the page, cursor, status and overlay land with padding
+    fn test_padding() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.set_padding((4, 2));
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for c in "ab".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.new_line();
+        buffer.insert_char('c');
+        renderer.set_status(Some("Saved".to_string()));
+        renderer.render(&buffer).unwrap();
+
+        // Lines start at column 4 from row 2 (1-based 5 and 3); the status row
+        // keeps the left padding
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(output.contains("\x1b[3;5Hab"));
+        assert!(output.contains("\x1b[4;5Hc"));
+        assert!(output.contains("\x1b[24;5H"));
+        assert!(output.ends_with("\x1b[4;6H\x1b[?25h"));
+        assert_eq!(renderer.cursor_pos, (5, 3));
+
+        // Clicks are mapped back through the padding
+        assert_eq!(renderer.position_at(4, 2), Some((0, 0)));
+        assert_eq!(renderer.position_at(0, 2), Some((0, 0)));
+        assert_eq!(renderer.position_at(5, 3), Some((1, 1)));
+        assert_eq!(renderer.position_at(4, 1), None);
+
+        // A 9x4 box is centered in the 76x22 inside the padding
+        renderer.output.contents.clear();
+        renderer.set_overlay(Some(Overlay {
+            title: "Menu".to_string(),
+            lines: vec!["Save".to_string(), "Quit".to_string()],
+            highlighted: None,
+        }));
+        renderer.render(&buffer).unwrap();
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(output.contains("\x1b[12;38H┌─ Menu ┐"));
+    }
+
+    #[test]
+    /// Test that the padding shrinks the page and raises the minimum size
+    fn test_padding_size() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.size = (80, 10);
+        renderer.set_padding((4, 2));
+        assert_eq!(renderer.size(), (76, 8));
+        assert_eq!(renderer.min_size(), (24, 6));
+
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for _ in 0..9 {
+            buffer.new_line();
+        }
+        // Seven rows are left for the page above the status row
+        renderer.render(&buffer).unwrap();
+        assert_eq!(renderer.scroll_top, 3);
+        assert_eq!(renderer.cursor_pos, (4, 8));
+    }
+
+    #[test]
+    /// Test that the hyphenation hint is drawn faintly at the hard margin
+    fn test_hyphen_hint() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.set_padding((2, 1));
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        buffer.set_hard_margin(Some(12));
+        buffer.set_hyphen_hints(true);
+        for c in "The type".chars() {
+            buffer.insert_char(c);
+        }
+        renderer.render(&buffer).unwrap();
+        // Four columns past the cursor at column 10, row 1 (1-based 15 and 2)
+        assert_eq!(renderer.frame(&buffer).hyphen_hint, Some((14, 1)));
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(output.contains("\x1b[2;15H\x1b[2m‐?"));
+
+        // Not after a space
+        buffer.insert_char(' ');
+        assert_eq!(renderer.frame(&buffer).hyphen_hint, None);
+    }
+
+    #[tokio::test]
+    /// Test that nothing is dimmed, flashed or hinted for a screen reader, and
+    /// the status row is plain
+    async fn test_accessible() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.set_accessible(true);
+        renderer.set_focus(Focus::Lines(1));
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        buffer.set_hard_margin(Some(12));
+        buffer.set_hyphen_hints(true);
+        for c in "Old line".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.new_line();
+        for c in "The type".chars() {
+            buffer.insert_char(c);
+        }
+        renderer.set_status(Some("Rest — typing out…".to_string()));
+        renderer.render(&buffer).unwrap();
+        renderer.flash().await.unwrap();
+
+        let output = String::from_utf8_lossy(renderer.output.contents()).to_string();
+        assert!(!output.contains("\x1b[2m"));
+        assert!(!output.contains('‐'));
+        assert!(!output.contains("\x1b[?5h"));
+        assert!(output.contains("Rest - typing out..."));
+        // The cursor ends up after the last character typed
+        assert!(output.ends_with("\x1b[2;9H\x1b[?25h"));
+    }
+
+    #[test]
+    /// Test that only lines with many corrections are tinted, and only when asked
+    fn test_tint_corrections() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for c in "abc".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.backspace();
+        buffer.insert_char('x');
+        buffer.new_line();
+        buffer.insert_char('d');
+        assert_eq!(renderer.frame(&buffer).tinted, [None, None]);
+
+        renderer.set_tint_corrections(true);
+        assert_eq!(renderer.frame(&buffer).tinted, [Some(Tint::Corrections), None]);
+        renderer.render(&buffer).unwrap();
+        // Dark yellow
+        assert!(String::from_utf8_lossy(renderer.output.contents()).contains("\x1b[38;5;3m"));
+    }
+
+    #[test]
+    /// Test that lines past the page being fitted are tinted, unless tinted
+    /// for their corrections
+    fn test_tint_overflow() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        let mut buffer = Buffer::from_reader("ab\ncd\n".as_bytes(), std::time::Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        for c in "abc".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.backspace();
+        buffer.insert_char('x');
+        renderer.set_overflow_from(Some(1));
+        assert_eq!(renderer.frame(&buffer).tinted, [None, Some(Tint::Overflow), Some(Tint::Overflow)]);
+        renderer.set_tint_corrections(true);
+        assert_eq!(renderer.frame(&buffer).tinted, [None, Some(Tint::Overflow), Some(Tint::Corrections)]);
+
+        renderer.set_overflow_from(None);
+        renderer.set_tint_corrections(false);
+        assert_eq!(renderer.frame(&buffer).tinted, [None, None, None]);
+        renderer.set_overflow_from(Some(2));
+        renderer.render(&buffer).unwrap();
+        // Dark cyan
+        assert!(String::from_utf8_lossy(renderer.output.contents()).contains("\x1b[38;5;6m"));
+    }
+
+    #[test]
+    /// Test that trailing whitespace is drawn as faint dots when asked, except
+    /// on the line being typed
+    fn test_show_trailing_whitespace() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.set_focus(Focus::Lines(10));
+        let mut buffer = Buffer::from_reader("a b  \nc \t".as_bytes(), std::time::Duration::ZERO).unwrap();
+        let drawn = |renderer: &mut Renderer<MockWriter>, buffer: &Buffer| rows(&renderer.frame(buffer));
+        // The tab is loaded as spaces to the next tab stop
+        assert_eq!(drawn(&mut renderer, &buffer), ["a b  ", "c       "]);
+
+        renderer.set_show_trailing_whitespace(true);
+        // The cursor is still on the first line
+        assert_eq!(drawn(&mut renderer, &buffer), ["a b  ", "c·······"]);
+        buffer.move_to_end();
+        assert_eq!(drawn(&mut renderer, &buffer), ["a b··", "c       "]);
+        let shades: Vec<Shade> = renderer.frame(&buffer).lines[0].iter().map(|&(_, shade, _)| shade).collect();
+        assert_eq!(shades, [Shade::Bright, Shade::Bright, Shade::Bright, Shade::Dim, Shade::Dim]);
+
+        renderer.render(&buffer).unwrap();
+        assert!(String::from_utf8_lossy(renderer.output.contents()).contains("a b\x1b[2m·\x1b[0m\x1b[2m·\x1b[0m\n"));
+    }
+
+    #[test]
+    /// Test that spaces typed at the end of the line show as dots until the
+    /// line is left, and that the other setting's dots are kept for other lines
+    fn test_show_current_trailing_spaces() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.set_focus(Focus::Lines(10));
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for c in "a b  ".chars() {
+            buffer.insert_char(c);
+        }
+        let drawn = |renderer: &mut Renderer<MockWriter>, buffer: &Buffer| rows(&renderer.frame(buffer));
+        assert_eq!(drawn(&mut renderer, &buffer), ["a b  "]);
+
+        renderer.set_show_current_trailing_spaces(true);
+        assert_eq!(drawn(&mut renderer, &buffer), ["a b··"]);
+        buffer.insert_char('c');
+        assert_eq!(drawn(&mut renderer, &buffer), ["a b  c"]);
+        buffer.insert_char(' ');
+        buffer.new_line();
+        assert_eq!(drawn(&mut renderer, &buffer), ["a b  c ", ""]);
+        renderer.set_show_trailing_whitespace(true);
+        assert_eq!(drawn(&mut renderer, &buffer), ["a b  c·", ""]);
+    }
+
+    #[test]
+    /// Test that changes since the last save are underlined when asked, and
+    /// drawn plain again once saved
+    fn test_show_changes() {
+        let dir = ScratchDir::new("renderer-changes");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "ab").unwrap();
+        let mut buffer = Buffer::from_file(&path.to_string_lossy(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        buffer.insert_char('c');
+        let drawn = |renderer: &mut Renderer<MockWriter>, buffer: &Buffer| {
+            renderer.output = MockWriter::new();
+            renderer.render(buffer).unwrap();
+            String::from_utf8_lossy(renderer.output.contents()).to_string()
+        };
+
+        let mut renderer = Renderer::new(MockWriter::new());
+        assert!(!drawn(&mut renderer, &buffer).contains("\x1b[4m"));
+        renderer.set_show_changes(true);
+        let output = drawn(&mut renderer, &buffer);
+        assert!(output.contains("ab\x1b[4mc\x1b[0m"));
+
+        buffer.save().unwrap();
+        let output = drawn(&mut renderer, &buffer);
+        assert!(output.contains("abc") && !output.contains("\x1b[4m"));
+    }
+
+    #[test]
+    /// Test that after the terminal shrinks the page, status row and overlays
+    /// are laid out again and nothing is drawn outside it
+    fn test_resize() {
+        let mut renderer = Renderer::new(MockWriter::new());
+        renderer.resize(100, 30);
+        let mut buffer = Buffer::new(std::time::Duration::ZERO);
+        for idx in 0..40 {
+            if idx > 0 {
+                buffer.new_line();
+            }
+            for c in "The quick brown fox jumps over the lazy dog, 漢字 and all. ".repeat(2).chars() {
+                buffer.insert_char(c);
+            }
+        }
+        // With the cursor at the start of its line, the page isn't scrolled sideways
+        buffer.move_to_column(0);
+        renderer.set_status(Some("Paragraph: 245/280 characters ".repeat(4)));
+        let help = Overlay {
+            title: "Help".to_string(),
+            lines: (0..25).map(|idx| format!("{:>2} {}", idx, "Ctrl+Shift+S  Save under a new name 漢字 ".repeat(2))).collect(),
+            highlighted: Some(20),
+        };
+        let rest = Overlay {
+            title: "Rest".to_string(),
+            lines: vec!["4:32 remaining".to_string()],
+            highlighted: None,
+        };
+        renderer.set_overlays(vec![rest, help]);
+
+        for size in [(100, 30), (60, 15)] {
+            renderer.output.contents.clear();
+            renderer.resize(size.0, size.1);
+            renderer.render(&buffer).unwrap();
+            let output = renderer.output.contents().to_vec();
+            assert!(String::from_utf8_lossy(&output).starts_with("\x1b[2J"));
+
+            let cells = painted_cells(&output);
+            assert!(cells.iter().all(|&(column, row)| column < size.0 as usize && row < size.1 as usize));
+            // The page and the overlay reach the right edge and the bottom row
+            assert!(cells.contains(&(size.0 as usize - 1, 0)));
+            assert!(cells.iter().any(|&(_, row)| row == size.1 as usize - 1));
+        }
+
+        // The help box fills the shorter screen, with the highlighted row cut off
+        let frame = renderer.last_frame.as_ref().unwrap();
+        assert_eq!(frame.layers[1].rows.len(), 15);
+        assert_eq!((frame.layers[1].left, frame.layers[1].top), (0, 0));
+        assert_eq!(frame.layers[1].highlighted_row, None);
+    }
+
+    #[test]
+    /// Test that overlays are cut down to the screen by width, and left out when
+    /// not even their borders fit
+    fn test_overlay_layout_clamped() {
+        let overlay = Overlay {
+            title: "漢字漢字".to_string(),
+            lines: vec!["漢字漢字漢字".to_string(), "ab".to_string()],
+            highlighted: None,
+        };
+        let layout = overlay.layout((11, 10));
+        assert_eq!(layout.rows, ["┌─ 漢字漢 ┐", "│ 漢字漢  │", "│ ab      │", "└─────────┘"]);
+        assert!(layout.rows.iter().all(|row| text_width(row) <= 11));
+
+        assert!(overlay.layout((3, 10)).rows.is_empty());
+        assert!(overlay.layout((40, 1)).rows.is_empty());
+    }
+}
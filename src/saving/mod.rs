@@ -0,0 +1,104 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use tokio::task::JoinHandle;
+
+/// Writes a file's text. `fs::write` unless a test stands in a slower one.
+#[derive(Clone, Copy)]
+pub struct Writer(pub fn(&str, &str) -> io::Result<()>);
+
+impl Default for Writer {
+    fn default() -> Self {
+        Writer(|path, text| fs::write(path, text))
+    }
+}
+
+impl fmt::Debug for Writer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Writer")
+    }
+}
+
+/// Writes files on a blocking thread, so a slow disk or network mount holds
+/// up neither typing nor the text appearing. One write runs at a time; the
+/// app holds back further saves until it has heard how this one went.
+#[derive(Debug, Default)]
+pub struct Saver {
+    /// What does the writing
+    writer: Writer,
+    /// The write in progress, if any
+    task: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Saver {
+    /// Creates a saver that writes with `writer`
+    pub fn new(writer: Writer) -> Self {
+        Self { writer, task: None }
+    }
+
+    /// Starts writing `text` to `path`. The caller waits for the previous
+    /// write to finish first.
+    pub fn start(&mut self, path: String, text: String) {
+        let Writer(write) = self.writer;
+        self.task = Some(tokio::task::spawn_blocking(move || write(&path, &text)));
+    }
+
+    /// Returns whether a write is in progress
+    pub fn is_busy(&self) -> bool {
+        self.task.is_some()
+    }
+
+    /// Waits for the write in progress to finish, or forever while there is
+    /// none. Safe to cancel.
+    pub async fn finished(&mut self) -> io::Result<()> {
+        let Some(task) = &mut self.task else {
+            return std::future::pending().await;
+        };
+        let result = task.await.unwrap_or_else(|e| Err(io::Error::other(e)));
+        self.task = None;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+    use std::time::{Duration, Instant};
+
+    /// Writes after a pause, like a slow network mount
+    fn slow_write(path: &str, text: &str) -> io::Result<()> {
+        std::thread::sleep(Duration::from_millis(100));
+        fs::write(path, text)
+    }
+
+    #[tokio::test]
+    /// Test that a slow write leaves the runtime free until it finishes, and
+    /// that an idle saver never finishes
+    async fn test_slow_write() {
+        let dir = ScratchDir::new("saving");
+        let path = dir.join("notes.txt");
+        let mut saver = Saver::new(Writer(slow_write));
+        let idle = tokio::time::timeout(Duration::from_millis(50), saver.finished()).await;
+        assert!(idle.is_err());
+
+        let start = Instant::now();
+        saver.start(path.to_string_lossy().into_owned(), "hi\n".to_string());
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert!(saver.is_busy());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!path.exists());
+
+        saver.finished().await.unwrap();
+        assert!(!saver.is_busy());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hi\n");
+    }
+
+    #[tokio::test]
+    /// Test that a failed write is reported
+    async fn test_failure() {
+        let mut saver = Saver::default();
+        saver.start("/nonexistent/typewriter/notes.txt".to_string(), String::new());
+        assert_eq!(saver.finished().await.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
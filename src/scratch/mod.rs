@@ -0,0 +1,70 @@
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Directories made so far by this process, so no two tests share one
+static MADE: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory for one test, removed with everything in it once the
+/// test is done with it. Bind it to a name for as long as the test uses it:
+/// a temporary one is gone by the end of its statement.
+#[derive(Debug)]
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    /// Makes an empty directory under the system's temporary one, named after
+    /// `name` and the process
+    pub fn new(name: &str) -> Self {
+        let made = MADE.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("typewriter-{}-{}-{}", name, process::id(), made));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for ScratchDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that each directory is empty, its own and removed once dropped
+    fn test_scratch_dir() {
+        let dir = ScratchDir::new("scratch");
+        let other = ScratchDir::new("scratch");
+        assert_ne!(dir.to_path_buf(), other.to_path_buf());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::create_dir(dir.join("nested")).unwrap();
+        fs::write(dir.join("nested").join("notes.txt"), "text").unwrap();
+        let path = dir.to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+        assert!(other.exists());
+    }
+}
@@ -0,0 +1,1404 @@
+use crate::app::{Alert, App, Effect, SessionEnd, SessionOptions, View};
+use crate::cli::Cli;
+use crate::clipboard::CopyMethod;
+use crate::config::{self, Config};
+use crate::editor::{Buffer, BufferBuilder};
+use crate::hooks::{HookOutcome, SaveHook};
+use crate::input::{EventSource, InputEvent, InputHandler};
+use crate::pacing::FramePacer;
+use crate::pomodoro::Pomodoro;
+use crate::print::{self, CommandSpooler};
+use crate::recent::RecentFiles;
+use crate::renderer::Renderer;
+use crate::saving::Saver;
+use crate::signals::{Signal, SignalSource};
+use crate::sound::{BellStyle, SoundSystem, SoundType};
+use crate::stats::SessionStats;
+use crate::ui::{Picker, PickerOutcome, Wizard, WizardChoices, WizardOutcome};
+use crate::{hooks, messages};
+use std::io::{self, stdout, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Pause between rings of the terminal bell
+const BELL_GAP: Duration = Duration::from_millis(250);
+
+/// What was chosen on the opening screen
+pub enum Picked {
+    /// A file, loaded and ready to edit
+    Open(Box<Buffer>),
+    /// A new, unnamed document
+    New,
+    /// Nothing; typewriter should exit
+    Quit,
+    /// A signal other than SIGINT arrived first
+    Signalled(Signal),
+}
+
+/// How the first-run setup ended
+enum SetupEnd {
+    /// Every question was answered
+    Finished(WizardChoices),
+    /// The user skipped it, keeping the defaults
+    Skipped,
+    /// A signal other than SIGINT arrived first
+    Signalled(Signal),
+}
+
+/// Asks the first-run questions in a terminal session of their own, then
+/// writes the config file: with the answers, or with every setting left at
+/// its default if they were skipped. Returns the signal that cut it short, if
+/// any, in which case nothing is written.
+pub async fn first_run(path: &Path, cli: &Cli, signals: &mut impl SignalSource) -> io::Result<Option<Signal>> {
+    let defaults = Config::default();
+    let mut renderer = Renderer::new(stdout());
+    renderer.init()?;
+    let mut input_handler = InputHandler::new(defaults.input_timeout);
+    input_handler.set_keyboard_enhanced(renderer.keyboard_enhanced());
+
+    // The sound system is tried once, at the first test click, so a user who
+    // keeps sound off never opens the audio device
+    let latency = cli.audio_latency_ms.map_or(defaults.audio_latency, Duration::from_millis);
+    let seed = cli.seed.unwrap_or_else(rand::random);
+    let mut sound_system: Option<Option<SoundSystem>> = None;
+    let click = || {
+        if let Some(sound) = sound_system.get_or_insert_with(|| SoundSystem::new(latency, seed, defaults.layout)) {
+            sound.schedule_sound(SoundType::KeyPress('a'), Instant::now());
+        }
+    };
+    let ended = setup(&mut input_handler, &mut renderer, signals, click).await;
+    renderer.cleanup()?;
+
+    let settings = match ended? {
+        SetupEnd::Finished(choices) => vec![
+            ("sound", choices.sound.to_string()),
+            ("reveal_rate_ms", choices.reveal_rate.as_millis().to_string()),
+        ],
+        SetupEnd::Skipped => Vec::new(),
+        SetupEnd::Signalled(signal) => return Ok(Some(signal)),
+    };
+    config::write_new(path, &config::template_with(&settings))?;
+    log::info!("wrote the first config to {}", path.display());
+    Ok(None)
+}
+
+/// Runs the first-run questions until they are answered or skipped, calling
+/// `click` whenever sound is highlighted
+async fn setup<S: EventSource, W: Write>(
+    input_handler: &mut InputHandler<S>,
+    renderer: &mut Renderer<W>,
+    signals: &mut impl SignalSource,
+    mut click: impl FnMut(),
+) -> io::Result<SetupEnd> {
+    let page = Buffer::new(Config::default().reveal_rate);
+    let mut wizard = Wizard::new(Instant::now());
+    loop {
+        renderer.set_status(Some("Up/Down to choose, Enter to go on, Esc to skip".to_string()));
+        renderer.set_status_cursor(None);
+        renderer.set_overlay(Some(wizard.overlay(Instant::now())));
+        renderer.render(&page)?;
+
+        // The demo line moves on while no key is pressed
+        let idle = input_handler.timeout();
+        let event = tokio::select! {
+            event = input_handler.next_event() => event?,
+            _ = tokio::time::sleep(idle) => continue,
+            signal = signals.recv() => match signal {
+                Signal::Interrupt => return Ok(SetupEnd::Skipped),
+                signal => return Ok(SetupEnd::Signalled(signal)),
+            }
+        };
+        match event {
+            InputEvent::Ignored | InputEvent::FocusGained | InputEvent::FocusLost => continue,
+            InputEvent::Redraw => {
+                renderer.invalidate();
+                continue;
+            }
+            InputEvent::Resize(columns, rows) => {
+                renderer.resize(columns, rows);
+                continue;
+            }
+            _ => (),
+        }
+
+        match wizard.handle(&event, Instant::now()) {
+            WizardOutcome::Open => (),
+            WizardOutcome::Click => click(),
+            WizardOutcome::Finished(choices) => return Ok(SetupEnd::Finished(choices)),
+            WizardOutcome::Skipped => return Ok(SetupEnd::Skipped),
+        }
+    }
+}
+
+/// Shows the opening screen over a blank page until a file is chosen, a new
+/// document is asked for, or the user quits. A chosen file is opened with the
+/// builder, its cursor on the line the last session on it ended on.
+pub async fn pick<S: EventSource, W: Write>(
+    input_handler: &mut InputHandler<S>,
+    renderer: &mut Renderer<W>,
+    signals: &mut impl SignalSource,
+    mut picker: Picker,
+    builder: &BufferBuilder,
+    recent: &RecentFiles,
+) -> io::Result<Picked> {
+    let page = Buffer::new(Duration::ZERO);
+    // Shown in the status row until the next key press
+    let mut message: Option<String> = None;
+    loop {
+        let status = message.clone().unwrap_or_else(|| "Enter to open, n for a new page, Esc to quit".to_string());
+        renderer.set_status(Some(status));
+        renderer.set_status_cursor(None);
+        renderer.set_overlay(Some(picker.overlay(renderer.size().1)));
+        renderer.render(&page)?;
+
+        let event = tokio::select! {
+            event = input_handler.next_event() => event?,
+            signal = signals.recv() => match signal {
+                Signal::Interrupt => return Ok(Picked::Quit),
+                signal => return Ok(Picked::Signalled(signal)),
+            }
+        };
+        match event {
+            InputEvent::Ignored | InputEvent::FocusGained | InputEvent::FocusLost => continue,
+            InputEvent::Redraw => {
+                renderer.invalidate();
+                continue;
+            }
+            InputEvent::Resize(columns, rows) => {
+                renderer.resize(columns, rows);
+                continue;
+            }
+            _ => message = None,
+        }
+
+        match picker.handle(&event, renderer.size().1) {
+            PickerOutcome::Open => (),
+            PickerOutcome::Chosen(path) => {
+                let position = recent.position_for(&path).map(|position| (position.line, position.column));
+                let builder = builder.clone().cursor(position);
+                match builder.open(&path.to_string_lossy()) {
+                    Ok(buffer) => return Ok(Picked::Open(Box::new(buffer))),
+                    Err(e) => message = Some(format!("Couldn't open {}: {}", path.display(), e)),
+                }
+            }
+            PickerOutcome::New => return Ok(Picked::New),
+            PickerOutcome::Quit => return Ok(Picked::Quit),
+        }
+    }
+}
+
+/// Runs the editor until the user quits or a signal other than SIGINT arrives
+#[allow(clippy::too_many_arguments)]
+pub async fn run<S: EventSource, W: Write>(
+    input_handler: &mut InputHandler<S>,
+    renderer: &mut Renderer<W>,
+    buffer: &mut Buffer,
+    signals: &mut impl SignalSource,
+    mut sound_system: Option<SoundSystem>,
+    pomodoro: &mut Option<Pomodoro>,
+    stats: &mut SessionStats,
+    options: SessionOptions,
+) -> io::Result<SessionEnd> {
+    let mut hook = options.on_save.clone().map(SaveHook::new);
+    let mut saver = Saver::new(options.writer);
+    let mut pacer = FramePacer::new(options.max_fps);
+    let keymap = input_handler.keymap().clone();
+    let clock = options.clock.clone();
+    let mut app = App::new(buffer, pomodoro, stats, keymap, renderer.size(), options);
+
+    // Main event loop, up to the end of the session
+    let end: io::Result<SessionEnd> = async {
+        loop {
+            // Render current state
+            let now = clock.now();
+            let end = apply(app.tick(now), &mut app, renderer, &mut sound_system, &mut hook, &mut saver).await?;
+            app.set_sound(sound_system.is_some());
+            show(app.view(now), renderer);
+            renderer.render(app.buffer())?;
+            pacer.rendered(now);
+            // A close waiting for the page to finish ends after the last frame
+            if let Some(end) = end {
+                return Ok(end);
+            }
+
+            // Wait for input, until more text is due to appear, for a timer, for
+            // the next replayed key, or for the idle tick that keeps countdowns
+            // and the clock moving. Text appearing on its own is drawn no faster
+            // than the frame rate cap; input is drawn straight away. Anything
+            // but input only needs the next frame, which runs the timers.
+            let next_reveal = pacer.next_wake(app.next_reveal(clock.now()));
+            let idle = input_handler.timeout();
+            let first = tokio::select! {
+                event = input_handler.next_event() => Some(event?),
+                _ = sleep_until(next_reveal) => None,
+                _ = sleep_until(app.next_deadline()) => None,
+                _ = tokio::time::sleep(idle) => None,
+                // A save written in the background is taken in as soon as it is done
+                written = saver.finished() => {
+                    let effects = app.written(written, clock.now());
+                    if let Some(end) = apply(effects, &mut app, renderer, &mut sound_system, &mut hook, &mut saver).await? {
+                        return Ok(end);
+                    }
+                    None
+                }
+                // The audio output opens in the background; the session goes on without sound if it can't
+                opened = sound_ready(&mut sound_system) => {
+                    if let Err(e) = opened {
+                        sound_system = None;
+                        app.notify(messages::Level::Error, format!("Sound is off: {}", e));
+                    }
+                    None
+                }
+                outcome = hook_finished(&mut hook) => {
+                    if let Some(warning) = outcome.warning() {
+                        app.notify(messages::Level::Error, warning);
+                    }
+                    None
+                }
+                _ = sleep_until(app.next_replay()) => {
+                    let effects = app.replay(clock.now());
+                    if let Some(end) = apply(effects, &mut app, renderer, &mut sound_system, &mut hook, &mut saver).await? {
+                        return Ok(end);
+                    }
+                    None
+                }
+                // SIGINT is taken as the close command, like Ctrl+C in raw mode
+                signal = signals.recv() => match signal {
+                    Signal::Interrupt => Some(InputEvent::Close),
+                    signal => {
+                        // Let a save being written finish, so the file isn't left half written
+                        if saver.is_busy() {
+                            app.written(saver.finished().await, clock.now());
+                        }
+                        return Ok(SessionEnd::Signalled(signal));
+                    }
+                }
+            };
+
+            // Handle everything typed since the last render before rendering again,
+            // so a burst of keys doesn't fall behind. The events are applied in order,
+            // so a save or close in the middle of a burst happens at its place in it.
+            let Some(first) = first else {
+                continue;
+            };
+            for event in input_handler.burst(first).await? {
+                let effects = app.handle(event, clock.now());
+                if let Some(end) = apply(effects, &mut app, renderer, &mut sound_system, &mut hook, &mut saver).await? {
+                    return Ok(end);
+                }
+            }
+        }
+    }
+    .await;
+
+    // A hook run for the last save gets a moment to finish, unless a signal
+    // asks to be quick
+    if let Some(hook) = &mut hook {
+        let grace = if matches!(end, Ok(SessionEnd::Signalled(_))) { Duration::ZERO } else { hooks::CLOSE_GRACE };
+        hook.settle(grace).await;
+    }
+    end
+}
+
+/// Carries out what the app asked for, in order, up to the end of the
+/// session if it asked for that
+pub async fn apply<W: Write>(
+    effects: Vec<Effect>,
+    app: &mut App<'_>,
+    renderer: &mut Renderer<W>,
+    sound_system: &mut Option<SoundSystem>,
+    hook: &mut Option<SaveHook>,
+    saver: &mut Saver,
+) -> io::Result<Option<SessionEnd>> {
+    for effect in effects {
+        match effect {
+            Effect::Sound { sound, at } => {
+                if let Some(sound_system) = sound_system {
+                    sound_system.schedule_sound(sound, at);
+                }
+            }
+            Effect::Alert(kind) => alert(renderer, sound_system.as_ref(), app.options().bell, kind).await?,
+            Effect::ToggleSound => {
+                *sound_system = match sound_system.take() {
+                    Some(_) => None,
+                    None => SoundSystem::new(app.options().audio_latency, app.options().seed, app.options().layout),
+                };
+            }
+            Effect::Write { path, text } => saver.start(path, text),
+            Effect::Saved { path, words } => {
+                if let Some(hook) = hook {
+                    hook.saved(&path, words);
+                }
+            }
+            Effect::FollowCursor => renderer.follow_cursor(),
+            Effect::Scroll(lines) => renderer.scroll_by(lines),
+            Effect::Resize(columns, rows) => renderer.resize(columns, rows),
+            Effect::Redraw => renderer.invalidate(),
+            Effect::Click(column, row) => {
+                if let Some((line, column)) = renderer.position_at(column, row) {
+                    app.click_at(line, column);
+                }
+            }
+            Effect::Copy(text) => {
+                let words = text.split_whitespace().count();
+                let (level, text) = match renderer.copy_to_clipboard(&text) {
+                    Ok(CopyMethod::Terminal | CopyMethod::Both) => (messages::Level::Info, format!("Copied {} words", words)),
+                    Ok(CopyMethod::System) => {
+                        (messages::Level::Info, format!("Copied {} words to the system clipboard", words))
+                    }
+                    Err(e) => (messages::Level::Error, format!("Couldn't copy: {}", e)),
+                };
+                app.notify(level, text);
+            }
+            Effect::Print { text, title } => {
+                let mut spooler = CommandSpooler::new(app.options().print_command.clone());
+                let printed = print::print(&mut spooler, &text, &title, chrono::Local::now().date_naive());
+                let (level, text) = match printed {
+                    Ok(pages) => (messages::Level::Info, format!("Sent {} to the printer", print::pages_word(pages))),
+                    Err(e) => (messages::Level::Error, format!("Couldn't print: {}", e)),
+                };
+                app.notify(level, text);
+            }
+            Effect::Exit(end) => return Ok(Some(end)),
+        }
+    }
+    Ok(None)
+}
+
+/// Updates the renderer's status row, overlays and ruler to what the app shows
+pub fn show<W: Write>(view: View, renderer: &mut Renderer<W>) {
+    renderer.set_ruler(view.ruler);
+    renderer.set_status_tone(view.tone);
+    renderer.set_status(view.status);
+    renderer.set_status_cursor(view.status_cursor);
+    renderer.set_overlays(view.overlays);
+    renderer.set_show_changes(view.show_changes);
+    renderer.set_overflow_from(view.overflow_from);
+}
+
+/// Calls attention to something the way the `bell` setting asks. Every bell
+/// and flash goes through here: a bell is a tone from the sound system (the
+/// terminal's bell while sound is off), the terminal's bell or a flash, and a
+/// flash is shown unless the bell is off altogether.
+async fn alert<W: Write>(
+    renderer: &mut Renderer<W>,
+    sound_system: Option<&SoundSystem>,
+    style: BellStyle,
+    kind: Alert,
+) -> io::Result<()> {
+    match (kind, style) {
+        (_, BellStyle::None) => Ok(()),
+        (Alert::Flash, _) => renderer.flash().await,
+        (Alert::Bell(times), style) => {
+            for ring in 0..times {
+                if ring > 0 {
+                    tokio::time::sleep(BELL_GAP).await;
+                }
+                match (style, sound_system) {
+                    (BellStyle::Audio, Some(sound_system)) => sound_system.schedule_sound(SoundType::Bell, Instant::now()),
+                    (BellStyle::Visual, _) => renderer.flash().await?,
+                    _ => renderer.bell()?,
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Waits for the save hook's current run to end, or forever if there is no hook
+async fn hook_finished(hook: &mut Option<SaveHook>) -> HookOutcome {
+    match hook {
+        Some(hook) => hook.finished().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the sound system's audio output to open, or forever once it has
+/// or if there is no sound system
+async fn sound_ready(sound_system: &mut Option<SoundSystem>) -> Result<(), String> {
+    match sound_system {
+        Some(sound_system) => sound_system.ready().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleeps until the given moment, or forever if there is none
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drafts;
+    use crate::input::ScriptedSource;
+    use crate::lock::Owner;
+    use crate::macros;
+    use crate::paragraph::Limit;
+    use crate::recent::Position;
+    use crate::saving::Writer;
+    use crate::scratch::ScratchDir;
+    use crate::signals::ScriptedSignals;
+    use crate::stats::DEFAULT_IDLE_THRESHOLD;
+    use crate::ui::Menu;
+    use clap::Parser;
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+    #[tokio::test]
+    /// Test that the main components can be initialized
+    async fn test_component_initialization() {
+        let reveal_rate = Duration::from_millis(100);
+        let input_timeout = Duration::from_millis(50);
+        
+        let buffer = Buffer::new(reveal_rate);
+        let _input_handler = InputHandler::new(input_timeout);
+        let renderer = Renderer::new(Vec::new()); // Use Vec as a mock writer
+        
+        assert_eq!(buffer.reveal_rate(), reveal_rate);
+        assert!(renderer.output.is_empty());
+    }
+
+    #[tokio::test]
+    /// Test that a burst of queued keys is handled before a single render
+    async fn test_burst_renders_once() {
+        let abort = Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL));
+        let script = ScriptedSource::keys("hello")
+            .chain([None, Some(abort)])
+            .chain(ScriptedSource::keys("y"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+
+        let mut signals = ScriptedSignals::none();
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut None,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Discarded);
+
+        // One render of the whole empty page, then one redrawing its row with
+        // all five keys
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert_eq!(output.matches("\x1b[2J").count(), 1);
+        assert_eq!(output.matches("\x1b[?25h").count(), 2);
+        assert!(output.contains("hello"));
+        assert_eq!(buffer.lines[0].len(), 5);
+    }
+
+    #[tokio::test]
+    /// Test that a zero-width non-joiner typed between two letters neither
+    /// moves the cursor nor counts as a key
+    async fn test_invisible_key() {
+        let abort = Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL));
+        let script = ScriptedSource::keys("a\u{200C}b").chain([None, Some(abort)]).chain(ScriptedSource::keys("y"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut signals = ScriptedSignals::none();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let options = SessionOptions::default();
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut None,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Discarded);
+        assert_eq!(buffer.text(), "ab");
+        assert_eq!(buffer.cursor_position(), (0, 2));
+    }
+
+    #[tokio::test]
+    /// Test that each bell style rings the terminal's bell, flashes or does
+    /// nothing, with the audio bell falling back to the terminal's
+    async fn test_alert() {
+        let cases = [
+            (BellStyle::Audio, "\x07\x07"),
+            (BellStyle::Terminal, "\x07\x07"),
+            (BellStyle::Visual, "\x1b[?5h\x1b[?5l\x1b[?5h\x1b[?5l"),
+            (BellStyle::None, ""),
+        ];
+        for (style, bell) in cases {
+            let mut renderer = Renderer::new(Vec::new());
+            alert(&mut renderer, None, style, Alert::Bell(2)).await.unwrap();
+            assert_eq!(String::from_utf8_lossy(&renderer.output), bell, "{:?}", style);
+
+            // A flash is a flash in every style but none
+            let mut renderer = Renderer::new(Vec::new());
+            alert(&mut renderer, None, style, Alert::Flash).await.unwrap();
+            let flash = if style == BellStyle::None { "" } else { "\x1b[?5h\x1b[?5l" };
+            assert_eq!(String::from_utf8_lossy(&renderer.output), flash, "{:?}", style);
+        }
+    }
+
+    #[tokio::test]
+    /// Test that a resize with the menu open lays the menu out again for the new
+    /// size without closing it
+    async fn test_resize_with_menu_open() {
+        let key = |code| Some(Event::Key(KeyEvent::new(code, KeyModifiers::empty())));
+        let abort = Some(Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL)));
+        let script = ScriptedSource::keys("hi")
+            .chain([key(KeyCode::Esc), None, Some(Event::Resize(40, 10)), None, key(KeyCode::Esc), abort])
+            .chain(ScriptedSource::keys("y"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+
+        let mut signals = ScriptedSignals::none();
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut None,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Discarded);
+        assert_eq!(renderer.size(), (40, 10));
+        assert_eq!(buffer.lines[0].len(), 2);
+
+        // The menu is drawn twice, the second time centered on the smaller screen
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        let layout = Menu::main_menu().overlay().layout((40, 10));
+        let corner = format!("\x1b[{};{}H┌─ Menu", layout.top + 1, layout.left + 1);
+        assert_eq!(output.matches("┌─ Menu").count(), 2);
+        assert!(output.contains(&corner));
+    }
+
+    #[tokio::test]
+    /// Test setting the margin along the ruler and saving it to the config file
+    async fn test_set_margin() {
+        let dir = ScratchDir::new("margin");
+        let config_path = dir.join("config.toml");
+        let key = |code| Some(Event::Key(KeyEvent::new(code, KeyModifiers::empty())));
+        let alt_m = Some(Event::Key(KeyEvent::new(KeyCode::Char('m'), KeyModifiers::ALT)));
+        let abort = Some(Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL)));
+        let script = ScriptedSource::keys("abcdef")
+            .chain([None, alt_m, None, key(KeyCode::Left), key(KeyCode::Left), key(KeyCode::Enter), None])
+            .chain(ScriptedSource::keys("y"))
+            .chain([None, abort])
+            .chain(ScriptedSource::keys("y"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+
+        let mut signals = ScriptedSignals::none();
+        let options = SessionOptions {
+            config_path: Some(config_path.clone()),
+            ..Default::default()
+        };
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut None,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Discarded);
+        assert_eq!(buffer.hard_margin(), Some(70));
+        assert_eq!(buffer.text(), "abcdef");
+
+        // The ruler showed the stop at 72, two past the 70 mark, and the config
+        // file was started from the template
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("\x1b[2m7\x1b[0m\x1b[2m.\x1b[0m\x1b[7m.\x1b[0m"));
+        assert!(output.contains("Margin set at 70. Save it to the config file? (y/n)"));
+        let saved = Config::load(&config_path).unwrap();
+        assert_eq!(saved.hard_margin, Some(70));
+    }
+
+    #[tokio::test]
+    /// Test that with --accessible a typed character shows in the very next
+    /// render, with the cursor after it, and marking out rings the bell
+    async fn test_accessible() {
+        let mut config = Config::default();
+        Cli::try_parse_from(["typewriter", "--accessible"]).unwrap().apply(&mut config);
+        let erase = Some(Event::Key(KeyEvent::new(KeyCode::Backspace, KeyModifiers::empty())));
+        let abort = Some(Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL)));
+        let script = ScriptedSource::keys("a")
+            .chain([None, erase])
+            .chain(ScriptedSource::keys("x"))
+            .chain([None, abort])
+            .chain(ScriptedSource::keys("y"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        renderer.set_accessible(config.accessible);
+        let mut buffer = Buffer::new(config.reveal_rate);
+        let mut signals = ScriptedSignals::none();
+        let options = SessionOptions {
+            accessible: config.accessible,
+            ..Default::default()
+        };
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut None,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Discarded);
+
+        // The first render after the key redraws its row with it, the cursor
+        // after it, and the next shows it marked out after the bell
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        let frames: Vec<&str> = output.split("\x1b[?25h").collect();
+        assert_eq!(frames[1], "\x1b[1;1H\x1b[K\x1b[1;1Ha\n\x1b[1;2H");
+        assert_eq!(frames[2], "\x07\x1b[1;1H\x1b[K\x1b[1;1H\x1b[9ma\x1b[0m\n\x1b[1;2H");
+    }
+
+    #[tokio::test]
+    /// Test that a big paste is asked about and then typed out at once, and a
+    /// small one is typed as if by hand
+    async fn test_large_paste() {
+        let paste = |text: &str| Some(Event::Paste(text.to_string()));
+        let big = format!("Chapter One\n{}", "word ".repeat(1000));
+        let abort = Some(Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL)));
+        let script = ScriptedSource::keys("ab")
+            .chain([None, paste(&big), None])
+            .chain(ScriptedSource::keys("n"))
+            .chain([None, paste(&big), None])
+            .chain(ScriptedSource::keys("y"))
+            .chain([None, paste("cd"), None, abort])
+            .chain(ScriptedSource::keys("y"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::from_secs(10));
+        let mut signals = ScriptedSignals::none();
+        let options = SessionOptions {
+            paste_confirm: 4096,
+            ..Default::default()
+        };
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut None,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Discarded);
+
+        // Declined once, then pasted, then the small paste typed
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Paste 4.9 KB starting \"Chapter One\"? (y/n)"));
+        assert_eq!(buffer.text(), format!("ab{}cd", big));
+        // Everything up to the end of the big paste is shown, while the small
+        // one is still to appear
+        assert_eq!(buffer.pending_reveals(Instant::now()), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Test recording a macro and playing it back. The session runs on tokio's
+    /// paused clock, so the playback is over by the time the script aborts
+    /// however slowly the test runs.
+    async fn test_macro_playback() {
+        let alt = |c| Some(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT)));
+        let enter = Some(Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty())));
+        let abort = Some(Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL)));
+        let script = [alt('r')]
+            .into_iter()
+            .chain(ScriptedSource::keys("a"))
+            .chain([enter])
+            .chain(ScriptedSource::keys("b"))
+            .chain([alt('r'), None, alt('p')]);
+        let source = ScriptedSource::new(script)
+            .wait(macros::PLAYBACK_DELAY * 10)
+            .then([abort])
+            .then(ScriptedSource::keys("y"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), source);
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        assert_eq!(session.await.unwrap(), SessionEnd::Discarded);
+
+        let text: Vec<String> = buffer
+            .lines
+            .iter()
+            .map(|line| line.characters.iter().map(|c| c.value).collect())
+            .collect();
+        assert_eq!(text, vec!["a", "ba", "b"]);
+    }
+
+    #[tokio::test]
+    /// Test that SIGTERM ends the session and is reported back
+    async fn test_terminate_signal() {
+        let source = ScriptedSource::new(ScriptedSource::keys("draft"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), source);
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut signals = ScriptedSignals::after(Duration::from_millis(50), Signal::Terminate);
+
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut None,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Signalled(Signal::Terminate));
+        assert!(buffer.is_modified());
+    }
+
+    #[tokio::test]
+    /// Test that SIGINT is handled as the close command
+    async fn test_interrupt_signal_closes() {
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new([]));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut signals = ScriptedSignals::after(Duration::from_millis(20), Signal::Interrupt);
+
+        // Nothing was typed, so closing doesn't ask to save
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut None,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Closed);
+    }
+
+    #[tokio::test]
+    /// Test that closing waits for the last characters to appear, unless the
+    /// close key is pressed again
+    async fn test_close_waits_for_reveals() {
+        let control = |c| Some(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)));
+        for (presses, shown) in [(1, true), (2, false)] {
+            let dir = ScratchDir::new(&format!("main-ink-{}", presses));
+            let path = dir.join("notes.txt");
+            let closes = vec![control('x'); presses];
+            let script: Vec<_> = ScriptedSource::keys("hi").chain([control('s')]).chain(closes).collect();
+            let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+            let mut renderer = Renderer::new(Vec::new());
+            let mut buffer = Buffer::new(Duration::from_millis(300));
+            buffer.set_file_path(&path.to_string_lossy()).unwrap();
+            let mut signals = ScriptedSignals::none();
+            let mut pomodoro = None;
+
+            let options = SessionOptions::default();
+            let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+            let session = run(
+                &mut input_handler,
+                &mut renderer,
+                &mut buffer,
+                &mut signals,
+                None,
+                &mut pomodoro,
+                &mut stats,
+                options,
+            );
+            let end = tokio::time::timeout(Duration::from_secs(2), session).await.unwrap().unwrap();
+            assert_eq!(end, SessionEnd::Closed);
+
+            let output = String::from_utf8_lossy(&renderer.output).to_string();
+            assert_eq!(output.contains("hi"), shown);
+        }
+    }
+
+    #[tokio::test]
+    /// Test a whole session through the real main loop: typing a sentence,
+    /// correcting a word by backspacing over it, saving and closing
+    async fn test_scripted_session() {
+        let dir = ScratchDir::new("main-session");
+        let path = dir.join("notes.txt");
+        let key = |code| Some(Event::Key(KeyEvent::new(code, KeyModifiers::empty())));
+        let control = |c| Some(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)));
+        let script = ScriptedSource::new(ScriptedSource::keys("The quick brwn"))
+            .wait(Duration::from_millis(30))
+            .then(vec![key(KeyCode::Backspace); 3])
+            .then(ScriptedSource::keys("xxx"))
+            .then([control('e'), None])
+            .then(ScriptedSource::keys("rown fox."))
+            .wait(Duration::from_millis(30))
+            .then([control('s'), None, control('x')]);
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), script);
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_file_path(&path.to_string_lossy()).unwrap();
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let end = tokio::time::timeout(Duration::from_secs(2), session).await.unwrap().unwrap();
+        assert_eq!(end, SessionEnd::Closed);
+
+        // The misspelling is struck through on screen and left out of the file
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "The quick brown fox.\n");
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("The quick b\x1b[9mr\x1b[0m\x1b[9mw\x1b[0m\x1b[9mn\x1b[0mrown fox."));
+    }
+
+    /// Writes after a pause, like a slow network mount
+    fn slow_write(path: &str, text: &str) -> io::Result<()> {
+        std::thread::sleep(Duration::from_millis(200));
+        std::fs::write(path, text)
+    }
+
+    #[tokio::test]
+    /// Test that typing carries on through a slow save, with the page unsaved
+    /// until it is written, and that saving on closing waits for the save
+    /// already being written before writing again
+    async fn test_slow_save() {
+        let dir = ScratchDir::new("main-slow");
+        let path = dir.join("notes.txt");
+        let control = |c| Some(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)));
+        let script = ScriptedSource::new(ScriptedSource::keys("hi").chain([control('s')]))
+            .wait(Duration::from_millis(30))
+            .then(ScriptedSource::keys("!"))
+            .then([control('x'), None])
+            .then(ScriptedSource::keys("y"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), script);
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_file_path(&path.to_string_lossy()).unwrap();
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+
+        let options = SessionOptions {
+            writer: Writer(slow_write),
+            ..SessionOptions::default()
+        };
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let start = Instant::now();
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let end = tokio::time::timeout(Duration::from_secs(2), session).await.unwrap().unwrap();
+        assert_eq!(end, SessionEnd::Closed);
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        // The page was drawn while the first save was being written
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Saving…"));
+        assert!(output.contains("Save changes before closing? (y/n)"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi!\n");
+        assert!(!buffer.is_modified());
+    }
+
+    #[tokio::test]
+    /// Test that a key skips an opened file typing itself out, and typing
+    /// carries on after it
+    async fn test_reveal_on_load_skip() {
+        let script = ScriptedSource::new(ScriptedSource::keys("xy"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), script);
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::from_reader("ab\ncd".as_bytes(), Duration::ZERO).unwrap();
+        buffer.move_to_end();
+        buffer.type_out(Instant::now(), Duration::from_secs(1), Duration::ZERO);
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+        let options = SessionOptions {
+            reveal_on_load: true,
+            ..SessionOptions::default()
+        };
+
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let _ = tokio::time::timeout(Duration::from_millis(300), session).await;
+
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Typing out the file (any key skips to the end)"));
+        assert_eq!(buffer.text(), "ab\ncdy");
+        assert_eq!(buffer.pending_reveals(Instant::now()), 0);
+    }
+
+    #[tokio::test]
+    /// Test that typing into the hard margin flashes once, however many keys
+    /// are refused, and again after a new line fills up
+    async fn test_hard_margin_flashes_once() {
+        let enter = Some(Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty())));
+        let script = ScriptedSource::keys("abcdef").chain([enter]).chain(ScriptedSource::keys("ghijk"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_hard_margin(Some(3));
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let _ = tokio::time::timeout(Duration::from_millis(500), session).await;
+
+        assert_eq!(buffer.text(), "abc\nghi");
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert_eq!(output.matches("\x1b[?5h").count(), 2);
+        assert_eq!(output.matches('\x07').count(), 2);
+    }
+
+    #[tokio::test]
+    /// Test that passing the paragraph limit rings once and colors the status
+    async fn test_paragraph_limit() {
+        let script = ScriptedSource::keys("abcdefghij")
+            .chain([None])
+            .chain(ScriptedSource::keys("k"))
+            .chain([None])
+            .chain(ScriptedSource::keys("l"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+
+        let options = SessionOptions {
+            paragraph_limit: Some(Limit::Characters(10)),
+            ..SessionOptions::default()
+        };
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let _ = tokio::time::timeout(Duration::from_millis(500), session).await;
+
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Paragraph: 10/10 characters"));
+        assert!(output.contains("Paragraph: 12/10 characters"));
+        assert!(output.contains("\x1b[48;5;1m"));
+        assert_eq!(output.matches('\x07').count(), 1);
+    }
+
+    #[tokio::test]
+    /// Test changing the reveal delay while typing, which stops at zero
+    async fn test_adjust_reveal_rate() {
+        let key = |code| Some(Event::Key(KeyEvent::new(code, KeyModifiers::empty())));
+        let script = [key(KeyCode::F(7)), None, key(KeyCode::F(8)), key(KeyCode::F(8)), None];
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let _ = tokio::time::timeout(Duration::from_millis(300), session).await;
+
+        assert_eq!(buffer.reveal_rate(), Duration::from_millis(100));
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Reveal delay: 0 ms"));
+        assert!(output.contains("Reveal delay: 100 ms"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Test that closing is refused until the sprint is over, leaving the
+    /// session to be ended by a signal. Tokio's clock only moves on once the
+    /// close has been handled, so the signal can't overtake it.
+    async fn test_sprint_refuses_close() {
+        let close = Event::Key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL));
+        let source = ScriptedSource::new([Some(close)]);
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), source);
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut signals = ScriptedSignals::after(Duration::from_millis(300), Signal::Terminate);
+        let mut pomodoro = None;
+        let options = SessionOptions {
+            sprint: Some(Duration::from_secs(60)),
+            ..SessionOptions::default()
+        };
+
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        assert_eq!(session.await.unwrap(), SessionEnd::Signalled(Signal::Terminate));
+
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("No stopping mid-sprint: 1:00 to go"));
+        assert!(output.contains("\x1b[?5h"));
+    }
+
+    #[tokio::test]
+    /// Test that typing is held off once a rest period starts
+    async fn test_pomodoro_rest_ignores_typing() {
+        let script = [None, None].into_iter().chain(ScriptedSource::keys("abc"));
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let mut signals = ScriptedSignals::none();
+
+        // The work period is already over by the first check
+        let started = Instant::now() - Duration::from_millis(10);
+        let mut pomodoro = Some(Pomodoro::start(Duration::from_millis(5), Duration::from_secs(60), started));
+        let options = SessionOptions::default();
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let _ = tokio::time::timeout(Duration::from_millis(300), session).await;
+
+        assert_eq!(buffer.text(), "");
+        assert_eq!(pomodoro.unwrap().cycles(), 1);
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Rest — 0:59 remaining"));
+        assert!(output.contains('\x07'));
+    }
+
+    #[tokio::test]
+    /// Test opening the second draft from the opening screen, with the settings
+    /// and the cursor on the line it was left on
+    async fn test_pick_draft() {
+        let dir = ScratchDir::new("main-pick");
+        std::fs::write(dir.join("a.txt"), "first").unwrap();
+        std::fs::write(dir.join("b.txt"), "second\nthird").unwrap();
+        let drafts = ["a.txt", "b.txt"]
+            .iter()
+            .map(|name| drafts::Draft {
+                path: dir.join(name),
+                modified: std::time::SystemTime::now(),
+            })
+            .collect();
+
+        let down = Event::Key(KeyEvent::new(KeyCode::Down, KeyModifiers::empty()));
+        let enter = Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty()));
+        let source = ScriptedSource::new([Some(down), Some(enter)]);
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), source);
+        let mut renderer = Renderer::new(Vec::new());
+        let mut signals = ScriptedSignals::none();
+
+        let picker = Picker::new(drafts);
+        let builder = BufferBuilder::new().caps(true);
+        let mut recent = RecentFiles::default();
+        recent.record(&dir.join("b.txt"), Position { line: 0, column: 3, scroll_top: 0 });
+        let picked = pick(&mut input_handler, &mut renderer, &mut signals, picker, &builder, &recent).await.unwrap();
+        let Picked::Open(buffer) = picked else {
+            panic!("expected a file to be opened");
+        };
+        assert_eq!(buffer.text(), "second\nthird");
+        assert!(buffer.caps());
+        assert_eq!(buffer.cursor_position(), (0, 3));
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Enter to open, n for a new page, Esc to quit"));
+    }
+
+    #[tokio::test]
+    /// Test answering the first-run questions, with a click when sound is picked
+    async fn test_first_run_setup() {
+        let key = |code| Some(Event::Key(KeyEvent::new(code, KeyModifiers::empty())));
+        let script = [key(KeyCode::Down), key(KeyCode::Enter), key(KeyCode::Up), key(KeyCode::Enter)];
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut signals = ScriptedSignals::none();
+
+        let mut clicks = 0;
+        let ended = setup(&mut input_handler, &mut renderer, &mut signals, || clicks += 1).await.unwrap();
+        let SetupEnd::Finished(choices) = ended else {
+            panic!("expected the questions to be answered");
+        };
+        assert!(choices.sound);
+        assert_eq!(choices.reveal_rate, Duration::from_millis(500));
+        assert_eq!(clicks, 1);
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Setup (2/2): Reveal speed"));
+
+        // Escape skips the rest
+        let script = [key(KeyCode::Enter), key(KeyCode::Esc)];
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let ended = setup(&mut input_handler, &mut renderer, &mut signals, || ()).await.unwrap();
+        assert!(matches!(ended, SetupEnd::Skipped));
+    }
+
+    #[tokio::test]
+    /// Test that declining to edit a locked file opens it read-only
+    async fn test_lock_conflict_read_only() {
+        let save = Event::Key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+        let script = ScriptedSource::keys("n")
+            .chain([None])
+            .chain(ScriptedSource::keys("abc"))
+            .chain([Some(save)]);
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_file_path("notes.txt").unwrap();
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+        let options = SessionOptions {
+            locked_by: Some(Owner {
+                pid: 4242,
+                host: "laptop".to_string(),
+            }),
+            ..SessionOptions::default()
+        };
+
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let _ = tokio::time::timeout(Duration::from_millis(300), session).await;
+
+        assert_eq!(buffer.text(), "");
+        assert!(!buffer.is_modified());
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("notes.txt is open elsewhere (PID 4242 on laptop). Edit anyway? (y/n)"));
+        assert!(output.contains("Read-only: the file is open in another typewriter"));
+    }
+
+    #[tokio::test]
+    /// Test that a failed save asks where else to save, without losing the text
+    async fn test_failed_save_asks_elsewhere() {
+        let dir = ScratchDir::new("main-unwritable");
+        let failing = dir.join("missing").join("notes.txt").to_string_lossy().into_owned();
+        let fallback = dir.join("notes.txt");
+
+        let save = Event::Key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+        let erase = Event::Key(KeyEvent::new(KeyCode::Backspace, KeyModifiers::empty()));
+        let enter = Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty()));
+        let fallback_path = fallback.to_string_lossy().into_owned();
+        // The failed write is reported back after a moment
+        let script = ScriptedSource::new(ScriptedSource::keys("hi").chain([Some(save)]))
+            .wait(Duration::from_millis(50))
+            .then(std::iter::repeat_n(Some(erase), failing.chars().count()))
+            .then(ScriptedSource::keys(&fallback_path))
+            // Typed that fast, the path reads as a paste, so Enter comes after
+            // long enough a pause for the paste to be over
+            .then([None, None, Some(enter)]);
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), script);
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_file_path(&failing).unwrap();
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+        let options = SessionOptions {
+            unwritable: Some(io::ErrorKind::NotFound),
+            ..SessionOptions::default()
+        };
+
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let _ = tokio::time::timeout(Duration::from_millis(500), session).await;
+
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Can't save to this file (entity not found); saving will ask for another place"));
+        assert!(output.contains("Couldn't save (entity not found). Save as: "));
+        assert_eq!(std::fs::read_to_string(&fallback).unwrap(), "hi\n");
+        assert_eq!(buffer.file_path, Some(fallback_path));
+        assert!(!buffer.is_modified());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    /// Test that closing right after a save waits for the save hook, so it
+    /// isn't cut off while it prints
+    async fn test_save_hook_on_close() {
+        let dir = ScratchDir::new("main-hook-close");
+        let path = dir.join("notes.txt");
+        let marker = path.with_extension("marker");
+        let ctrl = |c| Some(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)));
+        let script = ScriptedSource::keys("hi").chain([ctrl('s'), ctrl('c')]);
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_file_path(&path.to_string_lossy()).unwrap();
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+        let command = format!("sleep 0.2; echo printed; echo \"$TYPEWRITER_WORDS\" > {}", marker.display());
+        let options = SessionOptions {
+            on_save: Some(command),
+            ..SessionOptions::default()
+        };
+
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let end = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(end, SessionEnd::Closed);
+        assert_eq!(std::fs::read_to_string(&marker).unwrap(), "1\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    /// Test that a failing save hook is reported in the status row
+    async fn test_save_hook_failure() {
+        let dir = ScratchDir::new("main-hook");
+        let path = dir.join("notes.txt");
+        let save = Event::Key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+        let script = ScriptedSource::keys("hi").chain([Some(save)]);
+        let mut input_handler = InputHandler::with_source(Duration::from_millis(10), ScriptedSource::new(script));
+        let mut renderer = Renderer::new(Vec::new());
+        let mut buffer = Buffer::new(Duration::ZERO);
+        buffer.set_file_path(&path.to_string_lossy()).unwrap();
+        let mut signals = ScriptedSignals::none();
+        let mut pomodoro = None;
+        let options = SessionOptions {
+            on_save: Some("exit 4".to_string()),
+            ..SessionOptions::default()
+        };
+
+        let mut stats = SessionStats::start(&buffer, Instant::now(), DEFAULT_IDLE_THRESHOLD);
+        let session = run(
+            &mut input_handler,
+            &mut renderer,
+            &mut buffer,
+            &mut signals,
+            None,
+            &mut pomodoro,
+            &mut stats,
+            options,
+        );
+        let _ = tokio::time::timeout(Duration::from_millis(500), session).await;
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi\n");
+        let output = String::from_utf8_lossy(&renderer.output).to_string();
+        assert!(output.contains("Save hook failed (exit 4)"));
+    }
+}
@@ -0,0 +1,144 @@
+use std::io;
+
+/// A request from outside the editor to stop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    /// SIGINT, handled like the close command
+    Interrupt,
+    /// SIGTERM, or Ctrl+Break on Windows
+    Terminate,
+    /// SIGHUP, or the console window closing on Windows
+    Hangup,
+}
+
+impl Signal {
+    /// Returns the exit code shells expect after being stopped by this signal
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Signal::Interrupt => 128 + 2,
+            Signal::Hangup => 128 + 1,
+            Signal::Terminate => 128 + 15,
+        }
+    }
+}
+
+/// Where signals come from, so the event loop can be tested without real ones
+pub trait SignalSource {
+    /// Waits for the next signal
+    async fn recv(&mut self) -> Signal;
+}
+
+/// The process's own signals. Creating this replaces the default handlers, so
+/// a stray SIGINT no longer kills the editor with the terminal in raw mode.
+#[cfg(unix)]
+pub struct OsSignals {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+    hangup: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl OsSignals {
+    /// Starts listening for SIGINT, SIGTERM and SIGHUP
+    pub fn new() -> io::Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+            hangup: signal(SignalKind::hangup())?,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl SignalSource for OsSignals {
+    async fn recv(&mut self) -> Signal {
+        tokio::select! {
+            _ = self.interrupt.recv() => Signal::Interrupt,
+            _ = self.terminate.recv() => Signal::Terminate,
+            _ = self.hangup.recv() => Signal::Hangup,
+        }
+    }
+}
+
+/// The console's control events
+#[cfg(windows)]
+pub struct OsSignals {
+    interrupt: tokio::signal::windows::CtrlC,
+    terminate: tokio::signal::windows::CtrlBreak,
+    hangup: tokio::signal::windows::CtrlClose,
+}
+
+#[cfg(windows)]
+impl OsSignals {
+    /// Starts listening for Ctrl+C, Ctrl+Break and the console closing
+    pub fn new() -> io::Result<Self> {
+        use tokio::signal::windows::{ctrl_break, ctrl_c, ctrl_close};
+        Ok(Self {
+            interrupt: ctrl_c()?,
+            terminate: ctrl_break()?,
+            hangup: ctrl_close()?,
+        })
+    }
+}
+
+#[cfg(windows)]
+impl SignalSource for OsSignals {
+    async fn recv(&mut self) -> Signal {
+        tokio::select! {
+            _ = self.interrupt.recv() => Signal::Interrupt,
+            _ = self.terminate.recv() => Signal::Terminate,
+            _ = self.hangup.recv() => Signal::Hangup,
+        }
+    }
+}
+
+/// Sends one signal after a delay, then nothing, for tests
+#[cfg(test)]
+pub struct ScriptedSignals {
+    /// The signal still to send and when it is due. A deadline rather than a
+    /// delay, since the event loop starts a new wait on every pass.
+    next: Option<(tokio::time::Instant, Signal)>,
+}
+
+#[cfg(test)]
+impl ScriptedSignals {
+    /// Creates a source that never sends anything
+    pub fn none() -> Self {
+        Self { next: None }
+    }
+
+    /// Creates a source that sends one signal after a delay
+    pub fn after(delay: std::time::Duration, signal: Signal) -> Self {
+        Self {
+            next: Some((tokio::time::Instant::now() + delay, signal)),
+        }
+    }
+}
+
+#[cfg(test)]
+impl SignalSource for ScriptedSignals {
+    async fn recv(&mut self) -> Signal {
+        match self.next {
+            Some((deadline, signal)) => {
+                tokio::time::sleep_until(deadline).await;
+                self.next = None;
+                signal
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the conventional exit codes
+    fn test_exit_codes() {
+        assert_eq!(Signal::Interrupt.exit_code(), 130);
+        assert_eq!(Signal::Hangup.exit_code(), 129);
+        assert_eq!(Signal::Terminate.exit_code(), 143);
+    }
+}
@@ -0,0 +1,226 @@
+use chrono::NaiveDateTime;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many snapshots of a file are kept when the config doesn't say
+pub const DEFAULT_KEEP: usize = 20;
+
+/// How a snapshot's time is written in its name
+const NAME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// Length of a name without a number after it
+const NAME_LEN: usize = "20240301-142530".len();
+
+/// A copy of a file's text kept in `<file>.rev` beside it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The time it was taken, such as `20240301-142530`, with `-2`, `-3` and
+    /// so on after it for further snapshots in the same second
+    pub name: String,
+    /// Where the snapshot is
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    /// Orders snapshots by when they were taken: by time, then by the number
+    /// after it, the first of a second having none
+    fn order(&self) -> (&str, u32) {
+        let (time, number) = self.name.split_at(NAME_LEN);
+        (time, number.strip_prefix('-').map_or(1, |number| number.parse().unwrap_or(u32::MAX)))
+    }
+}
+
+/// Returns the directory a file's snapshots go in: `<file>.rev` next to it
+pub fn dir_for(file: &Path) -> PathBuf {
+    let mut dir = OsString::from(file);
+    dir.push(".rev");
+    PathBuf::from(dir)
+}
+
+/// Returns whether a file name is one a snapshot would have, returning the
+/// snapshot's name if so
+fn snapshot_name(file_name: &str) -> Option<&str> {
+    let name = file_name.strip_suffix(".txt")?;
+    let (time, extra) = name.split_at_checked(NAME_LEN)?;
+    NaiveDateTime::parse_from_str(time, NAME_FORMAT).ok()?;
+    let numbered = extra.strip_prefix('-').is_some_and(|n| n.parse::<u32>().is_ok_and(|n| n > 1));
+    (extra.is_empty() || numbered).then_some(name)
+}
+
+/// Lists the snapshots of a file, oldest first. A file without any has no
+/// directory for them, which is no error. Other files in the directory are
+/// left out.
+pub fn list(file: &Path) -> io::Result<Vec<Snapshot>> {
+    let dir = dir_for(file);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if let Some(name) = file_name.to_str().and_then(snapshot_name) {
+            snapshots.push(Snapshot { name: name.to_string(), path: entry.path() });
+        }
+    }
+    snapshots.sort_by(|a, b| a.order().cmp(&b.order()));
+    Ok(snapshots)
+}
+
+/// Finds a snapshot of a file by its name, with or without `.txt`
+pub fn find(file: &Path, name: &str) -> io::Result<Snapshot> {
+    let name = name.strip_suffix(".txt").unwrap_or(name);
+    list(file)?.into_iter().find(|snapshot| snapshot.name == name).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no snapshot {} of {}", name, file.display()))
+    })
+}
+
+/// Writes a snapshot of a file's text taken at `now`, creating the directory
+/// for them if need be, then removes the oldest until `keep` are left.
+/// Returns the snapshot and how many are kept, the new one being the last.
+pub fn write(file: &Path, text: &str, now: NaiveDateTime, keep: usize) -> io::Result<(Snapshot, usize)> {
+    let dir = dir_for(file);
+    fs::create_dir_all(&dir)?;
+
+    // A second snapshot within a second gets a number rather than replacing the first
+    let time = now.format(NAME_FORMAT).to_string();
+    let mut name = time.clone();
+    let mut number = 1;
+    let path = loop {
+        let path = dir.join(format!("{}.txt", name));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => break path,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                number += 1;
+                name = format!("{}-{}", time, number);
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    fs::write(&path, text)?;
+    let snapshot = Snapshot { name, path };
+
+    let mut snapshots = list(file)?;
+    let excess = snapshots.len().saturating_sub(keep.max(1));
+    for old in snapshots.drain(..excess) {
+        fs::remove_file(&old.path)?;
+    }
+    Ok((snapshot, snapshots.len()))
+}
+
+/// Formats the snapshots of a file for `--list-snapshots`, oldest first, one
+/// a line with its word count
+pub fn format_list(snapshots: &[Snapshot]) -> io::Result<String> {
+    let mut listed = String::new();
+    for snapshot in snapshots {
+        let words = fs::read_to_string(&snapshot.path)?.split_whitespace().count();
+        listed.push_str(&format!("{}  {} word{}\n", snapshot.name, words, if words == 1 { "" } else { "s" }));
+    }
+    Ok(listed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchDir;
+
+
+    /// Returns a moment on the first of March 2024
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2024-03-01 {}", time), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    /// Returns the names of a file's snapshots, oldest first
+    fn names(file: &Path) -> Vec<String> {
+        list(file).unwrap().into_iter().map(|snapshot| snapshot.name).collect()
+    }
+
+    #[test]
+    /// Test that snapshots go in a directory beside the file, made when the
+    /// first is taken
+    fn test_write() {
+        let dir = ScratchDir::new("snapshots-write");
+        let file = dir.join("draft.txt");
+        assert_eq!(list(&file).unwrap(), []);
+
+        let (snapshot, kept) = write(&file, "First draft\n", at("14:25:30"), 5).unwrap();
+        assert_eq!(snapshot.name, "20240301-142530");
+        assert_eq!(snapshot.path, file.with_file_name("draft.txt.rev").join("20240301-142530.txt"));
+        assert_eq!(fs::read_to_string(&snapshot.path).unwrap(), "First draft\n");
+        assert_eq!(kept, 1);
+        assert!(!file.exists());
+    }
+
+    #[test]
+    /// Test that snapshots taken in the same second are all kept, in order
+    fn test_same_second() {
+        let dir = ScratchDir::new("snapshots-same-second");
+        let file = dir.join("draft.txt");
+        for text in ["one", "two", "three"] {
+            write(&file, text, at("09:00:00"), 20).unwrap();
+        }
+        // The tenth sorts after the ninth, not after the first
+        for text in 4..=10 {
+            write(&file, &text.to_string(), at("09:00:00"), 20).unwrap();
+        }
+        write(&file, "later", at("09:00:01"), 20).unwrap();
+
+        let names = names(&file);
+        assert_eq!(names[..3], ["20240301-090000", "20240301-090000-2", "20240301-090000-3"]);
+        assert_eq!(names[9..], ["20240301-090000-10", "20240301-090001"]);
+        assert_eq!(fs::read_to_string(find(&file, "20240301-090000-2").unwrap().path).unwrap(), "two");
+    }
+
+    #[test]
+    /// Test that only the newest are kept, and that other files in the
+    /// directory are left alone
+    fn test_pruning() {
+        let dir = ScratchDir::new("snapshots-pruning");
+        let file = dir.join("draft.txt");
+        fs::create_dir_all(dir_for(&file)).unwrap();
+        fs::write(dir_for(&file).join("notes.txt"), "mine").unwrap();
+
+        for (minute, text) in ["10:00:00", "10:01:00", "10:02:00", "10:03:00"].iter().zip(["a", "b", "c", "d"]) {
+            let (_, kept) = write(&file, text, at(minute), 3).unwrap();
+            assert!(kept <= 3);
+        }
+        assert_eq!(names(&file), ["20240301-100100", "20240301-100200", "20240301-100300"]);
+        assert!(dir_for(&file).join("notes.txt").exists());
+
+        // Lowering the limit prunes down to it at the next snapshot
+        let (snapshot, kept) = write(&file, "e", at("10:04:00"), 1).unwrap();
+        assert_eq!(kept, 1);
+        assert_eq!(list(&file).unwrap(), [snapshot]);
+    }
+
+    #[test]
+    /// Test finding a snapshot by name and listing them with word counts
+    fn test_find_and_format() {
+        let dir = ScratchDir::new("snapshots-find");
+        let file = dir.join("draft.txt");
+        write(&file, "one\n", at("08:00:00"), 20).unwrap();
+        write(&file, "one more time\n", at("08:30:00"), 20).unwrap();
+
+        assert_eq!(find(&file, "20240301-083000.txt").unwrap().name, "20240301-083000");
+        let error = find(&file, "20240301-090000").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().starts_with("no snapshot 20240301-090000 of "));
+
+        let listed = format_list(&list(&file).unwrap()).unwrap();
+        assert_eq!(listed, "20240301-080000  1 word\n20240301-083000  3 words\n");
+    }
+
+    #[test]
+    /// Test which file names are taken for snapshots
+    fn test_snapshot_name() {
+        assert_eq!(snapshot_name("20240301-142530.txt"), Some("20240301-142530"));
+        assert_eq!(snapshot_name("20240301-142530-12.txt"), Some("20240301-142530-12"));
+        assert_eq!(snapshot_name("20240301-142530-1.txt"), None);
+        assert_eq!(snapshot_name("20240301-142530.md"), None);
+        assert_eq!(snapshot_name("20241301-142530.txt"), None);
+        assert_eq!(snapshot_name("notes.txt"), None);
+    }
+}
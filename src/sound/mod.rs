@@ -1,22 +1,209 @@
-use rodio::{Decoder, OutputStream, Sink};
+use rodio::buffer::SamplesBuffer;
+use rodio::source::{SineWave, Source};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
 use std::fs::File;
 use std::io::BufReader;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::time::{Duration, Instant};
-use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 use std::thread;
 use std::path::PathBuf;
 use std::env;
+use tokio::sync::oneshot;
 
 /// Different types of sounds that can be played
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SoundType {
     /// Regular keypress sound (click1-6.wav)
     KeyPress(char),
     /// Enter key sound (classic-return.wav)
     Return,
+    /// A finished word in word reveal mode: one keypress sound, a little louder
+    Word(char),
+    /// The bell, a short tone made rather than played from a file
+    Bell,
+    /// A quiet click for menus, prompts and toggles, at a volume from 0 to 1
+    /// (ui-tick.wav, or a click made on the spot if there is no such file)
+    UiTick(f32),
 }
 
+/// How the bell sounds, for the margin bell, the end of a sprint and other
+/// things that call for attention
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BellStyle {
+    /// A tone from the sound system, or the terminal's bell while sound is off
+    #[default]
+    Audio,
+    /// The terminal's bell, which sounds or flashes as the terminal is set up to
+    Terminal,
+    /// A flash of the screen
+    Visual,
+    /// Nothing, and no flashes either
+    None,
+}
+
+/// Parses a bell style as given on the command line or in the config
+pub fn parse_bell(value: &str) -> Result<BellStyle, String> {
+    match value {
+        "audio" => Ok(BellStyle::Audio),
+        "terminal" => Ok(BellStyle::Terminal),
+        "visual" => Ok(BellStyle::Visual),
+        "none" => Ok(BellStyle::None),
+        _ => Err(format!("must be `audio`, `terminal`, `visual` or `none`, not `{}`", value)),
+    }
+}
+
+/// The keyboard being typed on, which decides which click each key makes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Dvorak,
+}
+
+/// Parses a keyboard layout as given on the command line or in the config
+pub fn parse_layout(value: &str) -> Result<KeyboardLayout, String> {
+    match value {
+        "qwerty" => Ok(KeyboardLayout::Qwerty),
+        "azerty" => Ok(KeyboardLayout::Azerty),
+        "dvorak" => Ok(KeyboardLayout::Dvorak),
+        _ => Err(format!("must be `qwerty`, `azerty` or `dvorak`, not `{}`", value)),
+    }
+}
+
+/// The letter rows of each layout, top to bottom, split into the keys under
+/// the left hand and those under the right
+const QWERTY_ROWS: [[&str; 2]; 3] = [["qwert", "yuiop"], ["asdfg", "hjkl;"], ["zxcvb", "nm,./"]];
+const AZERTY_ROWS: [[&str; 2]; 3] = [["azert", "yuiop"], ["qsdfg", "hjklm"], ["wxcvb", "n,;:!"]];
+const DVORAK_ROWS: [[&str; 2]; 3] = [["',.py", "fgcrl"], ["aoeui", "dhtns"], [";qjkx", "bmwvz"]];
+
+/// The click each half of each letter row makes, matching the rows above.
+/// The bottom row is one click for both hands, leaving the last for keys off
+/// the letter rows.
+const ROW_CLICKS: [[usize; 2]; 3] = [[1, 2], [3, 4], [5, 5]];
+/// The click for the space bar, digits and anything else off the letter rows
+const OTHER_CLICK: usize = 6;
+
+impl KeyboardLayout {
+    /// Returns which click, `click1.wav` to `click6.wav`, a key makes, so
+    /// that keys near each other on the keyboard sound alike
+    pub fn click(self, c: char) -> usize {
+        let rows = match self {
+            Self::Qwerty => QWERTY_ROWS,
+            Self::Azerty => AZERTY_ROWS,
+            Self::Dvorak => DVORAK_ROWS,
+        };
+        let key = c.to_lowercase().next().unwrap_or(c);
+        rows.iter()
+            .zip(ROW_CLICKS)
+            .flat_map(|(halves, clicks)| halves.iter().zip(clicks))
+            .find_map(|(keys, click)| keys.contains(key).then_some(click))
+            .unwrap_or(OTHER_CLICK)
+    }
+}
+
+/// Checks a volume given as a percentage, from 0 (silent) to 100
+pub fn check_volume(percent: u64) -> Result<u8, String> {
+    match percent {
+        0..=100 => Ok(percent as u8),
+        _ => Err("must be from 0 to 100".to_string()),
+    }
+}
+
+/// The files played from the sound directory
+pub const SAMPLES: [&str; 7] = [
+    "click1.wav",
+    "click2.wav",
+    "click3.wav",
+    "click4.wav",
+    "click5.wav",
+    "click6.wav",
+    "classic-return.wav",
+];
+
+/// A file played if the sound directory has it, and made up for if not
+pub const UI_TICK_SAMPLE: &str = "ui-tick.wav";
+
+/// How far ahead of the visual reveal a sound starts playing
+const SOUND_LEAD: Duration = Duration::from_millis(100);
+
+/// Lowest playback speed for a keypress (pitch -5%)
+const MIN_SPEED: f32 = 0.95;
+/// Width of the random playback speed range
+const SPEED_SPREAD: f32 = 0.1;
+/// Lowest volume for a keypress (-10%)
+const MIN_VOLUME: f32 = 0.9;
+/// Width of the random volume range
+const VOLUME_SPREAD: f32 = 0.2;
+/// How much louder the click for a whole word is than a single keypress
+const WORD_VOLUME: f32 = 1.3;
+/// Pitch of the bell tone, in hertz
+const BELL_PITCH: f32 = 1320.0;
+/// How long the bell tone lasts
+const BELL_LENGTH: Duration = Duration::from_millis(150);
+/// Volume of the bell tone
+const BELL_VOLUME: f32 = 0.15;
+/// Pitch of the made-up UI tick, in hertz
+const UI_TICK_PITCH: f32 = 1800.0;
+/// How long the made-up UI tick lasts
+const UI_TICK_LENGTH: Duration = Duration::from_millis(15);
+/// How quickly the made-up UI tick dies away: it falls to about a third
+/// over this long, so to next to nothing by its end
+const UI_TICK_DECAY: Duration = Duration::from_millis(3);
+/// Loudest the made-up UI tick gets, before its volume is applied
+const UI_TICK_PEAK: f32 = 0.5;
+/// Sample rate the UI tick is made at
+const UI_TICK_RATE: u32 = 44_100;
+
+/// Pitch and volume variation applied to a single keypress sound
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Variation {
+    /// Playback speed, which also shifts the pitch
+    pub speed: f32,
+    /// Playback volume
+    pub volume: f32,
+}
+
+/// Produces the variation for each sound request from a session seed.
+/// Every request advances the counter, so replaying the same requests with
+/// the same seed yields the same variations.
+#[derive(Debug)]
+struct VariationSource {
+    seed: u64,
+    counter: u64,
+}
+
+impl VariationSource {
+    fn new(seed: u64) -> Self {
+        Self { seed, counter: 0 }
+    }
+
+    /// Returns the variation for the next request
+    fn next_variation(&mut self) -> Variation {
+        let mut rng = SmallRng::seed_from_u64(self.seed ^ Self::scramble(self.counter));
+        self.counter += 1;
+        Variation {
+            speed: MIN_SPEED + rng.gen::<f32>() * SPEED_SPREAD,
+            volume: MIN_VOLUME + rng.gen::<f32>() * VOLUME_SPREAD,
+        }
+    }
+
+    /// Spreads a counter across all 64 bits (the SplitMix64 step), so that
+    /// neighbouring seeds don't replay each other's variations a step apart
+    fn scramble(counter: u64) -> u64 {
+        let mut z = counter.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Most sounds kept while the audio output is opening. Older ones are
+/// dropped, so a slow start doesn't end in a long volley of clicks.
+const MAX_WAITING: usize = 50;
+
 /// A sound request with timing information
 #[derive(Debug)]
 struct SoundRequest {
@@ -24,48 +211,170 @@ struct SoundRequest {
     play_at: Instant,
 }
 
-/// Manages sound playback for the typewriter
-pub struct SoundSystem {
-    sender: Sender<SoundRequest>,
-    #[allow(dead_code)]
-    stream: OutputStream, // Keep the stream alive
+/// Where sounds are played. Opening it can take a second or more, as when
+/// PulseAudio has to wake up, so it is opened on the audio thread.
+trait Output: Send + 'static {
+    /// What plays sounds once the output is open, kept on the audio thread
+    type Player: Play;
+
+    /// Opens the output, or says why it can't be
+    fn open(self) -> Result<Self::Player, String>;
 }
 
-impl SoundSystem {
-    /// Creates a new sound system and starts the audio thread
-    pub fn new() -> Option<Self> {
-        // Try to initialize audio output
-        match OutputStream::try_default() {
-            Ok((stream, stream_handle)) => {
-                let (sender, receiver) = mpsc::channel();
-
-                // Find sound directory
-                let sound_dir = Self::find_sound_directory()?;
-
-                // Check if at least one sound file exists
-                let test_file = sound_dir.join("click1.wav");
-                if !test_file.exists() {
-                    eprintln!("Sound files not found in: {}", sound_dir.display());
-                    return None;
-                }
+/// Plays sounds on an open output
+trait Play {
+    /// Plays a sound now, with the given pitch and volume variation
+    fn play(&mut self, sound_type: &SoundType, variation: Variation);
+}
 
-                // Start audio thread
-                let sound_dir_clone = sound_dir.clone();
-                thread::spawn(move || {
-                    Self::audio_thread(receiver, stream_handle, sound_dir_clone);
-                });
+/// The system's default audio output, playing clicks from the sound directory
+struct Speakers {
+    sound_dir: PathBuf,
+    layout: KeyboardLayout,
+}
 
-                Some(Self { sender, stream })
-            }
+/// The default audio output once open
+struct SpeakersPlayer {
+    /// Keeps the output open
+    _stream: OutputStream,
+    stream_handle: OutputStreamHandle,
+    sound_dir: PathBuf,
+    layout: KeyboardLayout,
+}
+
+impl Output for Speakers {
+    type Player = SpeakersPlayer;
+
+    fn open(self) -> Result<SpeakersPlayer, String> {
+        let (stream, stream_handle) = OutputStream::try_default().map_err(|e| e.to_string())?;
+        Ok(SpeakersPlayer {
+            _stream: stream,
+            stream_handle,
+            sound_dir: self.sound_dir,
+            layout: self.layout,
+        })
+    }
+}
+
+impl Play for SpeakersPlayer {
+    fn play(&mut self, sound_type: &SoundType, variation: Variation) {
+        // Create a new sink for this sound
+        let sink = match Sink::try_new(&self.stream_handle) {
+            Ok(sink) => sink,
             Err(e) => {
-                eprintln!("Failed to initialize audio: {}", e);
-                None
+                log::warn!("dropped sound {:?}: failed to create audio sink: {}", sound_type, e);
+                return;
+            }
+        };
+        match sound_type {
+            SoundType::KeyPress(c) | SoundType::Word(c) => {
+                // Select sound based on where the key is
+                let sound_idx = self.layout.click(*c);
+
+                // Load and play the sound
+                let sound_path = self.sound_dir.join(format!("click{}.wav", sound_idx));
+                if let Some(sound) = SoundSystem::load_sound(sound_path) {
+                    // Apply random pitch/volume
+                    sink.set_speed(variation.speed);
+                    match sound_type {
+                        SoundType::Word(_) => sink.set_volume(variation.volume * WORD_VOLUME),
+                        _ => sink.set_volume(variation.volume),
+                    }
+                    sink.append(sound);
+                    sink.detach();
+                    log::debug!("played click{} with {:?}", sound_idx, variation);
+                } else {
+                    log::debug!("dropped sound {:?}", sound_type);
+                }
             }
+            SoundType::Bell => {
+                sink.append(SineWave::new(BELL_PITCH).take_duration(BELL_LENGTH).amplify(BELL_VOLUME));
+                sink.detach();
+                log::debug!("played the bell");
+            }
+            SoundType::UiTick(volume) => {
+                sink.set_volume(*volume);
+                // The sample is optional, so a missing one isn't worth a warning
+                let sample = self.sound_dir.join(UI_TICK_SAMPLE);
+                match sample.exists().then(|| SoundSystem::load_sound(sample)).flatten() {
+                    Some(sound) => sink.append(sound),
+                    None => sink.append(SamplesBuffer::new(1, UI_TICK_RATE, SoundSystem::synthesize_tick(UI_TICK_RATE))),
+                }
+                sink.detach();
+                log::debug!("played a UI tick");
+            }
+            SoundType::Return => {
+                // Load and play return sound at 20% volume
+                let return_path = self.sound_dir.join("classic-return.wav");
+                if let Some(sound) = SoundSystem::load_sound(return_path) {
+                    sink.set_volume(0.2);
+                    sink.append(sound);
+                    sink.detach();
+                    log::debug!("played classic-return");
+                } else {
+                    log::debug!("dropped sound {:?}", sound_type);
+                }
+            }
+        }
+    }
+}
+
+/// Manages sound playback for the typewriter. The audio output is opened in
+/// the background; sounds scheduled meanwhile wait for it, up to
+/// `MAX_WAITING` of them.
+pub struct SoundSystem {
+    sender: Sender<SoundRequest>,
+    /// Extra output latency to compensate for (e.g. Bluetooth headphones)
+    latency: Duration,
+    /// Hears whether the output opened, until `ready` has reported it
+    opened: Option<oneshot::Receiver<Result<(), String>>>,
+}
+
+impl SoundSystem {
+    /// Creates a new sound system and starts the audio thread, which opens
+    /// the audio output. None if there are no sound files to play.
+    /// `latency` shifts every sound earlier to make up for slow audio output,
+    /// `seed` drives the pitch/volume variation so sessions can be reproduced,
+    /// and `layout` picks the click for each key.
+    pub fn new(latency: Duration, seed: u64, layout: KeyboardLayout) -> Option<Self> {
+        // Find sound directory
+        let Some(sound_dir) = Self::find_sound_directory() else {
+            log::warn!("no sound directory found");
+            return None;
+        };
+
+        // Check if at least one sound file exists
+        let test_file = sound_dir.join("click1.wav");
+        if !test_file.exists() {
+            log::warn!("sound files not found in {}", sound_dir.display());
+            return None;
         }
+
+        log::info!("playing sounds from {} with seed {}", sound_dir.display(), seed);
+        Some(Self::start(Speakers { sound_dir, layout }, latency, seed))
+    }
+
+    /// Starts the audio thread, which opens `output` and then plays what is sent to it
+    fn start<O: Output>(output: O, latency: Duration, seed: u64) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let (opened_sender, opened) = oneshot::channel();
+        thread::spawn(move || Self::audio_thread(receiver, output, opened_sender, seed));
+        Self { sender, latency, opened: Some(opened) }
+    }
+
+    /// Waits for the audio output to open, returning why it couldn't if it
+    /// didn't. Once that is known, waits forever. Safe to cancel.
+    pub async fn ready(&mut self) -> Result<(), String> {
+        let Some(opened) = &mut self.opened else {
+            return std::future::pending().await;
+        };
+        let result = opened.await.unwrap_or_else(|_| Err("the audio thread stopped".to_string()));
+        self.opened = None;
+        result
     }
 
     /// Find the directory containing sound files by checking multiple locations
-    fn find_sound_directory() -> Option<PathBuf> {
+    pub fn find_sound_directory() -> Option<PathBuf> {
         // Check local directory first (when running from source)
         let cargo_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("sounds");
         if cargo_dir.exists() {
@@ -95,14 +404,23 @@ impl SoundSystem {
 
     /// Schedules a sound to be played
     pub fn schedule_sound(&self, sound_type: SoundType, reveal_time: Instant) {
-        // Schedule sound to play 100ms before reveal
-        let play_at = reveal_time - Duration::from_millis(100);
+        let play_at = Self::play_time(reveal_time, self.latency, Instant::now());
+        log::debug!("sound {:?} scheduled {:?} ahead", sound_type, play_at.saturating_duration_since(Instant::now()));
         let request = SoundRequest { sound_type, play_at };
         if let Err(e) = self.sender.send(request) {
-            eprintln!("Failed to schedule sound: {}", e);
+            log::warn!("dropped sound {:?}: {}", e.0.sound_type, e);
         }
     }
 
+    /// Computes when a sound should start so that it is heard `SOUND_LEAD` before
+    /// the reveal, shifted earlier by the output latency. Never returns a time before
+    /// `now`, so sounds that are already late play immediately.
+    fn play_time(reveal_time: Instant, latency: Duration, now: Instant) -> Instant {
+        reveal_time
+            .checked_sub(SOUND_LEAD + latency)
+            .map_or(now, |play_at| play_at.max(now))
+    }
+
     /// Loads and decodes a sound file
     fn load_sound(path: PathBuf) -> Option<Decoder<BufReader<File>>> {
         match File::open(&path) {
@@ -111,73 +429,65 @@ impl SoundSystem {
                 match Decoder::new(reader) {
                     Ok(decoder) => Some(decoder),
                     Err(e) => {
-                        eprintln!("Failed to decode sound file {}: {}", path.display(), e);
+                        log::warn!("failed to decode sound file {}: {}", path.display(), e);
                         None
                     }
                 }
             }
             Err(e) => {
-                eprintln!("Failed to open sound file {}: {}", path.display(), e);
+                log::warn!("failed to open sound file {}: {}", path.display(), e);
                 None
             }
         }
     }
 
-    /// Audio processing thread
-    fn audio_thread(
+    /// Makes the UI tick: a short sine wave that starts at its loudest and
+    /// dies away, so it clicks without ending in one
+    fn synthesize_tick(sample_rate: u32) -> Vec<f32> {
+        let count = (sample_rate as u128 * UI_TICK_LENGTH.as_micros() / 1_000_000) as usize;
+        let decay = UI_TICK_DECAY.as_secs_f32();
+        (0..count)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                UI_TICK_PEAK * (std::f32::consts::TAU * UI_TICK_PITCH * t).sin() * (-t / decay).exp()
+            })
+            .collect()
+    }
+
+    /// Audio processing thread: opens the output, then plays the sounds
+    /// that waited for it in order, then each sound as it comes
+    fn audio_thread<O: Output>(
         receiver: Receiver<SoundRequest>,
-        stream_handle: rodio::OutputStreamHandle,
-        sound_dir: PathBuf,
+        output: O,
+        opened: oneshot::Sender<Result<(), String>>,
+        seed: u64,
     ) {
-        let mut rng = rand::thread_rng();
+        let mut player = match output.open() {
+            Ok(player) => player,
+            Err(e) => {
+                log::warn!("failed to initialize audio: {}", e);
+                let _ = opened.send(Err(e));
+                return;
+            }
+        };
+        let _ = opened.send(Ok(()));
+        let waiting: Vec<SoundRequest> = receiver.try_iter().collect();
+        let dropped = waiting.len().saturating_sub(MAX_WAITING);
+        if dropped > 0 {
+            log::debug!("dropped {} sounds scheduled while the audio output opened", dropped);
+        }
+        let mut variations = VariationSource::new(seed);
 
-        while let Ok(request) = receiver.recv() {
+        for request in waiting.into_iter().skip(dropped).chain(receiver.iter()) {
             // Wait until it's time to play the sound
             let now = Instant::now();
             if request.play_at > now {
                 thread::sleep(request.play_at - now);
+            } else if now > request.play_at + SOUND_LEAD {
+                log::debug!("sound {:?} is {:?} late", request.sound_type, now - request.play_at);
             }
 
-            // Create a new sink for this sound
-            match Sink::try_new(&stream_handle) {
-                Ok(sink) => {
-                    match request.sound_type {
-                        SoundType::KeyPress(c) => {
-                            // Select sound based on character
-                            let sound_idx = match c {
-                                'a'..='f' => 1,
-                                'g'..='l' => 2,
-                                'm'..='r' => 3,
-                                's'..='x' => 4,
-                                'y'..='z' => 5,
-                                _ => 6,
-                            };
-
-                            // Load and play the sound
-                            let sound_path = sound_dir.join(format!("click{}.wav", sound_idx));
-                            if let Some(sound) = Self::load_sound(sound_path) {
-                                // Apply random pitch/volume
-                                let speed = 0.95 + rng.gen::<f32>() * 0.1; // Random pitch ±5%
-                                let volume = 0.9 + rng.gen::<f32>() * 0.2; // Random volume ±10%
-                                sink.set_speed(speed);
-                                sink.set_volume(volume);
-                                sink.append(sound);
-                                sink.detach();
-                            }
-                        }
-                        SoundType::Return => {
-                            // Load and play return sound at 20% volume
-                            let return_path = sound_dir.join("classic-return.wav");
-                            if let Some(sound) = Self::load_sound(return_path) {
-                                sink.set_volume(0.2);
-                                sink.append(sound);
-                                sink.detach();
-                            }
-                        }
-                    }
-                }
-                Err(e) => eprintln!("Failed to create audio sink: {}", e),
-            }
+            player.play(&request.sound_type, variations.next_variation());
         }
     }
 }
@@ -186,4 +496,235 @@ impl Drop for SoundSystem {
     fn drop(&mut self) {
         // Channel will be closed when SoundSystem is dropped
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Sounds played, in order
+    type Played = Arc<Mutex<Vec<SoundType>>>;
+
+    /// An output that takes a while to open, or fails to, and keeps what it plays
+    struct SlowOutput {
+        delay: Duration,
+        fails: bool,
+        played: Played,
+    }
+
+    /// Keeps what is played
+    struct Recorder(Played);
+
+    impl Output for SlowOutput {
+        type Player = Recorder;
+
+        fn open(self) -> Result<Recorder, String> {
+            thread::sleep(self.delay);
+            match self.fails {
+                true => Err("no audio device".to_string()),
+                false => Ok(Recorder(self.played)),
+            }
+        }
+    }
+
+    impl Play for Recorder {
+        fn play(&mut self, sound_type: &SoundType, _variation: Variation) {
+            self.0.lock().unwrap().push(sound_type.clone());
+        }
+    }
+
+    /// Starts a sound system on an output that opens after `delay`
+    fn slow(delay: Duration, fails: bool) -> (SoundSystem, Played) {
+        let played = Played::default();
+        let output = SlowOutput { delay, fails, played: played.clone() };
+        (SoundSystem::start(output, Duration::ZERO, 42), played)
+    }
+
+    /// Returns what was played once `count` sounds have been, or after a second
+    async fn played(played: &Played, count: usize) -> Vec<SoundType> {
+        for _ in 0..100 {
+            if played.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        played.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    /// Test that starting doesn't wait for a slow output, and that sounds
+    /// scheduled meanwhile are played in order once it opens
+    async fn test_slow_output() {
+        let start = Instant::now();
+        let (mut sound_system, played_sounds) = slow(Duration::from_millis(200), false);
+        assert!(start.elapsed() < Duration::from_millis(100));
+        let sounds = [SoundType::KeyPress('a'), SoundType::Return, SoundType::KeyPress('b')];
+        for sound in sounds.clone() {
+            sound_system.schedule_sound(sound, Instant::now());
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(played_sounds.lock().unwrap().is_empty());
+
+        assert_eq!(sound_system.ready().await, Ok(()));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(played(&played_sounds, 3).await, sounds);
+        sound_system.schedule_sound(SoundType::Bell, Instant::now());
+        assert_eq!(played(&played_sounds, 4).await.last(), Some(&SoundType::Bell));
+
+        // Once it has said, it has nothing more to say
+        let again = tokio::time::timeout(Duration::from_millis(50), sound_system.ready()).await;
+        assert!(again.is_err());
+    }
+
+    #[tokio::test]
+    /// Test that only the latest sounds are kept while the output opens
+    async fn test_waiting_sounds_capped() {
+        let (mut sound_system, played_sounds) = slow(Duration::from_millis(100), false);
+        let keys: Vec<char> = (0..MAX_WAITING as u8 + 10).map(|idx| char::from(b'!' + idx)).collect();
+        for &key in &keys {
+            sound_system.schedule_sound(SoundType::KeyPress(key), Instant::now());
+        }
+        sound_system.ready().await.unwrap();
+        let expected: Vec<SoundType> = keys[10..].iter().map(|&key| SoundType::KeyPress(key)).collect();
+        assert_eq!(played(&played_sounds, MAX_WAITING).await, expected);
+    }
+
+    #[tokio::test]
+    /// Test that an output that can't be opened says why, and that sounds
+    /// scheduled after that are dropped quietly
+    async fn test_output_fails() {
+        let (mut sound_system, played_sounds) = slow(Duration::from_millis(50), true);
+        sound_system.schedule_sound(SoundType::Bell, Instant::now());
+        assert_eq!(sound_system.ready().await, Err("no audio device".to_string()));
+        sound_system.schedule_sound(SoundType::Bell, Instant::now());
+        assert!(played_sounds.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    /// Test that sounds are scheduled ahead of the reveal by the lead time
+    fn test_play_time_lead() {
+        let now = Instant::now();
+        let reveal_time = now + Duration::from_millis(300);
+        let play_at = SoundSystem::play_time(reveal_time, Duration::ZERO, now);
+        assert_eq!(play_at, now + Duration::from_millis(200));
+    }
+
+    #[test]
+    /// Test that the latency offset composes with the lead time
+    fn test_play_time_with_latency() {
+        let now = Instant::now();
+        let reveal_time = now + Duration::from_millis(300);
+        let play_at = SoundSystem::play_time(reveal_time, Duration::from_millis(150), now);
+        assert_eq!(play_at, now + Duration::from_millis(50));
+    }
+
+    #[test]
+    /// Test that a large offset never schedules a sound in the past
+    fn test_play_time_clamped_to_now() {
+        let now = Instant::now();
+        let reveal_time = now + Duration::from_millis(300);
+        let play_at = SoundSystem::play_time(reveal_time, Duration::from_millis(500), now);
+        assert_eq!(play_at, now);
+
+        // Reveal times close to the Instant epoch must not underflow
+        let play_at = SoundSystem::play_time(now, Duration::from_secs(u32::MAX as u64), now);
+        assert_eq!(play_at, now);
+    }
+
+    #[test]
+    /// Test that a fixed seed always produces the same variation sequence
+    fn test_seeded_variation_sequence() {
+        let mut variations = VariationSource::new(42);
+        let sequence: Vec<Variation> = (0..3).map(|_| variations.next_variation()).collect();
+
+        let expected = [
+            Variation { speed: 0.9501677, volume: 0.9057738 },
+            Variation { speed: 1.0385991, volume: 1.0137999 },
+            Variation { speed: 1.0058984, volume: 0.97680616 },
+        ];
+        assert_eq!(sequence, expected);
+
+        // Replaying with the same seed gives identical parameters
+        let mut replay = VariationSource::new(42);
+        let replayed: Vec<Variation> = (0..3).map(|_| replay.next_variation()).collect();
+        assert_eq!(replayed, sequence);
+    }
+
+    #[test]
+    /// Test that variations stay within ±5% pitch and ±10% volume
+    fn test_variation_ranges() {
+        let mut variations = VariationSource::new(7);
+        for _ in 0..1000 {
+            let variation = variations.next_variation();
+            assert!((0.95..1.05).contains(&variation.speed));
+            assert!((0.9..1.1).contains(&variation.volume));
+        }
+    }
+
+    #[test]
+    /// Test that the made-up UI tick lasts as long as it should at any sample
+    /// rate, stays within its peak and dies away to nothing by its end
+    fn test_synthesize_tick() {
+        for rate in [8_000, 44_100, 48_000] {
+            let tick = SoundSystem::synthesize_tick(rate);
+            let length = Duration::from_secs_f64(tick.len() as f64 / rate as f64);
+            assert!(UI_TICK_LENGTH - length < Duration::from_secs_f64(1.0 / rate as f64));
+
+            assert_eq!(tick[0], 0.0);
+            let loudest = tick.iter().fold(0.0f32, |loudest, sample| loudest.max(sample.abs()));
+            assert!(loudest <= UI_TICK_PEAK);
+            assert!(loudest > UI_TICK_PEAK / 2.0);
+            assert!(tick[tick.len() - 10..].iter().all(|sample| sample.abs() < 0.01));
+        }
+    }
+
+    #[test]
+    /// Test that neighbouring seeds produce different sequences, with no
+    /// variation of one turning up anywhere in another
+    fn test_different_seeds_differ() {
+        let sequences: Vec<Vec<Variation>> = (0..4)
+            .map(|seed| {
+                let mut variations = VariationSource::new(seed);
+                (0..16).map(|_| variations.next_variation()).collect()
+            })
+            .collect();
+        for (i, first) in sequences.iter().enumerate() {
+            for second in &sequences[i + 1..] {
+                assert!(first.iter().all(|variation| !second.contains(variation)));
+            }
+        }
+    }
+
+    #[test]
+    /// Test that keys click by where they are on each layout, whatever their case
+    fn test_layout_clicks() {
+        let cases = [
+            (KeyboardLayout::Qwerty, "qtyp aghl zbnm", [1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 5, 5]),
+            (KeyboardLayout::Azerty, "azyp qgjm wbn!", [1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 5, 5]),
+            (KeyboardLayout::Dvorak, "'yfl aidn ;xbz", [1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 5, 5]),
+        ];
+        for (layout, keys, clicks) in cases {
+            let found: Vec<usize> = keys.chars().filter(|c| *c != ' ').map(|c| layout.click(c)).collect();
+            assert_eq!(found, clicks, "{:?}", layout);
+        }
+
+        // The same letter is somewhere else on another layout
+        assert_eq!(KeyboardLayout::Qwerty.click('a'), 3);
+        assert_eq!(KeyboardLayout::Azerty.click('a'), 1);
+        assert_eq!(KeyboardLayout::Qwerty.click('Q'), 1);
+
+        // Keys off the letter rows, and letters no layout has, keep the last click
+        for c in [' ', '7', '"', 'é', '漢'] {
+            assert_eq!(KeyboardLayout::Qwerty.click(c), 6, "{:?}", c);
+        }
+    }
+
+    #[test]
+    /// Test parsing a keyboard layout
+    fn test_parse_layout() {
+        assert_eq!(parse_layout("azerty"), Ok(KeyboardLayout::Azerty));
+        assert_eq!(parse_layout("dvorak"), Ok(KeyboardLayout::Dvorak));
+        assert_eq!(parse_layout("colemak"), Err("must be `qwerty`, `azerty` or `dvorak`, not `colemak`".to_string()));
+    }
+}
@@ -0,0 +1,253 @@
+use std::time::{Duration, Instant};
+
+use crate::editor::Buffer;
+use crate::renderer::Overlay;
+use crate::stats::{SessionStats, DEFAULT_IDLE_THRESHOLD};
+
+/// Longest length `parse_duration` accepts
+const MAX_LENGTH: Duration = Duration::from_secs(24 * 3600);
+
+/// Parses a length of time such as "15m", "900s", "1h" or "1h30m", up to a day
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("expected a length such as 15m, 900s or 1h".to_string());
+    }
+
+    let too_long = || format!("must be at most {}h", MAX_LENGTH.as_secs() / 3600);
+    let mut total = Duration::ZERO;
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("unknown unit `{}`; use h, m or s", c)),
+        };
+        if digits.is_empty() {
+            return Err(format!("expected a number before `{}`", c));
+        }
+        // Only a number too big for u64 fails to parse here
+        let amount: u64 = digits.parse().map_err(|_| too_long())?;
+        total = amount
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(Duration::from_secs(secs)))
+            .filter(|total| *total <= MAX_LENGTH)
+            .ok_or_else(too_long)?;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err(format!("`{}` needs a unit, such as {}m", text, digits));
+    }
+    if total.is_zero() {
+        return Err("must be longer than 0".to_string());
+    }
+    Ok(total)
+}
+
+/// Formats a time left as minutes and seconds, such as "14:05"
+pub fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// A writing sprint: a countdown that doesn't run while the terminal is out of focus
+#[derive(Debug, Clone)]
+pub struct Sprint {
+    /// How long the sprint lasts
+    length: Duration,
+    /// Time run before the current stretch
+    banked: Duration,
+    /// When the current stretch started, or None while paused
+    running_since: Option<Instant>,
+    /// The buffer as it was when the sprint started
+    stats: SessionStats,
+}
+
+impl Sprint {
+    /// Starts a sprint of the given length
+    pub fn start(length: Duration, buffer: &Buffer, now: Instant) -> Self {
+        Self {
+            length,
+            banked: Duration::ZERO,
+            running_since: Some(now),
+            stats: SessionStats::start(buffer, now, DEFAULT_IDLE_THRESHOLD),
+        }
+    }
+
+    /// Stops the countdown until `resume`
+    pub fn pause(&mut self, now: Instant) {
+        if let Some(since) = self.running_since.take() {
+            self.banked += now.saturating_duration_since(since);
+        }
+    }
+
+    /// Carries on counting down after `pause`
+    pub fn resume(&mut self, now: Instant) {
+        if self.running_since.is_none() {
+            self.running_since = Some(now);
+        }
+    }
+
+    /// Returns whether the countdown is paused
+    pub fn is_paused(&self) -> bool {
+        self.running_since.is_none()
+    }
+
+    /// Returns how much of the sprint has run, leaving out pauses
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        let running = self.running_since.map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+        (self.banked + running).min(self.length)
+    }
+
+    /// Returns how much of the sprint is left
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.length - self.elapsed(now)
+    }
+
+    /// Returns whether the whole length has run
+    pub fn is_over(&self, now: Instant) -> bool {
+        self.elapsed(now) >= self.length
+    }
+
+    /// Returns when the sprint will end, or None while paused
+    pub fn deadline(&self) -> Option<Instant> {
+        let since = self.running_since?;
+        since.checked_add(self.length.saturating_sub(self.banked))
+    }
+
+    /// Describes the countdown for the status row
+    pub fn status(&self, now: Instant) -> String {
+        let remaining = format_clock(self.remaining(now));
+        if self.is_paused() {
+            format!("Sprint paused: {} left", remaining)
+        } else {
+            format!("Sprint: {} left", remaining)
+        }
+    }
+
+    /// Sums up what was written during the sprint, for showing once it is over
+    pub fn summary(&self, buffer: &Buffer, now: Instant) -> Overlay {
+        let summary = self.stats.finish(buffer, now);
+        let minutes = self.length.as_secs_f64() / 60.0;
+        let wpm = (summary.words as f64 / minutes).round() as usize;
+
+        let rows = [
+            ("Words", summary.words.to_string()),
+            ("WPM", wpm.to_string()),
+            ("Corrections", summary.corrections.to_string()),
+        ];
+        let width = rows.iter().map(|(_, value)| value.len()).max().unwrap_or(0);
+        let mut lines: Vec<String> = rows
+            .iter()
+            .map(|(label, value)| format!("{:<11}  {:>width$}", label, value, width = width))
+            .collect();
+        lines.push(String::new());
+        lines.push("Any key to carry on".to_string());
+
+        Overlay {
+            title: format!("Sprint over ({})", format_clock(self.length)),
+            lines,
+            highlighted: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    /// Test the accepted ways of writing a length
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("15m"), Ok(15 * MINUTE));
+        assert_eq!(parse_duration("900s"), Ok(15 * MINUTE));
+        assert_eq!(parse_duration("1h"), Ok(60 * MINUTE));
+        assert_eq!(parse_duration(" 1h30m "), Ok(90 * MINUTE));
+    }
+
+    #[test]
+    /// Test that lengths without units or with bad ones are rejected
+    fn test_parse_duration_errors() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("15").unwrap_err().contains("needs a unit"));
+        assert!(parse_duration("15d").unwrap_err().contains("unknown unit"));
+        assert!(parse_duration("m").is_err());
+        assert_eq!(parse_duration("0m"), Err("must be longer than 0".to_string()));
+    }
+
+    #[test]
+    /// Test that lengths over a day are rejected rather than overflowing
+    fn test_parse_duration_too_long() {
+        assert_eq!(parse_duration("24h"), Ok(24 * 60 * MINUTE));
+        assert_eq!(parse_duration("23h60m"), Ok(24 * 60 * MINUTE));
+        let too_long = Err("must be at most 24h".to_string());
+        assert_eq!(parse_duration("24h1s"), too_long);
+        assert_eq!(parse_duration("18446744073709551615h"), too_long);
+        assert_eq!(parse_duration("99999999999999999999999s"), too_long);
+        assert_eq!(parse_duration("18446744073709551615s1s"), too_long);
+    }
+
+    #[test]
+    /// Test counting down without pauses
+    fn test_countdown() {
+        let start = Instant::now();
+        let sprint = Sprint::start(15 * MINUTE, &Buffer::new(Duration::ZERO), start);
+
+        assert_eq!(sprint.remaining(start + 5 * MINUTE), 10 * MINUTE);
+        assert_eq!(sprint.deadline(), Some(start + 15 * MINUTE));
+        assert!(!sprint.is_over(start + 14 * MINUTE));
+        assert!(sprint.is_over(start + 15 * MINUTE));
+        assert_eq!(sprint.remaining(start + 20 * MINUTE), Duration::ZERO);
+        assert_eq!(sprint.status(start + Duration::from_secs(55)), "Sprint: 14:05 left");
+    }
+
+    #[test]
+    /// Test that time spent paused isn't counted
+    fn test_pause_and_resume() {
+        let start = Instant::now();
+        let mut sprint = Sprint::start(15 * MINUTE, &Buffer::new(Duration::ZERO), start);
+
+        sprint.pause(start + 4 * MINUTE);
+        assert!(sprint.is_paused());
+        assert_eq!(sprint.deadline(), None);
+        assert_eq!(sprint.remaining(start + 30 * MINUTE), 11 * MINUTE);
+        assert_eq!(sprint.status(start + 30 * MINUTE), "Sprint paused: 11:00 left");
+
+        // Pausing twice doesn't lose the banked time
+        sprint.pause(start + 31 * MINUTE);
+        sprint.resume(start + 40 * MINUTE);
+        sprint.resume(start + 41 * MINUTE);
+        assert_eq!(sprint.remaining(start + 42 * MINUTE), 9 * MINUTE);
+        assert_eq!(sprint.deadline(), Some(start + 51 * MINUTE));
+        assert!(sprint.is_over(start + 51 * MINUTE));
+    }
+
+    #[test]
+    /// Test the numbers shown when the sprint is over
+    fn test_summary() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        let start = Instant::now();
+        let sprint = Sprint::start(2 * MINUTE, &buffer, start);
+        for c in "one two three fuor".chars() {
+            buffer.insert_char(c);
+        }
+        buffer.backspace();
+        buffer.backspace();
+        buffer.backspace();
+        buffer.insert_char('x');
+        buffer.insert_char('x');
+
+        let overlay = sprint.summary(&buffer, start + 2 * MINUTE);
+        assert_eq!(overlay.title, "Sprint over (2:00)");
+        assert_eq!(overlay.lines[0], "Words        4");
+        assert_eq!(overlay.lines[1], "WPM          2");
+        assert_eq!(overlay.lines[2], "Corrections  2");
+    }
+}
@@ -0,0 +1,524 @@
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::editor::{Buffer, CharacterState};
+
+/// Label of the widest summary row, which sets where the values start
+const LABEL_WIDTH: usize = "Corrections:".len();
+
+/// Label of the widest row of document statistics
+const DOCUMENT_LABEL_WIDTH: usize = "Reading time:".len();
+
+/// Words read in a minute, for the reading time
+const READING_WPM: usize = 200;
+
+/// Longest pause that still counts as writing time, unless set otherwise
+pub const DEFAULT_IDLE_THRESHOLD: Duration = Duration::from_secs(90);
+
+/// Where a session started from, so its progress can be measured at the end
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    /// When the session started
+    started: Instant,
+    /// Words in the buffer at the start
+    words_at_start: usize,
+    /// Marked-out characters in the buffer at the start
+    corrections_at_start: usize,
+    /// Longest gap between inputs that counts as active time
+    idle_threshold: Duration,
+    /// Active time up to the last input
+    active: Duration,
+    /// When the last input came, or None while idle after losing focus
+    last_input: Option<Instant>,
+    /// The seed of the sound variation, if sound is on
+    seed: Option<u64>,
+}
+
+/// What one session amounted to, as shown after a clean exit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    /// The file the text was saved to, if any
+    pub file: Option<String>,
+    /// Words added during the session
+    pub words: usize,
+    /// How long the session lasted
+    pub elapsed: Duration,
+    /// How much of that was spent writing rather than idle
+    pub active: Duration,
+    /// Characters marked out during the session
+    pub corrections: usize,
+    /// Share of the characters typed during the session that weren't marked out
+    pub cleanliness: f32,
+    /// Pomodoro work periods completed, if pomodoro mode was on
+    pub cycles: Option<usize>,
+    /// The seed of the sound variation, if sound was on, so `--seed` can
+    /// play the session's sounds back the same way
+    pub seed: Option<u64>,
+}
+
+impl SessionStats {
+    /// Starts measuring a session from the buffer as it is at `now`. Gaps
+    /// between inputs longer than `idle_threshold` don't count as active time.
+    pub fn start(buffer: &Buffer, now: Instant, idle_threshold: Duration) -> Self {
+        Self {
+            started: now,
+            words_at_start: word_count(buffer),
+            corrections_at_start: correction_count(buffer),
+            idle_threshold,
+            active: Duration::ZERO,
+            last_input: Some(now),
+            seed: None,
+        }
+    }
+
+    /// Records the seed of the sound variation, for the summary to show
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Notes an input, counting the gap since the last one unless it was idle
+    pub fn input(&mut self, now: Instant) {
+        self.active = self.active(now);
+        self.last_input = Some(now);
+    }
+
+    /// Notes the terminal losing focus, which starts idle time straight away
+    pub fn focus_lost(&mut self, now: Instant) {
+        self.active = self.active(now);
+        self.last_input = None;
+    }
+
+    /// Returns the active time up to `now`. The time since the last input
+    /// counts as long as it is within the threshold.
+    pub fn active(&self, now: Instant) -> Duration {
+        let gap = self.last_input.map_or(Duration::MAX, |last| now.saturating_duration_since(last));
+        if gap <= self.idle_threshold {
+            self.active + gap
+        } else {
+            self.active
+        }
+    }
+
+    /// Returns the words added a minute of active time up to `now`, or 0
+    /// before a second of it has passed
+    pub fn words_per_minute(&self, buffer: &Buffer, now: Instant) -> usize {
+        let active = self.active(now).as_secs_f64();
+        if active < 1.0 {
+            return 0;
+        }
+        let words = word_count(buffer).saturating_sub(self.words_at_start);
+        (words as f64 * 60.0 / active).round() as usize
+    }
+
+    /// Sums up the session from the buffer as it is at `now`
+    pub fn finish(&self, buffer: &Buffer, now: Instant) -> Summary {
+        Summary {
+            file: buffer.file_path.clone(),
+            words: word_count(buffer).saturating_sub(self.words_at_start),
+            elapsed: now.saturating_duration_since(self.started),
+            active: self.active(now),
+            corrections: correction_count(buffer).saturating_sub(self.corrections_at_start),
+            cleanliness: buffer.cleanliness(),
+            cycles: None,
+            seed: self.seed,
+        }
+    }
+}
+
+/// What a document holds, as printed by `--stats-only`. The fields are in
+/// the order they are printed, and their names are the JSON keys.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocumentStats {
+    /// The file the document was read from
+    pub file: String,
+    /// Words, separated by whitespace
+    pub words: usize,
+    /// Characters, not counting line breaks
+    pub characters: usize,
+    /// Sentences, each ended by `.`, `!` or `?` or by the end of its paragraph
+    pub sentences: usize,
+    /// Runs of lines that aren't blank
+    pub paragraphs: usize,
+    /// How long reading it takes, in whole seconds
+    pub reading_seconds: u64,
+}
+
+impl DocumentStats {
+    /// Counts what the buffer would save
+    pub fn count(file: &str, buffer: &Buffer) -> Self {
+        let text = buffer.saved_text();
+        let paragraphs: Vec<Vec<&str>> = text
+            .split('\n')
+            .collect::<Vec<_>>()
+            .split(|line| line.trim().is_empty())
+            .filter(|lines| !lines.is_empty())
+            .map(|lines| lines.iter().flat_map(|line| line.split_whitespace()).collect())
+            .collect();
+        let sentences = paragraphs
+            .iter()
+            .map(|words| {
+                let ends = words.iter().filter(|word| ends_sentence(word)).count();
+                // A paragraph that stops without a full stop still ends its sentence
+                ends + usize::from(words.last().is_some_and(|word| !ends_sentence(word)))
+            })
+            .sum();
+        let words = text.split_whitespace().count();
+        Self {
+            file: file.to_string(),
+            words,
+            characters: text.chars().filter(|&c| c != '\n').count(),
+            sentences,
+            paragraphs: paragraphs.len(),
+            reading_seconds: (words * 60).div_ceil(READING_WPM) as u64,
+        }
+    }
+
+    /// Formats the statistics as labelled rows, like the session summary
+    pub fn format(&self) -> String {
+        let numbers = [
+            ("Words:", self.words.to_string()),
+            ("Characters:", self.characters.to_string()),
+            ("Sentences:", self.sentences.to_string()),
+            ("Paragraphs:", self.paragraphs.to_string()),
+            ("Reading time:", format_elapsed(Duration::from_secs(self.reading_seconds))),
+        ];
+        let value_width = numbers.iter().map(|(_, value)| value.len()).max().unwrap_or(0);
+        let mut text = format!("{:<label$} {}\n", "File:", self.file, label = DOCUMENT_LABEL_WIDTH);
+        for (label, value) in numbers {
+            text.push_str(&format!(
+                "{:<label$} {:>value$}\n",
+                label,
+                value,
+                label = DOCUMENT_LABEL_WIDTH,
+                value = value_width
+            ));
+        }
+        text
+    }
+
+    /// Formats the statistics as one line of JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("document statistics are always valid JSON")
+    }
+}
+
+/// Returns whether a word ends a sentence: it ends in `.`, `!` or `?`,
+/// perhaps followed by closing quotes or brackets
+fn ends_sentence(word: &str) -> bool {
+    word.trim_end_matches(['"', '\'', '”', '’', ')', ']']).ends_with(['.', '!', '?'])
+}
+
+/// Returns the number of words that would be saved. Spaces at the end of a
+/// line only separate words, so they never change the count.
+pub fn word_count(buffer: &Buffer) -> usize {
+    buffer.text().split_whitespace().count()
+}
+
+/// Returns the number of marked-out characters
+fn correction_count(buffer: &Buffer) -> usize {
+    buffer
+        .lines
+        .iter()
+        .flat_map(|line| &line.characters)
+        .filter(|character| character.state == CharacterState::MarkedOut)
+        .count()
+}
+
+/// Formats the summary as labelled rows, with the numbers right-aligned so
+/// their last digits line up. The active time is left out when no idle time
+/// was taken off.
+pub fn format_summary(summary: &Summary) -> String {
+    let mut numbers = vec![("Words:", summary.words.to_string()), ("Time:", format_elapsed(summary.elapsed))];
+    if summary.active.as_secs() < summary.elapsed.as_secs() {
+        numbers.push(("Active:", format_elapsed(summary.active)));
+    }
+    numbers.push(("Corrections:", summary.corrections.to_string()));
+    numbers.push(("Cleanliness:", format_cleanliness(summary.cleanliness)));
+    if let Some(cycles) = summary.cycles {
+        numbers.push(("Pomodoros:", cycles.to_string()));
+    }
+    let value_width = numbers.iter().map(|(_, value)| value.len()).max().unwrap_or(0);
+
+    let file = summary.file.as_deref().unwrap_or("(not saved)");
+    let mut text = format!("{:<label$} {}\n", "File:", file, label = LABEL_WIDTH);
+    for (label, value) in numbers {
+        text.push_str(&format!(
+            "{:<label$} {:>value$}\n",
+            label,
+            value,
+            label = LABEL_WIDTH,
+            value = value_width
+        ));
+    }
+    // Left-aligned like the file, as a long seed would push the numbers apart
+    if let Some(seed) = summary.seed {
+        text.push_str(&format!("{:<label$} {}\n", "Seed:", seed, label = LABEL_WIDTH));
+    }
+    text
+}
+
+/// Formats a cleanliness as a percentage, rounded down so that only a page
+/// without a single correction shows 100%
+pub fn format_cleanliness(cleanliness: f32) -> String {
+    format!("{}%", (cleanliness * 100.0).floor())
+}
+
+/// Formats a duration as hours, minutes and seconds, leaving out leading zero units
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test the rows of a summary with a file
+    fn test_format_summary() {
+        let summary = Summary {
+            file: Some("notes.txt".to_string()),
+            words: 1204,
+            elapsed: Duration::from_secs(25 * 60 + 7),
+            active: Duration::from_secs(25 * 60 + 7),
+            corrections: 36,
+            cleanliness: 0.97,
+            cycles: None,
+            seed: None,
+        };
+        let expected = "\
+File:        notes.txt
+Words:          1204
+Time:        25m 07s
+Corrections:      36
+Cleanliness:     97%
+";
+        assert_eq!(format_summary(&summary), expected);
+    }
+
+    #[test]
+    /// Test a short session that was never saved
+    fn test_format_summary_unsaved() {
+        let summary = Summary {
+            file: None,
+            words: 3,
+            elapsed: Duration::from_millis(9500),
+            active: Duration::from_millis(9200),
+            corrections: 0,
+            cleanliness: 1.0,
+            cycles: None,
+            seed: None,
+        };
+        let expected = "\
+File:        (not saved)
+Words:          3
+Time:          9s
+Corrections:    0
+Cleanliness: 100%
+";
+        assert_eq!(format_summary(&summary), expected);
+    }
+
+    #[test]
+    /// Test the extra rows for pomodoro mode and for time spent idle
+    fn test_format_summary_cycles() {
+        let summary = Summary {
+            file: Some("draft.md".to_string()),
+            words: 950,
+            elapsed: Duration::from_secs(3600 + 30 * 60),
+            active: Duration::from_secs(3600 + 4 * 60 + 30),
+            corrections: 12,
+            cleanliness: 0.989,
+            cycles: Some(3),
+            seed: None,
+        };
+        let expected = "\
+File:        draft.md
+Words:              950
+Time:        1h 30m 00s
+Active:      1h 04m 30s
+Corrections:         12
+Cleanliness:        98%
+Pomodoros:            3
+";
+        assert_eq!(format_summary(&summary), expected);
+    }
+
+    #[test]
+    /// Test that the seed of the sound variation comes last, without widening
+    /// the numbers
+    fn test_format_summary_seed() {
+        let summary = Summary {
+            file: Some("notes.txt".to_string()),
+            words: 12,
+            elapsed: Duration::from_secs(65),
+            active: Duration::from_secs(65),
+            corrections: 1,
+            cleanliness: 0.98,
+            cycles: None,
+            seed: Some(14_695_981_039_346_656_037),
+        };
+        let expected = "\
+File:        notes.txt
+Words:           12
+Time:        1m 05s
+Corrections:      1
+Cleanliness:    98%
+Seed:        14695981039346656037
+";
+        assert_eq!(format_summary(&summary), expected);
+    }
+
+    #[test]
+    /// Test the counts and both output formats, which scripts rely on
+    fn test_document_stats() {
+        let text = "The End\n\nIt was late. \"Too late!\" she said (or so they say.)\nWas it?\n\n\nNo\nending here\n";
+        let buffer = Buffer::from_reader(text.as_bytes(), Duration::ZERO).unwrap();
+        let stats = DocumentStats::count("draft.txt", &buffer);
+        assert_eq!(
+            stats,
+            DocumentStats {
+                file: "draft.txt".to_string(),
+                words: 18,
+                characters: 78,
+                sentences: 6,
+                paragraphs: 3,
+                reading_seconds: 6,
+            }
+        );
+        let expected = "\
+File:         draft.txt
+Words:        18
+Characters:   78
+Sentences:     6
+Paragraphs:    3
+Reading time: 6s
+";
+        assert_eq!(stats.format(), expected);
+        assert_eq!(
+            stats.to_json(),
+            r#"{"file":"draft.txt","words":18,"characters":78,"sentences":6,"paragraphs":3,"reading_seconds":6}"#
+        );
+
+        // Reading time rounds up to the second
+        let words = "word ".repeat(1001);
+        let buffer = Buffer::from_reader(words.as_bytes(), Duration::ZERO).unwrap();
+        let stats = DocumentStats::count("long.md", &buffer);
+        assert_eq!((stats.words, stats.sentences, stats.paragraphs), (1001, 1, 1));
+        assert_eq!(stats.reading_seconds, 301);
+        assert!(stats.format().contains("Reading time: 5m 01s\n"));
+
+        let buffer = Buffer::new(Duration::ZERO);
+        let stats = DocumentStats::count("empty.txt", &buffer);
+        assert_eq!((stats.words, stats.characters, stats.sentences, stats.paragraphs), (0, 0, 0, 0));
+    }
+
+    #[test]
+    /// Test that trailing spaces never count as words, and count as characters
+    /// only while they would be saved
+    fn test_trailing_whitespace() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        for c in "one two   ".chars() {
+            buffer.insert_char(c);
+        }
+        assert_eq!(word_count(&buffer), 2);
+        assert_eq!(DocumentStats::count("draft.txt", &buffer).characters, 10);
+
+        // Trimming leaves the line being typed alone, but not one left behind
+        buffer.set_trim_trailing_whitespace(true);
+        assert_eq!(DocumentStats::count("draft.txt", &buffer).characters, 10);
+        buffer.new_line();
+        assert_eq!(word_count(&buffer), 2);
+        assert_eq!(DocumentStats::count("draft.txt", &buffer).characters, 7);
+    }
+
+    #[test]
+    /// Test durations of an hour and more
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(Duration::ZERO), "0s");
+        assert_eq!(format_elapsed(Duration::from_secs(60)), "1m 00s");
+        assert_eq!(format_elapsed(Duration::from_secs(2 * 3600 + 5)), "2h 00m 05s");
+    }
+
+    #[test]
+    /// Test that only what changed during the session is counted
+    fn test_session_counts() {
+        let mut buffer = Buffer::new(Duration::ZERO);
+        for c in "already here".chars() {
+            buffer.insert_char(c);
+        }
+        let now = Instant::now();
+        let stats = SessionStats::start(&buffer, now, DEFAULT_IDLE_THRESHOLD);
+
+        for c in " and mroe".chars() {
+            buffer.insert_char(c);
+        }
+        for _ in 0..3 {
+            buffer.backspace();
+        }
+        for c in "xxxore".chars() {
+            buffer.insert_char(c);
+        }
+
+        let summary = stats.finish(&buffer, now + Duration::from_secs(30));
+        assert_eq!(summary.elapsed, Duration::from_secs(30));
+        assert_eq!(buffer.text(), "already here and more");
+        assert_eq!(summary.words, 2);
+        assert_eq!(summary.corrections, 3);
+        // 24 characters typed, 3 of them marked out
+        assert_eq!(summary.cleanliness, 21.0 / 24.0);
+        assert_eq!(format_cleanliness(summary.cleanliness), "87%");
+        // Two words in the 30 seconds since the start
+        assert_eq!(stats.words_per_minute(&buffer, now), 0);
+        assert_eq!(stats.words_per_minute(&buffer, now + Duration::from_secs(30)), 4);
+        // The seed is only shown when one was recorded at the start
+        assert_eq!(summary.seed, None);
+        assert_eq!(stats.with_seed(Some(7)).finish(&buffer, now).seed, Some(7));
+    }
+
+    #[test]
+    /// Test that a gap up to the idle threshold counts as active time and a
+    /// longer one doesn't count at all
+    fn test_idle_threshold() {
+        let start = Instant::now();
+        let threshold = Duration::from_secs(90);
+        let mut stats = SessionStats::start(&Buffer::new(Duration::ZERO), start, threshold);
+
+        stats.input(start + Duration::from_secs(90));
+        assert_eq!(stats.active(start + Duration::from_secs(90)), Duration::from_secs(90));
+        stats.input(start + Duration::from_millis(180_001));
+        assert_eq!(stats.active(start + Duration::from_millis(180_001)), Duration::from_secs(90));
+
+        // The time since the last input counts until it passes the threshold
+        let last = start + Duration::from_millis(180_001);
+        assert_eq!(stats.active(last + Duration::from_secs(10)), Duration::from_secs(100));
+        assert_eq!(stats.active(last + threshold), Duration::from_secs(180));
+        assert_eq!(stats.active(last + threshold + Duration::from_millis(1)), Duration::from_secs(90));
+
+        // Left open over lunch
+        let summary = stats.finish(&Buffer::new(Duration::ZERO), last + Duration::from_secs(3600));
+        assert_eq!(summary.elapsed, Duration::from_millis(3_780_001));
+        assert_eq!(summary.active, Duration::from_secs(90));
+    }
+
+    #[test]
+    /// Test that losing focus starts idle time at once, however short the gap
+    fn test_focus_lost() {
+        let start = Instant::now();
+        let mut stats = SessionStats::start(&Buffer::new(Duration::ZERO), start, DEFAULT_IDLE_THRESHOLD);
+        stats.focus_lost(start + Duration::from_secs(20));
+        assert_eq!(stats.active(start + Duration::from_secs(30)), Duration::from_secs(20));
+
+        // Coming back, by focus or by a key, starts counting again
+        stats.input(start + Duration::from_secs(40));
+        assert_eq!(stats.active(start + Duration::from_secs(45)), Duration::from_secs(25));
+    }
+}
@@ -0,0 +1,308 @@
+/// What a placeholder in the status template stands for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placeholder {
+    /// The file's name, or "Untitled"
+    File,
+    /// `*` while there are unsaved changes
+    Modified,
+    /// The cursor's line, from 1
+    Line,
+    /// The cursor's column, from 1
+    Col,
+    /// Words in the text
+    Words,
+    /// Characters in the text
+    Chars,
+    /// Words a minute added this session, over the active time
+    Wpm,
+    /// The paragraph measured against its limit
+    Goal,
+    /// The printed page the cursor is on
+    Page,
+    /// `♪` while sound is on
+    Sound,
+    /// What the status row says without a template: saving, a sprint and so on
+    Mode,
+    /// The time of day
+    Clock,
+}
+
+/// Each placeholder's name, and how long it holds on when the row is too
+/// narrow: 0 goes last
+const PLACEHOLDERS: [(&str, Placeholder, u8); 12] = [
+    ("file", Placeholder::File, 0),
+    ("modified", Placeholder::Modified, 0),
+    ("mode", Placeholder::Mode, 0),
+    ("line", Placeholder::Line, 1),
+    ("col", Placeholder::Col, 1),
+    ("words", Placeholder::Words, 2),
+    ("goal", Placeholder::Goal, 2),
+    ("wpm", Placeholder::Wpm, 3),
+    ("page", Placeholder::Page, 3),
+    ("chars", Placeholder::Chars, 4),
+    ("sound", Placeholder::Sound, 4),
+    ("clock", Placeholder::Clock, 4),
+];
+
+/// How long a segment of plain text holds on: it goes first
+const TEXT_PRIORITY: u8 = 5;
+
+/// Characters that divide the template into segments
+const SEPARATORS: [char; 2] = ['│', '|'];
+
+/// Part of a segment
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    /// Text shown as it is
+    Text(String),
+    /// A value filled in each frame
+    Field(Placeholder),
+}
+
+/// What lies between two separators, dropped as a whole when the row is too
+/// narrow or when every placeholder in it is empty
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Segment {
+    /// The separator before it, with the spaces around it; left out when the
+    /// segment ends up first on its side
+    lead: String,
+    /// What it says
+    pieces: Vec<Piece>,
+}
+
+impl Segment {
+    /// Returns how long it holds on: as long as its most lasting placeholder
+    fn priority(&self) -> u8 {
+        self.fields().map(priority).min().unwrap_or(TEXT_PRIORITY)
+    }
+
+    /// Returns its placeholders
+    fn fields(&self) -> impl Iterator<Item = Placeholder> + '_ {
+        self.pieces.iter().filter_map(|piece| match piece {
+            Piece::Field(placeholder) => Some(*placeholder),
+            Piece::Text(_) => None,
+        })
+    }
+
+    /// Returns what it says with the values filled in, or None if it has
+    /// placeholders and they are all empty
+    fn fill(&self, value: &impl Fn(Placeholder) -> String) -> Option<String> {
+        let mut text = String::new();
+        let mut filled = self.fields().next().is_none();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(part) => text.push_str(part),
+                Piece::Field(placeholder) => {
+                    let part = value(*placeholder);
+                    filled |= !part.is_empty();
+                    text.push_str(&part);
+                }
+            }
+        }
+        filled.then_some(text)
+    }
+
+    /// Adds a character of plain text
+    fn push(&mut self, c: char) {
+        match self.pieces.last_mut() {
+            Some(Piece::Text(text)) => text.push(c),
+            _ => self.pieces.push(Piece::Text(c.to_string())),
+        }
+    }
+
+    /// Takes the spaces off the end of its text, returning them
+    fn take_trailing_spaces(&mut self) -> String {
+        let Some(Piece::Text(text)) = self.pieces.last_mut() else {
+            return String::new();
+        };
+        let spaces = text.split_off(text.trim_end().len());
+        if text.is_empty() {
+            self.pieces.pop();
+        }
+        spaces
+    }
+}
+
+/// Returns how long a placeholder holds on when the row is too narrow
+fn priority(placeholder: Placeholder) -> u8 {
+    PLACEHOLDERS.iter().find(|(_, known, _)| *known == placeholder).map_or(TEXT_PRIORITY, |(_, _, priority)| *priority)
+}
+
+/// The status row's layout, such as `{file}{modified} │ Ln {line} {>} {clock}`.
+/// `│` or `|` divide it into segments, and what follows `{>}` is aligned to
+/// the right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusTemplate {
+    /// Segments aligned to the left
+    left: Vec<Segment>,
+    /// Segments aligned to the right
+    right: Vec<Segment>,
+}
+
+/// Parses a status template, naming the first placeholder it doesn't know
+pub fn parse_status(template: &str) -> Result<StatusTemplate, String> {
+    let mut sides = vec![vec![Segment::default()]];
+    // Whether the spaces after a separator still belong to it
+    let mut in_lead = false;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        let segments = sides.last_mut().expect("there is always a side");
+        let segment = segments.last_mut().expect("there is always a segment");
+        match c {
+            '{' => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                if name == ">" {
+                    segment.take_trailing_spaces();
+                    if sides.len() > 1 {
+                        return Err("has more than one `{>}`".to_string());
+                    }
+                    sides.push(vec![Segment::default()]);
+                    in_lead = true;
+                    continue;
+                }
+                let Some((_, placeholder, _)) = PLACEHOLDERS.iter().find(|(known, _, _)| *known == name) else {
+                    let known: Vec<String> = PLACEHOLDERS.iter().map(|(name, _, _)| format!("{{{}}}", name)).collect();
+                    let known = known.join(", ");
+                    return Err(format!("has an unknown placeholder `{{{}}}`; known ones are {}", name, known));
+                };
+                segment.pieces.push(Piece::Field(*placeholder));
+                in_lead = false;
+            }
+            c if SEPARATORS.contains(&c) => {
+                let lead = segment.take_trailing_spaces() + &c.to_string();
+                segments.push(Segment { lead, pieces: Vec::new() });
+                in_lead = true;
+            }
+            c if in_lead && c.is_whitespace() => segment.lead.push(c),
+            c => {
+                segment.push(c);
+                in_lead = false;
+            }
+        }
+    }
+    let right = if sides.len() > 1 { sides.pop().unwrap_or_default() } else { Vec::new() };
+    let left = sides.pop().unwrap_or_default();
+    let keep = |segments: Vec<Segment>| -> Vec<Segment> {
+        segments.into_iter().filter(|segment| !segment.pieces.is_empty()).collect()
+    };
+    Ok(StatusTemplate { left: keep(left), right: keep(right) })
+}
+
+impl StatusTemplate {
+    /// Fills in the template for a row `width` characters wide. Segments
+    /// whose placeholders are all empty are left out. While the row is too
+    /// narrow, the least lasting segment goes, the rightmost of equals first;
+    /// the last one left is cut short. The right side is padded out to the
+    /// edge.
+    pub fn render(&self, width: usize, value: impl Fn(Placeholder) -> String) -> String {
+        // (side, text with lead, text without, priority) of each segment shown
+        let mut shown: Vec<(usize, String, String, u8)> = Vec::new();
+        for (side, segments) in [&self.left, &self.right].into_iter().enumerate() {
+            for segment in segments {
+                if let Some(text) = segment.fill(&value) {
+                    shown.push((side, format!("{}{}", segment.lead, text), text, segment.priority()));
+                }
+            }
+        }
+        let join = |shown: &[(usize, String, String, u8)], side: usize| -> String {
+            let mut texts = shown.iter().filter(|(on, ..)| *on == side);
+            let first = texts.next().map_or_else(String::new, |(_, _, text, _)| text.trim_start().to_string());
+            texts.fold(first, |row, (_, with_lead, _, _)| row + with_lead)
+        };
+        loop {
+            let (left, right) = (join(&shown, 0), join(&shown, 1));
+            let (left_len, right_len) = (left.chars().count(), right.chars().count());
+            let gap = usize::from(left_len > 0 && right_len > 0);
+            if left_len + gap + right_len <= width || shown.len() <= 1 {
+                if right.is_empty() {
+                    return left.chars().take(width).collect();
+                }
+                let padding = width.saturating_sub(left_len + right_len).max(gap);
+                let row = format!("{}{}{}", left, " ".repeat(padding), right);
+                return row.chars().take(width).collect();
+            }
+            let least = shown.iter().map(|(.., priority)| *priority).max().unwrap_or(TEXT_PRIORITY);
+            if let Some(idx) = shown.iter().rposition(|(.., priority)| *priority == least) {
+                shown.remove(idx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example values for each placeholder
+    fn value(placeholder: Placeholder) -> String {
+        match placeholder {
+            Placeholder::File => "notes.txt",
+            Placeholder::Modified => "*",
+            Placeholder::Line => "12",
+            Placeholder::Col => "7",
+            Placeholder::Words => "345",
+            Placeholder::Chars => "2001",
+            Placeholder::Wpm => "28",
+            Placeholder::Goal => "",
+            Placeholder::Page => "1",
+            Placeholder::Sound => "♪",
+            Placeholder::Mode => "",
+            Placeholder::Clock => "14:05",
+        }
+        .to_string()
+    }
+
+    #[test]
+    /// Test that a template is split into segments at its separators, and
+    /// filled in with the spaces around them kept
+    fn test_parse() {
+        let template = parse_status("{file}{modified} │ Ln {line}, Col {col} | {words}w").unwrap();
+        assert_eq!(template.left.len(), 3);
+        assert!(template.right.is_empty());
+        assert_eq!(template.left[1].lead, " │ ");
+        assert_eq!(
+            template.left[1].pieces,
+            [
+                Piece::Text("Ln ".to_string()),
+                Piece::Field(Placeholder::Line),
+                Piece::Text(", Col ".to_string()),
+                Piece::Field(Placeholder::Col),
+            ]
+        );
+        assert_eq!(template.left[2].priority(), 2);
+        assert_eq!(template.render(80, value), "notes.txt* │ Ln 12, Col 7 | 345w");
+
+        let template = parse_status("{file} {>} {wpm} wpm │ {clock}").unwrap();
+        assert_eq!(template.render(30, value), "notes.txt       28 wpm │ 14:05");
+    }
+
+    #[test]
+    /// Test that unknown placeholders and a second `{>}` are refused
+    fn test_parse_errors() {
+        let error = parse_status("{file} │ {lines}").unwrap_err();
+        assert!(error.starts_with("has an unknown placeholder `{lines}`; known ones are {file}, {modified}"));
+        assert!(parse_status("{file} {").unwrap_err().starts_with("has an unknown placeholder `{}`"));
+        assert_eq!(parse_status("{>}{>}").unwrap_err(), "has more than one `{>}`");
+    }
+
+    #[test]
+    /// Test that a segment with nothing to show is left out with its separator
+    fn test_empty_segments() {
+        let template = parse_status("{mode} │ {file} │ {goal} │ {words} words").unwrap();
+        assert_eq!(template.render(80, value), "notes.txt │ 345 words");
+    }
+
+    #[test]
+    /// Test that a narrow row drops the least lasting segments first, the
+    /// rightmost of equals before the others, and cuts the last one short
+    fn test_truncation() {
+        let template = "{file}{modified} │ Ln {line}, Col {col} │ {words}w │ {wpm} wpm {>} {sound} │ {clock}";
+        let template = parse_status(template).unwrap();
+        assert_eq!(template.render(60, value), "notes.txt* │ Ln 12, Col 7 │ 345w │ 28 wpm          ♪ │ 14:05");
+        assert_eq!(template.render(45, value), "notes.txt* │ Ln 12, Col 7 │ 345w │ 28 wpm   ♪");
+        assert_eq!(template.render(40, value), "notes.txt* │ Ln 12, Col 7 │ 345w");
+        assert_eq!(template.render(30, value), "notes.txt* │ Ln 12, Col 7");
+        assert_eq!(template.render(20, value), "notes.txt*");
+        assert_eq!(template.render(6, value), "notes.");
+    }
+}
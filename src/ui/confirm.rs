@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use crate::input::InputEvent;
+
+/// What the user answered to a confirmation question
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfirmOutcome {
+    /// No answer yet
+    Pending,
+    /// The user pressed `y`
+    Accepted,
+    /// The user pressed `n`
+    Declined,
+    /// The user pressed anything else, or took too long to answer
+    Cancelled,
+}
+
+/// A yes/no question shown in the status row, cancelled automatically
+/// if left unanswered
+#[derive(Debug, Clone)]
+pub struct Confirm {
+    /// The question shown to the user
+    question: String,
+    /// When the question was asked
+    asked_at: Instant,
+    /// How long to wait for an answer
+    timeout: Duration,
+}
+
+impl Confirm {
+    /// Asks a new question at `now`
+    pub fn new(question: &str, now: Instant, timeout: Duration) -> Self {
+        Self {
+            question: question.to_string(),
+            asked_at: now,
+            timeout,
+        }
+    }
+
+    /// Returns the question text
+    pub fn question(&self) -> &str {
+        &self.question
+    }
+
+    /// Returns when the question is given up on if nobody answers
+    pub fn deadline(&self) -> Instant {
+        self.asked_at + self.timeout
+    }
+
+    /// Works out the answer given the next input event at `now`
+    pub fn handle(&self, event: &InputEvent, now: Instant) -> ConfirmOutcome {
+        match event {
+            InputEvent::Char('y' | 'Y') => ConfirmOutcome::Accepted,
+            InputEvent::Char('n' | 'N') => ConfirmOutcome::Declined,
+            InputEvent::Ignored if now < self.deadline() => ConfirmOutcome::Pending,
+            _ => ConfirmOutcome::Cancelled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    /// Test that `y` accepts in either case
+    fn test_accept() {
+        let now = Instant::now();
+        let confirm = Confirm::new("Discard changes? (y/n)", now, TIMEOUT);
+        assert_eq!(confirm.question(), "Discard changes? (y/n)");
+        assert_eq!(confirm.handle(&InputEvent::Char('y'), now), ConfirmOutcome::Accepted);
+        assert_eq!(confirm.handle(&InputEvent::Char('Y'), now), ConfirmOutcome::Accepted);
+    }
+
+    #[test]
+    /// Test that `n` declines and any other key cancels
+    fn test_decline_and_cancel() {
+        let now = Instant::now();
+        let confirm = Confirm::new("Discard changes? (y/n)", now, TIMEOUT);
+        assert_eq!(confirm.handle(&InputEvent::Char('n'), now), ConfirmOutcome::Declined);
+        assert_eq!(confirm.handle(&InputEvent::Char('N'), now), ConfirmOutcome::Declined);
+        assert_eq!(confirm.handle(&InputEvent::NewLine, now), ConfirmOutcome::Cancelled);
+        assert_eq!(confirm.handle(&InputEvent::Abort, now), ConfirmOutcome::Cancelled);
+    }
+
+    #[test]
+    /// Test that the question stays open until the timeout passes
+    fn test_timeout() {
+        let now = Instant::now();
+        let confirm = Confirm::new("Discard changes? (y/n)", now, TIMEOUT);
+        let almost = now + TIMEOUT - Duration::from_millis(1);
+        assert_eq!(confirm.deadline(), now + TIMEOUT);
+        assert_eq!(confirm.handle(&InputEvent::Ignored, almost), ConfirmOutcome::Pending);
+        assert_eq!(confirm.handle(&InputEvent::Ignored, now + TIMEOUT), ConfirmOutcome::Cancelled);
+    }
+}
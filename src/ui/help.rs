@@ -0,0 +1,191 @@
+use crate::input::{InputEvent, KeyMap, ACTIONS};
+use crate::renderer::Overlay;
+
+/// Rows of the screen the help box can't use: its two borders and the status row
+const RESERVED_ROWS: usize = 3;
+
+/// The list of key bindings shown by F1, split into pages on short screens
+#[derive(Debug, Clone)]
+pub struct Help {
+    /// One line per bound action: its chords, then what it does
+    lines: Vec<String>,
+    /// Index of the page being shown
+    page: usize,
+}
+
+impl Help {
+    /// Describes the bindings in a key map, so rebound keys show up as configured.
+    /// Actions without any chord are left out.
+    pub fn from_keymap(keymap: &KeyMap) -> Self {
+        let entries: Vec<(String, &str)> = ACTIONS
+            .iter()
+            .filter_map(|(_, action, description)| {
+                let chords = keymap.chords_for(action);
+                if chords.is_empty() {
+                    return None;
+                }
+                let chords: Vec<String> = chords.iter().map(ToString::to_string).collect();
+                Some((chords.join(", "), *description))
+            })
+            .collect();
+
+        let width = entries.iter().map(|(chords, _)| chords.chars().count()).max().unwrap_or(0);
+        let lines = entries
+            .into_iter()
+            .map(|(chords, description)| format!("{:<width$}  {}", chords, description, width = width))
+            .collect();
+        Self { lines, page: 0 }
+    }
+
+    /// Handles a key while the help is open. The arrow keys flip between pages
+    /// when there is more than one; every other key dismisses the help.
+    /// Returns whether the help stays open.
+    pub fn handle(&mut self, event: &InputEvent, screen_height: u16) -> bool {
+        let pages = self.page_count(screen_height);
+        match event {
+            InputEvent::Ignored => true,
+            InputEvent::Down | InputEvent::Right if pages > 1 => {
+                self.page = (self.page + 1).min(pages - 1);
+                true
+            }
+            InputEvent::Up | InputEvent::Left if pages > 1 => {
+                self.page = self.page.saturating_sub(1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Describes the current page for a screen of the given height
+    pub fn overlay(&self, screen_height: u16) -> Overlay {
+        let per_page = Self::page_size(screen_height);
+        let pages = self.page_count(screen_height);
+        let page = self.page.min(pages - 1);
+        let title = if pages > 1 {
+            format!("Help ({}/{})", page + 1, pages)
+        } else {
+            "Help".to_string()
+        };
+        Overlay {
+            title,
+            lines: self.lines.iter().skip(page * per_page).take(per_page).cloned().collect(),
+            highlighted: None,
+        }
+    }
+
+    /// How many lines fit on one page
+    fn page_size(screen_height: u16) -> usize {
+        (screen_height as usize).saturating_sub(RESERVED_ROWS).max(1)
+    }
+
+    /// How many pages the help takes up, never less than one
+    fn page_count(&self, screen_height: u16) -> usize {
+        self.lines.len().div_ceil(Self::page_size(screen_height)).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws an overlay onto an otherwise blank screen as plain text
+    fn plain_screen(overlay: &Overlay, size: (u16, u16)) -> Vec<String> {
+        let mut screen = vec![" ".repeat(size.0 as usize); size.1 as usize];
+        let layout = overlay.layout(size);
+        for (idx, row) in layout.rows.iter().enumerate() {
+            let line = &mut screen[layout.top as usize + idx];
+            let mut chars: Vec<char> = line.chars().collect();
+            for (offset, c) in row.chars().enumerate() {
+                chars[layout.left as usize + offset] = c;
+            }
+            *line = chars.into_iter().collect();
+        }
+        screen.iter().map(|line| line.trim_end().to_string()).collect()
+    }
+
+    #[test]
+    /// Test the layout of the default bindings on an 80x29 screen
+    fn test_default_snapshot() {
+        let help = Help::from_keymap(&KeyMap::default());
+        let screen = plain_screen(&help.overlay(29), (80, 29));
+        let expected = [
+            "            ┌─ Help ───────────────────────────────────────────────┐",
+            "            │ Ctrl+S, F2           Save the file                   │",
+            "            │ Alt+S, Ctrl+Shift+S  Save under a new name           │",
+            "            │ Ctrl+C, Ctrl+X, F10  Close, offering to save         │",
+            "            │ Ctrl+Q               Quit without saving             │",
+            "            │ Backspace            Step back to mark out text      │",
+            "            │ Enter                Start a new line                │",
+            "            │ Right                Move right                      │",
+            "            │ Tab                  Move to the next tab stop       │",
+            "            │ Ctrl+E, End          Type after the marked-out text  │",
+            "            │ Ctrl+Z               Undo on the current line        │",
+            "            │ Left                 Move left                       │",
+            "            │ Up                   Move up                         │",
+            "            │ Down                 Move down                       │",
+            "            │ Esc                  Open the menu                   │",
+            "            │ Alt+U, Ctrl+Shift+U  Type in capitals, or stop       │",
+            "            │ Alt+D, Ctrl+Shift+D  Underline changes since saving  │",
+            "            │ Alt+M, Ctrl+Shift+M  Set the right margin            │",
+            "            │ Ctrl+Up, F7          Show typed text sooner          │",
+            "            │ Ctrl+Down, F8        Show typed text later           │",
+            "            │ Ctrl+G, F1           Show this help                  │",
+            "            │ Alt+L, Ctrl+Shift+L  Show recent messages            │",
+            "            │ Ctrl+L               Repaint the screen              │",
+            "            │ Alt+R, Ctrl+Shift+R  Start or stop recording a macro │",
+            "            │ Alt+P, Ctrl+Shift+P  Replay the recorded macro       │",
+            "            │ Alt+C, Ctrl+Shift+C  Copy the text to the clipboard  │",
+            "            │ F9                   Keep a snapshot of the text     │",
+            "            └──────────────────────────────────────────────────────┘",
+            "",
+        ];
+        assert_eq!(screen, expected);
+    }
+
+    #[test]
+    /// Test that rebound keys are listed as configured
+    fn test_rebound_keys() {
+        let mut keymap = KeyMap::default();
+        keymap.rebind("save", &["ctrl+w"]).unwrap();
+        let help = Help::from_keymap(&keymap);
+        assert!(help.lines.iter().any(|line| line.starts_with("Ctrl+W ") && line.ends_with("Save the file")));
+        assert!(!help.lines.iter().any(|line| line.contains("Ctrl+S,")));
+    }
+
+    #[test]
+    /// Test paging through the help on a short screen
+    fn test_pagination() {
+        let mut help = Help::from_keymap(&KeyMap::default());
+        let height = 8;
+        let pages = help.lines.len().div_ceil(5);
+        assert!(pages > 2);
+        assert_eq!(help.overlay(height).title, format!("Help (1/{})", pages));
+        assert_eq!(help.overlay(height).lines.len(), 5);
+
+        assert!(help.handle(&InputEvent::Down, height));
+        assert_eq!(help.overlay(height).title, format!("Help (2/{})", pages));
+        assert_eq!(help.overlay(height).lines[0], help.lines[5]);
+
+        // Paging stops at the last page
+        for _ in 0..pages {
+            assert!(help.handle(&InputEvent::Right, height));
+        }
+        assert_eq!(help.overlay(height).title, format!("Help ({}/{})", pages, pages));
+
+        assert!(help.handle(&InputEvent::Up, height));
+        assert_eq!(help.overlay(height).title, format!("Help ({}/{})", pages - 1, pages));
+
+        // Any other key dismisses it
+        assert!(!help.handle(&InputEvent::Char('a'), height));
+    }
+
+    #[test]
+    /// Test that any key dismisses a single-page help
+    fn test_dismiss() {
+        let mut help = Help::from_keymap(&KeyMap::default());
+        assert_eq!(help.overlay(29).title, "Help");
+        assert!(help.handle(&InputEvent::Ignored, 29));
+        assert!(!help.handle(&InputEvent::Down, 29));
+        assert!(!help.handle(&InputEvent::Help, 29));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::input::InputEvent;
+
+/// Where the stop starts when no margin is set
+const DEFAULT_STOP: usize = 72;
+
+/// What happened while setting the margin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarginOutcome {
+    /// Still moving the stop
+    Open,
+    /// Enter set the margin to this many columns
+    Set(usize),
+    /// Escape left the margin as it was
+    Cancelled,
+}
+
+/// Sets the right margin by sliding a stop along a ruler, like the margin
+/// stops on a typewriter's carriage
+#[derive(Debug, Clone)]
+pub struct MarginSetter {
+    /// How many characters a line can hold with the stop where it is
+    column: usize,
+    /// Furthest the stop can go, the width of the page on screen
+    widest: usize,
+    /// Characters in the longest line of the text
+    longest_line: usize,
+    /// Whether Enter was pressed with the stop inside the longest line, so
+    /// pressing it again sets the margin anyway
+    warned: bool,
+}
+
+impl MarginSetter {
+    /// Starts at the current margin, if any, on a page `widest` columns wide
+    pub fn new(margin: Option<usize>, longest_line: usize, widest: usize) -> Self {
+        let widest = widest.max(1);
+        Self {
+            column: margin.unwrap_or(DEFAULT_STOP).clamp(1, widest),
+            widest,
+            longest_line,
+            warned: false,
+        }
+    }
+
+    /// Returns how many characters a line could hold with the stop where it is
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Describes the stop and the keys for the status row
+    pub fn status(&self) -> String {
+        if self.warned {
+            format!(
+                "The longest line has {} characters; Enter again splits longer lines at {}",
+                self.longest_line, self.column
+            )
+        } else {
+            format!("Margin at {}: Left/Right to move, Enter to set, Esc to cancel", self.column)
+        }
+    }
+
+    /// Moves the stop or sets the margin for the next input event
+    pub fn handle(&mut self, event: &InputEvent) -> MarginOutcome {
+        match event {
+            InputEvent::Left | InputEvent::Backspace => self.move_to(self.column.saturating_sub(1)),
+            InputEvent::Right => self.move_to(self.column + 1),
+            InputEvent::NewLine if self.column < self.longest_line && !self.warned => {
+                self.warned = true;
+                MarginOutcome::Open
+            }
+            InputEvent::NewLine => MarginOutcome::Set(self.column),
+            InputEvent::Escape => MarginOutcome::Cancelled,
+            _ => MarginOutcome::Open,
+        }
+    }
+
+    /// Moves the stop, keeping it on the page
+    fn move_to(&mut self, column: usize) -> MarginOutcome {
+        self.column = column.clamp(1, self.widest);
+        self.warned = false;
+        MarginOutcome::Open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test moving the stop within the page, setting and cancelling
+    fn test_handle() {
+        let mut setter = MarginSetter::new(None, 10, 75);
+        assert_eq!(setter.column(), 72);
+        for _ in 0..5 {
+            assert_eq!(setter.handle(&InputEvent::Right), MarginOutcome::Open);
+        }
+        assert_eq!(setter.column(), 75);
+        setter.handle(&InputEvent::Left);
+        setter.handle(&InputEvent::Char('x'));
+        assert_eq!(setter.handle(&InputEvent::NewLine), MarginOutcome::Set(74));
+        assert_eq!(setter.handle(&InputEvent::Escape), MarginOutcome::Cancelled);
+
+        // The stop never goes past the page or below one column
+        let setter = MarginSetter::new(Some(100), 0, 40);
+        assert_eq!(setter.column(), 40);
+        let mut setter = MarginSetter::new(Some(2), 0, 40);
+        setter.handle(&InputEvent::Left);
+        setter.handle(&InputEvent::Left);
+        assert_eq!(setter.column(), 1);
+    }
+
+    #[test]
+    /// Test that a margin inside the longest line needs Enter twice
+    fn test_warns_below_longest_line() {
+        let mut setter = MarginSetter::new(Some(62), 61, 80);
+        setter.handle(&InputEvent::Left);
+        assert_eq!(setter.handle(&InputEvent::NewLine), MarginOutcome::Set(61));
+
+        setter.handle(&InputEvent::Left);
+        assert_eq!(setter.handle(&InputEvent::NewLine), MarginOutcome::Open);
+        assert!(setter.status().starts_with("The longest line has 61 characters"));
+
+        // Moving the stop asks again
+        setter.handle(&InputEvent::Left);
+        assert_eq!(setter.handle(&InputEvent::NewLine), MarginOutcome::Open);
+        assert_eq!(setter.handle(&InputEvent::NewLine), MarginOutcome::Set(59));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::input::InputEvent;
+use crate::renderer::Overlay;
+
+/// What happened to the menu after an input event
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuOutcome {
+    /// The menu stays open
+    Open,
+    /// The menu was dismissed without choosing anything
+    Closed,
+    /// An item was chosen; its event should be handled as if it had been typed
+    Selected(InputEvent),
+}
+
+/// A list of actions navigated with the arrow keys
+#[derive(Debug, Clone)]
+pub struct Menu {
+    /// Title shown in the overlay border
+    title: String,
+    /// Item labels and the events they trigger
+    items: Vec<(String, InputEvent)>,
+    /// Index of the highlighted item
+    selected: usize,
+}
+
+impl Menu {
+    /// Creates a menu with the first item highlighted
+    pub fn new(title: &str, items: Vec<(String, InputEvent)>) -> Self {
+        Self {
+            title: title.to_string(),
+            items,
+            selected: 0,
+        }
+    }
+
+    /// Creates the menu opened by Escape
+    pub fn main_menu() -> Self {
+        let items = [
+            ("Save", InputEvent::Save),
+            ("Save As", InputEvent::SaveAs),
+            ("Toggle Sound", InputEvent::ToggleSound),
+            ("Reveal Speed", InputEvent::SetRevealRate),
+            ("Print", InputEvent::Print),
+            ("Quit", InputEvent::Close),
+        ];
+        Self::new(
+            "Menu",
+            items
+                .into_iter()
+                .map(|(label, event)| (label.to_string(), event))
+                .collect(),
+        )
+    }
+
+    /// Updates the selection for an input event. Right moves down too, for
+    /// key maps without the other arrows.
+    pub fn handle(&mut self, event: &InputEvent) -> MenuOutcome {
+        if self.items.is_empty() {
+            return MenuOutcome::Closed;
+        }
+        match event {
+            InputEvent::Up => {
+                self.selected = self.selected.checked_sub(1).unwrap_or(self.items.len() - 1);
+                MenuOutcome::Open
+            }
+            InputEvent::Down | InputEvent::Right => {
+                self.selected = (self.selected + 1) % self.items.len();
+                MenuOutcome::Open
+            }
+            InputEvent::NewLine => MenuOutcome::Selected(self.items[self.selected].1.clone()),
+            InputEvent::Escape => MenuOutcome::Closed,
+            _ => MenuOutcome::Open,
+        }
+    }
+
+    /// Describes how the menu should be drawn
+    pub fn overlay(&self) -> Overlay {
+        Overlay {
+            title: self.title.clone(),
+            lines: self.items.iter().map(|(label, _)| label.clone()).collect(),
+            highlighted: Some(self.selected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test moving the selection with the arrow keys
+    fn test_navigation() {
+        let mut menu = Menu::main_menu();
+        assert_eq!(menu.selected, 0);
+
+        assert_eq!(menu.handle(&InputEvent::Down), MenuOutcome::Open);
+        assert_eq!(menu.selected, 1);
+
+        // Moving up from the top wraps to the last item
+        menu.handle(&InputEvent::Up);
+        menu.handle(&InputEvent::Up);
+        assert_eq!(menu.selected, 5);
+
+        // And down from the bottom wraps to the first
+        menu.handle(&InputEvent::Down);
+        assert_eq!(menu.selected, 0);
+
+        // Right moves down as well
+        menu.handle(&InputEvent::Right);
+        assert_eq!(menu.selected, 1);
+    }
+
+    #[test]
+    /// Test choosing an item with Enter
+    fn test_select() {
+        let mut menu = Menu::main_menu();
+        menu.handle(&InputEvent::Down);
+        assert_eq!(menu.handle(&InputEvent::NewLine), MenuOutcome::Selected(InputEvent::SaveAs));
+
+        let mut menu = Menu::main_menu();
+        menu.handle(&InputEvent::Up);
+        assert_eq!(menu.handle(&InputEvent::NewLine), MenuOutcome::Selected(InputEvent::Close));
+    }
+
+    #[test]
+    /// Test that Escape closes the menu and typing is ignored
+    fn test_close_and_ignore() {
+        let mut menu = Menu::main_menu();
+        assert_eq!(menu.handle(&InputEvent::Char('a')), MenuOutcome::Open);
+        assert_eq!(menu.handle(&InputEvent::Ignored), MenuOutcome::Open);
+        assert_eq!(menu.handle(&InputEvent::Escape), MenuOutcome::Closed);
+    }
+
+    #[test]
+    /// Test the overlay describing the menu
+    fn test_overlay() {
+        let mut menu = Menu::main_menu();
+        menu.handle(&InputEvent::Down);
+        let overlay = menu.overlay();
+        assert_eq!(overlay.title, "Menu");
+        assert_eq!(overlay.lines, vec!["Save", "Save As", "Toggle Sound", "Reveal Speed", "Print", "Quit"]);
+        assert_eq!(overlay.highlighted, Some(1));
+    }
+}
@@ -0,0 +1,16 @@
+mod confirm;
+mod help;
+mod margin;
+mod menu;
+mod picker;
+mod prompt;
+mod reveal;
+mod wizard;
+pub use confirm::*;
+pub use help::*;
+pub use margin::*;
+pub use menu::*;
+pub use picker::*;
+pub use prompt::*;
+pub use reveal::*;
+pub use wizard::*;
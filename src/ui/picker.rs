@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+
+use crate::drafts::Draft;
+use crate::input::InputEvent;
+use crate::renderer::Overlay;
+
+/// Rows of the screen the list can't use: its two borders and the status row
+const RESERVED_ROWS: usize = 3;
+
+/// What happened on the opening screen after an input event
+#[derive(Debug, Clone, PartialEq)]
+pub enum PickerOutcome {
+    /// Still choosing
+    Open,
+    /// A file was chosen
+    Chosen(PathBuf),
+    /// A new, unnamed document was asked for
+    New,
+    /// The user quit without opening anything
+    Quit,
+}
+
+/// The opening screen: recent drafts, scrolled to keep the selection in view
+#[derive(Debug, Clone)]
+pub struct Picker {
+    /// The files on offer, most recent first
+    drafts: Vec<Draft>,
+    /// Index of the highlighted file
+    selected: usize,
+    /// Index of the first file in view
+    top: usize,
+}
+
+impl Picker {
+    /// Creates a picker with the first file highlighted
+    pub fn new(drafts: Vec<Draft>) -> Self {
+        Self {
+            drafts,
+            selected: 0,
+            top: 0,
+        }
+    }
+
+    /// Updates the selection for an input event. Up and Down move one file,
+    /// Left and Right a screenful.
+    pub fn handle(&mut self, event: &InputEvent, screen_height: u16) -> PickerOutcome {
+        let last = self.drafts.len().saturating_sub(1);
+        let page = Self::page_size(screen_height);
+        match event {
+            InputEvent::Up | InputEvent::ScrollUp => self.selected = self.selected.saturating_sub(1),
+            InputEvent::Down | InputEvent::ScrollDown => self.selected = (self.selected + 1).min(last),
+            InputEvent::Left => self.selected = self.selected.saturating_sub(page),
+            InputEvent::Right => self.selected = (self.selected + page).min(last),
+            InputEvent::NewLine => {
+                return match self.drafts.get(self.selected) {
+                    Some(draft) => PickerOutcome::Chosen(draft.path.clone()),
+                    None => PickerOutcome::New,
+                };
+            }
+            InputEvent::Char('n' | 'N') => return PickerOutcome::New,
+            InputEvent::Escape | InputEvent::Close | InputEvent::Abort => return PickerOutcome::Quit,
+            _ => (),
+        }
+        self.top = Self::scrolled_top(self.top, self.selected, page);
+        PickerOutcome::Open
+    }
+
+    /// Describes the files in view on a screen of the given height
+    pub fn overlay(&self, screen_height: u16) -> Overlay {
+        let page = Self::page_size(screen_height);
+        let top = Self::scrolled_top(self.top, self.selected, page);
+        let width = self.drafts.iter().map(|draft| label(draft).chars().count()).max().unwrap_or(0);
+        let lines = self
+            .drafts
+            .iter()
+            .skip(top)
+            .take(page)
+            .map(|draft| format!("{:<width$}  {}", label(draft), modified(draft), width = width))
+            .collect();
+        let title = if self.drafts.len() > page {
+            format!("Open ({}/{})", self.selected + 1, self.drafts.len())
+        } else {
+            "Open".to_string()
+        };
+        Overlay {
+            title,
+            lines,
+            highlighted: Some(self.selected - top),
+        }
+    }
+
+    /// How many files fit on screen at once
+    fn page_size(screen_height: u16) -> usize {
+        (screen_height as usize).saturating_sub(RESERVED_ROWS).max(1)
+    }
+
+    /// Moves the first file in view just enough to show the selection
+    fn scrolled_top(top: usize, selected: usize, page: usize) -> usize {
+        top.min(selected).max((selected + 1).saturating_sub(page))
+    }
+}
+
+/// Returns how a draft is named in the list
+fn label(draft: &Draft) -> String {
+    draft.path.display().to_string()
+}
+
+/// Returns when a draft was last written, such as "2024-03-01 14:05"
+fn modified(draft: &Draft) -> String {
+    DateTime::<Local>::from(draft.modified).format("%Y-%m-%d %H:%M").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// Returns a picker over `count` files named 0.txt, 1.txt and so on
+    fn picker(count: usize) -> Picker {
+        let drafts = (0..count)
+            .map(|idx| Draft {
+                path: PathBuf::from(format!("{}.txt", idx)),
+                modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000),
+            })
+            .collect();
+        Picker::new(drafts)
+    }
+
+    #[test]
+    /// Test choosing a file, a new document, or nothing
+    fn test_outcomes() {
+        let mut picker = picker(3);
+        assert_eq!(picker.handle(&InputEvent::Down, 24), PickerOutcome::Open);
+        assert_eq!(picker.handle(&InputEvent::NewLine, 24), PickerOutcome::Chosen(PathBuf::from("1.txt")));
+        assert_eq!(picker.handle(&InputEvent::Char('n'), 24), PickerOutcome::New);
+        assert_eq!(picker.handle(&InputEvent::Escape, 24), PickerOutcome::Quit);
+        assert_eq!(picker.handle(&InputEvent::Char('x'), 24), PickerOutcome::Open);
+    }
+
+    #[test]
+    /// Test that the selection stops at either end instead of wrapping
+    fn test_ends() {
+        let mut picker = picker(3);
+        picker.handle(&InputEvent::Up, 24);
+        assert_eq!(picker.selected, 0);
+        for _ in 0..5 {
+            picker.handle(&InputEvent::Down, 24);
+        }
+        assert_eq!(picker.selected, 2);
+        assert_eq!(picker.overlay(24).title, "Open");
+        assert_eq!(picker.overlay(24).highlighted, Some(2));
+    }
+
+    #[test]
+    /// Test scrolling through more files than fit on screen
+    fn test_scrolling() {
+        let mut picker = picker(300);
+        let height = 8;
+
+        // Five rows fit; moving past them scrolls by one
+        for _ in 0..5 {
+            picker.handle(&InputEvent::Down, height);
+        }
+        let overlay = picker.overlay(height);
+        assert_eq!(overlay.title, "Open (6/300)");
+        assert_eq!(overlay.lines.len(), 5);
+        assert!(overlay.lines[0].starts_with("1.txt "));
+        assert_eq!(overlay.highlighted, Some(4));
+
+        // Left and Right move a screenful at a time
+        picker.handle(&InputEvent::Right, height);
+        assert_eq!(picker.selected, 10);
+        picker.handle(&InputEvent::Left, height);
+        picker.handle(&InputEvent::Left, height);
+        assert_eq!(picker.selected, 0);
+        assert!(picker.overlay(height).lines[0].starts_with("0.txt "));
+    }
+}
@@ -0,0 +1,208 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::input::InputEvent;
+
+/// What happened to the prompt after an input event
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptOutcome {
+    /// The user is still typing
+    Editing,
+    /// Enter was pressed with this text
+    Submitted(String),
+    /// Escape was pressed
+    Cancelled,
+}
+
+/// A single-line text input shown in the status row
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    /// Text shown before the input
+    label: String,
+    /// The characters typed so far
+    input: Vec<char>,
+    /// Cursor position as an index into `input`
+    cursor: usize,
+}
+
+impl Prompt {
+    /// Creates a prompt pre-filled with `initial`, with the cursor at the end
+    pub fn new(label: &str, initial: &str) -> Self {
+        let input: Vec<char> = initial.chars().collect();
+        Self {
+            label: label.to_string(),
+            cursor: input.len(),
+            input,
+        }
+    }
+
+    /// Returns the text typed so far
+    pub fn text(&self) -> String {
+        self.input.iter().collect()
+    }
+
+    /// Applies an input event to the prompt
+    pub fn handle(&mut self, event: &InputEvent) -> PromptOutcome {
+        match event {
+            InputEvent::Char(c) => {
+                self.input.insert(self.cursor, *c);
+                self.cursor += 1;
+            }
+            InputEvent::Paste(text) => {
+                // Only the first line, since the prompt is one line
+                let pasted: Vec<char> = text.lines().next().unwrap_or_default().chars().collect();
+                self.input.splice(self.cursor..self.cursor, pasted.iter().copied());
+                self.cursor += pasted.len();
+            }
+            InputEvent::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.input.remove(self.cursor);
+            }
+            InputEvent::Left => self.cursor = self.cursor.saturating_sub(1),
+            InputEvent::Right => self.cursor = (self.cursor + 1).min(self.input.len()),
+            InputEvent::NewLine => return PromptOutcome::Submitted(self.text()),
+            InputEvent::Escape => return PromptOutcome::Cancelled,
+            _ => (),
+        }
+        PromptOutcome::Editing
+    }
+
+    /// Returns the text to show in a row `width` columns wide and the column of the
+    /// cursor within it. Input too long for the row scrolls to keep the cursor visible.
+    pub fn view(&self, width: usize) -> (String, usize) {
+        let label_width = self.label.width();
+        // Keep one column free for the cursor after the last character
+        let (label, available) = if label_width + 2 <= width {
+            (self.label.as_str(), width - label_width - 1)
+        } else {
+            ("", width.saturating_sub(1).max(1))
+        };
+
+        // Scroll back from the cursor as far as fits, then fill forwards
+        let mut start = self.cursor;
+        let mut used = 0;
+        while start > 0 && used + char_width(self.input[start - 1]) <= available {
+            start -= 1;
+            used += char_width(self.input[start]);
+        }
+        let mut end = self.cursor;
+        while end < self.input.len() && used + char_width(self.input[end]) <= available {
+            used += char_width(self.input[end]);
+            end += 1;
+        }
+
+        let visible: String = self.input[start..self.cursor].iter().collect();
+        let cursor_column = label.width() + visible.width();
+        let text = format!("{}{}", label, self.input[start..end].iter().collect::<String>());
+        (text, cursor_column)
+    }
+}
+
+/// Returns how many terminal columns a character occupies
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds a string to the prompt one character at a time
+    fn type_text(prompt: &mut Prompt, text: &str) {
+        for c in text.chars() {
+            assert_eq!(prompt.handle(&InputEvent::Char(c)), PromptOutcome::Editing);
+        }
+    }
+
+    #[test]
+    /// Test typing and submitting text
+    fn test_typing_and_submit() {
+        let mut prompt = Prompt::new("Save as: ", "");
+        type_text(&mut prompt, "draft.txt");
+        assert_eq!(prompt.text(), "draft.txt");
+        assert_eq!(prompt.handle(&InputEvent::NewLine), PromptOutcome::Submitted("draft.txt".to_string()));
+    }
+
+    #[test]
+    /// Test backspace and cursor movement
+    fn test_editing() {
+        let mut prompt = Prompt::new("Save as: ", "draft.txt");
+        prompt.handle(&InputEvent::Backspace);
+        prompt.handle(&InputEvent::Backspace);
+        prompt.handle(&InputEvent::Backspace);
+        type_text(&mut prompt, "md");
+        assert_eq!(prompt.text(), "draft.md");
+
+        // Insert in the middle
+        for _ in 0..3 {
+            prompt.handle(&InputEvent::Left);
+        }
+        type_text(&mut prompt, "-1");
+        assert_eq!(prompt.text(), "draft-1.md");
+
+        // Movement stops at both ends
+        for _ in 0..20 {
+            prompt.handle(&InputEvent::Left);
+        }
+        prompt.handle(&InputEvent::Backspace);
+        assert_eq!(prompt.text(), "draft-1.md");
+        for _ in 0..20 {
+            prompt.handle(&InputEvent::Right);
+        }
+        type_text(&mut prompt, "!");
+        assert_eq!(prompt.text(), "draft-1.md!");
+
+        // A paste goes in at the cursor, up to its first line break
+        prompt.handle(&InputEvent::Left);
+        prompt.handle(&InputEvent::Paste("-old\nmore".to_string()));
+        assert_eq!(prompt.text(), "draft-1.md-old!");
+    }
+
+    #[test]
+    /// Test that Escape cancels and other events are ignored
+    fn test_cancel() {
+        let mut prompt = Prompt::new("Save as: ", "a");
+        assert_eq!(prompt.handle(&InputEvent::Ignored), PromptOutcome::Editing);
+        assert_eq!(prompt.handle(&InputEvent::Up), PromptOutcome::Editing);
+        assert_eq!(prompt.handle(&InputEvent::Escape), PromptOutcome::Cancelled);
+    }
+
+    #[test]
+    /// Test unicode input, including wide characters
+    fn test_unicode() {
+        let mut prompt = Prompt::new("> ", "");
+        type_text(&mut prompt, "日記é");
+        assert_eq!(prompt.text(), "日記é");
+        prompt.handle(&InputEvent::Backspace);
+        assert_eq!(prompt.text(), "日記");
+
+        // Each CJK character takes two columns
+        let (text, cursor) = prompt.view(80);
+        assert_eq!(text, "> 日記");
+        assert_eq!(cursor, 6);
+    }
+
+    #[test]
+    /// Test scrolling input that is wider than the row
+    fn test_view_scrolls() {
+        let mut prompt = Prompt::new("> ", "");
+        type_text(&mut prompt, "abcdefghij");
+
+        // 10 columns: label (2) + 7 characters + cursor
+        let (text, cursor) = prompt.view(10);
+        assert_eq!(text, "> defghij");
+        assert_eq!(cursor, 9);
+
+        // Moving to the start scrolls back
+        for _ in 0..10 {
+            prompt.handle(&InputEvent::Left);
+        }
+        let (text, cursor) = prompt.view(10);
+        assert_eq!(text, "> abcdefg");
+        assert_eq!(cursor, 2);
+
+        // Too narrow for the label: only the input is shown
+        let (text, cursor) = prompt.view(2);
+        assert_eq!(text, "a");
+        assert_eq!(cursor, 0);
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::{Duration, Instant};
+
+use crate::editor::{MAX_REVEAL_RATE, REVEAL_STEP};
+use crate::input::InputEvent;
+use crate::renderer::Overlay;
+
+/// The line that types itself to show a reveal delay
+pub const DEMO_TEXT: &str = "It was a dark and stormy night.";
+
+/// Characters' worth of pause before the demo line starts over
+pub const DEMO_PAUSE: usize = 6;
+
+/// What happened while choosing the reveal delay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RevealOutcome {
+    /// Still choosing
+    Open,
+    /// Enter chose this delay
+    Set(Duration),
+    /// Escape left the delay as it was
+    Cancelled,
+}
+
+/// Chooses the reveal delay by watching a sample line type itself at it
+#[derive(Debug, Clone)]
+pub struct RevealSetter {
+    /// The delay on show
+    rate: Duration,
+    /// When the demo line last started typing
+    demo_started: Instant,
+}
+
+impl RevealSetter {
+    /// Starts at the current delay, kept within the steps on offer
+    pub fn new(rate: Duration, now: Instant) -> Self {
+        Self {
+            rate: rate.clamp(REVEAL_STEP, MAX_REVEAL_RATE),
+            demo_started: now,
+        }
+    }
+
+    /// Describes the keys for the status row
+    pub fn status(&self) -> String {
+        "Left/Right to change the delay, Enter to set, Esc to cancel".to_string()
+    }
+
+    /// Changes the delay or sets it for the next input event. A new delay
+    /// starts the demo line again.
+    pub fn handle(&mut self, event: &InputEvent, now: Instant) -> RevealOutcome {
+        let rate = match event {
+            InputEvent::Left | InputEvent::Backspace => self.rate.saturating_sub(REVEAL_STEP),
+            InputEvent::Right => self.rate + REVEAL_STEP,
+            InputEvent::NewLine => return RevealOutcome::Set(self.rate),
+            InputEvent::Escape => return RevealOutcome::Cancelled,
+            _ => return RevealOutcome::Open,
+        };
+        let rate = rate.clamp(REVEAL_STEP, MAX_REVEAL_RATE);
+        if rate != self.rate {
+            self.rate = rate;
+            self.demo_started = now;
+        }
+        RevealOutcome::Open
+    }
+
+    /// Describes the delay and the demo line typed at it by now, padded so
+    /// the box doesn't grow as the line does
+    pub fn overlay(&self, now: Instant) -> Overlay {
+        let width = DEMO_TEXT.chars().count();
+        Overlay {
+            title: "Reveal speed".to_string(),
+            lines: vec![
+                format!("Characters appear after {} ms", self.rate.as_millis()),
+                String::new(),
+                format!("{:<width$}", demo_line(self.demo_started, self.rate, now)),
+            ],
+            highlighted: None,
+        }
+    }
+}
+
+/// Returns as much of the demo line as a delay of `rate` a character has
+/// typed between `started` and `now`, starting over after a short pause at
+/// the end
+pub fn demo_line(started: Instant, rate: Duration, now: Instant) -> String {
+    let typed = now.saturating_duration_since(started).as_millis() / rate.as_millis().max(1);
+    let length = DEMO_TEXT.chars().count();
+    let shown = (typed as usize % (length + DEMO_PAUSE)).min(length);
+    DEMO_TEXT.chars().take(shown).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test changing the delay within the steps on offer, setting and cancelling
+    fn test_handle() {
+        let now = Instant::now();
+        let mut setter = RevealSetter::new(Duration::from_millis(300), now);
+        assert_eq!(setter.handle(&InputEvent::Right, now), RevealOutcome::Open);
+        assert_eq!(setter.handle(&InputEvent::NewLine, now), RevealOutcome::Set(Duration::from_millis(350)));
+        setter.handle(&InputEvent::Left, now);
+        setter.handle(&InputEvent::Left, now);
+        setter.handle(&InputEvent::Char('x'), now);
+        assert_eq!(setter.handle(&InputEvent::NewLine, now), RevealOutcome::Set(Duration::from_millis(250)));
+        assert_eq!(setter.handle(&InputEvent::Escape, now), RevealOutcome::Cancelled);
+
+        // The delay never goes below one step or past the slowest
+        let mut setter = RevealSetter::new(Duration::ZERO, now);
+        assert_eq!(setter.handle(&InputEvent::NewLine, now), RevealOutcome::Set(REVEAL_STEP));
+        setter.handle(&InputEvent::Left, now);
+        assert_eq!(setter.handle(&InputEvent::NewLine, now), RevealOutcome::Set(REVEAL_STEP));
+        let mut setter = RevealSetter::new(Duration::from_secs(10), now);
+        setter.handle(&InputEvent::Right, now);
+        assert_eq!(setter.handle(&InputEvent::NewLine, now), RevealOutcome::Set(MAX_REVEAL_RATE));
+    }
+
+    #[test]
+    /// Test that the demo line types at the delay on show and starts over
+    /// when it changes
+    fn test_overlay() {
+        let start = Instant::now();
+        let mut setter = RevealSetter::new(Duration::from_millis(200), start);
+        let overlay = setter.overlay(start + Duration::from_millis(1000));
+        assert_eq!(overlay.title, "Reveal speed");
+        assert_eq!(overlay.lines[0], "Characters appear after 200 ms");
+        assert_eq!(overlay.lines[2].trim_end(), "It wa");
+        assert_eq!(overlay.lines[2].chars().count(), DEMO_TEXT.chars().count());
+
+        let later = start + Duration::from_secs(3);
+        setter.handle(&InputEvent::Left, later);
+        let overlay = setter.overlay(later + Duration::from_millis(300));
+        assert_eq!(overlay.lines[0], "Characters appear after 150 ms");
+        assert_eq!(overlay.lines[2].trim_end(), "It");
+    }
+}
@@ -0,0 +1,216 @@
+use std::time::{Duration, Instant};
+
+use crate::input::InputEvent;
+use crate::renderer::Overlay;
+
+use super::reveal::demo_line;
+
+/// The reveal speeds on offer, with their delays in milliseconds
+pub const SPEEDS: [(&str, u64); 3] = [("Slow", 500), ("Medium", 300), ("Fast", 120)];
+
+/// What the first-run setup settled on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WizardChoices {
+    /// Whether to play sounds
+    pub sound: bool,
+    /// Delay before a typed character appears
+    pub reveal_rate: Duration,
+}
+
+/// What happened in the setup after an input event
+#[derive(Debug, Clone, PartialEq)]
+pub enum WizardOutcome {
+    /// Still answering
+    Open,
+    /// Sound was highlighted; a test click should be played
+    Click,
+    /// Every question was answered
+    Finished(WizardChoices),
+    /// The user skipped the setup, keeping the defaults
+    Skipped,
+}
+
+/// The question being asked
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    /// Sound on or off
+    Sound,
+    /// How fast characters appear
+    Speed,
+}
+
+/// The questions asked on the very first launch, answered with the arrow
+/// keys and Enter. Left goes back to the previous question.
+#[derive(Debug, Clone)]
+pub struct Wizard {
+    /// The question being asked
+    step: Step,
+    /// Whether sound is highlighted
+    sound: bool,
+    /// Index into `SPEEDS` of the highlighted speed
+    speed: usize,
+    /// When the demo line last started typing
+    demo_started: Instant,
+}
+
+impl Wizard {
+    /// Starts at the first question with the defaults highlighted
+    pub fn new(now: Instant) -> Self {
+        Self {
+            step: Step::Sound,
+            sound: false,
+            speed: 1,
+            demo_started: now,
+        }
+    }
+
+    /// Updates the answers for an input event
+    pub fn handle(&mut self, event: &InputEvent, now: Instant) -> WizardOutcome {
+        if matches!(event, InputEvent::Escape | InputEvent::Close | InputEvent::Abort) {
+            return WizardOutcome::Skipped;
+        }
+        match self.step {
+            Step::Sound => match event {
+                InputEvent::Up | InputEvent::Down => {
+                    self.sound = !self.sound;
+                    if self.sound {
+                        return WizardOutcome::Click;
+                    }
+                }
+                InputEvent::NewLine => {
+                    self.step = Step::Speed;
+                    self.demo_started = now;
+                }
+                _ => (),
+            },
+            Step::Speed => match event {
+                InputEvent::Up if self.speed > 0 => {
+                    self.speed -= 1;
+                    self.demo_started = now;
+                }
+                InputEvent::Down if self.speed + 1 < SPEEDS.len() => {
+                    self.speed += 1;
+                    self.demo_started = now;
+                }
+                InputEvent::Left | InputEvent::Backspace => self.step = Step::Sound,
+                InputEvent::NewLine => return WizardOutcome::Finished(self.choices()),
+                _ => (),
+            },
+        }
+        WizardOutcome::Open
+    }
+
+    /// Returns the answers as they stand
+    pub fn choices(&self) -> WizardChoices {
+        WizardChoices {
+            sound: self.sound,
+            reveal_rate: Duration::from_millis(SPEEDS[self.speed].1),
+        }
+    }
+
+    /// Describes the current question. The demo line depends on the time, so
+    /// this is redrawn while waiting for input.
+    pub fn overlay(&self, now: Instant) -> Overlay {
+        match self.step {
+            Step::Sound => Overlay {
+                title: "Setup (1/2): Sound".to_string(),
+                lines: vec!["Sound on".to_string(), "Sound off".to_string()],
+                highlighted: Some(if self.sound { 0 } else { 1 }),
+            },
+            Step::Speed => {
+                let mut lines: Vec<String> =
+                    SPEEDS.iter().map(|(name, ms)| format!("{:<6}  {} ms", name, ms)).collect();
+                lines.push(String::new());
+                lines.push(demo_line(self.demo_started, self.choices().reveal_rate, now));
+                Overlay {
+                    title: "Setup (2/2): Reveal speed".to_string(),
+                    lines,
+                    highlighted: Some(self.speed),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{DEMO_PAUSE, DEMO_TEXT};
+
+    /// Feeds events to a wizard one after another, returning the last outcome
+    fn script(wizard: &mut Wizard, events: &[InputEvent], now: Instant) -> WizardOutcome {
+        events.iter().map(|event| wizard.handle(event, now)).last().unwrap()
+    }
+
+    #[test]
+    /// Test that answering every question gives the chosen settings
+    fn test_finished() {
+        let now = Instant::now();
+        let mut wizard = Wizard::new(now);
+        assert_eq!(wizard.handle(&InputEvent::Down, now), WizardOutcome::Click);
+        let events = [InputEvent::NewLine, InputEvent::Down, InputEvent::Down, InputEvent::NewLine];
+        let outcome = script(&mut wizard, &events, now);
+        assert_eq!(
+            outcome,
+            WizardOutcome::Finished(WizardChoices {
+                sound: true,
+                reveal_rate: Duration::from_millis(120),
+            })
+        );
+    }
+
+    #[test]
+    /// Test that Enter straight through keeps the defaults
+    fn test_defaults() {
+        let now = Instant::now();
+        let mut wizard = Wizard::new(now);
+        let outcome = script(&mut wizard, &[InputEvent::NewLine, InputEvent::NewLine], now);
+        assert_eq!(
+            outcome,
+            WizardOutcome::Finished(WizardChoices {
+                sound: false,
+                reveal_rate: Duration::from_millis(300),
+            })
+        );
+    }
+
+    #[test]
+    /// Test skipping from either question, and going back to the first
+    fn test_skip_and_back() {
+        let now = Instant::now();
+        let mut wizard = Wizard::new(now);
+        assert_eq!(wizard.handle(&InputEvent::Escape, now), WizardOutcome::Skipped);
+
+        let mut wizard = Wizard::new(now);
+        script(&mut wizard, &[InputEvent::NewLine, InputEvent::Up, InputEvent::Up], now);
+        assert_eq!(wizard.choices().reveal_rate, Duration::from_millis(500));
+        assert_eq!(wizard.handle(&InputEvent::Left, now), WizardOutcome::Open);
+        assert_eq!(wizard.overlay(now).title, "Setup (1/2): Sound");
+        assert_eq!(wizard.handle(&InputEvent::Up, now), WizardOutcome::Click);
+        assert_eq!(wizard.handle(&InputEvent::Up, now), WizardOutcome::Open);
+        assert_eq!(wizard.handle(&InputEvent::Abort, now), WizardOutcome::Skipped);
+    }
+
+    #[test]
+    /// Test that the demo line types at the highlighted speed and starts over
+    fn test_demo_line() {
+        let start = Instant::now();
+        let mut wizard = Wizard::new(start);
+        wizard.handle(&InputEvent::NewLine, start);
+
+        let overlay = wizard.overlay(start + Duration::from_millis(1000));
+        assert_eq!(overlay.lines.last().unwrap(), "It ");
+        assert_eq!(overlay.highlighted, Some(1));
+
+        // A new speed starts the line again
+        let later = start + Duration::from_secs(5);
+        wizard.handle(&InputEvent::Down, later);
+        assert_eq!(wizard.overlay(later + Duration::from_millis(720)).lines.last().unwrap(), "It was");
+
+        let length = DEMO_TEXT.chars().count() as u64;
+        let full = later + Duration::from_millis(120 * (length + 1));
+        assert_eq!(wizard.overlay(full).lines.last().unwrap(), DEMO_TEXT);
+        let again = later + Duration::from_millis(120 * (length + DEMO_PAUSE as u64 + 2));
+        assert_eq!(wizard.overlay(again).lines.last().unwrap(), "It");
+    }
+}